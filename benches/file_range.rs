@@ -95,6 +95,13 @@ mod benchmarks {
             let ranges = (0..size).map(|i| (base + i, base + i + 50)).collect();
             parameterized_add_bench(&mut group, &format!("overlap_{}", size), ranges);
         }
+
+        // 最后一个区间吞并之前所有不相交的区间
+        for &size in &[8, 16, 32, 64] {
+            let mut ranges = generate_sorted_ranges(size, 10);
+            ranges.push((0, size * 20));
+            parameterized_add_bench(&mut group, &format!("absorb_{}", size), ranges);
+        }
     }
 
     fn large_scale_ops(c: &mut Criterion) {
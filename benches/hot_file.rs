@@ -1,6 +1,6 @@
 use bytes::Bytes;
 use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
-use falcon_transfer::hot_file::{FileMultiRange, HotFile};
+use falcon_transfer::hot_file::{FileMultiRange, HotFile, ReadBackend};
 use rand::{Rng, rng};
 use std::fs::File;
 use std::io::Write;
@@ -124,6 +124,38 @@ fn bench_read(c: &mut Criterion) {
     group.finish();
 }
 
+// 大文件上的大量小区间读取，比较 seek 与内存映射，未启用 mmap feature 时两者相同
+fn bench_sparse_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sparse_read");
+    group.sample_size(10);
+
+    let size = 64 * MB;
+    let (file, _) = prepare_file_sync(size, true);
+    let ranges = (0..size)
+        .step_by(64 * KB)
+        .map(|start| start..start + 4 * KB)
+        .collect::<Vec<_>>();
+    let mask = FileMultiRange::try_from(ranges.as_slice()).unwrap();
+    let count = ranges.len();
+
+    for backend in [ReadBackend::Seek, ReadBackend::Mmap] {
+        let hot_file = rt().block_on(HotFile::open_existed(&file)).unwrap();
+        let backend = hot_file.set_read_backend(backend);
+        let hot_file = Arc::new(hot_file);
+        group.bench_function(format!("{backend:?}_{count}x4KB"), |b| {
+            b.to_async(rt()).iter(|| {
+                let hot_file = hot_file.clone();
+                let mask = mask.clone();
+                async move {
+                    let result = hot_file.read(mask).await.unwrap();
+                    assert_eq!(result.len(), count);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
 fn bench_concurrent(c: &mut Criterion) {
     let mut group = c.benchmark_group("concurrent");
     group.sample_size(10);
@@ -185,5 +217,11 @@ fn bench_concurrent(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_write, bench_read, bench_concurrent);
+criterion_group!(
+    benches,
+    bench_write,
+    bench_read,
+    bench_sparse_read,
+    bench_concurrent
+);
 criterion_main!(benches);
@@ -0,0 +1,79 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use falcon_transfer::{
+    addr::EndPoint,
+    link::{LinkStateTable, Uid},
+};
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::Runtime;
+
+const HOSTS: usize = 4096;
+const LINKS_PER_HOST: usize = 4;
+
+static RT: OnceLock<Runtime> = OnceLock::new();
+
+fn rt() -> &'static Runtime {
+    RT.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+fn endpoint(host: usize, link: usize) -> EndPoint {
+    format!("[fe80::{:x}:{:x}%{}]:5555", host, link, link + 1)
+        .parse()
+        .unwrap()
+}
+
+fn prepare_table() -> (Arc<LinkStateTable>, Vec<Uid>) {
+    // 构造时会启动后台协程，需要在运行时内
+    let _guard = rt().enter();
+    let table = Arc::new(LinkStateTable::new());
+    let local = endpoint(0, 0);
+    let hosts = (0..HOSTS)
+        .map(|i| {
+            let host = Uid::random();
+            for j in 0..LINKS_PER_HOST {
+                table.update(host.clone(), &local, &endpoint(i + 1, j));
+            }
+            host
+        })
+        .collect();
+    (table, hosts)
+}
+
+fn bench_assign(c: &mut Criterion) {
+    let (table, hosts) = prepare_table();
+    let mut group = c.benchmark_group("assign");
+    group.sample_size(20);
+
+    group.bench_function("single_caller", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % HOSTS;
+            table.assign(&hosts[i]).unwrap()
+        })
+    });
+
+    for callers in [4, 16].into_iter() {
+        group.bench_function(format!("{}_callers", callers), |b| {
+            b.iter(|| {
+                std::thread::scope(|s| {
+                    for t in 0..callers {
+                        let (table, hosts) = (&table, &hosts);
+                        s.spawn(move || {
+                            for i in (t..HOSTS).step_by(callers) {
+                                table.assign(&hosts[i]).unwrap();
+                            }
+                        });
+                    }
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_assign);
+criterion_main!(benches);
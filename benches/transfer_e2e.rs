@@ -0,0 +1,249 @@
+//! 端到端传输基准：同一进程内两个端点经回环 UDP 收发，走真实的编解码与会话层
+//!
+//! 每种配置传一遍 `FALCON_BENCH_BYTES`（默认 1 GiB），报告吞吐与每 GB 耗费的 CPU 时间。
+//! 设置 `FALCON_BENCH_MIN_MBPS` 或 `FALCON_BENCH_MAX_CPU_PER_GB` 后，任一配置越过阈值即以非零码退出，
+//! 供 CI 捕捉编解码、会话层的性能回退。
+//!
+//! ```sh
+//! cargo bench --bench transfer_e2e --features testing
+//! ```
+
+use bytes::Bytes;
+use falcon_transfer::{
+    inbound::{Capabilities, HostId, Msg, MsgCodec},
+    session::{Sealed, open_msg, seal_msg},
+    testing::establish,
+};
+use futures::{SinkExt, StreamExt};
+use std::{
+    env,
+    net::SocketAddr,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, runtime::Runtime, sync::Semaphore, time::timeout};
+use tokio_util::udp::UdpFramed;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+const GB: usize = 1024 * MB;
+
+/// 每个数据报文的载荷，加上报头与 AEAD 标签后仍在 `MAX_FRAME` 之内
+const CHUNK: usize = 8 * KB;
+/// 在途报文上限，保持在默认接收缓冲区以内，避免回环上丢包
+const WINDOW: usize = 16;
+/// 这么久没有收到任何报文即认为在途报文已丢失
+const IDLE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy)]
+struct Variant {
+    name: &'static str,
+    encrypt: bool,
+    compress: bool,
+}
+
+const VARIANTS: [Variant; 3] = [
+    Variant {
+        name: "plain",
+        encrypt: false,
+        compress: false,
+    },
+    Variant {
+        name: "sealed",
+        encrypt: true,
+        compress: false,
+    },
+    // 压缩在会话层加密之前进行，没有单独压缩不加密的路径
+    Variant {
+        name: "sealed+lz4",
+        encrypt: true,
+        compress: true,
+    },
+];
+
+struct Report {
+    delivered: usize,
+    lost: usize,
+    elapsed: Duration,
+    cpu: Option<Duration>,
+}
+
+impl Report {
+    fn mbps(&self) -> f64 {
+        self.delivered as f64 / MB as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn cpu_per_gb(&self) -> Option<f64> {
+        self.cpu
+            .map(|cpu| cpu.as_secs_f64() / (self.delivered as f64 / GB as f64))
+    }
+}
+
+/// 进程累计的用户态与内核态 CPU 时间
+#[cfg(target_os = "linux")]
+fn cpu_time() -> Option<Duration> {
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let to_duration = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok()?.parse().ok()
+}
+
+/// 半结构化的文本，lz4 能压到原来的几分之一，接近日志、源码一类的真实负载
+fn payload_pool() -> Bytes {
+    let mut pool = String::with_capacity(MB + 128);
+    let mut line = 0u64;
+    while pool.len() < MB {
+        let seed = line.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        pool.push_str(&format!(
+            "{line:08} falcon chunk seed={seed:016x} status=ok latency_us={}\n",
+            seed % 10_000
+        ));
+        line += 1;
+    }
+    Bytes::from(pool)
+}
+
+async fn bind() -> (UdpFramed<MsgCodec, Arc<UdpSocket>>, SocketAddr) {
+    let sock = Arc::new(UdpSocket::bind("[::1]:0").await.unwrap());
+    let addr = sock.local_addr().unwrap();
+    (UdpFramed::new(sock, MsgCodec::default()), addr)
+}
+
+async fn run(variant: Variant, total: usize, pool: Bytes) -> Report {
+    let (sender_host, receiver_host) = (HostId::random(), HostId::random());
+    if variant.encrypt {
+        let capabilities = if variant.compress {
+            Capabilities::DATA_COMPRESSION
+        } else {
+            Capabilities::empty()
+        };
+        establish(&sender_host, &receiver_host, capabilities).unwrap();
+    }
+    let (mut tx, _) = bind().await;
+    let (mut rx, rx_addr) = bind().await;
+    let credits = Arc::new(Semaphore::new(WINDOW));
+    let chunks = total.div_ceil(CHUNK);
+
+    // 接收端解开密文并累计载荷，每收到一个报文归还一个额度
+    let receiver = tokio::spawn({
+        let credits = credits.clone();
+        async move {
+            let (mut delivered, mut received) = (0, 0);
+            while received < chunks {
+                let Ok(Some(frame)) = timeout(IDLE, rx.next()).await else {
+                    break;
+                };
+                let msg = match frame.unwrap().0 {
+                    Msg::Sealed {
+                        host,
+                        epoch,
+                        nonce,
+                        body,
+                        ..
+                    } => open_msg(&host, &Sealed { epoch, nonce, body }).unwrap(),
+                    msg => msg,
+                };
+                let Msg::Transfer { payload, .. } = msg else {
+                    panic!("Unexpected message");
+                };
+                delivered += payload.len();
+                received += 1;
+                credits.add_permits(1);
+            }
+            (delivered, received)
+        }
+    });
+
+    let cpu_start = cpu_time();
+    let start = Instant::now();
+    for i in 0..chunks {
+        // 额度迟迟不归还说明在途报文丢了，补回整个窗口继续发
+        match timeout(IDLE, credits.acquire()).await {
+            Ok(permit) => permit.unwrap().forget(),
+            Err(_) => credits.add_permits(WINDOW),
+        }
+        let offset = i * CHUNK % (pool.len() - CHUNK);
+        let len = CHUNK.min(total - i * CHUNK);
+        let msg = Msg::Transfer {
+            host: sender_host.clone(),
+            payload: pool[offset..offset + len].to_vec(),
+        };
+        let msg = if variant.encrypt {
+            seal_msg(&receiver_host, msg).unwrap()
+        } else {
+            msg
+        };
+        tx.send((msg, rx_addr)).await.unwrap();
+    }
+    let (delivered, received) = receiver.await.unwrap();
+    let elapsed = start.elapsed();
+    let cpu = cpu_start.zip(cpu_time()).map(|(start, end)| end - start);
+    Report {
+        delivered,
+        lost: chunks - received,
+        elapsed,
+        cpu,
+    }
+}
+
+fn main() -> ExitCode {
+    let total = env_parse("FALCON_BENCH_BYTES").unwrap_or(GB);
+    let min_mbps: Option<f64> = env_parse("FALCON_BENCH_MIN_MBPS");
+    let max_cpu_per_gb: Option<f64> = env_parse("FALCON_BENCH_MAX_CPU_PER_GB");
+    // cargo bench 会附带 --bench 等参数，其余参数按名称过滤配置
+    let filters: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    let rt = Runtime::new().unwrap();
+    let pool = payload_pool();
+
+    let mut regressed = false;
+    for variant in VARIANTS {
+        if !filters.is_empty() && !filters.iter().any(|f| variant.name.contains(f.as_str())) {
+            continue;
+        }
+        let report = rt.block_on(run(variant, total, pool.clone()));
+        let cpu = report
+            .cpu_per_gb()
+            .map_or_else(|| "n/a".to_owned(), |cpu| format!("{cpu:.2}s"));
+        println!(
+            "transfer_e2e/{:<12} {:>9.1} MB/s  cpu {cpu}/GB  lost {}/{}",
+            variant.name,
+            report.mbps(),
+            report.lost,
+            total.div_ceil(CHUNK),
+        );
+        if min_mbps.is_some_and(|min| report.mbps() < min) {
+            eprintln!(
+                "{}: throughput below {} MB/s",
+                variant.name,
+                min_mbps.unwrap()
+            );
+            regressed = true;
+        }
+        if let (Some(max), Some(cpu)) = (max_cpu_per_gb, report.cpu_per_gb()) {
+            if cpu > max {
+                eprintln!("{}: {cpu:.2}s CPU per GB exceeds {max}s", variant.name);
+                regressed = true;
+            }
+        }
+    }
+    if regressed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,161 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use falcon_transfer::inbound::Offload;
+use std::{
+    net::SocketAddr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, runtime::Runtime};
+
+/// 与数据报文接近的大小
+const DATAGRAM: usize = 1200;
+/// 每轮收发的报文数，不超过接收缓冲，避免回环上丢包
+const COUNT: usize = 32;
+
+static RT: OnceLock<Runtime> = OnceLock::new();
+
+fn rt() -> &'static Runtime {
+    RT.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+async fn pair() -> (UdpSocket, UdpSocket, SocketAddr) {
+    let tx = UdpSocket::bind("[::1]:0").await.unwrap();
+    let rx = UdpSocket::bind("[::1]:0").await.unwrap();
+    let dst = rx.local_addr().unwrap();
+    (tx, rx, dst)
+}
+
+/// 收完这一轮，避免堆积在接收缓冲里
+fn drain(rx: &UdpSocket) {
+    let mut buf = vec![0; DATAGRAM];
+    while rx.try_recv_from(&mut buf).is_ok() {}
+}
+
+fn bench_send(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_send");
+    group.throughput(Throughput::Elements(COUNT as u64));
+    let payload = vec![0x5a; DATAGRAM * COUNT];
+    let datagrams = payload.chunks(DATAGRAM).collect::<Vec<_>>();
+
+    group.bench_function("send_to", |b| {
+        b.to_async(rt()).iter_custom(|iters| {
+            let datagrams = &datagrams;
+            async move {
+                let (tx, rx, dst) = pair().await;
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    for datagram in datagrams {
+                        tx.send_to(datagram, dst).await.unwrap();
+                    }
+                    elapsed += start.elapsed();
+                    drain(&rx);
+                }
+                elapsed
+            }
+        })
+    });
+
+    group.bench_function("sendmmsg", |b| {
+        b.to_async(rt()).iter_custom(|iters| {
+            let datagrams = &datagrams;
+            async move {
+                let (tx, rx, dst) = pair().await;
+                let offload = Offload::disabled().with_batch_size(COUNT);
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    offload.send_many(&tx, datagrams, dst).await.unwrap();
+                    elapsed += start.elapsed();
+                    drain(&rx);
+                }
+                elapsed
+            }
+        })
+    });
+
+    group.bench_function("gso", |b| {
+        b.to_async(rt()).iter_custom(|iters| {
+            let payload = &payload;
+            async move {
+                let (tx, rx, dst) = pair().await;
+                // 不支持时退回 sendmmsg，结果与上一项接近
+                let offload = Offload::detect(&tx).with_batch_size(COUNT);
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    offload
+                        .send_bulk(&tx, payload, DATAGRAM, dst)
+                        .await
+                        .unwrap();
+                    elapsed += start.elapsed();
+                    drain(&rx);
+                }
+                elapsed
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_recv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("udp_recv");
+    group.throughput(Throughput::Elements(COUNT as u64));
+    let payload = vec![0x5a; DATAGRAM * COUNT];
+    let datagrams = payload.chunks(DATAGRAM).collect::<Vec<_>>();
+
+    group.bench_function("recv_from", |b| {
+        b.to_async(rt()).iter_custom(|iters| {
+            let datagrams = &datagrams;
+            async move {
+                let (tx, rx, dst) = pair().await;
+                let mut buf = vec![0; DATAGRAM];
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    for datagram in datagrams {
+                        tx.send_to(datagram, dst).await.unwrap();
+                    }
+                    let start = Instant::now();
+                    for _ in 0..COUNT {
+                        rx.recv_from(&mut buf).await.unwrap();
+                    }
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            }
+        })
+    });
+
+    group.bench_function("recvmmsg", |b| {
+        b.to_async(rt()).iter_custom(|iters| {
+            let datagrams = &datagrams;
+            async move {
+                let (tx, rx, dst) = pair().await;
+                let offload = Offload::disabled().with_batch_size(COUNT);
+                let mut bufs = vec![vec![0; DATAGRAM]; COUNT];
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    for datagram in datagrams {
+                        tx.send_to(datagram, dst).await.unwrap();
+                    }
+                    let start = Instant::now();
+                    let mut received = 0;
+                    while received < COUNT {
+                        received += offload.recv_many(&rx, &mut bufs).await.unwrap().len();
+                    }
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_send, bench_recv);
+criterion_main!(benches);
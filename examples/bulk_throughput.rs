@@ -0,0 +1,55 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use falcon_transfer::inbound::{GRO_BUF_LEN, Offload, offload_stats, split_segments};
+use tokio::{net::UdpSocket, time::sleep};
+
+const SEGMENT: usize = 1200;
+
+// cargo run --release --example bulk_throughput --features gso
+#[tokio::main]
+async fn main() {
+    let tx = UdpSocket::bind("[::1]:0").await.unwrap();
+    let rx = UdpSocket::bind("[::1]:0").await.unwrap();
+    let dst = rx.local_addr().unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    tokio::spawn({
+        let received = received.clone();
+        async move {
+            let offload = Offload::detect(&rx);
+            let mut buf = vec![0u8; GRO_BUF_LEN];
+            loop {
+                let (len, segment, _) = offload.recv_bulk(&rx, &mut buf).await.unwrap();
+                let n = split_segments(&buf[..len], segment).count();
+                received.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let offload = Offload::detect(&tx);
+        let payload = vec![0xAB; SEGMENT * 32];
+        loop {
+            offload
+                .send_bulk(&tx, &payload, SEGMENT, dst)
+                .await
+                .unwrap();
+        }
+    });
+    sleep(Duration::from_secs(8)).await;
+    let stats = offload_stats();
+    println!(
+        "Sent: {} datagrams, {:.1} per syscall",
+        stats.sent_datagrams.load(Ordering::Relaxed),
+        stats.send_ratio()
+    );
+    println!(
+        "Received: {} datagrams, {:.1} per syscall",
+        received.load(Ordering::Relaxed),
+        stats.recv_ratio()
+    );
+}
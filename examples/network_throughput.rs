@@ -4,11 +4,11 @@ use std::{
 };
 
 use falcon_transfer::{
-    inbound::{Inbound, Msg, split_group},
+    inbound::{Inbound, InboundPolicy, Msg, split_group},
     link::Uid,
 };
 use futures::SinkExt;
-use tokio::time::sleep;
+use tokio::{sync::mpsc, time::sleep};
 
 #[derive(Default)]
 struct BenchMetrics {
@@ -20,8 +20,10 @@ struct BenchMetrics {
 #[tokio::main]
 async fn main() {
     let metrics = Arc::new(BenchMetrics::default());
-    let (tx, rx) = split_group().await.unwrap();
-    let (_inbound, mut rx) = Inbound::receiving(rx).await;
+    let (tx, streams, _handles) = split_group().await.unwrap();
+    // 示例不跟随网卡变化，不会再接入新的流
+    let (_attach, attach) = mpsc::unbounded_channel();
+    let (inbound, mut rx) = Inbound::receiving(streams, attach, InboundPolicy::default()).await;
     tokio::spawn({
         let metrics = metrics.clone();
         async move {
@@ -70,4 +72,5 @@ async fn main() {
         "Cached: {}",
         metrics.cached.load(std::sync::atomic::Ordering::Relaxed)
     );
+    println!("Queue: {:?}", inbound.queue_depth());
 }
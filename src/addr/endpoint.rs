@@ -70,6 +70,10 @@ impl EndPoint {
         self.addr.get_std()
     }
 
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
     pub fn scoped_addr(&self) -> &ScopedAddr {
         &self.addr
     }
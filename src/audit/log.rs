@@ -0,0 +1,456 @@
+use crate::{inbound::HostId, session::Fingerprint};
+use camino::{Utf8Path, Utf8PathBuf};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    iter,
+    sync::{
+        Mutex, OnceLock,
+        mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::warn;
+
+/// 等待写盘的事件上限，写盘跟不上时丢弃新事件而不是阻塞调用方
+const QUEUE_LEN: usize = 1024;
+/// 单个对端在一个窗口内最多记录的事件数，握手失败的洪泛不会刷满日志
+const SOURCE_BURST: u32 = 32;
+const SOURCE_WINDOW: Duration = Duration::from_secs(60);
+/// 同时统计的对端上限，满了先清理过期窗口，仍然满时不再接纳新对端
+const MAX_SOURCES: usize = 4096;
+/// 日志超过这个大小时轮转
+const ROTATE_LEN: u64 = 16 * 1024 * 1024;
+/// 保留的轮转文件数，`audit.jsonl.1` 最新
+const ROTATE_KEEP: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("data dir was not found")]
+    DataDirNotFound,
+    #[error("audit chain broken at record {0}")]
+    ChainBroken(u64),
+}
+
+/// 需要审计的安全相关事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditEvent {
+    Pairing { host: String },
+    KeyChanged { host: String, fingerprint: String },
+    HandshakeFailed { host: String, reason: String },
+    TransferRejected { host: String, reason: String },
+    PolicyOverride { host: String, detail: String },
+}
+
+impl AuditEvent {
+    pub fn pairing(host: &HostId) -> Self {
+        Self::Pairing {
+            host: host.to_string(),
+        }
+    }
+
+    pub fn key_changed(host: &HostId, fingerprint: Fingerprint) -> Self {
+        Self::KeyChanged {
+            host: host.to_string(),
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    pub fn handshake_failed(host: &HostId, reason: impl ToString) -> Self {
+        Self::HandshakeFailed {
+            host: host.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn transfer_rejected(host: &HostId, reason: impl ToString) -> Self {
+        Self::TransferRejected {
+            host: host.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn policy_override(host: &HostId, detail: impl ToString) -> Self {
+        Self::PolicyOverride {
+            host: host.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    /// 事件涉及的对端，限流按它计数
+    fn host(&self) -> &str {
+        match self {
+            Self::Pairing { host }
+            | Self::KeyChanged { host, .. }
+            | Self::HandshakeFailed { host, .. }
+            | Self::TransferRejected { host, .. }
+            | Self::PolicyOverride { host, .. } => host,
+        }
+    }
+}
+
+/// 按对端限制审计事件的频率
+#[derive(Debug, Default)]
+struct SourceGate {
+    sources: HashMap<String, (Instant, u32)>,
+}
+
+impl SourceGate {
+    fn admit(&mut self, host: &str, now: Instant) -> bool {
+        if !self.sources.contains_key(host) && self.sources.len() >= MAX_SOURCES {
+            self.sources
+                .retain(|_, (since, _)| now.duration_since(*since) < SOURCE_WINDOW);
+            if self.sources.len() >= MAX_SOURCES {
+                return false;
+            }
+        }
+        let (since, count) = self.sources.entry(host.to_string()).or_insert((now, 0));
+        if now.duration_since(*since) >= SOURCE_WINDOW {
+            (*since, *count) = (now, 0);
+        }
+        *count += 1;
+        *count <= SOURCE_BURST
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChainBody {
+    seq: u64,
+    at: u64,
+    event: AuditEvent,
+    prev: String,
+}
+
+/// 一行一条记录，hash 覆盖 body 与上一条的 hash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    #[serde(flatten)]
+    body: ChainBody,
+    hash: String,
+}
+
+impl AuditRecord {
+    const GENESIS: &str = "";
+
+    fn seal(body: ChainBody) -> Result<Self, AuditError> {
+        let hash = blake3::hash(&serde_json::to_vec(&body)?)
+            .to_hex()
+            .to_string();
+        Ok(Self { body, hash })
+    }
+
+    fn is_sealed(&self) -> Result<bool, AuditError> {
+        Ok(Self::seal(self.body.clone())?.hash == self.hash)
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.body.seq
+    }
+
+    pub fn event(&self) -> &AuditEvent {
+        &self.body.event
+    }
+}
+
+struct ChainHead {
+    file: File,
+    len: u64,
+    next_seq: u64,
+    last_hash: String,
+}
+
+/// 只追加的审计日志，哈希链使任意篡改都能被 verify 发现
+///
+/// 日志按大小轮转，链跨文件延续；最旧的轮转文件被删掉后，保留下来的第一条作为链的起点
+pub struct AuditLog {
+    path: Utf8PathBuf,
+    rotate_len: u64,
+    head: Mutex<ChainHead>,
+}
+
+pub fn audit_log() -> Result<&'static AuditLog, AuditError> {
+    static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+    AUDIT_LOG.get_or_try_init(|| {
+        let prj_dir = ProjectDirs::from("com", "tritium", "falcon_transfer")
+            .ok_or(AuditError::DataDirNotFound)?;
+        let data_dir = prj_dir.data_local_dir();
+        std::fs::create_dir_all(data_dir)?;
+        let path = Utf8PathBuf::from_path_buf(data_dir.join("audit.jsonl"))
+            .map_err(|_| AuditError::DataDirNotFound)?;
+        AuditLog::open(&path)
+    })
+}
+
+/// 事件交给后台线程写盘，调用方不等待文件同步
+struct Auditor {
+    queue: SyncSender<AuditEvent>,
+    gate: Mutex<SourceGate>,
+}
+
+fn auditor() -> &'static Auditor {
+    static AUDITOR: OnceLock<Auditor> = OnceLock::new();
+    AUDITOR.get_or_init(|| {
+        let (queue, events) = sync_channel(QUEUE_LEN);
+        let writer = thread::Builder::new()
+            .name("audit".into())
+            .spawn(move || write_events(events));
+        if let Err(err) = writer {
+            warn!("Failed to start audit writer: {err}");
+        }
+        Auditor {
+            queue,
+            gate: Default::default(),
+        }
+    })
+}
+
+/// 一次取走排队的全部事件，整批只同步一次
+fn write_events(events: Receiver<AuditEvent>) {
+    while let Ok(event) = events.recv() {
+        let batch = iter::once(event).chain(events.try_iter().take(QUEUE_LEN));
+        if let Err(err) = audit_log().and_then(|log| log.append_batch(batch)) {
+            warn!("Failed to write audit record: {err}");
+        }
+    }
+}
+
+/// 审计失败不应影响业务流程，只记录警告
+pub fn audit(event: AuditEvent) {
+    let auditor = auditor();
+    if !auditor
+        .gate
+        .lock()
+        .unwrap()
+        .admit(event.host(), Instant::now())
+    {
+        return;
+    }
+    match auditor.queue.try_send(event) {
+        Ok(()) => {}
+        Err(TrySendError::Full(event)) => warn!("Audit queue is full, drop {event:?}"),
+        Err(TrySendError::Disconnected(_)) => warn!("Audit writer has stopped"),
+    }
+}
+
+impl AuditLog {
+    pub fn open(path: &Utf8Path) -> Result<Self, AuditError> {
+        // 刚轮转完时当前文件还是空的，链头在上一个文件里
+        let mut last = None;
+        for file in Self::chain_files(path).iter().rev() {
+            last = Self::records(file)?.pop();
+            if last.is_some() {
+                break;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        let (next_seq, last_hash) = last
+            .map(|r| (r.body.seq + 1, r.hash))
+            .unwrap_or((0, AuditRecord::GENESIS.to_string()));
+        Ok(Self {
+            path: path.to_owned(),
+            rotate_len: ROTATE_LEN,
+            head: Mutex::new(ChainHead {
+                file,
+                len,
+                next_seq,
+                last_hash,
+            }),
+        })
+    }
+
+    pub fn with_rotate_len(mut self, rotate_len: u64) -> Self {
+        self.rotate_len = rotate_len;
+        self
+    }
+
+    pub fn append(&self, event: AuditEvent) -> Result<AuditRecord, AuditError> {
+        let mut head = self.head.lock().unwrap();
+        let record = self.write(&mut head, event)?;
+        head.file.sync_data()?;
+        Ok(record)
+    }
+
+    /// 逐条接链写入，最后同步一次
+    pub fn append_batch(
+        &self,
+        events: impl IntoIterator<Item = AuditEvent>,
+    ) -> Result<(), AuditError> {
+        let mut head = self.head.lock().unwrap();
+        for event in events {
+            self.write(&mut head, event)?;
+        }
+        Ok(head.file.sync_data()?)
+    }
+
+    fn write(&self, head: &mut ChainHead, event: AuditEvent) -> Result<AuditRecord, AuditError> {
+        if head.len >= self.rotate_len {
+            self.rotate(head)?;
+        }
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let record = AuditRecord::seal(ChainBody {
+            seq: head.next_seq,
+            at,
+            event,
+            prev: head.last_hash.clone(),
+        })?;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        head.file.write_all(&line)?;
+        head.len += line.len() as u64;
+        head.next_seq += 1;
+        head.last_hash = record.hash.clone();
+        Ok(record)
+    }
+
+    /// `audit.jsonl` -> `audit.jsonl.1` -> ... ，超出保留数的最旧文件被覆盖
+    fn rotate(&self, head: &mut ChainHead) -> Result<(), AuditError> {
+        head.file.sync_data()?;
+        for n in (1..ROTATE_KEEP).rev() {
+            let from = Self::rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, Self::rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, Self::rotated(&self.path, 1))?;
+        head.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        head.len = 0;
+        Ok(())
+    }
+
+    fn rotated(path: &Utf8Path, n: usize) -> Utf8PathBuf {
+        format!("{path}.{n}").into()
+    }
+
+    /// 现存的日志文件，从旧到新
+    fn chain_files(path: &Utf8Path) -> Vec<Utf8PathBuf> {
+        (1..=ROTATE_KEEP)
+            .rev()
+            .map(|n| Self::rotated(path, n))
+            .chain(iter::once(path.to_owned()))
+            .filter(|file| file.exists())
+            .collect()
+    }
+
+    fn records(path: &Utf8Path) -> Result<Vec<AuditRecord>, AuditError> {
+        BufReader::new(File::open(path)?)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+
+    /// 校验整条哈希链，返回记录条数
+    pub fn verify(&self) -> Result<usize, AuditError> {
+        let _head = self.head.lock().unwrap();
+        let files = Self::chain_files(&self.path);
+        // 轮转文件都在时更早的记录可能已被删掉，链可以从中间开始
+        let truncated = files.len() > ROTATE_KEEP;
+        let mut prev: Option<(u64, String)> = None;
+        let mut count = 0;
+        for file in &files {
+            for record in Self::records(file)? {
+                let (seq, linked) = match &prev {
+                    Some((seq, hash)) => (seq + 1, record.body.prev == *hash),
+                    None if truncated => (record.body.seq, true),
+                    None => (0, record.body.prev == AuditRecord::GENESIS),
+                };
+                if record.body.seq != seq || !linked || !record.is_sealed()? {
+                    return Err(AuditError::ChainBroken(seq));
+                }
+                prev = Some((seq, record.hash));
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 导出全部记录（JSON lines），从最旧的轮转文件开始
+    pub fn export(&self, mut writer: impl Write) -> Result<usize, AuditError> {
+        let count = self.verify()?;
+        let _head = self.head.lock().unwrap();
+        for file in Self::chain_files(&self.path) {
+            std::io::copy(&mut File::open(&file)?, &mut writer)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn chain_and_tamper() {
+        let dir = tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("audit.jsonl")).unwrap();
+        let host = HostId::random();
+        {
+            let log = AuditLog::open(&path).unwrap();
+            log.append(AuditEvent::pairing(&host)).unwrap();
+            log.append(AuditEvent::transfer_rejected(&host, "no token"))
+                .unwrap();
+        }
+        // 重新打开后继续接链
+        let log = AuditLog::open(&path).unwrap();
+        let record = log.append(AuditEvent::pairing(&host)).unwrap();
+        assert_eq!(record.seq(), 2);
+        assert_eq!(log.verify().unwrap(), 3);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replacen("no token", "allowed", 1)).unwrap();
+        assert!(matches!(log.verify(), Err(AuditError::ChainBroken(1))));
+    }
+
+    #[test]
+    fn chain_spans_rotation() {
+        let dir = tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("audit.jsonl")).unwrap();
+        let host = HostId::random();
+        let log = AuditLog::open(&path).unwrap().with_rotate_len(1);
+        log.append_batch((0..3).map(|_| AuditEvent::pairing(&host)))
+            .unwrap();
+        assert!(AuditLog::rotated(&path, 2).exists());
+        assert_eq!(log.verify().unwrap(), 3);
+        drop(log);
+
+        // 最旧的文件被轮转掉后，剩下的记录仍然能接上
+        let log = AuditLog::open(&path).unwrap().with_rotate_len(1);
+        let record = log.append(AuditEvent::pairing(&host)).unwrap();
+        assert_eq!(record.seq(), 3);
+        assert_eq!(log.verify().unwrap(), 4);
+        log.append(AuditEvent::pairing(&host)).unwrap();
+        assert_eq!(log.verify().unwrap(), 4);
+
+        std::fs::remove_file(AuditLog::rotated(&path, 2)).unwrap();
+        assert!(matches!(log.verify(), Err(AuditError::ChainBroken(_))));
+    }
+
+    #[test]
+    fn gate_limits_per_source() {
+        let mut gate = SourceGate::default();
+        let now = Instant::now();
+        for _ in 0..SOURCE_BURST {
+            assert!(gate.admit("noisy", now));
+        }
+        assert!(!gate.admit("noisy", now));
+        assert!(gate.admit("quiet", now));
+        assert!(gate.admit("noisy", now + SOURCE_WINDOW));
+    }
+}
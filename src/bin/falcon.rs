@@ -0,0 +1,400 @@
+use camino::Utf8PathBuf;
+use clap::{Parser, Subcommand};
+use falcon_transfer::{
+    Transfer,
+    config::{ConfigItem, config_manager},
+    history::Outcome,
+    hot_file::{ByteSize, percent},
+    inbound::{HostId, list_interfaces},
+    link::HandshakeStage,
+    policy::TransferPriority,
+    session::static_keys,
+    transfer::IncomingTransfer,
+};
+use futures::StreamExt;
+use std::{error::Error, pin::pin, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines, Stdin},
+    time::{Instant, sleep},
+};
+use tracing::info;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "falcon", version, about = "局域网点对点文件传输")]
+struct Cli {
+    /// 不带子命令时等同于 `receive`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 向对端发出邀约，等对方下载完再退出
+    Send {
+        file: Utf8PathBuf,
+        /// 接收方的主机 id，可以在对方的 `falcon peers` 输出中找到
+        #[arg(long)]
+        to: HostId,
+        /// 等待对端出现并完成握手的秒数
+        #[arg(long, default_value_t = 30)]
+        wait: u64,
+    },
+    /// 常驻运行，接收对端发来的文件
+    Receive {
+        /// 不询问，接受所有邀约
+        #[arg(long, short)]
+        yes: bool,
+        /// 接受的传输的优先级：high、normal 或 low
+        #[arg(long, default_value_t = TransferPriority::Normal)]
+        priority: TransferPriority,
+        /// 打开终端仪表盘
+        #[cfg(feature = "tui")]
+        #[arg(long)]
+        tui: bool,
+    },
+    /// 列出局域网内发现的对端
+    Peers {
+        /// 等待发现的秒数
+        #[arg(long, default_value_t = 3)]
+        wait: u64,
+    },
+    /// 显示链路、socket 计数、入站队列与最近的传输
+    Status {
+        #[arg(long, default_value_t = 3)]
+        wait: u64,
+        /// 显示最近结束的传输条数
+        #[arg(long, default_value_t = 10)]
+        history: usize,
+    },
+    /// 按传输历史重新校验下载目录，列出损坏、缺失与无记录的文件
+    Verify {
+        /// 每秒读盘字节数上限，0 表示不限速
+        #[arg(long, default_value_t = 0)]
+        limit: u64,
+    },
+    /// 列出本机网卡及其地址、MTU 与启用状态
+    Ifaces,
+    /// 打印本机公钥指纹，供对方带外核对
+    Fingerprint,
+    /// 读写配置文件
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// 列出所有配置项的当前值
+    List,
+    Get {
+        item: ConfigItem,
+    },
+    /// 校验后写入配置文件，运行中的实例会自动重新加载
+    Set {
+        item: ConfigItem,
+        value: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    // 日志走 stderr，stdout 只留给命令的输出
+    #[cfg(feature = "tui")]
+    let tui = matches!(cli.command, Some(Command::Receive { tui: true, .. }));
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
+    if !tui {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    match cli.command.unwrap_or(Command::Receive {
+        yes: false,
+        priority: TransferPriority::Normal,
+        #[cfg(feature = "tui")]
+        tui: false,
+    }) {
+        Command::Send { file, to, wait } => send(file, to, Duration::from_secs(wait)).await,
+        #[cfg(feature = "tui")]
+        Command::Receive { tui: true, .. } => {
+            let transfer = Transfer::start().await?;
+            falcon_transfer::tui::run_dashboard(&transfer).await?;
+            Ok(())
+        }
+        Command::Receive { yes, priority, .. } => receive(yes, priority).await,
+        Command::Peers { wait } => peers(Duration::from_secs(wait)).await,
+        Command::Status { wait, history } => status(Duration::from_secs(wait), history).await,
+        Command::Verify { limit } => verify(limit).await,
+        Command::Ifaces => ifaces(),
+        Command::Fingerprint => {
+            println!("{}", static_keys()?.fingerprint());
+            Ok(())
+        }
+        Command::Config { action } => config(action).await,
+    }
+}
+
+async fn send(file: Utf8PathBuf, to: HostId, wait: Duration) -> Result<()> {
+    let transfer = Transfer::start().await?;
+    wait_for_peer(&transfer, &to, wait).await?;
+    let mut progress = pin!(transfer.progress());
+    let hash = transfer.send_file(&file, &to).await?;
+    println!("Offered {file} to {to}, waiting for it to download");
+    loop {
+        tokio::select! {
+            Some(update) = progress.next() => {
+                if update.hash != hash {
+                    continue;
+                }
+                println!(
+                    "{} of {} ({:.0}%)",
+                    ByteSize(update.done),
+                    ByteSize(update.total),
+                    percent(update.done, update.total).floor()
+                );
+                if update.done >= update.total {
+                    break;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    transfer.shutdown().await;
+    Ok(())
+}
+
+/// 对端出现在对端表中且握手完成才能收到邀约
+async fn wait_for_peer(transfer: &Transfer, host: &HostId, wait: Duration) -> Result<()> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let established = transfer
+            .peers()
+            .iter()
+            .any(|peer| peer.host == *host && peer.handshake == HandshakeStage::Established);
+        if established {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("{host} was not reachable within {wait:?}").into());
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn receive(yes: bool, priority: TransferPriority) -> Result<()> {
+    let transfer = Arc::new(Transfer::start().await?);
+    println!("Receiving as {}", transfer.local_id());
+    #[cfg(feature = "rpc")]
+    let _control = control_server(&transfer).await?;
+    let mut incoming = pin!(transfer.subscribe_incoming());
+    let mut completions = pin!(transfer.completions());
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        tokio::select! {
+            Some(offer) = incoming.next() => {
+                match yes || confirm(&mut stdin, &offer).await? {
+                    true => {
+                        transfer.set_priority(offer.hash, priority);
+                        transfer.accept(&offer).await?;
+                    }
+                    false => transfer.decline(&offer, "declined by user").await?,
+                }
+            }
+            Some(done) = completions.next() => {
+                info!("Received {} from {}", done.path, done.peer);
+                let size = ByteSize(done.size as usize);
+                println!("{}\t{size}\t{:?}", done.path, done.elapsed);
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    transfer.shutdown().await;
+    Ok(())
+}
+
+/// 配置了监听地址时开启本地控制接口
+#[cfg(feature = "rpc")]
+async fn control_server(
+    transfer: &Arc<Transfer>,
+) -> Result<Option<falcon_transfer::rpc::ControlServer>> {
+    let listen = config_manager()?
+        .get_typed::<std::net::SocketAddr>(ConfigItem::RpcListen)
+        .await;
+    match listen {
+        Ok(listen) => Ok(Some(
+            falcon_transfer::rpc::ControlServer::run(transfer.clone(), listen).await?,
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+async fn confirm(stdin: &mut Lines<BufReader<Stdin>>, offer: &IncomingTransfer) -> Result<bool> {
+    println!(
+        "Accept {} ({} bytes) from {}? [y/N]",
+        offer.file_name, offer.size, offer.peer
+    );
+    let answer = stdin.next_line().await?.unwrap_or_default();
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+async fn peers(wait: Duration) -> Result<()> {
+    let transfer = Transfer::start().await?;
+    sleep(wait).await;
+    for peer in transfer.peers() {
+        let endpoints = peer
+            .endpoints
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{}\t{}\t{:?}\t{endpoints}",
+            peer.host,
+            peer.host_name.as_deref().unwrap_or("-"),
+            peer.handshake
+        );
+    }
+    transfer.shutdown().await;
+    Ok(())
+}
+
+async fn status(wait: Duration, history: usize) -> Result<()> {
+    let transfer = Transfer::start().await?;
+    sleep(wait).await;
+    let status = transfer.status();
+    println!("Host {}", transfer.local_id());
+    println!("Fingerprint {}", transfer.fingerprint());
+    println!(
+        "{} peers on {} links",
+        status.peer_count(),
+        status.links.len()
+    );
+    for link in &status.links {
+        let via = link
+            .via
+            .as_ref()
+            .map(|via| format!(" via {via}"))
+            .unwrap_or_default();
+        let health = match link.healthy {
+            true => "up",
+            false => "down",
+        };
+        println!(
+            "  {}\t{} -> {}\t{health}\tsrtt {:?}{via}",
+            link.host, link.local, link.remote, link.srtt
+        );
+    }
+    for socket in transfer.metrics() {
+        println!(
+            "  socket {}\tsent {}/{}B\treceived {}/{}B\terrors {}",
+            socket.local,
+            socket.packets_sent,
+            socket.bytes_sent,
+            socket.packets_received,
+            socket.bytes_received,
+            socket.decode_errors
+        );
+    }
+    let discovery = transfer.discovery_counts();
+    println!(
+        "Discovery {} received, {} duplicate, {} throttled",
+        discovery.received, discovery.duplicate, discovery.throttled
+    );
+    let queue = transfer.inbound_queue();
+    println!("Inbound queue {}/{}", queue.depth, queue.capacity);
+    let history = transfer.history(history)?;
+    if !history.is_empty() {
+        println!("Recent transfers");
+    }
+    for record in history {
+        let outcome = match record.outcome {
+            Outcome::Completed => "done".to_string(),
+            Outcome::Failed { reason } => format!("failed: {reason}"),
+            Outcome::Partial { unavailable } => {
+                format!("partial: {} unavailable", ByteSize(unavailable as usize))
+            }
+        };
+        println!(
+            "  {}\t{}\t{}\t{}/s\t{outcome}",
+            record.file_name,
+            ByteSize(record.size as usize),
+            record.peer,
+            ByteSize(record.speed as usize)
+        );
+    }
+    transfer.shutdown().await;
+    Ok(())
+}
+
+async fn verify(limit: u64) -> Result<()> {
+    let transfer = Transfer::start().await?;
+    let report = transfer.verify_downloads(limit).await?.report().await?;
+    for (label, paths) in [
+        ("corrupted", &report.corrupted),
+        ("missing", &report.missing),
+        ("unknown", &report.unknown),
+    ] {
+        for path in paths {
+            println!("  {label}\t{path}");
+        }
+    }
+    for (path, err) in &report.errors {
+        println!("  error\t{path}\t{err}");
+    }
+    println!(
+        "{} ok, {} corrupted, {} missing, {} unknown, {} errors",
+        report.ok.len(),
+        report.corrupted.len(),
+        report.missing.len(),
+        report.unknown.len(),
+        report.errors.len()
+    );
+    transfer.shutdown().await;
+    Ok(())
+}
+
+fn ifaces() -> Result<()> {
+    for nic in list_interfaces()? {
+        let state = match nic.up {
+            true => "up",
+            false => "down",
+        };
+        let mac = nic.mac.map(|mac| mac.to_string());
+        let mtu = nic.mtu.map(|mtu| mtu.to_string());
+        println!(
+            "{}\t{}\t{state}\tmac {}\tmtu {}",
+            nic.index,
+            nic.friendly_name,
+            mac.as_deref().unwrap_or("-"),
+            mtu.as_deref().unwrap_or("-")
+        );
+        for addr in &nic.link_local {
+            println!("  {addr}%{}", nic.index);
+        }
+        for addr in &nic.global {
+            println!("  {addr}");
+        }
+    }
+    Ok(())
+}
+
+async fn config(action: ConfigAction) -> Result<()> {
+    let cfg = config_manager()?;
+    match action {
+        ConfigAction::List => {
+            for &item in ConfigItem::ALL {
+                println!("{item} = {:?}", cfg.get(item).await);
+            }
+        }
+        ConfigAction::Get { item } => println!("{}", cfg.get(item).await),
+        ConfigAction::Set { item, value } => {
+            cfg.set_checked(item, &value).await?;
+            println!("{item} = {value:?}");
+        }
+    }
+    Ok(())
+}
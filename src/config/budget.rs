@@ -0,0 +1,97 @@
+use super::{ConfigItem, ConfigManager};
+
+/// 由一个总内存预算推导出各处缓冲区、队列的大小
+///
+/// 在小内存设备上只需要调整 `memory_budget` 一项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    total: usize,
+}
+
+impl MemoryBudget {
+    const KB: usize = 1024;
+    const MB: usize = 1024 * Self::KB;
+    /// 低于此值的预算没有意义，直接抬升
+    const MIN_TOTAL: usize = 8 * Self::MB;
+    /// 估算单条消息在通道中占用的内存
+    const MSG_FOOTPRINT: usize = 64 * Self::KB;
+
+    pub fn new(total: usize) -> Self {
+        Self {
+            total: total.max(Self::MIN_TOTAL),
+        }
+    }
+
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let total = cfg
+            .get(ConfigItem::MemoryBudget)
+            .await
+            .parse()
+            .unwrap_or_else(|_| {
+                ConfigItem::MemoryBudget
+                    .default()
+                    .parse()
+                    .expect("default memory budget must be valid")
+            });
+        Self::new(total)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// HotFile 脏数据上限：预算的一半
+    pub fn dirty_cap(&self) -> usize {
+        self.total / 2
+    }
+
+    /// 预读缓存：预算的 1/8
+    pub fn read_ahead(&self) -> usize {
+        self.total / 8
+    }
+
+    /// 有界通道容量：预算的 1/4 按单条消息估算
+    pub fn channel_bound(&self) -> usize {
+        (self.total / 4 / Self::MSG_FOOTPRINT).clamp(16, 4096)
+    }
+
+    /// 分享任务对每个对端已发出、未确认的字节上限：预算的 1/16，至少容纳几轮确认
+    pub fn upload_window(&self) -> usize {
+        (self.total / 16).max(4 * Self::MB)
+    }
+
+    /// 分享任务每批发送的块数
+    pub fn batch_size(&self) -> usize {
+        (self.total / (32 * Self::MB)).clamp(4, 64)
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::new(256 * Self::MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratios() {
+        let budget = MemoryBudget::new(256 * MemoryBudget::MB);
+        assert_eq!(budget.dirty_cap(), 128 * MemoryBudget::MB);
+        assert_eq!(budget.read_ahead(), 32 * MemoryBudget::MB);
+        assert_eq!(budget.channel_bound(), 1024);
+        assert_eq!(budget.batch_size(), 8);
+        assert_eq!(budget.upload_window(), 16 * MemoryBudget::MB);
+    }
+
+    #[test]
+    fn tiny_budget_is_clamped() {
+        let budget = MemoryBudget::new(0);
+        assert_eq!(budget.total(), MemoryBudget::MIN_TOTAL);
+        assert_eq!(budget.channel_bound(), 32);
+        assert_eq!(budget.batch_size(), 4);
+        assert_eq!(budget.upload_window(), 4 * MemoryBudget::MB);
+    }
+}
@@ -6,6 +6,7 @@ use notify_debouncer_mini::{
     notify::{self, RecursiveMode},
 };
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fmt::Display,
     fs::OpenOptions,
@@ -15,11 +16,13 @@ use std::{
 };
 use thiserror::Error;
 use tokio::{
-    sync::{RwLock as AsyncRwLock, mpsc},
+    sync::{RwLock as AsyncRwLock, mpsc, watch},
     task::yield_now,
 };
 use tracing::error;
 
+use super::{default_download_dir, default_host_id, default_host_name};
+
 #[derive(Debug, Error)]
 pub enum ConfigManagerError {
     #[error(transparent)]
@@ -32,6 +35,14 @@ pub enum ConfigManagerError {
     WriteError(#[from] atomicwrites::Error<std::io::Error>),
     #[error("config dir was not found")]
     ConfigDirNotFound,
+    #[error("Unknown config item {0:?}")]
+    UnknownItem(String),
+    #[error("Invalid value {value:?} for {item}: {reason}")]
+    Invalid {
+        item: ConfigItem,
+        value: String,
+        reason: String,
+    },
 }
 
 type Settings = HashMap<String, String>;
@@ -39,18 +50,145 @@ type Settings = HashMap<String, String>;
 pub struct ConfigManager {
     settings: Arc<AsyncRwLock<Settings>>,
     abs_path: Utf8PathBuf, // suffix must be .toml
+    changed: watch::Sender<()>, // 配置文件刷新成功后通知订阅者
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigItem {
+    HostId,
+    HostName,
     ProtocolPort,
+    ProtocolVersion,
+    DownloadDir,
+    MemoryBudget,
+    RetentionMaxEntries,
+    RetentionMaxAgeDays,
+    RetentionMaxBytes,
+    IoBatchSize,
+    MaxUploadBps,
+    MaxDownloadBps,
+    HandshakeTimeoutMs,
+    HandshakeMaxRetries,
+    HotFileMaxDirtyBytes,
+    HotFileMaxDirtyAgeMs,
+    InboundQueueCapacity,
+    InboundOverflow,
+    RelayEndpoint,
+    RelayServe,
+    PunchAttempts,
+    PunchIntervalMs,
+    RelayHost,
+    ProbeIntervalMs,
+    ProbeDeadAfter,
+    MaxActiveTransfers,
+    AnnounceIntervalMs,
+    AnnounceMaxIntervalMs,
+    PeerTtlMs,
+    DataCompression,
+    MetricsListen,
+    DiskLowWatermark,
+    DiskCheckIntervalMs,
+    HotFileReadAhead,
+    DecodeErrorBudget,
+    DecodeBanMs,
+    DiscoveryGroup,
+    DiscoveryHops,
+    RpcListen,
+    DownloadPerPeer,
+    DownloadCollision,
+    ControlEventsPerSec,
+    ChaosDrop,
+    ChaosDuplicate,
+    ChaosDelay,
+    ChaosMaxDelayMs,
+    BulkDscp,
+    ControlDscp,
+    RekeyBytes,
+    RekeyIntervalSecs,
+    SendRetries,
+    SendBackoffMs,
+    SendMaxBackoffMs,
+    IfaceAllowlist,
+    IfaceDenylist,
+    PlaintextData,
+    KeepaliveIntervalSecs,
+    SessionIdleTimeoutSecs,
+    DiscoveryDedupMs,
+    DiscoveryPerHostPerSec,
+    MtuLan,
+    MtuWan,
+    MtuRelayed,
+    MtuProbe,
 }
 
 impl From<ConfigItem> for &'static str {
     #[inline]
     fn from(item: ConfigItem) -> Self {
         match item {
+            ConfigItem::HostId => "host_id",
+            ConfigItem::HostName => "host_name",
             ConfigItem::ProtocolPort => "protocol_port",
+            ConfigItem::ProtocolVersion => "protocol_version",
+            ConfigItem::DownloadDir => "download_dir",
+            ConfigItem::MemoryBudget => "memory_budget",
+            ConfigItem::RetentionMaxEntries => "retention_max_entries",
+            ConfigItem::RetentionMaxAgeDays => "retention_max_age_days",
+            ConfigItem::RetentionMaxBytes => "retention_max_bytes",
+            ConfigItem::IoBatchSize => "io_batch_size",
+            ConfigItem::MaxUploadBps => "max_upload_bps",
+            ConfigItem::MaxDownloadBps => "max_download_bps",
+            ConfigItem::HandshakeTimeoutMs => "handshake_timeout_ms",
+            ConfigItem::HandshakeMaxRetries => "handshake_max_retries",
+            ConfigItem::HotFileMaxDirtyBytes => "hot_file_max_dirty_bytes",
+            ConfigItem::HotFileMaxDirtyAgeMs => "hot_file_max_dirty_age_ms",
+            ConfigItem::InboundQueueCapacity => "inbound_queue_capacity",
+            ConfigItem::InboundOverflow => "inbound_overflow",
+            ConfigItem::RelayEndpoint => "relay_endpoint",
+            ConfigItem::RelayServe => "relay_serve",
+            ConfigItem::PunchAttempts => "punch_attempts",
+            ConfigItem::PunchIntervalMs => "punch_interval_ms",
+            ConfigItem::RelayHost => "relay_host",
+            ConfigItem::ProbeIntervalMs => "probe_interval_ms",
+            ConfigItem::ProbeDeadAfter => "probe_dead_after",
+            ConfigItem::MaxActiveTransfers => "max_active_transfers",
+            ConfigItem::AnnounceIntervalMs => "announce_interval_ms",
+            ConfigItem::AnnounceMaxIntervalMs => "announce_max_interval_ms",
+            ConfigItem::PeerTtlMs => "peer_ttl_ms",
+            ConfigItem::DataCompression => "data_compression",
+            ConfigItem::MetricsListen => "metrics_listen",
+            ConfigItem::DiskLowWatermark => "disk_low_watermark",
+            ConfigItem::DiskCheckIntervalMs => "disk_check_interval_ms",
+            ConfigItem::HotFileReadAhead => "hot_file_read_ahead",
+            ConfigItem::DecodeErrorBudget => "decode_error_budget",
+            ConfigItem::DecodeBanMs => "decode_ban_ms",
+            ConfigItem::DiscoveryGroup => "discovery_group",
+            ConfigItem::DiscoveryHops => "discovery_hops",
+            ConfigItem::RpcListen => "rpc_listen",
+            ConfigItem::DownloadPerPeer => "download_per_peer",
+            ConfigItem::DownloadCollision => "download_collision",
+            ConfigItem::ControlEventsPerSec => "control_events_per_sec",
+            ConfigItem::ChaosDrop => "chaos_drop",
+            ConfigItem::ChaosDuplicate => "chaos_duplicate",
+            ConfigItem::ChaosDelay => "chaos_delay",
+            ConfigItem::ChaosMaxDelayMs => "chaos_max_delay_ms",
+            ConfigItem::BulkDscp => "bulk_dscp",
+            ConfigItem::ControlDscp => "control_dscp",
+            ConfigItem::RekeyBytes => "rekey_bytes",
+            ConfigItem::RekeyIntervalSecs => "rekey_interval_secs",
+            ConfigItem::SendRetries => "send_retries",
+            ConfigItem::SendBackoffMs => "send_backoff_ms",
+            ConfigItem::SendMaxBackoffMs => "send_max_backoff_ms",
+            ConfigItem::IfaceAllowlist => "iface_allowlist",
+            ConfigItem::IfaceDenylist => "iface_denylist",
+            ConfigItem::PlaintextData => "plaintext_data",
+            ConfigItem::KeepaliveIntervalSecs => "keepalive_interval_secs",
+            ConfigItem::SessionIdleTimeoutSecs => "session_idle_timeout_secs",
+            ConfigItem::DiscoveryDedupMs => "discovery_dedup_ms",
+            ConfigItem::DiscoveryPerHostPerSec => "discovery_per_host_per_sec",
+            ConfigItem::MtuLan => "mtu_lan",
+            ConfigItem::MtuWan => "mtu_wan",
+            ConfigItem::MtuRelayed => "mtu_relayed",
+            ConfigItem::MtuProbe => "mtu_probe",
         }
     }
 }
@@ -63,10 +201,148 @@ impl Display for ConfigItem {
 }
 
 impl ConfigItem {
+    pub const ALL: &'static [ConfigItem] = &[
+        ConfigItem::HostId,
+        ConfigItem::HostName,
+        ConfigItem::ProtocolPort,
+        ConfigItem::ProtocolVersion,
+        ConfigItem::DownloadDir,
+        ConfigItem::MemoryBudget,
+        ConfigItem::RetentionMaxEntries,
+        ConfigItem::RetentionMaxAgeDays,
+        ConfigItem::RetentionMaxBytes,
+        ConfigItem::IoBatchSize,
+        ConfigItem::MaxUploadBps,
+        ConfigItem::MaxDownloadBps,
+        ConfigItem::HandshakeTimeoutMs,
+        ConfigItem::HandshakeMaxRetries,
+        ConfigItem::HotFileMaxDirtyBytes,
+        ConfigItem::HotFileMaxDirtyAgeMs,
+        ConfigItem::InboundQueueCapacity,
+        ConfigItem::InboundOverflow,
+        ConfigItem::RelayEndpoint,
+        ConfigItem::RelayServe,
+        ConfigItem::PunchAttempts,
+        ConfigItem::PunchIntervalMs,
+        ConfigItem::RelayHost,
+        ConfigItem::ProbeIntervalMs,
+        ConfigItem::ProbeDeadAfter,
+        ConfigItem::MaxActiveTransfers,
+        ConfigItem::AnnounceIntervalMs,
+        ConfigItem::AnnounceMaxIntervalMs,
+        ConfigItem::PeerTtlMs,
+        ConfigItem::DataCompression,
+        ConfigItem::MetricsListen,
+        ConfigItem::DiskLowWatermark,
+        ConfigItem::DiskCheckIntervalMs,
+        ConfigItem::HotFileReadAhead,
+        ConfigItem::DecodeErrorBudget,
+        ConfigItem::DecodeBanMs,
+        ConfigItem::DiscoveryGroup,
+        ConfigItem::DiscoveryHops,
+        ConfigItem::RpcListen,
+        ConfigItem::DownloadPerPeer,
+        ConfigItem::DownloadCollision,
+        ConfigItem::ControlEventsPerSec,
+        ConfigItem::ChaosDrop,
+        ConfigItem::ChaosDuplicate,
+        ConfigItem::ChaosDelay,
+        ConfigItem::ChaosMaxDelayMs,
+        ConfigItem::BulkDscp,
+        ConfigItem::ControlDscp,
+        ConfigItem::RekeyBytes,
+        ConfigItem::RekeyIntervalSecs,
+        ConfigItem::SendRetries,
+        ConfigItem::SendBackoffMs,
+        ConfigItem::SendMaxBackoffMs,
+        ConfigItem::IfaceAllowlist,
+        ConfigItem::IfaceDenylist,
+        ConfigItem::PlaintextData,
+        ConfigItem::KeepaliveIntervalSecs,
+        ConfigItem::SessionIdleTimeoutSecs,
+        ConfigItem::DiscoveryDedupMs,
+        ConfigItem::DiscoveryPerHostPerSec,
+        ConfigItem::MtuLan,
+        ConfigItem::MtuWan,
+        ConfigItem::MtuRelayed,
+        ConfigItem::MtuProbe,
+    ];
+
+    /// 部分默认值与机器相关，在进程内只生成一次
     #[inline]
-    fn default(&self) -> &'static str {
+    pub(crate) fn default(&self) -> Cow<'static, str> {
         match self {
+            ConfigItem::HostId => Cow::Borrowed(default_host_id()),
+            ConfigItem::HostName => Cow::Owned(default_host_name()),
+            ConfigItem::DownloadDir => Cow::Owned(default_download_dir()),
+            item => Cow::Borrowed(item.static_default()),
+        }
+    }
+
+    fn static_default(&self) -> &'static str {
+        match self {
+            ConfigItem::HostId | ConfigItem::HostName | ConfigItem::DownloadDir => "",
             ConfigItem::ProtocolPort => "5555",
+            ConfigItem::ProtocolVersion => "0",
+            ConfigItem::MemoryBudget => "268435456", // 256MiB
+            ConfigItem::RetentionMaxEntries => "10000",
+            ConfigItem::RetentionMaxAgeDays => "90",
+            ConfigItem::RetentionMaxBytes => "67108864", // 64MiB
+            ConfigItem::IoBatchSize => "32",
+            ConfigItem::MaxUploadBps => "0", // 0 表示不限速
+            ConfigItem::MaxDownloadBps => "0",
+            ConfigItem::HandshakeTimeoutMs => "5000",
+            ConfigItem::HandshakeMaxRetries => "3",
+            ConfigItem::HotFileMaxDirtyBytes => "67108864",
+            ConfigItem::HotFileMaxDirtyAgeMs => "5000",
+            ConfigItem::InboundQueueCapacity => "4096",
+            ConfigItem::InboundOverflow => "park",
+            ConfigItem::RelayEndpoint => "",
+            ConfigItem::RelayServe => "false",
+            ConfigItem::PunchAttempts => "10",
+            ConfigItem::PunchIntervalMs => "200",
+            ConfigItem::RelayHost => "",
+            ConfigItem::ProbeIntervalMs => "2000",
+            ConfigItem::ProbeDeadAfter => "3",
+            ConfigItem::MaxActiveTransfers => "4",
+            ConfigItem::AnnounceIntervalMs => "5000",
+            ConfigItem::AnnounceMaxIntervalMs => "60000",
+            ConfigItem::PeerTtlMs => "30000",
+            ConfigItem::DataCompression => "true",
+            ConfigItem::MetricsListen => "",
+            ConfigItem::DiskLowWatermark => "536870912",
+            ConfigItem::DiskCheckIntervalMs => "5000",
+            ConfigItem::HotFileReadAhead => "4",
+            ConfigItem::DecodeErrorBudget => "32",
+            ConfigItem::DecodeBanMs => "60000",
+            ConfigItem::DiscoveryGroup => "ff02::fa1c",
+            ConfigItem::DiscoveryHops => "1",
+            ConfigItem::RpcListen => "",
+            ConfigItem::DownloadPerPeer => "false",
+            ConfigItem::DownloadCollision => "rename",
+            ConfigItem::ControlEventsPerSec => "256",
+            ConfigItem::ChaosDrop => "0",
+            ConfigItem::ChaosDuplicate => "0",
+            ConfigItem::ChaosDelay => "0",
+            ConfigItem::ChaosMaxDelayMs => "200",
+            ConfigItem::BulkDscp => "cs1",
+            ConfigItem::ControlDscp => "default",
+            ConfigItem::RekeyBytes => "1073741824",
+            ConfigItem::RekeyIntervalSecs => "600",
+            ConfigItem::SendRetries => "3",
+            ConfigItem::SendBackoffMs => "50",
+            ConfigItem::SendMaxBackoffMs => "2000",
+            ConfigItem::IfaceAllowlist => "",
+            ConfigItem::IfaceDenylist => "",
+            ConfigItem::PlaintextData => "false",
+            ConfigItem::KeepaliveIntervalSecs => "15",
+            ConfigItem::SessionIdleTimeoutSecs => "180",
+            ConfigItem::DiscoveryDedupMs => "2000",
+            ConfigItem::DiscoveryPerHostPerSec => "32",
+            ConfigItem::MtuLan => "1500",
+            ConfigItem::MtuWan => "1500",
+            ConfigItem::MtuRelayed => "1280",
+            ConfigItem::MtuProbe => "true",
         }
     }
 }
@@ -80,13 +356,16 @@ impl ConfigManager {
     }
 
     fn default_inner() -> Settings {
-        use ConfigItem::*;
-        HashMap::from_iter([(ProtocolPort.to_string(), ProtocolPort.default().to_string())])
+        ConfigItem::ALL
+            .iter()
+            .map(|item| (item.to_string(), item.default().to_string()))
+            .collect()
     }
 
     pub fn create(path: &Utf8Path) -> Result<Self, ConfigManagerError> {
+        // 首次运行时生成带注释的默认配置
         if !path.exists() {
-            std::fs::File::create(path)?;
+            std::fs::write(path, Self::default_toml())?;
         }
         let abs_path = path.canonicalize_utf8()?;
         let cfg = match Self::load_config(path) {
@@ -94,8 +373,13 @@ impl ConfigManager {
             Err(err) => {
                 error!("{err}, construct config manager in default values");
                 let settings = Arc::new(AsyncRwLock::new(Self::default_inner()));
-                Self::watch(abs_path.clone(), settings.clone())?;
-                return Ok(Self { settings, abs_path });
+                let changed = watch::Sender::new(());
+                Self::watch(abs_path.clone(), settings.clone(), changed.clone())?;
+                return Ok(Self {
+                    settings,
+                    abs_path,
+                    changed,
+                });
             }
         };
         let settings = cfg.try_deserialize::<Settings>().unwrap_or_else(|err| {
@@ -103,8 +387,18 @@ impl ConfigManager {
             Self::default_inner()
         });
         let settings = Arc::new(AsyncRwLock::new(settings));
-        Self::watch(abs_path.clone(), settings.clone())?;
-        Ok(Self { settings, abs_path })
+        let changed = watch::Sender::new(());
+        Self::watch(abs_path.clone(), settings.clone(), changed.clone())?;
+        Ok(Self {
+            settings,
+            abs_path,
+            changed,
+        })
+    }
+
+    /// 每次配置文件成功刷新后收到通知
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
     }
 
     /// 没有就映射到默认值
@@ -158,6 +452,7 @@ impl ConfigManager {
     pub(crate) fn watch(
         config_path: Utf8PathBuf,
         settings: Arc<AsyncRwLock<Settings>>,
+        changed: watch::Sender<()>,
     ) -> Result<(), notify::Error> {
         let (tx, mut rx) = mpsc::channel(1);
         let mut debouncer = new_debouncer(Duration::from_secs(1), move |result| {
@@ -171,7 +466,10 @@ impl ConfigManager {
         tokio::spawn(async move {
             let _debouncer = debouncer; // 移动到这个协程里防止被drop
             while let Some(_) = rx.recv().await {
-                let _ = Self::refresh(&config_path, settings.clone()).await; // 有时候刷新会失败，这是由于load时格式解析失败，直到格式正确锁中的内容才会被真正刷新
+                // 有时候刷新会失败，这是由于load时格式解析失败，直到格式正确锁中的内容才会被真正刷新
+                if Self::refresh(&config_path, settings.clone()).await.is_ok() {
+                    changed.send_replace(());
+                }
                 yield_now().await;
             }
         });
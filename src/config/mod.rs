@@ -1,6 +1,10 @@
+mod budget;
 mod config;
 mod instance;
+mod schema;
 
 
+pub use budget::*;
 pub use config::*;
-pub use instance::*; 
\ No newline at end of file
+pub use instance::*;
+pub(crate) use schema::*;
@@ -0,0 +1,412 @@
+use super::{ConfigItem, ConfigManager, ConfigManagerError};
+use crate::{
+    addr::EndPoint,
+    inbound::{Dscp, IfacePatterns, Overflow},
+    link::Uid,
+    task::CollisionPolicy,
+};
+use camino::Utf8PathBuf;
+use directories::UserDirs;
+use std::{
+    fmt::Display,
+    net::{Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::OnceLock,
+};
+use tracing::info;
+
+/// 首次运行时生成，之后由配置文件持久化
+pub(crate) fn default_host_id() -> &'static str {
+    static HOST_ID: OnceLock<String> = OnceLock::new();
+    HOST_ID.get_or_init(|| Uid::random().to_string())
+}
+
+pub(crate) fn default_host_name() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_else(|| "falcon".to_string())
+}
+
+pub(crate) fn default_download_dir() -> String {
+    UserDirs::new()
+        .and_then(|dirs| dirs.download_dir().map(|dir| dir.to_path_buf()))
+        .and_then(|dir| Utf8PathBuf::from_path_buf(dir).ok())
+        .map(|dir| dir.join("falcon").into_string())
+        .unwrap_or_else(|| "./downloads".to_string())
+}
+
+fn check<T>(raw: &str) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    raw.parse::<T>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+fn check_probability(raw: &str) -> Result<(), String> {
+    match raw.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(()),
+        Ok(p) => Err(format!("probability {p} is not within 0 and 1")),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+impl ConfigItem {
+    /// 写入默认配置文件时的注释
+    pub fn doc(&self) -> &'static str {
+        match self {
+            ConfigItem::HostId => "本机唯一标识，32 位带校验的 base32，首次运行时随机生成",
+            ConfigItem::HostName => "展示给其他主机的名称",
+            ConfigItem::ProtocolPort => "协议监听端口",
+            ConfigItem::ProtocolVersion => "协议版本号",
+            ConfigItem::DownloadDir => "接收文件的保存目录",
+            ConfigItem::MemoryBudget => "热文件缓存的内存预算（字节）",
+            ConfigItem::RetentionMaxEntries => "历史记录最多保留的条目数",
+            ConfigItem::RetentionMaxAgeDays => "历史记录最多保留的天数",
+            ConfigItem::RetentionMaxBytes => "历史记录最多占用的字节数",
+            ConfigItem::IoBatchSize => "单次批量收发的最大报文数（1..=64）",
+            ConfigItem::MaxUploadBps => "全局上传限速（字节每秒），0 表示不限速",
+            ConfigItem::MaxDownloadBps => "全局下载限速（字节每秒），0 表示不限速",
+            ConfigItem::HandshakeTimeoutMs => "单次握手的超时时间（毫秒）",
+            ConfigItem::HandshakeMaxRetries => "握手超时后发起方最多重试的次数",
+            ConfigItem::HotFileMaxDirtyBytes => "单个热文件允许积压的脏数据上限（字节），超过后后台立即落盘",
+            ConfigItem::HotFileMaxDirtyAgeMs => "脏数据最长驻留时间（毫秒），到期后后台落盘",
+            ConfigItem::InboundQueueCapacity => "入站队列容量（条），事件循环跟不上时按溢出策略处理",
+            ConfigItem::InboundOverflow => "入站队列满时的策略：park 暂停读取 socket，drop 丢弃新到的报文",
+            ConfigItem::RelayEndpoint => "打洞协调使用的中继对端，格式 [ipv6]:port，留空则只使用局域网发现",
+            ConfigItem::RelayServe => "是否为其他对端充当打洞与转发中继，打洞需要本机有公网地址",
+            ConfigItem::PunchAttempts => "收到介绍后向对端反射地址发送打洞探测的次数",
+            ConfigItem::PunchIntervalMs => "打洞探测的发送间隔（毫秒）",
+            ConfigItem::RelayHost => "直连全部失效时请求转发的中继主机 ID，留空则不自动回退",
+            ConfigItem::ProbeIntervalMs => "向每条直连链路发送保活探测的间隔（毫秒）",
+            ConfigItem::ProbeDeadAfter => "连续丢失多少个探测后判定链路失效",
+            ConfigItem::MaxActiveTransfers => "同时进行的下载任务数上限，其余任务排队等待",
+            ConfigItem::AnnounceIntervalMs => "组播发现报文的基础间隔（毫秒），网络安静时逐步退避",
+            ConfigItem::AnnounceMaxIntervalMs => "发现报文退避后的最大间隔（毫秒）",
+            ConfigItem::PeerTtlMs => "多久没有收到对端任何报文后将其从对端表中移除（毫秒）",
+            ConfigItem::DataCompression => "对同样启用压缩的对端，较大的数据报文使用 lz4 压缩",
+            ConfigItem::MetricsListen => "本地 Prometheus 指标监听地址，如 127.0.0.1:9555，留空不导出",
+            ConfigItem::DiskLowWatermark => "剩余磁盘空间低于该值（字节）时暂停下载，空间恢复后自动继续",
+            ConfigItem::DiskCheckIntervalMs => "下载过程中检查剩余磁盘空间的间隔（毫秒）",
+            ConfigItem::HotFileReadAhead => "分享文件时检测到顺序读取后额外预读的段数，0 表示关闭",
+            ConfigItem::DecodeErrorBudget => "同一来源地址在统计窗口内允许的畸形报文数，0 表示不限制",
+            ConfigItem::DecodeBanMs => "超出畸形报文预算的来源被屏蔽的时长（毫秒）",
+            ConfigItem::DiscoveryGroup => "链路本地发现使用的 IPv6 组播组，需为组播地址",
+            ConfigItem::DiscoveryHops => "发现报文的组播跳数，1 表示不出本链路",
+            ConfigItem::RpcListen => "本地控制接口（JSON-RPC）监听地址，如 127.0.0.1:9556，只允许回环地址，留空不开启",
+            ConfigItem::DownloadPerPeer => "按对端分子目录保存接收的文件",
+            ConfigItem::DownloadCollision => "目标文件已存在时的处理：rename 加序号、overwrite 覆盖、reject 拒绝",
+            ConfigItem::ControlEventsPerSec => "每个对端每秒最多处理的控制事件数（邀约、拒绝、请求），超出的直接丢弃",
+            ConfigItem::ChaosDrop => "启用 chaos 特性时，收到的报文被丢弃的概率，0 到 1",
+            ConfigItem::ChaosDuplicate => "启用 chaos 特性时，收到的报文被重复交出的概率，0 到 1",
+            ConfigItem::ChaosDelay => "启用 chaos 特性时，收到的报文被推迟交出的概率，0 到 1",
+            ConfigItem::ChaosMaxDelayMs => "启用 chaos 特性时，被推迟的报文最多推迟的毫秒数",
+            ConfigItem::BulkDscp => "批量数据报文的 DSCP 标记，如 cs1、le、default 或 0..=63 的数值",
+            ConfigItem::ControlDscp => "控制消息（发现、握手、邀约等）的 DSCP 标记，取值同 bulk_dscp",
+            ConfigItem::RekeyBytes => "发送这么多字节后与对端协调更换会话密钥，0 表示不按字节数换钥",
+            ConfigItem::RekeyIntervalSecs => "同一会话密钥最长使用的秒数，0 表示不按时间换钥",
+            ConfigItem::SendRetries => "控制消息发送失败后的最大重试次数",
+            ConfigItem::SendBackoffMs => "第一次重试前等待的毫秒数，之后逐次翻倍",
+            ConfigItem::SendMaxBackoffMs => "两次重试之间最多等待的毫秒数",
+            ConfigItem::IfaceAllowlist => "只在名称匹配的网卡上绑定与发现，逗号分隔，支持 * 与 ? 通配，留空表示不限制",
+            ConfigItem::IfaceDenylist => "名称匹配的网卡不参与绑定与发现，优先于白名单，格式同 iface_allowlist",
+            ConfigItem::PlaintextData => "仅用于可信局域网测速：对同样开启的对端，数据报文不加密，握手与控制消息仍加密认证",
+            ConfigItem::KeepaliveIntervalSecs => "会话没有报文发出时发送保活的间隔秒数，0 表示不发送保活",
+            ConfigItem::SessionIdleTimeoutSecs => "会话空闲多少秒后过期，下次发送时重新握手，0 表示永不过期",
+            ConfigItem::DiscoveryDedupMs => "同一对端经同一条路径重复到达的发现报文在这段时间（毫秒）内只处理一次，0 表示不去重",
+            ConfigItem::DiscoveryPerHostPerSec => "每个对端每秒最多处理的发现报文数，多网卡同时收到广播时超出的直接丢弃",
+            ConfigItem::MtuLan => "局域网直连链路的最大 IP 报文长度，不超过网卡 MTU",
+            ConfigItem::MtuWan => "公网直连链路的最大 IP 报文长度，开启探测时作为探测上限",
+            ConfigItem::MtuRelayed => "经中继转发的链路使用的最大 IP 报文长度",
+            ConfigItem::MtuProbe => "是否探测公网链路的路径 MTU，关闭时直接使用 mtu_wan",
+        }
+    }
+
+    /// 校验原始字符串能否解析为该项的类型
+    pub fn validate(&self, raw: &str) -> Result<(), String> {
+        match self {
+            // 旧版本生成的 nanoid 仍然有效，读取时迁移
+            ConfigItem::HostId => match raw.parse::<Uid>() {
+                Ok(_) => Ok(()),
+                Err(err) => Uid::from_legacy(raw).map(|_| ()).map_err(|_| err.to_string()),
+            },
+            ConfigItem::HostName => match raw.trim().is_empty() {
+                true => Err("host name must not be empty".to_string()),
+                false => Ok(()),
+            },
+            ConfigItem::ProtocolPort => match raw.parse::<u16>() {
+                Ok(0) => Err("port must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::ProtocolVersion => check::<u8>(raw),
+            ConfigItem::DownloadDir => match raw.is_empty() {
+                true => Err("download dir must not be empty".to_string()),
+                false => Ok(()),
+            },
+            ConfigItem::IoBatchSize => match raw.parse::<usize>() {
+                Ok(1..=64) => Ok(()),
+                Ok(n) => Err(format!("{n} is out of range 1..=64")),
+                Err(err) => Err(err.to_string()),
+            },
+            ConfigItem::MemoryBudget
+            | ConfigItem::RetentionMaxEntries
+            | ConfigItem::RetentionMaxBytes => check::<usize>(raw),
+            ConfigItem::HandshakeTimeoutMs
+            | ConfigItem::HotFileMaxDirtyAgeMs
+            | ConfigItem::PunchIntervalMs
+            | ConfigItem::ProbeIntervalMs
+            | ConfigItem::AnnounceIntervalMs
+            | ConfigItem::AnnounceMaxIntervalMs
+            | ConfigItem::PeerTtlMs
+            | ConfigItem::DiskCheckIntervalMs
+            | ConfigItem::DecodeBanMs => {
+                match raw.parse::<u64>() {
+                    Ok(0) => Err("duration must not be 0".to_string()),
+                    result => result.map(|_| ()).map_err(|err| err.to_string()),
+                }
+            }
+            ConfigItem::HandshakeMaxRetries => check::<u8>(raw),
+            ConfigItem::RetentionMaxAgeDays
+            | ConfigItem::MaxUploadBps
+            | ConfigItem::MaxDownloadBps => check::<u64>(raw),
+            ConfigItem::HotFileMaxDirtyBytes | ConfigItem::HotFileReadAhead => check::<usize>(raw),
+            ConfigItem::InboundQueueCapacity => match raw.parse::<usize>() {
+                Ok(0) => Err("capacity must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::InboundOverflow => check::<Overflow>(raw),
+            ConfigItem::RelayEndpoint => match raw.trim().is_empty() {
+                true => Ok(()),
+                false => check::<EndPoint>(raw),
+            },
+            ConfigItem::RelayServe => check::<bool>(raw),
+            ConfigItem::PunchAttempts => check::<u8>(raw),
+            ConfigItem::RelayHost => match raw.trim().is_empty() {
+                true => Ok(()),
+                false => check::<Uid>(raw),
+            },
+            ConfigItem::ProbeDeadAfter => match raw.parse::<u8>() {
+                Ok(0) => Err("threshold must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::MaxActiveTransfers => match raw.parse::<usize>() {
+                Ok(0) => Err("limit must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::DataCompression => check::<bool>(raw),
+            ConfigItem::MetricsListen => match raw.trim().is_empty() {
+                true => Ok(()),
+                false => check::<SocketAddr>(raw),
+            },
+            ConfigItem::DiskLowWatermark => check::<u64>(raw),
+            ConfigItem::DecodeErrorBudget => check::<u32>(raw),
+            ConfigItem::DiscoveryGroup => match raw.parse::<Ipv6Addr>() {
+                Ok(group) if group.is_multicast() => Ok(()),
+                Ok(group) => Err(format!("{group} is not a multicast address")),
+                Err(err) => Err(err.to_string()),
+            },
+            ConfigItem::DiscoveryHops => check::<u8>(raw),
+            ConfigItem::RpcListen => match raw.trim() {
+                "" => Ok(()),
+                raw => match raw.parse::<SocketAddr>() {
+                    Ok(addr) if addr.ip().is_loopback() => Ok(()),
+                    Ok(addr) => Err(format!("{addr} is not a loopback address")),
+                    Err(err) => Err(err.to_string()),
+                },
+            },
+            ConfigItem::DownloadPerPeer => check::<bool>(raw),
+            ConfigItem::DownloadCollision => check::<CollisionPolicy>(raw),
+            ConfigItem::ControlEventsPerSec => match raw.parse::<u32>() {
+                Ok(0) => Err("limit must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::ChaosDrop => check_probability(raw),
+            ConfigItem::ChaosDuplicate => check_probability(raw),
+            ConfigItem::ChaosDelay => check_probability(raw),
+            ConfigItem::ChaosMaxDelayMs => check::<u64>(raw),
+            ConfigItem::BulkDscp => check::<Dscp>(raw),
+            ConfigItem::ControlDscp => check::<Dscp>(raw),
+            ConfigItem::RekeyBytes => check::<u64>(raw),
+            ConfigItem::RekeyIntervalSecs => check::<u64>(raw),
+            ConfigItem::SendRetries => check::<u32>(raw),
+            ConfigItem::SendBackoffMs => check::<u64>(raw),
+            ConfigItem::SendMaxBackoffMs => check::<u64>(raw),
+            ConfigItem::IfaceAllowlist => check::<IfacePatterns>(raw),
+            ConfigItem::IfaceDenylist => check::<IfacePatterns>(raw),
+            ConfigItem::PlaintextData => check::<bool>(raw),
+            ConfigItem::KeepaliveIntervalSecs => check::<u64>(raw),
+            ConfigItem::SessionIdleTimeoutSecs => check::<u64>(raw),
+            ConfigItem::DiscoveryDedupMs => check::<u64>(raw),
+            ConfigItem::DiscoveryPerHostPerSec => match raw.parse::<u32>() {
+                Ok(0) => Err("limit must not be 0".to_string()),
+                result => result.map(|_| ()).map_err(|err| err.to_string()),
+            },
+            ConfigItem::MtuLan => check::<usize>(raw),
+            ConfigItem::MtuWan => check::<usize>(raw),
+            ConfigItem::MtuRelayed => check::<usize>(raw),
+            ConfigItem::MtuProbe => check::<bool>(raw),
+        }
+    }
+}
+
+/// 按配置文件中的键名查找
+impl FromStr for ConfigItem {
+    type Err = ConfigManagerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConfigItem::ALL
+            .iter()
+            .copied()
+            .find(|item| <&'static str>::from(*item) == s)
+            .ok_or_else(|| ConfigManagerError::UnknownItem(s.to_string()))
+    }
+}
+
+impl ConfigManager {
+    /// 校验后写入配置文件，值与默认配置一样按字符串保存
+    pub async fn set_checked(&self, item: ConfigItem, raw: &str) -> Result<(), ConfigManagerError> {
+        item.validate(raw)
+            .map_err(|reason| ConfigManagerError::Invalid {
+                item,
+                value: raw.to_string(),
+                reason,
+            })?;
+        self.set(item, toml::Value::String(raw.to_string())).await
+    }
+
+    /// 本机标识，旧版本的 nanoid 在这里换成规范形式写回
+    ///
+    /// 之后配置、协议与日志看到的都是同一个字符串
+    pub async fn host_id(&self) -> Result<Uid, ConfigManagerError> {
+        let raw = self.get(ConfigItem::HostId).await;
+        if let Ok(uid) = raw.parse() {
+            return Ok(uid);
+        }
+        let uid = Uid::from_legacy(&raw).map_err(|err| ConfigManagerError::Invalid {
+            item: ConfigItem::HostId,
+            value: raw.clone(),
+            reason: err.to_string(),
+        })?;
+        self.set_checked(ConfigItem::HostId, &uid.to_string()).await?;
+        info!("Migrated legacy host id {raw} to {uid}");
+        Ok(uid)
+    }
+
+    /// 读取并解析为具体类型，校验失败时返回 `Invalid`
+    pub async fn get_typed<T>(&self, item: ConfigItem) -> Result<T, ConfigManagerError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(item).await;
+        let invalid = |reason: String| ConfigManagerError::Invalid {
+            item,
+            value: value.clone(),
+            reason,
+        };
+        item.validate(&value).map_err(invalid)?;
+        value.parse().map_err(|err: T::Err| invalid(err.to_string()))
+    }
+
+    /// 校验所有配置项，返回第一个错误
+    pub async fn validate_all(&self) -> Result<(), ConfigManagerError> {
+        for &item in ConfigItem::ALL {
+            let value = self.get(item).await;
+            item.validate(&value)
+                .map_err(|reason| ConfigManagerError::Invalid {
+                    item,
+                    value,
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// 带注释的默认配置，值统一写成字符串以便反序列化为 `Settings`
+    pub fn default_toml() -> String {
+        ConfigItem::ALL
+            .iter()
+            .map(|item| {
+                let value = toml::Value::String(item.default().into_owned());
+                format!("# {}\n{} = {}\n", item.doc(), item, value)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        for item in ConfigItem::ALL {
+            assert!(item.validate(&item.default()).is_ok(), "{item}");
+        }
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert!(ConfigItem::ProtocolPort.validate("0").is_err());
+        assert!(ConfigItem::ProtocolPort.validate("65536").is_err());
+        assert!(ConfigItem::ProtocolVersion.validate("256").is_err());
+        assert!(ConfigItem::DiscoveryGroup.validate("ff02::fa1c").is_ok());
+        assert!(ConfigItem::DiscoveryGroup.validate("fe80::1").is_err());
+        assert!(ConfigItem::RpcListen.validate("").is_ok());
+        assert!(ConfigItem::RpcListen.validate("127.0.0.1:9556").is_ok());
+        assert!(ConfigItem::RpcListen.validate("0.0.0.0:9556").is_err());
+        assert!(ConfigItem::HostId.validate("short").is_err());
+        assert!(ConfigItem::IoBatchSize.validate("65").is_err());
+    }
+
+    #[test]
+    fn parse_item_key() {
+        for &item in ConfigItem::ALL {
+            assert_eq!(item.to_string().parse::<ConfigItem>().unwrap(), item);
+        }
+        assert!(matches!(
+            "no_such_item".parse::<ConfigItem>(),
+            Err(ConfigManagerError::UnknownItem(_))
+        ));
+    }
+
+    #[test]
+    fn default_toml_roundtrip() {
+        let table: toml::value::Table = toml::from_str(&ConfigManager::default_toml()).unwrap();
+        for item in ConfigItem::ALL {
+            let value = table.get(&item.to_string()).and_then(|v| v.as_str()).unwrap();
+            assert_eq!(value, item.default());
+        }
+    }
+
+    #[tokio::test]
+    async fn typed_get_on_first_run() {
+        let dir = tempfile::Builder::new().tempdir().unwrap();
+        let path: Utf8PathBuf = dir.path().join("config.toml").try_into().unwrap();
+        let manager = ConfigManager::create(&path).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().contains("# "));
+        let port: u16 = manager.get_typed(ConfigItem::ProtocolPort).await.unwrap();
+        assert_eq!(port, 5555);
+        let host: Uid = manager.get_typed(ConfigItem::HostId).await.unwrap();
+        assert_eq!(host.to_string(), default_host_id());
+        assert_eq!(manager.host_id().await.unwrap(), host);
+        manager.validate_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrate_legacy_host_id() {
+        let dir = tempfile::Builder::new().tempdir().unwrap();
+        let path: Utf8PathBuf = dir.path().join("config.toml").try_into().unwrap();
+        let legacy = "V1StGXR8_Z5jdHi6B-myTV1StGXR8_Z5";
+        let content = ConfigManager::default_toml().replace(default_host_id(), legacy);
+        std::fs::write(&path, content).unwrap();
+        let manager = ConfigManager::create(&path).unwrap();
+        manager.validate_all().await.unwrap();
+        let migrated = manager.host_id().await.unwrap();
+        assert_eq!(migrated, Uid::from_legacy(legacy).unwrap());
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains(&migrated.to_string()) && !written.contains(legacy));
+    }
+}
@@ -2,7 +2,6 @@ use crate::{
     iface::Outbound,
     utils::{HandshakeState, HostId, Msg},
 };
-use anyhow::{Context, Result, anyhow};
 use bytes::BytesMut;
 use snow::{Builder, HandshakeState as NoiseHandshakeState, params::NoiseParams};
 use std::sync::{Arc, OnceLock};
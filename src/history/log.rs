@@ -0,0 +1,332 @@
+use crate::{
+    inbound::HostId,
+    task::{Archive, ArchiveEntry, CompletedTransfer, FileHash},
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("data dir was not found")]
+    DataDirNotFound,
+}
+
+/// 传输的最终结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    Completed,
+    Failed {
+        reason: String,
+    },
+    /// 发送端读不出部分范围，其余范围已收到，临时文件保留待补齐
+    Partial {
+        unavailable: u64,
+    },
+}
+
+/// 一次结束的传输，一行一条
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// 结束时间，unix 秒
+    pub at: u64,
+    pub file_name: String,
+    pub size: u64,
+    pub peer: String,
+    pub elapsed_ms: u64,
+    /// 平均速度，字节每秒
+    pub speed: u64,
+    /// 与控制接口一致，十六进制表示
+    pub hash: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl HistoryRecord {
+    fn new(
+        file_name: String,
+        size: u64,
+        peer: &HostId,
+        hash: FileHash,
+        elapsed: Duration,
+        outcome: Outcome,
+    ) -> Self {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        // 瞬间完成的小文件不让速度变成无穷大
+        let speed = size as f64 / elapsed.as_secs_f64().max(1e-3);
+        Self {
+            at,
+            file_name,
+            size,
+            peer: peer.to_string(),
+            elapsed_ms: elapsed.as_millis() as u64,
+            speed: speed as u64,
+            hash: format!("{hash:016x}"),
+            outcome,
+        }
+    }
+
+    pub fn completed(done: &CompletedTransfer) -> Self {
+        let file_name = done.path.file_name().unwrap_or(done.path.as_str());
+        Self::new(
+            file_name.to_string(),
+            done.size,
+            &done.peer,
+            done.hash,
+            done.elapsed,
+            Outcome::Completed,
+        )
+    }
+
+    pub fn failed(
+        file_name: impl ToString,
+        size: u64,
+        peer: &HostId,
+        hash: FileHash,
+        elapsed: Duration,
+        reason: impl ToString,
+    ) -> Self {
+        let reason = reason.to_string();
+        Self::new(
+            file_name.to_string(),
+            size,
+            peer,
+            hash,
+            elapsed,
+            Outcome::Failed { reason },
+        )
+    }
+
+    pub fn partial(
+        file_name: impl ToString,
+        size: u64,
+        peer: &HostId,
+        hash: FileHash,
+        elapsed: Duration,
+        unavailable: u64,
+    ) -> Self {
+        Self::new(
+            file_name.to_string(),
+            size,
+            peer,
+            hash,
+            elapsed,
+            Outcome::Partial { unavailable },
+        )
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.outcome == Outcome::Completed
+    }
+}
+
+/// 只追加的传输历史，供 GUI 与 `falcon status` 查询
+pub struct HistoryLog {
+    path: Utf8PathBuf,
+    file: Mutex<File>,
+}
+
+pub fn history_log() -> Result<&'static HistoryLog, HistoryError> {
+    static HISTORY_LOG: OnceLock<HistoryLog> = OnceLock::new();
+    HISTORY_LOG.get_or_try_init(|| {
+        let prj_dir = ProjectDirs::from("com", "tritium", "falcon_transfer")
+            .ok_or(HistoryError::DataDirNotFound)?;
+        let data_dir = prj_dir.data_local_dir();
+        std::fs::create_dir_all(data_dir)?;
+        let path = Utf8PathBuf::from_path_buf(data_dir.join("history.jsonl"))
+            .map_err(|_| HistoryError::DataDirNotFound)?;
+        HistoryLog::open(&path)
+    })
+}
+
+/// 写历史失败不影响传输本身，只记录警告
+pub fn record_history(record: HistoryRecord) {
+    if let Err(err) = history_log().and_then(|log| log.append(&record)) {
+        warn!("Failed to write history record: {err}");
+    }
+}
+
+impl HistoryLog {
+    pub fn open(path: &Utf8Path) -> Result<Self, HistoryError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        // 上次写到一半的行先补上换行，免得和新记录粘在一起
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last != *b"\n" {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn append(&self, record: &HistoryRecord) -> Result<(), HistoryError> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// 最近的 `limit` 条记录，新的在前
+    ///
+    /// 掉电时最后一行可能只写了一半，无法解析的行直接跳过
+    pub fn recent(&self, limit: usize) -> Result<Vec<HistoryRecord>, HistoryError> {
+        let _file = self.file.lock().unwrap();
+        let mut records = Vec::new();
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(record) => records.push(record),
+                Err(err) => warn!("Skip malformed history record: {err}"),
+            }
+        }
+        records.reverse();
+        records.truncate(limit);
+        Ok(records)
+    }
+}
+
+/// 以行号作为记录 id，追加只会在末尾加行，不影响已有的编号
+impl Archive for HistoryLog {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn entries(&self) -> io::Result<Vec<ArchiveEntry>> {
+        let _file = self.file.lock().unwrap();
+        let mut entries = Vec::new();
+        for (id, line) in BufReader::new(File::open(&self.path)?).lines().enumerate() {
+            let line = line?;
+            let Ok(record) = serde_json::from_str::<HistoryRecord>(&line) else {
+                continue;
+            };
+            let Ok(hash) = FileHash::from_str_radix(&record.hash, 16) else {
+                continue;
+            };
+            entries.push(ArchiveEntry {
+                id: id as u64,
+                hash,
+                finished_at: UNIX_EPOCH + Duration::from_secs(record.at),
+                size: line.len() as u64 + 1,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// 把保留的行写入临时文件后原子替换，再重新打开供追加
+    fn remove(&self, ids: &HashSet<u64>) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        for (id, line) in BufReader::new(File::open(&self.path)?).lines().enumerate() {
+            let line = line?;
+            if !ids.contains(&(id as u64)) {
+                tmp.write_all(line.as_bytes())?;
+                tmp.write_all(b"\n")?;
+            }
+        }
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        *file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn append_and_query_recent() {
+        let dir = tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("history.jsonl")).unwrap();
+        let peer = HostId::random();
+        let done = CompletedTransfer {
+            path: "/tmp/movie.mkv".into(),
+            peer: peer.clone(),
+            hash: 42,
+            size: 2048,
+            elapsed: Duration::from_secs(2),
+            copy_method: None,
+        };
+        {
+            let log = HistoryLog::open(&path).unwrap();
+            log.append(&HistoryRecord::completed(&done)).unwrap();
+        }
+        // 重新打开后继续追加，残缺的行不影响查询
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"at\":")
+            .unwrap();
+        let log = HistoryLog::open(&path).unwrap();
+        let failed = HistoryRecord::failed("a.bin", 10, &peer, 7, Duration::ZERO, "hash mismatch");
+        log.append(&failed).unwrap();
+
+        let recent = log.recent(10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0], failed);
+        assert!(recent[1].is_completed());
+        assert_eq!(recent[1].file_name, "movie.mkv");
+        assert_eq!(recent[1].speed, 1024);
+        assert_eq!(recent[1].hash, "000000000000002a");
+        assert_eq!(log.recent(1).unwrap(), vec![failed]);
+    }
+
+    #[test]
+    fn prune_as_archive() {
+        let dir = tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("history.jsonl")).unwrap();
+        let log = HistoryLog::open(&path).unwrap();
+        let peer = HostId::random();
+        for hash in 1..=3 {
+            let record = HistoryRecord::failed("a.bin", 10, &peer, hash, Duration::ZERO, "x");
+            log.append(&record).unwrap();
+        }
+
+        let entries = log.entries().unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.hash).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        log.remove(&HashSet::from([entries[0].id, entries[2].id]))
+            .unwrap();
+        // 裁剪后仍可继续追加
+        let record = HistoryRecord::failed("b.bin", 10, &peer, 4, Duration::ZERO, "x");
+        log.append(&record).unwrap();
+        let hashes = log.entries().unwrap().into_iter().map(|e| e.hash);
+        assert_eq!(hashes.collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(log.recent(10).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,56 @@
+use std::io;
+use tokio::fs::File;
+
+/// 一次性分配磁盘空间，失败时退回 `set_len`
+#[cfg(target_os = "linux")]
+pub(super) async fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let len_off = libc::off_t::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "length overflow off_t"))?;
+    // mode 0 会分配实际的块并扩展文件长度
+    match unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len_off) } {
+        0 => Ok(()),
+        _ => {
+            let err = io::Error::last_os_error();
+            if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOSYS)) {
+                file.set_len(len).await
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) async fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    file.set_len(len).await
+}
+
+/// 释放区间占用的磁盘块，读取时得到零，返回文件系统是否支持
+#[cfg(target_os = "linux")]
+pub(super) fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+    let (Ok(offset), Ok(len)) = (libc::off_t::try_from(offset), libc::off_t::try_from(len)) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "range overflow off_t",
+        ));
+    };
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    match unsafe { libc::fallocate(file.as_raw_fd(), mode, offset, len) } {
+        0 => Ok(true),
+        _ => {
+            let err = io::Error::last_os_error();
+            if matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOSYS)) {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn punch_hole(_file: &File, _offset: u64, _len: u64) -> io::Result<bool> {
+    Ok(false)
+}
@@ -0,0 +1,149 @@
+use bytes::{Buf, Bytes, BytesMut};
+use std::{collections::VecDeque, io::IoSlice};
+
+/// 由多个 `Bytes` 组成的只读缓冲，按顺序拼接但不拷贝
+///
+/// 实现了 `Buf`，可以直接交给向量化写入
+#[derive(Debug, Default, Clone)]
+pub struct ChunkedBuf {
+    chunks: VecDeque<Bytes>,
+    remaining: usize,
+}
+
+impl ChunkedBuf {
+    pub fn push(&mut self, chunk: Bytes) {
+        if chunk.is_empty() {
+            return;
+        }
+        self.remaining += chunk.len();
+        self.chunks.push_back(chunk);
+    }
+
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bytes> {
+        self.chunks.iter()
+    }
+
+    /// 供 `send_to_vectored` 使用
+    pub fn io_slices(&self) -> Vec<IoSlice<'_>> {
+        self.chunks.iter().map(|chunk| IoSlice::new(chunk)).collect()
+    }
+
+    /// 只有一块时零拷贝，否则拷贝一次
+    pub fn into_bytes(mut self) -> Bytes {
+        match self.chunks.len() {
+            0 => Bytes::new(),
+            1 => self.chunks.pop_front().unwrap(),
+            _ => {
+                let mut buf = BytesMut::with_capacity(self.remaining);
+                for chunk in self.chunks {
+                    buf.extend_from_slice(&chunk);
+                }
+                buf.freeze()
+            }
+        }
+    }
+}
+
+impl From<Vec<Bytes>> for ChunkedBuf {
+    fn from(chunks: Vec<Bytes>) -> Self {
+        let mut buf = Self::default();
+        chunks.into_iter().for_each(|chunk| buf.push(chunk));
+        buf
+    }
+}
+
+impl Buf for ChunkedBuf {
+    fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.chunks.front().map(|c| c.as_ref()).unwrap_or_default()
+    }
+
+    fn advance(&mut self, mut cnt: usize) {
+        assert!(cnt <= self.remaining, "advance past end of ChunkedBuf");
+        self.remaining -= cnt;
+        while cnt > 0 {
+            let front = self.chunks.front_mut().unwrap();
+            if cnt < front.len() {
+                front.advance(cnt);
+                return;
+            }
+            cnt -= front.len();
+            self.chunks.pop_front();
+        }
+    }
+
+    fn chunks_vectored<'a>(&'a self, dst: &mut [IoSlice<'a>]) -> usize {
+        self.chunks
+            .iter()
+            .zip(dst.iter_mut())
+            .map(|(chunk, slot)| *slot = IoSlice::new(chunk))
+            .count()
+    }
+
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        // 落在第一块内时直接切片
+        if let Some(front) = self.chunks.front_mut()
+            && len <= front.len()
+        {
+            self.remaining -= len;
+            let bytes = front.split_to(len);
+            if front.is_empty() {
+                self.chunks.pop_front();
+            }
+            return bytes;
+        }
+        assert!(len <= self.remaining, "copy past end of ChunkedBuf");
+        let mut buf = BytesMut::with_capacity(len);
+        while buf.len() < len {
+            let take = self.chunk().len().min(len - buf.len());
+            buf.extend_from_slice(&self.chunk()[..take]);
+            self.advance(take);
+        }
+        buf.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buf_across_chunks() {
+        let mut buf = ChunkedBuf::from(vec![
+            Bytes::from_static(b"hello"),
+            Bytes::new(),
+            Bytes::from_static(b" world"),
+        ]);
+        assert_eq!(buf.chunk_count(), 2);
+        assert_eq!(buf.remaining(), 11);
+        let mut slices = [IoSlice::new(&[]); 4];
+        assert_eq!(buf.chunks_vectored(&mut slices), 2);
+        buf.advance(3);
+        assert_eq!(buf.chunk(), b"lo");
+        assert_eq!(buf.copy_to_bytes(4), Bytes::from_static(b"lo w"));
+        assert_eq!(buf.into_bytes(), Bytes::from_static(b"orld"));
+    }
+
+    #[test]
+    fn single_chunk_zero_copy() {
+        let data = Bytes::from(vec![1u8; 1024]);
+        let ptr = data.as_ptr();
+        let buf = ChunkedBuf::from(vec![data]);
+        assert_eq!(buf.into_bytes().as_ptr(), ptr);
+    }
+}
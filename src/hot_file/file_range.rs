@@ -289,29 +289,22 @@ impl FileMultiRange {
         }
     }
 
+    /// 加入区间并与重叠或相邻的区间合并，保持有序、不相交
+    ///
+    /// 二分定位受影响的区间 [left, right)，合并为一个后移除其余部分
     #[inline]
     pub fn add(&mut self, range: FileRange) {
-        if unlikely(self.inner.is_empty()) {
-            self.inner.push(range);
-        }
         let left = self.inner.partition_point(|r| r.end < range.start);
         let right = self.inner.partition_point(|r| r.start <= range.end);
-        unsafe {
-            if likely(left < right) {
-                let ranges = self.inner.as_mut_ptr();
-                let first = &mut *ranges.add(left);
-                first.start = first.start.min(range.start);
-                let last = &*ranges.add(right - 1);
-                first.end = last.end.max(range.end);
-                if right - left > 1 {
-                    let tail = self.inner.len() - right;
-                    std::ptr::copy(ranges.add(right), ranges.add(left + 1), tail);
-                    self.inner.set_len(left + 1 + tail);
-                }
-            } else {
-                self.inner.insert(left, range);
-            }
+        if likely(left == right) {
+            self.inner.insert(left, range);
+            return;
         }
+        let end = self.inner[right - 1].end.max(range.end);
+        let first = &mut self.inner[left];
+        first.start = first.start.min(range.start);
+        first.end = end;
+        self.inner.drain(left + 1..right);
     }
 
     #[inline]
@@ -409,6 +402,47 @@ impl FileMultiRange {
         })
     }
 
+    /// `total` 中不属于本集合的部分
+    #[inline]
+    pub fn complement(&self, total: FileRange) -> Self {
+        FileMultiRange::from(total).subtract(self)
+    }
+
+    /// 起点向下、终点向上对齐到 `block` 的整数倍，终点不超过 `limit`
+    ///
+    /// 原本就越过 `limit` 的区间保留原来的终点；对齐后相接的区间会合并
+    #[inline]
+    pub fn align(&self, block: usize, limit: usize) -> Self {
+        if unlikely(block <= 1) {
+            return self.clone();
+        }
+        let mut result = Self::new();
+        for range in &self.inner {
+            let start = range.start - range.start % block;
+            let end = range
+                .end
+                .checked_next_multiple_of(block)
+                .unwrap_or(usize::MAX)
+                .min(limit.max(range.end));
+            result.add(FileRange::new(start, end));
+        }
+        result
+    }
+
+    /// 与集合相交的固定大小块的序号，升序且不重复
+    #[inline]
+    pub fn blocks(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        let block = block.max(1);
+        let mut next = 0;
+        self.inner.iter().flat_map(move |range| {
+            // 相邻区间可能落在同一块中，跳过已经交出的块
+            let first = (range.start / block).max(next);
+            let last = range.end.div_ceil(block);
+            next = next.max(last);
+            first..last
+        })
+    }
+
     #[inline]
     pub fn interval_count(&self) -> usize {
         self.inner.len()
@@ -477,7 +511,9 @@ fn extract_range_bounds(rgn: &impl ToRangeBoundPair) -> Result<(usize, usize), F
 mod tests {
     use super::*;
     use Bound::*;
+    use proptest::prelude::*;
     use smallvec::smallvec_inline;
+    use std::collections::BTreeSet;
 
     // FileRange 基础测试
     #[test]
@@ -832,4 +868,90 @@ mod tests {
             ]
         );
     }
+
+    fn points(multi: &FileMultiRange) -> BTreeSet<usize> {
+        multi.iter().flat_map(|r| r.start..r.end).collect()
+    }
+
+    fn ranges() -> impl Strategy<Value = Vec<(usize, usize)>> {
+        prop::collection::vec((0usize..200, 1usize..40), 0..32)
+    }
+
+    // 与逐点的集合模型对照
+    proptest! {
+        #[test]
+        fn add_matches_point_model(ranges in ranges()) {
+            let mut multi = FileMultiRange::new();
+            let mut model = BTreeSet::new();
+            for (start, len) in ranges {
+                multi.add(FileRange::new(start, start + len));
+                model.extend(start..start + len);
+            }
+            // 有序、不相交且不相邻
+            for pair in multi.windows(2) {
+                prop_assert!(pair[0].end < pair[1].start);
+            }
+            prop_assert_eq!(points(&multi), model);
+        }
+
+        #[test]
+        fn set_ops_match_point_model(a in ranges(), b in ranges()) {
+            let to_multi = |ranges: Vec<(usize, usize)>| {
+                let mut multi = FileMultiRange::new();
+                for (start, len) in ranges {
+                    multi.add(FileRange::new(start, start + len));
+                }
+                multi
+            };
+            let (a, b) = (to_multi(a), to_multi(b));
+            let (pa, pb) = (points(&a), points(&b));
+            prop_assert_eq!(points(&a.intersect(&b)), &pa & &pb);
+            prop_assert_eq!(points(&a.subtract(&b)), &pa - &pb);
+        }
+
+        #[test]
+        fn block_adapters_match_point_model(ranges in ranges(), block in 1usize..16) {
+            let mut multi = FileMultiRange::new();
+            for (start, len) in ranges {
+                multi.add(FileRange::new(start, start + len));
+            }
+            let model = points(&multi);
+            let total = FileRange::new(0, 250);
+            let all: BTreeSet<usize> = (total.start..total.end).collect();
+            prop_assert_eq!(points(&multi.complement(total)), &all - &model);
+
+            let blocks: BTreeSet<usize> = model.iter().map(|p| p / block).collect();
+            prop_assert_eq!(
+                multi.blocks(block).collect::<BTreeSet<_>>(),
+                blocks.clone()
+            );
+            prop_assert!(multi.blocks(block).is_sorted_by(|a, b| a < b));
+
+            let aligned: BTreeSet<usize> = blocks
+                .iter()
+                .flat_map(|b| b * block..((b + 1) * block).min(total.end))
+                .chain(model.iter().copied())
+                .collect();
+            prop_assert_eq!(points(&multi.align(block, total.end)), aligned);
+        }
+    }
+
+    #[test]
+    fn align_and_blocks() {
+        let multi = FileMultiRange::try_from([(3, 5), (6, 9), (17, 18)].as_slice()).unwrap();
+        let aligned = multi.align(4, 18);
+        assert_eq!(
+            aligned.inner,
+            smallvec_inline![FileRange::new(0, 12), FileRange::new(16, 18)]
+        );
+        assert_eq!(multi.blocks(4).collect::<Vec<_>>(), vec![0, 1, 2, 4]);
+        assert_eq!(
+            multi.complement(FileRange::new(0, 10)).inner,
+            smallvec_inline![
+                FileRange::new(0, 3),
+                FileRange::new(5, 6),
+                FileRange::new(9, 10)
+            ]
+        );
+    }
 }
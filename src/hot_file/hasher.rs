@@ -0,0 +1,204 @@
+use super::{FileRange, HotFile, HotFileError};
+use bincode::{Decode, Encode};
+use std::{fmt, sync::atomic::Ordering};
+use xxhash_rust::xxh3::Xxh3;
+
+/// 流式计算校验值，任务层按用途选择具体算法
+pub trait Hasher: Default + Send {
+    type Output;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn finish(&self) -> Self::Output;
+
+    fn digest<I, B>(chunks: I) -> Self::Output
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let mut hasher = Self::default();
+        for chunk in chunks {
+            hasher.update(chunk.as_ref());
+        }
+        hasher.finish()
+    }
+}
+
+/// 速度快但不抗碰撞，用于块级的快速比对与文件 id
+pub struct Xxh3Hasher(Xxh3);
+
+impl Default for Xxh3Hasher {
+    fn default() -> Self {
+        Self(Xxh3::new())
+    }
+}
+
+impl Hasher for Xxh3Hasher {
+    type Output = u64;
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.digest()
+    }
+}
+
+/// 密码学哈希，用于整文件的最终校验
+#[derive(Default)]
+pub struct Blake3Hasher(blake3::Hasher);
+
+impl Hasher for Blake3Hasher {
+    type Output = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(&self) -> [u8; 32] {
+        *self.0.finalize().as_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChecksumAlgorithm {
+    Xxh3,
+    Blake3,
+}
+
+/// 校验值的用途，决定可以使用哪些算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumPurpose {
+    /// 块清单、重传前的部分比对，只需要发现意外损坏
+    Block,
+    /// 下载完成后对整个文件的最终校验
+    File,
+}
+
+impl ChecksumAlgorithm {
+    /// 块级比对总是使用 xxh3，整文件校验在双方都支持时使用 BLAKE3
+    pub fn for_purpose(purpose: ChecksumPurpose, blake3: bool) -> Self {
+        match purpose {
+            ChecksumPurpose::File if blake3 => Self::Blake3,
+            _ => Self::Xxh3,
+        }
+    }
+
+    pub fn digest<I, B>(self, chunks: I) -> Checksum
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        match self {
+            Self::Xxh3 => Checksum::Xxh3(Xxh3Hasher::digest(chunks)),
+            Self::Blake3 => Checksum::Blake3(Blake3Hasher::digest(chunks)),
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Xxh3 => "xxh3",
+            Self::Blake3 => "blake3",
+        })
+    }
+}
+
+/// 带算法标记的校验值，不同算法的结果之间不相等
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum Checksum {
+    Xxh3(u64),
+    Blake3([u8; 32]),
+}
+
+impl Checksum {
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        match self {
+            Self::Xxh3(_) => ChecksumAlgorithm::Xxh3,
+            Self::Blake3(_) => ChecksumAlgorithm::Blake3,
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Xxh3(hash) => write!(f, "xxh3:{hash:016x}"),
+            Self::Blake3(hash) => {
+                f.write_str("blake3:")?;
+                hash.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+        }
+    }
+}
+
+impl HotFile {
+    /// 分段读出整个文件（含脏数据）计算校验值，不会一次读入内存
+    pub async fn checksum(&self, algorithm: ChecksumAlgorithm) -> Result<Checksum, HotFileError> {
+        Ok(match algorithm {
+            ChecksumAlgorithm::Xxh3 => Checksum::Xxh3(self.digest::<Xxh3Hasher>().await?),
+            ChecksumAlgorithm::Blake3 => Checksum::Blake3(self.digest::<Blake3Hasher>().await?),
+        })
+    }
+
+    async fn digest<H: Hasher>(&self) -> Result<H::Output, HotFileError> {
+        const CHUNK: usize = 1024 * 1024;
+        let total = self.sync_len_state.load(Ordering::Relaxed);
+        let mut hasher = H::default();
+        let mut start = 0;
+        while start < total {
+            let rgn = FileRange::new(start, (start + CHUNK).min(total));
+            for buf in self.read(rgn.into()).await? {
+                hasher.update(buf.as_ref());
+            }
+            start = rgn.end();
+        }
+        Ok(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn pick_by_purpose() {
+        use ChecksumPurpose::*;
+        assert_eq!(
+            ChecksumAlgorithm::for_purpose(Block, true),
+            ChecksumAlgorithm::Xxh3
+        );
+        assert_eq!(
+            ChecksumAlgorithm::for_purpose(File, false),
+            ChecksumAlgorithm::Xxh3
+        );
+        assert_eq!(
+            ChecksumAlgorithm::for_purpose(File, true),
+            ChecksumAlgorithm::Blake3
+        );
+    }
+
+    #[tokio::test]
+    async fn streaming_matches_oneshot() {
+        let dir = tempdir().unwrap();
+        let file = HotFile::open_new(dir.path().join("a.bin")).await.unwrap();
+        let data = (0..3 << 20).map(|i| i as u8).collect::<Vec<_>>();
+        file.write(&data, 0).await.unwrap();
+
+        let xxh3 = file.checksum(ChecksumAlgorithm::Xxh3).await.unwrap();
+        assert_eq!(xxh3, Checksum::Xxh3(HotFile::hash([&data])));
+        let digest = file.checksum(ChecksumAlgorithm::Blake3).await.unwrap();
+        assert_eq!(digest, Checksum::Blake3(*blake3::hash(&data).as_bytes()));
+        assert_eq!(digest.algorithm(), ChecksumAlgorithm::Blake3);
+        assert_ne!(
+            ChecksumAlgorithm::Blake3.digest([&data[1..]]),
+            ChecksumAlgorithm::Blake3.digest([&data])
+        );
+        assert_eq!(Checksum::Xxh3(0xabc).to_string(), "xxh3:0000000000000abc");
+        assert!(digest.to_string().starts_with("blake3:"));
+        assert_eq!(digest.to_string().len(), "blake3:".len() + 64);
+    }
+}
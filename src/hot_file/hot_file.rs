@@ -1,20 +1,29 @@
-use super::{FileMultiRange, FileRange, FileRangeError};
+use super::{
+    ChunkedBuf, FileMultiRange, FileRange, FileRangeError, Hasher as _, Xxh3Hasher, extended_path,
+};
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    shutdown::ShutdownToken,
+};
 use bytes::{Bytes, BytesMut};
 use futures::future::try_join_all;
 use std::collections::{BTreeMap, HashMap};
-use std::hash::Hasher;
 use std::hint::{likely, unlikely};
 use std::io::SeekFrom;
 use std::ops::{Bound, Deref};
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 use std::usize;
 use thiserror::Error;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::Result as IoResult;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
-use tokio::sync::Mutex;
-use xxhash_rust::xxh3::Xxh3;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::AbortHandle;
+use tokio::time::{Instant, MissedTickBehavior, interval};
+use tracing::{info, warn};
 
 pub type Offset = usize;
 
@@ -30,24 +39,79 @@ pub enum HotFileError {
 
 pub struct HotFile {
     disk: Mutex<File>,
+    /// 打开时的路径，`purge` 据此删除文件
+    path: PathBuf,
     dirty: Mutex<BTreeMap<FileRange, Bytes>>,
     pub sync_len_state: AtomicUsize,
+    /// 当前积压的脏数据字节数
+    dirty_bytes: AtomicUsize,
+    /// 最早一块未落盘数据的写入时间
+    dirty_since: StdMutex<Option<Instant>>,
+    /// 超过阈值时唤醒后台刷盘
+    flush_needed: Arc<Notify>,
+    max_dirty_bytes: AtomicUsize,
+    /// 是否走内存映射读取磁盘部分
+    use_mmap: AtomicBool,
+    #[cfg(feature = "mmap")]
+    mapping: super::mmap::MmapReader,
+    /// 顺序读取时的预读缓存，默认关闭
+    read_ahead: super::read_ahead::ReadAhead,
+}
+
+/// 磁盘部分的读取方式，按文件选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadBackend {
+    #[default]
+    Seek,
+    /// 需要启用 `mmap` feature，否则回退到 `Seek`
+    Mmap,
 }
 
 impl HotFile {
+    pub const DEFAULT_READ_AHEAD: usize = 4;
+
     pub async fn open_new<P: AsRef<Path>>(path: P) -> Result<Self, HotFileError> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create_new(true)
-            .open(path)
+            .open(extended_path(path.as_ref()))
             .await?;
-        let len = file.metadata().await?.len() as usize;
-        Ok(Self {
-            disk: Mutex::new(file),
-            dirty: Default::default(),
-            sync_len_state: AtomicUsize::new(len),
-        })
+        Self::from_file(file, path.as_ref()).await
+    }
+
+    /// 已知最终大小时预先分配磁盘空间，之后的写入不再反复扩展文件
+    pub async fn open_new_with_len<P: AsRef<Path>>(
+        path: P,
+        len: usize,
+    ) -> Result<Self, HotFileError> {
+        let file = Self::open_new(path).await?;
+        file.preallocate(len).await?;
+        Ok(file)
+    }
+
+    pub async fn preallocate(&self, len: usize) -> Result<(), HotFileError> {
+        let disk_guard = self.disk.lock().await;
+        if disk_guard.metadata().await?.len() < len as u64 {
+            super::alloc::preallocate(&disk_guard, len as u64).await?;
+        }
+        self.sync_len_state.fetch_max(len, Ordering::Relaxed);
+        #[cfg(feature = "mmap")]
+        self.mapping.invalidate();
+        Ok(())
+    }
+
+    /// 把从未写入的区间标记为稀疏，释放预分配的磁盘块
+    ///
+    /// 调用方需保证该区间没有待落盘的数据，返回文件系统是否支持
+    pub async fn punch_hole(&self, rgn: FileRange) -> Result<bool, HotFileError> {
+        let disk_guard = self.disk.lock().await;
+        self.read_ahead.invalidate(rgn);
+        Ok(super::alloc::punch_hole(
+            &disk_guard,
+            rgn.start() as u64,
+            rgn.interval() as u64,
+        )?)
     }
 
     pub async fn open_existed<P: AsRef<Path>>(path: P) -> Result<Self, HotFileError> {
@@ -55,16 +119,143 @@ impl HotFile {
             .read(true)
             .write(true)
             .create(true)
-            .open(path)
+            .open(extended_path(path.as_ref()))
             .await?;
+        Self::from_file(file, path.as_ref()).await
+    }
+
+    async fn from_file(file: File, path: &Path) -> Result<Self, HotFileError> {
         let len = file.metadata().await?.len() as usize;
         Ok(Self {
             disk: Mutex::new(file),
+            path: path.to_owned(),
             dirty: Default::default(),
             sync_len_state: AtomicUsize::new(len),
+            dirty_bytes: AtomicUsize::new(0),
+            dirty_since: StdMutex::new(None),
+            flush_needed: Arc::new(Notify::new()),
+            max_dirty_bytes: AtomicUsize::new(usize::MAX),
+            use_mmap: AtomicBool::new(false),
+            #[cfg(feature = "mmap")]
+            mapping: Default::default(),
+            read_ahead: Default::default(),
         })
     }
 
+    /// 返回实际生效的读取方式
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 截断到 `len`，丢弃之后的脏数据，跨越 `len` 的脏块只保留前半段
+    pub async fn truncate(&self, len: usize) -> Result<(), HotFileError> {
+        let mut dirty_guard = self.dirty.lock().await;
+        // 在锁内更新长度，进行中的刷盘拿到磁盘锁后只写到新长度为止
+        self.sync_len_state.store(len, Ordering::Relaxed);
+        let beyond = dirty_guard
+            .keys()
+            .filter(|rgn| rgn.end() > len)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut removed = 0;
+        for rgn in beyond {
+            let Some(buf) = dirty_guard.remove(&rgn) else {
+                continue;
+            };
+            removed += buf.len();
+            if rgn.start() < len {
+                let kept = buf.slice(..len - rgn.start());
+                removed -= kept.len();
+                dirty_guard.insert(FileRange::new(rgn.start(), len), kept);
+            }
+        }
+        self.account_dirty(0, removed);
+        self.read_ahead.invalidate(FileRange::new(len, usize::MAX));
+        let disk_guard = self.disk.lock().await;
+        disk_guard.set_len(len as u64).await?;
+        drop(disk_guard);
+        #[cfg(feature = "mmap")]
+        self.mapping.invalidate();
+        Ok(())
+    }
+
+    /// 丢弃脏数据，关闭并删除文件，取消下载且不保留已下载部分时使用
+    pub async fn purge(self) -> Result<(), HotFileError> {
+        let Self {
+            disk,
+            dirty,
+            path,
+            #[cfg(feature = "mmap")]
+            mapping,
+            ..
+        } = self;
+        drop(dirty);
+        #[cfg(feature = "mmap")]
+        drop(mapping);
+        // Windows 上无法删除仍被打开的文件，等句柄真正关闭后再删
+        drop(disk.into_inner().into_std().await);
+        tokio::fs::remove_file(extended_path(&path)).await?;
+        Ok(())
+    }
+
+    pub fn set_read_backend(&self, backend: ReadBackend) -> ReadBackend {
+        let mmap = backend == ReadBackend::Mmap && cfg!(feature = "mmap");
+        self.use_mmap.store(mmap, Ordering::Relaxed);
+        self.read_backend()
+    }
+
+    pub fn read_backend(&self) -> ReadBackend {
+        if self.use_mmap.load(Ordering::Relaxed) {
+            ReadBackend::Mmap
+        } else {
+            ReadBackend::Seek
+        }
+    }
+
+    /// 顺序读取时额外预读的段数，0 关闭；只作用于 seek 读取
+    pub fn set_read_ahead(&self, depth: usize) {
+        self.read_ahead.set_depth(depth);
+    }
+
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead.depth()
+    }
+
+    pub async fn read_ahead_from_config(cfg: &ConfigManager) -> usize {
+        cfg.get_typed(ConfigItem::HotFileReadAhead)
+            .await
+            .unwrap_or(Self::DEFAULT_READ_AHEAD)
+    }
+
+    pub fn dirty_bytes(&self) -> usize {
+        self.dirty_bytes.load(Ordering::Relaxed)
+    }
+
+    /// 最早的脏数据已经驻留了多久
+    pub fn dirty_age(&self) -> Option<Duration> {
+        self.dirty_since
+            .lock()
+            .unwrap()
+            .map(|since| since.elapsed())
+    }
+
+    /// 更新脏数据计数，`added` 与 `removed` 为本次增减的字节数
+    fn account_dirty(&self, added: usize, removed: usize) {
+        // 调用方持有 dirty 锁，这里不会并发
+        let now = (self.dirty_bytes.load(Ordering::Relaxed) + added).saturating_sub(removed);
+        self.dirty_bytes.store(now, Ordering::Relaxed);
+        let mut since = self.dirty_since.lock().unwrap();
+        match (now, *since) {
+            (0, _) => *since = None,
+            (_, None) => *since = Some(Instant::now()),
+            _ => {}
+        }
+        drop(since);
+        if now >= self.max_dirty_bytes.load(Ordering::Relaxed) {
+            self.flush_needed.notify_one();
+        }
+    }
+
     pub async fn write(&self, buf: &[u8], offset: Offset) -> Result<(), HotFileError> {
         let buf_len = buf.len();
         let buf_rgn = FileRange::try_new(offset, offset + buf_len)?;
@@ -100,38 +291,112 @@ impl HotFile {
         let merged_start = offset - merged_start;
         merged_buf[merged_start..merged_start + buf_len].copy_from_slice(&buf);
         let mut dirty_guard = self.dirty.lock().await;
+        // 读取持有 dirty 锁，在锁内失效才不会与预读交错
+        self.read_ahead.invalidate(merged_rgn);
+        // 期间可能已被刷盘移除，只统计真正移除的部分
+        let mut removed = 0;
         for (rgn, _) in overlapped {
-            dirty_guard.remove(&rgn);
+            removed += dirty_guard.remove(&rgn).map_or(0, |buf| buf.len());
         }
-        dirty_guard.insert(merged_rgn, merged_buf.freeze());
+        let added = merged_rgn.interval();
+        removed += dirty_guard
+            .insert(merged_rgn, merged_buf.freeze())
+            .map_or(0, |buf| buf.len());
+        self.account_dirty(added, removed);
         Ok(())
     }
 
+    /// 关停时把脏数据落盘，只持有弱引用，不延长文件的生命周期
+    pub fn sync_on_shutdown(self: &Arc<Self>, shutdown: &ShutdownToken) {
+        let file = Arc::downgrade(self);
+        let token = shutdown.clone();
+        shutdown.spawn(async move {
+            token.cancelled().await;
+            if let Some(file) = file.upgrade()
+                && let Err(err) = file.sync().await
+            {
+                warn!("Failed to flush hot file on shutdown: {err}");
+            }
+        });
+    }
+
+    /// 后台按策略增量刷盘，脏数据超过上限或驻留超时都会触发
+    pub fn spawn_flusher(self: &Arc<Self>, policy: FlushPolicy) -> HotFileFlusher {
+        self.max_dirty_bytes
+            .store(policy.max_dirty_bytes, Ordering::Relaxed);
+        // 只持有弱引用，文件释放后自动退出
+        let file = Arc::downgrade(self);
+        let flush_needed = self.flush_needed.clone();
+        let abort = tokio::spawn(async move {
+            let mut ticker = interval(policy.max_dirty_age / 2);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                let should_flush = tokio::select! {
+                    _ = flush_needed.notified() => true,
+                    _ = ticker.tick() => false,
+                };
+                let Some(file) = file.upgrade() else {
+                    break;
+                };
+                let expired = file
+                    .dirty_age()
+                    .is_some_and(|age| age >= policy.max_dirty_age);
+                if (should_flush || expired)
+                    && let Err(err) = file.sync().await
+                {
+                    warn!("Background flush failed: {err}");
+                }
+            }
+        })
+        .abort_handle();
+        HotFileFlusher { abort }
+    }
+
     pub async fn sync(&self) -> IoResult<()> {
         let dirty_guard = self.dirty.lock().await;
         if unlikely(dirty_guard.is_empty()) {
             return Ok(());
         }
-        let target_len = self.sync_len_state.load(Ordering::Relaxed);
         let snapshot = dirty_guard
             .iter()
             .map(|(&rgn, data)| (rgn, data.clone()))
             .collect::<Vec<_>>();
         drop(dirty_guard);
         let mut disk_guard = self.disk.lock().await;
+        // 拿到磁盘锁后再读长度，期间被截断的部分不再写回
+        let target_len = self.sync_len_state.load(Ordering::Relaxed);
         if likely(disk_guard.metadata().await?.len() < target_len as u64) {
             disk_guard.set_len(target_len as u64).await?;
         }
         for (rgn, buf) in &snapshot {
+            if unlikely(rgn.start() >= target_len) {
+                continue;
+            }
+            let kept = buf.len().min(target_len - rgn.start());
             disk_guard.seek(SeekFrom::Start(rgn.start() as u64)).await?;
-            disk_guard.write_all(buf).await?;
+            disk_guard.write_all(&buf[..kept]).await?;
         }
         disk_guard.sync_all().await?;
         drop(disk_guard);
+        // 预读可能在这些数据落盘之前读到了旧内容
+        for (rgn, _) in &snapshot {
+            self.read_ahead.invalidate(*rgn);
+        }
+        #[cfg(feature = "mmap")]
+        self.mapping.invalidate();
         let mut dirty_guard = self.dirty.lock().await;
-        for (rgn, _) in snapshot.iter() {
-            dirty_guard.remove(rgn);
+        // 刷盘期间被新写入合并掉的块留到下次
+        let mut removed = 0;
+        for (rgn, buf) in &snapshot {
+            if dirty_guard
+                .get(rgn)
+                .is_some_and(|cur| cur.as_ptr() == buf.as_ptr())
+            {
+                dirty_guard.remove(rgn);
+                removed += buf.len();
+            }
         }
+        self.account_dirty(0, removed);
         Ok(())
     }
 
@@ -140,20 +405,36 @@ impl HotFile {
         if unlikely(rgn.end() > logical_len) {
             return Err(HotFileError::OutOfFile);
         }
+        #[cfg(feature = "mmap")]
+        if self.use_mmap.load(Ordering::Relaxed) {
+            match self.mapping.read(&self.disk, rgn).await {
+                Ok(buf) => return Ok(buf),
+                Err(err) => {
+                    warn!("Failed to map file, fall back to seek: {err}");
+                    self.use_mmap.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+        if let Some(buf) = self.read_ahead.get(rgn) {
+            return Ok(buf);
+        }
+        // 顺序读取时连同后面几段一次读出，减少加锁与寻道
+        let planned = self.read_ahead.plan(rgn, logical_len);
         let mut disk_guard = self.disk.lock().await;
         let disk_len = disk_guard.metadata().await?.len() as usize;
-        let read_rgn = FileRange::new(rgn.start(), disk_len.min(rgn.end()));
-        let mut buf = BytesMut::with_capacity(rgn.interval());
-        buf.resize(rgn.interval(), 0);
-        if likely(read_rgn.interval() > 0) {
-            disk_guard
-                .seek(SeekFrom::Start(read_rgn.start() as u64))
-                .await?;
+        let mut buf = BytesMut::with_capacity(planned.interval());
+        buf.resize(planned.interval(), 0);
+        if likely(disk_len > planned.start()) {
+            let read_len = disk_len.min(planned.end()) - planned.start();
             disk_guard
-                .read_exact(&mut buf[0..read_rgn.interval()])
+                .seek(SeekFrom::Start(planned.start() as u64))
                 .await?;
+            disk_guard.read_exact(&mut buf[0..read_len]).await?;
         }
-        Ok(buf.freeze())
+        drop(disk_guard);
+        let buf = buf.freeze();
+        self.read_ahead.fill(rgn, planned, &buf);
+        Ok(buf.slice(0..rgn.interval()))
     }
 
     pub async fn read(&self, mask: FileMultiRange) -> Result<Vec<Bytes>, HotFileError> {
@@ -206,17 +487,63 @@ impl HotFile {
         Ok(rst)
     }
 
-    // todo 重整约束
+    /// 与 `read` 相同，但结果不拼接，直接交给向量化发送
+    pub async fn read_vectored(&self, mask: FileMultiRange) -> Result<ChunkedBuf, HotFileError> {
+        self.read(mask).await.map(ChunkedBuf::from)
+    }
+
+    /// 块级比对用的 xxh3，见 `Xxh3Hasher`
     pub fn hash<I, B>(chunks: I) -> u64
     where
         I: IntoIterator<Item = B>,
         B: AsRef<[u8]>,
     {
-        let mut hasher = Xxh3::new();
-        for chunk in chunks {
-            hasher.update(chunk.as_ref());
+        Xxh3Hasher::digest(chunks)
+    }
+}
+
+/// 后台刷盘策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushPolicy {
+    pub max_dirty_bytes: usize,
+    pub max_dirty_age: Duration,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            max_dirty_bytes: 64 << 20,
+            max_dirty_age: Duration::from_secs(5),
         }
-        hasher.finish()
+    }
+}
+
+impl FlushPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            max_dirty_bytes: cfg
+                .get_typed(ConfigItem::HotFileMaxDirtyBytes)
+                .await
+                .unwrap_or(default.max_dirty_bytes),
+            max_dirty_age: cfg
+                .get_typed(ConfigItem::HotFileMaxDirtyAgeMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_dirty_age),
+        }
+    }
+}
+
+/// 文件被释放后自动退出，提前 drop 则立即停止
+pub struct HotFileFlusher {
+    abort: AbortHandle,
+}
+
+impl Drop for HotFileFlusher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Hot file flusher has been dropped");
     }
 }
 
@@ -242,8 +569,10 @@ where
 mod tests {
     use super::*;
     use bytes::Bytes;
+    use std::hash::Hasher;
     use tempfile::tempdir;
     use tokio::io::AsyncReadExt;
+    use xxhash_rust::xxh3::Xxh3;
 
     #[tokio::test]
     async fn create_new_file() {
@@ -444,6 +773,28 @@ mod tests {
         assert_eq!(dirty.len(), 10);
     }
 
+    #[tokio::test]
+    async fn sequential_read_ahead() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("read_ahead");
+        let mut file = File::create(&file_path).await.unwrap();
+        file.write_all(b"AAAABBBBCCCCDDDD").await.unwrap();
+        let hot_file = HotFile::open_existed(&file_path).await.unwrap();
+        hot_file.set_read_ahead(2);
+
+        let read = async |start: usize| {
+            let mask = FileRange::new(start, start + 4).into();
+            hot_file.read(mask).await.unwrap().concat()
+        };
+        assert_eq!(read(0).await, b"AAAA");
+        assert_eq!(read(4).await, b"BBBB");
+        // 预读的内容在落盘前后都不能盖过新写入的数据
+        let _ = hot_file.write(b"cc", 8).await;
+        hot_file.sync().await.unwrap();
+        assert_eq!(read(8).await, b"ccCC");
+        assert_eq!(read(12).await, b"DDDD");
+    }
+
     #[tokio::test]
     async fn hash_calculation() {
         let data1 = b"hello";
@@ -506,6 +857,110 @@ mod tests {
         assert!(dirty.is_empty(), "0长度写入不应产生脏数据");
     }
 
+    #[tokio::test]
+    async fn dirty_accounting() {
+        let temp_dir = tempdir().unwrap();
+        let hot_file = HotFile::open_new(temp_dir.path().join("dirty_accounting"))
+            .await
+            .unwrap();
+        hot_file.write(b"hello", 0).await.unwrap();
+        hot_file.write(b"world", 3).await.unwrap(); // 合并为 0..8
+        hot_file.write(b"!", 20).await.unwrap();
+        assert_eq!(hot_file.dirty_bytes(), 9);
+        assert!(hot_file.dirty_age().is_some());
+        hot_file.sync().await.unwrap();
+        assert_eq!(hot_file.dirty_bytes(), 0);
+        assert!(hot_file.dirty_age().is_none());
+    }
+
+    #[tokio::test]
+    async fn flusher_on_threshold() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("flusher");
+        let hot_file = Arc::new(HotFile::open_new(&file_path).await.unwrap());
+        let _flusher = hot_file.spawn_flusher(FlushPolicy {
+            max_dirty_bytes: 4,
+            max_dirty_age: Duration::from_secs(3600),
+        });
+        hot_file.write(b"flush me", 0).await.unwrap();
+        for _ in 0..100 {
+            if hot_file.dirty_bytes() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(hot_file.dirty_bytes(), 0);
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"flush me");
+    }
+
+    #[tokio::test]
+    async fn mmap_backend_matches_seek() {
+        let temp_dir = tempdir().unwrap();
+        let hot_file = HotFile::open_new(temp_dir.path().join("mmap"))
+            .await
+            .unwrap();
+        hot_file.write(b"ABCDEFGHIJKL", 0).await.unwrap();
+        hot_file.sync().await.unwrap();
+        hot_file.write(b"zz", 20).await.unwrap();
+        let mask = FileMultiRange::try_from([1..4, 10..22].as_slice()).unwrap();
+        let seek = hot_file.read(mask.clone()).await.unwrap();
+        let backend = hot_file.set_read_backend(ReadBackend::Mmap);
+        assert_eq!(backend == ReadBackend::Mmap, cfg!(feature = "mmap"));
+        assert_eq!(hot_file.read(mask).await.unwrap(), seek);
+    }
+
+    #[tokio::test]
+    async fn preallocate_and_punch() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("prealloc");
+        let hot_file = HotFile::open_new_with_len(&file_path, 1 << 20)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::metadata(&file_path).unwrap().len(), 1 << 20);
+
+        hot_file.write(b"head", 0).await.unwrap();
+        hot_file.sync().await.unwrap();
+        hot_file
+            .punch_hole(FileRange::new(4096, 1 << 20))
+            .await
+            .unwrap();
+        // 长度不变，未写入的部分读出为零
+        assert_eq!(std::fs::metadata(&file_path).unwrap().len(), 1 << 20);
+        let mask = FileMultiRange::try_from([0..4, 8192..8196].as_slice()).unwrap();
+        let result = hot_file.read(mask).await.unwrap();
+        assert_eq!(result[0].as_ref(), b"head");
+        assert_eq!(result[1].as_ref(), &[0; 4]);
+    }
+
+    #[tokio::test]
+    async fn truncate_and_purge() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("truncate");
+        let hot_file = HotFile::open_new(&file_path).await.unwrap();
+        hot_file.write(b"ABCDEFGH", 0).await.unwrap();
+        hot_file.sync().await.unwrap();
+        hot_file.write(b"1234", 4).await.unwrap();
+        hot_file.write(b"zz", 20).await.unwrap();
+
+        // 跨越新长度的脏块只保留前半段，之后的整块丢弃
+        hot_file.truncate(6).await.unwrap();
+        assert_eq!(hot_file.dirty_bytes(), 2);
+        assert_eq!(hot_file.sync_len_state.load(Ordering::Relaxed), 6);
+        let mask = FileMultiRange::from(FileRange::new(0, 6));
+        let read = hot_file.read(mask).await.unwrap();
+        assert_eq!(arrange_bytes_to_vec(read.into_iter()), b"ABCD12");
+        assert!(matches!(
+            hot_file.read(FileRange::new(4, 8).into()).await,
+            Err(HotFileError::OutOfFile)
+        ));
+        hot_file.sync().await.unwrap();
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"ABCD12");
+
+        hot_file.write(b"dirty", 6).await.unwrap();
+        hot_file.purge().await.unwrap();
+        assert!(!file_path.exists());
+    }
+
     #[tokio::test]
     async fn read_complex_ranges() {
         let temp_dir = tempdir().unwrap();
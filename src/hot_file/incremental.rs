@@ -0,0 +1,240 @@
+use super::{
+    Blake3Hasher, BlockHash, Checksum, FileMultiRange, FileRange, Hasher, HotFile, HotFileError,
+    Xxh3Hasher,
+};
+use std::sync::atomic::Ordering;
+use tracing::debug;
+
+/// 边写边算的整文件哈希，完成时不必再把文件整个读一遍
+///
+/// 按顺序写入的数据直接计入；乱序到达的范围先记下，等前面补齐后再从文件读回补算。
+/// 已计入的部分被改写时按块比对，内容变了就放弃增量结果，收尾时退回完整重读
+pub struct IncrementalHash {
+    block_size: usize,
+    file: Xxh3Hasher,
+    digest: Option<Blake3Hasher>,
+    /// 已计入部分的逐块哈希，最后一块未满时在 `block` 里
+    blocks: Vec<BlockHash>,
+    block: Xxh3Hasher,
+    /// [0, hashed) 已计入
+    hashed: usize,
+    /// hashed 之后已写入、等待补算的范围
+    pending: FileMultiRange,
+    /// 从文件读回补算的字节数
+    caught_up: usize,
+    stale: bool,
+}
+
+impl IncrementalHash {
+    /// blake3 为真时同时计算 BLAKE3 摘要
+    pub fn new(block_size: usize, blake3: bool) -> Self {
+        Self {
+            block_size: block_size.max(1),
+            file: Xxh3Hasher::default(),
+            digest: blake3.then(Blake3Hasher::default),
+            blocks: Vec::new(),
+            block: Xxh3Hasher::default(),
+            hashed: 0,
+            pending: FileMultiRange::new(),
+            caught_up: 0,
+            stale: false,
+        }
+    }
+
+    pub fn hashed(&self) -> usize {
+        self.hashed
+    }
+
+    pub fn caught_up(&self) -> usize {
+        self.caught_up
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// data 已成功写入 offset 处后调用
+    pub async fn record(&mut self, file: &HotFile, offset: usize, data: &[u8]) {
+        if self.stale || data.is_empty() {
+            return;
+        }
+        let end = offset + data.len();
+        if offset < self.hashed
+            && let Err(err) = self.recheck(file, offset, end.min(self.hashed)).await
+        {
+            debug!("Give up incremental hash: {err}");
+            self.stale = true;
+        }
+        if self.stale {
+            return;
+        }
+        if offset <= self.hashed && end > self.hashed {
+            self.feed(&data[self.hashed - offset..]);
+        } else if offset > self.hashed {
+            self.pending.add(FileRange::new(offset, end));
+        }
+        if let Err(err) = self.catch_up(file).await {
+            debug!("Give up incremental hash: {err}");
+            self.stale = true;
+        }
+    }
+
+    /// 补算剩余部分后返回整文件的 xxh3 与 BLAKE3 摘要，计入的部分被改写过时返回 None
+    pub async fn finish(
+        mut self,
+        file: &HotFile,
+    ) -> Result<Option<(u64, Option<Checksum>)>, HotFileError> {
+        if self.stale {
+            return Ok(None);
+        }
+        let total = file.sync_len_state.load(Ordering::Relaxed);
+        if total < self.hashed {
+            return Ok(None);
+        }
+        self.read_into(file, total).await?;
+        debug!(
+            "Incremental hash read back {} of {total} bytes",
+            self.caught_up
+        );
+        let digest = self.digest.map(|digest| Checksum::Blake3(digest.finish()));
+        Ok(Some((self.file.finish(), digest)))
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        self.file.update(data);
+        if let Some(digest) = &mut self.digest {
+            digest.update(data);
+        }
+        while !data.is_empty() {
+            let room = self.block_size - self.hashed % self.block_size;
+            let (head, rest) = data.split_at(room.min(data.len()));
+            self.block.update(head);
+            self.hashed += head.len();
+            if self.hashed.is_multiple_of(self.block_size) {
+                let block = std::mem::take(&mut self.block);
+                self.blocks.push(block.finish());
+            }
+            data = rest;
+        }
+    }
+
+    /// 从文件读回 [hashed, end) 计入
+    async fn read_into(&mut self, file: &HotFile, end: usize) -> Result<(), HotFileError> {
+        while self.hashed < end {
+            let rgn = FileRange::new(self.hashed, (self.hashed + self.block_size).min(end));
+            for buf in file.read(rgn.into()).await? {
+                self.caught_up += buf.as_ref().len();
+                self.feed(buf.as_ref());
+            }
+        }
+        Ok(())
+    }
+
+    /// 前面补齐后，紧接着的乱序范围读回补算
+    async fn catch_up(&mut self, file: &HotFile) -> Result<(), HotFileError> {
+        while let Some(&next) = self.pending.first()
+            && next.start() <= self.hashed
+        {
+            self.pending = self.pending.subtract(&next.into());
+            self.read_into(file, next.end()).await?;
+        }
+        Ok(())
+    }
+
+    /// [start, end) 已经计入又被改写，逐块比对内容是否与计入时相同
+    async fn recheck(
+        &mut self,
+        file: &HotFile,
+        start: usize,
+        end: usize,
+    ) -> Result<(), HotFileError> {
+        for index in start / self.block_size..end.div_ceil(self.block_size) {
+            let block_start = index * self.block_size;
+            let block_end = (block_start + self.block_size).min(self.hashed);
+            let expected = match self.blocks.get(index) {
+                Some(&hash) => hash,
+                // 未满的最后一块
+                None => self.block.finish(),
+            };
+            let actual = HotFile::hash(
+                file.read(FileRange::new(block_start, block_end).into())
+                    .await?,
+            );
+            if actual != expected {
+                debug!("Block {index} changed after being hashed");
+                self.stale = true;
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hot_file::ChecksumAlgorithm;
+    use tempfile::tempdir;
+
+    const BLOCK: usize = 4;
+
+    async fn write(file: &HotFile, hash: &mut IncrementalHash, offset: usize, data: &[u8]) {
+        file.write(data, offset).await.unwrap();
+        hash.record(file, offset, data).await;
+    }
+
+    #[tokio::test]
+    async fn sequential_needs_no_read_back() {
+        let dir = tempdir().unwrap();
+        let file = HotFile::open_new(dir.path().join("a.bin")).await.unwrap();
+        let mut hash = IncrementalHash::new(BLOCK, true);
+        let data = b"falcon transfers files";
+        for (i, chunk) in data.chunks(3).enumerate() {
+            write(&file, &mut hash, i * 3, chunk).await;
+        }
+        // 与已计入部分内容相同的重传不影响结果
+        write(&file, &mut hash, 2, &data[2..9]).await;
+        assert!(!hash.is_stale());
+        assert_eq!(hash.caught_up(), 0);
+
+        let (xxh3, digest) = hash.finish(&file).await.unwrap().unwrap();
+        assert_eq!(xxh3, HotFile::hash([data]));
+        assert_eq!(digest, Some(ChecksumAlgorithm::Blake3.digest([data])));
+    }
+
+    #[tokio::test]
+    async fn out_of_order_caught_up() {
+        let dir = tempdir().unwrap();
+        let file = HotFile::open_new(dir.path().join("a.bin")).await.unwrap();
+        let mut hash = IncrementalHash::new(BLOCK, false);
+        let data = b"0123456789abcdef";
+        write(&file, &mut hash, 8, &data[8..12]).await;
+        write(&file, &mut hash, 12, &data[12..]).await;
+        assert_eq!(hash.hashed(), 0);
+        write(&file, &mut hash, 0, &data[..8]).await;
+        // 只有乱序到达的部分被读回
+        assert_eq!(hash.hashed(), 16);
+        assert_eq!(hash.caught_up(), 8);
+
+        let (xxh3, digest) = hash.finish(&file).await.unwrap().unwrap();
+        assert_eq!(xxh3, HotFile::hash([data]));
+        assert_eq!(digest, None);
+    }
+
+    #[tokio::test]
+    async fn rewrite_falls_back() {
+        let dir = tempdir().unwrap();
+        let file = HotFile::open_new(dir.path().join("a.bin")).await.unwrap();
+        let mut hash = IncrementalHash::new(BLOCK, false);
+        write(&file, &mut hash, 0, b"falcon").await;
+        // 修正已计入的数据
+        write(&file, &mut hash, 1, b"A").await;
+        assert!(hash.is_stale());
+        assert!(hash.finish(&file).await.unwrap().is_none());
+
+        // 没有记录任何写入时整个读回
+        let hash = IncrementalHash::new(BLOCK, false);
+        let (xxh3, _) = hash.finish(&file).await.unwrap().unwrap();
+        assert_eq!(xxh3, HotFile::hash([b"fAlcon"]));
+    }
+}
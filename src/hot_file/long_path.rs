@@ -0,0 +1,105 @@
+use std::{borrow::Cow, path::Path};
+
+/// 达到这个长度（UTF-16 码元）的路径在 Windows 上需要 `\\?\` 前缀
+///
+/// 建目录的上限比 MAX_PATH 少 12，留给 8.3 短文件名
+#[cfg(windows)]
+const LEGACY_MAX_PATH: usize = 260 - 12;
+
+/// 返回可以直接交给文件系统调用的路径
+///
+/// Windows 上过长的路径转为绝对路径并加上 `\\?\` 前缀，其他平台原样返回；
+/// 只在调用文件系统时使用，交给上层的路径保持原样
+pub fn extended_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    return extend(path);
+    #[cfg(not(windows))]
+    Cow::Borrowed(path)
+}
+
+#[cfg(windows)]
+fn extend(path: &Path) -> Cow<'_, Path> {
+    use std::{
+        ffi::OsString,
+        os::windows::ffi::{OsStrExt, OsStringExt},
+        path::{Component, Prefix},
+    };
+
+    if path.as_os_str().encode_wide().count() < LEGACY_MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+    // 带前缀的路径不再经过规范化，正斜杠与 `..` 要先由系统解析掉
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let Some(Component::Prefix(prefix)) = absolute.components().next() else {
+        return Cow::Owned(absolute);
+    };
+    let wide = absolute.as_os_str().encode_wide().collect::<Vec<_>>();
+    let extended = match prefix.kind() {
+        // `\\server\share\..` -> `\\?\UNC\server\share\..`
+        Prefix::UNC(..) => [r"\\?\UNC".encode_utf16().collect(), wide[1..].to_vec()].concat(),
+        // `C:\..` -> `\\?\C:\..`
+        Prefix::Disk(_) => [r"\\?\".encode_utf16().collect(), wide].concat(),
+        // 已经带有前缀或是设备路径
+        _ => return Cow::Owned(absolute),
+    };
+    Cow::Owned(OsString::from_wide(&extended).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hot_file::{FileRange, HotFile};
+    use tempfile::tempdir;
+
+    #[test]
+    fn short_paths_unchanged() {
+        let path = Path::new("下载/falcon.bin");
+        assert!(matches!(extended_path(path), Cow::Borrowed(p) if p == path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn prefix_long_paths() {
+        let nested = "目录\\".repeat(150);
+        let disk = format!(r"C:\{nested}a.bin");
+        let extended = extended_path(Path::new(&disk));
+        assert_eq!(extended.as_os_str(), format!(r"\\?\{disk}").as_str());
+
+        let unc = format!(r"\\server\share\{nested}a.bin");
+        let extended = extended_path(Path::new(&unc));
+        assert_eq!(
+            extended.as_os_str(),
+            format!(r"\\?\UNC\server\share\{nested}a.bin").as_str()
+        );
+        // 已有前缀的路径不再重复添加
+        let verbatim = format!(r"\\?\{disk}");
+        assert_eq!(
+            extended_path(Path::new(&verbatim)).as_os_str(),
+            verbatim.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn cjk_names_in_long_paths() {
+        let root = tempdir().unwrap();
+        // 超过 300 个字符，单段仍在文件名长度限制以内
+        let dir = (0..60).fold(root.path().to_path_buf(), |dir, _| dir.join("长路径目录"));
+        assert!(dir.as_os_str().len() > 300);
+        std::fs::create_dir_all(extended_path(&dir)).unwrap();
+        let path = dir.join("测试文件 📦.bin");
+
+        let hot_file = HotFile::open_new(&path).await.unwrap();
+        hot_file.write("你好，猎鹰".as_bytes(), 0).await.unwrap();
+        hot_file.sync().await.unwrap();
+        let read = hot_file.read(FileRange::new(0, 6).into()).await.unwrap();
+        assert_eq!(read[0].as_ref(), "你好".as_bytes());
+        assert_eq!(
+            std::fs::read(extended_path(&path)).unwrap(),
+            "你好，猎鹰".as_bytes()
+        );
+        hot_file.purge().await.unwrap();
+        assert!(!std::fs::exists(extended_path(&path)).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+use super::{FileMultiRange, FileRange, HotFile, HotFileError, MerkleProof, MerkleTree};
+use bincode::{Decode, Encode};
+use std::sync::atomic::Ordering;
+
+pub type BlockHash = u64;
+
+/// 按固定块大小切分文件得到的逐块哈希清单
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct BlockManifest {
+    block_size: usize,
+    total: usize,
+    hashes: Vec<BlockHash>,
+}
+
+impl BlockManifest {
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn hashes(&self) -> &[BlockHash] {
+        &self.hashes
+    }
+
+    /// 第 index 块对应的文件范围，最后一块可能不足 block_size
+    pub fn block_range(&self, index: usize) -> Option<FileRange> {
+        let start = index.checked_mul(self.block_size)?;
+        (start < self.total).then(|| FileRange::new(start, (start + self.block_size).min(self.total)))
+    }
+
+    pub fn merkle(&self) -> MerkleTree {
+        MerkleTree::from_leaves(self.hashes.clone())
+    }
+
+    /// 整个清单的默克尔根，可作为清单本身的指纹
+    pub fn root(&self) -> BlockHash {
+        self.merkle().root()
+    }
+
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        self.merkle().proof(index)
+    }
+
+    /// 校验某一块的内容
+    pub fn verify_block(&self, index: usize, data: &[u8]) -> bool {
+        self.block_range(index)
+            .is_some_and(|rgn| rgn.interval() == data.len())
+            && self.hashes.get(index) == Some(&HotFile::hash([data]))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        bincode::encode_to_vec(self, bincode::config::standard())
+    }
+
+    /// 反序列化后检查块数与长度是否自洽
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let (manifest, _): (Self, _) =
+            bincode::decode_from_slice(buf, bincode::config::standard()).ok()?;
+        (manifest.block_size > 0
+            && manifest.hashes.len() == manifest.total.div_ceil(manifest.block_size))
+        .then_some(manifest)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (FileRange, BlockHash)> + '_ {
+        self.hashes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &hash)| self.block_range(i).map(|rgn| (rgn, hash)))
+    }
+
+    /// 与给定范围相交的块，序号升序
+    pub fn blocks_in<'a>(
+        &'a self,
+        ranges: &'a FileMultiRange,
+    ) -> impl Iterator<Item = (usize, FileRange, BlockHash)> + 'a {
+        ranges
+            .blocks(self.block_size)
+            .map_while(|i| Some((i, self.block_range(i)?, *self.hashes.get(i)?)))
+    }
+}
+
+impl HotFile {
+    /// 计算当前文件（含脏数据）的块清单
+    pub async fn manifest(&self, block_size: usize) -> Result<BlockManifest, HotFileError> {
+        let total = self.sync_len_state.load(Ordering::Relaxed);
+        let block_size = block_size.max(1);
+        let mut hashes = Vec::with_capacity(total.div_ceil(block_size));
+        let mut start = 0;
+        while start < total {
+            let rgn = FileRange::new(start, (start + block_size).min(total));
+            hashes.push(HotFile::hash(self.read(rgn.into()).await?));
+            start = rgn.end();
+        }
+        Ok(BlockManifest {
+            block_size,
+            total,
+            hashes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn manifest_blocks() {
+        let temp_dir = tempdir().unwrap();
+        let hot_file = HotFile::open_new(temp_dir.path().join("manifest"))
+            .await
+            .unwrap();
+        hot_file.write(b"ABCDEFGHIJ", 0).await.unwrap();
+
+        let manifest = hot_file.manifest(4).await.unwrap();
+        assert_eq!(manifest.total(), 10);
+        assert_eq!(manifest.hashes().len(), 3);
+        assert_eq!(manifest.block_range(2), Some(FileRange::new(8, 10)));
+        assert_eq!(manifest.block_range(3), None);
+        assert_eq!(manifest.hashes()[0], HotFile::hash([b"ABCD"]));
+        assert!(manifest.verify_block(2, b"IJ"));
+        assert!(!manifest.verify_block(2, b"IK"));
+
+        let ranges = FileMultiRange::try_from([(1, 2), (9, 12)].as_slice()).unwrap();
+        let covering: Vec<_> = manifest.blocks_in(&ranges).map(|(i, ..)| i).collect();
+        assert_eq!(covering, vec![0, 2]);
+
+        let decoded = BlockManifest::from_bytes(&manifest.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.root(), manifest.root());
+        let proof = decoded.proof(1).unwrap();
+        assert!(proof.verify(HotFile::hash([b"EFGH"]), manifest.root()));
+    }
+}
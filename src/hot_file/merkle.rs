@@ -0,0 +1,136 @@
+use super::BlockHash;
+use bincode::{Decode, Encode};
+use std::hash::Hasher;
+use xxhash_rust::xxh3::Xxh3;
+
+/// 内部节点与叶子使用不同前缀，防止把内部节点伪装成叶子
+const NODE_PREFIX: u8 = 1;
+
+fn hash_pair(left: BlockHash, right: BlockHash) -> BlockHash {
+    let mut hasher = Xxh3::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(&left.to_le_bytes());
+    hasher.update(&right.to_le_bytes());
+    hasher.finish()
+}
+
+/// 以块哈希为叶子的默克尔树，奇数个节点时最后一个直接晋升
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleTree {
+    /// levels[0] 为叶子，最后一层只有根
+    levels: Vec<Vec<BlockHash>>,
+}
+
+impl MerkleTree {
+    pub fn from_leaves(leaves: Vec<BlockHash>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match *pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// 空树的根为 0
+    pub fn root(&self) -> BlockHash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// 单块的包含证明，可在只拿到根的情况下校验某一块
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut idx = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = idx ^ 1;
+            if let Some(&hash) = level.get(sibling) {
+                siblings.push(hash);
+            }
+            idx /= 2;
+        }
+        Some(MerkleProof {
+            index,
+            leaf_count: self.leaf_count(),
+            siblings,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    pub siblings: Vec<BlockHash>,
+}
+
+impl MerkleProof {
+    pub fn verify(&self, leaf: BlockHash, root: BlockHash) -> bool {
+        let mut hash = leaf;
+        let mut idx = self.index;
+        let mut width = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+        while width > 1 {
+            let sibling = idx ^ 1;
+            // 没有兄弟节点的奇数尾节点直接晋升
+            if sibling < width {
+                let Some(&other) = siblings.next() else {
+                    return false;
+                };
+                hash = if idx.is_multiple_of(2) {
+                    hash_pair(hash, other)
+                } else {
+                    hash_pair(other, hash)
+                };
+            }
+            idx /= 2;
+            width = width.div_ceil(2);
+        }
+        siblings.next().is_none() && hash == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proofs_verify() {
+        for n in 1..=9u64 {
+            let leaves = (0..n).map(|i| i * 7919).collect::<Vec<_>>();
+            let tree = MerkleTree::from_leaves(leaves.clone());
+            for (i, &leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(i).unwrap();
+                assert!(proof.verify(leaf, tree.root()), "n={n} i={i}");
+                assert!(!proof.verify(leaf + 1, tree.root()));
+            }
+            assert!(tree.proof(n as usize).is_none());
+        }
+    }
+
+    #[test]
+    fn root_changes_with_any_leaf() {
+        let tree = MerkleTree::from_leaves(vec![1, 2, 3]);
+        assert_ne!(tree.root(), MerkleTree::from_leaves(vec![1, 2, 4]).root());
+        assert_ne!(tree.root(), MerkleTree::from_leaves(vec![2, 1, 3]).root());
+        assert_eq!(MerkleTree::from_leaves(vec![]).root(), 0);
+    }
+}
@@ -0,0 +1,51 @@
+use super::FileRange;
+use bytes::{Bytes, BytesMut};
+use memmap2::Mmap;
+use std::{
+    io,
+    sync::{Arc, RwLock},
+};
+use tokio::{fs::File, sync::Mutex};
+
+/// 磁盘部分的内存映射，落盘后失效，下次读取时重新映射
+///
+/// 读出的数据会拷贝一份，避免之后的写入改变已经交出去的缓冲
+#[derive(Default)]
+pub(super) struct MmapReader {
+    map: RwLock<Option<Arc<Mmap>>>,
+}
+
+impl MmapReader {
+    pub(super) fn invalidate(&self) {
+        *self.map.write().unwrap() = None;
+    }
+
+    async fn remap(&self, disk: &Mutex<File>) -> io::Result<Option<Arc<Mmap>>> {
+        let file = disk.lock().await.try_clone().await?.into_std().await;
+        // 空文件无法映射
+        if file.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+        // SAFETY: HotFile 只会扩展文件长度，不会截断，映射区域始终有效
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        *self.map.write().unwrap() = Some(map.clone());
+        Ok(Some(map))
+    }
+
+    /// 超出磁盘长度的部分补零，与 seek 路径一致
+    pub(super) async fn read(&self, disk: &Mutex<File>, rgn: FileRange) -> io::Result<Bytes> {
+        let cached = self.map.read().unwrap().clone();
+        let map = match cached {
+            Some(map) => Some(map),
+            None => self.remap(disk).await?,
+        };
+        let mut buf = BytesMut::zeroed(rgn.interval());
+        if let Some(map) = map {
+            let end = map.len().min(rgn.end());
+            if end > rgn.start() {
+                buf[..end - rgn.start()].copy_from_slice(&map[rgn.start()..end]);
+            }
+        }
+        Ok(buf.freeze())
+    }
+}
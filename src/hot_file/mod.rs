@@ -1,5 +1,25 @@
+mod alloc;
+mod chunked;
 mod file_range;
+mod hasher;
 mod hot_file;
+mod incremental;
+mod long_path;
+mod manifest;
+mod merkle;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod range_summary;
+mod range_wire;
+mod read_ahead;
 
+pub use chunked::*;
 pub use file_range::*;
+pub use hasher::*;
 pub use hot_file::*;
+pub use incremental::*;
+pub use long_path::*;
+pub use manifest::*;
+pub use merkle::*;
+pub use range_summary::*;
+pub use range_wire::*;
@@ -0,0 +1,151 @@
+use super::{FileMultiRange, FileRange};
+use std::fmt;
+
+/// 摘要里最多列出的区间数，其余只给出个数
+const MAX_LISTED: usize = 4;
+
+const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+/// 能让 `bytes` 不小于 1 的最大单位
+fn unit_of(bytes: usize) -> usize {
+    let mut unit = 0;
+    while unit < UNITS.len() - 1 && bytes >> (10 * (unit + 1)) > 0 {
+        unit += 1;
+    }
+    unit
+}
+
+/// 保留一位小数，整数不带小数点
+fn write_scaled(f: &mut fmt::Formatter<'_>, bytes: usize, unit: usize) -> fmt::Result {
+    let value = bytes as f64 / (1u64 << (10 * unit)) as f64;
+    let value = (value * 10.0).round() / 10.0;
+    match value.fract() == 0.0 {
+        true => write!(f, "{value:.0}"),
+        false => write!(f, "{value:.1}"),
+    }
+}
+
+/// 以二进制单位显示字节数，如 `512B`、`12MiB`、`1.5GiB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub usize);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = unit_of(self.0);
+        write_scaled(f, self.0, unit)?;
+        f.write_str(UNITS[unit])
+    }
+}
+
+/// 两端共用终点的单位，如 `14-20MiB`；区间短到一位小数分辨不出时换用更小的单位
+fn write_range(f: &mut fmt::Formatter<'_>, rgn: &FileRange) -> fmt::Result {
+    let unit = unit_of(rgn.end()).min(unit_of(rgn.interval().saturating_mul(10)));
+    write_scaled(f, rgn.start(), unit)?;
+    f.write_str("-")?;
+    write_scaled(f, rgn.end(), unit)?;
+    f.write_str(UNITS[unit])
+}
+
+/// `done` 占 `total` 的百分比，`total` 为零时视为已完成
+pub fn percent(done: usize, total: usize) -> f64 {
+    match total {
+        0 => 100.0,
+        total => done.min(total) as f64 * 100.0 / total as f64,
+    }
+}
+
+/// 供日志与命令行输出的区间摘要，如 `0-12MiB, 14-20MiB, 9% of 200MiB`
+pub struct RangeSummary<'a> {
+    ranges: &'a FileMultiRange,
+    total: usize,
+}
+
+impl fmt::Display for RangeSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ranges.is_empty() {
+            f.write_str("nothing, ")?;
+        }
+        for rgn in self.ranges.iter().take(MAX_LISTED) {
+            write_range(f, rgn)?;
+            f.write_str(", ")?;
+        }
+        let hidden = self.ranges.interval_count().saturating_sub(MAX_LISTED);
+        if hidden > 0 {
+            write!(f, "... {hidden} more, ")?;
+        }
+        // 向下取整，没有完成时不会显示 100%
+        let percent = self.ranges.percent_of(self.total).floor();
+        write!(f, "{percent}% of {}", ByteSize(self.total))
+    }
+}
+
+impl FileMultiRange {
+    /// 已覆盖的字节占 `total` 的百分比，超出 `total` 的部分不计
+    pub fn percent_of(&self, total: usize) -> f64 {
+        let covered = self
+            .iter()
+            .map(|rgn| rgn.end().min(total).saturating_sub(rgn.start()))
+            .sum();
+        percent(covered, total)
+    }
+
+    pub fn summary(&self, total: usize) -> RangeSummary<'_> {
+        RangeSummary {
+            ranges: self,
+            total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+
+    const MIB: usize = 1024 * 1024;
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(ByteSize(0).to_string(), "0B");
+        assert_eq!(ByteSize(512).to_string(), "512B");
+        assert_eq!(ByteSize(12 * MIB).to_string(), "12MiB");
+        assert_eq!(ByteSize(1536 * MIB).to_string(), "1.5GiB");
+        assert_eq!(ByteSize(1000).to_string(), "1000B");
+        assert_eq!(ByteSize(1025).to_string(), "1KiB");
+    }
+
+    #[test]
+    fn summaries() {
+        let ranges =
+            FileMultiRange::try_from([0..12 * MIB, 14 * MIB..20 * MIB].as_slice()).unwrap();
+        assert_eq!(
+            ranges.summary(200 * MIB).to_string(),
+            "0-12MiB, 14-20MiB, 9% of 200MiB"
+        );
+        assert_eq!(ranges.percent_of(20 * MIB), 90.0);
+        // 超出总长的部分不计
+        assert_eq!(ranges.percent_of(10 * MIB), 100.0);
+
+        let almost = FileMultiRange::try_from(slice::from_ref(&(0..199 * MIB + MIB / 2))).unwrap();
+        assert_eq!(
+            almost.summary(200 * MIB).to_string(),
+            "0-199.5MiB, 99% of 200MiB"
+        );
+        let tail = FileMultiRange::try_from(slice::from_ref(&(1536 * MIB..2048 * MIB))).unwrap();
+        assert_eq!(
+            tail.summary(2048 * MIB).to_string(),
+            "1.5-2GiB, 25% of 2GiB"
+        );
+        assert_eq!(
+            FileMultiRange::new().summary(MIB).to_string(),
+            "nothing, 0% of 1MiB"
+        );
+
+        let sparse = (0..6).map(|i| i * MIB..i * MIB + 1024).collect::<Vec<_>>();
+        let sparse = FileMultiRange::try_from(sparse.as_slice()).unwrap();
+        assert_eq!(
+            sparse.summary(6 * MIB).to_string(),
+            "0-1KiB, 1024-1025KiB, 2048-2049KiB, 3072-3073KiB, ... 2 more, 0% of 6MiB"
+        );
+    }
+}
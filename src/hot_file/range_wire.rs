@@ -0,0 +1,179 @@
+use super::{FileMultiRange, FileRange};
+use bincode::{
+    Decode, Encode,
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RangeWireError {
+    #[error("Truncated range encoding")]
+    Truncated,
+    #[error("Varint in range encoding overflows")]
+    Overflow,
+    #[error("Empty or overlapping range in encoding")]
+    Malformed,
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn get_varint(buf: &mut &[u8]) -> Result<u64, RangeWireError> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or(RangeWireError::Truncated)?;
+        *buf = rest;
+        let bits = (byte & 0x7f) as u64;
+        if bits << shift >> shift != bits {
+            return Err(RangeWireError::Overflow);
+        }
+        n |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(RangeWireError::Overflow)
+}
+
+impl FileMultiRange {
+    /// 紧凑编码：区间数，然后每个区间记录与上一区间末尾的间隔和自身长度，均为 varint
+    ///
+    /// 碎片化的进度也只需要每个区间几个字节
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + self.len() * 4);
+        put_varint(&mut buf, self.len() as u64);
+        let mut cursor = 0;
+        for rgn in self.iter() {
+            put_varint(&mut buf, (rgn.start() - cursor) as u64);
+            put_varint(&mut buf, rgn.interval() as u64);
+            cursor = rgn.end();
+        }
+        buf
+    }
+
+    /// 对端发来的数据不可信，空区间、相连区间和越界都会被拒绝
+    pub fn from_wire(mut buf: &[u8]) -> Result<Self, RangeWireError> {
+        let count = get_varint(&mut buf)?;
+        // 每个区间至少占两个字节，先挡住伪造的超大计数
+        if count > buf.len() as u64 / 2 {
+            return Err(RangeWireError::Truncated);
+        }
+        let mut ranges = Self::new();
+        let mut cursor = 0usize;
+        for n in 0..count {
+            let gap =
+                usize::try_from(get_varint(&mut buf)?).map_err(|_| RangeWireError::Overflow)?;
+            let len =
+                usize::try_from(get_varint(&mut buf)?).map_err(|_| RangeWireError::Overflow)?;
+            // 除第一个区间外，间隔为 0 意味着与上一个区间相连，编码端不会产生
+            if len == 0 || (n > 0 && gap == 0) {
+                return Err(RangeWireError::Malformed);
+            }
+            let start = cursor.checked_add(gap).ok_or(RangeWireError::Overflow)?;
+            let end = start.checked_add(len).ok_or(RangeWireError::Overflow)?;
+            ranges.add(FileRange::new(start, end));
+            cursor = end;
+        }
+        if !buf.is_empty() {
+            return Err(RangeWireError::Malformed);
+        }
+        Ok(ranges)
+    }
+}
+
+/// 协议消息里的进度同样走紧凑编码
+impl Encode for FileMultiRange {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.to_wire().encode(encoder)
+    }
+}
+
+impl<Context> Decode<Context> for FileMultiRange {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let buf = Vec::<u8>::decode(decoder)?;
+        Self::from_wire(&buf).map_err(|err| DecodeError::OtherString(err.to_string()))
+    }
+}
+
+bincode::impl_borrow_decode!(FileMultiRange);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn wire_round_trip() {
+        let mut ranges = FileMultiRange::new();
+        for (start, end) in [(0, 100), (4096, 1 << 20), (usize::MAX - 10, usize::MAX)] {
+            ranges.add(FileRange::new(start, end));
+        }
+        let encoded = ranges.to_wire();
+        assert_eq!(FileMultiRange::from_wire(&encoded), Ok(ranges.clone()));
+        assert_eq!(
+            FileMultiRange::from_wire(&encoded[..encoded.len() - 1]),
+            Err(RangeWireError::Truncated)
+        );
+        let empty = FileMultiRange::new();
+        assert_eq!(FileMultiRange::from_wire(&empty.to_wire()), Ok(empty));
+
+        let config = bincode::config::standard();
+        let buf = bincode::encode_to_vec(&ranges, config).unwrap();
+        let (decoded, _): (FileMultiRange, _) = bincode::decode_from_slice(&buf, config).unwrap();
+        assert_eq!(decoded, ranges);
+    }
+
+    #[test]
+    fn reject_forged_encoding() {
+        // 声称有海量区间
+        assert_eq!(
+            FileMultiRange::from_wire(&[0xff, 0xff, 0x03, 0, 1]),
+            Err(RangeWireError::Truncated)
+        );
+        // 长度为 0 的区间
+        assert_eq!(
+            FileMultiRange::from_wire(&[1, 5, 0]),
+            Err(RangeWireError::Malformed)
+        );
+        // 与上一个区间相连
+        assert_eq!(
+            FileMultiRange::from_wire(&[2, 0, 1, 0, 1]),
+            Err(RangeWireError::Malformed)
+        );
+        assert_eq!(
+            FileMultiRange::from_wire(&[
+                1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 1
+            ]),
+            Err(RangeWireError::Overflow)
+        );
+    }
+
+    #[test]
+    fn fuzz_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..1000 {
+            let mut ranges = FileMultiRange::new();
+            for _ in 0..rng.random_range(0..64) {
+                let start = rng.random_range(0..1 << 30);
+                let len = rng.random_range(1..1 << 16);
+                ranges.add(FileRange::new(start, start + len));
+            }
+            let encoded = ranges.to_wire();
+            assert_eq!(FileMultiRange::from_wire(&encoded), Ok(ranges));
+            // 随机字节只能解出合法结果或报错，不能 panic
+            let noise = (0..rng.random_range(0..32))
+                .map(|_| rng.random())
+                .collect::<Vec<u8>>();
+            if let Ok(decoded) = FileMultiRange::from_wire(&noise) {
+                assert_eq!(FileMultiRange::from_wire(&decoded.to_wire()), Ok(decoded));
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use super::FileRange;
+use bytes::Bytes;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// 顺序读取时一次多读后面几段，缓存在一个小的 LRU 里
+///
+/// 只服务磁盘部分，写入与落盘都会使重叠的缓存失效
+#[derive(Debug, Default)]
+pub(super) struct ReadAhead {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// 预读的段数，0 表示关闭
+    depth: usize,
+    /// 上一次读取的结束位置，用于判断是否顺序访问
+    last_end: Option<usize>,
+    /// 最近使用的在队尾
+    cache: VecDeque<(FileRange, Bytes)>,
+}
+
+impl Inner {
+    /// 预读一轮的段数再留一轮余量
+    fn capacity(&self) -> usize {
+        self.depth * 2
+    }
+}
+
+impl ReadAhead {
+    pub(super) fn set_depth(&self, depth: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.depth = depth;
+        let capacity = inner.capacity();
+        while inner.cache.len() > capacity {
+            inner.cache.pop_front();
+        }
+    }
+
+    pub(super) fn depth(&self) -> usize {
+        self.inner.lock().unwrap().depth
+    }
+
+    /// 命中时返回缓存中的切片
+    pub(super) fn get(&self, rgn: FileRange) -> Option<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        let idx = inner
+            .cache
+            .iter()
+            .position(|(cached, _)| cached.contains(&rgn))?;
+        inner.last_end = Some(rgn.end());
+        let entry = inner.cache.remove(idx)?;
+        let buf = entry
+            .1
+            .slice(rgn.start() - entry.0.start()..rgn.end() - entry.0.start());
+        inner.cache.push_back(entry);
+        Some(buf)
+    }
+
+    /// 未命中时决定这次实际从磁盘读多少：顺序访问时向后扩展 `depth` 段
+    pub(super) fn plan(&self, rgn: FileRange, limit: usize) -> FileRange {
+        let mut inner = self.inner.lock().unwrap();
+        let sequential = inner.last_end.replace(rgn.end()) == Some(rgn.start());
+        if inner.depth == 0 || !sequential || rgn.end() >= limit {
+            return rgn;
+        }
+        let extended = rgn.interval().saturating_mul(inner.depth + 1);
+        FileRange::new(rgn.start(), limit.min(rgn.start().saturating_add(extended)))
+    }
+
+    /// 缓存读出但还没被请求的部分，`buf` 覆盖 `read`，请求的是其开头的 `rgn`
+    pub(super) fn fill(&self, rgn: FileRange, read: FileRange, buf: &Bytes) {
+        if read.end() <= rgn.end() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let ahead = FileRange::new(rgn.end(), read.end());
+        let slice = buf.slice(rgn.end() - read.start()..);
+        inner
+            .cache
+            .retain(|(cached, _)| cached.intersect(&ahead).is_none());
+        inner.cache.push_back((ahead, slice));
+        let capacity = inner.capacity();
+        while inner.cache.len() > capacity {
+            inner.cache.pop_front();
+        }
+    }
+
+    pub(super) fn invalidate(&self, rgn: FileRange) {
+        self.inner
+            .lock()
+            .unwrap()
+            .cache
+            .retain(|(cached, _)| cached.intersect(&rgn).is_none());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefetch_only_when_sequential() {
+        let read_ahead = ReadAhead::default();
+        read_ahead.set_depth(2);
+        // 第一次访问无从判断是否顺序
+        assert_eq!(
+            read_ahead.plan(FileRange::new(0, 4), 100),
+            FileRange::new(0, 4)
+        );
+        assert_eq!(
+            read_ahead.plan(FileRange::new(8, 12), 100),
+            FileRange::new(8, 12)
+        );
+
+        let rgn = FileRange::new(12, 16);
+        assert!(read_ahead.get(rgn).is_none());
+        let read = read_ahead.plan(rgn, 100);
+        assert_eq!(read, FileRange::new(12, 24));
+        read_ahead.fill(rgn, read, &Bytes::from_static(b"BBBBCCCCDDDD"));
+        assert_eq!(read_ahead.get(FileRange::new(16, 20)).unwrap(), "CCCC");
+        assert_eq!(read_ahead.get(FileRange::new(20, 24)).unwrap(), "DDDD");
+
+        read_ahead.invalidate(FileRange::new(21, 22));
+        assert!(read_ahead.get(FileRange::new(20, 24)).is_none());
+        // 命中也算作访问，之后的读取依然是顺序的，但不超过文件末尾
+        assert_eq!(
+            read_ahead.plan(FileRange::new(24, 28), 30),
+            FileRange::new(24, 30)
+        );
+    }
+}
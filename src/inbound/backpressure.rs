@@ -0,0 +1,195 @@
+use crate::config::{ConfigItem, ConfigManager};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// 入站队列满时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// 暂停读取 socket，由内核缓冲区吸收或丢弃
+    #[default]
+    Park,
+    /// 丢弃新到的报文，保持读取
+    Drop,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "park" => Ok(Overflow::Park),
+            "drop" => Ok(Overflow::Drop),
+            other => Err(format!(
+                "unknown overflow policy: {other}, expected park or drop"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Park => f.write_str("park"),
+            Overflow::Drop => f.write_str("drop"),
+        }
+    }
+}
+
+/// 入站队列的容量与溢出策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InboundPolicy {
+    pub capacity: usize,
+    pub overflow: Overflow,
+    /// 同一来源在统计窗口内允许的畸形报文数
+    pub error_budget: u32,
+    /// 超出预算的来源被屏蔽的时长
+    pub ban: Duration,
+}
+
+impl Default for InboundPolicy {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            overflow: Overflow::Park,
+            error_budget: 32,
+            ban: Duration::from_secs(60),
+        }
+    }
+}
+
+impl InboundPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            capacity: cfg
+                .get_typed(ConfigItem::InboundQueueCapacity)
+                .await
+                .unwrap_or(default.capacity),
+            overflow: cfg
+                .get_typed(ConfigItem::InboundOverflow)
+                .await
+                .unwrap_or(default.overflow),
+            error_budget: cfg
+                .get_typed(ConfigItem::DecodeErrorBudget)
+                .await
+                .unwrap_or(default.error_budget),
+            ban: cfg
+                .get_typed(ConfigItem::DecodeBanMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.ban),
+        }
+    }
+}
+
+/// 队列某一时刻的快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueDepth {
+    /// 当前排队的报文数
+    pub depth: usize,
+    pub capacity: usize,
+    /// 历史最高排队数
+    pub peak: usize,
+    /// 因队列满被丢弃的报文数
+    pub dropped: u64,
+    /// 因队列满暂停读取的次数
+    pub parked: u64,
+}
+
+impl QueueDepth {
+    /// 排队占容量的比例，接近 1 说明接收端跟不上
+    pub fn saturation(&self) -> f64 {
+        match self.capacity {
+            0 => 0.0,
+            capacity => self.depth as f64 / capacity as f64,
+        }
+    }
+}
+
+/// 入站队列的统计，由转发任务更新
+pub struct QueueMetrics<T> {
+    // 弱引用不会阻止通道关闭
+    tx: mpsc::WeakSender<T>,
+    capacity: usize,
+    peak: AtomicUsize,
+    dropped: AtomicU64,
+    parked: AtomicU64,
+}
+
+impl<T> QueueMetrics<T> {
+    pub(super) fn new(tx: &mpsc::Sender<T>) -> Self {
+        Self {
+            tx: tx.downgrade(),
+            capacity: tx.max_capacity(),
+            peak: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            parked: AtomicU64::new(0),
+        }
+    }
+
+    /// 通道关闭后深度视为 0
+    pub fn depth(&self) -> usize {
+        self.tx
+            .upgrade()
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .unwrap_or(0)
+    }
+
+    pub(super) fn record_sent(&self) {
+        self.peak.fetch_max(self.depth(), Ordering::Relaxed);
+    }
+
+    pub(super) fn record_dropped(&self) -> u64 {
+        self.dropped.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub(super) fn record_parked(&self) {
+        self.parked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> QueueDepth {
+        QueueDepth {
+            depth: self.depth(),
+            capacity: self.capacity,
+            peak: self.peak.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            parked: self.parked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_overflow() {
+        assert_eq!("Park".parse(), Ok(Overflow::Park));
+        assert_eq!(" drop ".parse(), Ok(Overflow::Drop));
+        assert!("block".parse::<Overflow>().is_err());
+        assert_eq!(Overflow::Drop.to_string().parse(), Ok(Overflow::Drop));
+    }
+
+    #[tokio::test]
+    async fn depth_follows_channel() {
+        let (tx, mut rx) = mpsc::channel::<u8>(4);
+        let metrics = QueueMetrics::new(&tx);
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+            metrics.record_sent();
+        }
+        assert_eq!(metrics.depth(), 3);
+        rx.recv().await.unwrap();
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.depth, 2);
+        assert_eq!(snapshot.peak, 3);
+        assert_eq!(snapshot.capacity, 4);
+        drop(tx);
+        assert_eq!(metrics.depth(), 0);
+    }
+}
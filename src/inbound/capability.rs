@@ -0,0 +1,121 @@
+use crate::config::{ConfigItem, ConfigManager};
+use std::fmt;
+
+/// 线路协议版本：主版本不同无法互通，次版本只做向后兼容的扩展
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl ProtocolVersion {
+    pub const CURRENT: Self = Self::new(0, 1);
+    /// 版本字节最高位留给压缩标记，主版本占 3 位
+    const MAJOR_MASK: u8 = 0x07;
+    const MINOR_MASK: u8 = 0x0f;
+
+    pub const fn new(major: u8, minor: u8) -> Self {
+        Self {
+            major: major & Self::MAJOR_MASK,
+            minor: minor & Self::MINOR_MASK,
+        }
+    }
+
+    /// 报文头中的版本字节，高位的压缩标记由编码器另行设置
+    pub const fn to_wire(self) -> u8 {
+        (self.major << 4) | self.minor
+    }
+
+    /// 调用前需去掉压缩标记
+    pub const fn from_wire(byte: u8) -> Self {
+        Self::new(byte >> 4, byte)
+    }
+
+    pub fn is_compatible(self, other: Self) -> bool {
+        self.major == other.major
+    }
+
+    /// 双方都能理解的版本，主版本不同时无法协商
+    pub fn negotiate(self, other: Self) -> Option<Self> {
+        self.is_compatible(other).then(|| self.min(other))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+bitflags::bitflags! {
+    /// 本端支持的协议特性
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Capabilities: u32 {
+        /// 控制类消息超过阈值时使用 lz4 压缩
+        const CONTROL_COMPRESSION = 1;
+        /// 数据报文超过阈值时使用 lz4 压缩，需双方在握手中都声明
+        const DATA_COMPRESSION = 1 << 1;
+        /// 同一文件的数据可以分散到多条链路上并行发送
+        const STRIPING = 1 << 2;
+        /// 愿意为其他对端转发报文
+        const RELAY = 1 << 3;
+        /// 按字节数或时间与对端协调更换会话密钥
+        const REKEY = 1 << 4;
+        /// 数据报文可以不加密，仅用于可信局域网测速，握手与控制消息照常加密认证
+        const PLAINTEXT_DATA = 1 << 5;
+        /// 空闲会话定期发送 `Msg::Keepalive`，长时间收不到任何报文的会话视为对端已离开
+        const KEEPALIVE = 1 << 6;
+        /// 整文件的最终校验使用 BLAKE3，块级比对仍然使用 xxh3
+        const BLAKE3 = 1 << 7;
+    }
+}
+
+impl Capabilities {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let mut caps = Self::default();
+        let data_compression = cfg
+            .get_typed::<bool>(ConfigItem::DataCompression)
+            .await
+            .unwrap_or(true);
+        caps.set(Self::DATA_COMPRESSION, data_compression);
+        let relay = cfg
+            .get_typed::<bool>(ConfigItem::RelayServe)
+            .await
+            .unwrap_or_default();
+        caps.set(Self::RELAY, relay);
+        let plaintext = cfg
+            .get_typed::<bool>(ConfigItem::PlaintextData)
+            .await
+            .unwrap_or_default();
+        caps.set(Self::PLAINTEXT_DATA, plaintext);
+        caps
+    }
+}
+
+impl Default for Capabilities {
+    /// 明文数据必须显式开启
+    fn default() -> Self {
+        Self::all().difference(Self::PLAINTEXT_DATA)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_wire_roundtrip() {
+        let version = ProtocolVersion::new(3, 9);
+        assert_eq!(ProtocolVersion::from_wire(version.to_wire()), version);
+        assert_eq!(ProtocolVersion::CURRENT.to_string(), "0.1");
+        // 不会占用压缩标记位
+        assert_eq!(ProtocolVersion::new(0xff, 0xff).to_wire() & 0x80, 0);
+
+        let older = ProtocolVersion::new(0, 0);
+        assert_eq!(ProtocolVersion::CURRENT.negotiate(older), Some(older));
+        assert_eq!(
+            ProtocolVersion::CURRENT.negotiate(ProtocolVersion::new(1, 0)),
+            None
+        );
+    }
+}
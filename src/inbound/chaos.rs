@@ -0,0 +1,212 @@
+use super::{MsgStream, StreamHandle};
+use crate::config::{ConfigItem, ConfigManager};
+use futures::{
+    StreamExt,
+    stream::{self, Abortable},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        OnceLock, RwLock,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    },
+    time::Duration,
+};
+use tokio::time::{Instant, sleep_until};
+use tracing::{trace, warn};
+
+/// 对收到的报文注入的故障，各项概率相互独立
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChaosPolicy {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub delay: f64,
+    /// 被推迟的报文在零到此值之间均匀推迟
+    pub max_delay: Duration,
+}
+
+impl ChaosPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self {
+            max_delay: Duration::from_millis(200),
+            ..Default::default()
+        };
+        Self {
+            drop: cfg
+                .get_typed(ConfigItem::ChaosDrop)
+                .await
+                .unwrap_or(default.drop),
+            duplicate: cfg
+                .get_typed(ConfigItem::ChaosDuplicate)
+                .await
+                .unwrap_or(default.duplicate),
+            delay: cfg
+                .get_typed(ConfigItem::ChaosDelay)
+                .await
+                .unwrap_or(default.delay),
+            max_delay: cfg
+                .get_typed(ConfigItem::ChaosMaxDelayMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_delay),
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.drop <= 0.0 && self.duplicate <= 0.0 && (self.delay <= 0.0 || self.max_delay.is_zero())
+    }
+}
+
+/// 已注入的故障数
+#[derive(Debug, Default)]
+pub struct ChaosStats {
+    pub dropped: AtomicU64,
+    pub duplicated: AtomicU64,
+    pub delayed: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct Chaos {
+    policy: RwLock<ChaosPolicy>,
+    stats: ChaosStats,
+}
+
+pub fn chaos() -> &'static Chaos {
+    static CHAOS: OnceLock<Chaos> = OnceLock::new();
+    CHAOS.get_or_init(Chaos::default)
+}
+
+impl Chaos {
+    /// 只影响之后绑定的 socket
+    pub fn configure(&self, policy: ChaosPolicy) {
+        if !policy.is_noop() {
+            warn!("Fault injection enabled on received datagrams: {policy:?}");
+        }
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> ChaosPolicy {
+        *self.policy.read().unwrap()
+    }
+
+    pub fn stats(&self) -> &ChaosStats {
+        &self.stats
+    }
+
+    /// 按当前策略包装接收流，策略为空时原样返回
+    ///
+    /// 只作用于接收方向，收发两端都开启即可模拟双向的恶劣链路
+    pub fn wrap(&'static self, stream: MsgStream) -> MsgStream {
+        let policy = self.policy();
+        if policy.is_noop() {
+            return stream;
+        }
+        // 外层不单独终止，原来的 `StreamHandle` 终止内层后外层随之结束
+        let (_, registration) = StreamHandle::new_pair();
+        let rng = StdRng::from_os_rng();
+        let pending = BTreeMap::<(Instant, u64), _>::new();
+        let faulty = stream::unfold(
+            (stream, rng, pending, 0u64),
+            move |(mut inner, mut rng, mut pending, mut seq)| async move {
+                loop {
+                    let next = pending.first_key_value().map(|(&(at, _), _)| at);
+                    let received = tokio::select! {
+                        _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                            let (_, item) = pending.pop_first().expect("next comes from pending");
+                            return Some((item, (inner, rng, pending, seq)));
+                        }
+                        received = inner.next() => received,
+                    };
+                    let Some(received) = received else {
+                        // 原始流结束时丢弃还没到期的报文
+                        return None;
+                    };
+                    // 只对解码成功的报文注入故障，错误原样交出
+                    let (msg, src) = match received {
+                        Ok((Ok(msg), src)) => (msg, src),
+                        received => return Some((received, (inner, rng, pending, seq))),
+                    };
+                    if rng.random_bool(policy.drop.clamp(0.0, 1.0)) {
+                        trace!("Chaos drops datagram from {src}");
+                        self.stats.dropped.fetch_add(1, Relaxed);
+                        continue;
+                    }
+                    let copies = match rng.random_bool(policy.duplicate.clamp(0.0, 1.0)) {
+                        true => {
+                            self.stats.duplicated.fetch_add(1, Relaxed);
+                            2
+                        }
+                        false => 1,
+                    };
+                    for _ in 0..copies {
+                        let delay = match rng.random_bool(policy.delay.clamp(0.0, 1.0)) {
+                            true => {
+                                self.stats.delayed.fetch_add(1, Relaxed);
+                                policy.max_delay.mul_f64(rng.random())
+                            }
+                            false => Duration::ZERO,
+                        };
+                        seq += 1;
+                        pending.insert((Instant::now() + delay, seq), Ok((Ok(msg.clone()), src)));
+                    }
+                }
+            },
+        );
+        Abortable::new(faulty.boxed(), registration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addr::EndPoint,
+        inbound::{HostId, Msg},
+        testing::MemoryNetwork,
+    };
+    use futures::SinkExt;
+    use std::net::SocketAddr;
+
+    #[tokio::test(start_paused = true)]
+    async fn inject_faults() {
+        let chaos: &'static Chaos = Box::leak(Box::default());
+        let a: EndPoint = "[2001:db8::1]:5555".parse().unwrap();
+        let b: EndPoint = "[2001:db8::2]:5555".parse().unwrap();
+        let network = MemoryNetwork::new(1);
+        let (_, mut sink, _, _) = network.bind(a);
+        let (_, _, stream, _) = network.bind(b);
+
+        chaos.configure(ChaosPolicy {
+            duplicate: 1.0,
+            delay: 1.0,
+            max_delay: Duration::from_millis(100),
+            ..Default::default()
+        });
+        let mut stream = chaos.wrap(stream);
+        let start = Instant::now();
+        let msg = Msg::Discovery {
+            host: HostId::random(),
+            remote: a,
+        };
+        sink.send((msg.clone(), SocketAddr::from(b))).await.unwrap();
+        for _ in 0..2 {
+            let (decoded, _) = stream.next().await.unwrap().unwrap();
+            assert_eq!(decoded.unwrap(), msg);
+        }
+        assert!(start.elapsed() <= Duration::from_millis(100));
+        assert_eq!(chaos.stats().duplicated.load(Relaxed), 1);
+        assert_eq!(chaos.stats().delayed.load(Relaxed), 2);
+
+        chaos.configure(ChaosPolicy {
+            drop: 1.0,
+            ..Default::default()
+        });
+        let (_, _, stream, _) = network.bind(b);
+        let mut stream = chaos.wrap(stream);
+        sink.send((msg, SocketAddr::from(b))).await.unwrap();
+        let timeout = tokio::time::timeout(Duration::from_secs(1), stream.next());
+        assert!(timeout.await.is_err());
+        assert_eq!(chaos.stats().dropped.load(Relaxed), 1);
+    }
+}
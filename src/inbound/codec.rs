@@ -1,65 +1,309 @@
-use super::Msg;
-use anyhow::anyhow;
+use super::{Capabilities, HostId, Msg, ProtocolVersion, SocketStats};
+use crate::{addr::EndPoint, link::link_state_table, session::peer_capabilities};
 use bytes::{Buf, BytesMut};
+use dashmap::DashMap;
+use std::{
+    borrow::Cow,
+    net::SocketAddr,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use thiserror::Error;
 use tokio_util::codec::{Decoder, Encoder};
+use tracing::debug;
 
-const PROTOCOL_VERSION: u8 = 0;
+const PROTOCOL_VERSION: u8 = ProtocolVersion::CURRENT.to_wire();
+/// 版本字节的最高位标记消息体已压缩
+const COMPRESSED_FLAG: u8 = 0x80;
+/// 小于该长度的控制消息不值得压缩
+const COMPRESS_THRESHOLD: usize = 256;
+/// 数据报文压缩要消耗 CPU，只对足够大的报文尝试
+const DATA_COMPRESS_THRESHOLD: usize = 1024;
+/// 支持巨型帧的链路 MTU 上限
+pub const MAX_MTU: usize = 9000;
+/// IPv6 与 UDP 头
+pub const IP_UDP_OVERHEAD: usize = 40 + 8;
+/// 单个报文的长度上限，收发两端都按它检查，长度字段超过它的报文直接视为畸形
+pub const MAX_FRAME: usize = MAX_MTU - IP_UDP_OVERHEAD;
+/// 解压后与 bincode 解码时允许的最大字节数，防止构造的长度前缀触发巨量分配
+pub(super) const MAX_DECODED: usize = 1 << 20;
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error(transparent)]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error(transparent)]
+    Decompress(#[from] lz4_flex::block::DecompressError),
+    #[error("Frame of {0} bytes exceeds the {MAX_FRAME}-byte limit")]
+    Oversized(usize),
+    #[error("Malformed frame header")]
+    Malformed,
+    #[error("Datagram ends after {0} bytes, before the frame it declares")]
+    Truncated(usize),
+    #[error("Compressed body claims {0} bytes, over the {MAX_DECODED}-byte limit")]
+    DecompressedTooLarge(usize),
+    #[error("Message of {0} bytes exceeds the {MAX_DECODED}-byte reassembly limit")]
+    Unfragmentable(usize),
+    #[error("{} batched datagram groups failed to send", .0.len())]
+    Batch(Vec<GroupFailure>),
+}
+
+/// 批量发送中没能发出的一组报文，发送方据此找到对应的主机与链路
+#[derive(Debug)]
+pub struct GroupFailure {
+    pub dst: SocketAddr,
+    pub count: usize,
+    pub error: std::io::Error,
+}
+
+/// 单个报文的解码结果
+///
+/// 畸形报文作为一项交出而不是流的错误，这样 `UdpFramed` 会附上来源地址，
+/// 接收端据此按来源计数，流本身只因 io 错误结束
+pub type Decoded = Result<Msg, CodecError>;
+
+pub(super) fn bincode_config() -> impl bincode::config::Config {
+    bincode::config::standard().with_limit::<MAX_DECODED>()
+}
+
+#[derive(Debug, Default)]
+pub struct CompressionStats {
+    pub frames: AtomicU64,
+    pub raw_bytes: AtomicU64,
+    pub compressed_bytes: AtomicU64,
+}
+
+pub fn compression_stats() -> &'static CompressionStats {
+    static COMPRESSION_STATS: OnceLock<CompressionStats> = OnceLock::new();
+    COMPRESSION_STATS.get_or_init(CompressionStats::default)
+}
+
+/// 发往各对端的会话层报文的压缩统计，按对端区分以便观察每个传输的压缩效果
+pub fn data_compression_stats() -> &'static DashMap<HostId, CompressionStats> {
+    static DATA_COMPRESSION_STATS: OnceLock<DashMap<HostId, CompressionStats>> = OnceLock::new();
+    DATA_COMPRESSION_STATS.get_or_init(DashMap::new)
+}
+
+impl CompressionStats {
+    fn record(&self, raw: usize, compressed: usize) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.raw_bytes.fetch_add(raw as u64, Ordering::Relaxed);
+        self.compressed_bytes
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    /// 压缩后与压缩前的字节比，没有压缩过时为 1
+    pub fn ratio(&self) -> f64 {
+        match self.raw_bytes.load(Ordering::Relaxed) {
+            0 => 1.0,
+            raw => self.compressed_bytes.load(Ordering::Relaxed) as f64 / raw as f64,
+        }
+    }
+}
+
+/// 与 peer 协商过数据压缩时压缩会话层报文，返回 None 表示发原文
+///
+/// 加密后的报文压不动，所以由会话层在加密前调用，而不是在编码时
+pub fn compress_for(peer: &HostId, buf: &[u8]) -> Option<Vec<u8>> {
+    if buf.len() < DATA_COMPRESS_THRESHOLD
+        || !peer_capabilities(peer).contains(Capabilities::DATA_COMPRESSION)
+    {
+        return None;
+    }
+    let compressed = lz4_flex::compress_prepend_size(buf);
+    (compressed.len() < buf.len()).then(|| {
+        data_compression_stats()
+            .entry(peer.clone())
+            .or_default()
+            .record(buf.len(), compressed.len());
+        compressed
+    })
+}
+
+/// 先检查长度前缀再解压，解压结果不超过 `MAX_DECODED`
+pub fn decompress(buf: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let claimed = buf
+        .first_chunk::<4>()
+        .map(|prefix| u32::from_le_bytes(*prefix) as usize)
+        .ok_or(CodecError::Malformed)?;
+    if claimed > MAX_DECODED {
+        return Err(CodecError::DecompressedTooLarge(claimed));
+    }
+    Ok(lz4_flex::decompress_size_prepended(buf)?)
+}
 
 #[derive(Default)]
-pub struct MsgCodec;
+pub struct MsgCodec {
+    caps: Capabilities,
+    /// 所属 socket 的收发计数
+    stats: Arc<SocketStats>,
+}
 
 impl MsgCodec {
-    const HDR_LEN: usize = size_of::<u16>() + size_of::<u8>();
+    /// 包长、版本
+    ///
+    /// 头部不经认证，重放由会话层按 Noise nonce 检测
+    pub const HDR_LEN: usize = size_of::<u16>() + size_of::<u8>();
+
+    pub fn with_capabilities(caps: Capabilities) -> Self {
+        Self {
+            caps,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_stats(stats: Arc<SocketStats>) -> Self {
+        Self {
+            stats,
+            ..Default::default()
+        }
+    }
 }
 
-impl Encoder<Msg> for MsgCodec {
-    type Error = anyhow::Error;
-    fn encode(&mut self, item: Msg, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let msg_buf = bincode::encode_to_vec(item, bincode::config::standard())?;
-        let total_len = msg_buf
-            .len()
-            .checked_add(Self::HDR_LEN)
-            .ok_or_else(|| anyhow!("Length overflow usize"))?;
-        let total_len: u16 = total_len
-            .try_into()
-            .map_err(|_| anyhow!("Length overflow u16"))?;
+impl MsgCodec {
+    /// 只有确定目标地址上的对端协商了控制消息压缩时才压缩，不知道目标时发原文
+    fn encode_to(
+        &mut self,
+        item: Msg,
+        to: Option<SocketAddr>,
+        dst: &mut BytesMut,
+    ) -> Result<(), CodecError> {
+        let is_control = item.is_control();
+        let mut msg_buf = bincode::encode_to_vec(item, bincode::config::standard())?;
+        let mut header = PROTOCOL_VERSION;
+        if is_control
+            && self.caps.contains(Capabilities::CONTROL_COMPRESSION)
+            && msg_buf.len() >= COMPRESS_THRESHOLD
+            && to.is_some_and(accepts_compression)
+        {
+            let compressed = lz4_flex::compress_prepend_size(&msg_buf);
+            // 压不动就发原文
+            if compressed.len() < msg_buf.len() {
+                compression_stats().record(msg_buf.len(), compressed.len());
+                msg_buf = compressed;
+                header |= COMPRESSED_FLAG;
+            }
+        }
+        let total_len = msg_buf.len().saturating_add(Self::HDR_LEN);
+        if total_len > MAX_FRAME {
+            return Err(CodecError::Oversized(total_len));
+        }
+        let total_len = total_len as u16;
+        self.stats.record_sent(total_len as usize);
         dst.extend(
             total_len // udp 包长
                 .to_be_bytes()
                 .iter()
                 .copied()
-                .chain([PROTOCOL_VERSION].iter().copied())
+                .chain([header].iter().copied())
                 .chain(msg_buf),
         );
         Ok(())
     }
 }
 
+/// 按地址反查直连的对端，看它在握手中是否声明了控制消息压缩
+fn accepts_compression(to: SocketAddr) -> bool {
+    let SocketAddr::V6(to) = to else {
+        return false;
+    };
+    EndPoint::try_from(to)
+        .ok()
+        .and_then(|remote| link_state_table().host_at(&remote))
+        .is_some_and(|peer| peer_capabilities(&peer).contains(Capabilities::CONTROL_COMPRESSION))
+}
+
+impl Encoder<(Msg, SocketAddr)> for MsgCodec {
+    type Error = CodecError;
+    fn encode(
+        &mut self,
+        (item, to): (Msg, SocketAddr),
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        self.encode_to(item, Some(to), dst)
+    }
+}
+
+impl Encoder<Msg> for MsgCodec {
+    type Error = CodecError;
+    fn encode(&mut self, item: Msg, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_to(item, None, dst)
+    }
+}
+
 impl Decoder for MsgCodec {
-    type Item = Msg;
-    type Error = anyhow::Error;
+    type Item = Decoded;
+    type Error = CodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode_frame(src) {
+            Ok(msg) => Ok(msg.map(Ok)),
+            Err(err) => Ok(Some(Err(self.reject(src, err)))),
+        }
+    }
+
+    /// 数据报总是完整到达，剩下不完整的报文只可能是长度字段与实际不符
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            None if !src.is_empty() => {
+                let err = CodecError::Truncated(src.len());
+                Ok(Some(Err(self.reject(src, err))))
+            }
+            decoded => Ok(decoded),
+        }
+    }
+}
+
+impl MsgCodec {
+    /// 同一数据报中剩下的内容也不再可信，整个丢弃
+    fn reject(&self, src: &mut BytesMut, err: CodecError) -> CodecError {
+        self.stats.record_decode_error();
+        src.clear();
+        err
+    }
+
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<Msg>, CodecError> {
         if src.len() < MsgCodec::HDR_LEN {
             // 消息头未接收完
             return Ok(None);
         }
         let msg_len = u16::from_be_bytes([src[0], src[1]]) as usize;
-        let protocol_version = src[2];
+        let compressed = src[2] & COMPRESSED_FLAG != 0;
+        let version = ProtocolVersion::from_wire(src[2] & !COMPRESSED_FLAG);
+        // 长度字段不可信，先检查范围再决定是否等待更多数据
+        if msg_len < Self::HDR_LEN {
+            return Err(CodecError::Malformed);
+        }
+        if msg_len > MAX_FRAME {
+            return Err(CodecError::Oversized(msg_len));
+        }
         if src.len() < msg_len {
             // 消息长度大于当前缓冲区，请求扩容，等消息完整再取出
             src.reserve(msg_len - src.len());
             return Ok(None);
         }
-        if protocol_version != PROTOCOL_VERSION {
-            // 协议版本不对，忽略此条消息
+        self.stats.record_received(msg_len);
+        // 次版本只做兼容扩展，旧的次版本照常解码，主版本不同才丢弃
+        if !version.is_compatible(ProtocolVersion::CURRENT) {
+            debug!("Drop message of incompatible protocol version {version}");
+            self.stats.record_version_dropped();
             src.advance(msg_len);
             return Ok(None);
         }
-        let (msg, _) = bincode::decode_from_slice::<Msg, _>(
-            &src.split_to(msg_len)[Self::HDR_LEN..], // 截断消息长度前的部分并去除消息头
-            bincode::config::standard(),
-        )?;
+        let frame = src.split_to(msg_len); // 截断消息长度前的部分
+        let body = &frame[Self::HDR_LEN..]; // 去除消息头
+        let body = if compressed {
+            Cow::Owned(decompress(body)?)
+        } else {
+            Cow::Borrowed(body)
+        };
+        let (msg, _) = bincode::decode_from_slice::<Msg, _>(&body, bincode_config())?;
         Ok(Some(msg))
     }
 }
@@ -67,7 +311,10 @@ impl Decoder for MsgCodec {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::link::Uid;
+    use crate::{
+        link::Uid,
+        session::{Negotiated, record_negotiated},
+    };
     use bytes::{BufMut, BytesMut};
 
     // 辅助函数：构造编码后的完整报文
@@ -84,7 +331,7 @@ mod tests {
 
     #[test]
     fn test_encoder_success() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let msg = Msg::Transfer {
             host: Uid::random(),
             payload: b"114514".to_vec(),
@@ -99,42 +346,56 @@ mod tests {
 
     #[test]
     fn test_decoder_complete_message() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let msg = Msg::Transfer {
             host: Uid::random(),
             payload: b"114514".to_vec(),
         };
         let mut bytes = build_encoded_message(&msg, PROTOCOL_VERSION);
 
-        let result = codec.decode(&mut bytes).unwrap().unwrap();
+        let result = codec.decode_frame(&mut bytes).unwrap().unwrap();
         assert_eq!(result, msg);
     }
 
     #[test]
     fn test_decoder_incomplete_header() {
-        let mut codec = MsgCodec;
-        let mut bytes = BytesMut::from([0x00, 0x00].as_slice()); // 仅2字节（不足3字节头）
+        let mut codec = MsgCodec::default();
+        let mut bytes = BytesMut::from([0x00, 0x00].as_slice()); // 仅2字节（不足消息头）
 
-        assert!(codec.decode(&mut bytes).unwrap().is_none());
+        assert!(codec.decode_frame(&mut bytes).unwrap().is_none());
     }
 
     #[test]
     fn test_decoder_invalid_protocol_version() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let msg = Msg::Transfer {
             host: Uid::random(),
             payload: b"114514".to_vec(),
         };
-        let mut bytes = build_encoded_message(&msg, PROTOCOL_VERSION + 1); // 错误协议版本
+        let major = ProtocolVersion::CURRENT.major + 1;
+        let mut bytes = build_encoded_message(&msg, ProtocolVersion::new(major, 0).to_wire()); // 不兼容的主版本
 
-        let result = codec.decode(&mut bytes).unwrap();
+        let result = codec.decode_frame(&mut bytes).unwrap();
         assert!(result.is_none());
         assert!(bytes.is_empty()); // 错误版本的消息应被跳过
     }
 
+    #[test]
+    fn test_decoder_older_minor_version() {
+        let mut codec = MsgCodec::default();
+        let msg = Msg::Transfer {
+            host: Uid::random(),
+            payload: b"114514".to_vec(),
+        };
+        let older = ProtocolVersion::new(ProtocolVersion::CURRENT.major, 0);
+        let mut bytes = build_encoded_message(&msg, older.to_wire());
+
+        assert_eq!(codec.decode_frame(&mut bytes).unwrap(), Some(msg));
+    }
+
     #[test]
     fn test_decoder_partial_body() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let msg = Msg::Transfer {
             host: Uid::random(),
             payload: b"114514".to_vec(),
@@ -143,29 +404,92 @@ mod tests {
 
         // 先发送头+1字节数据（不足消息体）
         let mut bytes = full_bytes.split_to(MsgCodec::HDR_LEN + 1);
-        assert!(codec.decode(&mut bytes).unwrap().is_none());
+        assert!(codec.decode_frame(&mut bytes).unwrap().is_none());
 
         // 补充剩余数据
         bytes.unsplit(full_bytes);
-        let result = codec.decode(&mut bytes).unwrap();
+        let result = codec.decode_frame(&mut bytes).unwrap();
         assert_eq!(result, Some(msg));
     }
 
     #[test]
     fn test_decoder_invalid_bincode_data() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let mut bytes = BytesMut::new();
-        bytes.put_u16(5 + MsgCodec::HDR_LEN as u16); // 总长度5+3=8
+        bytes.put_u16(7 + MsgCodec::HDR_LEN as u16);
         bytes.put_u8(PROTOCOL_VERSION);
         bytes.put_slice(b"INVALID"); // 无效的bincode数据（5字节）
 
-        let result = codec.decode(&mut bytes);
-        assert!(result.is_err()); // 应返回反序列化错误
+        let result = codec.decode(&mut bytes).unwrap();
+        assert!(matches!(result, Some(Err(CodecError::Decode(_))))); // 反序列化错误作为一项交出
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_decoder_rejects_lying_length() {
+        let mut codec = MsgCodec::default();
+        // 声称 65535 字节但只带了几个字节，不应等待也不应按声称的长度扩容
+        let mut bytes = BytesMut::new();
+        bytes.put_u16(u16::MAX);
+        bytes.put_u8(PROTOCOL_VERSION);
+        bytes.put_slice(b"tiny");
+        let capacity = bytes.capacity();
+        assert!(matches!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(Err(CodecError::Oversized(_)))
+        ));
+        assert!(bytes.is_empty());
+        assert_eq!(bytes.capacity(), capacity);
+
+        // 长度字段比消息头还短
+        let mut bytes = BytesMut::new();
+        bytes.put_u16(1);
+        bytes.put_u8(PROTOCOL_VERSION);
+        assert!(matches!(
+            codec.decode(&mut bytes).unwrap(),
+            Some(Err(CodecError::Malformed))
+        ));
+
+        // 数据报到头了报文还不完整
+        let msg = Msg::Transfer {
+            host: Uid::random(),
+            payload: b"114514".to_vec(),
+        };
+        let mut bytes = build_encoded_message(&msg, PROTOCOL_VERSION);
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            codec.decode_eof(&mut bytes).unwrap(),
+            Some(Err(CodecError::Truncated(_)))
+        ));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_decompression_bomb_rejected() {
+        let mut body = ((MAX_DECODED + 1) as u32).to_le_bytes().to_vec();
+        body.extend_from_slice(b"falcon");
+        assert!(matches!(
+            decompress(&body),
+            Err(CodecError::DecompressedTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_encoder_rejects_oversized() {
+        let mut codec = MsgCodec::with_capabilities(Capabilities::empty());
+        let msg = Msg::Transfer {
+            host: Uid::random(),
+            payload: vec![0; MAX_FRAME],
+        };
+        assert!(matches!(
+            codec.encode(msg, &mut BytesMut::new()),
+            Err(CodecError::Oversized(_))
+        ));
     }
 
     #[test]
     fn test_multiple_messages_in_stream() {
-        let mut codec = MsgCodec;
+        let mut codec = MsgCodec::default();
         let msg1 = Msg::Transfer {
             host: Uid::random(),
             payload: b"114514".to_vec(),
@@ -180,13 +504,96 @@ mod tests {
         bytes.unsplit(build_encoded_message(&msg2, PROTOCOL_VERSION));
 
         // 解析第一个消息
-        let result1 = codec.decode(&mut bytes).unwrap();
+        let result1 = codec.decode_frame(&mut bytes).unwrap();
         assert_eq!(result1, Some(msg1));
 
         // 解析第二个消息
-        let result2 = codec.decode(&mut bytes).unwrap();
+        let result2 = codec.decode_frame(&mut bytes).unwrap();
         assert_eq!(result2, Some(msg2));
 
         assert!(bytes.is_empty()); // 缓冲区应无剩余数据
     }
+
+    #[tokio::test]
+    async fn test_control_message_compressed() {
+        let mut codec = MsgCodec::default();
+        let msg = Msg::Offer {
+            host: Uid::random(),
+            hash: 114514,
+            file_name: "falcon".repeat(100),
+            size: 1919810,
+            mtime: Some(1_700_000_000_000),
+            permissions: Some(0o644),
+        };
+        let raw = build_encoded_message(&msg, PROTOCOL_VERSION);
+        // 文档地址不是全局单播，无法转换成 EndPoint，这里要用真实的全局地址
+        let (peer, local, remote): (_, EndPoint, EndPoint) = (
+            Uid::random(),
+            "[2400:cb00::1]:5555".parse().unwrap(),
+            "[2400:cb00::7]:5555".parse().unwrap(),
+        );
+        // 不知道目标，或目标没有协商压缩时发原文
+        let mut bytes = BytesMut::new();
+        codec.encode(msg.clone(), &mut bytes).unwrap();
+        assert_eq!(bytes, raw);
+        link_state_table().update(peer.clone(), &local, &remote);
+        let mut bytes = BytesMut::new();
+        codec
+            .encode((msg.clone(), SocketAddr::from(remote)), &mut bytes)
+            .unwrap();
+        assert_eq!(bytes, raw);
+
+        let negotiated = Negotiated {
+            version: ProtocolVersion::CURRENT,
+            capabilities: Capabilities::default(),
+        };
+        record_negotiated(&peer, negotiated);
+        let mut bytes = BytesMut::new();
+        codec
+            .encode((msg.clone(), SocketAddr::from(remote)), &mut bytes)
+            .unwrap();
+        assert_ne!(bytes[2] & COMPRESSED_FLAG, 0);
+        assert!(bytes.len() < raw.len());
+
+        let result = codec.decode_frame(&mut bytes).unwrap();
+        assert_eq!(result, Some(msg));
+    }
+
+    #[test]
+    fn test_unknown_destination_uncompressed() {
+        let mut codec = MsgCodec::default();
+        let msg = Msg::Offer {
+            host: Uid::random(),
+            hash: 114514,
+            file_name: "falcon".repeat(100),
+            size: 1919810,
+            mtime: Some(1_700_000_000_000),
+            permissions: Some(0o644),
+        };
+        let raw = build_encoded_message(&msg, PROTOCOL_VERSION);
+        // 无法转换成 EndPoint 的地址查不到对端，按未协商处理
+        for to in ["[2001:db8::7]:5555", "127.0.0.1:5555"] {
+            let mut bytes = BytesMut::new();
+            codec
+                .encode((msg.clone(), to.parse().unwrap()), &mut bytes)
+                .unwrap();
+            assert_eq!(bytes, raw);
+        }
+    }
+
+    #[test]
+    fn test_compression_disabled() {
+        let mut codec = MsgCodec::with_capabilities(Capabilities::empty());
+        let msg = Msg::Offer {
+            host: Uid::random(),
+            hash: 114514,
+            file_name: "falcon".repeat(100),
+            size: 1919810,
+            mtime: Some(1_700_000_000_000),
+            permissions: Some(0o644),
+        };
+        let mut bytes = BytesMut::new();
+        codec.encode(msg.clone(), &mut bytes).unwrap();
+        assert_eq!(bytes, build_encoded_message(&msg, PROTOCOL_VERSION));
+    }
 }
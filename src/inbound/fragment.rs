@@ -0,0 +1,424 @@
+use super::{
+    CodecError, Decoded, Frame, HostId, IP_UDP_OVERHEAD, MAX_DECODED, MAX_FRAME, Msg, MsgCodec,
+    bincode_config,
+};
+use crate::link::IPV6_MIN_MTU;
+use bincode::enc::{EncoderImpl, write::SizeWriter};
+use futures::{Sink, StreamExt, future, stream::BoxStream};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// 任何 IPv6 链路都不会再被 IP 分片的帧长
+pub const SAFE_FRAME: usize = IPV6_MIN_MTU - IP_UDP_OVERHEAD;
+/// `Fragment` 在分片数据之外的编码开销上限
+const FRAGMENT_OVERHEAD: usize = 40;
+/// 每个分片携带的原消息字节数
+const CHUNK_LEN: usize = SAFE_FRAME - MsgCodec::HDR_LEN - FRAGMENT_OVERHEAD;
+/// 超过这么久还没收齐的消息整个丢弃，由上层重试
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// 所有未收齐消息占用的内存上限
+const MAX_BUFFERED: usize = 8 * MAX_DECODED;
+/// 单个来源地址同时重组的消息数上限，防止一个对端换着端口占满缓冲
+const MAX_PARTIAL_PER_SOURCE: usize = 16;
+/// 同时重组的消息总数上限
+const MAX_PARTIALS: usize = 256;
+/// 一条消息最多拆成的分片数
+const MAX_CHUNKS: usize = MAX_DECODED.div_ceil(CHUNK_LEN);
+
+/// 编码后的长度，不实际分配缓冲
+fn encoded_len(msg: &Msg) -> Result<usize, CodecError> {
+    let mut encoder = EncoderImpl::new(SizeWriter::default(), bincode::config::standard());
+    bincode::Encode::encode(msg, &mut encoder)?;
+    Ok(encoder.into_writer().bytes_written)
+}
+
+/// 超过单个数据报的消息拆成若干 `Fragment`，否则原样返回
+///
+/// 控制消息按最小 MTU 拆分，到哪条链路都不会被 IP 分片；
+/// 数据报文已由发送方按链路 MTU 切块，只有超过帧长上限时才拆
+pub fn fragment(msg: Msg, id: u64) -> Result<Vec<Msg>, CodecError> {
+    let limit = match msg.is_control() {
+        true => SAFE_FRAME,
+        false => MAX_FRAME,
+    };
+    let len = encoded_len(&msg)?;
+    if len + MsgCodec::HDR_LEN <= limit {
+        return Ok(vec![msg]);
+    }
+    if len > MAX_DECODED {
+        return Err(CodecError::Unfragmentable(len));
+    }
+    let host = msg.host().clone();
+    let encoded = bincode::encode_to_vec(&msg, bincode::config::standard())?;
+    let total = encoded.len().div_ceil(CHUNK_LEN) as u16;
+    Ok(encoded
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(index, chunk)| Msg::Fragment {
+            host: host.clone(),
+            id,
+            index: index as u16,
+            total,
+            chunk: chunk.to_vec(),
+        })
+        .collect())
+}
+
+/// 发送前拆分过大的消息，分片依次交给内层 sink
+pub struct FragmentSink<S> {
+    inner: S,
+    pending: VecDeque<(Msg, SocketAddr)>,
+    next_id: u64,
+}
+
+impl<S> FragmentSink<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pending: VecDeque::new(),
+            next_id: rand::random(),
+        }
+    }
+}
+
+impl<S> FragmentSink<S>
+where
+    S: Sink<(Msg, SocketAddr), Error = CodecError> + Unpin,
+{
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), CodecError>> {
+        while !self.pending.is_empty() {
+            ready!(Pin::new(&mut self.inner).poll_ready(cx))?;
+            let item = self.pending.pop_front().expect("checked non-empty");
+            Pin::new(&mut self.inner).start_send(item)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> Sink<(Msg, SocketAddr)> for FragmentSink<S>
+where
+    S: Sink<(Msg, SocketAddr), Error = CodecError> + Unpin,
+{
+    type Error = CodecError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, (msg, dst): (Msg, SocketAddr)) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let id = this.next_id;
+        this.next_id = this.next_id.wrapping_add(1);
+        let mut fragments = fragment(msg, id)?.into_iter();
+        let first = fragments.next().expect("at least one fragment");
+        Pin::new(&mut this.inner).start_send((first, dst))?;
+        this.pending
+            .extend(fragments.map(|fragment| (fragment, dst)));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+type PartialKey = (SocketAddr, HostId, u64);
+
+/// 每条未收齐的消息在分片数据之外的固定开销
+const PARTIAL_COST: usize =
+    size_of::<PartialKey>() + size_of::<Partial>() + size_of::<(Instant, PartialKey)>();
+
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// 计入缓冲的字节数，包括固定开销与分片槽位
+    cost: usize,
+}
+
+/// 按来源、发送方与消息 id 收集分片，收齐后解出原消息
+///
+/// 超时未收齐或缓冲超限时丢弃最早开始的消息，丢失的分片不重传，由上层重发整条消息
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<PartialKey, Partial>,
+    /// 按截止时间排列，超时都相同，所以也就是开始的先后
+    deadlines: VecDeque<(Instant, PartialKey)>,
+    buffered: usize,
+}
+
+impl Reassembler {
+    /// 不是分片的消息原样交出，分片收齐前返回 None
+    pub fn accept(&mut self, from: SocketAddr, msg: Msg) -> Option<Decoded> {
+        let Msg::Fragment {
+            host,
+            id,
+            index,
+            total,
+            chunk,
+        } = msg
+        else {
+            return Some(Ok(msg));
+        };
+        let (index, total) = (index as usize, total as usize);
+        if index >= total || total > MAX_CHUNKS {
+            return Some(Err(CodecError::Malformed));
+        }
+        // 除最后一片外都是整片，短分片不能用来以少量数据占住大量槽位
+        let full = chunk.len() == CHUNK_LEN;
+        let last = index + 1 == total && !chunk.is_empty() && chunk.len() <= CHUNK_LEN;
+        if !full && !last {
+            return Some(Err(CodecError::Malformed));
+        }
+        self.expire();
+        let key = (from, host, id);
+        match self.partial.get(&key) {
+            Some(partial) if partial.chunks.len() != total => {
+                self.remove(&key);
+                return Some(Err(CodecError::Malformed));
+            }
+            // 重复的分片忽略
+            Some(partial) if partial.chunks[index].is_some() => return None,
+            Some(_) => self.make_room_except(&key, chunk.len()),
+            None => {
+                let cost = PARTIAL_COST + total * size_of::<Option<Vec<u8>>>();
+                self.make_room(from.ip(), cost + chunk.len());
+                self.partial.insert(
+                    key.clone(),
+                    Partial {
+                        chunks: vec![None; total],
+                        received: 0,
+                        cost,
+                    },
+                );
+                self.buffered += cost;
+                self.deadlines
+                    .push_back((Instant::now() + REASSEMBLY_TIMEOUT, key.clone()));
+            }
+        }
+        let partial = self.partial.get_mut(&key)?;
+        partial.received += 1;
+        partial.cost += chunk.len();
+        self.buffered += chunk.len();
+        partial.chunks[index] = Some(chunk);
+        if partial.received < total {
+            return None;
+        }
+        let partial = self.remove(&key)?;
+        let encoded = partial
+            .chunks
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect::<Vec<_>>();
+        Some(Self::decode(&encoded))
+    }
+
+    fn decode(encoded: &[u8]) -> Decoded {
+        let (msg, _) = bincode::decode_from_slice::<Msg, _>(encoded, bincode_config())?;
+        // 分片里不能再嵌套分片
+        match msg {
+            Msg::Fragment { .. } => Err(CodecError::Malformed),
+            msg => Ok(msg),
+        }
+    }
+
+    fn remove(&mut self, key: &PartialKey) -> Option<Partial> {
+        let partial = self.partial.remove(key)?;
+        self.deadlines.retain(|(_, queued)| queued != key);
+        self.buffered -= partial.cost;
+        Some(partial)
+    }
+
+    /// 队首总是最早到期的消息
+    fn expire(&mut self) {
+        let now = Instant::now();
+        while let Some((deadline, key)) = self.deadlines.front()
+            && *deadline <= now
+        {
+            let key = key.clone();
+            debug!("Drop incomplete message {} from {}", key.2, key.0);
+            self.remove(&key);
+        }
+    }
+
+    /// 新消息开始前按来源地址、消息总数与总内存腾出空间
+    fn make_room(&mut self, source: IpAddr, incoming: usize) {
+        let from_source = self
+            .partial
+            .keys()
+            .filter(|key| key.0.ip() == source)
+            .count();
+        if from_source >= MAX_PARTIAL_PER_SOURCE {
+            self.evict_oldest(|key| key.0.ip() == source);
+        }
+        if self.partial.len() >= MAX_PARTIALS {
+            self.evict_oldest(|_| true);
+        }
+        while self.buffered + incoming > MAX_BUFFERED && self.evict_oldest(|_| true) {}
+    }
+
+    fn make_room_except(&mut self, keep: &PartialKey, incoming: usize) {
+        while self.buffered + incoming > MAX_BUFFERED && self.evict_oldest(|key| key != keep) {}
+    }
+
+    fn evict_oldest(&mut self, filter: impl Fn(&PartialKey) -> bool) -> bool {
+        let Some(key) = self
+            .deadlines
+            .iter()
+            .map(|(_, key)| key)
+            .find(|key| filter(key))
+            .cloned()
+        else {
+            return false;
+        };
+        debug!("Evict incomplete message {} from {}", key.2, key.0);
+        self.remove(&key);
+        true
+    }
+}
+
+/// 在解码后的报文流上重组分片
+pub fn reassembled(stream: BoxStream<'static, Frame>) -> BoxStream<'static, Frame> {
+    stream
+        .scan(Reassembler::default(), |reassembler, item| {
+            let item = match item {
+                Ok((Ok(msg), from)) => reassembler
+                    .accept(from, msg)
+                    .map(|decoded| Ok((decoded, from))),
+                other => Some(other),
+            };
+            future::ready(Some(item))
+        })
+        .filter_map(future::ready)
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::FileHash;
+    use futures::{SinkExt, channel::mpsc};
+
+    fn large_offer(len: usize) -> Msg {
+        Msg::Offer {
+            host: HostId::random(),
+            hash: FileHash::default(),
+            file_name: "x".repeat(len),
+            size: 1,
+            mtime: None,
+            permissions: None,
+        }
+    }
+
+    #[test]
+    fn small_messages_pass_through() {
+        let msg = large_offer(10);
+        assert_eq!(fragment(msg.clone(), 0).unwrap(), vec![msg]);
+    }
+
+    #[tokio::test]
+    async fn out_of_order_reassembly() {
+        let msg = large_offer(10_000);
+        let mut fragments = fragment(msg.clone(), 7).unwrap();
+        assert!(fragments.len() > 1);
+        fragments.reverse();
+        let from = "[::1]:5555".parse().unwrap();
+        let mut reassembler = Reassembler::default();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments.iter().cloned() {
+            assert!(reassembler.accept(from, fragment).is_none());
+        }
+        // 重复的分片不影响结果
+        assert!(reassembler.accept(from, fragments[0].clone()).is_none());
+        assert_eq!(reassembler.accept(from, last).unwrap().unwrap(), msg);
+        assert_eq!(reassembler.buffered, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn incomplete_messages_expire() {
+        let from = "[::1]:5555".parse().unwrap();
+        let mut reassembler = Reassembler::default();
+        let fragments = fragment(large_offer(5_000), 1).unwrap();
+        assert!(reassembler.accept(from, fragments[0].clone()).is_none());
+        tokio::time::advance(REASSEMBLY_TIMEOUT).await;
+        // 超时后原先的分片已被丢弃，剩下的收齐也无法还原
+        for fragment in fragments[1..].iter().cloned() {
+            assert!(reassembler.accept(from, fragment).is_none());
+        }
+        assert_eq!(reassembler.partial.len(), 1);
+
+        // 同一来源同时重组的消息数受限
+        for id in 0..2 * MAX_PARTIAL_PER_SOURCE as u64 {
+            let first = fragment(large_offer(5_000), 100 + id).unwrap().remove(0);
+            reassembler.accept(from, first);
+        }
+        assert_eq!(reassembler.partial.len(), MAX_PARTIAL_PER_SOURCE);
+
+        // 换端口也算同一来源
+        for port in 0..MAX_PARTIAL_PER_SOURCE as u16 {
+            let from = SocketAddr::new(from.ip(), 6000 + port);
+            let first = fragment(large_offer(5_000), 200).unwrap().remove(0);
+            reassembler.accept(from, first);
+        }
+        assert_eq!(reassembler.partial.len(), MAX_PARTIAL_PER_SOURCE);
+        assert_eq!(reassembler.deadlines.len(), reassembler.partial.len());
+    }
+
+    #[test]
+    fn short_chunks_rejected() {
+        let from = "[::1]:5555".parse().unwrap();
+        let mut reassembler = Reassembler::default();
+        let mut fragments = fragment(large_offer(5_000), 1).unwrap();
+        let Msg::Fragment { chunk, .. } = &mut fragments[0] else {
+            panic!("expected fragment");
+        };
+        chunk.truncate(1);
+        assert!(matches!(
+            reassembler.accept(from, fragments[0].clone()),
+            Some(Err(CodecError::Malformed))
+        ));
+        assert_eq!(reassembler.buffered, 0);
+
+        // 槽位按声明的分片数计入缓冲
+        let last = fragments.pop().unwrap();
+        assert!(reassembler.accept(from, last).is_none());
+        let total = fragments.len() + 1;
+        assert!(reassembler.buffered >= PARTIAL_COST + total * size_of::<Option<Vec<u8>>>());
+    }
+
+    #[tokio::test]
+    async fn sink_to_stream() {
+        let (tx, rx) = mpsc::unbounded::<(Msg, SocketAddr)>();
+        let mut sink = FragmentSink::new(tx.sink_map_err(|_| CodecError::Malformed));
+        let dst = "[::1]:5555".parse().unwrap();
+        let msgs = [large_offer(10), large_offer(20_000), large_offer(3_000)];
+        for msg in &msgs {
+            sink.feed((msg.clone(), dst)).await.unwrap();
+        }
+        sink.close().await.unwrap();
+
+        let frames: BoxStream<'static, Frame> = rx.map(|(msg, from)| Ok((Ok(msg), from))).boxed();
+        let received = reassembled(frames)
+            .map(|item| item.unwrap().0.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(received, msgs);
+    }
+}
@@ -0,0 +1,341 @@
+//! `Msg` 线上格式的快照测试
+//!
+//! 每个变体一组固定输入，编码结果与检入的字节逐一比对，检入的字节也必须仍能解码；
+//! 设置 `FALCON_BLESS=1` 运行时改为重写快照与 `PROTOCOL.md`
+
+use super::{Handshake, HostId, Msg, ProtocolVersion, Rendezvous};
+use std::{fmt::Write, fs, path::PathBuf};
+
+const BLESS: &str = "FALCON_BLESS";
+const GOLDEN: &str = "src/inbound/golden/msg.hex";
+const PROTOCOL: &str = "PROTOCOL.md";
+
+const PREAMBLE: &str = r#"# 线上协议
+
+> 本文件由 `src/inbound/golden.rs` 中的测试生成，请勿手动修改。
+> 有意修改线上格式时，以 `FALCON_BLESS=1 cargo test golden` 重新生成本文件与 `src/inbound/golden/msg.hex`，并在评审中说明兼容性影响。
+
+## 帧
+
+每个 UDP 数据报承载一帧：
+
+| 偏移 | 长度 | 内容 |
+| --- | --- | --- |
+| 0 | 2 | 帧总长（含帧头），大端 |
+| 2 | 1 | 最高位为压缩标记，其余位为协议版本 |
+| 3 | 不定 | 消息体 |
+
+消息体是 `Msg` 的 bincode 2 标准编码；带压缩标记时为 lz4 块压缩，并在前面附上 4 字节小端的原始长度。
+
+帧头不经认证，去重与防重放只对会话层报文做，由会话按 Noise nonce 的滑动窗口完成。
+
+## 消息体编码
+
+- 无符号整数使用变长编码：小于 251 占 1 字节；否则先写 251、252、253 分别表示随后是 2、4、8 字节的小端整数。
+- 枚举先写变体序号（变长 u32），再按声明顺序写各字段。
+- `Option` 先写 0 或 1；`String` 与 `Vec<u8>` 先写变长长度再写内容。
+- `HostId` 固定为 16 字节原文；IPv6 地址固定为 16 字节。
+
+兼容性约定：只能在 `Msg` 末尾追加新变体；已有变体的字段不得增删、重排或改变类型。
+"#;
+
+struct Sample {
+    name: &'static str,
+    fields: &'static str,
+    msg: Msg,
+}
+
+fn host(byte: u8) -> HostId {
+    HostId::from_bytes([byte; 16])
+}
+
+/// 新增变体时这里的 match 不再完整，编译失败提醒补上样例
+fn variant_name(msg: &Msg) -> &'static str {
+    match msg {
+        Msg::Discovery { .. } => "Discovery",
+        Msg::Auth { .. } => "Auth",
+        Msg::Offer { .. } => "Offer",
+        Msg::Decline { .. } => "Decline",
+        Msg::Fetch { .. } => "Fetch",
+        Msg::Rendezvous { .. } => "Rendezvous",
+        Msg::RelayRequest { .. } => "RelayRequest",
+        Msg::RelayReply { .. } => "RelayReply",
+        Msg::Relayed { .. } => "Relayed",
+        Msg::Ping { .. } => "Ping",
+        Msg::Pong { .. } => "Pong",
+        Msg::LinkAck { .. } => "LinkAck",
+        Msg::Transfer { .. } => "Transfer",
+        Msg::Sealed { .. } => "Sealed",
+        Msg::Rekey { .. } => "Rekey",
+        Msg::Keepalive { .. } => "Keepalive",
+        Msg::MtuProbe { .. } => "MtuProbe",
+        Msg::MtuAck { .. } => "MtuAck",
+        Msg::Fragment { .. } => "Fragment",
+    }
+}
+
+/// 按变体声明顺序排列，字段说明与声明保持一致
+fn samples() -> Vec<Sample> {
+    let sample = |name, fields, msg| Sample { name, fields, msg };
+    vec![
+        sample(
+            "Discovery",
+            "host: HostId, remote: EndPoint",
+            Msg::Discovery {
+                host: host(0x11),
+                remote: "[fe80::1%2]:5555".parse().unwrap(),
+            },
+        ),
+        sample(
+            "Auth",
+            "host: HostId, state: Handshake",
+            Msg::Auth {
+                host: host(0x11),
+                state: Handshake::Exchange(vec![1, 2, 3]),
+            },
+        ),
+        sample(
+            "Offer",
+            "host: HostId, hash: FileHash, file_name: String, size: u64, mtime: Option<u64>, permissions: Option<u32>",
+            Msg::Offer {
+                host: host(0x11),
+                hash: 0x0123_4567_89ab_cdef,
+                file_name: "report.pdf".into(),
+                size: 1 << 20,
+                mtime: Some(1_700_000_000_000),
+                permissions: Some(0o644),
+            },
+        ),
+        sample(
+            "Decline",
+            "host: HostId, hash: FileHash, reason: String",
+            Msg::Decline {
+                host: host(0x11),
+                hash: 7,
+                reason: "busy".into(),
+            },
+        ),
+        sample(
+            "Fetch",
+            "host: HostId, hash: FileHash, token: Option<String>",
+            Msg::Fetch {
+                host: host(0x11),
+                hash: 7,
+                token: Some("otp".into()),
+            },
+        ),
+        sample(
+            "Rendezvous",
+            "host: HostId, signal: Rendezvous",
+            Msg::Rendezvous {
+                host: host(0x11),
+                signal: Rendezvous::Introduce {
+                    peer: host(0x22),
+                    endpoint: "[2001:db8::1]:5555".parse().unwrap(),
+                },
+            },
+        ),
+        sample(
+            "RelayRequest",
+            "host: HostId, target: HostId",
+            Msg::RelayRequest {
+                host: host(0x11),
+                target: host(0x22),
+            },
+        ),
+        sample(
+            "RelayReply",
+            "host: HostId, target: HostId, accepted: bool",
+            Msg::RelayReply {
+                host: host(0x11),
+                target: host(0x22),
+                accepted: true,
+            },
+        ),
+        sample(
+            "Relayed",
+            "host: HostId, from: HostId, target: HostId, inner: Box<Msg>",
+            Msg::Relayed {
+                host: host(0x33),
+                from: host(0x22),
+                target: host(0x11),
+                inner: Box::new(Msg::Ping {
+                    host: host(0x22),
+                    nonce: 1,
+                }),
+            },
+        ),
+        sample(
+            "Ping",
+            "host: HostId, nonce: u64",
+            Msg::Ping {
+                host: host(0x11),
+                nonce: 42,
+            },
+        ),
+        sample(
+            "Pong",
+            "host: HostId, nonce: u64",
+            Msg::Pong {
+                host: host(0x11),
+                nonce: 42,
+            },
+        ),
+        sample(
+            "LinkAck",
+            "host: HostId, bytes: u32",
+            Msg::LinkAck {
+                host: host(0x11),
+                bytes: 1200,
+            },
+        ),
+        sample(
+            "Transfer",
+            "host: HostId, payload: Vec<u8>",
+            Msg::Transfer {
+                host: host(0x11),
+                payload: vec![0xde, 0xad, 0xbe, 0xef],
+            },
+        ),
+        sample(
+            "Sealed",
+            "host: HostId, control: bool, epoch: u32, nonce: u64, body: Vec<u8>",
+            Msg::Sealed {
+                host: host(0x11),
+                control: false,
+                epoch: 3,
+                nonce: 9,
+                body: vec![0xaa; 4],
+            },
+        ),
+        sample(
+            "Rekey",
+            "host: HostId, epoch: u32, ack: bool",
+            Msg::Rekey {
+                host: host(0x11),
+                epoch: 2,
+                ack: true,
+            },
+        ),
+        sample(
+            "Keepalive",
+            "host: HostId",
+            Msg::Keepalive { host: host(0x11) },
+        ),
+        sample(
+            "MtuProbe",
+            "host: HostId, nonce: u64, padding: Vec<u8>",
+            Msg::MtuProbe {
+                host: host(0x11),
+                nonce: 42,
+                padding: vec![0x5a; 4],
+            },
+        ),
+        sample(
+            "MtuAck",
+            "host: HostId, nonce: u64",
+            Msg::MtuAck {
+                host: host(0x11),
+                nonce: 42,
+            },
+        ),
+        sample(
+            "Fragment",
+            "host: HostId, id: u64, index: u16, total: u16, chunk: Vec<u8>",
+            Msg::Fragment {
+                host: host(0x11),
+                id: 7,
+                index: 1,
+                total: 3,
+                chunk: vec![0xab; 4],
+            },
+        ),
+    ]
+}
+
+fn encode(msg: &Msg) -> Vec<u8> {
+    bincode::encode_to_vec(msg, bincode::config::standard()).unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn render_golden(samples: &[Sample]) -> String {
+    samples
+        .iter()
+        .map(|sample| format!("{} {}\n", sample.name, hex(&encode(&sample.msg))))
+        .collect()
+}
+
+fn render_protocol(samples: &[Sample]) -> String {
+    let mut doc = PREAMBLE.to_string();
+    writeln!(doc, "\n当前协议版本为 {}。", ProtocolVersion::CURRENT).unwrap();
+    writeln!(doc, "\n## 变体").unwrap();
+    for sample in samples {
+        let bytes = encode(&sample.msg);
+        let lines = bytes
+            .chunks(16)
+            .map(|line| {
+                line.iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        // 变体序号都小于 251，恰好是编码的第一个字节
+        writeln!(doc, "\n### {} `{}`\n", bytes[0], sample.name).unwrap();
+        writeln!(doc, "字段：`{}`\n", sample.fields).unwrap();
+        writeln!(doc, "```text\n{lines}\n```").unwrap();
+    }
+    doc
+}
+
+fn check(relative: &str, actual: String) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative);
+    if std::env::var_os(BLESS).is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        expected == actual,
+        "{relative} is out of date, rerun with {BLESS}=1 if the wire format change is intended\n\
+         --- expected\n{expected}\n--- actual\n{actual}"
+    );
+}
+
+#[test]
+fn golden_bytes() {
+    let samples = samples();
+    for sample in &samples {
+        assert_eq!(variant_name(&sample.msg), sample.name);
+    }
+    check(GOLDEN, render_golden(&samples));
+
+    // 检入的字节仍能原样解码，旧版本发出的报文不会被误读
+    let golden = include_str!("golden/msg.hex");
+    assert_eq!(golden.lines().count(), samples.len());
+    for (line, sample) in golden.lines().zip(&samples) {
+        let (name, hex) = line.split_once(' ').unwrap();
+        assert_eq!(name, sample.name);
+        let bytes = unhex(hex);
+        let (msg, read) =
+            bincode::decode_from_slice::<Msg, _>(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(read, bytes.len());
+        assert_eq!(msg, sample.msg);
+    }
+}
+
+#[test]
+fn protocol_doc() {
+    check(PROTOCOL, render_protocol(&samples()));
+}
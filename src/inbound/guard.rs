@@ -0,0 +1,143 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// 统计窗口，窗口过后错误计数清零
+const ERROR_WINDOW: Duration = Duration::from_secs(10);
+/// 来源过多时清理一次过期记录，清理后仍然满时不再统计新来源，
+/// 伪造源地址的洪泛也不会让表无限增长
+const MAX_TRACKED: usize = 4096;
+
+#[derive(Debug)]
+struct Strikes {
+    count: u32,
+    since: Instant,
+    banned_until: Option<Instant>,
+}
+
+/// 按来源地址统计畸形报文，窗口内超出预算的来源在一段时间内被屏蔽
+///
+/// 同一主机换端口重发不会绕过预算，所以只按 IP 计
+#[derive(Debug)]
+pub struct ErrorBudget {
+    /// 0 表示不限制
+    limit: u32,
+    ban: Duration,
+    peers: HashMap<IpAddr, Strikes>,
+}
+
+impl ErrorBudget {
+    pub fn new(limit: u32, ban: Duration) -> Self {
+        Self {
+            limit,
+            ban,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// 记一次错误，刚好超出预算而被屏蔽时返回 true
+    pub fn charge(&mut self, src: SocketAddr) -> bool {
+        if self.limit == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        if !self.peers.contains_key(&src.ip()) && self.peers.len() >= MAX_TRACKED {
+            self.prune(now);
+            // 剩下的都在窗口或屏蔽期内，不能为了腾位置提前放出
+            if self.peers.len() >= MAX_TRACKED {
+                return false;
+            }
+        }
+        let strikes = self.peers.entry(src.ip()).or_insert(Strikes {
+            count: 0,
+            since: now,
+            banned_until: None,
+        });
+        if now.duration_since(strikes.since) >= ERROR_WINDOW {
+            strikes.count = 0;
+            strikes.since = now;
+        }
+        strikes.count += 1;
+        match strikes.count > self.limit && strikes.banned_until.is_none() {
+            true => {
+                strikes.banned_until = Some(now + self.ban);
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// 屏蔽到期后计数重新开始
+    pub fn is_banned(&mut self, src: &SocketAddr) -> bool {
+        let Some(strikes) = self.peers.get(&src.ip()) else {
+            return false;
+        };
+        match strikes.banned_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                self.peers.remove(&src.ip());
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        self.peers.retain(|_, strikes| match strikes.banned_until {
+            Some(until) => now < until,
+            None => now.duration_since(strikes.since) < ERROR_WINDOW,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn ban_after_budget() {
+        let mut budget = ErrorBudget::new(2, Duration::from_secs(60));
+        let src: SocketAddr = "[fe80::1]:5555".parse().unwrap();
+        let other_port: SocketAddr = "[fe80::1]:6666".parse().unwrap();
+        assert!(!budget.charge(src));
+        assert!(!budget.charge(other_port));
+        assert!(!budget.is_banned(&src));
+        assert!(budget.charge(src));
+        assert!(budget.is_banned(&other_port));
+        // 已被屏蔽时不再重复报告
+        assert!(!budget.charge(src));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(!budget.is_banned(&src));
+        assert!(!budget.charge(src));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tracking_is_capped() {
+        let mut budget = ErrorBudget::new(1, Duration::from_secs(60));
+        for n in 0..MAX_TRACKED as u32 {
+            let src = SocketAddr::from((std::net::Ipv4Addr::from(n), 5555));
+            budget.charge(src);
+            budget.charge(src);
+        }
+        assert_eq!(budget.peers.len(), MAX_TRACKED);
+        let late: SocketAddr = "[fe80::1]:5555".parse().unwrap();
+        assert!(!budget.charge(late));
+        assert!(!budget.charge(late));
+        assert_eq!(budget.peers.len(), MAX_TRACKED);
+        assert!(budget.is_banned(&SocketAddr::from(([0, 0, 0, 1], 5555))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn window_resets_count() {
+        let mut budget = ErrorBudget::new(1, Duration::from_secs(60));
+        let src: SocketAddr = "[fe80::1]:5555".parse().unwrap();
+        assert!(!budget.charge(src));
+        tokio::time::advance(ERROR_WINDOW).await;
+        assert!(!budget.charge(src));
+        assert!(!budget.is_banned(&src));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::{
+    addr::ScopedAddr,
+    config::{ConfigItem, ConfigManager},
+};
+use std::{
+    fmt, io,
+    net::Ipv6Addr,
+    str::FromStr,
+    sync::{OnceLock, RwLock},
+};
+use tracing::warn;
+
+/// 硬件地址，按常见的冒号分隔十六进制显示
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl MacAddr {
+    /// 回环与隧道接口没有硬件地址，全零视为没有
+    fn from_slice(bytes: &[u8]) -> Option<Self> {
+        let mac: [u8; 6] = bytes.try_into().ok()?;
+        (mac != [0; 6]).then_some(Self(mac))
+    }
+}
+
+/// 一块网卡的概况，供 CLI 展示与按名称筛选接口
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NicInfo {
+    /// 系统内部名称，Linux 上如 `eth0`，Windows 上为适配器 GUID
+    pub name: String,
+    /// 面向用户的名称，Linux 上与 name 相同
+    pub friendly_name: String,
+    /// 即链路本地地址的 scope id
+    pub index: u32,
+    pub mac: Option<MacAddr>,
+    pub link_local: Vec<Ipv6Addr>,
+    pub global: Vec<Ipv6Addr>,
+    pub mtu: Option<u32>,
+    pub up: bool,
+}
+
+impl NicInfo {
+    /// 该地址是否属于这块网卡
+    pub fn owns(&self, addr: &Ipv6Addr) -> bool {
+        self.link_local.contains(addr) || self.global.contains(addr)
+    }
+}
+
+/// 列出本机所有网卡，包括未启用的，按 index 排序
+pub fn list_interfaces() -> io::Result<Vec<NicInfo>> {
+    let mut nics = sys::list()?;
+    nics.sort_by_key(|nic| nic.index);
+    Ok(nics)
+}
+
+/// 逗号分隔的网卡名称模式，`*` 匹配任意个字符，`?` 匹配一个字符，不区分大小写
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IfacePatterns(Vec<String>);
+
+impl FromStr for IfacePatterns {
+    type Err = String;
+
+    /// 空串表示没有模式
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        s.split(',')
+            .map(str::trim)
+            .map(|pattern| match pattern.is_empty() {
+                true => Err(format!("empty interface pattern in {s:?}")),
+                false => Ok(pattern.to_owned()),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl IfacePatterns {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 系统名称与友好名称任一匹配即可
+    pub fn matches(&self, nic: &NicInfo) -> bool {
+        self.0
+            .iter()
+            .any(|pattern| glob(pattern, &nic.name) || glob(pattern, &nic.friendly_name))
+    }
+}
+
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置与它当时对应的文本位置，失配时回溯到这里多吞一个字符
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 参与绑定与发现的网卡，黑名单优先，白名单为空时不限制
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfacePolicy {
+    pub allow: IfacePatterns,
+    pub deny: IfacePatterns,
+}
+
+impl InterfacePolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            allow: cfg
+                .get_typed(ConfigItem::IfaceAllowlist)
+                .await
+                .unwrap_or(default.allow),
+            deny: cfg
+                .get_typed(ConfigItem::IfaceDenylist)
+                .await
+                .unwrap_or(default.deny),
+        }
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, nic: &NicInfo) -> bool {
+        !self.deny.matches(nic) && (self.allow.is_empty() || self.allow.matches(nic))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InterfaceGate {
+    policy: RwLock<InterfacePolicy>,
+}
+
+pub fn interface_gate() -> &'static InterfaceGate {
+    static INTERFACE_GATE: OnceLock<InterfaceGate> = OnceLock::new();
+    INTERFACE_GATE.get_or_init(InterfaceGate::default)
+}
+
+impl InterfaceGate {
+    /// 已绑定的接口在下一次比对网卡时按新策略增删
+    pub fn configure(&self, policy: InterfacePolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> InterfacePolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// 为一轮网卡枚举取一次快照，不受限时不必查询网卡
+    pub fn snapshot(&self) -> Option<(InterfacePolicy, Vec<NicInfo>)> {
+        let policy = self.policy();
+        if policy.is_unrestricted() {
+            return None;
+        }
+        match list_interfaces() {
+            Ok(nics) => Some((policy, nics)),
+            // 查不到名称时无从筛选，宁可多绑也不要一个都不绑
+            Err(err) => {
+                warn!("Failed to list interfaces, ignore interface policy: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// 按快照判断地址所在的网卡是否放行，找不到所属网卡时只在没有白名单时放行
+pub fn permits_addr(snapshot: &(InterfacePolicy, Vec<NicInfo>), addr: &ScopedAddr) -> bool {
+    let (policy, nics) = snapshot;
+    let nic = match addr {
+        ScopedAddr::Lan { addr, scope } => nics
+            .iter()
+            .find(|nic| nic.index == *scope && nic.owns(addr)),
+        ScopedAddr::Wan(addr) => nics.iter().find(|nic| nic.owns(addr)),
+    };
+    match nic {
+        Some(nic) => policy.permits(nic),
+        None => policy.allow.is_empty(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::{MacAddr, NicInfo};
+    use std::{collections::HashMap, fs, io, net::Ipv6Addr};
+
+    /// `/proc/net/if_inet6` 中链路本地地址的 scope 字段
+    const SCOPE_LINK: u8 = 0x20;
+    const SCOPE_GLOBAL: u8 = 0x00;
+    /// `/sys/class/net/*/flags` 中的 IFF_UP
+    const IFF_UP: u32 = 0x1;
+
+    pub(super) fn list() -> io::Result<Vec<NicInfo>> {
+        // 没有启用 IPv6 时该文件不存在，仍然列出网卡
+        let if_inet6 = fs::read_to_string("/proc/net/if_inet6").unwrap_or_default();
+        let mut addrs = HashMap::<String, Vec<(Ipv6Addr, u8)>>::new();
+        for (addr, scope, name) in if_inet6.lines().filter_map(parse_if_inet6) {
+            addrs.entry(name).or_default().push((addr, scope));
+        }
+        let mut nics = Vec::new();
+        for entry in fs::read_dir("/sys/class/net")? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let read = |attr: &str| {
+                fs::read_to_string(format!("/sys/class/net/{name}/{attr}"))
+                    .map(|s| s.trim().to_owned())
+                    .ok()
+            };
+            // 枚举期间消失的网卡直接跳过
+            let Some(index) = read("ifindex").and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            let flags = read("flags")
+                .and_then(|s| u32::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+                .unwrap_or_default();
+            let owned = addrs.remove(&name).unwrap_or_default();
+            let pick = |wanted| {
+                owned
+                    .iter()
+                    .filter(|(_, scope)| *scope == wanted)
+                    .map(|(addr, _)| *addr)
+                    .collect()
+            };
+            nics.push(NicInfo {
+                friendly_name: name.clone(),
+                index,
+                mac: read("address").and_then(|s| parse_mac(&s)),
+                link_local: pick(SCOPE_LINK),
+                global: pick(SCOPE_GLOBAL),
+                mtu: read("mtu").and_then(|s| s.parse().ok()),
+                up: flags & IFF_UP != 0,
+                name,
+            });
+        }
+        Ok(nics)
+    }
+
+    /// `fe800000000000000000000000000001 02 40 20 80 eth0`
+    pub(super) fn parse_if_inet6(line: &str) -> Option<(Ipv6Addr, u8, String)> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let addr = u128::from_str_radix(fields.first()?, 16).ok()?;
+        let scope = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+        Some((Ipv6Addr::from(addr), scope, fields.get(5)?.to_string()))
+    }
+
+    pub(super) fn parse_mac(s: &str) -> Option<MacAddr> {
+        let bytes = s
+            .split(':')
+            .map(|octet| u8::from_str_radix(octet, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        MacAddr::from_slice(&bytes)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod sys {
+    use super::{MacAddr, NicInfo};
+    use std::{io, net::IpAddr};
+
+    pub(super) fn list() -> io::Result<Vec<NicInfo>> {
+        let adapters = ipconfig::get_adapters().map_err(io::Error::other)?;
+        Ok(adapters
+            .iter()
+            .map(|adapter| {
+                let v6 = adapter.ip_addresses().iter().filter_map(|addr| match addr {
+                    IpAddr::V6(addr) => Some(*addr),
+                    IpAddr::V4(_) => None,
+                });
+                let (link_local, global) = v6.partition(|addr| addr.is_unicast_link_local());
+                NicInfo {
+                    name: adapter.adapter_name().to_owned(),
+                    friendly_name: adapter.friendly_name().to_owned(),
+                    index: adapter.ipv6_if_index(),
+                    mac: adapter.physical_address().and_then(MacAddr::from_slice),
+                    link_local,
+                    global,
+                    // ipconfig 不提供 mtu
+                    mtu: None,
+                    up: adapter.oper_status() == ipconfig::OperStatus::IfOperStatusUp,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod sys {
+    use super::NicInfo;
+    use std::io;
+
+    pub(super) fn list() -> io::Result<Vec<NicInfo>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nic(name: &str, friendly_name: &str) -> NicInfo {
+        NicInfo {
+            name: name.to_owned(),
+            friendly_name: friendly_name.to_owned(),
+            index: 1,
+            mac: None,
+            link_local: Vec::new(),
+            global: Vec::new(),
+            mtu: None,
+            up: true,
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob("eth*", "eth0"));
+        assert!(glob("*", ""));
+        assert!(glob("wl?0", "WLP0"));
+        assert!(glob("*vmnet*", "VMware Network Adapter VMnet8"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("eth?", "eth10"));
+        assert!(!glob("docker*", "br-docker0"));
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let parse = |raw: &str| raw.parse::<IfacePatterns>().unwrap();
+        assert!("eth0,,wlan0".parse::<IfacePatterns>().is_err());
+        assert!(parse("  ").is_empty());
+
+        let policy = InterfacePolicy {
+            allow: parse("eth*, Wi-Fi"),
+            deny: parse("eth9"),
+        };
+        assert!(policy.permits(&nic("eth0", "eth0")));
+        assert!(policy.permits(&nic("{6B29FC40-CA47-1067-B31D-00DD010662DA}", "Wi-Fi")));
+        assert!(!policy.permits(&nic("eth9", "eth9")));
+        assert!(!policy.permits(&nic("tun0", "tun0")));
+
+        let deny_only = InterfacePolicy {
+            deny: parse("tun*,utun*"),
+            ..Default::default()
+        };
+        assert!(deny_only.permits(&nic("eth0", "eth0")));
+        assert!(!deny_only.permits(&nic("utun3", "utun3")));
+        assert!(InterfacePolicy::default().is_unrestricted());
+    }
+
+    #[test]
+    fn format_mac() {
+        let mac = MacAddr([0x02, 0x42, 0xac, 0x11, 0x00, 0x02]);
+        assert_eq!(mac.to_string(), "02:42:ac:11:00:02");
+        assert_eq!(MacAddr::from_slice(&[0; 6]), None);
+        assert_eq!(MacAddr::from_slice(&[1, 2, 3]), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_entries() {
+        let line = "fe800000000000000000000000000001 02 40 20 80 eth0";
+        let (addr, scope, name) = sys::parse_if_inet6(line).unwrap();
+        assert!(addr.is_unicast_link_local());
+        assert_eq!((scope, name.as_str()), (0x20, "eth0"));
+        assert_eq!(
+            sys::parse_mac("02:42:ac:11:00:02"),
+            Some(MacAddr([0x02, 0x42, 0xac, 0x11, 0x00, 0x02]))
+        );
+        assert_eq!(sys::parse_mac("00:00:00:00:00:00"), None);
+
+        // 回环接口总是存在
+        let nics = list_interfaces().unwrap();
+        let lo = nics.iter().find(|nic| nic.name == "lo").unwrap();
+        assert!(lo.up);
+        assert_eq!(lo.mac, None);
+    }
+}
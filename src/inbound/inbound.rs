@@ -1,26 +1,99 @@
-use super::{Msg, MsgStream};
+use super::{ErrorBudget, InboundPolicy, Msg, MsgStream, Overflow, QueueDepth, QueueMetrics};
 use futures::{StreamExt, stream::SelectAll};
-use std::net::SocketAddr;
-use tokio::{sync::mpsc, task::AbortHandle};
-use tracing::{error, info};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::AbortHandle,
+};
+use tracing::{debug, error, info, warn};
+
+pub type Parcel = (Msg, SocketAddr);
 
 pub struct Inbound {
     abort: AbortHandle,
+    metrics: Arc<QueueMetrics<Parcel>>,
 }
 
 impl Inbound {
+    /// `attach` 接收网卡变化后新绑定的 socket
     pub async fn receiving(
         mut stream: SelectAll<MsgStream>,
-    ) -> (Self, mpsc::UnboundedReceiver<(Msg, SocketAddr)>) {
-        let (tx, rx) = mpsc::unbounded_channel(); //需要足够大的buffer
-        let abort = tokio::spawn(async move {
-            while let Ok(parcel) = stream.select_next_some().await {
-                tx.send(parcel).unwrap(); // 不要阻塞
+        mut attach: mpsc::UnboundedReceiver<MsgStream>,
+        policy: InboundPolicy,
+    ) -> (Self, mpsc::Receiver<Parcel>) {
+        let (tx, rx) = mpsc::channel(policy.capacity.max(1));
+        let metrics = Arc::new(QueueMetrics::new(&tx));
+        let abort = tokio::spawn({
+            let metrics = metrics.clone();
+            async move {
+                let mut budget = ErrorBudget::new(policy.error_budget, policy.ban);
+                loop {
+                    // 所有接口都消失时只等待新的 socket
+                    let received = tokio::select! {
+                        Some(added) = attach.recv() => {
+                            stream.push(added);
+                            continue;
+                        }
+                        Some(received) = stream.next(), if !stream.is_empty() => received,
+                        else => break,
+                    };
+                    // 只有 socket 本身出错才结束，畸形报文带着来源地址交出
+                    let Ok((decoded, src)) = received else { break };
+                    if budget.is_banned(&src) {
+                        continue;
+                    }
+                    let msg = match decoded {
+                        Ok(msg) => msg,
+                        Err(err) => {
+                            debug!("Drop malformed datagram from {src}: {err}");
+                            if budget.charge(src) {
+                                warn!(
+                                    "Ignore {src} for {:?} after repeated malformed datagrams",
+                                    policy.ban
+                                );
+                            }
+                            continue;
+                        }
+                    };
+                    let parcel = (msg, src);
+                    let parcel = match tx.try_send(parcel) {
+                        Ok(()) => {
+                            metrics.record_sent();
+                            continue;
+                        }
+                        Err(TrySendError::Closed(_)) => break,
+                        Err(TrySendError::Full(parcel)) => parcel,
+                    };
+                    match policy.overflow {
+                        Overflow::Drop => {
+                            let dropped = metrics.record_dropped();
+                            // 避免刷屏，只在数量级变化时告警
+                            if dropped.is_power_of_two() {
+                                warn!("Inbound queue full, {dropped} messages dropped so far");
+                            } else {
+                                debug!("Inbound queue full, drop message from {}", parcel.1);
+                            }
+                        }
+                        Overflow::Park => {
+                            // 不再读 socket，直到事件循环腾出空位
+                            metrics.record_parked();
+                            if tx.send(parcel).await.is_err() {
+                                break;
+                            }
+                            metrics.record_sent();
+                        }
+                    }
+                }
+                error!("error occuered while forwarding msg from msgstreammux to mpsc");
             }
-            error!("error occuered while forwarding msg from msgstreammux to mpsc");
         })
         .abort_handle();
-        (Self { abort }, rx)
+        (Self { abort, metrics }, rx)
+    }
+
+    /// 入站队列的深度与溢出统计
+    pub fn queue_depth(&self) -> QueueDepth {
+        self.metrics.snapshot()
     }
 }
 
@@ -1,11 +1,39 @@
+mod backpressure;
+#[cfg(all(target_os = "linux", feature = "gso"))]
+mod batch;
+mod capability;
+#[cfg(feature = "chaos")]
+mod chaos;
 mod codec;
+mod fragment;
+#[cfg(test)]
+mod golden;
+mod guard;
+mod iface;
 mod inbound;
 mod msg;
+mod multicast;
 mod nic;
+mod offload;
+mod qos;
 mod socket;
+mod stats;
 
+pub use backpressure::*;
+#[cfg(all(target_os = "linux", feature = "gso"))]
+pub use batch::*;
+pub use capability::*;
+#[cfg(feature = "chaos")]
+pub use chaos::*;
 pub use codec::*;
+pub use fragment::*;
+pub use guard::*;
+pub use iface::*;
 pub use inbound::*;
 pub use msg::*;
+pub use multicast::*;
 pub use nic::*;
+pub use offload::*;
+pub use qos::*;
 pub use socket::*;
+pub use stats::*;
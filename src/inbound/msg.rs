@@ -23,20 +23,175 @@ pub enum Msg {
         host: HostId,
         state: Handshake,
     },
-    Task {
-        owner: HostId,
+    /// 传输邀约，携带接收端预分配与完成后还原所需的元数据
+    ///
+    /// 接收端以 `Fetch` 接受，以 `Decline` 拒绝
+    Offer {
+        host: HostId,
         hash: FileHash,
         file_name: String,
-        total: u64,
+        size: u64,
+        /// unix 毫秒
+        mtime: Option<u64>,
+        permissions: Option<u32>,
+    },
+    /// 拒绝邀约
+    Decline {
+        host: HostId,
+        hash: FileHash,
+        reason: String,
+    },
+    /// 请求下载对方分享的文件，可附带带外获得的一次性令牌
+    Fetch {
+        host: HostId,
+        hash: FileHash,
+        token: Option<String>,
+    },
+    /// 穿越 NAT 的打洞协调，在链路层处理
+    Rendezvous {
+        host: HostId,
+        signal: Rendezvous,
+    },
+    /// 请求第三方为本机与 target 转发报文，直连全部失效时使用
+    RelayRequest {
+        host: HostId,
+        target: HostId,
+    },
+    /// 中继对转发请求的答复
+    RelayReply {
+        host: HostId,
+        target: HostId,
+        accepted: bool,
+    },
+    /// 经中继转发的报文，host 为本跳发送方，from 为原始发送方
+    ///
+    /// 内层报文仍由两端的会话加密，中继无法读取
+    Relayed {
+        host: HostId,
+        from: HostId,
+        target: HostId,
+        inner: Box<Msg>,
+    },
+    /// 链路保活探测，沿被探测的链路原路回复 `Pong`，在链路层处理
+    Ping {
+        host: HostId,
+        nonce: u64,
+    },
+    Pong {
+        host: HostId,
+        nonce: u64,
+    },
+    /// 链路级的字节确认，只用于推进发送方在这条链路上的拥塞窗口
+    LinkAck {
+        host: HostId,
+        bytes: u32,
     },
     /// 里面都是加密的taskevent
     Transfer {
         host: HostId,
         payload: Vec<u8>,
     },
+    /// 握手完成后的会话层报文（邀约、拉取、数据）整体加密后放在这里，在会话层解开
+    Sealed {
+        host: HostId,
+        /// 内层是否为控制消息，链路层据此决定是否确认，不必解密
+        control: bool,
+        epoch: u32,
+        nonce: u64,
+        body: Vec<u8>,
+    },
+    /// 通告即将切换到的发送纪元，对端以 `ack` 为真的同一消息确认，只以密文传输
+    Rekey {
+        host: HostId,
+        epoch: u32,
+        ack: bool,
+    },
+    /// 会话保活，刷新对端的空闲计时，只以密文传输
+    Keepalive {
+        host: HostId,
+    },
+    /// 路径 MTU 探测，用随机填充凑到待测长度，对端回复 `MtuAck`，在链路层处理
+    MtuProbe {
+        host: HostId,
+        nonce: u64,
+        padding: Vec<u8>,
+    },
+    MtuAck {
+        host: HostId,
+        nonce: u64,
+    },
+    /// 超过单个数据报的消息编码后拆成的一段，接收端在解码后重组，见 `FragmentSink`
+    Fragment {
+        host: HostId,
+        id: u64,
+        index: u16,
+        total: u16,
+        chunk: Vec<u8>,
+    },
 }
 
 impl Msg {
+    /// 除数据报文外都属于控制类消息
+    pub fn is_control(&self) -> bool {
+        match self {
+            Msg::Transfer { .. } => false,
+            Msg::Sealed { control, .. } => *control,
+            Msg::Relayed { inner, .. } => inner.is_control(),
+            _ => true,
+        }
+    }
+
+    /// 报文的发送方
+    pub fn host(&self) -> &HostId {
+        match self {
+            Msg::Discovery { host, .. }
+            | Msg::Auth { host, .. }
+            | Msg::Offer { host, .. }
+            | Msg::Decline { host, .. }
+            | Msg::Fetch { host, .. }
+            | Msg::Rendezvous { host, .. }
+            | Msg::RelayRequest { host, .. }
+            | Msg::RelayReply { host, .. }
+            | Msg::Relayed { host, .. }
+            | Msg::Ping { host, .. }
+            | Msg::Pong { host, .. }
+            | Msg::LinkAck { host, .. }
+            | Msg::Transfer { host, .. }
+            | Msg::Sealed { host, .. }
+            | Msg::Rekey { host, .. }
+            | Msg::Keepalive { host }
+            | Msg::MtuProbe { host, .. }
+            | Msg::MtuAck { host, .. }
+            | Msg::Fragment { host, .. } => host,
+        }
+    }
+
+    /// 数据报文在线路上的载荷长度，用于拥塞窗口记账，控制消息为 None
+    pub fn data_len(&self) -> Option<usize> {
+        match self {
+            Msg::Transfer { payload, .. } => Some(payload.len()),
+            Msg::Sealed {
+                control: false,
+                body,
+                ..
+            } => Some(body.len()),
+            _ => None,
+        }
+    }
+
+    /// 会话层报文，握手完成后必须加密传输
+    pub fn is_session(&self) -> bool {
+        matches!(
+            self,
+            Msg::Offer { .. }
+                | Msg::Decline { .. }
+                | Msg::Fetch { .. }
+                | Msg::Transfer { .. }
+                | Msg::Rekey { .. }
+                | Msg::Keepalive { .. }
+        )
+    }
+
     pub fn auth(state: Handshake, local: HostId) -> Self {
         Msg::Auth { host: local, state }
     }
@@ -52,3 +207,18 @@ pub enum Handshake {
     // -> s,se
     Full(Vec<u8>),
 }
+
+/// 打洞信令，中继可以是任意一台具有公网地址且配置为中继的对端
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub enum Rendezvous {
+    /// -> relay 登记自身，中继从源地址得知反射地址
+    Register,
+    /// <- relay 告知发送方在中继看来的反射地址
+    Observed(EndPoint),
+    /// -> relay 请求与目标主机互相介绍
+    Connect(HostId),
+    /// <- relay 对端的反射地址，双方收到后同时向对方打洞
+    Introduce { peer: HostId, endpoint: EndPoint },
+    /// 直接发往对端反射地址的探测，收到即说明对方的 NAT 映射已打开
+    Punch,
+}
@@ -0,0 +1,162 @@
+use crate::{
+    addr::{EndPoint, ScopeId, StdIpv6Addr},
+    config::{ConfigItem, ConfigManager},
+};
+use dashmap::DashMap;
+use socket2::SockRef;
+use std::{
+    net::SocketAddrV6,
+    sync::{OnceLock, RwLock},
+};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// 默认的发现组，链路本地范围的永久组播地址，不依赖链路层广播
+pub const DISCOVERY_GROUP: StdIpv6Addr = StdIpv6Addr::new(0xFF02, 0, 0, 0, 0, 0, 0, 0xFA1C);
+
+/// 发现使用的组播组与跳数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastPolicy {
+    pub group: StdIpv6Addr,
+    /// 发出的组播报文的跳数限制，链路本地发现为 1
+    pub hops: u32,
+}
+
+impl Default for MulticastPolicy {
+    fn default() -> Self {
+        Self {
+            group: DISCOVERY_GROUP,
+            hops: 1,
+        }
+    }
+}
+
+impl MulticastPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            group: cfg
+                .get_typed(ConfigItem::DiscoveryGroup)
+                .await
+                .unwrap_or(default.group),
+            hops: cfg
+                .get_typed::<u8>(ConfigItem::DiscoveryHops)
+                .await
+                .map(u32::from)
+                .unwrap_or(default.hops),
+        }
+    }
+}
+
+/// 某个接口上组播组的加入状态，用于诊断
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinState {
+    /// 已加入的组，离开时以它为准，即使之后配置换了组
+    Joined(StdIpv6Addr),
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct MulticastMembership {
+    policy: RwLock<MulticastPolicy>,
+    states: DashMap<ScopeId, JoinState>,
+}
+
+pub fn multicast_membership() -> &'static MulticastMembership {
+    static MULTICAST_MEMBERSHIP: OnceLock<MulticastMembership> = OnceLock::new();
+    MULTICAST_MEMBERSHIP.get_or_init(MulticastMembership::default)
+}
+
+/// 计算某个本地端点对应接口上的发现目标地址
+///
+/// 只有链路本地地址才需要组播，全局地址返回 None
+pub fn discovery_destination(local: &EndPoint) -> Option<SocketAddrV6> {
+    let group = multicast_membership().policy().group;
+    local
+        .get_scope_id()
+        .map(|&scope| SocketAddrV6::new(group, local.port(), 0, scope))
+}
+
+impl MulticastMembership {
+    /// 之后绑定的 socket 按新策略加入，已加入的接口保持不变
+    pub fn configure(&self, policy: MulticastPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> MulticastPolicy {
+        *self.policy.read().unwrap()
+    }
+
+    /// 在 socket 所在接口上加入发现组，并让发出的组播报文固定走该接口
+    ///
+    /// 失败会记录下来而不是静默忽略
+    pub fn join(&self, sock: &UdpSocket, local: &EndPoint) -> std::io::Result<()> {
+        let Some(&scope) = local.get_scope_id() else {
+            return Ok(());
+        };
+        let MulticastPolicy { group, hops } = self.policy();
+        let sock = SockRef::from(sock);
+        let result = sock
+            .join_multicast_v6(&group, scope)
+            .and_then(|_| sock.set_multicast_if_v6(scope))
+            .and_then(|_| sock.set_multicast_hops_v6(hops))
+            .and_then(|_| sock.set_multicast_loop_v6(false));
+        match &result {
+            Ok(_) => {
+                info!("Joined {group} on interface {scope} with {hops} hops");
+                self.states.insert(scope, JoinState::Joined(group));
+            }
+            Err(err) => {
+                warn!("Failed to join {group} on interface {scope}: {err}");
+                self.states.insert(scope, JoinState::Failed(err.to_string()));
+            }
+        }
+        result
+    }
+
+    /// 接口消失时调用，socket 已经关闭的情况下只清理状态
+    pub fn leave(&self, sock: Option<&UdpSocket>, scope: ScopeId) {
+        let joined = self
+            .states
+            .remove(&scope)
+            .and_then(|(_, state)| match state {
+                JoinState::Joined(group) => Some(group),
+                JoinState::Failed(_) => None,
+            });
+        if let Some(sock) = sock
+            && let Some(group) = joined
+            && let Err(err) = sock.leave_multicast_v6(&group, scope)
+        {
+            warn!("Failed to leave {group} on interface {scope}: {err}");
+        }
+    }
+
+    pub fn state(&self, scope: ScopeId) -> Option<JoinState> {
+        self.states.get(&scope).map(|s| s.clone())
+    }
+
+    pub fn snapshot(&self) -> Vec<(ScopeId, JoinState)> {
+        self.states
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::{mock_endpoint_lan, mock_endpoint_wan};
+
+    #[test]
+    fn destination_per_scope() {
+        let lan = mock_endpoint_lan();
+        let dst = discovery_destination(&lan).unwrap();
+        assert_eq!(*dst.ip(), multicast_membership().policy().group);
+        assert!(DISCOVERY_GROUP.is_multicast());
+        assert_eq!(Some(&dst.scope_id()), lan.get_scope_id());
+        assert_eq!(dst.port(), lan.port());
+
+        assert!(discovery_destination(&mock_endpoint_wan()).is_none());
+    }
+}
@@ -1,9 +1,30 @@
-use crate::addr::ScopedAddr;
+use super::{
+    HostId, InterfacePolicy, MsgStream, NicInfo, SinkCommand, StreamHandles, bind, interface_gate,
+    multicast_membership, permits_addr,
+};
+use crate::{
+    addr::{EndPoint, ScopedAddr},
+    config::ConfigManager,
+    link::{link_state_table, local_for, peer_table},
+};
 use netif::{Interface, Up};
-use std::net::IpAddr;
+use std::{collections::HashSet, io, net::IpAddr, time::Duration};
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{MissedTickBehavior, interval, sleep},
+};
+use tracing::{info, warn};
+#[cfg(target_os = "linux")]
+use {
+    std::os::fd::{AsRawFd, OwnedFd},
+    tokio::io::unix::AsyncFd,
+};
 
+/// 活跃网卡上可用于绑定的地址，按网卡策略筛选
 pub struct NicView {
     iter: Option<Up>,
+    gate: Option<(InterfacePolicy, Vec<NicInfo>)>,
 }
 
 impl Iterator for NicView {
@@ -22,7 +43,12 @@ impl Iterator for NicView {
                 IpAddr::V6(addr) if addr.is_unicast_global() => Some(ScopedAddr::Wan(addr)),
                 _ => None,
             };
-            if let Some(item) = item {
+            if let Some(item) = item
+                && self
+                    .gate
+                    .as_ref()
+                    .is_none_or(|gate| permits_addr(gate, &item))
+            {
                 return Some(item);
             }
         }
@@ -33,6 +59,374 @@ impl Default for NicView {
     fn default() -> Self {
         Self {
             iter: netif::up().ok(),
+            gate: interface_gate().snapshot(),
         }
     }
 }
+
+/// 收到变化通知后等待这么久再比对，合并同一次插拔引起的多条通知
+const SETTLE: Duration = Duration::from_millis(500);
+/// 通知可能丢失或平台不支持，兜底定期比对
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 监听网卡增减，重新绑定 socket、清理链路并重新发现
+///
+/// Linux 上订阅 netlink 的链路与 IPv6 地址组，Windows 上注册
+/// `NotifyIpInterfaceChange` 与 `NotifyUnicastIpAddressChange`，其余平台只靠轮询。
+/// 网卡黑白名单变化时立即比对，被排除的接口按消失处理
+pub struct NicWatcher {
+    abort: AbortHandle,
+}
+
+impl NicWatcher {
+    pub fn run(
+        cfg: &'static ConfigManager,
+        local: HostId,
+        mut handles: StreamHandles,
+        sinks: mpsc::UnboundedSender<SinkCommand>,
+        streams: mpsc::UnboundedSender<MsgStream>,
+    ) -> Self {
+        let mut config_changed = cfg.subscribe();
+        let abort = tokio::spawn(async move {
+            let mut source = ChangeSource::open()
+                .inspect_err(|err| warn!("Interface change notification unavailable: {err}"))
+                .ok();
+            let mut poll = interval(POLL_INTERVAL);
+            poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = poll.tick() => {}
+                    Ok(_) = config_changed.changed() => {
+                        let policy = InterfacePolicy::from_config(cfg).await;
+                        if policy == interface_gate().policy() {
+                            continue;
+                        }
+                        info!("Interface policy changed to {policy:?}");
+                        interface_gate().configure(policy);
+                    }
+                    result = Self::changed(&mut source) => {
+                        if let Err(err) = result {
+                            warn!("Interface change notification failed, poll instead: {err}");
+                            source = None;
+                            continue;
+                        }
+                        sleep(SETTLE).await;
+                        if let Some(source) = source.as_mut() {
+                            source.drain();
+                        }
+                    }
+                }
+                if !Self::reconcile(&local, &mut handles, &sinks, &streams).await {
+                    break;
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    async fn changed(source: &mut Option<ChangeSource>) -> io::Result<()> {
+        match source {
+            Some(source) => source.changed().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// 比对当前接口与已绑定的 socket，通道关闭时返回 false
+    async fn reconcile(
+        local: &HostId,
+        handles: &mut StreamHandles,
+        sinks: &mpsc::UnboundedSender<SinkCommand>,
+        streams: &mpsc::UnboundedSender<MsgStream>,
+    ) -> bool {
+        let current = NicView::default().collect::<HashSet<_>>();
+        let gone = handles
+            .keys()
+            .filter(|addr| !current.contains(addr.scoped_addr()))
+            .copied()
+            .collect::<Vec<_>>();
+        for addr in &gone {
+            if let Some(handle) = handles.remove(addr) {
+                handle.abort();
+            }
+            if sinks.send(SinkCommand::Detach(*addr)).is_err() {
+                return false;
+            }
+            if let Some(&scope) = addr.get_scope_id() {
+                multicast_membership().leave(None, scope);
+            }
+            let dropped = link_state_table().drop_local(addr);
+            info!("Interface {addr} disappeared, {dropped} links dropped");
+        }
+        let mut added = Vec::new();
+        for iface in current {
+            if handles.keys().any(|addr| *addr.scoped_addr() == iface) {
+                continue;
+            }
+            // 绑定失败的接口下一轮再试
+            let (addr, sink, stream, handle) = match bind(iface).await {
+                Ok(bound) => bound,
+                Err(err) => {
+                    warn!("Failed to bind on new interface {iface}: {err}");
+                    continue;
+                }
+            };
+            if sinks.send(SinkCommand::Attach(addr, sink)).is_err() || streams.send(stream).is_err()
+            {
+                return false;
+            }
+            handles.insert(addr, handle);
+            added.push(addr);
+            info!("Interface {addr} appeared");
+        }
+        // 让对端尽快得知接口变化，不必等下一次周期广播
+        if (!added.is_empty() || !gone.is_empty())
+            && sinks.send(SinkCommand::Announce(local.clone())).is_err()
+        {
+            return false;
+        }
+        // 组播只覆盖链路本地，广域网上的已知对端需要逐个单播
+        for addr in added {
+            let targets = Self::known_endpoints(&addr);
+            if targets.is_empty() {
+                continue;
+            }
+            let rediscover = SinkCommand::Rediscover {
+                local: addr,
+                host: local.clone(),
+                targets,
+            };
+            if sinks.send(rediscover).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 已知对端通告过、经由本地端点 addr 可达的端点
+    fn known_endpoints(addr: &EndPoint) -> Vec<EndPoint> {
+        peer_table()
+            .list_peers()
+            .into_iter()
+            .flat_map(|peer| peer.endpoints)
+            .filter(|remote| local_for(remote, std::iter::once(addr)).is_some())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl Drop for NicWatcher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Nic watcher has been dropped");
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct ChangeSource {
+    fd: AsyncFd<OwnedFd>,
+}
+
+#[cfg(target_os = "linux")]
+impl ChangeSource {
+    fn open() -> io::Result<Self> {
+        use std::os::fd::FromRawFd;
+        // SAFETY: 参数均为常量，返回值检查后才交给 OwnedFd
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd 刚刚创建且有效，所有权交给 OwnedFd
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // SAFETY: sockaddr_nl 全零是合法值
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = (libc::RTMGRP_LINK | libc::RTMGRP_IPV6_IFADDR) as u32;
+        // SAFETY: addr 在调用期间有效，长度与类型一致
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// 只把通知当作触发信号，不解析内容
+    async fn changed(&mut self) -> io::Result<()> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match guard.try_io(|fd| recv(fd.get_ref())) {
+                Ok(result) => return result.map(|_| ()),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn drain(&mut self) {
+        while recv(self.fd.get_ref()).is_ok() {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn recv(fd: &OwnedFd) -> io::Result<usize> {
+    let mut buf = [0u8; 8192];
+    // SAFETY: buf 在调用期间有效，长度与缓冲区一致
+    let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+    match n {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        n => Ok(n as usize),
+    }
+}
+
+#[cfg(windows)]
+struct ChangeSource {
+    rx: mpsc::UnboundedReceiver<()>,
+    _notify: windows::ChangeNotify,
+}
+
+#[cfg(windows)]
+impl ChangeSource {
+    fn open() -> io::Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            rx,
+            _notify: windows::ChangeNotify::register(tx)?,
+        })
+    }
+
+    async fn changed(&mut self) -> io::Result<()> {
+        self.rx
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("notification channel closed"))
+    }
+
+    fn drain(&mut self) {
+        while self.rx.try_recv().is_ok() {}
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::c_void, io, ptr};
+    use tokio::sync::mpsc;
+    use windows_sys::Win32::{
+        Foundation::{HANDLE, NO_ERROR},
+        NetworkManagement::IpHelper::{
+            CancelMibChangeNotify2, MIB_IPINTERFACE_ROW, MIB_NOTIFICATION_TYPE,
+            MIB_UNICASTIPADDRESS_ROW, NotifyIpInterfaceChange, NotifyUnicastIpAddressChange,
+        },
+        Networking::WinSock::AF_INET6,
+    };
+
+    type Context = mpsc::UnboundedSender<()>;
+
+    /// 回调在系统线程上执行，只往通道里塞一个信号
+    pub struct ChangeNotify {
+        handles: [HANDLE; 2],
+        context: *mut Context,
+    }
+
+    // SAFETY: 句柄与上下文只在注册与注销时使用，注销后才释放上下文
+    unsafe impl Send for ChangeNotify {}
+    unsafe impl Sync for ChangeNotify {}
+
+    unsafe extern "system" fn on_interface(
+        context: *const c_void,
+        _row: *const MIB_IPINTERFACE_ROW,
+        _kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        // SAFETY: context 由 register 创建，注销前一直有效
+        let _ = unsafe { &*(context as *const Context) }.send(());
+    }
+
+    unsafe extern "system" fn on_address(
+        context: *const c_void,
+        _row: *const MIB_UNICASTIPADDRESS_ROW,
+        _kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        // SAFETY: 同上
+        let _ = unsafe { &*(context as *const Context) }.send(());
+    }
+
+    impl ChangeNotify {
+        pub fn register(tx: Context) -> io::Result<Self> {
+            let mut notify = Self {
+                handles: [ptr::null_mut(); 2],
+                context: Box::into_raw(Box::new(tx)),
+            };
+            let context = notify.context as *const c_void;
+            // SAFETY: 回调签名与 API 一致，context 在注销前有效
+            let err = unsafe {
+                NotifyIpInterfaceChange(
+                    AF_INET6,
+                    Some(on_interface),
+                    context,
+                    0,
+                    &mut notify.handles[0],
+                )
+            };
+            if err != NO_ERROR {
+                return Err(io::Error::from_raw_os_error(err as i32));
+            }
+            // SAFETY: 同上
+            let err = unsafe {
+                NotifyUnicastIpAddressChange(
+                    AF_INET6,
+                    Some(on_address),
+                    context,
+                    0,
+                    &mut notify.handles[1],
+                )
+            };
+            if err != NO_ERROR {
+                return Err(io::Error::from_raw_os_error(err as i32));
+            }
+            Ok(notify)
+        }
+    }
+
+    impl Drop for ChangeNotify {
+        fn drop(&mut self) {
+            for handle in self.handles {
+                if !handle.is_null() {
+                    // SAFETY: 句柄由注册得到，注销会等待进行中的回调返回
+                    unsafe { CancelMibChangeNotify2(handle) };
+                }
+            }
+            // SAFETY: 所有回调均已注销，不会再访问上下文
+            drop(unsafe { Box::from_raw(self.context) });
+        }
+    }
+}
+
+/// 其余平台没有通知机制，只靠轮询
+#[cfg(not(any(target_os = "linux", windows)))]
+struct ChangeSource;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl ChangeSource {
+    fn open() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    async fn changed(&mut self) -> io::Result<()> {
+        std::future::pending().await
+    }
+
+    fn drain(&mut self) {}
+}
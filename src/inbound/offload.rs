@@ -0,0 +1,644 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+};
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    hot_file::ChunkedBuf,
+};
+use socket2::{SockAddr, SockRef};
+use tokio::{io::Interest, net::UdpSocket};
+use tracing::{debug, info, warn};
+
+/// 单次 GSO 发送最多携带的报文数（内核 UDP_MAX_SEGMENTS）
+pub const MAX_SEGMENTS: usize = 64;
+/// 单次 GSO 发送的总长度仍受 UDP 报文长度上限约束，超出时内核返回 EMSGSIZE
+pub const MAX_GSO_PAYLOAD: usize = 65507;
+/// GRO 接收缓冲需要容纳合并后的超大报文
+pub const GRO_BUF_LEN: usize = u16::MAX as usize;
+
+/// 批量收发的系统调用统计，用于比较每次系统调用处理的报文数
+#[derive(Debug, Default)]
+pub struct OffloadStats {
+    pub send_syscalls: AtomicU64,
+    pub sent_datagrams: AtomicU64,
+    pub recv_syscalls: AtomicU64,
+    pub recv_datagrams: AtomicU64,
+}
+
+pub fn offload_stats() -> &'static OffloadStats {
+    static OFFLOAD_STATS: OnceLock<OffloadStats> = OnceLock::new();
+    OFFLOAD_STATS.get_or_init(OffloadStats::default)
+}
+
+impl OffloadStats {
+    fn record_send(&self, datagrams: usize) {
+        self.send_syscalls.fetch_add(1, Ordering::Relaxed);
+        self.sent_datagrams
+            .fetch_add(datagrams as u64, Ordering::Relaxed);
+    }
+
+    fn record_recv(&self, datagrams: usize) {
+        self.recv_syscalls.fetch_add(1, Ordering::Relaxed);
+        self.recv_datagrams
+            .fetch_add(datagrams as u64, Ordering::Relaxed);
+    }
+
+    pub fn send_ratio(&self) -> f64 {
+        ratio(&self.sent_datagrams, &self.send_syscalls)
+    }
+
+    pub fn recv_ratio(&self) -> f64 {
+        ratio(&self.recv_datagrams, &self.recv_syscalls)
+    }
+}
+
+fn ratio(datagrams: &AtomicU64, syscalls: &AtomicU64) -> f64 {
+    match syscalls.load(Ordering::Relaxed) {
+        0 => 0.0,
+        calls => datagrams.load(Ordering::Relaxed) as f64 / calls as f64,
+    }
+}
+
+/// 之后探测的 socket 默认的批量大小
+static BATCH_SIZE: AtomicUsize = AtomicUsize::new(Offload::DEFAULT_BATCH);
+
+/// 单个 socket 的卸载能力，运行时探测，失败时自动退回批量系统调用
+#[derive(Debug)]
+pub struct Offload {
+    gso: AtomicBool,
+    gro: bool,
+    batch: usize,
+}
+
+impl Offload {
+    pub const DEFAULT_BATCH: usize = 32;
+
+    pub fn detect(sock: &UdpSocket) -> Self {
+        let (gso, gro) = sys::detect(sock);
+        info!("UDP offload on {:?}: gso={gso}, gro={gro}", sock.local_addr());
+        Self {
+            gso: AtomicBool::new(gso),
+            gro,
+            batch: BATCH_SIZE.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 只影响之后绑定的 socket
+    pub fn configure_batch_size(batch: usize) {
+        BATCH_SIZE.store(batch.clamp(1, MAX_SEGMENTS), Ordering::Relaxed);
+    }
+
+    /// 不做任何卸载
+    pub fn disabled() -> Self {
+        Self {
+            gso: AtomicBool::new(false),
+            gro: false,
+            batch: Self::DEFAULT_BATCH,
+        }
+    }
+
+    /// 每次 sendmmsg / recvmmsg 最多处理的报文数
+    pub fn with_batch_size(mut self, batch: usize) -> Self {
+        self.batch = batch.clamp(1, MAX_SEGMENTS);
+        self
+    }
+
+    pub async fn batch_size_from_config(cfg: &ConfigManager) -> usize {
+        cfg.get(ConfigItem::IoBatchSize)
+            .await
+            .parse()
+            .unwrap_or(Self::DEFAULT_BATCH)
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch
+    }
+
+    pub fn gso(&self) -> bool {
+        self.gso.load(Ordering::Relaxed)
+    }
+
+    pub fn gro(&self) -> bool {
+        self.gro
+    }
+
+    /// 一次 GSO 发送能携带的段数，同时受段数与总长度的限制
+    pub fn segments_per_send(segment: usize) -> usize {
+        (MAX_GSO_PAYLOAD / segment.max(1)).clamp(1, MAX_SEGMENTS)
+    }
+
+    /// 将 `buf` 按 `segment` 切分为多个报文发往 `dst`，返回报文数
+    ///
+    /// 除最后一个外每段长度必须等于 `segment`
+    pub async fn send_bulk(
+        &self,
+        sock: &UdpSocket,
+        buf: &[u8],
+        segment: usize,
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        if segment == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero segment"));
+        }
+        let datagrams = buf.chunks(segment).collect::<Vec<_>>();
+        self.send_segments(sock, &datagrams, segment, dst).await
+    }
+
+    /// 与 `send_bulk` 相同，但报文分散在各自的缓冲里，作为 iovec 交给内核，不必先拼接
+    ///
+    /// 除最后一个外每个报文长度必须等于 `segment`
+    pub async fn send_segments(
+        &self,
+        sock: &UdpSocket,
+        datagrams: &[&[u8]],
+        segment: usize,
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        if segment == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero segment"));
+        }
+        let mut sent = 0;
+        for batch in datagrams.chunks(Self::segments_per_send(segment)) {
+            sent += self.send_batch(sock, batch, segment, dst).await?;
+        }
+        Ok(sent)
+    }
+
+    async fn send_batch(
+        &self,
+        sock: &UdpSocket,
+        batch: &[&[u8]],
+        segment: usize,
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        let count = batch.len();
+        if count > 1 && self.gso() {
+            match sys::send_segmented(sock, batch, segment, dst).await {
+                Ok(_) => {
+                    offload_stats().record_send(count);
+                    return Ok(count);
+                }
+                // 网卡不支持校验和卸载时内核返回 EIO
+                Err(err) if sys::is_unsupported(&err) => {
+                    warn!("GSO rejected by kernel, fall back to per-datagram: {err}");
+                    self.gso.store(false, Ordering::Relaxed);
+                }
+                // 只是这一批太长，GSO 本身可用，这一批逐个报文发出
+                Err(err) if sys::is_oversized(&err) => {
+                    let len = batch.iter().map(|datagram| datagram.len()).sum::<usize>();
+                    debug!("GSO batch of {len} bytes too large: {err}");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        self.send_many(sock, batch, dst).await?;
+        Ok(count)
+    }
+
+    /// 不依赖 GSO 的批量发送，每次系统调用最多发出 `batch_size` 个报文
+    pub async fn send_many(
+        &self,
+        sock: &UdpSocket,
+        datagrams: &[&[u8]],
+        dst: SocketAddr,
+    ) -> io::Result<()> {
+        let mut rest = datagrams;
+        while !rest.is_empty() {
+            let take = rest.len().min(self.batch);
+            // 底层一个都没发出时按 WouldBlock 等待可写，返回的至少为 1
+            let sent = sys::send_many(sock, &rest[..take], dst).await?;
+            offload_stats().record_send(sent);
+            rest = &rest[sent.min(take)..];
+        }
+        Ok(())
+    }
+
+    /// 把多块缓冲作为一个报文发出，由内核聚合 iovec，省去用户态拼接
+    pub async fn send_vectored(
+        &self,
+        sock: &UdpSocket,
+        buf: &ChunkedBuf,
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        let addr = SockAddr::from(dst);
+        let slices = buf.io_slices();
+        let sent = sock
+            .async_io(Interest::WRITABLE, || {
+                SockRef::from(sock).send_to_vectored(&slices, &addr)
+            })
+            .await?;
+        offload_stats().record_send(1);
+        Ok(sent)
+    }
+
+    /// 批量接收，每个缓冲区装一个报文，至少返回一个
+    pub async fn recv_many(
+        &self,
+        sock: &UdpSocket,
+        bufs: &mut [Vec<u8>],
+    ) -> io::Result<Vec<(usize, SocketAddr)>> {
+        let take = bufs.len().min(self.batch);
+        let received = sys::recv_many(sock, &mut bufs[..take]).await?;
+        offload_stats().record_recv(received.len());
+        Ok(received)
+    }
+
+    /// 接收一次，返回总长度、分段长度与来源；未合并时分段长度等于总长度
+    pub async fn recv_bulk(
+        &self,
+        sock: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, usize, SocketAddr)> {
+        let (len, segment, from) = if self.gro {
+            sys::recv_coalesced(sock, buf).await?
+        } else {
+            let (len, from) = sock.recv_from(buf).await?;
+            (len, len, from)
+        };
+        let segment = if segment == 0 { len } else { segment };
+        offload_stats().record_recv(len.div_ceil(segment.max(1)).max(1));
+        Ok((len, segment, from))
+    }
+}
+
+/// 将合并后的缓冲区切回单个报文
+pub fn split_segments(buf: &[u8], segment: usize) -> impl Iterator<Item = &[u8]> {
+    buf.chunks(segment.max(1))
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use socket2::SockAddr;
+    use std::{
+        io,
+        mem::{size_of, zeroed},
+        net::SocketAddr,
+        os::fd::AsRawFd,
+        ptr,
+    };
+    use tokio::{io::Interest, net::UdpSocket};
+
+    pub(super) fn detect(sock: &UdpSocket) -> (bool, bool) {
+        if !cfg!(feature = "gso") {
+            return (false, false);
+        }
+        let fd = sock.as_raw_fd();
+        let mut val: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+        // 内核 >= 4.18 才认识 UDP_SEGMENT
+        let gso = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_UDP,
+                libc::UDP_SEGMENT,
+                &mut val as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        } == 0;
+        let on: libc::c_int = 1;
+        let gro = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_UDP,
+                libc::UDP_GRO,
+                &on as *const _ as *const libc::c_void,
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        } == 0;
+        (gso, gro)
+    }
+
+    pub(super) fn is_unsupported(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(libc::EIO | libc::EINVAL))
+    }
+
+    pub(super) fn is_oversized(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::EMSGSIZE)
+    }
+
+    const CMSG_SPACE: usize = 64;
+
+    #[repr(C, align(8))]
+    struct CmsgBuf([u8; CMSG_SPACE]);
+
+    /// 内核把各个 iovec 首尾相接后按段长切开
+    pub(super) async fn send_segmented(
+        sock: &UdpSocket,
+        datagrams: &[&[u8]],
+        segment: usize,
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        let addr = SockAddr::from(dst);
+        let segment = u16::try_from(segment)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "segment too large"))?;
+        sock.async_io(Interest::WRITABLE, || unsafe {
+            let mut iovs = datagrams
+                .iter()
+                .map(|d| libc::iovec {
+                    iov_base: d.as_ptr() as *mut libc::c_void,
+                    iov_len: d.len(),
+                })
+                .collect::<Vec<_>>();
+            let mut control = CmsgBuf([0; CMSG_SPACE]);
+            let mut msg: libc::msghdr = zeroed();
+            msg.msg_name = addr.as_ptr() as *mut libc::c_void;
+            msg.msg_namelen = addr.len();
+            msg.msg_iov = iovs.as_mut_ptr();
+            msg.msg_iovlen = iovs.len() as _;
+            msg.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = libc::CMSG_SPACE(size_of::<u16>() as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<u16>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment);
+            match libc::sendmsg(sock.as_raw_fd(), &msg, 0) {
+                -1 => Err(io::Error::last_os_error()),
+                n => Ok(n as usize),
+            }
+        })
+        .await
+    }
+
+    pub(super) async fn recv_coalesced(
+        sock: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, usize, SocketAddr)> {
+        sock.async_io(Interest::READABLE, || unsafe {
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            };
+            let mut control = CmsgBuf([0; CMSG_SPACE]);
+            let mut storage: libc::sockaddr_storage = zeroed();
+            let mut msg: libc::msghdr = zeroed();
+            msg.msg_name = &mut storage as *mut _ as *mut libc::c_void;
+            msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = CMSG_SPACE as _;
+            let len = match libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) {
+                -1 => return Err(io::Error::last_os_error()),
+                n => n as usize,
+            };
+            let mut segment = len;
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                    segment =
+                        ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int) as usize;
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            let from = SockAddr::new(storage, msg.msg_namelen)
+                .as_socket()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown family"))?;
+            Ok((len, segment, from))
+        })
+        .await
+    }
+
+    pub(super) async fn send_many(
+        sock: &UdpSocket,
+        datagrams: &[&[u8]],
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        let addr = SockAddr::from(dst);
+        sock.async_io(Interest::WRITABLE, || unsafe {
+            let mut iovs = datagrams
+                .iter()
+                .map(|d| libc::iovec {
+                    iov_base: d.as_ptr() as *mut libc::c_void,
+                    iov_len: d.len(),
+                })
+                .collect::<Vec<_>>();
+            let mut msgs = iovs
+                .iter_mut()
+                .map(|iov| {
+                    let mut hdr: libc::mmsghdr = zeroed();
+                    hdr.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+                    hdr.msg_hdr.msg_namelen = addr.len();
+                    hdr.msg_hdr.msg_iov = iov;
+                    hdr.msg_hdr.msg_iovlen = 1;
+                    hdr
+                })
+                .collect::<Vec<_>>();
+            match libc::sendmmsg(sock.as_raw_fd(), msgs.as_mut_ptr(), msgs.len() as _, 0) {
+                -1 => Err(io::Error::last_os_error()),
+                // 发送缓冲已满，等下一次可写再试，不能跳过未发出的报文
+                0 => Err(io::ErrorKind::WouldBlock.into()),
+                n => Ok(n as usize),
+            }
+        })
+        .await
+    }
+
+    pub(super) async fn recv_many(
+        sock: &UdpSocket,
+        bufs: &mut [Vec<u8>],
+    ) -> io::Result<Vec<(usize, SocketAddr)>> {
+        sock.async_io(Interest::READABLE, || unsafe {
+            let mut iovs = bufs
+                .iter_mut()
+                .map(|b| libc::iovec {
+                    iov_base: b.as_mut_ptr() as *mut libc::c_void,
+                    iov_len: b.len(),
+                })
+                .collect::<Vec<_>>();
+            let mut storages = vec![zeroed::<libc::sockaddr_storage>(); iovs.len()];
+            let mut msgs = iovs
+                .iter_mut()
+                .zip(storages.iter_mut())
+                .map(|(iov, storage)| {
+                    let mut hdr: libc::mmsghdr = zeroed();
+                    hdr.msg_hdr.msg_name = storage as *mut _ as *mut libc::c_void;
+                    hdr.msg_hdr.msg_namelen =
+                        size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                    hdr.msg_hdr.msg_iov = iov;
+                    hdr.msg_hdr.msg_iovlen = 1;
+                    hdr
+                })
+                .collect::<Vec<_>>();
+            let n = match libc::recvmmsg(
+                sock.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                msgs.len() as _,
+                libc::MSG_DONTWAIT,
+                ptr::null_mut(),
+            ) {
+                -1 => return Err(io::Error::last_os_error()),
+                n => n as usize,
+            };
+            msgs[..n]
+                .iter()
+                .zip(storages)
+                .map(|(msg, storage)| {
+                    let from = SockAddr::new(storage, msg.msg_hdr.msg_namelen)
+                        .as_socket()
+                        .ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidData, "unknown family")
+                        })?;
+                    Ok((msg.msg_len as usize, from))
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+/// 其余平台没有 mmsg，用非阻塞的连续收发近似批处理
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::{io, net::SocketAddr};
+    use tokio::net::UdpSocket;
+
+    pub(super) async fn send_many(
+        sock: &UdpSocket,
+        datagrams: &[&[u8]],
+        dst: SocketAddr,
+    ) -> io::Result<usize> {
+        sock.send_to(datagrams[0], dst).await?;
+        let mut sent = 1;
+        for datagram in &datagrams[1..] {
+            match sock.try_send_to(datagram, dst) {
+                Ok(_) => sent += 1,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(sent)
+    }
+
+    pub(super) async fn recv_many(
+        sock: &UdpSocket,
+        bufs: &mut [Vec<u8>],
+    ) -> io::Result<Vec<(usize, SocketAddr)>> {
+        let mut received = Vec::with_capacity(bufs.len());
+        let (first, rest) = bufs
+            .split_first_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no buffer"))?;
+        received.push(sock.recv_from(first).await?);
+        for buf in rest {
+            match sock.try_recv_from(buf) {
+                Ok(r) => received.push(r),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(received)
+    }
+
+    pub(super) fn detect(_: &UdpSocket) -> (bool, bool) {
+        (false, false)
+    }
+
+    pub(super) fn is_unsupported(_: &io::Error) -> bool {
+        true
+    }
+
+    pub(super) fn is_oversized(_: &io::Error) -> bool {
+        false
+    }
+
+    pub(super) async fn send_segmented(
+        _: &UdpSocket,
+        _: &[&[u8]],
+        _: usize,
+        _: SocketAddr,
+    ) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub(super) async fn recv_coalesced(
+        _: &UdpSocket,
+        _: &mut [u8],
+    ) -> io::Result<(usize, usize, SocketAddr)> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bulk_roundtrip() {
+        let tx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let rx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let tx_offload = Offload::detect(&tx);
+        let rx_offload = Offload::disabled();
+        let payload = (0..1000u16).map(|i| i as u8).collect::<Vec<_>>();
+        let sent = tx_offload
+            .send_bulk(&tx, &payload, 300, rx.local_addr().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(sent, 4);
+        let mut received = Vec::new();
+        let mut buf = vec![0u8; GRO_BUF_LEN];
+        while received.len() < payload.len() {
+            let (len, segment, _) = rx_offload.recv_bulk(&rx, &mut buf).await.unwrap();
+            for datagram in split_segments(&buf[..len], segment) {
+                assert!(datagram.len() <= 300);
+                received.extend_from_slice(datagram);
+            }
+        }
+        assert_eq!(received, payload);
+    }
+
+    #[test]
+    fn gso_send_fits_one_datagram() {
+        for segment in [1, 300, 1500, 9000, MAX_GSO_PAYLOAD, MAX_GSO_PAYLOAD + 1] {
+            let count = Offload::segments_per_send(segment);
+            assert!((1..=MAX_SEGMENTS).contains(&count));
+            assert!(count == 1 || count * segment <= MAX_GSO_PAYLOAD);
+        }
+        assert_eq!(Offload::segments_per_send(1500), 43);
+    }
+
+    #[tokio::test]
+    async fn vectored_roundtrip() {
+        let tx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let rx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let buf = ChunkedBuf::from(vec![
+            bytes::Bytes::from_static(b"falcon "),
+            bytes::Bytes::from_static(b"transfer"),
+        ]);
+        let sent = Offload::disabled()
+            .send_vectored(&tx, &buf, rx.local_addr().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(sent, buf.len());
+        let mut recv = vec![0u8; 64];
+        let (len, _) = rx.recv_from(&mut recv).await.unwrap();
+        assert_eq!(&recv[..len], b"falcon transfer");
+    }
+
+    #[tokio::test]
+    async fn many_roundtrip() {
+        let tx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let rx = UdpSocket::bind("[::1]:0").await.unwrap();
+        let offload = Offload::disabled().with_batch_size(4);
+        let datagrams = (0..10u8).map(|i| vec![i; 100]).collect::<Vec<_>>();
+        let refs = datagrams.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        offload
+            .send_many(&tx, &refs, rx.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut bufs = vec![vec![0u8; 1500]; 8];
+        let mut received = Vec::new();
+        while received.len() < datagrams.len() {
+            let batch = offload.recv_many(&rx, &mut bufs).await.unwrap();
+            assert!(batch.len() <= offload.batch_size());
+            for (i, (len, _)) in batch.into_iter().enumerate() {
+                received.push(bufs[i][..len].to_vec());
+            }
+        }
+        assert_eq!(received, datagrams);
+    }
+}
@@ -0,0 +1,298 @@
+use super::Msg;
+use crate::config::{ConfigItem, ConfigManager};
+use futures::Sink;
+use std::{
+    fmt,
+    net::SocketAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, OnceLock, RwLock},
+    task::{Context, Poll},
+};
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+/// 6 位差分服务代码点，写入 IPv6 Traffic Class 的高 6 位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    pub const DEFAULT: Dscp = Dscp(0);
+    /// 低于尽力而为的后台流量（RFC 8622）
+    pub const LE: Dscp = Dscp(1);
+    /// 旧式的后台流量标记，支持面比 LE 广
+    pub const CS1: Dscp = Dscp(8);
+    pub const EF: Dscp = Dscp(46);
+
+    pub fn new(value: u8) -> Option<Self> {
+        (value < 64).then_some(Self(value))
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// 低 2 位是 ECN，保持为 0
+    pub fn traffic_class(&self) -> u32 {
+        (self.0 as u32) << 2
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    /// 接受 `cs0`..`cs7`、`af11`..`af43`、`ef`、`le`、`default` 或 0..=63 的数值
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let invalid =
+            || format!("unknown DSCP: {s}, expected a name like cs1 or a value in 0..=63");
+        let dscp = match s.as_str() {
+            "default" | "be" => Dscp::DEFAULT,
+            "le" => Dscp::LE,
+            "ef" => Dscp::EF,
+            name if name.starts_with("cs") => match name[2..].parse::<u8>() {
+                Ok(n @ 0..=7) => Dscp(n << 3),
+                _ => return Err(invalid()),
+            },
+            name if name.starts_with("af") && name.len() == 4 => {
+                let digits = name.as_bytes();
+                match (digits[2], digits[3]) {
+                    (class @ b'1'..=b'4', drop @ b'1'..=b'3') => {
+                        Dscp(((class - b'0') << 3) | ((drop - b'0') << 1))
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            value => value.parse().ok().and_then(Dscp::new).ok_or_else(invalid)?,
+        };
+        Ok(dscp)
+    }
+}
+
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// 批量数据与控制消息使用不同的标记
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QosPolicy {
+    pub bulk: Dscp,
+    pub control: Dscp,
+}
+
+impl Default for QosPolicy {
+    fn default() -> Self {
+        Self {
+            bulk: Dscp::CS1,
+            control: Dscp::DEFAULT,
+        }
+    }
+}
+
+impl QosPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            bulk: cfg
+                .get_typed(ConfigItem::BulkDscp)
+                .await
+                .unwrap_or(default.bulk),
+            control: cfg
+                .get_typed(ConfigItem::ControlDscp)
+                .await
+                .unwrap_or(default.control),
+        }
+    }
+
+    pub fn dscp_for(&self, msg: &Msg) -> Dscp {
+        match msg.is_control() {
+            true => self.control,
+            false => self.bulk,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Qos {
+    policy: RwLock<QosPolicy>,
+}
+
+pub fn qos() -> &'static Qos {
+    static QOS: OnceLock<Qos> = OnceLock::new();
+    QOS.get_or_init(Qos::default)
+}
+
+impl Qos {
+    /// 只影响之后绑定的 socket
+    pub fn configure(&self, policy: QosPolicy) {
+        info!(
+            "Mark bulk traffic with DSCP {} and control traffic with DSCP {}",
+            policy.bulk, policy.control
+        );
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> QosPolicy {
+        *self.policy.read().unwrap()
+    }
+}
+
+/// 设置之后从 sock 发出的报文的标记，失败只记录
+pub(super) fn set_dscp(sock: &UdpSocket, dscp: Dscp) {
+    if let Err(err) = sys::set_traffic_class(sock, dscp.traffic_class()) {
+        debug!("Failed to set DSCP {dscp}: {err}");
+    }
+}
+
+/// 按消息类型切换 socket 的 Traffic Class 后再交给内层发送
+///
+/// 内层在 `poll_ready` 中会先把上一条发出去，切换只影响随后的这一条；
+/// 连续同类的消息不会重复设置
+pub struct QosSink<S> {
+    inner: S,
+    sock: Arc<UdpSocket>,
+    policy: QosPolicy,
+    current: Option<Dscp>,
+}
+
+impl<S> QosSink<S> {
+    pub fn new(inner: S, sock: Arc<UdpSocket>, policy: QosPolicy) -> Self {
+        Self {
+            inner,
+            sock,
+            policy,
+            current: None,
+        }
+    }
+}
+
+impl<S> Sink<(Msg, SocketAddr)> for QosSink<S>
+where
+    S: Sink<(Msg, SocketAddr)> + Unpin,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: (Msg, SocketAddr)) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let dscp = this.policy.dscp_for(&item.0);
+        if this.current != Some(dscp) {
+            // 标记失败不影响发送，之后也不再重试同一个值
+            set_dscp(&this.sock, dscp);
+            this.current = Some(dscp);
+        }
+        Pin::new(&mut this.inner).start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{io, mem::size_of, os::fd::AsRawFd};
+    use tokio::net::UdpSocket;
+
+    pub(super) fn set_traffic_class(sock: &UdpSocket, class: u32) -> io::Result<()> {
+        let class = class as libc::c_int;
+        // SAFETY: 指针与长度都指向栈上的 c_int
+        let ret = unsafe {
+            libc::setsockopt(
+                sock.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_TCLASS,
+                &class as *const _ as *const libc::c_void,
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn traffic_class(sock: &UdpSocket) -> io::Result<u32> {
+        let mut class: libc::c_int = 0;
+        let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: 同上
+        let ret = unsafe {
+            libc::getsockopt(
+                sock.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_TCLASS,
+                &mut class as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        match ret {
+            0 => Ok(class as u32),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+/// 其他平台需要系统的 QoS 接口才能标记，这里只记录
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+    use tokio::net::UdpSocket;
+
+    pub(super) fn set_traffic_class(_sock: &UdpSocket, _class: u32) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inbound::HostId;
+    use std::io;
+
+    #[test]
+    fn parse_dscp() {
+        assert_eq!("cs1".parse::<Dscp>().unwrap(), Dscp::CS1);
+        assert_eq!("LE".parse::<Dscp>().unwrap(), Dscp::LE);
+        assert_eq!("af41".parse::<Dscp>().unwrap().value(), 34);
+        assert_eq!("46".parse::<Dscp>().unwrap(), Dscp::EF);
+        assert_eq!(Dscp::CS1.traffic_class(), 0x20);
+        for invalid in ["cs8", "af44", "64", "bulk"] {
+            assert!(invalid.parse::<Dscp>().is_err(), "{invalid}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn switch_per_message() -> io::Result<()> {
+        use futures::{SinkExt, sink::drain};
+
+        let sock = Arc::new(UdpSocket::bind("[::1]:0").await?);
+        let dst = sock.local_addr()?;
+        let mut sink = QosSink::new(drain(), sock.clone(), QosPolicy::default());
+        let host = HostId::random();
+        let bulk = Msg::Transfer {
+            host: host.clone(),
+            payload: vec![0; 8],
+        };
+        sink.send((bulk, dst)).await.unwrap();
+        assert_eq!(sys::traffic_class(&sock)?, Dscp::CS1.traffic_class());
+        let control = Msg::Decline {
+            host,
+            hash: 1,
+            reason: "busy".into(),
+        };
+        sink.send((control, dst)).await.unwrap();
+        assert_eq!(sys::traffic_class(&sock)?, Dscp::DEFAULT.traffic_class());
+        Ok(())
+    }
+}
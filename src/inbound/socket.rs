@@ -1,46 +1,111 @@
-use super::{Msg, MsgCodec, NicView};
-use crate::addr::{EndPoint, Port, StdIpv6Addr};
-use anyhow::Result;
+#[cfg(all(target_os = "linux", feature = "gso"))]
+use super::{BatchSink, Offload, batched_stream};
+use super::{
+    CodecError, Decoded, FragmentSink, HostId, Msg, MsgCodec, NicView, SocketStats,
+    multicast_membership, qos, reassembled, register_socket,
+};
+use crate::addr::{EndPoint, Port, ScopedAddr};
 use futures::{
-    StreamExt,
+    Sink, StreamExt,
     future::try_join_all,
-    stream::{SelectAll, SplitSink, SplitStream},
+    stream::{self, Abortable, BoxStream, SelectAll},
 };
-use std::{collections::HashMap, net::SocketAddr};
+use std::{collections::HashMap, io::Result, net::SocketAddr, pin::Pin, sync::Arc};
 use tokio::net::UdpSocket;
-use tokio_util::udp::UdpFramed;
+#[cfg(not(all(target_os = "linux", feature = "gso")))]
+use {
+    super::QosSink,
+    futures::{SinkExt, future::ready},
+    tokio_util::udp::UdpFramed,
+};
 
-const PROTOCOL_PORT: Port = 5555;
+pub const PROTOCOL_PORT: Port = 5555;
 
 /// 为所有活跃的网络接口创建 socket
 /// 对于本地链路地址需要加入特定组播进行发现
 /// 对于 scope 比 link_local 更广的地址则不需要加入组播
 async fn create_socket(addr: &EndPoint) -> Result<UdpSocket> {
-    let multi_addr = StdIpv6Addr::from_segments([0xFF12, 0, 0, 0, 0, 0, 0, 1]);
     let sock = UdpSocket::bind(SocketAddr::from(*addr)).await?;
-    if let Some(scope_id) = addr.get_scope_id() {
-        sock.join_multicast_v6(&multi_addr, *scope_id)?;
-        sock.set_multicast_loop_v6(false)?;
-    }
+    multicast_membership().join(&sock, addr)?;
     Ok(sock)
 }
 
-pub type MsgSink = SplitSink<UdpFramed<MsgCodec>, (Msg, SocketAddr)>;
-pub type MsgStream = SplitStream<UdpFramed<MsgCodec>>;
+/// 收发两半都擦除具体类型，测试中可以换成内存传输
+pub type MsgSink = Pin<Box<dyn Sink<(Msg, SocketAddr), Error = CodecError> + Send>>;
+/// 接收半边交出的一项，来源地址由 socket 附上
+pub type Frame = std::result::Result<(Decoded, SocketAddr), CodecError>;
+/// 接口消失时通过对应的 `StreamHandle` 终止接收
+pub type MsgStream = Abortable<BoxStream<'static, Frame>>;
 pub type MsgSinkMap = HashMap<EndPoint, MsgSink>; // key 应当是 scoped addr
+pub type StreamHandle = stream::AbortHandle;
+pub type StreamHandles = HashMap<EndPoint, StreamHandle>;
+
+/// 交给发送端的 sink 增减，随网卡变化而来
+pub enum SinkCommand {
+    Attach(EndPoint, MsgSink),
+    Detach(EndPoint),
+    /// 在所有链路本地接口上组播发现报文
+    Announce(HostId),
+    /// 接口恢复后从该接口向已知对端的端点单播发现报文，让双方的 bond 尽快重建
+    Rediscover {
+        local: EndPoint,
+        host: HostId,
+        targets: Vec<EndPoint>,
+    },
+}
+
+/// 收发各用一个编解码器，发送半边需要直接改 socket 选项
+#[cfg(not(all(target_os = "linux", feature = "gso")))]
+fn split(sock: Arc<UdpSocket>, stats: Arc<SocketStats>) -> (MsgSink, BoxStream<'static, Frame>) {
+    let stream = UdpFramed::new(sock.clone(), MsgCodec::with_stats(stats.clone()));
+    // 编码时带上目标地址，控制消息据此决定能否压缩
+    let framed = UdpFramed::new(sock.clone(), MsgCodec::with_stats(stats))
+        .with(|(msg, dst): (Msg, SocketAddr)| ready(Ok::<_, CodecError>(((msg, dst), dst))));
+    let sink = QosSink::new(framed, sock, qos().policy());
+    (Box::pin(FragmentSink::new(sink)), stream.boxed())
+}
+
+/// 一次系统调用收发多个报文，见 `BatchSink`
+#[cfg(all(target_os = "linux", feature = "gso"))]
+fn split_batched(
+    sock: Arc<UdpSocket>,
+    stats: Arc<SocketStats>,
+) -> (MsgSink, BoxStream<'static, Frame>) {
+    let offload = Arc::new(Offload::detect(&sock));
+    let stream = batched_stream(
+        sock.clone(),
+        offload.clone(),
+        MsgCodec::with_stats(stats.clone()),
+    );
+    let sink = BatchSink::new(sock, offload, MsgCodec::with_stats(stats), qos().policy());
+    (Box::pin(FragmentSink::new(sink)), stream.boxed())
+}
+
+/// 在单个接口地址上绑定 socket 并拆分收发两半
+pub async fn bind(iface: ScopedAddr) -> Result<(EndPoint, MsgSink, MsgStream, StreamHandle)> {
+    let addr = EndPoint::new(iface, PROTOCOL_PORT);
+    let sock = Arc::new(create_socket(&addr).await?);
+    let stats = register_socket(addr);
+    #[cfg(all(target_os = "linux", feature = "gso"))]
+    let (sink, stream) = split_batched(sock, stats);
+    #[cfg(not(all(target_os = "linux", feature = "gso")))]
+    let (sink, stream) = split(sock, stats);
+    let (handle, registration) = StreamHandle::new_pair();
+    let stream = Abortable::new(reassembled(stream), registration);
+    #[cfg(feature = "chaos")]
+    let stream = super::chaos().wrap(stream);
+    Ok((addr, sink, stream, handle))
+}
 
-pub async fn split_group() -> Result<(MsgSinkMap, SelectAll<MsgStream>)> {
-    let results = try_join_all(NicView::default().map(async move |iface| -> Result<_> {
-        let addr = EndPoint::new(iface, PROTOCOL_PORT);
-        let sock = create_socket(&addr).await?;
-        Ok((addr, UdpFramed::new(sock, MsgCodec).split()))
-    }))
-    .await?;
+pub async fn split_group() -> Result<(MsgSinkMap, SelectAll<MsgStream>, StreamHandles)> {
+    let results = try_join_all(NicView::default().map(bind)).await?;
     let mut sinks = HashMap::with_capacity(results.len());
     let mut streams = SelectAll::new();
-    for (addr, (sink, stream)) in results {
+    let mut handles = HashMap::with_capacity(results.len());
+    for (addr, sink, stream, handle) in results {
         sinks.insert(addr, sink);
         streams.push(stream);
+        handles.insert(addr, handle);
     }
-    Ok((sinks, streams))
+    Ok((sinks, streams, handles))
 }
@@ -0,0 +1,278 @@
+use crate::addr::EndPoint;
+use dashmap::DashMap;
+use std::{
+    fmt::Write,
+    io,
+    net::SocketAddr,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::AbortHandle,
+};
+use tracing::{debug, info};
+
+/// 单个 socket 的收发计数，由该 socket 的编解码器更新
+#[derive(Debug, Default)]
+pub struct SocketStats {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+    decode_errors: AtomicU64,
+    version_dropped: AtomicU64,
+}
+
+impl SocketStats {
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_decode_error(&self) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_version_dropped(&self) {
+        self.version_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, local: EndPoint) -> SocketSnapshot {
+        SocketSnapshot {
+            local,
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            version_dropped: self.version_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketSnapshot {
+    pub local: EndPoint,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub decode_errors: u64,
+    /// 协议版本不符被丢弃的报文
+    pub version_dropped: u64,
+}
+
+/// 按本地端点登记的 socket 计数，网卡重新出现时沿用原来的计数
+pub fn socket_stats() -> &'static DashMap<EndPoint, Arc<SocketStats>> {
+    static SOCKET_STATS: OnceLock<DashMap<EndPoint, Arc<SocketStats>>> = OnceLock::new();
+    SOCKET_STATS.get_or_init(DashMap::new)
+}
+
+pub fn register_socket(local: EndPoint) -> Arc<SocketStats> {
+    socket_stats().entry(local).or_default().clone()
+}
+
+/// 所有 socket 的计数快照，按端点排序
+pub fn metrics() -> Vec<SocketSnapshot> {
+    let mut snapshots = socket_stats()
+        .iter()
+        .map(|entry| entry.snapshot(*entry.key()))
+        .collect::<Vec<_>>();
+    snapshots.sort_by_key(|snapshot| snapshot.local.to_string());
+    snapshots
+}
+
+/// 发现报文的处理计数，由链路层更新
+#[derive(Debug, Default)]
+pub struct DiscoveryStats {
+    received: AtomicU64,
+    duplicate: AtomicU64,
+    throttled: AtomicU64,
+}
+
+impl DiscoveryStats {
+    pub(crate) fn record_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_duplicate(&self) {
+        self.duplicate.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_throttled(&self) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DiscoveryCounts {
+        DiscoveryCounts {
+            received: self.received.load(Ordering::Relaxed),
+            duplicate: self.duplicate.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryCounts {
+    pub received: u64,
+    /// 同一路径上重复到达而被忽略的
+    pub duplicate: u64,
+    /// 超出单个对端的速率上限而被丢弃的
+    pub throttled: u64,
+}
+
+pub fn discovery_stats() -> &'static DiscoveryStats {
+    static DISCOVERY_STATS: OnceLock<DiscoveryStats> = OnceLock::new();
+    DISCOVERY_STATS.get_or_init(DiscoveryStats::default)
+}
+
+/// Prometheus 文本格式
+pub fn render_prometheus(snapshots: &[SocketSnapshot]) -> String {
+    type Field = fn(&SocketSnapshot) -> u64;
+    let families: [(&str, &str, Field); 6] = [
+        ("packets_sent", "Packets sent", |s| s.packets_sent),
+        ("bytes_sent", "Bytes sent", |s| s.bytes_sent),
+        ("packets_received", "Packets received", |s| {
+            s.packets_received
+        }),
+        ("bytes_received", "Bytes received", |s| s.bytes_received),
+        ("decode_errors", "Frames that failed to decode", |s| {
+            s.decode_errors
+        }),
+        (
+            "version_dropped",
+            "Frames dropped for protocol version mismatch",
+            |s| s.version_dropped,
+        ),
+    ];
+    let mut out = String::new();
+    for (name, help, field) in families {
+        let _ = writeln!(out, "# HELP falcon_socket_{name}_total {help}");
+        let _ = writeln!(out, "# TYPE falcon_socket_{name}_total counter");
+        for snapshot in snapshots {
+            let _ = writeln!(
+                out,
+                "falcon_socket_{name}_total{{endpoint=\"{}\"}} {}",
+                snapshot.local,
+                field(snapshot)
+            );
+        }
+    }
+    out
+}
+
+/// 发现报文计数的 Prometheus 文本格式
+pub fn render_discovery(counts: &DiscoveryCounts) -> String {
+    let families = [
+        ("received", "Discovery frames received", counts.received),
+        (
+            "duplicate",
+            "Discovery frames ignored as duplicates",
+            counts.duplicate,
+        ),
+        (
+            "throttled",
+            "Discovery frames dropped over the per-host rate limit",
+            counts.throttled,
+        ),
+    ];
+    let mut out = String::new();
+    for (name, help, value) in families {
+        let _ = writeln!(out, "# HELP falcon_discovery_{name}_total {help}");
+        let _ = writeln!(out, "# TYPE falcon_discovery_{name}_total counter");
+        let _ = writeln!(out, "falcon_discovery_{name}_total {value}");
+    }
+    out
+}
+
+/// 在本地 TCP 端口上以 Prometheus 文本格式导出 socket 计数
+pub struct MetricsExporter {
+    abort: AbortHandle,
+}
+
+impl MetricsExporter {
+    pub async fn run(listen: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen).await?;
+        info!("Exporting metrics on {listen}");
+        let abort = tokio::spawn(async move {
+            loop {
+                let Ok((stream, peer)) = listener.accept().await else {
+                    continue;
+                };
+                tokio::spawn(async move {
+                    if let Err(err) = Self::serve(stream).await {
+                        debug!("Failed to serve metrics to {peer}: {err}");
+                    }
+                });
+            }
+        })
+        .abort_handle();
+        Ok(Self { abort })
+    }
+
+    /// 不解析请求，任何请求都返回全部指标
+    async fn serve(mut stream: TcpStream) -> io::Result<()> {
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).await?;
+        let body = render_prometheus(&metrics()) + &render_discovery(&discovery_stats().snapshot());
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Metrics exporter has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::mock_endpoint_lan;
+
+    #[test]
+    fn prometheus_text() {
+        let local = mock_endpoint_lan();
+        let stats = register_socket(local);
+        stats.record_sent(100);
+        stats.record_received(40);
+        stats.record_version_dropped();
+        let snapshot = metrics()
+            .into_iter()
+            .find(|snapshot| snapshot.local == local)
+            .unwrap();
+        assert_eq!((snapshot.packets_sent, snapshot.bytes_sent), (1, 100));
+        assert_eq!(snapshot.version_dropped, 1);
+
+        let text = render_prometheus(&[snapshot]);
+        assert!(text.contains("# TYPE falcon_socket_bytes_received_total counter"));
+        assert!(text.contains(&format!(
+            "falcon_socket_bytes_received_total{{endpoint=\"{local}\"}} 40"
+        )));
+
+        let counts = DiscoveryCounts {
+            received: 5,
+            duplicate: 3,
+            throttled: 1,
+        };
+        let text = render_discovery(&counts);
+        assert!(text.contains("# TYPE falcon_discovery_duplicate_total counter"));
+        assert!(text.contains("falcon_discovery_throttled_total 1\n"));
+    }
+}
@@ -13,11 +13,24 @@
 #![feature(once_cell_try)]
 
 pub mod addr;
+pub mod audit;
 pub mod config;
 pub mod event_handler;
+pub mod history;
 pub mod hot_file;
 pub mod inbound;
 pub mod link;
 // pub mod outbound;
+pub mod policy;
+#[cfg(feature = "rpc")]
+pub mod rpc;
 pub mod session;
+pub mod shutdown;
 pub mod task;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transfer;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+pub use transfer::Transfer;
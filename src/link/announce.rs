@@ -0,0 +1,117 @@
+use super::link_state_table;
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, SinkCommand},
+};
+use std::time::Duration;
+use tokio::{sync::mpsc, task::AbortHandle, time::sleep};
+use tracing::{debug, info};
+
+/// 实际间隔在 [1 - JITTER, 1 + JITTER] 倍之间随机，避免同时启动的主机同步广播
+const JITTER: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnnouncePolicy {
+    pub interval: Duration,
+    /// 没有新主机出现时间隔逐次翻倍，直到这个上限
+    pub max_interval: Duration,
+}
+
+impl Default for AnnouncePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl AnnouncePolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        let interval = cfg
+            .get_typed(ConfigItem::AnnounceIntervalMs)
+            .await
+            .map(Duration::from_millis)
+            .unwrap_or(default.interval);
+        let max_interval = cfg
+            .get_typed(ConfigItem::AnnounceMaxIntervalMs)
+            .await
+            .map(Duration::from_millis)
+            .unwrap_or(default.max_interval);
+        Self {
+            interval,
+            max_interval: max_interval.max(interval),
+        }
+    }
+
+    /// 发现了新主机就回到基础间隔，否则指数退避
+    fn next(&self, delay: Duration, discovered: bool) -> Duration {
+        match discovered {
+            true => self.interval,
+            false => delay.saturating_mul(2).min(self.max_interval),
+        }
+    }
+
+    fn jittered(delay: Duration) -> Duration {
+        delay.mul_f64(rand::random_range(1.0 - JITTER..=1.0 + JITTER))
+    }
+}
+
+/// 周期性地在所有链路本地接口上组播 `Msg::Discovery`，让晚启动的主机也能发现本机
+pub struct Announcer {
+    abort: AbortHandle,
+}
+
+impl Announcer {
+    pub fn run(
+        local: HostId,
+        policy: AnnouncePolicy,
+        sinks: mpsc::UnboundedSender<SinkCommand>,
+    ) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut delay = policy.interval;
+            let mut known = link_state_table().host_count();
+            loop {
+                if sinks.send(SinkCommand::Announce(local.clone())).is_err() {
+                    break;
+                }
+                sleep(AnnouncePolicy::jittered(delay)).await;
+                let now_known = link_state_table().host_count();
+                delay = policy.next(delay, now_known > known);
+                known = now_known;
+                debug!("Next discovery announcement in about {delay:?}");
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for Announcer {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Announcer has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_and_reset() {
+        let policy = AnnouncePolicy::default();
+        let mut delay = policy.interval;
+        for _ in 0..10 {
+            delay = policy.next(delay, false);
+        }
+        assert_eq!(delay, policy.max_interval);
+        assert_eq!(policy.next(delay, true), policy.interval);
+        for _ in 0..100 {
+            let jittered = AnnouncePolicy::jittered(policy.interval);
+            assert!(jittered >= policy.interval.mul_f64(1.0 - JITTER));
+            assert!(jittered <= policy.interval.mul_f64(1.0 + JITTER));
+        }
+    }
+}
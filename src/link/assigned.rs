@@ -1,5 +1,6 @@
-use super::LinkResumeTaskError;
-use crate::addr::EndPoint;
+use super::{LinkResumeTaskError, LinkState};
+use crate::{addr::EndPoint, inbound::HostId};
+use std::sync::{Arc, Weak};
 
 type SolveClosure =
     Box<dyn FnOnce() -> Result<(), super::LinkResumeTaskError> + 'static + Send + Sync>;
@@ -7,6 +8,9 @@ type SolveClosure =
 pub struct AssignedLink {
     local: EndPoint,
     remote: EndPoint,
+    via: Option<HostId>,
+    /// 不持有强引用，链路被移除后取不到状态
+    state: Weak<LinkState>,
     solve: SolveClosure,
 }
 
@@ -19,14 +23,32 @@ impl AssignedLink {
         &self.remote
     }
 
+    /// 中继链路需要把报文封装后发给中继
+    pub fn via(&self) -> Option<&HostId> {
+        self.via.as_ref()
+    }
+
+    /// 链路的实时状态，用于拥塞窗口等按链路的控制
+    pub fn state(&self) -> Option<Arc<LinkState>> {
+        self.state.upgrade()
+    }
+
     pub fn solve(self) -> Result<(), LinkResumeTaskError> {
         (self.solve)()
     }
 
-    pub fn new(local: EndPoint, remote: EndPoint, solve: SolveClosure) -> Self {
+    pub fn new(
+        local: EndPoint,
+        remote: EndPoint,
+        via: Option<HostId>,
+        state: Weak<LinkState>,
+        solve: SolveClosure,
+    ) -> Self {
         Self {
             local,
             remote,
+            via,
+            state,
             solve,
         }
     }
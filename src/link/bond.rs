@@ -1,21 +1,110 @@
-use super::{BondStateFlag, LinkState};
-use crate::addr::EndPoint;
+use super::{BondStateFlag, LinkState, Weight, interface_cache};
+use crate::{addr::EndPoint, inbound::HostId};
 use indexmap::{IndexSet, indexset};
-use std::sync::Arc;
+use rand::Rng;
+use std::sync::{Arc, atomic::Ordering};
 
 #[derive(Debug, Clone)]
 pub struct Bond {
     pub links: IndexSet<Arc<LinkState>>,
     pub flag: BondStateFlag, // 该状态描述bond状态而非link状态
+    /// 所有链路权重的前缀和，链路增删时重建
+    prefix_weights: Vec<Weight>,
+    /// 打洞学到的对端反射地址（NAT 外侧地址）
+    reflexive: IndexSet<EndPoint>,
 }
 
 impl Bond {
     /// 此时bond状态必为发现
     pub fn new(local: &EndPoint, remote: &EndPoint) -> Self {
-        Self {
-            links: indexset! {Arc::new(LinkState::new(*local, *remote, 0))},
+        Self::with_link(LinkState::new(*local, *remote, interface_cache().metric(local)))
+    }
+
+    /// 只能经中继到达的主机
+    pub fn relayed(local: &EndPoint, remote: &EndPoint, via: HostId) -> Self {
+        Self::with_link(LinkState::relayed(*local, *remote, via))
+    }
+
+    fn with_link(link: LinkState) -> Self {
+        let mut bond = Self {
+            links: indexset! {Arc::new(link)},
             flag: BondStateFlag::DISCOVED,
+            prefix_weights: Vec::new(),
+            reflexive: IndexSet::new(),
+        };
+        bond.rebuild_weights();
+        bond
+    }
+
+    pub(crate) fn rebuild_weights(&mut self) {
+        self.prefix_weights = self
+            .links
+            .iter()
+            .scan(0 as Weight, |acc, link| {
+                *acc = acc.saturating_add(link.weight());
+                Some(*acc)
+            })
+            .collect();
+    }
+
+    /// 按权重随机挑选一条健康链路，不分配内存
+    ///
+    /// 中继链路只在没有健康直连时使用
+    pub fn pick(&self) -> Option<Arc<LinkState>> {
+        self.pick_direct().or_else(|| {
+            self.links
+                .iter()
+                .find(|link| link.is_relayed() && link.is_healthy.load(Ordering::Relaxed))
+                .cloned()
+        })
+    }
+
+    /// 全部直连且健康时直接在缓存的前缀和上二分，否则只在健康直连中线性挑选
+    fn pick_direct(&self) -> Option<Arc<LinkState>> {
+        let is_healthy = |link: &&Arc<LinkState>| {
+            !link.is_relayed() && link.is_healthy.load(Ordering::Relaxed)
+        };
+        let mut rng = rand::rng();
+        if self.links.iter().all(|link| is_healthy(&link)) {
+            let total = *self.prefix_weights.last()?;
+            if total == 0 {
+                return None;
+            }
+            let selected = rng.random_range(0..total);
+            let index = self.prefix_weights.partition_point(|&acc| acc <= selected);
+            return self.links.get_index(index).cloned();
         }
+        let total = self
+            .links
+            .iter()
+            .filter(is_healthy)
+            .fold(0 as Weight, |acc, link| acc.saturating_add(link.weight()));
+        if total == 0 {
+            return None;
+        }
+        let mut selected = rng.random_range(0..total);
+        self.links
+            .iter()
+            .filter(is_healthy)
+            .find(|link| {
+                let weight = link.weight();
+                if selected < weight {
+                    return true;
+                }
+                selected -= weight;
+                false
+            })
+            .cloned()
+    }
+
+    /// 移除链路并重建权重缓存，返回移除后 bond 是否为空
+    pub fn remove(&mut self, link: &Arc<LinkState>) -> bool {
+        // LinkState 的哈希包含原子字段，按指针查找而非按值
+        if let Some(index) = self.links.iter().position(|l| Arc::ptr_eq(l, link)) {
+            self.links.swap_remove_index(index);
+            self.rebuild_weights();
+        }
+        self.links.is_empty()
     }
 
     /// 仅当不存在时才构造link_state
@@ -28,20 +117,52 @@ impl Bond {
         {
             return false;
         }
-        // todo query metric
+        let inserted = self
+            .links
+            .insert(Arc::new(LinkState::new(local, remote, interface_cache().metric(&local))));
+        self.rebuild_weights();
+        inserted
+    }
+
+    /// 添加经 `via` 转发的链路，已存在时返回 false
+    pub fn update_relay(&mut self, local: EndPoint, remote: EndPoint, via: HostId) -> bool {
+        if self.links.iter().any(|link| {
+            link.local_remote_addr() == (local, remote) && link.via.as_ref() == Some(&via)
+        }) {
+            return false;
+        }
+        let inserted = self
+            .links
+            .insert(Arc::new(LinkState::relayed(local, remote, via)));
+        self.rebuild_weights();
+        inserted
+    }
+
+    /// 是否有可用的直连链路
+    pub fn has_direct(&self) -> bool {
         self.links
-            .insert(Arc::new(LinkState::new(local, remote, 0)))
+            .iter()
+            .any(|link| !link.is_relayed() && link.is_healthy.load(Ordering::Relaxed))
+    }
+
+    /// 记录对端的反射地址，已知时返回 false
+    pub fn learn_reflexive(&mut self, endpoint: EndPoint) -> bool {
+        self.reflexive.insert(endpoint)
+    }
+
+    pub fn reflexive(&self) -> impl Iterator<Item = &EndPoint> {
+        self.reflexive.iter()
     }
 
-    // 没有remove 方法是因为bond 空了整个容器都会被移除
     // todo 实现迁移状态
 }
 
 #[cfg(test)]
 mod tests {
     use super::Bond;
-    use crate::addr::EndPoint;
+    use crate::{addr::EndPoint, inbound::HostId};
     use anyhow::Result;
+    use std::sync::atomic::Ordering;
 
     #[test]
     fn avoid_reconstructing() -> Result<()> {
@@ -51,4 +172,51 @@ mod tests {
         assert!(!bond.update(local, remote));
         Ok(())
     }
+
+    #[test]
+    fn relay_only_as_fallback() -> Result<()> {
+        let local = "[fe80::14dc:2dd0:51e7:fa65%17]:88".parse::<EndPoint>()?;
+        let remote = "[fe80::addf:f8cf:506a:be8f%4]:88".parse::<EndPoint>()?;
+        let relay = "[fe80::addf:f8cf:506a:be90%4]:88".parse::<EndPoint>()?;
+        let mut bond = Bond::new(&local, &remote);
+        assert!(bond.update_relay(local, relay, HostId::random()));
+        for _ in 0..32 {
+            assert!(!bond.pick().unwrap().is_relayed());
+        }
+        bond.links[0].is_healthy.store(false, Ordering::Release);
+        assert!(!bond.has_direct());
+        assert_eq!(bond.pick().unwrap().addr_remote, relay);
+        Ok(())
+    }
+
+    #[test]
+    fn learn_reflexive_once() -> Result<()> {
+        let local = "[2001:db8::1]:5555".parse::<EndPoint>()?;
+        let remote = "[2001:db8::2]:40123".parse::<EndPoint>()?;
+        let mut bond = Bond::new(&local, &remote);
+        assert!(bond.learn_reflexive(remote));
+        assert!(!bond.learn_reflexive(remote));
+        assert_eq!(bond.reflexive().collect::<Vec<_>>(), vec![&remote]);
+        Ok(())
+    }
+
+    #[test]
+    fn pick_skips_unhealthy() -> Result<()> {
+        let local = "[fe80::14dc:2dd0:51e7:fa65%17]:88".parse::<EndPoint>()?;
+        let remote1 = "[fe80::addf:f8cf:506a:be8f%4]:88".parse::<EndPoint>()?;
+        let remote2 = "[fe80::addf:f8cf:506a:be90%4]:88".parse::<EndPoint>()?;
+        let mut bond = Bond::new(&local, &remote1);
+        bond.update(local, remote2);
+        bond.links[0].is_healthy.store(false, Ordering::Release);
+        for _ in 0..32 {
+            assert_eq!(bond.pick().unwrap().addr_remote, remote2);
+        }
+        bond.links[1].is_healthy.store(false, Ordering::Release);
+        assert!(bond.pick().is_none());
+
+        let first = bond.links[0].clone();
+        assert!(!bond.remove(&first));
+        assert_eq!(bond.prefix_weights.len(), 1);
+        Ok(())
+    }
 }
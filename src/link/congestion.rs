@@ -0,0 +1,239 @@
+use super::{DirectParcel, link_state_table};
+use crate::{
+    addr::EndPoint,
+    inbound::{HostId, Msg},
+};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, mpsc},
+    task::AbortHandle,
+    time::{interval, timeout},
+};
+use tracing::{debug, info};
+
+/// 估算的单个数据报载荷，窗口以它为增长单位
+pub const SEGMENT: usize = 1400;
+pub const INITIAL_WINDOW: usize = 10 * SEGMENT;
+pub const MIN_WINDOW: usize = 2 * SEGMENT;
+pub const MAX_WINDOW: usize = 64 * 1024 * 1024;
+/// 累计收到这么多字节就立刻确认
+const ACK_EVERY: usize = 16 * SEGMENT;
+/// 未攒够时的延迟确认间隔
+const ACK_DELAY: Duration = Duration::from_millis(10);
+
+/// 每条链路的拥塞窗口（AIMD）
+///
+/// 慢启动阶段每确认一个字节窗口加一个字节，超过门限后每个窗口只加一个 SEGMENT；
+/// 一个 RTO 内没有任何确认即视为丢包，门限减半、窗口退回最小值并清空在途字节。
+/// 确认只携带字节数而不区分报文，丢失的确认同样由超时兜底。
+#[derive(Debug)]
+pub struct CongestionWindow {
+    cwnd: AtomicUsize,
+    ssthresh: AtomicUsize,
+    in_flight: AtomicUsize,
+    released: Notify,
+}
+
+impl Default for CongestionWindow {
+    fn default() -> Self {
+        Self {
+            cwnd: AtomicUsize::new(INITIAL_WINDOW),
+            ssthresh: AtomicUsize::new(MAX_WINDOW),
+            in_flight: AtomicUsize::new(0),
+            released: Notify::new(),
+        }
+    }
+}
+
+impl Clone for CongestionWindow {
+    fn clone(&self) -> Self {
+        Self {
+            cwnd: AtomicUsize::new(self.cwnd()),
+            ssthresh: AtomicUsize::new(self.ssthresh.load(Ordering::Relaxed)),
+            in_flight: AtomicUsize::new(self.in_flight()),
+            released: Notify::new(),
+        }
+    }
+}
+
+impl CongestionWindow {
+    pub fn cwnd(&self) -> usize {
+        self.cwnd.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// 窗口剩余可发送的字节数，供条带调度按窗口取数据
+    pub fn available(&self) -> usize {
+        self.cwnd().saturating_sub(self.in_flight())
+    }
+
+    /// 窗口放得下时占用 len 字节，在途为空时总是放行，避免大报文永远发不出去
+    pub fn try_acquire(&self, len: usize) -> bool {
+        let cwnd = self.cwnd();
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                (in_flight == 0 || in_flight + len <= cwnd).then_some(in_flight + len)
+            })
+            .is_ok()
+    }
+
+    /// 等待窗口腾出空间，超过 rto 仍无确认时按丢包处理后再试
+    pub async fn acquire(&self, len: usize, rto: Duration) {
+        loop {
+            let released = self.released.notified();
+            if self.try_acquire(len) {
+                return;
+            }
+            if timeout(rto, released).await.is_err() {
+                self.on_timeout();
+            }
+        }
+    }
+
+    pub fn on_ack(&self, bytes: usize) {
+        let _ = self
+            .in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                Some(in_flight.saturating_sub(bytes))
+            });
+        let ssthresh = self.ssthresh.load(Ordering::Relaxed);
+        let _ = self
+            .cwnd
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |cwnd| {
+                let grow = match cwnd < ssthresh {
+                    true => bytes,
+                    false => (SEGMENT * bytes / cwnd).max(1),
+                };
+                Some(cwnd.saturating_add(grow).min(MAX_WINDOW))
+            });
+        self.released.notify_waiters();
+    }
+
+    /// 乘性减：门限取当前窗口的一半，窗口回到最小值，在途字节视为全部丢失
+    pub fn on_timeout(&self) {
+        let halved = (self.cwnd() / 2).max(MIN_WINDOW);
+        self.ssthresh.store(halved, Ordering::Relaxed);
+        self.cwnd.store(MIN_WINDOW, Ordering::Relaxed);
+        self.in_flight.store(0, Ordering::Release);
+        self.released.notify_waiters();
+        debug!("Congestion timeout, ssthresh drops to {halved}");
+    }
+}
+
+/// 链路层截获的拥塞反馈
+#[derive(Debug)]
+pub enum Feedback {
+    /// 从 src 收到一个数据报文
+    Received { src: EndPoint, bytes: usize },
+    /// 对端确认了经 src 这条链路收到的字节
+    Acked {
+        host: HostId,
+        src: EndPoint,
+        bytes: u32,
+    },
+}
+
+/// 接收方对每条链路按字节延迟确认，发送方据此推进对应链路的拥塞窗口
+///
+/// 确认与文件范围无关，只用于拥塞控制，范围级的确认与重传由任务层负责
+pub struct Acknowledger {
+    abort: AbortHandle,
+}
+
+impl Acknowledger {
+    pub fn run(
+        local: HostId,
+        direct: mpsc::UnboundedSender<DirectParcel>,
+    ) -> (Self, mpsc::Sender<Feedback>) {
+        let (tx, mut rx) = mpsc::channel::<Feedback>(4096);
+        let abort = tokio::spawn(async move {
+            let mut unacked = HashMap::<EndPoint, usize>::new();
+            let mut delay = interval(ACK_DELAY);
+            let ack = |bytes: usize| Msg::LinkAck {
+                host: local.clone(),
+                bytes: bytes.min(u32::MAX as usize) as u32,
+            };
+            loop {
+                tokio::select! {
+                    _ = delay.tick(), if !unacked.is_empty() => {
+                        for (src, bytes) in unacked.drain() {
+                            let _ = direct.send((src, ack(bytes)));
+                        }
+                    }
+                    Some(feedback) = rx.recv() => match feedback {
+                        Feedback::Received { src, bytes } => {
+                            let unacked_bytes = unacked.entry(src).or_default();
+                            *unacked_bytes += bytes;
+                            if *unacked_bytes >= ACK_EVERY {
+                                let bytes = unacked.remove(&src).unwrap_or_default();
+                                let _ = direct.send((src, ack(bytes)));
+                            }
+                        }
+                        Feedback::Acked { host, src, bytes } => {
+                            match link_state_table().direct_link(&host, &src) {
+                                Some(link) => link.cwnd.on_ack(bytes as usize),
+                                None => debug!("Ack from {host} at {src} matches no link"),
+                            }
+                        }
+                    },
+                    else => break,
+                }
+            }
+        })
+        .abort_handle();
+        (Self { abort }, tx)
+    }
+}
+
+impl Drop for Acknowledger {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Acknowledger has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_start_then_avoidance() {
+        let window = CongestionWindow::default();
+        assert!(window.try_acquire(INITIAL_WINDOW));
+        assert!(!window.try_acquire(1));
+        window.on_ack(INITIAL_WINDOW);
+        assert_eq!(window.in_flight(), 0);
+        assert_eq!(window.cwnd(), 2 * INITIAL_WINDOW);
+
+        window.on_timeout();
+        assert_eq!(window.cwnd(), MIN_WINDOW);
+        // 门限是超时前窗口的一半，越过之后线性增长
+        window.on_ack(INITIAL_WINDOW - MIN_WINDOW);
+        assert_eq!(window.cwnd(), INITIAL_WINDOW);
+        window.on_ack(INITIAL_WINDOW);
+        assert_eq!(window.cwnd(), INITIAL_WINDOW + SEGMENT);
+    }
+
+    #[test]
+    fn oversized_when_idle() {
+        let window = CongestionWindow::default();
+        assert!(window.try_acquire(MAX_WINDOW));
+        assert!(!window.try_acquire(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_releases_window() {
+        let window = CongestionWindow::default();
+        assert!(window.try_acquire(INITIAL_WINDOW));
+        window.acquire(SEGMENT, Duration::from_secs(1)).await;
+        assert_eq!(window.cwnd(), MIN_WINDOW);
+        assert_eq!(window.in_flight(), SEGMENT);
+    }
+}
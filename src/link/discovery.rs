@@ -0,0 +1,154 @@
+use crate::{
+    addr::EndPoint,
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, discovery_stats},
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// 发现报文的去重与限速
+///
+/// 多块网卡收到同一个广播时各自产生一条发现报文，组播与广播也可能同时送达。
+/// 本地端点不同的报文对应不同的链路，不能只按主机去重；同一路径上的重复报文只处理一次，
+/// 每个对端另有速率上限，防止发现风暴挤占链路层的事件循环
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryPolicy {
+    /// 同一路径上的重复报文在这段时间内只处理一次，为零时不去重
+    pub dedup: Duration,
+    /// 每个对端每秒最多处理的发现报文数
+    pub per_host_per_sec: u32,
+}
+
+impl Default for DiscoveryPolicy {
+    fn default() -> Self {
+        Self {
+            dedup: Duration::from_secs(2),
+            per_host_per_sec: 32,
+        }
+    }
+}
+
+impl DiscoveryPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            dedup: cfg
+                .get_typed(ConfigItem::DiscoveryDedupMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.dedup),
+            per_host_per_sec: cfg
+                .get_typed(ConfigItem::DiscoveryPerHostPerSec)
+                .await
+                .unwrap_or(default.per_host_per_sec),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Fresh,
+    Duplicate,
+    Throttled,
+}
+
+/// 只在链路层的事件循环里使用，不需要加锁
+#[derive(Debug)]
+pub struct DiscoveryFilter {
+    policy: DiscoveryPolicy,
+    seen: HashMap<(HostId, EndPoint, EndPoint), Instant>,
+    windows: HashMap<HostId, (Instant, u32)>,
+}
+
+impl DiscoveryFilter {
+    const MAX_TRACKED: usize = 4096;
+
+    pub fn new(policy: DiscoveryPolicy) -> Self {
+        Self {
+            policy,
+            seen: HashMap::new(),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// 判断经 local 收到的、来自 remote 的发现报文是否需要处理，并更新计数
+    pub fn admit(&mut self, host: &HostId, local: &EndPoint, remote: &EndPoint) -> Admission {
+        let admission = self.check(host, local, remote);
+        let stats = discovery_stats();
+        stats.record_received();
+        match admission {
+            Admission::Fresh => {}
+            Admission::Duplicate => stats.record_duplicate(),
+            Admission::Throttled => {
+                stats.record_throttled();
+                debug!("Drop discovery from {host} over rate limit");
+            }
+        }
+        admission
+    }
+
+    fn check(&mut self, host: &HostId, local: &EndPoint, remote: &EndPoint) -> Admission {
+        let now = Instant::now();
+        if self.seen.len() >= Self::MAX_TRACKED {
+            let dedup = self.policy.dedup;
+            self.seen.retain(|_, at| now.duration_since(*at) < dedup);
+        }
+        if self.windows.len() >= Self::MAX_TRACKED {
+            self.windows
+                .retain(|_, (start, _)| now.duration_since(*start) < Duration::from_secs(1));
+        }
+        // 重复的报文也计入速率，风暴中的重复报文同样会挤占事件循环
+        let (start, count) = self.windows.entry(host.clone()).or_insert((now, 0));
+        if now.duration_since(*start) >= Duration::from_secs(1) {
+            (*start, *count) = (now, 0);
+        }
+        *count += 1;
+        if *count > self.policy.per_host_per_sec {
+            return Admission::Throttled;
+        }
+        if self.policy.dedup.is_zero() {
+            return Admission::Fresh;
+        }
+        let key = (host.clone(), *local, *remote);
+        // 重复的不刷新时间，每个窗口仍处理一次，失效链路的恢复不会被一直推迟
+        if let Some(at) = self.seen.get(&key)
+            && now.duration_since(*at) < self.policy.dedup
+        {
+            return Admission::Duplicate;
+        }
+        self.seen.insert(key, now);
+        Admission::Fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::{mock_endpoint_lan, mock_endpoint_wan};
+    use tokio::time::advance;
+
+    #[tokio::test(start_paused = true)]
+    async fn dedup_and_throttle() {
+        let mut filter = DiscoveryFilter::new(DiscoveryPolicy {
+            dedup: Duration::from_secs(2),
+            per_host_per_sec: 4,
+        });
+        let (host, other) = (HostId::random(), HostId::random());
+        let (lan, wan) = (mock_endpoint_lan(), mock_endpoint_wan());
+        assert_eq!(filter.admit(&host, &lan, &wan), Admission::Fresh);
+        assert_eq!(filter.admit(&host, &lan, &wan), Admission::Duplicate);
+        // 另一块网卡收到的是另一条链路
+        assert_eq!(filter.admit(&host, &wan, &wan), Admission::Fresh);
+        assert_eq!(filter.admit(&host, &wan, &lan), Admission::Fresh);
+        assert_eq!(filter.admit(&host, &lan, &lan), Admission::Throttled);
+        // 限速按对端分开计算
+        assert_eq!(filter.admit(&other, &lan, &wan), Admission::Fresh);
+
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(filter.admit(&host, &lan, &lan), Admission::Fresh);
+        assert_eq!(filter.admit(&host, &lan, &wan), Admission::Duplicate);
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(filter.admit(&host, &lan, &wan), Admission::Fresh);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
+    addr::EndPoint,
     inbound::{Handshake, HostId, Msg},
-    task::FileHash,
+    policy::TransferToken,
+    session::Sealed,
+    task::{FileHash, FileMeta},
 };
 use bytes::Bytes;
 use camino::{Utf8Component, Utf8PathBuf};
@@ -11,35 +14,69 @@ pub enum Event {
     /// 后续的事件都是基于该链路已经发现的假设
     Auth {
         host: HostId,
+        /// 实际观察到的对端地址，用于握手通道绑定
+        remote: EndPoint,
         state: Box<Handshake>,
     },
-    Task {
+    Offer {
         owner: HostId,
         hash: FileHash,
         file_name: Utf8PathBuf,
-        total: usize,
+        meta: FileMeta,
+    },
+    Decline {
+        host: HostId,
+        hash: FileHash,
+        reason: String,
+    },
+    Fetch {
+        host: HostId,
+        hash: FileHash,
+        token: Option<TransferToken>,
     },
     Transfer {
         host: HostId,
         payload: Bytes,
     },
+    /// 加密的会话层报文，由会话层解开后还原为上面的事件
+    Sealed {
+        host: HostId,
+        remote: EndPoint,
+        sealed: Sealed,
+    },
+}
+
+impl Event {
+    /// 会话层报文的发送方，握手与密文事件为 None
+    pub fn session_host(&self) -> Option<&HostId> {
+        match self {
+            Event::Offer { owner: host, .. }
+            | Event::Decline { host, .. }
+            | Event::Fetch { host, .. }
+            | Event::Transfer { host, .. } => Some(host),
+            Event::Auth { .. } | Event::Sealed { .. } => None,
+        }
+    }
 }
 
-impl From<Msg> for Event {
+impl From<(Msg, EndPoint)> for Event {
     #[inline(always)]
-    fn from(msg: Msg) -> Self {
+    fn from((msg, remote): (Msg, EndPoint)) -> Self {
         let event = match msg {
             Msg::Auth { host, state } => Event::Auth {
                 host,
+                remote,
                 state: Box::new(state),
             },
-            Msg::Task {
-                owner,
+            Msg::Offer {
+                host,
                 hash,
                 file_name,
-                total,
-            } => Event::Task {
-                owner,
+                size,
+                mtime,
+                permissions,
+            } => Event::Offer {
+                owner: host,
                 hash,
                 file_name: Utf8PathBuf::from(file_name)
                     .components()
@@ -47,13 +84,30 @@ impl From<Msg> for Event {
                     .filter(|c| matches!(c, Utf8Component::Normal(_)))
                     .iter()
                     .collect(),
-                total: total as usize,
+                meta: FileMeta::from_wire(size, mtime, permissions),
+            },
+            Msg::Decline { host, hash, reason } => Event::Decline { host, hash, reason },
+            Msg::Fetch { host, hash, token } => Event::Fetch {
+                host,
+                hash,
+                token: token.map(TransferToken::from),
             },
             Msg::Transfer { host, payload } => Event::Transfer {
                 host,
                 payload: payload.into(),
             },
-            _ => unreachable!("Discovery should be handled in link layer"),
+            Msg::Sealed {
+                host,
+                epoch,
+                nonce,
+                body,
+                ..
+            } => Event::Sealed {
+                host,
+                remote,
+                sealed: Sealed { epoch, nonce, body },
+            },
+            _ => unreachable!("Discovery and rendezvous should be handled in link layer"),
         };
         event
     }
@@ -0,0 +1,52 @@
+use super::bond::Bond;
+use crate::inbound::HostId;
+use dashmap::DashMap;
+use std::{sync::Arc, time::Duration};
+use tokio::{task::AbortHandle, time::Instant};
+use tracing::{debug, info};
+
+/// 被移除的 bond 在隔离期内拒绝旧的发现报文重新加入
+pub const TOMBSTONE_QUARANTINE: Duration = Duration::from_secs(10);
+const GC_INTERVAL: Duration = Duration::from_secs(30);
+
+pub type Tombstones = DashMap<HostId, Instant>;
+
+/// 定期清理空 bond 与过期墓碑
+pub struct LinkGc {
+    abort: AbortHandle,
+}
+
+impl LinkGc {
+    pub fn run(links: Arc<DashMap<HostId, Bond>>, tombstones: Arc<Tombstones>) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                Self::collect(&links, &tombstones);
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    pub fn collect(links: &DashMap<HostId, Bond>, tombstones: &Tombstones) {
+        // retain 持有分片写锁，期间不会有并发 update 插入链路
+        links.retain(|host, bond| {
+            let keep = !bond.links.is_empty();
+            if !keep {
+                debug!("Prune empty bond of {host}");
+                tombstones.insert(host.clone(), Instant::now());
+            }
+            keep
+        });
+        let now = Instant::now();
+        tombstones.retain(|_, buried| now.duration_since(*buried) < TOMBSTONE_QUARANTINE);
+    }
+}
+
+impl Drop for LinkGc {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Link GC has been dropped")
+    }
+}
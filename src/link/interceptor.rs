@@ -1,21 +1,32 @@
 use std::net::SocketAddr;
 
 use tokio::{sync::mpsc, task::AbortHandle};
-use tracing::warn;
+use tracing::{debug, info, warn};
 
-use crate::{addr::EndPoint, inbound::Msg, link::link_state_table};
+use crate::{
+    addr::EndPoint,
+    inbound::Msg,
+    link::{link_state_table, peer_table},
+};
 
-use super::Event;
+use super::{Admission, DiscoveryFilter, DiscoveryPolicy, Echo, Event, Feedback, Signal};
 
-struct Interceptor {
+/// 在链路层截获发现、打洞与转发报文，其余报文转为事件向上传递
+pub struct Interceptor {
     abort: AbortHandle,
 }
 
 impl Interceptor {
     pub fn run(
-        mut up_rx: mpsc::UnboundedReceiver<(Msg, SocketAddr)>,
+        mut up_rx: mpsc::Receiver<(Msg, SocketAddr)>,
+        punch: mpsc::Sender<Signal>,
+        relay: mpsc::Sender<Msg>,
+        probe: mpsc::Sender<Echo>,
+        feedback: mpsc::Sender<Feedback>,
+        discovery: DiscoveryPolicy,
     ) -> (Self, mpsc::Receiver<Event>) {
         let (down_tx, down_rx) = mpsc::channel::<Event>(1024);
+        let mut discoveries = DiscoveryFilter::new(discovery);
         let abort = tokio::spawn(async move {
             while let Some((msg, local)) = up_rx.recv().await {
                 let SocketAddr::V6(local) = local else {
@@ -26,16 +37,118 @@ impl Interceptor {
                     warn!("failed to convert socket addr to endpoint");
                     continue;
                 };
-                if let Msg::Discovery { host, remote } = msg {
-                    println!("Intercepted discovery message from {} to {}", host, remote);
-                    link_state_table().update(host, &local, &remote);
-                } else {
-                    let event: Event = msg.into();
-                    down_tx.send(event).await.unwrap();
-                }
+                peer_table().touch(msg.host());
+                let msg = match msg {
+                    Msg::Discovery { host, remote } => {
+                        if discoveries.admit(&host, &local, &remote) != Admission::Fresh {
+                            continue;
+                        }
+                        debug!("Intercepted discovery message from {host} to {remote}");
+                        peer_table().discovered(&host, remote);
+                        link_state_table().update(host, &local, &remote);
+                        continue;
+                    }
+                    Msg::Rendezvous { host, signal } => {
+                        // 打洞信令丢了也会被定时重发，不阻塞接收
+                        if let Err(err) = punch.try_send((host, signal, local)) {
+                            warn!("Drop rendezvous signal: {err}");
+                        }
+                        continue;
+                    }
+                    msg @ (Msg::Ping { .. }
+                    | Msg::Pong { .. }
+                    | Msg::MtuProbe { .. }
+                    | Msg::MtuAck { .. }) => {
+                        // 探测丢失本身就是要测量的信号，队列满时直接丢弃
+                        if let Err(err) = probe.try_send((msg, local)) {
+                            warn!("Drop probe: {err}");
+                        }
+                        continue;
+                    }
+                    Msg::LinkAck { host, bytes } => {
+                        let acked = Feedback::Acked {
+                            host,
+                            src: local,
+                            bytes,
+                        };
+                        if let Err(err) = feedback.try_send(acked) {
+                            warn!("Drop link ack: {err}");
+                        }
+                        continue;
+                    }
+                    // 直连收到的数据报文需要确认，转发帧的拥塞由中继两侧各自负责
+                    msg @ (Msg::Transfer { .. } | Msg::Sealed { control: false, .. }) => {
+                        let received = Feedback::Received {
+                            src: local,
+                            bytes: msg.data_len().unwrap_or_default(),
+                        };
+                        // 确认丢了由发送方超时兜底
+                        let _ = feedback.try_send(received);
+                        msg
+                    }
+                    // 换钥通告与保活只能以密文到达，在会话层解开后处理
+                    Msg::Rekey { host, .. } | Msg::Keepalive { host } => {
+                        warn!("Drop plaintext rekey or keepalive from {host}");
+                        continue;
+                    }
+                    // 转发协商与首跳的转发帧由本机作为中继处理
+                    msg @ (Msg::RelayRequest { .. }
+                    | Msg::RelayReply { .. }
+                    | Msg::Relayed { .. })
+                        if !matches!(&msg, Msg::Relayed { host, from, .. } if host != from) =>
+                    {
+                        if let Err(err) = relay.try_send(msg) {
+                            warn!("Drop relay message: {err}");
+                        }
+                        continue;
+                    }
+                    // 中继转来的帧，拆封后按原始发送方交给上层
+                    Msg::Relayed {
+                        host: via,
+                        from,
+                        inner,
+                        ..
+                    } => {
+                        if matches!(
+                            *inner,
+                            Msg::Discovery { .. }
+                                | Msg::Rendezvous { .. }
+                                | Msg::RelayRequest { .. }
+                                | Msg::RelayReply { .. }
+                                | Msg::Relayed { .. }
+                                | Msg::Ping { .. }
+                                | Msg::Pong { .. }
+                                | Msg::MtuProbe { .. }
+                                | Msg::MtuAck { .. }
+                                | Msg::Fragment { .. }
+                                | Msg::LinkAck { .. }
+                                | Msg::Rekey { .. }
+                                | Msg::Keepalive { .. }
+                        ) || *inner.host() != from
+                        {
+                            warn!("Drop malformed frame relayed by {via}");
+                            continue;
+                        }
+                        // 直连失效时回复也要经中继发出
+                        if !link_state_table().has_direct(&from) {
+                            link_state_table().add_relay(from, &via);
+                        }
+                        *inner
+                    }
+                    msg => msg,
+                };
+                let event: Event = (msg, local).into();
+                down_tx.send(event).await.unwrap();
             }
         })
         .abort_handle();
         (Self { abort }, down_rx)
     }
 }
+
+impl Drop for Interceptor {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Link interceptor has been dropped");
+    }
+}
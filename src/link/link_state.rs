@@ -1,10 +1,10 @@
-use super::LinkResumeTask;
-use crate::addr::EndPoint;
+use super::{CongestionWindow, LinkResumeTask, LossWindow, PathMtu, RttEstimator};
+use crate::{addr::EndPoint, inbound::HostId};
 use std::hash::Hash;
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering},
     },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -14,6 +14,9 @@ use tracing::{error, info};
 pub type Metric = usize;
 pub type Weight = usize;
 
+/// 经中继转发的链路权重最低，只在直连全部失效时使用
+pub const RELAY_WEIGHT: Weight = 1;
+
 #[derive(Debug, Error, PartialEq)]
 pub enum LinkError {
     #[error("No healthy links available")]
@@ -26,10 +29,18 @@ pub enum LinkError {
 pub struct LinkState {
     pub addr_local: EndPoint,
     pub addr_remote: EndPoint,
-    pub metric: Metric,
+    metric: AtomicUsize, // 由 MetricRefresher 周期性刷新
     pub failure_count: AtomicU8,
     pub is_healthy: AtomicBool,
     pub last_used: AtomicU64,
+    pub rtt: RttEstimator,
+    /// 保活探测统计的丢包
+    pub loss: LossWindow,
+    /// 只约束数据报文，控制报文不占窗口
+    pub cwnd: CongestionWindow,
+    pub mtu: PathMtu,
+    /// 经由该主机转发，此时 addr_remote 是中继的地址
+    pub via: Option<HostId>,
 }
 
 impl Clone for LinkState {
@@ -37,10 +48,15 @@ impl Clone for LinkState {
         Self {
             addr_local: self.addr_local.clone(),
             addr_remote: self.addr_remote.clone(),
-            metric: self.metric,
+            metric: AtomicUsize::new(self.metric()),
             failure_count: AtomicU8::new(self.failure_count.load(Ordering::Acquire)),
             is_healthy: AtomicBool::new(self.is_healthy.load(Ordering::Acquire)),
             last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+            rtt: self.rtt.clone(),
+            loss: self.loss.clone(),
+            cwnd: self.cwnd.clone(),
+            mtu: self.mtu.clone(),
+            via: self.via.clone(),
         }
     }
 }
@@ -49,10 +65,11 @@ impl Hash for LinkState {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.addr_local.hash(state);
         self.addr_remote.hash(state);
-        self.metric.hash(state);
+        self.metric().hash(state);
         self.failure_count.load(Ordering::Acquire).hash(state);
         self.is_healthy.load(Ordering::Acquire).hash(state);
         self.last_used.load(Ordering::Relaxed).hash(state);
+        self.via.hash(state);
     }
 }
 
@@ -60,11 +77,12 @@ impl PartialEq for LinkState {
     fn eq(&self, other: &Self) -> bool {
         self.addr_local == other.addr_local
             && self.addr_remote == other.addr_remote
-            && self.metric == other.metric
+            && self.metric() == other.metric()
             && self.failure_count.load(Ordering::Acquire)
                 == other.failure_count.load(Ordering::Acquire)
             && self.is_healthy.load(Ordering::Acquire) == other.is_healthy.load(Ordering::Acquire)
             && self.last_used.load(Ordering::Relaxed) == other.last_used.load(Ordering::Relaxed)
+            && self.via == other.via
     }
 }
 
@@ -75,13 +93,40 @@ impl LinkState {
         Self {
             addr_local,
             addr_remote,
-            metric,
+            metric: AtomicUsize::new(metric),
             failure_count: AtomicU8::new(0),
             is_healthy: AtomicBool::new(true),
             last_used: AtomicU64::new(0),
+            rtt: RttEstimator::new(),
+            loss: LossWindow::default(),
+            cwnd: CongestionWindow::default(),
+            mtu: PathMtu::direct(&addr_local, &addr_remote),
+            via: None,
+        }
+    }
+
+    /// 借用到中继的直连链路，经中继转发到目标主机
+    pub fn relayed(addr_local: EndPoint, addr_remote: EndPoint, via: HostId) -> Self {
+        Self {
+            mtu: PathMtu::relayed(),
+            via: Some(via),
+            ..Self::new(addr_local, addr_remote, Metric::MAX)
         }
     }
 
+    pub fn is_relayed(&self) -> bool {
+        self.via.is_some()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric.load(Ordering::Relaxed)
+    }
+
+    /// 返回旧值，调用方需要重建所在 bond 的权重缓存
+    pub fn set_metric(&self, metric: Metric) -> Metric {
+        self.metric.swap(metric, Ordering::Relaxed)
+    }
+
     pub fn reset(&self) {
         self.is_healthy.store(true, Ordering::Release);
         info!(
@@ -90,24 +135,38 @@ impl LinkState {
         );
     }
 
+    pub fn weight(&self) -> Weight {
+        match self.via {
+            Some(_) => RELAY_WEIGHT,
+            None => self.probed(self.direct_weight()),
+        }
+    }
+
+    /// 按探测到的丢包率与 RTT 折减权重，每 10ms RTT 再分一份，健康链路至少为 1
+    fn probed(&self, weight: Weight) -> Weight {
+        let delivered = 100 - self.loss.percent() as Weight;
+        let rtt_ms = self.rtt.srtt().map_or(0, |srtt| srtt.as_millis() as Weight);
+        (weight.saturating_mul(delivered) / 100 / (1 + rtt_ms / 10)).max(1)
+    }
+
     #[cfg(target_os = "windows")]
     // 应当对不同系统有不一样的行为
-    pub fn weight(&self) -> Weight {
+    fn direct_weight(&self) -> Weight {
         // Use inverse metric + 1 to avoid division by zero
         // Higher metric means lower weight
-        9999 as Metric / self.metric
+        9999 as Metric / (self.metric() + 1)
     }
     #[cfg(target_os = "macos")]
     // 应当对不同系统有不一样的行为
     // Higher metric means lower weight
-    pub fn weight(&self) -> Weight {
+    fn direct_weight(&self) -> Weight {
         // Use inverse metric + 1 to avoid division by zero
-        u16::MAX as Metric / self.metric
+        u16::MAX as Metric / (self.metric() + 1)
     }
     #[cfg(target_os = "linux")]
-    pub fn weight(&self) -> Weight {
+    fn direct_weight(&self) -> Weight {
         // Use inverse metric + 1 to avoid division by zero
-        u32::MAX as Metric / self.metric
+        u32::MAX as Metric / (self.metric() + 1)
     }
     // 分配链路后立刻调用
     pub fn update_usage(&self) {
@@ -219,6 +278,17 @@ mod test {
         assert!(link.last_used.load(Ordering::Relaxed) > now - MAX_CONSUME_TIME);
     }
 
+    #[test]
+    fn probed_weight() {
+        let link = default_link().clone();
+        let clean = link.weight();
+        link.loss.record(true);
+        link.loss.record(false);
+        assert_eq!(link.weight(), clean / 2);
+        link.rtt.on_sample(Duration::from_millis(30));
+        assert_eq!(link.weight(), clean / 2 / 4);
+    }
+
     #[test]
     fn reset_link() {
         let link = Arc::new(default_link().clone());
@@ -0,0 +1,298 @@
+use super::{LinkState, Metric};
+use crate::{addr::EndPoint, inbound::HostId};
+use dashmap::DashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv6Addr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    task::{AbortHandle, spawn_blocking},
+};
+use tracing::{debug, info, warn};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// 速率折算到 metric 的比例：1Gbps 记 100，100Mbps 记 1000
+const SPEED_SCALE: u64 = 100_000;
+/// 查询失败时的默认 metric
+pub const FALLBACK_METRIC: Metric = 1024;
+
+/// 从操作系统获取的接口信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InterfaceInfo {
+    /// 路由或接口 metric，越小越优
+    pub route_metric: u32,
+    pub mtu: Option<u32>,
+    pub speed_mbps: Option<u64>,
+}
+
+impl InterfaceInfo {
+    /// 综合路由 metric 与链路速率得到用于加权的 metric
+    pub fn effective_metric(&self) -> Metric {
+        let speed_penalty = self
+            .speed_mbps
+            .filter(|&speed| speed > 0)
+            .map(|speed| SPEED_SCALE / speed)
+            .unwrap_or(SPEED_SCALE / 100);
+        self.route_metric as Metric + speed_penalty as Metric
+    }
+}
+
+/// 查询本地地址所在接口的 metric，失败时退回默认值
+///
+/// 会读取 /proc 与 /sys，只能在阻塞线程中调用，异步代码使用 `interface_cache()`
+pub fn query_metric(local: &EndPoint) -> Metric {
+    sys::query(local.std_addr())
+        .map(|info| info.effective_metric())
+        .unwrap_or(FALLBACK_METRIC)
+}
+
+/// 同 `query_metric`，只能在阻塞线程中调用
+pub fn query_interface(local: &EndPoint) -> Option<InterfaceInfo> {
+    sys::query(local.std_addr())
+}
+
+/// 各本地地址最近一次查询到的接口信息，由 `MetricRefresher` 在阻塞线程池里填充
+#[derive(Debug, Default)]
+pub struct InterfaceCache {
+    interfaces: DashMap<Ipv6Addr, Option<InterfaceInfo>>,
+    /// 出现了尚未查询过的地址，刷新任务不必等到下个周期
+    stale: Notify,
+}
+
+pub fn interface_cache() -> &'static InterfaceCache {
+    static INTERFACE_CACHE: OnceLock<InterfaceCache> = OnceLock::new();
+    INTERFACE_CACHE.get_or_init(InterfaceCache::default)
+}
+
+impl InterfaceCache {
+    /// 不阻塞，尚未查询过的地址返回 None 并让刷新任务尽快查询
+    pub fn interface(&self, local: &EndPoint) -> Option<InterfaceInfo> {
+        match self.interfaces.get(local.std_addr()) {
+            Some(info) => *info,
+            None => {
+                self.stale.notify_one();
+                None
+            }
+        }
+    }
+
+    /// 建立链路时使用，查询到之前先用默认值，之后由刷新任务更正
+    pub fn metric(&self, local: &EndPoint) -> Metric {
+        self.interface(local)
+            .map_or(FALLBACK_METRIC, |info| info.effective_metric())
+    }
+
+    fn insert(&self, addr: Ipv6Addr, info: Option<InterfaceInfo>) {
+        self.interfaces.insert(addr, info);
+    }
+}
+
+/// 周期性刷新链路 metric，变化时重建 bond 的权重缓存
+pub struct MetricRefresher {
+    abort: AbortHandle,
+}
+
+impl MetricRefresher {
+    pub fn run(links: Arc<DashMap<HostId, super::bond::Bond>>) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = interface_cache().stale.notified() => {}
+                }
+                Self::refresh(&links).await;
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    /// 查询在阻塞线程池里进行，期间不持有任何分片锁，查完再短暂加写锁更新
+    pub async fn refresh(links: &DashMap<HostId, super::bond::Bond>) {
+        // 同一接口上的链路只查询一次
+        let locals = links
+            .iter()
+            .flat_map(|bond| {
+                bond.links
+                    .iter()
+                    .map(|link| link.addr_local)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+        let queried = spawn_blocking(move || {
+            locals
+                .into_iter()
+                .map(|local| (*local.std_addr(), query_interface(&local)))
+                .collect::<HashMap<_, _>>()
+        })
+        .await;
+        let infos = match queried {
+            Ok(infos) => infos,
+            Err(err) => {
+                warn!("Failed to query interface metrics: {err}");
+                return;
+            }
+        };
+        let mut metrics = HashMap::with_capacity(infos.len());
+        for (addr, info) in infos {
+            interface_cache().insert(addr, info);
+            metrics.insert(
+                addr,
+                info.map_or(FALLBACK_METRIC, |info| info.effective_metric()),
+            );
+        }
+        for mut bond in links.iter_mut() {
+            let changed = bond
+                .links
+                .iter()
+                .filter(|link| Self::refresh_link(link, &metrics))
+                .count();
+            if changed > 0 {
+                debug!("{changed} link metrics of {} changed", bond.key());
+                bond.rebuild_weights();
+            }
+        }
+    }
+
+    fn refresh_link(link: &LinkState, metrics: &HashMap<Ipv6Addr, Metric>) -> bool {
+        // 查询期间新建的链路等下一轮
+        let Some(&metric) = metrics.get(link.addr_local.std_addr()) else {
+            return false;
+        };
+        link.set_metric(metric) != metric
+    }
+}
+
+impl Drop for MetricRefresher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Metric refresher has been dropped");
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::InterfaceInfo;
+    use std::{fs, net::Ipv6Addr};
+
+    pub(super) fn query(addr: &Ipv6Addr) -> Option<InterfaceInfo> {
+        let if_inet6 = fs::read_to_string("/proc/net/if_inet6").ok()?;
+        let name = if_inet6
+            .lines()
+            .filter_map(parse_if_inet6)
+            .find(|(a, _)| a == addr)
+            .map(|(_, name)| name)?;
+        let routes = fs::read_to_string("/proc/net/ipv6_route").ok()?;
+        let route_metric = route_metric(&routes, addr, &name)?;
+        let read = |attr: &str| {
+            fs::read_to_string(format!("/sys/class/net/{name}/{attr}"))
+                .ok()
+                .and_then(|s| s.trim().parse::<i64>().ok())
+                .filter(|&v| v > 0)
+        };
+        Some(InterfaceInfo {
+            route_metric,
+            mtu: read("mtu").map(|v| v as u32),
+            // 无线网卡通常读不到速率
+            speed_mbps: read("speed").map(|v| v as u64),
+        })
+    }
+
+    fn parse_addr(hex: &str) -> Option<Ipv6Addr> {
+        u128::from_str_radix(hex, 16).ok().map(Ipv6Addr::from)
+    }
+
+    /// `fe800000000000000000000000000001 02 40 20 80 eth0`
+    pub(super) fn parse_if_inet6(line: &str) -> Option<(Ipv6Addr, String)> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        Some((parse_addr(fields.first()?)?, fields.get(5)?.to_string()))
+    }
+
+    /// 在该接口上取最长前缀匹配的路由 metric，忽略 /128 的主机路由
+    pub(super) fn route_metric(routes: &str, addr: &Ipv6Addr, dev: &str) -> Option<u32> {
+        let bits = u128::from(*addr);
+        routes
+            .lines()
+            .filter_map(|line| {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                if fields.len() < 10 || fields[9] != dev {
+                    return None;
+                }
+                let dest = u128::from(parse_addr(fields[0])?);
+                let plen = u32::from_str_radix(fields[1], 16).ok()?;
+                let metric = u32::from_str_radix(fields[5], 16).ok()?;
+                let mask = u128::MAX.checked_shl(128 - plen).unwrap_or(0);
+                (plen < 128 && bits & mask == dest & mask).then_some((plen, metric))
+            })
+            .max_by_key(|&(plen, _)| plen)
+            .map(|(_, metric)| metric)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod sys {
+    use super::InterfaceInfo;
+    use std::net::{IpAddr, Ipv6Addr};
+
+    pub(super) fn query(addr: &Ipv6Addr) -> Option<InterfaceInfo> {
+        let adapters = ipconfig::get_adapters().ok()?;
+        let adapter = adapters
+            .iter()
+            .find(|adapter| adapter.ip_addresses().contains(&IpAddr::V6(*addr)))?;
+        Some(InterfaceInfo {
+            route_metric: adapter.ipv6_metric(),
+            mtu: None,
+            speed_mbps: Some(adapter.transmit_link_speed() / 1_000_000).filter(|&s| s > 0),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod sys {
+    use super::InterfaceInfo;
+    use std::net::Ipv6Addr;
+
+    pub(super) fn query(_: &Ipv6Addr) -> Option<InterfaceInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faster_links_rank_lower() {
+        let gigabit = InterfaceInfo {
+            route_metric: 256,
+            mtu: Some(1500),
+            speed_mbps: Some(1000),
+        };
+        let wifi = InterfaceInfo {
+            speed_mbps: None,
+            ..gigabit
+        };
+        assert!(gigabit.effective_metric() < wifi.effective_metric());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_routes() {
+        let line = "fe80000000000000021122fffe334455 02 40 20 80     eth0";
+        let (addr, name) = sys::parse_if_inet6(line).unwrap();
+        assert_eq!(addr, "fe80::211:22ff:fe33:4455".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(name, "eth0");
+
+        let routes = "\
+fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0
+fe80000000000000021122fffe334455 80 00000000000000000000000000000000 00 00000000000000000000000000000000 00000000 00000002 00000000 80200001     eth0
+fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000258 00000001 00000000 00000001    wlan0";
+        assert_eq!(sys::route_metric(routes, &addr, "eth0"), Some(0x100));
+        assert_eq!(sys::route_metric(routes, &addr, "wlan0"), Some(0x258));
+        assert_eq!(sys::route_metric(routes, &addr, "lo"), None);
+    }
+}
@@ -1,17 +1,40 @@
+mod announce;
 mod assigned;
 mod bond;
+mod congestion;
+mod discovery;
 mod event;
 mod flag;
+mod gc;
 mod interceptor;
 mod link_state;
+mod metric;
+mod mtu;
+mod peer;
+mod probe;
+mod punch;
+mod relay;
 mod resume;
+mod rtt;
 mod table;
 mod uid;
 
+pub use announce::*;
+pub use assigned::AssignedLink;
+pub use congestion::*;
+pub use discovery::*;
 pub use event::*;
 pub use flag::BondStateFlag;
+pub use gc::*;
 pub use interceptor::*;
 pub use link_state::*;
+pub use metric::*;
+pub use mtu::*;
+pub use peer::*;
+pub use probe::*;
+pub use punch::*;
+pub use relay::*;
 pub use resume::*;
+pub use rtt::*;
 pub use table::*;
 pub use uid::*;
@@ -0,0 +1,260 @@
+use super::interface_cache;
+use crate::{
+    addr::EndPoint,
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, IP_UDP_OVERHEAD, MAX_MTU, Msg, MsgCodec},
+};
+use std::sync::{
+    OnceLock, RwLock,
+    atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+use tracing::{debug, info};
+
+/// IPv6 要求所有链路至少支持的 MTU，不必探测
+pub const IPV6_MIN_MTU: usize = 1280;
+/// 数据报文在文件数据之外的开销上限：帧头、`Sealed` 的字段与 AEAD 标签、
+/// 加密前 `Transfer` 的封装以及任务标签与偏移
+pub const DATA_OVERHEAD: usize = 192;
+/// 上下界相差不超过这么多字节时停止探测
+const PROBE_PRECISION: usize = 32;
+/// 同一长度连续丢失这么多次才认为超过了路径 MTU，偶发丢包不影响
+const PROBE_MISSES: u8 = 2;
+
+/// 按链路类型限定的最大 IP 报文长度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MtuPolicy {
+    /// 局域网直连与本机网卡同一个二层网络，网卡 MTU 就是路径 MTU
+    pub lan: usize,
+    /// 公网直连开启探测时作为上限，否则直接使用
+    pub wan: usize,
+    /// 中继两侧的路径都不可知，不探测
+    pub relayed: usize,
+    pub probe: bool,
+}
+
+impl Default for MtuPolicy {
+    fn default() -> Self {
+        Self {
+            lan: 1500,
+            wan: 1500,
+            relayed: IPV6_MIN_MTU,
+            probe: true,
+        }
+    }
+}
+
+impl MtuPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        let limit = async |item, default| {
+            cfg.get_typed::<usize>(item)
+                .await
+                .map(|mtu| mtu.clamp(IPV6_MIN_MTU, MAX_MTU))
+                .unwrap_or(default)
+        };
+        Self {
+            lan: limit(ConfigItem::MtuLan, default.lan).await,
+            wan: limit(ConfigItem::MtuWan, default.wan).await,
+            relayed: limit(ConfigItem::MtuRelayed, default.relayed).await,
+            probe: cfg
+                .get_typed(ConfigItem::MtuProbe)
+                .await
+                .unwrap_or(default.probe),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Mtu {
+    policy: RwLock<MtuPolicy>,
+}
+
+pub fn mtu() -> &'static Mtu {
+    static MTU: OnceLock<Mtu> = OnceLock::new();
+    MTU.get_or_init(Mtu::default)
+}
+
+impl Mtu {
+    /// 只影响之后建立的链路
+    pub fn configure(&self, policy: MtuPolicy) {
+        info!(
+            "Limit datagrams to {} bytes on LAN, {} on WAN (probe: {}), {} when relayed",
+            policy.lan, policy.wan, policy.probe, policy.relayed
+        );
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> MtuPolicy {
+        *self.policy.read().unwrap()
+    }
+}
+
+/// 单条链路的路径 MTU，以 IP 报文长度计
+///
+/// 在已确认可达的长度与已知不可达的上限之间二分探测，收敛后停止
+#[derive(Debug)]
+pub struct PathMtu {
+    confirmed: AtomicUsize,
+    ceiling: AtomicUsize,
+    misses: AtomicU8,
+}
+
+impl Clone for PathMtu {
+    fn clone(&self) -> Self {
+        Self {
+            confirmed: AtomicUsize::new(self.current()),
+            ceiling: AtomicUsize::new(self.ceiling.load(Ordering::Relaxed)),
+            misses: AtomicU8::new(self.misses.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for PathMtu {
+    fn default() -> Self {
+        Self::fixed(IPV6_MIN_MTU)
+    }
+}
+
+impl PathMtu {
+    /// 不再探测
+    pub fn fixed(mtu: usize) -> Self {
+        Self::probing(mtu, mtu)
+    }
+
+    pub fn probing(floor: usize, ceiling: usize) -> Self {
+        Self {
+            confirmed: AtomicUsize::new(floor),
+            ceiling: AtomicUsize::new(ceiling.max(floor)),
+            misses: AtomicU8::new(0),
+        }
+    }
+
+    /// 按链路类型与本地网卡 MTU 确定上限，只有公网直连需要探测
+    pub fn direct(local: &EndPoint, remote: &EndPoint) -> Self {
+        let policy = mtu().policy();
+        let limit = match remote.is_lan() {
+            true => policy.lan,
+            false => policy.wan,
+        };
+        // 不在这里查询系统，尚未查询过的网卡按策略的上限处理
+        let nic = interface_cache().interface(local).and_then(|info| info.mtu);
+        let ceiling = nic
+            .map_or(limit, |nic| limit.min(nic as usize))
+            .max(IPV6_MIN_MTU);
+        match remote.is_wan() && policy.probe {
+            true => Self::probing(IPV6_MIN_MTU, ceiling),
+            false => Self::fixed(ceiling),
+        }
+    }
+
+    pub fn relayed() -> Self {
+        Self::fixed(mtu().policy().relayed)
+    }
+
+    /// 已确认不会分片的最大 IP 报文长度
+    pub fn current(&self) -> usize {
+        self.confirmed.load(Ordering::Relaxed)
+    }
+
+    /// 一个数据报文最多能装的文件数据
+    pub fn max_payload(&self) -> usize {
+        self.current() - IP_UDP_OVERHEAD - DATA_OVERHEAD
+    }
+
+    /// 下一次探测的长度，已收敛时为 None
+    pub fn next_probe(&self) -> Option<usize> {
+        let confirmed = self.current();
+        let ceiling = self.ceiling.load(Ordering::Relaxed);
+        (ceiling - confirmed > PROBE_PRECISION)
+            .then(|| confirmed + (ceiling - confirmed).div_ceil(2))
+    }
+
+    pub fn on_ack(&self, size: usize) {
+        self.misses.store(0, Ordering::Relaxed);
+        let ceiling = self.ceiling.load(Ordering::Relaxed);
+        let old = self
+            .confirmed
+            .fetch_max(size.min(ceiling), Ordering::Relaxed);
+        if size > old {
+            debug!("Path MTU confirmed up to {size}");
+        }
+    }
+
+    /// 探测超时未回复，连续丢失足够多次时下调上限
+    pub fn on_lost(&self, size: usize) {
+        if self.misses.fetch_add(1, Ordering::Relaxed) + 1 < PROBE_MISSES {
+            return;
+        }
+        self.misses.store(0, Ordering::Relaxed);
+        let floor = self.current();
+        self.ceiling
+            .fetch_min((size - 1).max(floor), Ordering::Relaxed);
+        debug!("Path MTU below {size}");
+    }
+}
+
+/// 构造编码后 IP 报文长度约为 size 的探测，填充随机数据以免被压缩
+pub fn mtu_probe(host: HostId, nonce: u64, size: usize) -> Msg {
+    let empty = Msg::MtuProbe {
+        host: host.clone(),
+        nonce,
+        padding: Vec::new(),
+    };
+    let base = bincode::encode_to_vec(&empty, bincode::config::standard())
+        .map_or(0, |encoded| encoded.len());
+    // 填充超过 250 字节后长度前缀由 1 字节变为 3 字节
+    let len = size.saturating_sub(IP_UDP_OVERHEAD + MsgCodec::HDR_LEN + base + 2);
+    Msg::MtuProbe {
+        host,
+        nonce,
+        padding: (0..len).map(|_| rand::random()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use tokio_util::codec::Encoder;
+
+    #[test]
+    fn binary_search_converges() {
+        let mtu = PathMtu::probing(IPV6_MIN_MTU, 9000);
+        // 路径 MTU 为 1500
+        while let Some(size) = mtu.next_probe() {
+            for _ in 0..PROBE_MISSES {
+                match size <= 1500 {
+                    true => mtu.on_ack(size),
+                    false => mtu.on_lost(size),
+                }
+            }
+        }
+        assert!(mtu.current() <= 1500);
+        assert!(1500 - mtu.current() <= PROBE_PRECISION);
+        assert_eq!(
+            mtu.max_payload(),
+            mtu.current() - IP_UDP_OVERHEAD - DATA_OVERHEAD
+        );
+    }
+
+    #[test]
+    fn single_loss_keeps_ceiling() {
+        let mtu = PathMtu::probing(IPV6_MIN_MTU, 1500);
+        let size = mtu.next_probe().unwrap();
+        mtu.on_lost(size);
+        assert_eq!(mtu.next_probe(), Some(size));
+        mtu.on_ack(size);
+        assert_eq!(mtu.current(), size);
+        assert!(PathMtu::fixed(1500).next_probe().is_none());
+    }
+
+    #[test]
+    fn probe_matches_requested_size() {
+        for size in [IPV6_MIN_MTU, 1400, 1500, 9000] {
+            let probe = mtu_probe(HostId::random(), 7, size);
+            let mut buf = BytesMut::new();
+            MsgCodec::default().encode(probe, &mut buf).unwrap();
+            assert_eq!(buf.len() + IP_UDP_OVERHEAD, size);
+        }
+    }
+}
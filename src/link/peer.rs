@@ -0,0 +1,320 @@
+use crate::{
+    addr::EndPoint,
+    config::{ConfigItem, ConfigManager},
+    inbound::{Capabilities, HostId, ProtocolVersion},
+    session::{Fingerprint, HostMetadata},
+};
+use dashmap::DashMap;
+use std::{sync::OnceLock, time::Duration};
+use tokio::{
+    sync::broadcast,
+    task::AbortHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tracing::info;
+
+/// 对端的握手进度，由会话层更新
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HandshakeStage {
+    #[default]
+    Unknown,
+    Handshaking,
+    Established,
+    Failed,
+    /// 会话空闲过期，下次有会话层报文要发时重新握手
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    pub host: HostId,
+    /// 握手之前不知道对端主机名
+    pub host_name: Option<String>,
+    /// 以下由对端在握手中声明，旧版本不声明主机名与端口
+    pub listen_port: Option<u16>,
+    pub version: Option<ProtocolVersion>,
+    pub capabilities: Capabilities,
+    /// 对端在发现报文中通告的端点
+    pub endpoints: Vec<EndPoint>,
+    pub handshake: HandshakeStage,
+    pub last_seen: Instant,
+}
+
+impl PeerInfo {
+    fn new(host: HostId) -> Self {
+        Self {
+            host,
+            host_name: None,
+            listen_port: None,
+            version: None,
+            capabilities: Capabilities::empty(),
+            endpoints: Vec::new(),
+            handshake: HandshakeStage::default(),
+            last_seen: Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerChange {
+    Joined(PeerInfo),
+    /// 通告端点、主机名或握手进度变化，单纯的存活刷新不通知
+    Updated(PeerInfo),
+    Expired(HostId),
+}
+
+/// 当前已知的对端，超过 TTL 没有收到任何报文即移除
+pub struct PeerTable {
+    peers: DashMap<HostId, PeerInfo>,
+    /// 首次握手时记下的对端静态公钥指纹，对端过期后仍然保留
+    pins: DashMap<HostId, Fingerprint>,
+    changes: broadcast::Sender<PeerChange>,
+}
+
+pub fn peer_table() -> &'static PeerTable {
+    static PEER_TABLE: OnceLock<PeerTable> = OnceLock::new();
+    PEER_TABLE.get_or_init(PeerTable::new)
+}
+
+impl PeerTable {
+    const CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            peers: DashMap::new(),
+            pins: DashMap::new(),
+            changes: broadcast::channel(Self::CAPACITY).0,
+        }
+    }
+
+    /// 收到发现报文，新对端加入表中
+    pub fn discovered(&self, host: &HostId, endpoint: EndPoint) {
+        self.modify(host, |peer| match peer.endpoints.contains(&endpoint) {
+            true => false,
+            false => {
+                peer.endpoints.push(endpoint);
+                true
+            }
+        });
+    }
+
+    /// 收到对端的任意报文，只刷新已知对端的存活时间
+    pub fn touch(&self, host: &HostId) {
+        if let Some(mut peer) = self.peers.get_mut(host) {
+            peer.last_seen = Instant::now();
+        }
+    }
+
+    pub fn set_host_name(&self, host: &HostId, name: String) {
+        self.modify(host, |peer| {
+            let changed = peer.host_name.as_ref() != Some(&name);
+            peer.host_name = Some(name);
+            changed
+        });
+    }
+
+    /// 握手校验通过后记录对端声明的版本、特性与主机信息
+    pub fn identified(
+        &self,
+        host: &HostId,
+        version: ProtocolVersion,
+        capabilities: Capabilities,
+        metadata: Option<&HostMetadata>,
+    ) {
+        self.modify(host, |peer| {
+            let before = peer.clone();
+            if let Some(metadata) = metadata {
+                peer.host_name = Some(metadata.host_name.clone());
+                peer.listen_port = Some(metadata.listen_port);
+            }
+            peer.version = Some(version);
+            peer.capabilities = capabilities;
+            *peer != before
+        });
+    }
+
+    /// 首次见到的公钥直接信任，之后同一 HostId 必须出示同一把公钥，否则返回记下的指纹
+    pub fn pin_key(&self, host: &HostId, fingerprint: Fingerprint) -> Result<(), Fingerprint> {
+        let pinned = *self.pins.entry(host.clone()).or_insert(fingerprint);
+        match pinned == fingerprint {
+            true => Ok(()),
+            false => Err(pinned),
+        }
+    }
+
+    pub fn set_handshake(&self, host: &HostId, stage: HandshakeStage) {
+        self.modify(host, |peer| {
+            let changed = peer.handshake != stage;
+            peer.handshake = stage;
+            changed
+        });
+    }
+
+    pub fn get(&self, host: &HostId) -> Option<PeerInfo> {
+        self.peers.get(host).map(|peer| peer.clone())
+    }
+
+    pub fn list_peers(&self) -> Vec<PeerInfo> {
+        self.peers.iter().map(|peer| peer.clone()).collect()
+    }
+
+    /// 订阅对端加入、变化与过期
+    pub fn on_peer_change(&self) -> broadcast::Receiver<PeerChange> {
+        self.changes.subscribe()
+    }
+
+    /// 移除超过 ttl 未见的对端，返回移除数
+    pub fn expire(&self, ttl: Duration) -> usize {
+        let mut expired = Vec::new();
+        self.peers.retain(|host, peer| {
+            let alive = peer.last_seen.elapsed() < ttl;
+            if !alive {
+                expired.push(host.clone());
+            }
+            alive
+        });
+        for host in &expired {
+            info!("Peer {host} expired");
+            let _ = self.changes.send(PeerChange::Expired(host.clone()));
+        }
+        expired.len()
+    }
+
+    /// f 返回是否有值得通知的变化
+    fn modify(&self, host: &HostId, f: impl FnOnce(&mut PeerInfo) -> bool) {
+        let (joined, changed, info) = {
+            let mut joined = false;
+            let mut peer = self.peers.entry(host.clone()).or_insert_with(|| {
+                joined = true;
+                PeerInfo::new(host.clone())
+            });
+            peer.last_seen = Instant::now();
+            let changed = f(&mut peer);
+            (joined, changed, peer.clone())
+        };
+        let change = match (joined, changed) {
+            (true, _) => PeerChange::Joined(info),
+            (false, true) => PeerChange::Updated(info),
+            (false, false) => return,
+        };
+        let _ = self.changes.send(change);
+    }
+}
+
+impl Default for PeerTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerPolicy {
+    pub ttl: Duration,
+}
+
+impl Default for PeerPolicy {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+impl PeerPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            ttl: cfg
+                .get_typed(ConfigItem::PeerTtlMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.ttl),
+        }
+    }
+}
+
+/// 定期清理过期对端
+pub struct PeerReaper {
+    abort: AbortHandle,
+}
+
+impl PeerReaper {
+    pub fn run(policy: PeerPolicy) -> Self {
+        let abort = tokio::spawn(async move {
+            // 过期最多推迟四分之一个 ttl
+            let mut tick = interval(policy.ttl / 4);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                peer_table().expire(policy.ttl);
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for PeerReaper {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Peer reaper has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::{mock_endpoint_lan, mock_endpoint_wan};
+
+    #[tokio::test(start_paused = true)]
+    async fn join_update_expire() {
+        let table = PeerTable::new();
+        let mut changes = table.on_peer_change();
+        let (host, lan) = (HostId::random(), mock_endpoint_lan());
+        table.discovered(&host, lan);
+        assert!(matches!(changes.try_recv(), Ok(PeerChange::Joined(_))));
+        // 重复的发现报文只刷新存活
+        table.discovered(&host, lan);
+        assert!(changes.try_recv().is_err());
+        table.discovered(&host, mock_endpoint_wan());
+        table.set_handshake(&host, HandshakeStage::Established);
+        let Ok(PeerChange::Updated(_)) = changes.try_recv() else {
+            panic!("endpoint change not notified");
+        };
+        let Ok(PeerChange::Updated(peer)) = changes.try_recv() else {
+            panic!("handshake change not notified");
+        };
+        assert_eq!(peer.endpoints.len(), 2);
+        assert_eq!(peer.handshake, HandshakeStage::Established);
+        assert_eq!(table.list_peers(), vec![peer]);
+
+        let metadata = HostMetadata::new("falcon", 5555);
+        let caps = Capabilities::RELAY;
+        table.identified(&host, ProtocolVersion::CURRENT, caps, Some(&metadata));
+        let Ok(PeerChange::Updated(peer)) = changes.try_recv() else {
+            panic!("identity change not notified");
+        };
+        assert_eq!(peer.host_name.as_deref(), Some("falcon"));
+        assert_eq!(peer.listen_port, Some(5555));
+        assert_eq!(peer.capabilities, caps);
+        // 重连后声明相同的信息不再通知
+        table.identified(&host, ProtocolVersion::CURRENT, caps, Some(&metadata));
+        assert!(changes.try_recv().is_err());
+
+        // 首次握手记下公钥，换了公钥的同名对端被拒绝
+        let key = Fingerprint::of(&[1; 32]);
+        assert_eq!(table.pin_key(&host, key), Ok(()));
+        assert_eq!(table.pin_key(&host, key), Ok(()));
+        assert_eq!(table.pin_key(&host, Fingerprint::of(&[2; 32])), Err(key));
+
+        let ttl = Duration::from_secs(30);
+        tokio::time::advance(ttl / 2).await;
+        table.touch(&host);
+        tokio::time::advance(ttl / 2).await;
+        assert_eq!(table.expire(ttl), 0);
+        tokio::time::advance(ttl).await;
+        assert_eq!(table.expire(ttl), 1);
+        assert_eq!(changes.try_recv(), Ok(PeerChange::Expired(host)));
+        assert!(table.list_peers().is_empty());
+    }
+}
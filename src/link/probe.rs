@@ -0,0 +1,331 @@
+use super::{DirectParcel, LinkState, link_state_table, mtu_probe};
+use crate::{
+    addr::EndPoint,
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, Msg},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU8, AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{Instant, interval},
+};
+use tracing::{debug, info, warn};
+
+/// 链路层收到的探测报文与其源地址
+pub type Echo = (Msg, EndPoint);
+
+/// 丢包率按最近这么多个探测统计
+const LOSS_WINDOW: u8 = 32;
+
+/// 最近若干次探测的丢失情况，每一位代表一次探测，1 为丢失
+#[derive(Debug, Default)]
+pub struct LossWindow {
+    history: AtomicU32,
+    samples: AtomicU8,
+    consecutive: AtomicU8,
+}
+
+impl Clone for LossWindow {
+    fn clone(&self) -> Self {
+        Self {
+            history: AtomicU32::new(self.history.load(Ordering::Relaxed)),
+            samples: AtomicU8::new(self.samples.load(Ordering::Relaxed)),
+            consecutive: AtomicU8::new(self.consecutive.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl LossWindow {
+    /// 记录一次探测结果，返回连续丢失的次数
+    pub fn record(&self, lost: bool) -> u8 {
+        let _ = self
+            .history
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some(bits << 1 | lost as u32)
+            });
+        let _ = self
+            .samples
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < LOSS_WINDOW).then_some(n + 1)
+            });
+        match lost {
+            true => self
+                .consecutive
+                .fetch_add(1, Ordering::Relaxed)
+                .saturating_add(1),
+            false => {
+                self.consecutive.store(0, Ordering::Relaxed);
+                0
+            }
+        }
+    }
+
+    /// 丢包百分比，尚无样本时为 0
+    pub fn percent(&self) -> u8 {
+        let samples = self.samples.load(Ordering::Relaxed);
+        if samples == 0 {
+            return 0;
+        }
+        let mask = u32::MAX >> (32 - samples as u32);
+        let lost = (self.history.load(Ordering::Relaxed) & mask).count_ones();
+        (lost * 100 / samples as u32) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbePolicy {
+    pub interval: Duration,
+    /// 连续丢失这么多个探测即判定链路失效
+    pub dead_after: u8,
+}
+
+impl Default for ProbePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            dead_after: 3,
+        }
+    }
+}
+
+impl ProbePolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            interval: cfg
+                .get_typed(ConfigItem::ProbeIntervalMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.interval),
+            dead_after: cfg
+                .get_typed(ConfigItem::ProbeDeadAfter)
+                .await
+                .unwrap_or(default.dead_after),
+        }
+    }
+}
+
+struct InFlight {
+    host: HostId,
+    link: Weak<LinkState>,
+    sent: Instant,
+    deadline: Instant,
+}
+
+/// 每条链路同时只有一个路径 MTU 探测
+struct MtuInFlight {
+    host: HostId,
+    link: Weak<LinkState>,
+    size: usize,
+    deadline: Instant,
+}
+
+/// 周期性地沿每条直连链路发送 `Ping`
+///
+/// 收到 `Pong` 时把往返时间喂给链路的 RTT 估计器，超过 RTO 未回复记为丢失，
+/// RTT 与丢包率参与链路权重计算，连续丢失达到阈值时提前判定链路失效，
+/// 不必等到用户流量发送失败；失效链路仍会被探测，收到回复即提前恢复。
+/// 尚未收敛的健康链路同时用填充过的 `MtuProbe` 二分探测路径 MTU
+pub struct LinkProber {
+    abort: AbortHandle,
+}
+
+impl LinkProber {
+    pub fn run(
+        local: HostId,
+        policy: ProbePolicy,
+        direct: mpsc::UnboundedSender<DirectParcel>,
+    ) -> (Self, mpsc::Sender<Echo>) {
+        let (tx, mut rx) = mpsc::channel::<Echo>(1024);
+        let abort = tokio::spawn(async move {
+            let mut tick = interval(policy.interval);
+            let mut in_flight = HashMap::<u64, InFlight>::new();
+            let mut mtu_in_flight = HashMap::<u64, MtuInFlight>::new();
+            let mut next_nonce = rand::random::<u64>();
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {
+                        Self::expire(&mut in_flight, policy.dead_after);
+                        Self::expire_mtu(&mut mtu_in_flight);
+                        for (host, link) in link_state_table().probe_targets() {
+                            if let Some(size) = Self::mtu_target(&link, &mtu_in_flight) {
+                                let nonce = next_nonce;
+                                next_nonce = next_nonce.wrapping_add(1);
+                                let probe = mtu_probe(local.clone(), nonce, size);
+                                if direct.send((link.addr_remote, probe)).is_err() {
+                                    return;
+                                }
+                                mtu_in_flight.insert(nonce, MtuInFlight {
+                                    host: host.clone(),
+                                    link: Arc::downgrade(&link),
+                                    size,
+                                    deadline: Instant::now() + link.rtt.rto(),
+                                });
+                            }
+                            let nonce = next_nonce;
+                            next_nonce = next_nonce.wrapping_add(1);
+                            let ping = Msg::Ping { host: local.clone(), nonce };
+                            if direct.send((link.addr_remote, ping)).is_err() {
+                                return;
+                            }
+                            let sent = Instant::now();
+                            in_flight.insert(nonce, InFlight {
+                                host,
+                                deadline: sent + link.rtt.rto(),
+                                link: Arc::downgrade(&link),
+                                sent,
+                            });
+                        }
+                        link_state_table().rebuild_weights();
+                    }
+                    Some((msg, src)) = rx.recv() => match msg {
+                        Msg::Ping { nonce, .. } => {
+                            let pong = Msg::Pong { host: local.clone(), nonce };
+                            let _ = direct.send((src, pong));
+                        }
+                        Msg::Pong { host, nonce } => Self::answered(&mut in_flight, host, nonce),
+                        Msg::MtuProbe { nonce, .. } => {
+                            let ack = Msg::MtuAck { host: local.clone(), nonce };
+                            let _ = direct.send((src, ack));
+                        }
+                        Msg::MtuAck { host, nonce } => {
+                            Self::mtu_answered(&mut mtu_in_flight, host, nonce)
+                        }
+                        other => debug!("Unexpected probe message {other:?}"),
+                    },
+                    else => break,
+                }
+            }
+        })
+        .abort_handle();
+        (Self { abort }, tx)
+    }
+
+    fn answered(in_flight: &mut HashMap<u64, InFlight>, host: HostId, nonce: u64) {
+        // 迟到的回复已经记为丢失，不再作为样本
+        let Some(probe) = in_flight.remove(&nonce) else {
+            return;
+        };
+        if probe.host != host {
+            warn!("Pong from {host} answers a probe sent to {}", probe.host);
+            return;
+        }
+        let Some(link) = probe.link.upgrade() else {
+            return;
+        };
+        link.rtt.on_sample(probe.sent.elapsed());
+        link.loss.record(false);
+        if !link.is_healthy.load(Ordering::Acquire) {
+            let (local, remote) = link.local_remote_addr();
+            if link_state_table().revive(&host, &local, &remote) {
+                info!("Link {local} -> {remote} of {host} answers probes again");
+            }
+        }
+    }
+
+    /// 健康、尚未收敛且没有探测在途的链路返回下一次探测的长度
+    fn mtu_target(link: &Arc<LinkState>, in_flight: &HashMap<u64, MtuInFlight>) -> Option<usize> {
+        if !link.is_healthy.load(Ordering::Acquire)
+            || in_flight
+                .values()
+                .any(|probe| probe.link.as_ptr() == Arc::as_ptr(link))
+        {
+            return None;
+        }
+        link.mtu.next_probe()
+    }
+
+    fn mtu_answered(in_flight: &mut HashMap<u64, MtuInFlight>, host: HostId, nonce: u64) {
+        let Some(probe) = in_flight.remove(&nonce) else {
+            return;
+        };
+        if probe.host != host {
+            warn!("MTU ack from {host} answers a probe sent to {}", probe.host);
+            return;
+        }
+        if let Some(link) = probe.link.upgrade() {
+            link.mtu.on_ack(probe.size);
+        }
+    }
+
+    /// 探测报文过大时通常被静默丢弃，超时即记为一次丢失
+    fn expire_mtu(in_flight: &mut HashMap<u64, MtuInFlight>) {
+        let now = Instant::now();
+        in_flight.retain(|_, probe| {
+            if probe.deadline > now {
+                return true;
+            }
+            if let Some(link) = probe.link.upgrade()
+                && link.is_healthy.load(Ordering::Acquire)
+            {
+                link.mtu.on_lost(probe.size);
+            }
+            false
+        });
+    }
+
+    fn expire(in_flight: &mut HashMap<u64, InFlight>, dead_after: u8) {
+        let now = Instant::now();
+        in_flight.retain(|_, probe| {
+            if probe.deadline > now {
+                return true;
+            }
+            let Some(link) = probe.link.upgrade() else {
+                return false;
+            };
+            // 失效链路本来就不通，只等待回复，不重复计入失败
+            if !link.is_healthy.load(Ordering::Acquire) {
+                return false;
+            }
+            link.rtt.on_timeout();
+            if link.loss.record(true) >= dead_after {
+                let (local, remote) = link.local_remote_addr();
+                warn!(
+                    "Link {local} -> {remote} of {} stopped answering probes",
+                    probe.host
+                );
+                if let Err(err) = link_state_table().retire(&probe.host, link) {
+                    warn!("Failed to deactivate link: {err}");
+                }
+            }
+            false
+        });
+    }
+}
+
+impl Drop for LinkProber {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Link prober has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_window() {
+        let window = LossWindow::default();
+        assert_eq!(window.percent(), 0);
+        assert_eq!(window.record(true), 1);
+        assert_eq!(window.record(true), 2);
+        assert_eq!(window.percent(), 100);
+        assert_eq!(window.record(false), 0);
+        assert_eq!(window.record(false), 0);
+        assert_eq!(window.percent(), 50);
+        // 超出窗口的旧样本不再计入
+        for _ in 0..LOSS_WINDOW {
+            window.record(false);
+        }
+        assert_eq!(window.percent(), 0);
+    }
+}
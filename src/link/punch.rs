@@ -0,0 +1,281 @@
+use super::link_state_table;
+use crate::{
+    addr::EndPoint,
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, Msg, Rendezvous},
+    session::record_reflexive,
+};
+use dashmap::DashMap;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    sync::mpsc,
+    task::{AbortHandle, JoinSet},
+    time::{Instant, interval, sleep},
+};
+use tracing::{debug, info, warn};
+
+/// NAT 映射通常在 30 秒左右过期，登记间隔要短于它
+const REGISTER_INTERVAL: Duration = Duration::from_secs(15);
+/// 中继侧登记的有效期，超过后不再介绍
+const REGISTRATION_TTL: Duration = Duration::from_secs(60);
+
+/// 链路层收到的打洞信令：发送方、信令、报文源地址
+pub type Signal = (HostId, Rendezvous, EndPoint);
+/// 不经链路表、直接发往某个地址的报文
+pub type DirectParcel = (EndPoint, Msg);
+
+#[derive(Debug, Error)]
+pub enum PunchError {
+    #[error("No relay endpoint configured")]
+    NoRelay,
+    #[error("Hole puncher has stopped")]
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PunchPolicy {
+    /// 协调用的中继，None 时只响应对端发起的打洞
+    pub relay: Option<EndPoint>,
+    /// 本机是否为其他对端充当中继
+    pub serve: bool,
+    pub attempts: u8,
+    pub interval: Duration,
+}
+
+impl Default for PunchPolicy {
+    fn default() -> Self {
+        Self {
+            relay: None,
+            serve: false,
+            attempts: 10,
+            interval: Duration::from_millis(200),
+        }
+    }
+}
+
+impl PunchPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            relay: cfg.get_typed(ConfigItem::RelayEndpoint).await.ok(),
+            serve: cfg
+                .get_typed(ConfigItem::RelayServe)
+                .await
+                .unwrap_or(default.serve),
+            attempts: cfg
+                .get_typed(ConfigItem::PunchAttempts)
+                .await
+                .unwrap_or(default.attempts),
+            interval: cfg
+                .get_typed(ConfigItem::PunchIntervalMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.interval),
+        }
+    }
+}
+
+/// 为远端地址挑选同一作用域的本地 socket，广域网地址任选一个公网 socket
+pub fn local_for<'a>(
+    remote: &EndPoint,
+    mut locals: impl Iterator<Item = &'a EndPoint>,
+) -> Option<&'a EndPoint> {
+    locals.find(|local| match remote.is_wan() {
+        true => local.is_wan(),
+        false => local.is_lan() && local.get_scope_id() == remote.get_scope_id(),
+    })
+}
+
+#[derive(Default)]
+struct PunchState {
+    /// 中继观察到的本机反射地址
+    observed: RwLock<Option<EndPoint>>,
+    /// 作为中继时登记的对端反射地址
+    registry: DashMap<HostId, (EndPoint, Instant)>,
+}
+
+/// 通过中继协调的 UDP 同时打开
+///
+/// 双方先向中继登记，发起方请求介绍后，中继把彼此的反射地址发给双方，
+/// 双方同时向对方发送探测，先到的探测会被对方 NAT 丢弃，但会在己方 NAT 上留下映射，
+/// 之后对方的探测即可穿过，收到探测的一方把该地址作为链路加入 bond
+pub struct HolePuncher {
+    abort: AbortHandle,
+    local: HostId,
+    relay: Option<EndPoint>,
+    state: Arc<PunchState>,
+    direct: mpsc::UnboundedSender<DirectParcel>,
+}
+
+impl HolePuncher {
+    pub fn run(
+        local: HostId,
+        locals: Vec<EndPoint>,
+        policy: PunchPolicy,
+        direct: mpsc::UnboundedSender<DirectParcel>,
+    ) -> (Self, mpsc::Sender<Signal>) {
+        let (tx, mut rx) = mpsc::channel::<Signal>(256);
+        let state = Arc::new(PunchState::default());
+        let abort = tokio::spawn({
+            let local = local.clone();
+            let state = state.clone();
+            let direct = direct.clone();
+            async move {
+                let mut register = interval(REGISTER_INTERVAL);
+                // 随主任务一起中止
+                let mut bursts = JoinSet::new();
+                let rendezvous = |signal| Msg::Rendezvous {
+                    host: local.clone(),
+                    signal,
+                };
+                loop {
+                    tokio::select! {
+                        _ = register.tick(), if policy.relay.is_some() => {
+                            let Some(relay) = policy.relay else { continue };
+                            let _ = direct.send((relay, rendezvous(Rendezvous::Register)));
+                        }
+                        Some(_) = bursts.join_next(), if !bursts.is_empty() => {}
+                        Some((host, signal, src)) = rx.recv() => match signal {
+                            Rendezvous::Register if policy.serve => {
+                                state.registry.insert(host.clone(), (src, Instant::now()));
+                                let _ = direct.send((src, rendezvous(Rendezvous::Observed(src))));
+                            }
+                            Rendezvous::Connect(target) if policy.serve => {
+                                let Some(target_ep) = state
+                                    .registry
+                                    .get(&target)
+                                    .filter(|entry| entry.1.elapsed() < REGISTRATION_TTL)
+                                    .map(|entry| entry.0)
+                                else {
+                                    warn!("Cannot introduce {host} to unregistered {target}");
+                                    continue;
+                                };
+                                let introduce = |peer: &HostId, endpoint| {
+                                    rendezvous(Rendezvous::Introduce { peer: peer.clone(), endpoint })
+                                };
+                                let _ = direct.send((target_ep, introduce(&host, src)));
+                                let _ = direct.send((src, introduce(&target, target_ep)));
+                            }
+                            Rendezvous::Register | Rendezvous::Connect(_) => {
+                                debug!("Ignore relay request from {host}, relay serving disabled");
+                            }
+                            // 只信任来自配置中继的地址信息，避免被诱导向任意地址发包
+                            Rendezvous::Observed(endpoint) if Some(src) == policy.relay => {
+                                let previous = state.observed.write().unwrap().replace(endpoint);
+                                // 经 NAT 的对端在握手绑定里声明的正是这个地址
+                                record_reflexive(&endpoint);
+                                if previous != Some(endpoint) {
+                                    info!("Reflexive endpoint observed by relay: {endpoint}");
+                                }
+                            }
+                            Rendezvous::Introduce { peer, endpoint }
+                                if Some(src) == policy.relay =>
+                            {
+                                debug!("Punch {peer} at {endpoint}");
+                                let probe = rendezvous(Rendezvous::Punch);
+                                let direct = direct.clone();
+                                bursts.spawn(Self::burst(probe, peer, endpoint, policy, direct));
+                            }
+                            Rendezvous::Observed(_) | Rendezvous::Introduce { .. } => {
+                                warn!("Ignore rendezvous from {src}, not the configured relay");
+                            }
+                            Rendezvous::Punch => {
+                                let Some(local_ep) = local_for(&src, locals.iter()) else {
+                                    warn!("No local socket can reach {src}");
+                                    continue;
+                                };
+                                // 首次打通时回一个探测，保证对方也能学到这条链路
+                                let table = link_state_table();
+                                if table.learn_reflexive(host.clone(), local_ep, &src) {
+                                    info!("Hole punched to {host} at {src}");
+                                    let _ = direct.send((src, rendezvous(Rendezvous::Punch)));
+                                }
+                            }
+                        },
+                        else => break,
+                    }
+                }
+            }
+        })
+        .abort_handle();
+        let puncher = Self {
+            abort,
+            local,
+            relay: policy.relay,
+            state,
+            direct,
+        };
+        (puncher, tx)
+    }
+
+    /// 定时向对端反射地址发送探测，直到打通或次数耗尽
+    async fn burst(
+        probe: Msg,
+        peer: HostId,
+        endpoint: EndPoint,
+        policy: PunchPolicy,
+        direct: mpsc::UnboundedSender<DirectParcel>,
+    ) {
+        for _ in 0..policy.attempts {
+            if link_state_table().knows_reflexive(&peer, &endpoint) {
+                return;
+            }
+            if direct.send((endpoint, probe.clone())).is_err() {
+                return;
+            }
+            sleep(policy.interval).await;
+        }
+        if !link_state_table().knows_reflexive(&peer, &endpoint) {
+            warn!(
+                "Failed to punch {peer} at {endpoint} after {} attempts",
+                policy.attempts
+            );
+        }
+    }
+
+    /// 请求中继介绍目标主机，打通后链路会出现在链路表中
+    pub fn connect(&self, target: &HostId) -> Result<(), PunchError> {
+        let relay = self.relay.ok_or(PunchError::NoRelay)?;
+        let msg = Msg::Rendezvous {
+            host: self.local.clone(),
+            signal: Rendezvous::Connect(target.clone()),
+        };
+        self.direct
+            .send((relay, msg))
+            .map_err(|_| PunchError::Stopped)
+    }
+
+    /// 中继观察到的本机反射地址
+    pub fn observed(&self) -> Option<EndPoint> {
+        *self.state.observed.read().unwrap()
+    }
+}
+
+impl Drop for HolePuncher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Hole puncher has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_local_by_scope() {
+        let lan4 = "[fe80::1%4]:5555".parse::<EndPoint>().unwrap();
+        let lan7 = "[fe80::2%7]:5555".parse::<EndPoint>().unwrap();
+        let wan = "[240e::1]:5555".parse::<EndPoint>().unwrap();
+        let locals = [lan4, lan7, wan];
+        let remote_lan = "[fe80::9%7]:5555".parse::<EndPoint>().unwrap();
+        let remote_wan = "[2408::9]:40000".parse::<EndPoint>().unwrap();
+        assert_eq!(local_for(&remote_lan, locals.iter()), Some(&lan7));
+        assert_eq!(local_for(&remote_wan, locals.iter()), Some(&wan));
+        assert_eq!(local_for(&remote_wan, locals[..2].iter()), None);
+    }
+}
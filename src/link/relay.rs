@@ -0,0 +1,200 @@
+use super::link_state_table;
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::{Capabilities, HostId, Msg},
+    session::peer_capabilities,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{Instant, interval},
+};
+use tracing::{debug, info, warn};
+
+/// 转发授权在空闲这么久后失效，每转发一帧都会续期
+const GRANT_TTL: Duration = Duration::from_secs(600);
+/// 检查直连是否全部失效的间隔
+const FALLBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RelayPolicy {
+    /// 本机是否为其他对端转发
+    pub serve: bool,
+    /// 直连失效时自动请求的中继
+    pub fallback: Option<HostId>,
+}
+
+impl RelayPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        Self {
+            serve: cfg
+                .get_typed(ConfigItem::RelayServe)
+                .await
+                .unwrap_or_default(),
+            fallback: cfg.get_typed(ConfigItem::RelayHost).await.ok(),
+        }
+    }
+}
+
+/// 两台主机之间的转发授权，不区分方向
+#[derive(Default)]
+struct Grants(HashMap<(HostId, HostId), Instant>);
+
+impl Grants {
+    fn allow(&mut self, a: &HostId, b: &HostId) {
+        self.0.insert((a.clone(), b.clone()), Instant::now());
+    }
+
+    /// 命中时续期
+    fn check(&mut self, a: &HostId, b: &HostId) -> bool {
+        let now = Instant::now();
+        [(a.clone(), b.clone()), (b.clone(), a.clone())]
+            .into_iter()
+            .any(|key| match self.0.get_mut(&key) {
+                Some(last) if now.duration_since(*last) < GRANT_TTL => {
+                    *last = now;
+                    true
+                }
+                _ => false,
+            })
+    }
+
+    fn prune(&mut self) {
+        let now = Instant::now();
+        self.0
+            .retain(|_, last| now.duration_since(*last) < GRANT_TTL);
+    }
+}
+
+/// 经可信第三方转发的回退路径
+///
+/// 发起方向中继发送 `RelayRequest`，中继确认自己与双方都有直连后答复接受，
+/// 发起方随即在目标主机的 bond 中加入经中继的链路，这些链路权重最低，只在直连全部失效时使用，
+/// 目标主机收到第一帧转发后也会建立反向的中继链路
+pub struct RelayAgent {
+    abort: AbortHandle,
+    local: HostId,
+    out: mpsc::UnboundedSender<(HostId, Msg)>,
+}
+
+impl RelayAgent {
+    pub fn run(
+        local: HostId,
+        policy: RelayPolicy,
+        out: mpsc::UnboundedSender<(HostId, Msg)>,
+    ) -> (Self, mpsc::Sender<Msg>) {
+        let (tx, mut rx) = mpsc::channel::<Msg>(256);
+        let abort = tokio::spawn({
+            let local = local.clone();
+            let out = out.clone();
+            async move {
+                let mut grants = Grants::default();
+                let mut fallback = interval(FALLBACK_INTERVAL);
+                loop {
+                    tokio::select! {
+                        _ = fallback.tick() => {
+                            grants.prune();
+                            let Some(relay) = &policy.fallback else { continue };
+                            // 只向握手时声明愿意转发的对端请求中继
+                            if !peer_capabilities(relay).contains(Capabilities::RELAY) {
+                                continue;
+                            }
+                            for target in link_state_table().stranded() {
+                                if target == *relay {
+                                    continue;
+                                }
+                                debug!("Direct links to {target} are down, relay via {relay}");
+                                let request = Msg::RelayRequest {
+                                    host: local.clone(),
+                                    target,
+                                };
+                                let _ = out.send((relay.clone(), request));
+                            }
+                        }
+                        Some(msg) = rx.recv() => match msg {
+                            Msg::RelayRequest { host, target } => {
+                                let table = link_state_table();
+                                let accepted = policy.serve
+                                    && host != target
+                                    && table.has_direct(&host)
+                                    && table.has_direct(&target);
+                                if accepted {
+                                    info!("Relay frames between {host} and {target}");
+                                    grants.allow(&host, &target);
+                                }
+                                let reply = Msg::RelayReply {
+                                    host: local.clone(),
+                                    target,
+                                    accepted,
+                                };
+                                let _ = out.send((host, reply));
+                            }
+                            Msg::RelayReply { host, target, accepted: true } => {
+                                let added = link_state_table().add_relay(target.clone(), &host);
+                                info!("Reach {target} via relay {host} on {added} links");
+                            }
+                            Msg::RelayReply { host, target, accepted: false } => {
+                                warn!("Relay {host} refused to forward to {target}");
+                            }
+                            Msg::Relayed { from, target, inner, .. } => {
+                                // 不允许多级转发
+                                let nested = matches!(*inner, Msg::Relayed { .. });
+                                if nested || !grants.check(&from, &target) {
+                                    debug!("Drop frame from {from} to {target} without grant");
+                                    continue;
+                                }
+                                let frame = Msg::Relayed {
+                                    host: local.clone(),
+                                    from,
+                                    target: target.clone(),
+                                    inner,
+                                };
+                                let _ = out.send((target, frame));
+                            }
+                            other => debug!("Unexpected relay message {other:?}"),
+                        },
+                        else => break,
+                    }
+                }
+            }
+        })
+        .abort_handle();
+        (Self { abort, local, out }, tx)
+    }
+
+    /// 请求 `via` 为本机与 `target` 转发，返回 false 说明发送管线已停止
+    pub fn request(&self, target: &HostId, via: &HostId) -> bool {
+        let request = Msg::RelayRequest {
+            host: self.local.clone(),
+            target: target.clone(),
+        };
+        self.out.send((via.clone(), request)).is_ok()
+    }
+}
+
+impl Drop for RelayAgent {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Relay agent has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn grant_is_symmetric_and_expires() {
+        let (a, b, c) = (HostId::random(), HostId::random(), HostId::random());
+        let mut grants = Grants::default();
+        grants.allow(&a, &b);
+        assert!(grants.check(&a, &b));
+        assert!(grants.check(&b, &a));
+        assert!(!grants.check(&a, &c));
+        tokio::time::advance(GRANT_TTL).await;
+        assert!(!grants.check(&a, &b));
+        grants.prune();
+        assert!(grants.0.is_empty());
+    }
+}
@@ -24,11 +24,60 @@ mod tests {
                 shared_state_clone.store(true, Ordering::Release);
             }),
         );
-        task_sender.send(task).await.unwrap();
+        task_sender.send(task.into()).await.unwrap();
         yield_now().await;
         tokio::time::advance(Duration::from_secs(10)).await;
         yield_now().await;
         assert!(shared_state.load(Ordering::Acquire));
         drop(scheduler);
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancel_and_reschedule() {
+        let (_scheduler, task_sender) = LinkResumeScheduler::run();
+        let flag = |fired: &Arc<AtomicBool>| {
+            let fired = fired.clone();
+            Box::new(move || fired.store(true, Ordering::Release))
+        };
+        let (cancelled, shortened) = (
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+        let cancel_task = LinkResumeTask::new(Duration::from_secs(30), flag(&cancelled));
+        let shorten_task = LinkResumeTask::new(Duration::from_secs(30), flag(&shortened));
+        let cancel = ResumeHandle::new(cancel_task.id, task_sender.clone());
+        let shorten = ResumeHandle::new(shorten_task.id, task_sender.clone());
+        task_sender.send(cancel_task.into()).await.unwrap();
+        task_sender.send(shorten_task.into()).await.unwrap();
+        yield_now().await;
+
+        assert!(cancel.cancel());
+        assert!(shorten.reschedule(Duration::from_secs(1)));
+        yield_now().await;
+        tokio::time::advance(Duration::from_secs(2)).await;
+        yield_now().await;
+        assert!(shortened.load(Ordering::Acquire));
+        assert!(!cancelled.load(Ordering::Acquire));
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        yield_now().await;
+        assert!(!cancelled.load(Ordering::Acquire));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_drops_pending() {
+        let token = crate::shutdown::ShutdownToken::new();
+        let (_scheduler, task_sender) = LinkResumeScheduler::run_with(&token);
+        let fired = Arc::new(AtomicBool::new(false));
+        let task = LinkResumeTask::new(Duration::from_secs(3), {
+            let fired = fired.clone();
+            Box::new(move || fired.store(true, Ordering::Release))
+        });
+        task_sender.send(task.into()).await.unwrap();
+        yield_now().await;
+        token.shutdown().await;
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!fired.load(Ordering::Acquire));
+        assert!(task_sender.is_closed());
+    }
 }
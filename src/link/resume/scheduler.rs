@@ -1,5 +1,7 @@
-use super::task::LinkResumeTask;
+use super::task::{LinkResumeTask, ResumeId};
+use crate::shutdown::{ShutdownToken, shutdown_token};
 use futures::StreamExt;
+use std::{collections::HashMap, time::Duration};
 use thiserror::Error;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::{
@@ -12,7 +14,7 @@ use tracing::info;
 #[derive(Debug, Error)]
 pub enum LinkResumeTaskError {
     #[error(transparent)]
-    TaskSendError(#[from] TrySendError<LinkResumeTask>),
+    TaskSendError(#[from] TrySendError<ResumeCommand>),
     #[error("the arc refference of this link is invalid for now")]
     LinkRefInvalid,
 }
@@ -20,22 +22,89 @@ pub enum LinkResumeTaskError {
 unsafe impl Sync for LinkResumeTaskError {}
 unsafe impl Send for LinkResumeTaskError {}
 
+pub enum ResumeCommand {
+    Schedule(LinkResumeTask),
+    /// 链路或主机已移除，不再需要恢复
+    Cancel(ResumeId),
+    /// 有了新的健康证据，从现在起按新的延迟重新计时
+    Reschedule(ResumeId, Duration),
+}
+
+impl From<LinkResumeTask> for ResumeCommand {
+    fn from(task: LinkResumeTask) -> Self {
+        ResumeCommand::Schedule(task)
+    }
+}
+
+/// 已排队任务的句柄，任务到期或被取消后操作无效
+#[derive(Debug, Clone)]
+pub struct ResumeHandle {
+    id: ResumeId,
+    tx: Sender<ResumeCommand>,
+}
+
+impl ResumeHandle {
+    pub fn new(id: ResumeId, tx: Sender<ResumeCommand>) -> Self {
+        Self { id, tx }
+    }
+
+    pub fn id(&self) -> ResumeId {
+        self.id
+    }
+
+    /// 返回 false 说明调度器已停止或队列已满
+    pub fn cancel(&self) -> bool {
+        self.tx.try_send(ResumeCommand::Cancel(self.id)).is_ok()
+    }
+
+    pub fn reschedule(&self, delay: Duration) -> bool {
+        self.tx
+            .try_send(ResumeCommand::Reschedule(self.id, delay))
+            .is_ok()
+    }
+}
+
 pub struct LinkResumeScheduler {
     abort: AbortHandle,
 }
 
 impl LinkResumeScheduler {
-    pub fn run() -> (Self, Sender<LinkResumeTask>) {
-        let (tx, mut rx) = channel::<LinkResumeTask>(128); // todo 认真考虑背压
-        let abort = tokio::spawn(async move {
+    pub fn run() -> (Self, Sender<ResumeCommand>) {
+        Self::run_with(shutdown_token())
+    }
+
+    /// 关停时直接丢弃尚未到期的恢复任务
+    pub fn run_with(shutdown: &ShutdownToken) -> (Self, Sender<ResumeCommand>) {
+        let (tx, mut rx) = channel::<ResumeCommand>(128); // todo 认真考虑背压
+        let token = shutdown.clone();
+        let abort = shutdown.spawn(async move {
             let mut delay_queue = DelayQueue::new();
+            let mut keys = HashMap::new();
             loop {
                 tokio::select! {
-                    Some(task) = rx.recv() => {
-                        delay_queue.insert(task.callback, task.timeout);
+                    _ = token.cancelled() => {
+                        info!("Link Resume Scheduler stopped with {} pending tasks", delay_queue.len());
+                        break;
                     }
+                    Some(cmd) = rx.recv() => match cmd {
+                        ResumeCommand::Schedule(task) => {
+                            let key = delay_queue.insert((task.id, task.callback), task.timeout);
+                            keys.insert(task.id, key);
+                        }
+                        ResumeCommand::Cancel(id) => {
+                            if let Some(key) = keys.remove(&id) {
+                                delay_queue.remove(&key);
+                            }
+                        }
+                        ResumeCommand::Reschedule(id, delay) => {
+                            if let Some(key) = keys.get(&id) {
+                                delay_queue.reset(key, delay);
+                            }
+                        }
+                    },
                     Some(expired) = delay_queue.next() => {
-                        let callback = expired.into_inner();
+                        let (id, callback) = expired.into_inner();
+                        keys.remove(&id);
                         callback();
                     }
                 }
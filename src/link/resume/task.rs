@@ -1,14 +1,26 @@
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 type ResetCallback = Box<dyn FnOnce() + Send + 'static>;
 
+/// 进程内唯一，用于取消或改期尚未到期的任务
+pub type ResumeId = u64;
+
 pub struct LinkResumeTask {
+    pub id: ResumeId,
     pub timeout: Duration,
     pub callback: ResetCallback,
 }
 
 impl LinkResumeTask {
     pub fn new(timeout: Duration, callback: ResetCallback) -> Self {
-        Self { timeout, callback }
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            timeout,
+            callback,
+        }
     }
 }
@@ -0,0 +1,143 @@
+use std::{
+    sync::atomic::{AtomicU8, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// RFC 6298 中的时钟粒度 G
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+/// 局域网场景下 1s 的下限过于保守
+pub const MIN_RTO: Duration = Duration::from_millis(200);
+pub const MAX_RTO: Duration = Duration::from_secs(60);
+/// 尚未采样时使用的初始 RTO
+pub const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: u8 = 6;
+
+/// 每条链路的平滑 RTT / RTO 估计器（RFC 6298）
+///
+/// 由探测与确认的往返时间喂养，重传、保活和任务看门狗都从这里派生超时。
+/// 调用方需遵循 Karn 算法：重传过的报文不应作为样本。
+#[derive(Debug, Default)]
+pub struct RttEstimator {
+    // 单位均为微秒，srtt 为 0 表示尚无样本
+    srtt: AtomicU64,
+    rttvar: AtomicU64,
+    backoff: AtomicU8,
+}
+
+impl Clone for RttEstimator {
+    fn clone(&self) -> Self {
+        Self {
+            srtt: AtomicU64::new(self.srtt.load(Ordering::Relaxed)),
+            rttvar: AtomicU64::new(self.rttvar.load(Ordering::Relaxed)),
+            backoff: AtomicU8::new(self.backoff.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次往返样本，同时清除退避
+    // 并发采样时允许少量样本被覆盖，估计值仍然收敛
+    pub fn on_sample(&self, rtt: Duration) {
+        let r = (rtt.as_micros() as u64).max(1);
+        let srtt = self.srtt.load(Ordering::Relaxed);
+        if srtt == 0 {
+            self.srtt.store(r, Ordering::Relaxed);
+            self.rttvar.store(r / 2, Ordering::Relaxed);
+        } else {
+            let rttvar = self.rttvar.load(Ordering::Relaxed);
+            self.rttvar
+                .store((3 * rttvar + srtt.abs_diff(r)) / 4, Ordering::Relaxed);
+            self.srtt.store((7 * srtt + r) / 8, Ordering::Relaxed);
+        }
+        self.backoff.store(0, Ordering::Relaxed);
+    }
+
+    /// 超时后指数退避 RTO
+    pub fn on_timeout(&self) {
+        let _ = self
+            .backoff
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| {
+                (b < MAX_BACKOFF).then_some(b + 1)
+            });
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        match self.srtt.load(Ordering::Relaxed) {
+            0 => None,
+            us => Some(Duration::from_micros(us)),
+        }
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        Duration::from_micros(self.rttvar.load(Ordering::Relaxed))
+    }
+
+    /// 重传超时
+    pub fn rto(&self) -> Duration {
+        let base = match self.srtt() {
+            None => INITIAL_RTO,
+            Some(srtt) => srtt + CLOCK_GRANULARITY.max(4 * self.rttvar()),
+        };
+        let backoff = self.backoff.load(Ordering::Relaxed) as u32;
+        // RFC 6298：先取下限再退避，退避后才封顶
+        (base.max(MIN_RTO) * (1 << backoff)).min(MAX_RTO)
+    }
+
+    /// 保活探测间隔，取若干个 RTO 避免在慢链路上误判
+    pub fn keepalive_interval(&self) -> Duration {
+        (self.rto() * 4).max(Duration::from_secs(5))
+    }
+
+    /// 任务看门狗：连续 `rounds` 个 RTO 无进展即视为停滞
+    pub fn watchdog_timeout(&self, rounds: u32) -> Duration {
+        self.rto() * rounds.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_rto() {
+        let est = RttEstimator::new();
+        assert_eq!(est.srtt(), None);
+        assert_eq!(est.rto(), INITIAL_RTO);
+    }
+
+    #[test]
+    fn smoothing() {
+        let est = RttEstimator::new();
+        est.on_sample(Duration::from_millis(100));
+        assert_eq!(est.srtt(), Some(Duration::from_millis(100)));
+        assert_eq!(est.rttvar(), Duration::from_millis(50));
+        assert_eq!(est.rto(), Duration::from_millis(300));
+
+        est.on_sample(Duration::from_millis(200));
+        // rttvar = 3/4 * 50 + 1/4 * 100, srtt = 7/8 * 100 + 1/8 * 200
+        assert_eq!(est.rttvar(), Duration::from_micros(62_500));
+        assert_eq!(est.srtt(), Some(Duration::from_micros(112_500)));
+    }
+
+    #[test]
+    fn clamp_and_backoff() {
+        let est = RttEstimator::new();
+        est.on_sample(Duration::from_micros(100));
+        assert_eq!(est.rto(), MIN_RTO);
+        est.on_sample(Duration::from_millis(100));
+        let base = est.rto();
+        est.on_timeout();
+        est.on_timeout();
+        assert_eq!(est.rto(), (base * 4).min(MAX_RTO));
+        for _ in 0..20 {
+            est.on_timeout();
+        }
+        assert_eq!(est.rto(), MAX_RTO);
+        est.on_sample(Duration::from_millis(100));
+        assert!(est.rto() < MAX_RTO);
+    }
+}
@@ -3,13 +3,19 @@ use crate::addr::EndPoint;
 use crate::inbound::HostId;
 use crate::link::assigned::AssignedLink;
 use crate::link::bond::Bond;
-use crate::link::link_state::LinkError;
-use crate::link::{LinkResumeScheduler, LinkResumeTask};
-use dashmap::DashMap;
-use rand::Rng;
+use crate::link::link_state::{LinkError, LinkState, Metric, Weight};
+use crate::link::{
+    LinkGc, LinkResumeScheduler, LinkResumeTask, MetricRefresher, PathMtu, ResumeCommand,
+    ResumeHandle, TOMBSTONE_QUARANTINE, Tombstones,
+};
+use dashmap::{DashMap, mapref::entry::Entry};
 use std::sync::OnceLock;
 use std::sync::{Arc, atomic::Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
+use tokio::time::Instant;
+use tracing::{debug, info};
 
 static LINK_STATE_TABLE: OnceLock<LinkStateTable> = OnceLock::new();
 pub fn link_state_table() -> &'static LinkStateTable {
@@ -17,68 +23,455 @@ pub fn link_state_table() -> &'static LinkStateTable {
 }
 pub struct LinkStateTable {
     links: Arc<DashMap<HostId, Bond>>,
+    tombstones: Arc<Tombstones>,
     _scheduler: LinkResumeScheduler,
-    delay_task_sender: Sender<LinkResumeTask>,
+    _gc: LinkGc,
+    _metrics: MetricRefresher,
+    delay_task_sender: Sender<ResumeCommand>,
+    /// 尚未到期的恢复任务，链路或主机移除时据此取消
+    resumes: Arc<DashMap<LinkKey, ResumeHandle>>,
+    reachability: Arc<Reachability>,
+}
+
+type LinkKey = (HostId, EndPoint, EndPoint);
+
+/// 主机整体的可达性变化，任务层据此暂停与恢复传输
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// 最后一条健康链路失效或被移除
+    HostUnreachable(HostId),
+    /// 此前不可达的主机又有了健康链路
+    HostReachable(HostId),
+}
+
+/// 记录已通报不可达的主机，只在状态翻转时广播，避免每次失败都重复通知
+struct Reachability {
+    links: Arc<DashMap<HostId, Bond>>,
+    unreachable: DashMap<HostId, ()>,
+    events: broadcast::Sender<LinkEvent>,
+}
+
+impl Reachability {
+    const CAPACITY: usize = 64;
+
+    fn new(links: Arc<DashMap<HostId, Bond>>) -> Self {
+        Self {
+            links,
+            unreachable: DashMap::new(),
+            events: broadcast::channel(Self::CAPACITY).0,
+        }
+    }
+
+    fn healthy(&self, host_id: &HostId) -> bool {
+        self.links.get(host_id).is_some_and(|bond| {
+            bond.links
+                .iter()
+                .any(|link| link.is_healthy.load(Ordering::Acquire))
+        })
+    }
+
+    /// 链路增删或健康状态变化后调用，调用时不能持有该主机 bond 的锁
+    fn check(&self, host_id: &HostId) {
+        let event = match self.healthy(host_id) {
+            false if self.unreachable.insert(host_id.clone(), ()).is_none() => {
+                LinkEvent::HostUnreachable(host_id.clone())
+            }
+            true if self.unreachable.remove(host_id).is_some() => {
+                LinkEvent::HostReachable(host_id.clone())
+            }
+            _ => return,
+        };
+        info!("Link table: {event:?}");
+        let _ = self.events.send(event);
+    }
+
+    /// 恢复任务到期时链路重新变得健康，需要顺带检查一次
+    fn wrap(self: &Arc<Self>, host_id: HostId, task: LinkResumeTask) -> LinkResumeTask {
+        let reachability = self.clone();
+        let LinkResumeTask {
+            id,
+            timeout,
+            callback,
+        } = task;
+        LinkResumeTask {
+            id,
+            timeout,
+            callback: Box::new(move || {
+                callback();
+                reachability.check(&host_id);
+            }),
+        }
+    }
+}
+
+/// 提交恢复任务并记下句柄，同一链路的旧句柄会被覆盖
+fn schedule_resume(
+    sender: &Sender<ResumeCommand>,
+    resumes: &DashMap<LinkKey, ResumeHandle>,
+    key: LinkKey,
+    task: LinkResumeTask,
+) -> Result<(), LinkResumeTaskError> {
+    let handle = ResumeHandle::new(task.id, sender.clone());
+    sender.try_send(task.into())?;
+    resumes.insert(key, handle);
+    Ok(())
+}
+
+/// 链路失败：安排延迟恢复，失败次数耗尽时从 bond 中移除，bond 空了就立碑
+fn retire(
+    links: &DashMap<HostId, Bond>,
+    tombstones: &Tombstones,
+    sender: &Sender<ResumeCommand>,
+    resumes: &DashMap<LinkKey, ResumeHandle>,
+    reachability: &Arc<Reachability>,
+    host_id: HostId,
+    link: Arc<LinkState>,
+) -> Result<(), LinkResumeTaskError> {
+    let (local, remote) = link.local_remote_addr();
+    if let Some(task) = link.clone().deacitve() {
+        let task = reachability.wrap(host_id.clone(), task);
+        let scheduled = schedule_resume(sender, resumes, (host_id.clone(), local, remote), task);
+        reachability.check(&host_id);
+        return scheduled;
+    }
+    // 返回none代表没必要延迟了
+    // todo 持有锁可能会造成死锁
+    resumes.remove(&(host_id.clone(), local, remote));
+    let need_remove = match links.get_mut(&host_id) {
+        Some(mut entry) => entry.remove(&link),
+        None => false,
+    };
+    // 此时可以安全获取锁，但期间可能有新链路加入，需要再次确认为空
+    if need_remove
+        && links
+            .remove_if(&host_id, |_, bond| bond.links.is_empty())
+            .is_some()
+    {
+        tombstones.insert(host_id.clone(), Instant::now());
+    }
+    reachability.check(&host_id);
+    Ok(())
 }
 
 impl LinkStateTable {
     pub fn new() -> Self {
         let (scheduler, delay_task_sender) = LinkResumeScheduler::run();
+        let links = Arc::new(DashMap::new());
+        let tombstones = Arc::new(Tombstones::new());
         LinkStateTable {
-            links: Arc::new(DashMap::new()),
+            _gc: LinkGc::run(links.clone(), tombstones.clone()),
+            _metrics: MetricRefresher::run(links.clone()),
+            reachability: Arc::new(Reachability::new(links.clone())),
+            links,
+            tombstones,
             _scheduler: scheduler,
             delay_task_sender,
+            resumes: Arc::new(DashMap::new()),
         }
     }
+
+    /// 订阅主机可达性的变化
+    pub fn on_link_event(&self) -> broadcast::Receiver<LinkEvent> {
+        self.reachability.events.subscribe()
+    }
     // 仅仅在不存在时才插入
+    // 处于隔离期的 host 不接受新的链路，避免过期的发现报文把刚移除的 bond 加回来
     pub fn update(&self, host_id: HostId, local: &EndPoint, remote: &EndPoint) {
+        if let Some(buried) = self.tombstones.get(&host_id)
+            && buried.elapsed() < TOMBSTONE_QUARANTINE
+        {
+            debug!("Reject link {local} -> {remote} of {host_id} in quarantine");
+            return;
+        }
+        // 重新发现一条失效链路说明它很可能已恢复，提前唤醒恢复任务
+        self.revive(&host_id, local, remote);
         self.links
-            .entry(host_id)
+            .entry(host_id.clone())
             .and_modify(|bond| {
                 bond.update(*local, *remote);
             })
             .or_insert_with(|| Bond::new(local, remote));
+        self.reachability.check(&host_id);
+    }
+
+    /// 移除主机的全部链路并取消其未到期的恢复任务，返回是否存在该主机
+    pub fn remove_host(&self, host_id: &HostId) -> bool {
+        self.resumes.retain(|(host, ..), handle| {
+            let keep = host != host_id;
+            if !keep {
+                handle.cancel();
+            }
+            keep
+        });
+        let removed = self.links.remove(host_id).is_some();
+        if removed {
+            self.tombstones.insert(host_id.clone(), Instant::now());
+            self.reachability.check(host_id);
+        }
+        removed
+    }
+
+    /// 本地接口消失时移除经过它的所有链路，返回移除的链路数
+    ///
+    /// 不立碑，接口恢复后重新发现即可加回
+    pub fn drop_local(&self, local: &EndPoint) -> usize {
+        self.resumes.retain(|(_, addr, _), handle| {
+            let keep = addr != local;
+            if !keep {
+                handle.cancel();
+            }
+            keep
+        });
+        let mut dropped = 0;
+        let mut affected = Vec::new();
+        self.links.retain(|host, bond| {
+            let gone = bond
+                .links
+                .iter()
+                .filter(|link| link.local_remote_addr().0 == *local)
+                .cloned()
+                .collect::<Vec<_>>();
+            for link in &gone {
+                bond.remove(link);
+            }
+            if !gone.is_empty() {
+                affected.push(host.clone());
+            }
+            dropped += gone.len();
+            !bond.links.is_empty()
+        });
+        for host in &affected {
+            self.reachability.check(host);
+        }
+        dropped
+    }
+
+    /// 立即恢复一条等待中的失效链路，没有待恢复任务时返回 false
+    pub fn revive(&self, host_id: &HostId, local: &EndPoint, remote: &EndPoint) -> bool {
+        match self.resumes.remove(&(host_id.clone(), *local, *remote)) {
+            Some((_, handle)) => handle.reschedule(Duration::ZERO),
+            None => false,
+        }
+    }
+
+    /// 主动探测判定链路失效，与发送失败走同一套恢复流程
+    pub fn retire(&self, host_id: &HostId, link: Arc<LinkState>) -> Result<(), LinkResumeTaskError> {
+        retire(
+            &self.links,
+            &self.tombstones,
+            &self.delay_task_sender,
+            &self.resumes,
+            &self.reachability,
+            host_id.clone(),
+            link,
+        )
+    }
+
+    /// 所有直连链路，包括失效的，中继链路的远端是中继本身，不在此探测
+    pub fn probe_targets(&self) -> Vec<(HostId, Arc<LinkState>)> {
+        self.links
+            .iter()
+            .flat_map(|bond| {
+                let host = bond.key().clone();
+                bond.links
+                    .iter()
+                    .filter(|link| !link.is_relayed())
+                    .map(|link| (host.clone(), link.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// 探测更新了 RTT 与丢包后重建权重缓存
+    pub fn rebuild_weights(&self) {
+        for mut bond in self.links.iter_mut() {
+            bond.rebuild_weights();
+        }
+    }
+
+    /// 按新的健康证据调整该主机所有待恢复链路的延迟，返回调整的任务数
+    pub fn reschedule(&self, host_id: &HostId, delay: Duration) -> usize {
+        self.resumes
+            .iter()
+            .filter(|entry| entry.key().0 == *host_id)
+            .filter(|entry| entry.value().reschedule(delay))
+            .count()
+    }
+    /// 借用到中继 `via` 的直连链路，为 `host_id` 建立转发路径
+    ///
+    /// 返回新增的链路数，到中继没有直连时为 0
+    pub fn add_relay(&self, host_id: HostId, via: &HostId) -> usize {
+        if host_id == *via {
+            return 0;
+        }
+        let paths = match self.links.get(via) {
+            Some(bond) => bond
+                .links
+                .iter()
+                .filter(|link| !link.is_relayed())
+                .map(|link| link.local_remote_addr())
+                .collect::<Vec<_>>(),
+            None => return 0,
+        };
+        let mut added = 0;
+        for (local, remote) in paths {
+            match self.links.entry(host_id.clone()) {
+                Entry::Occupied(mut bond) => {
+                    added += bond.get_mut().update_relay(local, remote, via.clone()) as usize;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Bond::relayed(&local, &remote, via.clone()));
+                    added += 1;
+                }
+            }
+        }
+        self.reachability.check(&host_id);
+        added
+    }
+
+    /// 已知主机数，发现广播据此判断网络是否安静
+    pub fn host_count(&self) -> usize {
+        self.links.len()
+    }
+
+    /// 至少有一条健康链路，直连或中继均可
+    pub fn is_reachable(&self, host_id: &HostId) -> bool {
+        self.reachability.healthy(host_id)
+    }
+
+    pub fn has_direct(&self, host_id: &HostId) -> bool {
+        self.links.get(host_id).is_some_and(|bond| bond.has_direct())
+    }
+
+    /// 发往该主机的一块数据的最大长度，取所有健康链路中最小的，发到哪条链路上都不会分片
+    pub fn max_payload(&self, host_id: &HostId) -> usize {
+        self.links
+            .get(host_id)
+            .and_then(|bond| {
+                bond.links
+                    .iter()
+                    .filter(|link| link.is_healthy.load(Ordering::Relaxed))
+                    .map(|link| link.mtu.max_payload())
+                    .min()
+            })
+            .unwrap_or_else(|| PathMtu::default().max_payload())
+    }
+
+    /// 按对端地址查找直连链路，用于把链路级反馈归到具体链路上
+    pub fn direct_link(&self, host_id: &HostId, remote: &EndPoint) -> Option<Arc<LinkState>> {
+        self.links
+            .get(host_id)?
+            .links
+            .iter()
+            .find(|link| !link.is_relayed() && link.addr_remote == *remote)
+            .cloned()
+    }
+
+    /// 按对端地址反查主机，只看直连链路
+    pub fn host_at(&self, remote: &EndPoint) -> Option<HostId> {
+        self.links
+            .iter()
+            .find(|bond| {
+                bond.links
+                    .iter()
+                    .any(|link| !link.is_relayed() && link.addr_remote == *remote)
+            })
+            .map(|bond| bond.key().clone())
+    }
+
+    /// 直连全部失效且尚无中继路径的主机
+    pub fn stranded(&self) -> Vec<HostId> {
+        self.links
+            .iter()
+            .filter(|bond| !bond.has_direct() && !bond.links.iter().any(|l| l.is_relayed()))
+            .map(|bond| bond.key().clone())
+            .collect()
     }
+
+    /// 打洞成功后建立链路并记录对端反射地址，首次学到时返回 true
+    pub fn learn_reflexive(&self, host_id: HostId, local: &EndPoint, reflexive: &EndPoint) -> bool {
+        self.update(host_id.clone(), local, reflexive);
+        self.links
+            .get_mut(&host_id)
+            .is_some_and(|mut bond| bond.learn_reflexive(*reflexive))
+    }
+
+    pub fn knows_reflexive(&self, host_id: &HostId, reflexive: &EndPoint) -> bool {
+        self.links
+            .get(host_id)
+            .is_some_and(|bond| bond.reflexive().any(|ep| ep == reflexive))
+    }
+
     //metric 加权
-    // todo 重写
     /// 如果返回的链路不能用，那就调用solution，然后再重新申请一条
     pub fn assign(&self, host_id: &HostId) -> Result<AssignedLink, LinkError> {
-        let bond = self
+        // 只在持有分片读锁期间挑选，避免克隆整个 bond
+        let selected_link = self
+            .links
+            .get(host_id)
+            .ok_or(LinkError::BondNotFound)?
+            .pick()
+            .ok_or(LinkError::LinksNotFound)?;
+        Ok(self.hand_out(host_id, selected_link))
+    }
+
+    /// 条带化传输使用：一次性分配该主机所有健康链路，按权重从高到低排列
+    pub fn assign_all(&self, host_id: &HostId) -> Result<Vec<(AssignedLink, Weight)>, LinkError> {
+        let mut healthy = self
             .links
             .get(host_id)
             .ok_or(LinkError::BondNotFound)?
-            .clone();
-        let (candidates, total_weight) = bond
             .links
             .iter()
             .filter(|link| link.is_healthy.load(Ordering::Relaxed))
-            .fold(
-                (Vec::with_capacity(bond.links.len()), 0usize),
-                |(mut candidates, total_weight), link| {
-                    candidates.push(link);
-                    (candidates, total_weight.saturating_add(link.weight()))
-                },
-            );
-        // 提前处理无候选情况
-        if candidates.is_empty() || total_weight == 0 {
+            .cloned()
+            .collect::<Vec<_>>();
+        if healthy.is_empty() {
             return Err(LinkError::LinksNotFound);
         }
-        let selected = {
-            let mut rng = rand::rng();
-            rng.random_range(0..total_weight)
+        healthy.sort_by_key(|link| std::cmp::Reverse(link.weight()));
+        Ok(healthy
+            .into_iter()
+            .map(|link| {
+                let weight = link.weight();
+                (self.hand_out(host_id, link), weight)
+            })
+            .collect())
+    }
+
+    /// 握手失败等会话层故障时，将该主机的所有链路标记为不健康并安排恢复
+    ///
+    /// 返回被标记的链路数
+    pub fn mark_unhealthy(&self, host_id: &HostId) -> usize {
+        let Some(bond) = self.links.get(host_id) else {
+            return 0;
         };
-        // 使用二分查找优化权重选择 (O(log n))
-        let weight_distributes = candidates
+        let links = bond
+            .links
             .iter()
-            .scan(0usize, |acc, link| {
-                *acc += link.weight();
-                Some(*acc)
-            })
-            .collect::<Vec<usize>>();
-        let selected_index = weight_distributes
-            .binary_search_by(|probe| probe.cmp(&selected))
-            .unwrap_or_else(|i| i);
-        let selected_link = candidates[selected_index].clone();
+            .filter(|link| link.is_healthy.load(Ordering::Acquire))
+            .cloned()
+            .collect::<Vec<_>>();
+        drop(bond);
+        for link in &links {
+            // 失败次数耗尽的链路交给 gc 回收
+            let (local, remote) = link.local_remote_addr();
+            if let Some(task) = link.clone().deacitve()
+                && let Err(err) = schedule_resume(
+                    &self.delay_task_sender,
+                    &self.resumes,
+                    (host_id.clone(), local, remote),
+                    self.reachability.wrap(host_id.clone(), task),
+                )
+            {
+                debug!("Failed to schedule link resume: {err}");
+            }
+        }
+        self.reachability.check(host_id);
+        links.len()
+    }
+
+    fn hand_out(&self, host_id: &HostId, selected_link: Arc<LinkState>) -> AssignedLink {
         let (addr_local, addr_remote) = selected_link.local_remote_addr();
         // 以分配时间为准
         selected_link.update_usage();
@@ -86,38 +479,70 @@ impl LinkStateTable {
             let selected_link = Arc::downgrade(&selected_link);
             let host_id = host_id.clone();
             let links = self.links.clone();
+            let tombstones = self.tombstones.clone();
             let delay_task_sender = self.delay_task_sender.clone();
+            let resumes = self.resumes.clone();
+            let reachability = self.reachability.clone();
             //  最重要的引用保存在表中，这里也会持有一份，此函数调用之后返回的结果不包含强引用
             // 很显然它可能会被很多线程同时调用，因为可能会派发相同的链路
             Box::new(move || {
                 let selected_link = selected_link
                     .upgrade()
                     .ok_or(LinkResumeTaskError::LinkRefInvalid)?;
-                if let Some(task) = selected_link.clone().deacitve() {
-                    delay_task_sender.try_send(task)?;
-                    Ok(())
-                }
-                // 返回none代表没必要延迟了
-                // todo 持有锁可能会造成死锁
-                else {
-                    let need_remove = {
-                        if let Some(mut entry) = links.get_mut(&host_id) {
-                            entry.links.swap_remove(&selected_link);
-                            entry.links.is_empty()
-                        } else {
-                            false
-                        }
-                    };
-                    if need_remove {
-                        links.remove(&host_id); // 此时可以安全获取锁
-                    }
-                    Ok(())
-                }
+                retire(
+                    &links,
+                    &tombstones,
+                    &delay_task_sender,
+                    &resumes,
+                    &reachability,
+                    host_id,
+                    selected_link,
+                )
             })
         };
 
-        Ok(AssignedLink::new(addr_local, addr_remote, solve))
+        AssignedLink::new(
+            addr_local,
+            addr_remote,
+            selected_link.via.clone(),
+            Arc::downgrade(&selected_link),
+            solve,
+        )
     }
+
+    /// 供状态展示使用的只读快照
+    pub fn snapshot(&self) -> Vec<LinkSnapshot> {
+        self.links
+            .iter()
+            .flat_map(|bond| {
+                let host = bond.key().clone();
+                bond.links
+                    .iter()
+                    .map(|link| LinkSnapshot {
+                        host: host.clone(),
+                        local: link.addr_local,
+                        remote: link.addr_remote,
+                        metric: link.metric(),
+                        healthy: link.is_healthy.load(Ordering::Acquire),
+                        srtt: link.rtt.srtt(),
+                        via: link.via.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkSnapshot {
+    pub host: HostId,
+    pub local: EndPoint,
+    pub remote: EndPoint,
+    pub metric: Metric,
+    pub healthy: bool,
+    pub srtt: Option<Duration>,
+    /// 经由该主机转发
+    pub via: Option<HostId>,
 }
 
 #[cfg(test)]
@@ -125,7 +550,7 @@ mod tests {
     use super::*;
     use crate::addr::{mock_endpoint_lan, mock_endpoint_wan};
     use anyhow::Result;
-    use tokio::{task::yield_now, time::Duration};
+    use tokio::task::yield_now;
 
     // 测试update方法
     #[tokio::test(start_paused = true)]
@@ -246,6 +671,37 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn reachability_events() -> Result<()> {
+        let table = LinkStateTable::new();
+        let mut events = table.on_link_event();
+        let host = HostId::random();
+        let ep_local = mock_endpoint_lan();
+        let (ep_remote1, ep_remote2) = (mock_endpoint_lan(), mock_endpoint_lan());
+        table.update(host.clone(), &ep_local, &ep_remote1);
+        table.update(host.clone(), &ep_local, &ep_remote2);
+
+        // 还剩一条健康链路时不通报
+        table.assign(&host)?.solve()?;
+        assert!(events.try_recv().is_err());
+        table.assign(&host)?.solve()?;
+        assert_eq!(events.try_recv()?, LinkEvent::HostUnreachable(host.clone()));
+
+        // 恢复任务到期，链路重新健康
+        yield_now().await;
+        tokio::time::advance(Duration::from_secs(10)).await;
+        yield_now().await;
+        assert_eq!(events.try_recv()?, LinkEvent::HostReachable(host.clone()));
+        assert!(events.try_recv().is_err());
+
+        // 接口消失同样视为不可达，重新发现后恢复
+        table.drop_local(&ep_local);
+        assert_eq!(events.try_recv()?, LinkEvent::HostUnreachable(host.clone()));
+        table.update(host.clone(), &ep_local, &ep_remote1);
+        assert_eq!(events.try_recv()?, LinkEvent::HostReachable(host));
+        Ok(())
+    }
+
     #[tokio::test(start_paused = true)]
     async fn link_eviction() -> Result<()> {
         let table = LinkStateTable::new();
@@ -272,4 +728,46 @@ mod tests {
         assert!(matches!(l, Err(LinkError::BondNotFound)));
         Ok(())
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn tombstone_quarantine() -> Result<()> {
+        let table = LinkStateTable::new();
+        let host = HostId::random();
+        let ep_local = mock_endpoint_lan();
+        let ep_remote = mock_endpoint_lan();
+        table.update(host.clone(), &ep_local, &ep_remote);
+
+        // 连续失败直到 bond 被移除
+        for _ in 0..3 {
+            table.assign(&host)?.solve()?;
+            yield_now().await;
+            tokio::time::advance(Duration::from_mins(2)).await;
+            yield_now().await;
+        }
+        table.assign(&host)?.solve()?;
+        assert!(table.links.get(&host).is_none());
+        assert!(table.tombstones.contains_key(&host));
+
+        // 隔离期内的重新发现被拒绝
+        table.update(host.clone(), &ep_local, &ep_remote);
+        assert!(table.links.get(&host).is_none());
+
+        tokio::time::advance(TOMBSTONE_QUARANTINE).await;
+        table.update(host.clone(), &ep_local, &ep_remote);
+        assert!(table.links.get(&host).is_some());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gc_prunes_expired_tombstones() {
+        let table = LinkStateTable::new();
+        let host = HostId::random();
+        table.tombstones.insert(host.clone(), Instant::now());
+        LinkGc::collect(&table.links, &table.tombstones);
+        assert!(table.tombstones.contains_key(&host));
+
+        tokio::time::advance(TOMBSTONE_QUARANTINE).await;
+        LinkGc::collect(&table.links, &table.tombstones);
+        assert!(!table.tombstones.contains_key(&host));
+    }
 }
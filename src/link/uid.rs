@@ -1,50 +1,119 @@
 use bincode::{Decode, Encode};
-use nanoid::nanoid;
-use std::{
-    fmt::Display,
-    ops::{Deref, Not},
-    str::FromStr,
-};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{fmt::Display, str::FromStr};
 
 #[derive(Debug, thiserror::Error)]
 pub enum UidError {
     #[error("Invalid Uid: {0}")]
     Invalid(String),
+    #[error("Checksum mismatch in Uid: {0}")]
+    Checksum(String),
 }
 
+/// 小写 RFC 4648 base32，不含容易混淆的 0、1、8、9
+const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// 128 位随机标识
+///
+/// 文本形式为 16 字节加 4 字节 blake3 校验的 base32，恰好 32 个字符；
+/// 线上只传 16 字节原文
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Encode, Decode)]
-pub struct Uid(String);
+pub struct Uid([u8; 16]);
 
 impl Uid {
-    const ID_LEN: usize = 32;
+    const BYTES: usize = 16;
+    const CHECKSUM: usize = 4;
+    const ID_LEN: usize = (Self::BYTES + Self::CHECKSUM) * 8 / 5;
+
     pub fn random() -> Self {
-        #[allow(unused_braces)]
-        Self(nanoid!({ Self::ID_LEN }))
+        Self(rand::random())
+    }
+
+    pub const fn from_bytes(bytes: [u8; Self::BYTES]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; Self::BYTES] {
+        &self.0
+    }
+
+    fn checksum(bytes: &[u8; Self::BYTES]) -> [u8; Self::CHECKSUM] {
+        let hash = blake3::hash(bytes);
+        hash.as_bytes()[..Self::CHECKSUM].try_into().unwrap()
+    }
+
+    /// 旧版本配置中的 32 位 nanoid，按内容派生出固定的标识
+    pub fn from_legacy(s: &str) -> Result<Self, UidError> {
+        let legacy =
+            s.len() == Self::ID_LEN && s.chars().all(|c| nanoid::alphabet::SAFE.contains(&c));
+        if !legacy {
+            return Err(UidError::Invalid(s.to_string()));
+        }
+        let hash = blake3::hash(s.as_bytes());
+        Ok(Self(hash.as_bytes()[..Self::BYTES].try_into().unwrap()))
     }
 }
 
 impl FromStr for Uid {
     type Err = UidError;
 
+    /// 不区分大小写，便于口头或手抄传递
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != Self::ID_LEN || s.chars().all(|c| nanoid::alphabet::SAFE.contains(&c)).not() {
-            return Err(UidError::Invalid(s.to_string()));
+        let invalid = || UidError::Invalid(s.to_string());
+        if s.len() != Self::ID_LEN {
+            return Err(invalid());
+        }
+        let mut raw = [0u8; Self::BYTES + Self::CHECKSUM];
+        let (mut acc, mut bits, mut at) = (0u64, 0, 0);
+        for c in s.bytes() {
+            let value = ALPHABET
+                .iter()
+                .position(|&a| a == c.to_ascii_lowercase())
+                .ok_or_else(invalid)?;
+            acc = (acc << 5) | value as u64;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                raw[at] = (acc >> bits) as u8;
+                at += 1;
+            }
         }
-        Ok(Uid(s.to_string()))
+        let (bytes, checksum) = raw.split_at(Self::BYTES);
+        let bytes: [u8; Self::BYTES] = bytes.try_into().unwrap();
+        if Self::checksum(&bytes) != checksum {
+            return Err(UidError::Checksum(s.to_string()));
+        }
+        Ok(Self(bytes))
     }
 }
 
-impl Deref for Uid {
-    type Target = String;
+impl Display for Uid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut text = String::with_capacity(Self::ID_LEN);
+        let (mut acc, mut bits) = (0u64, 0);
+        for byte in self.0.iter().chain(&Self::checksum(&self.0)) {
+            acc = (acc << 8) | *byte as u64;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                text.push(ALPHABET[((acc >> bits) & 0x1f) as usize] as char);
+            }
+        }
+        f.write_str(&text)
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl Serialize for Uid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
-impl Display for Uid {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+impl<'de> Deserialize<'de> for Uid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
     }
 }
 
@@ -54,13 +123,14 @@ mod tests {
     #[test]
     fn generate() {
         let uid = Uid::random();
-        assert_eq!(uid.len(), Uid::ID_LEN);
+        assert_eq!(uid.to_string().len(), Uid::ID_LEN);
     }
 
     #[test]
     fn valid() {
-        let uid = Uid::from_str(Uid::random().as_str());
-        assert!(uid.is_ok());
+        let uid = Uid::random();
+        assert_eq!(Uid::from_str(&uid.to_string()).unwrap(), uid);
+        assert_eq!(Uid::from_str(&uid.to_string().to_uppercase()).unwrap(), uid);
     }
 
     #[test]
@@ -74,4 +144,30 @@ mod tests {
     fn str_with_invalid_char_into_uid() {
         Uid::from_str(" ").unwrap();
     }
+
+    #[test]
+    fn checksum_catches_typo() {
+        let text = Uid::random().to_string();
+        let first = if text.starts_with('a') { "b" } else { "a" };
+        let typo = format!("{first}{}", &text[1..]);
+        assert!(matches!(Uid::from_str(&typo), Err(UidError::Checksum(_))));
+    }
+
+    #[test]
+    fn legacy_and_serde() {
+        let legacy = "V1StGXR8_Z5jdHi6B-myTV1StGXR8_Z5";
+        let uid = Uid::from_legacy(legacy).unwrap();
+        assert_eq!(Uid::from_legacy(legacy).unwrap(), uid);
+        assert!(Uid::from_str(legacy).is_err());
+
+        let json = serde_json::to_string(&uid).unwrap();
+        assert_eq!(json, format!("\"{uid}\""));
+        assert_eq!(serde_json::from_str::<Uid>(&json).unwrap(), uid);
+
+        let config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&uid, config).unwrap();
+        assert_eq!(encoded.len(), 16);
+        let (decoded, _) = bincode::decode_from_slice::<Uid, _>(&encoded, config).unwrap();
+        assert_eq!(decoded, uid);
+    }
 }
@@ -0,0 +1,7 @@
+mod rate;
+mod schedule;
+mod token;
+
+pub use rate::*;
+pub use schedule::*;
+pub use token::*;
@@ -0,0 +1,221 @@
+use super::{TransferScheduler, transfer_scheduler};
+use crate::config::{ConfigItem, ConfigManager};
+use std::{
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    task::AbortHandle,
+    time::{Instant, sleep},
+};
+use tracing::info;
+
+/// 令牌桶限速器，速率为 0 表示不限速
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: AtomicU64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// 至少允许这么多突发，避免小速率下单个数据块永远凑不够令牌
+    const MIN_BURST: f64 = 64.0 * 1024.0;
+
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            state: Mutex::new(BucketState {
+                tokens: Self::burst(rate),
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0)
+    }
+
+    /// 突发上限为一秒的流量
+    fn burst(rate: u64) -> f64 {
+        (rate as f64).max(Self::MIN_BURST)
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    /// 运行时调整，已欠下的令牌按新速率偿还
+    pub fn set_rate(&self, rate: u64) {
+        if self.rate.swap(rate, Ordering::Relaxed) != rate {
+            info!("Rate limit set to {rate} B/s");
+        }
+    }
+
+    /// 取走 `bytes` 个令牌，不足时睡眠到补足为止
+    ///
+    /// 允许透支，这样大于突发上限的请求也能按平均速率通过
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.rate();
+        if rate == 0 {
+            return;
+        }
+        let deficit = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(state.last).as_secs_f64() * rate as f64;
+            state.tokens = (state.tokens + refill).min(Self::burst(rate)) - bytes as f64;
+            state.last = now;
+            state.tokens
+        };
+        if deficit < 0.0 {
+            sleep(Duration::from_secs_f64(-deficit / rate as f64)).await;
+        }
+    }
+}
+
+/// 全局上传限速，作用在出站 sink 上
+pub fn upload_limiter() -> &'static TokenBucket {
+    static UPLOAD_LIMITER: OnceLock<TokenBucket> = OnceLock::new();
+    UPLOAD_LIMITER.get_or_init(TokenBucket::unlimited)
+}
+
+/// 全局下载限速，作用在下载任务的写盘循环上
+pub fn download_limiter() -> &'static TokenBucket {
+    static DOWNLOAD_LIMITER: OnceLock<TokenBucket> = OnceLock::new();
+    DOWNLOAD_LIMITER.get_or_init(TokenBucket::unlimited)
+}
+
+/// 任务循环使用的限速组合：全局桶、调度器分配的份额，加上可选的单任务桶
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    global: Option<&'static TokenBucket>,
+    share: Option<Arc<TokenBucket>>,
+    task: Arc<TokenBucket>,
+}
+
+impl Throttle {
+    /// 分享任务只做单任务限速，全局上传限速已在出站路径上生效
+    pub fn upload(task_rate: u64) -> Self {
+        Self {
+            global: None,
+            share: None,
+            task: Arc::new(TokenBucket::new(task_rate)),
+        }
+    }
+
+    pub fn download(task_rate: u64) -> Self {
+        Self {
+            global: Some(download_limiter()),
+            share: None,
+            task: Arc::new(TokenBucket::new(task_rate)),
+        }
+    }
+
+    /// 附加调度器分配的带宽份额
+    pub fn with_share(mut self, share: Arc<TokenBucket>) -> Self {
+        self.share = Some(share);
+        self
+    }
+
+    /// 用于运行时调整单任务速率
+    pub fn task_bucket(&self) -> Arc<TokenBucket> {
+        self.task.clone()
+    }
+
+    pub async fn acquire(&self, bytes: usize) {
+        if let Some(global) = self.global {
+            global.acquire(bytes).await;
+        }
+        if let Some(share) = &self.share {
+            share.acquire(bytes).await;
+        }
+        self.task.acquire(bytes).await;
+    }
+}
+
+/// 监听配置变化，实时调整全局限速与并发传输数
+pub struct RateLimitWatcher {
+    abort: AbortHandle,
+}
+
+impl RateLimitWatcher {
+    pub fn run(cfg: &'static ConfigManager) -> Self {
+        let mut changed = cfg.subscribe();
+        let abort = tokio::spawn(async move {
+            loop {
+                upload_limiter().set_rate(parse_rate(cfg, ConfigItem::MaxUploadBps).await);
+                download_limiter().set_rate(parse_rate(cfg, ConfigItem::MaxDownloadBps).await);
+                let limit = cfg
+                    .get_typed(ConfigItem::MaxActiveTransfers)
+                    .await
+                    .unwrap_or(TransferScheduler::DEFAULT_LIMIT);
+                transfer_scheduler().set_limit(limit);
+                // 全局限速变化后重新分配份额
+                transfer_scheduler().rebalance();
+                if changed.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for RateLimitWatcher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Rate limit watcher has been dropped");
+    }
+}
+
+async fn parse_rate(cfg: &ConfigManager, item: ConfigItem) -> u64 {
+    cfg.get(item).await.parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn unlimited_never_waits() {
+        let bucket = TokenBucket::unlimited();
+        let start = Instant::now();
+        bucket.acquire(usize::MAX).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn average_rate() {
+        const RATE: u64 = 1024 * 1024;
+        let bucket = TokenBucket::new(RATE);
+        let start = Instant::now();
+        // 先用掉突发额度，之后 4MiB 约需 4 秒
+        bucket.acquire(RATE as usize).await;
+        for _ in 0..64 {
+            bucket.acquire(64 * 1024).await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(3900), "{elapsed:?}");
+        assert!(elapsed <= Duration::from_millis(4100), "{elapsed:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn adjust_at_runtime() {
+        let bucket = TokenBucket::new(64 * 1024);
+        bucket.acquire(64 * 1024).await;
+        bucket.set_rate(0);
+        let start = Instant::now();
+        bucket.acquire(1024 * 1024).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,300 @@
+use super::{TokenBucket, download_limiter};
+use crate::task::FileHash;
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info};
+
+/// 传输的优先级，决定限速时分到的带宽份额
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum TransferPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl TransferPriority {
+    /// 份额的权重，高优先级是普通的两倍、低优先级的四倍
+    pub fn weight(self) -> u64 {
+        match self {
+            TransferPriority::Low => 1,
+            TransferPriority::Normal => 2,
+            TransferPriority::High => 4,
+        }
+    }
+}
+
+impl FromStr for TransferPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(TransferPriority::Low),
+            "normal" => Ok(TransferPriority::Normal),
+            "high" => Ok(TransferPriority::High),
+            other => Err(format!(
+                "unknown priority: {other}, expected high, normal or low"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TransferPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferPriority::Low => f.write_str("low"),
+            TransferPriority::Normal => f.write_str("normal"),
+            TransferPriority::High => f.write_str("high"),
+        }
+    }
+}
+
+/// 全局传输调度：限制同时进行的传输数，其余按到达顺序排队
+///
+/// 设置了全局下载限速时，活跃传输按优先级的权重分配限速，大文件不会把小文件饿死；
+/// 未限速时不做整形，由网络自行分配
+#[derive(Debug)]
+pub struct TransferScheduler {
+    slots: Arc<Semaphore>,
+    limit: AtomicUsize,
+    /// 调小上限时仍被占用、归还后需要销毁的名额
+    shrink: AtomicUsize,
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, (FileHash, Arc<TokenBucket>)>>,
+    /// 按文件设置的优先级，可以在排队前设置，传输结束时清除
+    priorities: Mutex<HashMap<FileHash, TransferPriority>>,
+}
+
+pub fn transfer_scheduler() -> &'static TransferScheduler {
+    static TRANSFER_SCHEDULER: OnceLock<TransferScheduler> = OnceLock::new();
+    TRANSFER_SCHEDULER.get_or_init(|| TransferScheduler::new(TransferScheduler::DEFAULT_LIMIT))
+}
+
+impl TransferScheduler {
+    pub const DEFAULT_LIMIT: usize = 4;
+
+    pub fn new(limit: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(limit)),
+            limit: AtomicUsize::new(limit),
+            shrink: AtomicUsize::new(0),
+            next_id: AtomicU64::new(0),
+            active: Mutex::new(HashMap::new()),
+            priorities: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    /// 运行时调整上限，调小时不打断已在进行的传输
+    pub fn set_limit(&self, limit: usize) {
+        let old = self.limit.swap(limit, Ordering::Relaxed);
+        if limit > old {
+            // 先抵消还没销毁的名额
+            let grow = limit - old;
+            let debt = self
+                .shrink
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |debt| {
+                    Some(debt - debt.min(grow))
+                })
+                .unwrap_or_default();
+            self.slots.add_permits(grow - debt.min(grow));
+        } else if limit < old {
+            let forgotten = self.slots.forget_permits(old - limit);
+            self.shrink
+                .fetch_add(old - limit - forgotten, Ordering::Relaxed);
+        }
+    }
+
+    pub fn priority(&self, file: FileHash) -> TransferPriority {
+        self.priorities
+            .lock()
+            .unwrap()
+            .get(&file)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 调整文件的优先级，进行中的传输立即按新的权重分配份额
+    pub fn set_priority(&self, file: FileHash, priority: TransferPriority) {
+        let old = self.priorities.lock().unwrap().insert(file, priority);
+        if old.unwrap_or_default() != priority {
+            info!("Priority of {file:016x} set to {priority}");
+            self.rebalance();
+        }
+    }
+
+    /// 等待空闲名额，返回的守卫在传输结束时释放名额
+    pub async fn admit(&'static self, file: FileHash) -> ActiveTransfer {
+        let permit = self.slots.clone().acquire_owned().await.unwrap();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let share = Arc::new(TokenBucket::unlimited());
+        self.active
+            .lock()
+            .unwrap()
+            .insert(id, (file, share.clone()));
+        self.rebalance();
+        ActiveTransfer {
+            id,
+            share,
+            permit: Some(permit),
+            scheduler: self,
+        }
+    }
+
+    /// 按当前全局限速、活跃传输及其优先级重新分配每个传输的份额
+    pub fn rebalance(&self) {
+        self.rebalance_with(download_limiter().rate());
+    }
+
+    fn rebalance_with(&self, rate: u64) {
+        let active = self.active.lock().unwrap();
+        let priorities = self.priorities.lock().unwrap();
+        let weight = |file: &FileHash| priorities.get(file).copied().unwrap_or_default().weight();
+        let total = active.values().map(|(file, _)| weight(file)).sum::<u64>();
+        for (file, bucket) in active.values() {
+            let share = match (rate, total) {
+                (0, _) | (_, 0) => 0,
+                (rate, total) => {
+                    (rate as u128 * weight(file) as u128 / total as u128).max(1) as u64
+                }
+            };
+            bucket.set_rate(share);
+        }
+        debug!(
+            "{} active transfers share {rate} B/s by priority",
+            active.len()
+        );
+    }
+
+    fn leave(&self, id: u64, permit: OwnedSemaphorePermit) {
+        let left = self.active.lock().unwrap().remove(&id);
+        if let Some((file, _)) = left {
+            self.priorities.lock().unwrap().remove(&file);
+        }
+        let shrunk = self
+            .shrink
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |debt| {
+                debt.checked_sub(1)
+            })
+            .is_ok();
+        match shrunk {
+            true => permit.forget(),
+            false => drop(permit),
+        }
+        self.rebalance();
+    }
+}
+
+/// 占用一个传输名额，持有期间按份额限速
+#[derive(Debug)]
+pub struct ActiveTransfer {
+    id: u64,
+    share: Arc<TokenBucket>,
+    permit: Option<OwnedSemaphorePermit>,
+    scheduler: &'static TransferScheduler,
+}
+
+impl ActiveTransfer {
+    /// 本传输的带宽份额，交给任务的 `Throttle`
+    pub fn share(&self) -> Arc<TokenBucket> {
+        self.share.clone()
+    }
+}
+
+impl Drop for ActiveTransfer {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            self.scheduler.leave(self.id, permit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn leaked(limit: usize) -> &'static TransferScheduler {
+        Box::leak(Box::new(TransferScheduler::new(limit)))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queue_beyond_limit() {
+        let scheduler = leaked(2);
+        let first = scheduler.admit(1).await;
+        let _second = scheduler.admit(2).await;
+        assert!(
+            timeout(Duration::from_secs(1), scheduler.admit(3))
+                .await
+                .is_err()
+        );
+        drop(first);
+        let _third = scheduler.admit(3).await;
+        assert_eq!(scheduler.active(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shrink_while_busy() {
+        let scheduler = leaked(2);
+        let first = scheduler.admit(1).await;
+        let second = scheduler.admit(2).await;
+        scheduler.set_limit(1);
+        drop(first);
+        // 归还的名额被销毁，仍然只有一个传输在进行
+        assert!(
+            timeout(Duration::from_secs(1), scheduler.admit(3))
+                .await
+                .is_err()
+        );
+        drop(second);
+        let _third = scheduler.admit(3).await;
+        scheduler.set_limit(2);
+        let _fourth = scheduler.admit(4).await;
+        assert_eq!(scheduler.active(), 2);
+    }
+
+    #[tokio::test]
+    async fn weight_by_priority() {
+        let scheduler = leaked(3);
+        // 排队之前就可以设置
+        scheduler.set_priority(1, TransferPriority::High);
+        let high = scheduler.admit(1).await;
+        let normal = scheduler.admit(2).await;
+        scheduler.rebalance_with(6000);
+        assert_eq!((high.share().rate(), normal.share().rate()), (4000, 2000));
+
+        // 运行中调整立即生效
+        scheduler.set_priority(2, TransferPriority::Low);
+        let low = scheduler.admit(3).await;
+        scheduler.set_priority(3, TransferPriority::Low);
+        scheduler.rebalance_with(6000);
+        assert_eq!(
+            (
+                high.share().rate(),
+                normal.share().rate(),
+                low.share().rate()
+            ),
+            (4000, 1000, 1000)
+        );
+        drop(high);
+        assert_eq!(scheduler.priority(1), TransferPriority::Normal);
+        assert_eq!("HIGH".parse(), Ok(TransferPriority::High));
+        assert!("urgent".parse::<TransferPriority>().is_err());
+    }
+}
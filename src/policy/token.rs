@@ -0,0 +1,222 @@
+use crate::{
+    audit::{AuditEvent, audit},
+    inbound::HostId,
+    task::FileHash,
+};
+use dashmap::DashMap;
+use nanoid::nanoid;
+use std::{fmt::Display, sync::OnceLock, time::Duration};
+use thiserror::Error;
+use tokio::{
+    task::AbortHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tracing::info;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TokenError {
+    #[error("token not found or already consumed")]
+    NotFound,
+    #[error("token has expired")]
+    Expired,
+    #[error("token does not grant access to this file or peer")]
+    Mismatch,
+}
+
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+pub struct TransferToken(String);
+
+impl TransferToken {
+    const TOKEN_LEN: usize = 24;
+
+    fn random() -> Self {
+        #[allow(unused_braces)]
+        Self(nanoid!({ Self::TOKEN_LEN }))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for TransferToken {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl Display for TransferToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// 令牌授权给谁
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenScope {
+    Anyone,
+    Peer(HostId),
+}
+
+#[derive(Debug)]
+struct Grant {
+    file: FileHash,
+    scope: TokenScope,
+    expires: Instant,
+}
+
+/// 一次性令牌：带外分享给对方，对方在 Fetch 中出示即可下载一次
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    grants: DashMap<TransferToken, Grant>,
+}
+
+pub fn token_store() -> &'static TokenStore {
+    static TOKEN_STORE: OnceLock<TokenStore> = OnceLock::new();
+    TOKEN_STORE.get_or_init(TokenStore::default)
+}
+
+impl TokenStore {
+    pub fn issue(&self, file: FileHash, scope: TokenScope, ttl: Duration) -> TransferToken {
+        let token = TransferToken::random();
+        self.grants.insert(
+            token.clone(),
+            Grant {
+                file,
+                scope,
+                expires: Instant::now() + ttl,
+            },
+        );
+        token
+    }
+
+    /// 校验并消费令牌，校验与删除在同一把分片锁内完成
+    ///
+    /// 不匹配的请求不会消费令牌，过期的令牌会被顺便清理
+    pub fn redeem(
+        &self,
+        token: &TransferToken,
+        file: FileHash,
+        peer: &HostId,
+    ) -> Result<(), TokenError> {
+        let mut verdict = Err(TokenError::NotFound);
+        self.grants.remove_if(token, |_, grant| {
+            verdict = if grant.expires <= Instant::now() {
+                Err(TokenError::Expired)
+            } else if grant.file != file
+                || matches!(&grant.scope, TokenScope::Peer(host) if host != peer)
+            {
+                Err(TokenError::Mismatch)
+            } else {
+                Ok(())
+            };
+            !matches!(verdict, Err(TokenError::Mismatch))
+        });
+        verdict
+    }
+
+    /// 处理 Fetch 请求的授权，拒绝时写入审计日志
+    pub fn authorize_fetch(
+        &self,
+        peer: &HostId,
+        file: FileHash,
+        token: Option<&TransferToken>,
+    ) -> Result<(), TokenError> {
+        let result = token
+            .ok_or(TokenError::NotFound)
+            .and_then(|token| self.redeem(token, file, peer));
+        if let Err(err) = &result {
+            audit(AuditEvent::transfer_rejected(peer, err));
+        }
+        result
+    }
+
+    pub fn revoke(&self, token: &TransferToken) -> bool {
+        self.grants.remove(token).is_some()
+    }
+
+    /// 清理所有过期令牌
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.grants.retain(|_, grant| grant.expires > now);
+    }
+}
+
+/// 定期清理全局令牌表中的过期令牌
+pub struct TokenReaper {
+    abort: AbortHandle,
+}
+
+impl TokenReaper {
+    const INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn run() -> Self {
+        let abort = tokio::spawn(async move {
+            let mut tick = interval(Self::INTERVAL);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                token_store().prune();
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for TokenReaper {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Token reaper has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn redeem_once() {
+        let store = TokenStore::default();
+        let peer = HostId::random();
+        let token = store.issue(42, TokenScope::Anyone, Duration::from_secs(60));
+        assert_eq!(store.redeem(&token, 42, &peer), Ok(()));
+        assert_eq!(store.redeem(&token, 42, &peer), Err(TokenError::NotFound));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mismatch_keeps_token() {
+        let store = TokenStore::default();
+        let peer = HostId::random();
+        let token = store.issue(42, TokenScope::Peer(peer.clone()), Duration::from_secs(60));
+        assert_eq!(
+            store.redeem(&token, 42, &HostId::random()),
+            Err(TokenError::Mismatch)
+        );
+        assert_eq!(store.redeem(&token, 7, &peer), Err(TokenError::Mismatch));
+        assert_eq!(store.redeem(&token, 42, &peer), Ok(()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired() {
+        let store = TokenStore::default();
+        let token = store.issue(42, TokenScope::Anyone, Duration::from_secs(60));
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(
+            store.redeem(&token, 42, &HostId::random()),
+            Err(TokenError::Expired)
+        );
+        assert!(!store.revoke(&token));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn prune_expired() {
+        let store = TokenStore::default();
+        let short = store.issue(42, TokenScope::Anyone, Duration::from_secs(60));
+        let long = store.issue(42, TokenScope::Anyone, Duration::from_secs(600));
+        tokio::time::advance(Duration::from_secs(61)).await;
+        store.prune();
+        assert!(!store.revoke(&short));
+        assert!(store.revoke(&long));
+    }
+}
@@ -0,0 +1,181 @@
+use super::{Request, RpcError, format_hash, param, param_hash};
+use crate::{
+    Transfer,
+    inbound::HostId,
+    task::{FileHash, global_rates},
+    transfer::{IncomingTransfer, TransferProgress},
+};
+use camino::Utf8PathBuf;
+use dashmap::DashMap;
+use serde_json::{Value, json};
+
+/// 不指定 `limit` 时返回的历史条数
+const HISTORY_LIMIT: usize = 20;
+
+/// 门面只提供事件流，服务端自己记下最新的邀约与进度供查询
+#[derive(Debug, Default)]
+pub(super) struct Tracked {
+    pub(super) offers: DashMap<FileHash, IncomingTransfer>,
+    pub(super) progress: DashMap<FileHash, TransferProgress>,
+}
+
+impl Tracked {
+    fn progress_of(&self, hash: FileHash) -> Value {
+        self.progress
+            .get(&hash)
+            .map(|progress| {
+                json!({
+                    "done": progress.done,
+                    "total": progress.total,
+                    "rate": progress.rate,
+                    "eta_secs": progress.eta.map(|eta| eta.as_secs_f64()),
+                })
+            })
+            .unwrap_or(Value::Null)
+    }
+}
+
+/// 每个方法只是对应门面 API 的一次调用
+pub(super) async fn dispatch(
+    transfer: &Transfer,
+    tracked: &Tracked,
+    request: &Request,
+) -> Result<Value, RpcError> {
+    let params = &request.params;
+    match request.method.as_str() {
+        "peers" => Ok(transfer
+            .peers()
+            .into_iter()
+            .map(|peer| {
+                json!({
+                    "host": peer.host.to_string(),
+                    "host_name": peer.host_name,
+                    "listen_port": peer.listen_port,
+                    "version": peer.version.map(|version| version.to_string()),
+                    "capabilities": format!("{:?}", peer.capabilities),
+                    "handshake": format!("{:?}", peer.handshake),
+                    "endpoints": peer.endpoints.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                })
+            })
+            .collect()),
+        "status" => {
+            let status = transfer.status();
+            let queue = transfer.inbound_queue();
+            let rates = global_rates();
+            Ok(json!({
+                "host": transfer.local_id().to_string(),
+                "fingerprint": transfer.fingerprint().to_string(),
+                "peer_count": status.peer_count(),
+                "links": status.links.iter().map(|link| json!({
+                    "host": link.host.to_string(),
+                    "local": link.local.to_string(),
+                    "remote": link.remote.to_string(),
+                    "healthy": link.healthy,
+                    "srtt_ms": link.srtt.map(|srtt| srtt.as_secs_f64() * 1000.0),
+                    "via": link.via.as_ref().map(ToString::to_string),
+                })).collect::<Vec<_>>(),
+                "inbound_queue": { "depth": queue.depth, "capacity": queue.capacity },
+                "rate": { "download": rates.download(), "upload": rates.upload() },
+            }))
+        }
+        "transfers" => {
+            let shared = transfer
+                .shared_files()
+                .into_iter()
+                .map(|(hash, path)| {
+                    json!({
+                        "hash": format_hash(hash),
+                        "path": path,
+                        "progress": tracked.progress_of(hash),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let incoming = tracked
+                .offers
+                .iter()
+                .map(|offer| {
+                    json!({
+                        "hash": format_hash(offer.hash),
+                        "peer": offer.peer.to_string(),
+                        "file_name": offer.file_name,
+                        "size": offer.size,
+                        "progress": tracked.progress_of(offer.hash),
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(json!({ "shared": shared, "incoming": incoming }))
+        }
+        "progress" => Ok(tracked.progress_of(param_hash(params)?)),
+        "history" => {
+            let limit = params
+                .get("limit")
+                .and_then(Value::as_u64)
+                .unwrap_or(HISTORY_LIMIT as u64);
+            let history = transfer.history(limit as usize).map_err(RpcError::server)?;
+            serde_json::to_value(history).map_err(RpcError::server)
+        }
+        "send" => {
+            let path = param::<Utf8PathBuf>(params, "path")?;
+            let to = param::<HostId>(params, "to")?;
+            let hash = transfer
+                .send_file(&path, &to)
+                .await
+                .map_err(RpcError::server)?;
+            Ok(json!({ "hash": format_hash(hash) }))
+        }
+        "accept" => {
+            let offer = offer(tracked, params)?;
+            transfer.accept(&offer).await.map_err(RpcError::server)?;
+            Ok(Value::Null)
+        }
+        "decline" => {
+            let offer = offer(tracked, params)?;
+            let reason = params
+                .get("reason")
+                .and_then(Value::as_str)
+                .unwrap_or("declined");
+            transfer
+                .decline(&offer, reason)
+                .await
+                .map_err(RpcError::server)?;
+            tracked.offers.remove(&offer.hash);
+            Ok(Value::Null)
+        }
+        "prioritize" => {
+            let hash = param_hash(params)?;
+            transfer.set_priority(hash, param(params, "priority")?);
+            Ok(json!({ "priority": transfer.priority(hash).to_string() }))
+        }
+        "cancel" => {
+            transfer
+                .unshare(param_hash(params)?)
+                .map_err(RpcError::server)?;
+            Ok(Value::Null)
+        }
+        "connect" => {
+            transfer
+                .connect_wan(&param(params, "host")?)
+                .map_err(RpcError::server)?;
+            Ok(Value::Null)
+        }
+        "relay" => {
+            transfer
+                .relay_through(&param(params, "host")?, &param(params, "via")?)
+                .map_err(RpcError::server)?;
+            Ok(Value::Null)
+        }
+        method => Err(RpcError::new(
+            RpcError::METHOD_NOT_FOUND,
+            format!("unknown method {method:?}"),
+        )),
+    }
+}
+
+fn offer(tracked: &Tracked, params: &Value) -> Result<IncomingTransfer, RpcError> {
+    let hash = param_hash(params)?;
+    tracked
+        .offers
+        .get(&hash)
+        .map(|offer| offer.clone())
+        .ok_or_else(|| RpcError::invalid_params(format!("no pending offer {}", format_hash(hash))))
+}
@@ -0,0 +1,6 @@
+mod methods;
+mod protocol;
+mod server;
+
+pub use protocol::*;
+pub use server::*;
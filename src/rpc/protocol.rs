@@ -0,0 +1,136 @@
+use crate::task::FileHash;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{fmt::Display, str::FromStr};
+
+/// JSON-RPC 2.0 请求，每行一个
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// 没有 id 的是通知，不需要答复
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// 门面 API 调用失败
+    pub const SERVER_ERROR: i64 = -32000;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+
+    pub fn server(err: impl Display) -> Self {
+        Self::new(Self::SERVER_ERROR, err.to_string())
+    }
+}
+
+/// 解析失败时也尽量带回请求的 id
+pub fn parse_request(line: &str) -> Result<Request, (Value, RpcError)> {
+    let request = serde_json::from_str::<Request>(line).map_err(|err| {
+        (
+            Value::Null,
+            RpcError::new(RpcError::PARSE_ERROR, err.to_string()),
+        )
+    })?;
+    match request.jsonrpc == "2.0" {
+        true => Ok(request),
+        false => Err((
+            request.id.unwrap_or_default(),
+            RpcError::new(RpcError::INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+        )),
+    }
+}
+
+/// 序列化后的一行答复，不含换行
+pub fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code, "message": err.message },
+        }),
+    }
+    .to_string()
+}
+
+/// 按 `FromStr` 解析参数中的字符串字段
+pub fn param<T>(params: &Value, key: &str) -> Result<T, RpcError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    params
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params(format!("missing string param {key:?}")))?
+        .parse()
+        .map_err(|err: T::Err| RpcError::invalid_params(format!("invalid {key:?}: {err}")))
+}
+
+/// 文件哈希在接口中与日志一致，写成 16 位十六进制
+pub fn format_hash(hash: FileHash) -> String {
+    format!("{hash:016x}")
+}
+
+pub fn param_hash(params: &Value) -> Result<FileHash, RpcError> {
+    let raw = param::<String>(params, "hash")?;
+    FileHash::from_str_radix(&raw, 16)
+        .map_err(|err| RpcError::invalid_params(format!("invalid \"hash\": {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope() {
+        let request = parse_request(
+            r#"{"jsonrpc":"2.0","id":7,"method":"cancel","params":{"hash":"000000000000002a"}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(param_hash(&request.params), Ok(42));
+        assert_eq!(
+            param::<String>(&request.params, "path").unwrap_err().code,
+            RpcError::INVALID_PARAMS
+        );
+
+        let (id, err) =
+            parse_request(r#"{"jsonrpc":"1.0","id":"a","method":"peers"}"#).unwrap_err();
+        assert_eq!((id, err.code), (json!("a"), RpcError::INVALID_REQUEST));
+        assert_eq!(
+            parse_request("not json").unwrap_err().1.code,
+            RpcError::PARSE_ERROR
+        );
+
+        let reply: Value = serde_json::from_str(&response(
+            json!(1),
+            Err(RpcError::new(RpcError::METHOD_NOT_FOUND, "nope")),
+        ))
+        .unwrap();
+        assert_eq!(reply["error"]["code"], RpcError::METHOD_NOT_FOUND);
+        assert_eq!(format_hash(42), "000000000000002a");
+    }
+}
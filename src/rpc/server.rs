@@ -0,0 +1,93 @@
+use super::{methods::Tracked, methods::dispatch, parse_request, response};
+use crate::Transfer;
+use futures::StreamExt;
+use std::{io, net::SocketAddr, pin::pin, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::{AbortHandle, JoinSet},
+};
+use tracing::{debug, info};
+
+/// 本地控制接口，供 Electron/Tauri 等前端驱动守护进程
+///
+/// 每行一个 JSON-RPC 2.0 请求，答复同样一行一个。接口没有鉴权，只允许监听回环地址
+pub struct ControlServer {
+    abort: AbortHandle,
+}
+
+impl ControlServer {
+    pub async fn run(transfer: Arc<Transfer>, listen: SocketAddr) -> io::Result<Self> {
+        if !listen.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("control API must listen on loopback, not {listen}"),
+            ));
+        }
+        let listener = TcpListener::bind(listen).await?;
+        info!("Control API listening on {listen}");
+        let abort = tokio::spawn(async move {
+            let tracked = Arc::new(Tracked::default());
+            let mut incoming = pin!(transfer.subscribe_incoming());
+            let mut progress = pin!(transfer.progress());
+            let mut completions = pin!(transfer.completions());
+            // 服务端停止时随之中止所有连接
+            let mut clients = JoinSet::new();
+            loop {
+                tokio::select! {
+                    Some(offer) = incoming.next() => {
+                        tracked.offers.insert(offer.hash, offer);
+                    }
+                    Some(update) = progress.next() => {
+                        tracked.progress.insert(update.hash, update);
+                    }
+                    Some(done) = completions.next() => {
+                        tracked.offers.remove(&done.hash);
+                        tracked.progress.remove(&done.hash);
+                    }
+                    accepted = listener.accept() => {
+                        let Ok((stream, peer)) = accepted else { continue };
+                        let (transfer, tracked) = (transfer.clone(), tracked.clone());
+                        clients.spawn(async move {
+                            if let Err(err) = Self::serve(stream, &transfer, &tracked).await {
+                                debug!("Control connection from {peer} closed: {err}");
+                            }
+                        });
+                    }
+                    Some(_) = clients.join_next(), if !clients.is_empty() => {}
+                }
+            }
+        })
+        .abort_handle();
+        Ok(Self { abort })
+    }
+
+    async fn serve(stream: TcpStream, transfer: &Transfer, tracked: &Tracked) -> io::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match parse_request(&line) {
+                Ok(request) => {
+                    let result = dispatch(transfer, tracked, &request).await;
+                    request.id.map(|id| response(id, result))
+                }
+                Err((id, err)) => Some(response(id, Err(err))),
+            };
+            if let Some(mut reply) = reply {
+                reply.push('\n');
+                write.write_all(reply.as_bytes()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Control server has been dropped");
+    }
+}
@@ -0,0 +1,335 @@
+use super::Fingerprint;
+use crate::{
+    addr::{EndPoint, ScopedAddr},
+    inbound::{Capabilities, HostId, NicView, ProtocolVersion},
+};
+use bincode::{Decode, Encode};
+use indexmap::IndexSet;
+use std::sync::{OnceLock, RwLock};
+use thiserror::Error;
+
+/// 记住的本机反射地址数，超出时丢弃最早的
+const MAX_REFLEXIVE: usize = 8;
+
+/// 中继观察到的本机反射地址，经 NAT 或打洞连上的对端看到的是这些地址而不是网卡地址
+fn reflexive_addrs() -> &'static RwLock<IndexSet<ScopedAddr>> {
+    static REFLEXIVE_ADDRS: OnceLock<RwLock<IndexSet<ScopedAddr>>> = OnceLock::new();
+    REFLEXIVE_ADDRS.get_or_init(Default::default)
+}
+
+pub fn record_reflexive(endpoint: &EndPoint) {
+    let mut addrs = reflexive_addrs().write().unwrap();
+    if addrs.insert(*endpoint.scoped_addr()) && addrs.len() > MAX_REFLEXIVE {
+        addrs.shift_remove_index(0);
+    }
+}
+
+/// 对端在绑定中声明的观察地址可以是本机网卡地址，也可以是记下的反射地址
+pub fn observable_addrs() -> impl Iterator<Item = ScopedAddr> {
+    let reflexive: Vec<_> = reflexive_addrs().read().unwrap().iter().copied().collect();
+    NicView::default().chain(reflexive)
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BindingError {
+    #[error("malformed channel binding payload")]
+    Malformed,
+    #[error("channel binding was issued by {actual}, expected {expected}")]
+    SenderMismatch { expected: HostId, actual: HostId },
+    #[error("channel binding is addressed to {0}, not to us")]
+    ReceiverMismatch(HostId),
+    #[error("peer observed us at {0}, which is not a local address")]
+    AddrMismatch(EndPoint),
+    #[error("peer speaks protocol {0}, incompatible with {current}", current = ProtocolVersion::CURRENT)]
+    IncompatibleVersion(ProtocolVersion),
+    #[error("invalid host metadata: {0}")]
+    InvalidMetadata(&'static str),
+    #[error("peer presented static key {actual}, but {pinned} was pinned")]
+    KeyMismatch {
+        pinned: Fingerprint,
+        actual: Fingerprint,
+    },
+}
+
+/// 随握手发送的主机信息，界面在握手完成后即可展示对端名称
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct HostMetadata {
+    pub host_name: String,
+    /// 对端监听协议报文的端口
+    pub listen_port: u16,
+}
+
+impl HostMetadata {
+    pub const MAX_HOST_NAME_LEN: usize = 64;
+
+    /// 本端名称来自配置，去掉控制字符并截断到上限，免得对端拒绝握手
+    pub fn new(host_name: &str, listen_port: u16) -> Self {
+        let mut host_name: String = host_name
+            .trim()
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect();
+        if host_name.len() > Self::MAX_HOST_NAME_LEN {
+            let end = (0..=Self::MAX_HOST_NAME_LEN)
+                .rev()
+                .find(|&i| host_name.is_char_boundary(i))
+                .unwrap_or_default();
+            host_name.truncate(end);
+        }
+        Self {
+            host_name,
+            listen_port,
+        }
+    }
+
+    /// 主机名会直接显示在界面上，不接受空名、超长或含控制字符的名称
+    pub fn validate(&self) -> Result<(), BindingError> {
+        let name = self.host_name.trim();
+        if name.is_empty() {
+            return Err(BindingError::InvalidMetadata("empty host name"));
+        }
+        if self.host_name.len() > Self::MAX_HOST_NAME_LEN {
+            return Err(BindingError::InvalidMetadata("host name is too long"));
+        }
+        if self.host_name.chars().any(char::is_control) {
+            return Err(BindingError::InvalidMetadata(
+                "host name contains control characters",
+            ));
+        }
+        if self.listen_port == 0 {
+            return Err(BindingError::InvalidMetadata("listen port must not be 0"));
+        }
+        Ok(())
+    }
+}
+
+/// 握手负载中的通道绑定，随加密的握手消息发送
+///
+/// 发送方声明双方身份以及它看到的对端地址。中间人转发时对端看到的是中间人的地址，
+/// 接收方据此发现地址不属于本机从而拒绝，防止未知密钥共享/中继攻击
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelBinding {
+    sender: HostId,
+    receiver: HostId,
+    observed: EndPoint,
+    /// 发送方的协议版本，与线路上的版本字节编码相同
+    version: u8,
+    /// 发送方支持的协议特性，与本端取交集即为协商结果
+    capabilities: u32,
+    /// 编码在绑定之后，旧版本不发送也会忽略
+    metadata: Option<HostMetadata>,
+}
+
+/// 绑定本身在线路上的编码，与加入主机信息之前相同
+type Claims = (HostId, HostId, EndPoint, u8, u32);
+
+impl ChannelBinding {
+    pub fn new(sender: HostId, receiver: HostId, observed: EndPoint) -> Self {
+        Self {
+            sender,
+            receiver,
+            observed,
+            version: ProtocolVersion::CURRENT.to_wire(),
+            capabilities: 0,
+            metadata: None,
+        }
+    }
+
+    pub fn with_capabilities(mut self, caps: Capabilities) -> Self {
+        self.capabilities = caps.bits();
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: HostMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn sender(&self) -> &HostId {
+        &self.sender
+    }
+
+    pub fn version(&self) -> ProtocolVersion {
+        ProtocolVersion::from_wire(self.version)
+    }
+
+    /// 不认识的特性位直接忽略
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_bits_truncate(self.capabilities)
+    }
+
+    pub fn metadata(&self) -> Option<&HostMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn to_payload(&self) -> Vec<u8> {
+        let config = bincode::config::standard();
+        let claims: Claims = (
+            self.sender.clone(),
+            self.receiver.clone(),
+            self.observed,
+            self.version,
+            self.capabilities,
+        );
+        let mut payload =
+            bincode::encode_to_vec(claims, config).expect("channel binding is always encodable");
+        if let Some(metadata) = &self.metadata {
+            payload.extend(
+                bincode::encode_to_vec(metadata, config)
+                    .expect("host metadata is always encodable"),
+            );
+        }
+        payload
+    }
+
+    /// 绑定之后没有剩余字节说明对端没有发送主机信息
+    pub fn from_payload(payload: &[u8]) -> Result<Self, BindingError> {
+        let config = bincode::config::standard();
+        let ((sender, receiver, observed, version, capabilities), read): (Claims, _) =
+            bincode::decode_from_slice(payload, config).map_err(|_| BindingError::Malformed)?;
+        let metadata = match &payload[read..] {
+            [] => None,
+            rest => {
+                let (metadata, _) = bincode::decode_from_slice::<HostMetadata, _>(rest, config)
+                    .map_err(|_| BindingError::Malformed)?;
+                Some(metadata)
+            }
+        };
+        Ok(Self {
+            sender,
+            receiver,
+            observed,
+            version,
+            capabilities,
+            metadata,
+        })
+    }
+
+    /// 校验对端发来的绑定
+    ///
+    /// scope id 只在本机有意义，地址比较忽略 scope；主版本不同的对端无法互通
+    pub fn verify(
+        &self,
+        peer: &HostId,
+        local: &HostId,
+        local_addrs: impl IntoIterator<Item = ScopedAddr>,
+    ) -> Result<(), BindingError> {
+        if self.sender != *peer {
+            return Err(BindingError::SenderMismatch {
+                expected: peer.clone(),
+                actual: self.sender.clone(),
+            });
+        }
+        if self.receiver != *local {
+            return Err(BindingError::ReceiverMismatch(self.receiver.clone()));
+        }
+        if !self.version().is_compatible(ProtocolVersion::CURRENT) {
+            return Err(BindingError::IncompatibleVersion(self.version()));
+        }
+        if let Some(metadata) = &self.metadata {
+            metadata.validate()?;
+        }
+        let observed = self.observed.std_addr();
+        local_addrs
+            .into_iter()
+            .any(|addr| addr.get_std() == observed)
+            .then_some(())
+            .ok_or(BindingError::AddrMismatch(self.observed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::mock_endpoint_lan;
+
+    #[test]
+    fn verify_binding() {
+        let (a, b) = (HostId::random(), HostId::random());
+        let b_ep = mock_endpoint_lan();
+        let binding = ChannelBinding::new(a.clone(), b.clone(), b_ep)
+            .with_capabilities(Capabilities::DATA_COMPRESSION);
+        let decoded = ChannelBinding::from_payload(&binding.to_payload()).unwrap();
+        assert_eq!(decoded, binding);
+        assert_eq!(decoded.capabilities(), Capabilities::DATA_COMPRESSION);
+        assert_eq!(decoded.version(), ProtocolVersion::CURRENT);
+
+        // scope 不同但地址相同视为本机地址
+        let local = ScopedAddr::Lan {
+            addr: *b_ep.std_addr(),
+            scope: b_ep.get_scope_id().unwrap().wrapping_add(1),
+        };
+        assert_eq!(decoded.verify(&a, &b, [local]), Ok(()));
+        assert!(matches!(
+            decoded.verify(&b, &b, [local]),
+            Err(BindingError::SenderMismatch { .. })
+        ));
+        assert!(matches!(
+            decoded.verify(&a, &a, [local]),
+            Err(BindingError::ReceiverMismatch(_))
+        ));
+        let relayed = *mock_endpoint_lan().scoped_addr();
+        assert_eq!(
+            decoded.verify(&a, &b, [relayed]),
+            Err(BindingError::AddrMismatch(b_ep))
+        );
+        // 经 NAT 观察到的反射地址记下之后同样接受
+        record_reflexive(&b_ep);
+        assert!(observable_addrs().any(|addr| addr == *b_ep.scoped_addr()));
+        assert_eq!(decoded.verify(&a, &b, observable_addrs()), Ok(()));
+
+        let future = ChannelBinding {
+            version: ProtocolVersion::new(ProtocolVersion::CURRENT.major + 1, 0).to_wire(),
+            ..decoded
+        };
+        assert!(matches!(
+            future.verify(&a, &b, [local]),
+            Err(BindingError::IncompatibleVersion(_))
+        ));
+    }
+
+    #[test]
+    fn carry_metadata() {
+        let (a, b) = (HostId::random(), HostId::random());
+        let b_ep = mock_endpoint_lan();
+        let bare = ChannelBinding::new(a.clone(), b.clone(), b_ep);
+        let binding = bare
+            .clone()
+            .with_metadata(HostMetadata::new("falcon", 5555));
+        let decoded = ChannelBinding::from_payload(&binding.to_payload()).unwrap();
+        assert_eq!(decoded.metadata(), Some(&HostMetadata::new("falcon", 5555)));
+        // 不带主机信息的负载与之前的格式相同，仍能解码
+        let payload = bare.to_payload();
+        assert!(binding.to_payload().starts_with(&payload));
+        assert_eq!(
+            ChannelBinding::from_payload(&payload).unwrap().metadata(),
+            None
+        );
+        let mut truncated = binding.to_payload();
+        truncated.pop();
+        assert_eq!(
+            ChannelBinding::from_payload(&truncated),
+            Err(BindingError::Malformed)
+        );
+
+        let local = *b_ep.scoped_addr();
+        for name in ["", "  ", "tab\tname", "x".repeat(65).as_str()] {
+            let metadata = HostMetadata {
+                host_name: name.into(),
+                listen_port: 5555,
+            };
+            let binding = bare.clone().with_metadata(metadata);
+            assert!(matches!(
+                binding.verify(&a, &b, [local]),
+                Err(BindingError::InvalidMetadata(_))
+            ));
+        }
+        let binding = bare.with_metadata(HostMetadata::new("falcon", 0));
+        assert!(binding.verify(&a, &b, [local]).is_err());
+        // 本端的名称发送前先清理
+        let long = format!("\u{7}{}", "鹰".repeat(30));
+        let metadata = HostMetadata::new(&long, 5555);
+        assert_eq!(metadata.host_name, "鹰".repeat(21));
+        assert_eq!(metadata.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,26 @@
+use super::{BindingError, KeyError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Session with this host already exists")]
+    AlreadyExists,
+    #[error("Session not found")]
+    NotFound,
+    #[error("Session is not an initiator")]
+    NotInitiator,
+    #[error("Session is not a responder")]
+    NotResponder,
+    #[error("Session has already completed the handshake")]
+    AlreadyTransport,
+    #[error(transparent)]
+    Noise(#[from] snow::Error),
+    #[error(transparent)]
+    Key(#[from] KeyError),
+    #[error(transparent)]
+    Binding(#[from] BindingError),
+    #[error(transparent)]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error("Malformed sealed message")]
+    Malformed,
+}
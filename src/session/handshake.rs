@@ -0,0 +1,245 @@
+use super::{MAX_MSG_LEN, session_table, set_hello};
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::{HostId, Msg},
+    link::{HandshakeStage, link_state_table, peer_table},
+};
+use bytes::BytesMut;
+use dashmap::DashMap;
+use std::{sync::OnceLock, time::Duration};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::AbortHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tracing::{info, warn};
+
+/// 握手超时与重试策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakePolicy {
+    /// 单次握手从发出到完成的最长时间
+    pub timeout: Duration,
+    /// 发起方超时后最多重新发起的次数
+    pub max_retries: u8,
+}
+
+impl Default for HandshakePolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            max_retries: 3,
+        }
+    }
+}
+
+impl HandshakePolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        let timeout = cfg
+            .get_typed(ConfigItem::HandshakeTimeoutMs)
+            .await
+            .map(Duration::from_millis)
+            .unwrap_or(default.timeout);
+        let max_retries = cfg
+            .get_typed(ConfigItem::HandshakeMaxRetries)
+            .await
+            .unwrap_or(default.max_retries);
+        Self {
+            timeout,
+            max_retries,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeRole {
+    Initiator,
+    Responder,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeFailure {
+    /// 重试耗尽仍未完成
+    Timeout { attempts: u8 },
+    /// 对端发来的握手报文无法处理
+    Protocol(String),
+}
+
+/// 握手失败事件，链路表据此将对应路径标记为不健康
+#[derive(Debug, Clone)]
+pub struct HandshakeFailed {
+    pub host: HostId,
+    pub reason: HandshakeFailure,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    role: HandshakeRole,
+    attempts: u8,
+    started: Instant,
+}
+
+/// 未完成的握手，与会话表中的 Initiator/Responder 条目一一对应
+fn pending_table() -> &'static DashMap<HostId, Pending> {
+    static PENDING: OnceLock<DashMap<HostId, Pending>> = OnceLock::new();
+    PENDING.get_or_init(DashMap::new)
+}
+
+fn failures() -> &'static broadcast::Sender<HandshakeFailed> {
+    static FAILURES: OnceLock<broadcast::Sender<HandshakeFailed>> = OnceLock::new();
+    FAILURES.get_or_init(|| broadcast::channel(64).0)
+}
+
+/// 订阅握手失败事件
+pub fn handshake_failures() -> broadcast::Receiver<HandshakeFailed> {
+    failures().subscribe()
+}
+
+/// 记录一次握手尝试，重新发起时累加尝试次数
+pub(crate) fn track(host: &HostId, role: HandshakeRole) {
+    pending_table()
+        .entry(host.clone())
+        .and_modify(|pending| {
+            pending.role = role;
+            pending.attempts = pending.attempts.saturating_add(1);
+            pending.started = Instant::now();
+        })
+        .or_insert(Pending {
+            role,
+            attempts: 1,
+            started: Instant::now(),
+        });
+    peer_table().set_handshake(host, HandshakeStage::Handshaking);
+}
+
+/// 会话进入传输模式后调用
+pub(crate) fn complete(host: &HostId) {
+    pending_table().remove(host);
+    peer_table().set_handshake(host, HandshakeStage::Established);
+}
+
+/// 清理会话表中未完成的条目，并通知链路表
+pub(crate) fn fail(host: &HostId, reason: HandshakeFailure) {
+    pending_table().remove(host);
+    session_table().remove_if(host, |_, session| !session.is_transport());
+    let marked = link_state_table().mark_unhealthy(host);
+    peer_table().set_handshake(host, HandshakeStage::Failed);
+    warn!("Handshake with {host} failed ({reason:?}), {marked} link(s) marked unhealthy");
+    let _ = failures().send(HandshakeFailed {
+        host: host.clone(),
+        reason,
+    });
+}
+
+/// 周期性检查超时的握手：发起方在重试次数内重新 hello，否则清理并上报失败
+pub struct HandshakeWatchdog {
+    abort: AbortHandle,
+}
+
+impl HandshakeWatchdog {
+    pub fn run(
+        policy: HandshakePolicy,
+        local: HostId,
+        out: mpsc::UnboundedSender<(HostId, Msg)>,
+    ) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut ticker = interval(policy.timeout / 4);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let expired = pending_table()
+                    .iter()
+                    .filter(|entry| entry.started.elapsed() >= policy.timeout)
+                    .map(|entry| (entry.key().clone(), *entry.value()))
+                    .collect::<Vec<_>>();
+                for (host, pending) in expired {
+                    Self::on_expired(&policy, &local, &out, host, pending);
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    fn on_expired(
+        policy: &HandshakePolicy,
+        local: &HostId,
+        out: &mpsc::UnboundedSender<(HostId, Msg)>,
+        host: HostId,
+        pending: Pending,
+    ) {
+        // 响应方只等待，不主动重试
+        if pending.role == HandshakeRole::Responder || pending.attempts > policy.max_retries {
+            fail(
+                &host,
+                HandshakeFailure::Timeout {
+                    attempts: pending.attempts,
+                },
+            );
+            return;
+        }
+        // 丢弃旧的握手状态，从头发起
+        session_table().remove_if(&host, |_, session| !session.is_transport());
+        match set_hello(host.clone(), BytesMut::zeroed(MAX_MSG_LEN)) {
+            Ok(state) => {
+                info!("Retry handshake with {host}, attempt {}", pending.attempts + 1);
+                let _ = out.send((host, Msg::auth(state, local.clone())));
+            }
+            Err(err) => fail(&host, HandshakeFailure::Protocol(err.to_string())),
+        }
+    }
+}
+
+impl Drop for HandshakeWatchdog {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Handshake watchdog has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 失败事件是全局广播，并行测试需要按 host 过滤
+    async fn next_failure(
+        rx: &mut broadcast::Receiver<HandshakeFailed>,
+        host: &HostId,
+    ) -> HandshakeFailed {
+        loop {
+            let event = rx.recv().await.unwrap();
+            if &event.host == host {
+                return event;
+            }
+        }
+    }
+
+    // 等待表是全局的，放在同一个测试里避免多个看门狗互相干扰
+    #[tokio::test(start_paused = true)]
+    async fn retry_then_cleanup() {
+        let (initiator, responder) = (HostId::random(), HostId::random());
+        let mut failed = handshake_failures();
+        set_hello(initiator.clone(), BytesMut::zeroed(MAX_MSG_LEN)).unwrap();
+        track(&responder, HandshakeRole::Responder);
+        let (out, mut rx) = mpsc::unbounded_channel();
+        let policy = HandshakePolicy {
+            timeout: Duration::from_secs(1),
+            max_retries: 2,
+        };
+        let _watchdog = HandshakeWatchdog::run(policy, HostId::random(), out);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        let mut retries = 0;
+        while let Ok((to, _)) = rx.try_recv() {
+            assert_eq!(to, initiator);
+            retries += 1;
+        }
+        assert_eq!(retries, 2);
+        let event = next_failure(&mut failed, &responder).await;
+        assert_eq!(event.reason, HandshakeFailure::Timeout { attempts: 1 });
+        let event = next_failure(&mut failed, &initiator).await;
+        assert_eq!(event.reason, HandshakeFailure::Timeout { attempts: 3 });
+        assert!(!session_table().contains_key(&initiator));
+        assert!(!pending_table().contains_key(&responder));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::addr::EndPoint;
+use crate::inbound::Capabilities;
+use crate::inbound::Handshake;
+use crate::inbound::HostId;
+use crate::inbound::Msg;
+use crate::link::Event;
+use crate::link::Uid;
+use bytes::BytesMut;
+use tokio::{sync::mpsc, task::AbortHandle};
+use tracing::{info, warn};
+
+use super::{ChannelBinding, HostMetadata};
+use super::set_exchange_or_full;
+use super::set_last_full;
+use super::{
+    HandshakeFailure, Layer, Pipeline, accepts_plaintext, fail, is_established, on_rekey,
+    open_msg, set_hello, touch,
+};
+
+/// 处理握手事件并解开密文，以明文到达的会话层报文一律丢弃
+pub struct AuthLayer {
+    local: Uid,
+    caps: Capabilities, // 随握手声明的本端特性
+    metadata: Option<HostMetadata>,
+    out: mpsc::UnboundedSender<(HostId, Msg)>,
+    buf: BytesMut,
+}
+
+impl AuthLayer {
+    pub fn new(local: Uid, caps: Capabilities, out: mpsc::UnboundedSender<(HostId, Msg)>) -> Self {
+        Self {
+            local,
+            caps,
+            metadata: None,
+            out,
+            buf: BytesMut::with_capacity(u32::MAX as usize),
+        }
+    }
+
+    /// 随握手发送的本端主机信息
+    pub fn with_metadata(mut self, metadata: HostMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    fn binding(&self, host: &HostId, remote: EndPoint) -> ChannelBinding {
+        let binding = ChannelBinding::new(self.local.clone(), host.clone(), remote)
+            .with_capabilities(self.caps);
+        match &self.metadata {
+            Some(metadata) => binding.with_metadata(metadata.clone()),
+            None => binding,
+        }
+    }
+
+    fn reply(&self, host: HostId, state: Handshake) {
+        let _ = self.out.send((host, Msg::auth(state, self.local.clone())));
+    }
+}
+
+impl Layer for AuthLayer {
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::Auth {
+                host,
+                remote,
+                state: event,
+            } => match *event {
+                //-> Exchange(e,ee)
+                Handshake::Hello => match set_hello(host.clone(), self.buf.clone()) {
+                    Ok(state) => self.reply(host, state),
+                    // 已有会话或握手进行中，交给看门狗处理超时
+                    Err(err) => warn!("Ignore hello to {host}: {err}"),
+                },
+                // <- Exchange(e,ee,s,es) then -> Full(s,es) and set full
+                // <- Exchange(e,ee) and then -> Exchange(e,ee,s,es)
+                Handshake::Exchange(payload) => {
+                    let binding = self.binding(&host, remote);
+                    match set_exchange_or_full(host.clone(), payload, self.buf.clone(), &binding) {
+                        Ok(state) => self.reply(host, state),
+                        Err(err) => fail(&host, HandshakeFailure::Protocol(err.to_string())),
+                    }
+                }
+                // <- Full(s,es) and set full
+                Handshake::Full(payload) => {
+                    let binding = self.binding(&host, remote);
+                    if let Err(err) =
+                        set_last_full(host.clone(), payload, self.buf.clone(), &binding)
+                    {
+                        fail(&host, HandshakeFailure::Protocol(err.to_string()));
+                    }
+                }
+            },
+            Event::Sealed {
+                host,
+                remote,
+                sealed,
+            } => match open_msg(&host, &sealed) {
+                // 换钥在会话层消化，不再向上传递
+                Ok(Msg::Rekey { epoch, ack, .. }) => {
+                    if let Some(reply) = on_rekey(&host, &self.local, epoch, ack) {
+                        let _ = self.out.send((host, reply));
+                    }
+                }
+                // 保活在解开时已刷新收包时间
+                Ok(Msg::Keepalive { .. }) => {}
+                Ok(msg) => return Some((msg, remote).into()),
+                Err(err) => warn!("Drop sealed message from {host}: {err}"),
+            },
+            // 双方在握手中协商了明文数据时，数据报文可以不加密
+            event @ Event::Transfer { .. }
+                if event
+                    .session_host()
+                    .is_some_and(|host| is_established(host) && accepts_plaintext(host)) =>
+            {
+                if let Some(host) = event.session_host() {
+                    touch(host);
+                }
+                return Some(event);
+            }
+            // 明文的发送方无从验证，握手之前也不接受
+            event if event.session_host().is_some() => {
+                let host = event.session_host();
+                warn!("Drop plaintext session message from {host:?}");
+            }
+            event => return Some(event),
+        }
+        None
+    }
+}
+
+/// 让上游事件依次经过拦截链，走完整条链的事件向上传递
+pub struct Interceptor {
+    abort: AbortHandle,
+}
+
+impl Interceptor {
+    pub fn run(
+        mut pipeline: Pipeline,
+        mut up_rx: mpsc::Receiver<Event>,
+    ) -> (Self, mpsc::Receiver<Event>) {
+        let (down_tx, down_rx) = mpsc::channel::<Event>(1024);
+        let abort = tokio::spawn(async move {
+            while let Some(event) = up_rx.recv().await {
+                let Some(event) = pipeline.handle(event) else {
+                    continue;
+                };
+                if down_tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .abort_handle();
+        (Self { abort }, down_rx)
+    }
+}
+
+impl Drop for Interceptor {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Session interceptor has been dropped");
+    }
+}
@@ -0,0 +1,265 @@
+use super::{
+    MAX_MSG_LEN, Session, forget_negotiated, is_established, peer_capabilities, session_table,
+    set_hello,
+};
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::{Capabilities, HostId, Msg},
+    link::{HandshakeStage, peer_table},
+};
+use bytes::BytesMut;
+use dashmap::DashSet;
+use std::{sync::OnceLock, time::Duration};
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{MissedTickBehavior, interval},
+};
+use tracing::{debug, info, warn};
+
+/// 会话保活与空闲过期策略，为零的一项不生效
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepalivePolicy {
+    /// 会话在这么久内没有报文发出时发送保活
+    pub interval: Duration,
+    /// 这么久没有保活以外的报文，或者没有收到对端任何报文，会话即过期
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepalivePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            idle_timeout: Duration::from_secs(180),
+        }
+    }
+}
+
+impl KeepalivePolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            interval: cfg
+                .get_typed(ConfigItem::KeepaliveIntervalSecs)
+                .await
+                .map(Duration::from_secs)
+                .unwrap_or(default.interval),
+            idle_timeout: cfg
+                .get_typed(ConfigItem::SessionIdleTimeoutSecs)
+                .await
+                .map(Duration::from_secs)
+                .unwrap_or(default.idle_timeout),
+        }
+    }
+
+    /// 检查的周期，两项都不生效时不必检查
+    fn period(&self) -> Option<Duration> {
+        [self.interval, self.idle_timeout]
+            .into_iter()
+            .filter(|period| !period.is_zero())
+            .min()
+            .map(|period| period / 2)
+    }
+}
+
+/// 会话过期后尚未重新握手的对端，握手完成之前会话层报文不能以明文发出
+fn resuming() -> &'static DashSet<HostId> {
+    static RESUMING: OnceLock<DashSet<HostId>> = OnceLock::new();
+    RESUMING.get_or_init(DashSet::new)
+}
+
+/// 收发了保活以外的报文，刷新会话的空闲计时
+pub(crate) fn touch(host: &HostId) {
+    if let Some(Session::Transport(transport)) = session_table().get_mut(host).as_deref_mut() {
+        transport.touch();
+    }
+}
+
+/// 拆除空闲的会话与协商结果，并通知对端表下次发送时重新握手
+pub(crate) fn expire(host: &HostId) {
+    if session_table()
+        .remove_if(host, |_, session| session.is_transport())
+        .is_none()
+    {
+        return;
+    }
+    forget_negotiated(host);
+    resuming().insert(host.clone());
+    peer_table().set_handshake(host, HandshakeStage::Expired);
+    info!("Session with {host} expired after idling");
+}
+
+/// 会话已过期、重新握手还没完成
+pub fn awaits_handshake(host: &HostId) -> bool {
+    resuming().contains(host) && !is_established(host)
+}
+
+/// 过期会话的对端又有报文要发时重新发起握手，返回要发出的 hello
+///
+/// 握手进行中或失败后仍在等待重试时返回 None
+pub fn resume_handshake(host: &HostId, local: &HostId) -> Option<Msg> {
+    if !resuming().contains(host) {
+        return None;
+    }
+    if is_established(host) {
+        resuming().remove(host);
+        return None;
+    }
+    if session_table().contains_key(host) {
+        return None;
+    }
+    match set_hello(host.clone(), BytesMut::zeroed(MAX_MSG_LEN)) {
+        Ok(state) => {
+            info!("Resume expired session with {host}");
+            Some(Msg::auth(state, local.clone()))
+        }
+        Err(err) => {
+            warn!("Failed to resume session with {host}: {err}");
+            None
+        }
+    }
+}
+
+/// 周期性检查已建立的会话：没有报文发出的发送保活，空闲过久的过期拆除
+pub struct SessionReaper {
+    abort: AbortHandle,
+}
+
+impl SessionReaper {
+    pub fn run(
+        policy: KeepalivePolicy,
+        local: HostId,
+        out: mpsc::UnboundedSender<(HostId, Msg)>,
+    ) -> Self {
+        let abort = tokio::spawn(async move {
+            let Some(period) = policy.period() else {
+                return;
+            };
+            let mut ticker = interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let (expired, quiet) = Self::sweep(&policy);
+                for host in expired {
+                    expire(&host);
+                }
+                for host in quiet {
+                    debug!("Send keepalive to {host}");
+                    let keepalive = Msg::Keepalive {
+                        host: local.clone(),
+                    };
+                    let _ = out.send((host, keepalive));
+                }
+                // 重新建立的会话不再需要等待
+                resuming().retain(|host| !is_established(host));
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    /// 返回应当过期的会话与需要保活的会话
+    fn sweep(policy: &KeepalivePolicy) -> (Vec<HostId>, Vec<HostId>) {
+        let (mut expired, mut quiet) = (Vec::new(), Vec::new());
+        for entry in session_table().iter() {
+            let Session::Transport(transport) = entry.value() else {
+                continue;
+            };
+            let host = entry.key();
+            // 不支持保活的对端空闲时不会发来任何报文，只按使用情况过期
+            let keepalive = peer_capabilities(host).contains(Capabilities::KEEPALIVE);
+            let idle = !policy.idle_timeout.is_zero()
+                && (transport.idle_for() >= policy.idle_timeout
+                    || (keepalive && transport.silent_for() >= policy.idle_timeout));
+            if idle {
+                expired.push(host.clone());
+            } else if keepalive
+                && !policy.interval.is_zero()
+                && transport.quiet_for() >= policy.interval
+            {
+                quiet.push(host.clone());
+            }
+        }
+        (expired, quiet)
+    }
+}
+
+impl Drop for SessionReaper {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Session reaper has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        session::{Sealed, negotiated, open_msg, seal_msg},
+        testing::establish,
+    };
+    use tokio::time::advance;
+
+    const POLICY: KeepalivePolicy = KeepalivePolicy {
+        interval: Duration::from_secs(10),
+        idle_timeout: Duration::from_secs(60),
+    };
+
+    /// 会话表是全局的，只看本测试的对端
+    fn sweep(hosts: &[&HostId]) -> (Vec<HostId>, Vec<HostId>) {
+        let (expired, quiet) = SessionReaper::sweep(&POLICY);
+        let ours = |host: &HostId| hosts.contains(&host);
+        (
+            expired.into_iter().filter(ours).collect(),
+            quiet.into_iter().filter(ours).collect(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_then_expire() {
+        let (a, b) = (HostId::random(), HostId::random());
+        establish(&a, &b, Capabilities::KEEPALIVE).unwrap();
+        let data = Msg::Offer {
+            host: a.clone(),
+            hash: 7,
+            file_name: "a.bin".to_owned(),
+            size: 1,
+            mtime: None,
+            permissions: None,
+        };
+        seal_msg(&b, data).unwrap();
+        advance(Duration::from_secs(10)).await;
+        assert_eq!(sweep(&[&b]), (vec![], vec![b.clone()]));
+
+        // 保活能送达对端，但不算作使用
+        let Msg::Sealed {
+            epoch, nonce, body, ..
+        } = seal_msg(&b, Msg::Keepalive { host: a.clone() }).unwrap()
+        else {
+            panic!("Keepalive must be sealed");
+        };
+        let keepalive = open_msg(&a, &Sealed { epoch, nonce, body }).unwrap();
+        assert_eq!(keepalive, Msg::Keepalive { host: a.clone() });
+        assert_eq!(sweep(&[&b]), (vec![], vec![]));
+
+        advance(Duration::from_secs(50)).await;
+        let (expired, _) = sweep(&[&a, &b]);
+        assert_eq!(expired.len(), 2);
+        expire(&b);
+        assert!(!is_established(&b));
+        assert!(negotiated(&b).is_none());
+        assert!(awaits_handshake(&b));
+        assert_eq!(
+            peer_table().get(&b).map(|peer| peer.handshake),
+            Some(HandshakeStage::Expired)
+        );
+
+        // 下一次发送触发重新握手，握手进行中不重复发起
+        assert!(matches!(resume_handshake(&b, &a), Some(Msg::Auth { .. })));
+        assert!(resume_handshake(&b, &a).is_none());
+        assert!(awaits_handshake(&b));
+        // 没有过期过的对端不受影响
+        assert!(resume_handshake(&a, &b).is_none());
+        assert!(!awaits_handshake(&a));
+    }
+}
@@ -0,0 +1,176 @@
+use super::PATTERN;
+use atomicwrites::{AtomicFile, OverwriteBehavior::AllowOverwrite};
+use camino::{Utf8Path, Utf8PathBuf};
+use directories::ProjectDirs;
+use std::{fmt::Display, fs::File, io::Write, sync::OnceLock};
+use thiserror::Error;
+use tracing::info;
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Write(#[from] atomicwrites::Error<std::io::Error>),
+    #[error(transparent)]
+    Noise(#[from] snow::Error),
+    #[error("key file {0} is corrupted")]
+    Corrupted(Utf8PathBuf),
+    #[error("config dir was not found")]
+    ConfigDirNotFound,
+}
+
+const KEY_LEN: usize = 32;
+
+/// 公钥指纹，用于带外核对对端身份
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 16]);
+
+impl Fingerprint {
+    pub fn of(public: &[u8]) -> Self {
+        let hash = blake3::hash(public);
+        let mut digest = [0u8; 16];
+        digest.copy_from_slice(&hash.as_bytes()[..16]);
+        Self(digest)
+    }
+}
+
+/// 四个十六进制字符一组，便于口头核对
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, pair) in self.0.chunks(2).enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{:02x}{:02x}", pair[0], pair[1])?;
+        }
+        Ok(())
+    }
+}
+
+/// 本机的 X25519 静态密钥对，首次运行时生成并持久化
+pub struct StaticKeys {
+    private: [u8; KEY_LEN],
+    public: [u8; KEY_LEN],
+}
+
+pub fn static_keys() -> Result<&'static StaticKeys, KeyError> {
+    static STATIC_KEYS: OnceLock<StaticKeys> = OnceLock::new();
+    STATIC_KEYS.get_or_try_init(|| {
+        let prj_dir = ProjectDirs::from("com", "tritium", "falcon_transfer")
+            .ok_or(KeyError::ConfigDirNotFound)?;
+        let cfg_dir = prj_dir.config_local_dir();
+        std::fs::create_dir_all(cfg_dir)?;
+        let path = Utf8PathBuf::from_path_buf(cfg_dir.join("noise.key"))
+            .map_err(|_| KeyError::ConfigDirNotFound)?;
+        StaticKeys::load_or_generate(&path)
+    })
+}
+
+impl StaticKeys {
+    pub fn generate() -> Result<Self, KeyError> {
+        let keypair = snow::Builder::new(PATTERN.parse()?).generate_keypair()?;
+        let mut keys = Self {
+            private: [0; KEY_LEN],
+            public: [0; KEY_LEN],
+        };
+        keys.private.copy_from_slice(&keypair.private);
+        keys.public.copy_from_slice(&keypair.public);
+        Ok(keys)
+    }
+
+    /// 文件内容为私钥与公钥依次拼接，仅所有者可读写
+    pub fn load_or_generate(path: &Utf8Path) -> Result<Self, KeyError> {
+        if path.exists() {
+            let content = std::fs::read(path)?;
+            if content.len() != KEY_LEN * 2 {
+                return Err(KeyError::Corrupted(path.to_owned()));
+            }
+            let (private, public) = content.split_at(KEY_LEN);
+            let mut keys = Self {
+                private: [0; KEY_LEN],
+                public: [0; KEY_LEN],
+            };
+            keys.private.copy_from_slice(private);
+            keys.public.copy_from_slice(public);
+            return Ok(keys);
+        }
+        let keys = Self::generate()?;
+        keys.save(path)?;
+        info!("Generated static key {} at {path}", keys.fingerprint());
+        Ok(keys)
+    }
+
+    fn save(&self, path: &Utf8Path) -> Result<(), KeyError> {
+        // 在临时文件上收紧权限后再替换，避免私钥短暂可读
+        AtomicFile::new(path, AllowOverwrite).write(|f| {
+            restrict_permissions(f)?;
+            f.write_all(&self.private)?;
+            f.write_all(&self.public)?;
+            f.sync_all()
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn private(&self) -> &[u8] {
+        &self.private
+    }
+
+    pub fn public(&self) -> &[u8] {
+        &self.public
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.public)
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(file: &File) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))
+}
+
+// windows 下配置目录默认只对当前用户可见
+#[cfg(not(unix))]
+fn restrict_permissions(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persist_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: Utf8PathBuf = dir.path().join("noise.key").try_into().unwrap();
+        let first = StaticKeys::load_or_generate(&path).unwrap();
+        let second = StaticKeys::load_or_generate(&path).unwrap();
+        assert_eq!(first.private(), second.private());
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn reject_corrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: Utf8PathBuf = dir.path().join("noise.key").try_into().unwrap();
+        std::fs::write(&path, b"123").unwrap();
+        assert!(matches!(
+            StaticKeys::load_or_generate(&path),
+            Err(KeyError::Corrupted(_))
+        ));
+    }
+
+    #[test]
+    fn fingerprint_format() {
+        let fp = Fingerprint::of(&[0; KEY_LEN]).to_string();
+        assert_eq!(fp.len(), 8 * 4 + 7);
+    }
+}
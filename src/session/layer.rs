@@ -0,0 +1,277 @@
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::HostId,
+    link::Event,
+    task::FileHash,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// 拦截链中的一环
+///
+/// 返回 None 表示事件已被消费，否则把（可能改写过的）事件交给下一环
+pub trait Layer: Send + 'static {
+    fn handle(&mut self, event: Event) -> Option<Event>;
+}
+
+impl<F> Layer for F
+where
+    F: FnMut(Event) -> Option<Event> + Send + 'static,
+{
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        self(event)
+    }
+}
+
+/// 按加入顺序依次处理事件的拦截链，走完整条链的事件交给分发
+#[derive(Default)]
+pub struct Pipeline {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer(mut self, layer: impl Layer) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// 把另一条链整体接在后面
+    pub fn chain(mut self, other: Pipeline) -> Self {
+        self.layers.extend(other.layers);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn handle(&mut self, event: Event) -> Option<Event> {
+        self.layers
+            .iter_mut()
+            .try_fold(event, |event, layer| layer.handle(event))
+    }
+}
+
+/// 短时间内重复到达的同一控制事件只处理一次
+///
+/// 对端超时重发的邀约与请求在确认丢失时会成批到达，重复处理会重复弹窗或重复发送
+pub struct DedupLayer {
+    window: Duration,
+    seen: HashMap<(HostId, FileHash, u8), Instant>,
+}
+
+impl Default for DedupLayer {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+impl DedupLayer {
+    const MAX_TRACKED: usize = 4096;
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl Layer for DedupLayer {
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        let key = match &event {
+            Event::Offer { owner, hash, .. } => (owner.clone(), *hash, 0),
+            Event::Decline { host, hash, .. } => (host.clone(), *hash, 1),
+            Event::Fetch { host, hash, .. } => (host.clone(), *hash, 2),
+            _ => return Some(event),
+        };
+        let now = Instant::now();
+        if self.seen.len() >= Self::MAX_TRACKED {
+            let window = self.window;
+            self.seen.retain(|_, at| now.duration_since(*at) < window);
+        }
+        // 重复的不刷新时间，一直重发的对端每个窗口仍能通过一次
+        if let Some(at) = self.seen.get(&key)
+            && now.duration_since(*at) < self.window
+        {
+            debug!("Drop duplicate event from {}", key.0);
+            return None;
+        }
+        self.seen.insert(key, now);
+        Some(event)
+    }
+}
+
+/// 限制每个对端每秒的控制事件数，数据报文不受影响
+pub struct RateLimitLayer {
+    per_sec: u32,
+    windows: HashMap<HostId, (Instant, u32)>,
+}
+
+impl RateLimitLayer {
+    pub fn new(per_sec: u32) -> Self {
+        Self {
+            per_sec,
+            windows: HashMap::new(),
+        }
+    }
+
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let per_sec = cfg
+            .get_typed(ConfigItem::ControlEventsPerSec)
+            .await
+            .unwrap_or(256);
+        Self::new(per_sec)
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        if matches!(event, Event::Transfer { .. }) {
+            return Some(event);
+        }
+        // 握手与密文在会话层解开之前没有可信的发送方
+        let Some(host) = event.session_host().cloned() else {
+            return Some(event);
+        };
+        let now = Instant::now();
+        let (start, count) = self.windows.entry(host.clone()).or_insert((now, 0));
+        if now.duration_since(*start) >= Duration::from_secs(1) {
+            (*start, *count) = (now, 0);
+        }
+        *count += 1;
+        match *count <= self.per_sec {
+            true => Some(event),
+            false => {
+                debug!("Drop event from {host} over rate limit");
+                None
+            }
+        }
+    }
+}
+
+/// 各类事件的累计数量
+#[derive(Debug, Default)]
+pub struct EventCounters {
+    pub auth: AtomicU64,
+    pub sealed: AtomicU64,
+    pub offer: AtomicU64,
+    pub decline: AtomicU64,
+    pub fetch: AtomicU64,
+    pub transfer: AtomicU64,
+}
+
+impl EventCounters {
+    pub fn snapshot(&self) -> EventCounts {
+        EventCounts {
+            auth: self.auth.load(Relaxed),
+            sealed: self.sealed.load(Relaxed),
+            offer: self.offer.load(Relaxed),
+            decline: self.decline.load(Relaxed),
+            fetch: self.fetch.load(Relaxed),
+            transfer: self.transfer.load(Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventCounts {
+    pub auth: u64,
+    pub sealed: u64,
+    pub offer: u64,
+    pub decline: u64,
+    pub fetch: u64,
+    pub transfer: u64,
+}
+
+/// 只计数不改写，放在链首统计的是到达的全部事件
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer {
+    counters: Arc<EventCounters>,
+}
+
+impl MetricsLayer {
+    pub fn counters(&self) -> Arc<EventCounters> {
+        self.counters.clone()
+    }
+}
+
+impl Layer for MetricsLayer {
+    fn handle(&mut self, event: Event) -> Option<Event> {
+        let counter = match &event {
+            Event::Auth { .. } => &self.counters.auth,
+            Event::Sealed { .. } => &self.counters.sealed,
+            Event::Offer { .. } => &self.counters.offer,
+            Event::Decline { .. } => &self.counters.decline,
+            Event::Fetch { .. } => &self.counters.fetch,
+            Event::Transfer { .. } => &self.counters.transfer,
+        };
+        counter.fetch_add(1, Relaxed);
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch(host: &HostId, hash: FileHash) -> Event {
+        Event::Fetch {
+            host: host.clone(),
+            hash,
+            token: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn compose_layers() {
+        let host = HostId::random();
+        let metrics = MetricsLayer::default();
+        let counters = metrics.counters();
+        // 自定义的一环：改写后交给下游
+        let rewrite = |event: Event| match event {
+            Event::Fetch { host, .. } => Some(Event::Fetch {
+                host,
+                hash: 7,
+                token: None,
+            }),
+            event => Some(event),
+        };
+        let mut pipeline = Pipeline::new()
+            .layer(metrics)
+            .layer(RateLimitLayer::new(3))
+            .layer(DedupLayer::new(Duration::from_secs(1)))
+            .layer(rewrite);
+        assert_eq!(pipeline.len(), 4);
+
+        assert!(matches!(
+            pipeline.handle(fetch(&host, 1)),
+            Some(Event::Fetch { hash: 7, .. })
+        ));
+        // 重复的请求被去重
+        assert!(pipeline.handle(fetch(&host, 1)).is_none());
+        assert!(pipeline.handle(fetch(&host, 2)).is_some());
+        // 同一秒内第四个事件超出限速
+        assert!(pipeline.handle(fetch(&host, 3)).is_none());
+        assert_eq!(counters.snapshot().fetch, 4);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(pipeline.handle(fetch(&host, 1)).is_some());
+    }
+}
@@ -1,4 +1,25 @@
-mod Interceptor;
+mod binding;
+mod error;
+mod handshake;
+mod interceptor;
+mod keepalive;
+mod keys;
+mod layer;
+mod plaintext;
+mod replay;
 mod session;
-pub use Interceptor::*;
+mod transport;
+pub use binding::*;
+pub use error::*;
+pub use handshake::*;
+pub use interceptor::*;
+pub use keepalive::*;
+pub use keys::*;
+pub use layer::*;
+pub use plaintext::*;
+pub use replay::*;
 pub use session::*;
+pub use transport::*;
+
+/// Noise 单条报文的最大长度
+const MAX_MSG_LEN: usize = 65535;
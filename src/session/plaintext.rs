@@ -0,0 +1,55 @@
+use super::peer_capabilities;
+use crate::inbound::{Capabilities, HostId, Msg};
+use dashmap::DashSet;
+use std::sync::OnceLock;
+use tracing::info;
+
+/// 可信局域网测速时数据报文不加密，用来对比协议本身与 AES 的开销
+///
+/// 双方都在握手中声明 `Capabilities::PLAINTEXT_DATA` 才会生效，握手与控制消息照常加密认证；
+/// 协商之后仍可以对个别对端强制恢复加密
+#[derive(Debug, Default)]
+pub struct PlaintextData {
+    /// 强制加密的对端
+    encrypted: DashSet<HostId>,
+}
+
+pub fn plaintext_data() -> &'static PlaintextData {
+    static PLAINTEXT_DATA: OnceLock<PlaintextData> = OnceLock::new();
+    PLAINTEXT_DATA.get_or_init(PlaintextData::default)
+}
+
+impl PlaintextData {
+    /// 对端协商了明文数据时，是否仍然加密发往它的数据
+    pub fn set_encrypted(&self, host: &HostId, encrypted: bool) {
+        let changed = match encrypted {
+            true => self.encrypted.insert(host.clone()),
+            false => self.encrypted.remove(host).is_some(),
+        };
+        if changed && accepts_plaintext(host) {
+            info!("Data to {host} is now sent in {}", mode(encrypted));
+        }
+    }
+
+    /// 发往 host 的数据是否加密，没有协商明文数据时总是加密
+    pub fn is_encrypted(&self, host: &HostId) -> bool {
+        !accepts_plaintext(host) || self.encrypted.contains(host)
+    }
+
+    /// 发往 host 的这条报文是否以明文发送，只有数据报文可能不加密
+    pub fn applies(&self, host: &HostId, msg: &Msg) -> bool {
+        matches!(msg, Msg::Transfer { .. }) && !self.is_encrypted(host)
+    }
+}
+
+/// 握手时双方都同意了明文数据，来自 host 的明文数据报文不必丢弃
+pub fn accepts_plaintext(host: &HostId) -> bool {
+    peer_capabilities(host).contains(Capabilities::PLAINTEXT_DATA)
+}
+
+fn mode(encrypted: bool) -> &'static str {
+    match encrypted {
+        true => "ciphertext",
+        false => "plaintext",
+    }
+}
@@ -0,0 +1,72 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replay {
+    Fresh,
+    /// 窗口内已经收到过
+    Duplicate,
+    /// 落后于窗口，无法判断，按重放处理
+    Stale,
+}
+
+/// 滑动窗口重放检测，第 i 位表示 `highest - i` 是否已收到
+///
+/// 按 Noise nonce 检测，窗口随会话存放，会话过期拆除时一并丢弃
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReplayWindow {
+    highest: Option<u64>,
+    seen: u128,
+}
+
+impl ReplayWindow {
+    /// 多链路并发时报文可能乱序，窗口需要足够宽
+    pub const SIZE: u64 = u128::BITS as u64;
+
+    pub fn check(&mut self, seq: u64) -> Replay {
+        let Some(highest) = self.highest else {
+            self.highest = Some(seq);
+            self.seen = 1;
+            return Replay::Fresh;
+        };
+        if seq > highest {
+            let shift = seq - highest;
+            self.seen = if shift >= Self::SIZE {
+                0
+            } else {
+                self.seen << shift
+            } | 1;
+            self.highest = Some(seq);
+            return Replay::Fresh;
+        }
+        let offset = highest - seq;
+        if offset >= Self::SIZE {
+            return Replay::Stale;
+        }
+        let bit = 1u128 << offset;
+        if self.seen & bit != 0 {
+            return Replay::Duplicate;
+        }
+        self.seen |= bit;
+        Replay::Fresh
+    }
+
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window() {
+        let mut window = ReplayWindow::default();
+        assert_eq!(window.check(100), Replay::Fresh);
+        assert_eq!(window.check(100), Replay::Duplicate);
+        assert_eq!(window.check(98), Replay::Fresh);
+        assert_eq!(window.check(101), Replay::Fresh);
+        assert_eq!(window.check(98), Replay::Duplicate);
+        assert_eq!(window.check(99), Replay::Fresh);
+        assert_eq!(window.check(101 + ReplayWindow::SIZE), Replay::Fresh);
+        assert_eq!(window.check(101), Replay::Stale);
+    }
+}
@@ -1,12 +1,21 @@
-use crate::inbound::{Handshake, HostId};
-use anyhow::{Result, anyhow};
+use super::{
+    BindingError, ChannelBinding, Fingerprint, HandshakeRole, SessionError, Transport, complete,
+    observable_addrs, rekeying, static_keys, track,
+};
+use crate::audit::{AuditEvent, audit};
+use crate::inbound::{Capabilities, Handshake, HostId, ProtocolVersion};
+use crate::link::peer_table;
 use bytes::{Bytes, BytesMut};
 use dashmap::DashMap;
 use std::sync::OnceLock;
-enum Session {
+use tracing::{debug, warn};
+
+type Result<T> = std::result::Result<T, SessionError>;
+
+pub enum Session {
     Initiator(snow::HandshakeState),
     Responder(snow::HandshakeState),
-    Transport(snow::TransportState),
+    Transport(Transport),
 }
 
 pub fn session_table() -> &'static DashMap<HostId, Session> {
@@ -14,6 +23,39 @@ pub fn session_table() -> &'static DashMap<HostId, Session> {
     SESSION_TABLE.get_or_init(DashMap::new)
 }
 
+/// 握手时与对端协商出的协议版本与特性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: ProtocolVersion,
+    pub capabilities: Capabilities,
+}
+
+fn negotiated_table() -> &'static DashMap<HostId, Negotiated> {
+    static NEGOTIATED_TABLE: OnceLock<DashMap<HostId, Negotiated>> = OnceLock::new();
+    NEGOTIATED_TABLE.get_or_init(DashMap::new)
+}
+
+pub(crate) fn record_negotiated(host: &HostId, negotiated: Negotiated) {
+    negotiated_table().insert(host.clone(), negotiated);
+}
+
+/// 会话过期后协商结果随之作废，重新握手时再次记录
+pub(crate) fn forget_negotiated(host: &HostId) {
+    negotiated_table().remove(host);
+}
+
+/// 尚未完成握手的对端返回 None
+pub fn negotiated(host: &HostId) -> Option<Negotiated> {
+    negotiated_table().get(host).map(|negotiated| *negotiated)
+}
+
+/// 尚未完成握手的对端视为不支持任何可选特性
+pub fn peer_capabilities(host: &HostId) -> Capabilities {
+    negotiated(host)
+        .map(|negotiated| negotiated.capabilities)
+        .unwrap_or_else(Capabilities::empty)
+}
+
 /// 发现对方以后，先手进行 hello，此操作会操作会话表和链路状态表
 ///
 /// 记得操作链路状态表
@@ -21,74 +63,155 @@ pub fn session_table() -> &'static DashMap<HostId, Session> {
 pub fn set_hello(host: HostId, buf: BytesMut) -> Result<Handshake> {
     let st = session_table();
     if st.contains_key(&host) {
-        return Err(anyhow!("current session has already exists"));
+        return Err(SessionError::AlreadyExists);
     }
     // todo 需要注意潜在的key状态不一致，当然只存在于并发中
-    let mut session = Session::new_initiator();
+    let mut session = Session::new_initiator()?;
     let payload = session.hello(buf)?;
+    track(&host, HandshakeRole::Initiator);
     st.insert(host, session);
     Ok(Handshake::Exchange(payload.to_vec()))
 }
 
 // 接受者还需要一步进入full,发起者会直接进入full
-pub fn set_exchange_or_full(host: HostId, msg: Vec<u8>, buf: BytesMut) -> Result<Handshake> {
+// binding 是本端对这条通道的声明，会放进加密的握手负载中
+pub fn set_exchange_or_full(
+    host: HostId,
+    msg: Vec<u8>,
+    buf: BytesMut,
+    binding: &ChannelBinding,
+) -> Result<Handshake> {
     let st = session_table();
     let result = if let Some((host, mut session)) = st.remove(&host) {
-        let payload = session.exchange(msg, buf)?;
-        let session = session.full()?;
+        let payload = session.exchange(&host, msg, buf, binding)?;
+        let session = session.full(&host)?;
+        audit(AuditEvent::pairing(&host));
+        complete(&host);
         st.insert(host, session);
         Handshake::Full(payload.to_vec())
     } else {
-        let mut session = Session::new_responder();
-        let payload = session.exchange(msg, buf)?;
+        let mut session = Session::new_responder()?;
+        let payload = session.exchange(&host, msg, buf, binding)?;
+        track(&host, HandshakeRole::Responder);
         st.insert(host, session);
         Handshake::Exchange(payload.to_vec())
     };
     Ok(result)
 }
 
-pub fn set_last_full(host: HostId, msg: Vec<u8>, buf: BytesMut) -> Result<()> {
+pub fn set_last_full(
+    host: HostId,
+    msg: Vec<u8>,
+    buf: BytesMut,
+    binding: &ChannelBinding,
+) -> Result<()> {
     let st = session_table();
     if let Some((host, session)) = st.remove(&host) {
-        let session = session.full_with_msg(msg, buf)?;
+        let session = session.full_with_msg(&host, msg, buf, binding)?;
+        audit(AuditEvent::pairing(&host));
+        complete(&host);
         st.insert(host, session);
         return Ok(());
     };
-    Err(anyhow!("session not found"))
+    Err(SessionError::NotFound)
+}
+
+/// 对端的静态公钥与 HostId 绑定，首次握手时记下，之后换了公钥的同名对端一律拒绝
+fn pin_remote_static(
+    peer: &HostId,
+    remote_static: Option<&[u8]>,
+) -> std::result::Result<(), BindingError> {
+    let actual = Fingerprint::of(remote_static.ok_or(BindingError::Malformed)?);
+    peer_table().pin_key(peer, actual).map_err(|pinned| {
+        warn!("{peer} presented static key {actual}, but {pinned} was pinned");
+        audit(AuditEvent::key_changed(peer, actual));
+        BindingError::KeyMismatch { pinned, actual }
+    })
+}
+
+/// 校验对端的静态公钥与在握手负载中发来的通道绑定，记录协商结果与对端声明的主机信息
+fn verify_binding(
+    payload: &[u8],
+    remote_static: Option<&[u8]>,
+    peer: &HostId,
+    local: &ChannelBinding,
+) -> Result<()> {
+    let binding = ChannelBinding::from_payload(payload)
+        .and_then(|binding| {
+            binding.verify(peer, local.sender(), observable_addrs())?;
+            pin_remote_static(peer, remote_static)?;
+            Ok(binding)
+        })
+        .inspect_err(|err| audit(AuditEvent::handshake_failed(peer, err)))?;
+    // verify 已经排除了主版本不同的情况
+    let version = binding.version().min(local.version());
+    let negotiated = Negotiated {
+        version,
+        capabilities: local.capabilities() & binding.capabilities(),
+    };
+    debug!(
+        "Negotiated with {peer}: {version}, {:?}",
+        negotiated.capabilities
+    );
+    record_negotiated(peer, negotiated);
+    if negotiated.capabilities.contains(Capabilities::PLAINTEXT_DATA) {
+        warn!("Data exchanged with {peer} will NOT be encrypted, only the handshake and control messages are");
+        audit(AuditEvent::policy_override(peer, "plaintext data negotiated"));
+    }
+    if let Some(metadata) = binding.metadata() {
+        debug!("{peer} is {} listening on {}", metadata.host_name, metadata.listen_port);
+    }
+    peer_table().identified(
+        peer,
+        binding.version(),
+        binding.capabilities(),
+        binding.metadata(),
+    );
+    Ok(())
+}
+
+/// 双方都支持时协调换钥，否则沿用按报文数的隐式换钥
+fn transport(peer: &HostId, state: snow::HandshakeState) -> Result<Session> {
+    let transport = Transport::new(state)?;
+    let transport = match peer_capabilities(peer).contains(Capabilities::REKEY) {
+        true => transport.coordinated(rekeying().policy()),
+        false => transport,
+    };
+    Ok(Session::Transport(transport))
 }
 
-const PATTERN: &str = "Noise_XX_25519_AESGCM_BLAKE2b";
+pub(crate) const PATTERN: &str = "Noise_XX_25519_AESGCM_BLAKE2b";
 
 impl Session {
-    fn new_initiator() -> Self {
-        Session::Initiator(
-            snow::Builder::new(PATTERN.parse().unwrap())
-                .local_private_key(b"123")
-                .build_initiator()
-                .unwrap(),
-        )
+    fn new_initiator() -> Result<Self> {
+        let keys = static_keys()?;
+        Ok(Session::Initiator(
+            snow::Builder::new(PATTERN.parse()?)
+                .local_private_key(keys.private())
+                .build_initiator()?,
+        ))
     }
 
-    fn new_responder() -> Self {
-        Session::Initiator(
-            snow::Builder::new(PATTERN.parse().unwrap())
-                .local_private_key(b"321")
-                .build_responder()
-                .unwrap(),
-        )
+    fn new_responder() -> Result<Self> {
+        let keys = static_keys()?;
+        Ok(Session::Responder(
+            snow::Builder::new(PATTERN.parse()?)
+                .local_private_key(keys.private())
+                .build_responder()?,
+        ))
     }
 
     pub fn initiator_mut(&mut self) -> Result<&mut snow::HandshakeState> {
         match self {
             Session::Initiator(s) => Ok(s),
-            Session::Responder(_) | Session::Transport(_) => Err(anyhow!("not initiator")),
+            Session::Responder(_) | Session::Transport(_) => Err(SessionError::NotInitiator),
         }
     }
 
     pub fn responder_mut(&mut self) -> Result<&mut snow::HandshakeState> {
         match self {
             Session::Responder(s) => Ok(s),
-            Session::Initiator(_) | Session::Transport(_) => Err(anyhow!("not responder")),
+            Session::Initiator(_) | Session::Transport(_) => Err(SessionError::NotResponder),
         }
     }
 
@@ -96,7 +219,7 @@ impl Session {
     /// 通常由gui事件发起
     pub fn hello(&mut self, mut buf: BytesMut) -> Result<Bytes> {
         if !self.is_initialtor() {
-            return Err(anyhow!("not initiator"));
+            return Err(SessionError::NotInitiator);
         }
         let state = self.initiator_mut()?;
         // -> e,ee
@@ -106,54 +229,64 @@ impl Session {
     }
 
     /// exchange key mainly
-    pub fn exchange(&mut self, msg: Vec<u8>, mut buf: BytesMut) -> Result<Bytes> {
+    pub fn exchange(
+        &mut self,
+        peer: &HostId,
+        msg: Vec<u8>,
+        mut buf: BytesMut,
+        binding: &ChannelBinding,
+    ) -> Result<Bytes> {
+        let mut read_buf = vec![0u8; msg.len()];
         match self {
             Session::Initiator(state) => {
                 // <- e,ee,s,es
-                state.read_message(&msg, &mut buf)?;
+                let sz = state.read_message(&msg, &mut read_buf)?;
+                verify_binding(&read_buf[..sz], state.get_remote_static(), peer, binding)?;
                 // -> s,es
-                let sz = state.write_message(&[], &mut buf)?;
+                let sz = state.write_message(&binding.to_payload(), &mut buf)?;
                 let payload = buf.split_to(sz).freeze();
                 Ok(payload)
             }
             Session::Responder(state) => {
                 // <- e,ee
-                state.read_message(&msg, &mut buf)?;
+                state.read_message(&msg, &mut read_buf)?;
                 // -> e,ee,s,es
-                let sz = state.write_message(&[], &mut buf)?;
+                let sz = state.write_message(&binding.to_payload(), &mut buf)?;
                 let payload = buf.split_to(sz).freeze();
                 Ok(payload)
             }
-            Session::Transport(_) => Err(anyhow!(
-                "Incorrect use of transport session during exchange"
-            )),
+            Session::Transport(_) => Err(SessionError::AlreadyTransport),
         }
     }
 
     // into transport mode
-    pub fn full_with_msg(self, msg: Vec<u8>, mut buf: BytesMut) -> Result<Self> {
+    pub fn full_with_msg(
+        self,
+        peer: &HostId,
+        msg: Vec<u8>,
+        _buf: BytesMut,
+        binding: &ChannelBinding,
+    ) -> Result<Self> {
         use Session::*;
         match self {
             Responder(mut state) => {
                 // <- s,es
-                state.read_message(&msg, &mut buf)?;
-                let session = Session::Transport(state.into_transport_mode()?);
-                Ok(session)
+                let mut read_buf = vec![0u8; msg.len()];
+                let sz = state.read_message(&msg, &mut read_buf)?;
+                verify_binding(&read_buf[..sz], state.get_remote_static(), peer, binding)?;
+                transport(peer, state)
             }
-            Initiator(_) => Err(anyhow!("not responder, no need msg to full")),
-            Transport(_) => Err(anyhow!("alread handshaked")),
+            Initiator(_) => Err(SessionError::NotResponder),
+            Transport(_) => Err(SessionError::AlreadyTransport),
         }
     }
 
-    pub fn full(self) -> Result<Self> {
+    pub fn full(self, peer: &HostId) -> Result<Self> {
         use Session::*;
         match self {
-            Initiator(state) => {
-                let session = Session::Transport(state.into_transport_mode()?);
-                Ok(session)
-            }
-            Responder(_) => Err(anyhow!("not initiator, need msg to full")),
-            Transport(_) => Err(anyhow!("alread handshaked")),
+            Initiator(state) => transport(peer, state),
+            Responder(_) => Err(SessionError::NotInitiator),
+            Transport(_) => Err(SessionError::AlreadyTransport),
         }
     }
 
@@ -0,0 +1,690 @@
+use super::{
+    MAX_MSG_LEN, Replay, ReplayWindow, Session, SessionError, plaintext_data, session_table, touch,
+};
+use crate::config::{ConfigItem, ConfigManager};
+use crate::inbound::{HostId, Msg, compress_for, decompress};
+use snow::{HandshakeState, StatelessTransportState};
+use std::{
+    borrow::Cow,
+    sync::{OnceLock, RwLock},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+type Result<T> = std::result::Result<T, SessionError>;
+
+/// 每个方向发出这么多条报文后换一次密钥
+pub const REKEY_INTERVAL: u64 = 1 << 16;
+/// 换钥通告迟迟没有确认时，随之后的报文重发的间隔
+const ANNOUNCE_RETRY: Duration = Duration::from_secs(1);
+/// AEAD 标签长度
+const TAG_LEN: usize = 16;
+/// 由当前纪元的密钥派生下一纪元的密钥
+const REKEY_CONTEXT: &str = "falcon_transfer 2025 session rekey";
+/// 明文首字节，标记内层报文是否经过压缩
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+/// 协调换钥的阈值，任一项达到即换钥，为零的一项不生效
+///
+/// 报文数达到 `REKEY_INTERVAL` 时总会换钥
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    pub bytes: u64,
+    pub interval: Duration,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            bytes: 1 << 30,
+            interval: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RekeyPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            bytes: cfg
+                .get_typed(ConfigItem::RekeyBytes)
+                .await
+                .unwrap_or(default.bytes),
+            interval: cfg
+                .get_typed(ConfigItem::RekeyIntervalSecs)
+                .await
+                .map(Duration::from_secs)
+                .unwrap_or(default.interval),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rekeying {
+    policy: RwLock<RekeyPolicy>,
+}
+
+pub fn rekeying() -> &'static Rekeying {
+    static REKEYING: OnceLock<Rekeying> = OnceLock::new();
+    REKEYING.get_or_init(Rekeying::default)
+}
+
+impl Rekeying {
+    /// 只影响之后建立的会话
+    pub fn configure(&self, policy: RekeyPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> RekeyPolicy {
+        *self.policy.read().unwrap()
+    }
+}
+
+/// 握手完成后的加密通道
+///
+/// UDP 会丢包乱序，所以使用显式 nonce 的无状态传输，nonce 随报文发送。
+/// 默认每 `REKEY_INTERVAL` 条报文隐式换钥，纪元由 nonce 推出，接收方看到更新的纪元时跟着换钥；
+/// 双方都支持 `Capabilities::REKEY` 时改为协调换钥：发送方以 `Msg::Rekey` 通告下一纪元，
+/// 收到确认前继续使用旧密钥，传输不必停顿
+///
+/// 密钥由本端派生，下一纪元的报文先试解，认证通过后才提交换钥
+pub struct Transport {
+    state: StatelessTransportState,
+    /// 当前纪元的收发密钥
+    send_key: [u8; 32],
+    recv_key: [u8; 32],
+    next_nonce: u64,
+    send_epoch: u32,
+    recv_epoch: u32,
+    /// 换钥后重新开始，旧纪元的迟到报文已无法解密
+    window: ReplayWindow,
+    /// 为 None 时隐式换钥
+    policy: Option<RekeyPolicy>,
+    /// 当前发送纪元的起始时间、起始 nonce 与已加密的字节数
+    epoch_started: Instant,
+    epoch_nonce: u64,
+    epoch_bytes: u64,
+    /// 已通告、等待确认的下一发送纪元，及最近一次通告的时间
+    announced: Option<(u32, Instant)>,
+    /// 对端通告过的下一接收纪元
+    peer_next: Option<u32>,
+    /// 最近一次加密、解开报文的时间，保活也计入
+    last_sent: Instant,
+    last_received: Instant,
+    /// 最近一次收发保活以外的报文的时间
+    last_active: Instant,
+}
+
+/// 加密后的报文，nonce 与纪元以明文随行
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sealed {
+    pub epoch: u32,
+    pub nonce: u64,
+    pub body: Vec<u8>,
+}
+
+impl Transport {
+    /// 握手必须已经完成
+    pub fn new(mut handshake: HandshakeState) -> std::result::Result<Self, snow::Error> {
+        let (initiator, responder) = handshake.dangerously_get_raw_split();
+        let (send_key, recv_key) = match handshake.is_initiator() {
+            true => (initiator, responder),
+            false => (responder, initiator),
+        };
+        Ok(Self {
+            state: handshake.into_stateless_transport_mode()?,
+            send_key,
+            recv_key,
+            next_nonce: 0,
+            send_epoch: 0,
+            recv_epoch: 0,
+            window: ReplayWindow::default(),
+            policy: None,
+            epoch_started: Instant::now(),
+            epoch_nonce: 0,
+            epoch_bytes: 0,
+            announced: None,
+            peer_next: None,
+            last_sent: Instant::now(),
+            last_received: Instant::now(),
+            last_active: Instant::now(),
+        })
+    }
+
+    /// 改为按策略与对端协调换钥，双方必须一致
+    pub fn coordinated(mut self, policy: RekeyPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn is_coordinated(&self) -> bool {
+        self.policy.is_some()
+    }
+
+    pub fn send_epoch(&self) -> u32 {
+        self.send_epoch
+    }
+
+    pub fn recv_epoch(&self) -> u32 {
+        self.recv_epoch
+    }
+
+    /// 收发了保活以外的报文，会话仍在使用
+    pub fn touch(&mut self) {
+        self.last_active = Instant::now();
+    }
+
+    /// 距最近一次收发保活以外的报文
+    pub fn idle_for(&self) -> Duration {
+        self.last_active.elapsed()
+    }
+
+    /// 距最近一次发出报文
+    pub fn quiet_for(&self) -> Duration {
+        self.last_sent.elapsed()
+    }
+
+    /// 距最近一次收到对端的报文
+    pub fn silent_for(&self) -> Duration {
+        self.last_received.elapsed()
+    }
+
+    fn next_key(key: &[u8; 32]) -> [u8; 32] {
+        blake3::derive_key(REKEY_CONTEXT, key)
+    }
+
+    fn install_outgoing(&mut self, key: &[u8; 32]) {
+        match self.state.is_initiator() {
+            true => self.state.rekey_initiator_manually(key),
+            false => self.state.rekey_responder_manually(key),
+        }
+    }
+
+    fn install_incoming(&mut self, key: &[u8; 32]) {
+        match self.state.is_initiator() {
+            true => self.state.rekey_responder_manually(key),
+            false => self.state.rekey_initiator_manually(key),
+        }
+    }
+
+    fn rekey_outgoing(&mut self) {
+        self.send_key = Self::next_key(&self.send_key);
+        let key = self.send_key;
+        self.install_outgoing(&key);
+    }
+
+    fn epoch_of(nonce: u64) -> u32 {
+        (nonce / REKEY_INTERVAL) as u32
+    }
+
+    pub fn seal(&mut self, plain: &[u8]) -> Result<Sealed> {
+        if plain.len() + TAG_LEN > MAX_MSG_LEN {
+            return Err(snow::Error::Input.into());
+        }
+        let nonce = self.next_nonce;
+        // 纪元耗尽前会话早已过期，这里只防止溢出
+        let next = nonce.checked_add(1).ok_or(snow::Error::Input)?;
+        if !self.is_coordinated() {
+            let epoch = Self::epoch_of(nonce);
+            while self.send_epoch < epoch {
+                self.rekey_outgoing();
+                self.send_epoch += 1;
+            }
+        }
+        let mut body = vec![0; plain.len() + TAG_LEN];
+        let len = self.state.write_message(nonce, plain, &mut body)?;
+        body.truncate(len);
+        self.next_nonce = next;
+        self.epoch_bytes += plain.len() as u64;
+        self.last_sent = Instant::now();
+        Ok(Sealed {
+            epoch: self.send_epoch,
+            nonce,
+            body,
+        })
+    }
+
+    /// 协调换钥时，达到阈值或上次通告超时未确认则返回要通告的纪元
+    pub fn announcement(&mut self) -> Option<u32> {
+        let policy = self.policy?;
+        let now = Instant::now();
+        if let Some((epoch, at)) = self.announced {
+            if now.duration_since(at) < ANNOUNCE_RETRY {
+                return None;
+            }
+            self.announced = Some((epoch, now));
+            return Some(epoch);
+        }
+        let due = self.next_nonce - self.epoch_nonce >= REKEY_INTERVAL
+            || (policy.bytes > 0 && self.epoch_bytes >= policy.bytes)
+            || (!policy.interval.is_zero()
+                && now.duration_since(self.epoch_started) >= policy.interval);
+        if !due {
+            return None;
+        }
+        let epoch = self.send_epoch.checked_add(1)?;
+        self.announced = Some((epoch, now));
+        Some(epoch)
+    }
+
+    /// 对端确认通告后切换发送密钥，之前发出的报文仍按旧纪元解密
+    pub fn confirm(&mut self, epoch: u32) -> bool {
+        if self.announced.map(|(announced, _)| announced) != Some(epoch) {
+            return false;
+        }
+        self.rekey_outgoing();
+        self.send_epoch = epoch;
+        self.epoch_started = Instant::now();
+        self.epoch_nonce = self.next_nonce;
+        self.epoch_bytes = 0;
+        self.announced = None;
+        true
+    }
+
+    /// 记下对端通告的纪元，只接受当前纪元或紧接着的下一个；返回是否应当确认
+    ///
+    /// 确认丢失时对端会重发通告，此时仍需再次确认
+    pub fn accept(&mut self, epoch: u32) -> bool {
+        if !self.is_coordinated() {
+            return false;
+        }
+        if Some(epoch) == self.recv_epoch.checked_add(1) {
+            self.peer_next = Some(epoch);
+        }
+        epoch == self.recv_epoch || self.peer_next == Some(epoch)
+    }
+
+    pub fn open(&mut self, sealed: &Sealed) -> Result<Vec<u8>> {
+        let acceptable = match self.is_coordinated() {
+            // 下一纪元必须先经旧密钥保护的通告声明过
+            true => sealed.epoch == self.recv_epoch || self.peer_next == Some(sealed.epoch),
+            // 纪元必须与 nonce 自洽，且只接受当前或下一个纪元
+            false => {
+                sealed.epoch == Self::epoch_of(sealed.nonce)
+                    && sealed.epoch >= self.recv_epoch
+                    && sealed.epoch <= self.recv_epoch + 1
+            }
+        };
+        if !acceptable {
+            return Err(snow::Error::Decrypt.into());
+        }
+        if sealed.epoch > self.recv_epoch {
+            // 隐式换钥时至少要已经收到当前纪元的后半段，免得每个伪造报文都要试解一次
+            let boundary = u64::from(sealed.epoch) * REKEY_INTERVAL;
+            if !self.is_coordinated()
+                && self
+                    .window
+                    .highest()
+                    .is_none_or(|highest| highest < boundary - REKEY_INTERVAL / 2)
+            {
+                return Err(snow::Error::Decrypt.into());
+            }
+            // 发送方的 nonce 单调递增，新纪元的报文不会早于旧纪元收到过的
+            if self
+                .window
+                .highest()
+                .is_some_and(|highest| sealed.nonce <= highest)
+            {
+                return Err(snow::Error::Decrypt.into());
+            }
+            // 用下一纪元的密钥试解，认证失败就换回当前密钥，纪元与窗口都不动
+            let next = Self::next_key(&self.recv_key);
+            self.install_incoming(&next);
+            let plain = match self.read(sealed) {
+                Ok(plain) => plain,
+                Err(err) => {
+                    let current = self.recv_key;
+                    self.install_incoming(&current);
+                    return Err(err);
+                }
+            };
+            self.recv_key = next;
+            self.recv_epoch = sealed.epoch;
+            self.window = ReplayWindow::default();
+            self.peer_next = None;
+            return self.admit(sealed.nonce, plain);
+        }
+        let plain = self.read(sealed)?;
+        self.admit(sealed.nonce, plain)
+    }
+
+    fn read(&self, sealed: &Sealed) -> Result<Vec<u8>> {
+        let mut plain = vec![0; sealed.body.len()];
+        let len = self
+            .state
+            .read_message(sealed.nonce, &sealed.body, &mut plain)?;
+        plain.truncate(len);
+        Ok(plain)
+    }
+
+    /// 解密成功才计入窗口，伪造的报文不会挤掉真实的序号
+    fn admit(&mut self, nonce: u64, plain: Vec<u8>) -> Result<Vec<u8>> {
+        match self.window.check(nonce) {
+            Replay::Fresh => {
+                self.last_received = Instant::now();
+                Ok(plain)
+            }
+            _ => Err(snow::Error::Decrypt.into()),
+        }
+    }
+}
+
+/// 加密发往 host 的会话层报文，其他报文原样返回
+///
+/// 会话层报文只能以密文发出，握手完成之前返回 `SessionError::NotFound`
+pub fn seal_msg(host: &HostId, msg: Msg) -> Result<Msg> {
+    if !msg.is_session() {
+        return Ok(msg);
+    }
+    let mut session = session_table()
+        .get_mut(host)
+        .ok_or(SessionError::NotFound)?;
+    let Session::Transport(transport) = &mut *session else {
+        return Err(SessionError::NotFound);
+    };
+    if !matches!(msg, Msg::Keepalive { .. }) {
+        transport.touch();
+    }
+    if plaintext_data().applies(host, &msg) {
+        return Ok(msg);
+    }
+    let encoded = bincode::encode_to_vec(&msg, bincode::config::standard())?;
+    // 压缩必须在加密之前
+    let plain = match compress_for(host, &encoded) {
+        Some(compressed) => [&[COMPRESSED], compressed.as_slice()].concat(),
+        None => [&[RAW], encoded.as_slice()].concat(),
+    };
+    let epoch = transport.send_epoch();
+    let sealed = transport.seal(&plain)?;
+    if sealed.epoch != epoch {
+        info!("Rekeyed outgoing session to {host}, epoch {}", sealed.epoch);
+    }
+    Ok(Msg::Sealed {
+        host: msg.host().clone(),
+        control: msg.is_control(),
+        epoch: sealed.epoch,
+        nonce: sealed.nonce,
+        body: sealed.body,
+    })
+}
+
+/// 解开 host 发来的加密报文，内层必须是同一对端发出的会话层报文
+pub fn open_msg(host: &HostId, sealed: &Sealed) -> Result<Msg> {
+    let plain = match session_table().get_mut(host).as_deref_mut() {
+        Some(Session::Transport(transport)) => {
+            let epoch = transport.recv_epoch();
+            let plain = transport.open(sealed)?;
+            if transport.recv_epoch() != epoch {
+                info!(
+                    "Rekeyed incoming session from {host}, epoch {}",
+                    sealed.epoch
+                );
+            }
+            plain
+        }
+        _ => return Err(SessionError::NotFound),
+    };
+    let (flag, body) = plain.split_first().ok_or(SessionError::Malformed)?;
+    let body = match *flag {
+        RAW => Cow::Borrowed(body),
+        COMPRESSED => Cow::Owned(decompress(body).map_err(|_| SessionError::Malformed)?),
+        _ => return Err(SessionError::Malformed),
+    };
+    let (msg, _) = bincode::decode_from_slice::<Msg, _>(&body, bincode::config::standard())
+        .map_err(|_| SessionError::Malformed)?;
+    if !msg.is_session() || msg.host() != host {
+        return Err(SessionError::Malformed);
+    }
+    if !matches!(msg, Msg::Keepalive { .. }) {
+        touch(host);
+    }
+    Ok(msg)
+}
+
+/// 握手完成后，对端的会话层报文只能以密文到达
+pub fn is_established(host: &HostId) -> bool {
+    session_table()
+        .get(host)
+        .is_some_and(|session| session.is_transport())
+}
+
+/// 该向 host 通告换钥时返回通告，由发送路径插在下一条会话层报文之前
+///
+/// 只在有报文要发时检查，空闲的会话等到恢复发送时再换钥
+pub fn rekey_announcement(host: &HostId, local: &HostId) -> Option<Msg> {
+    let mut session = session_table().get_mut(host)?;
+    let Session::Transport(transport) = &mut *session else {
+        return None;
+    };
+    let epoch = transport.announcement()?;
+    debug!("Announce rekey to epoch {epoch} for {host}");
+    Some(Msg::Rekey {
+        host: local.clone(),
+        epoch,
+        ack: false,
+    })
+}
+
+/// 处理 host 以密文发来的换钥消息，需要回复确认时返回确认
+pub fn on_rekey(host: &HostId, local: &HostId, epoch: u32, ack: bool) -> Option<Msg> {
+    let mut session = session_table().get_mut(host)?;
+    let Session::Transport(transport) = &mut *session else {
+        return None;
+    };
+    if ack {
+        if transport.confirm(epoch) {
+            info!("Rekeyed outgoing session to {host}, epoch {epoch}");
+        }
+        return None;
+    }
+    if !transport.accept(epoch) {
+        debug!("Ignore rekey to epoch {epoch} from {host}");
+        return None;
+    }
+    debug!("Accept rekey to epoch {epoch} from {host}");
+    Some(Msg::Rekey {
+        host: local.clone(),
+        epoch,
+        ack: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inbound::{Capabilities, ProtocolVersion},
+        session::{Negotiated, PATTERN, record_negotiated},
+    };
+
+    fn pair() -> (Transport, Transport) {
+        let builder = || snow::Builder::new(PATTERN.parse().unwrap());
+        let keys = builder().generate_keypair().unwrap();
+        let mut initiator = builder()
+            .local_private_key(&keys.private)
+            .build_initiator()
+            .unwrap();
+        let keys = builder().generate_keypair().unwrap();
+        let mut responder = builder()
+            .local_private_key(&keys.private)
+            .build_responder()
+            .unwrap();
+        let (mut buf, mut read) = (vec![0; MAX_MSG_LEN], vec![0; MAX_MSG_LEN]);
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        responder.read_message(&buf[..len], &mut read).unwrap();
+        let len = responder.write_message(&[], &mut buf).unwrap();
+        initiator.read_message(&buf[..len], &mut read).unwrap();
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        responder.read_message(&buf[..len], &mut read).unwrap();
+        (
+            Transport::new(initiator).unwrap(),
+            Transport::new(responder).unwrap(),
+        )
+    }
+
+    #[test]
+    fn reorder_and_replay() {
+        let (mut a, mut b) = pair();
+        let first = a.seal(b"falcon").unwrap();
+        let second = a.seal(b"transfer").unwrap();
+        assert_ne!(first.body, b"falcon");
+        // 乱序到达也能解开，重放的报文被拒绝
+        assert_eq!(b.open(&second).unwrap(), b"transfer");
+        assert_eq!(b.open(&first).unwrap(), b"falcon");
+        assert!(b.open(&first).is_err());
+
+        let mut forged = a.seal(b"114514").unwrap();
+        forged.body[0] ^= 1;
+        assert!(b.open(&forged).is_err());
+    }
+
+    #[test]
+    fn rekey_after_interval() {
+        let (mut a, mut b) = pair();
+        a.next_nonce = REKEY_INTERVAL - 1;
+        let last = a.seal(b"old").unwrap();
+        let rekeyed = a.seal(b"new").unwrap();
+        assert_eq!((last.epoch, rekeyed.epoch), (0, 1));
+        assert_eq!(b.open(&last).unwrap(), b"old");
+        assert_eq!(b.open(&rekeyed).unwrap(), b"new");
+        // 旧纪元的迟到报文无法再解开
+        assert!(b.open(&last).is_err());
+        // 纪元与 nonce 不一致的报文直接拒绝
+        let mut skewed = a.seal(b"skew").unwrap();
+        skewed.epoch = 2;
+        assert!(b.open(&skewed).is_err());
+    }
+
+    #[test]
+    fn coordinated_rekey() {
+        let (a, b) = pair();
+        let policy = RekeyPolicy {
+            bytes: 8,
+            interval: Duration::ZERO,
+        };
+        let (mut a, mut b) = (a.coordinated(policy), b.coordinated(policy));
+        assert_eq!(a.announcement(), None);
+        let old = a.seal(b"before rekey").unwrap();
+        assert_eq!(a.announcement(), Some(1));
+        // 重发间隔内不重复通告
+        assert_eq!(a.announcement(), None);
+
+        // 没有通告过的下一纪元直接拒绝
+        let mut forged = a.seal(b"forged").unwrap();
+        forged.epoch = 1;
+        assert!(b.open(&forged).is_err());
+        assert!(b.accept(1));
+        assert!(!b.accept(3));
+        // 通告之后，认证不过的下一纪元报文也不会让接收方提前换钥
+        assert!(b.open(&forged).is_err());
+        assert_eq!(b.recv_epoch(), 0);
+
+        // 确认之前仍用旧密钥，在途的报文都能解开
+        let in_flight = a.seal(b"in flight").unwrap();
+        assert_eq!(in_flight.epoch, 0);
+        assert!(a.confirm(1));
+        assert!(!a.confirm(1));
+        // 新纪元的计数重新开始
+        assert_eq!(a.announcement(), None);
+        let rekeyed = a.seal(b"after rekey").unwrap();
+        assert_eq!(rekeyed.epoch, 1);
+        assert_eq!(b.open(&old).unwrap(), b"before rekey");
+        assert_eq!(b.open(&in_flight).unwrap(), b"in flight");
+        assert_eq!(b.open(&rekeyed).unwrap(), b"after rekey");
+        assert_eq!(b.recv_epoch(), 1);
+    }
+
+    #[test]
+    fn seal_session_messages() {
+        let (a, b) = pair();
+        let (host_a, host_b) = (HostId::random(), HostId::random());
+        session_table().insert(host_b.clone(), Session::Transport(a));
+        session_table().insert(host_a.clone(), Session::Transport(b));
+        record_negotiated(
+            &host_b,
+            Negotiated {
+                version: ProtocolVersion::CURRENT,
+                capabilities: Capabilities::DATA_COMPRESSION,
+            },
+        );
+        assert!(is_established(&host_b));
+
+        let msg = Msg::Transfer {
+            host: host_a.clone(),
+            payload: b"falcon".repeat(1000),
+        };
+        let Msg::Sealed {
+            control: false,
+            epoch,
+            nonce,
+            body,
+            ..
+        } = seal_msg(&host_b, msg.clone()).unwrap()
+        else {
+            panic!("data should be sealed after handshake");
+        };
+        // 先压缩再加密
+        assert!(body.len() < 1000);
+        let sealed = Sealed { epoch, nonce, body };
+        assert_eq!(open_msg(&host_a, &sealed).unwrap(), msg);
+        // 其他对端的会话解不开
+        assert!(open_msg(&host_b, &sealed).is_err());
+
+        // 链路层报文不加密
+        let ping = Msg::Ping {
+            host: host_a.clone(),
+            nonce: 0,
+        };
+        assert_eq!(seal_msg(&host_b, ping.clone()).unwrap(), ping);
+
+        // 没有会话时会话层报文不能以明文发出
+        let stranger = HostId::random();
+        assert!(matches!(
+            seal_msg(&stranger, msg),
+            Err(SessionError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn plaintext_data_when_negotiated() {
+        let (a, _) = pair();
+        let (host_a, host_b) = (HostId::random(), HostId::random());
+        session_table().insert(host_b.clone(), Session::Transport(a));
+        record_negotiated(
+            &host_b,
+            Negotiated {
+                version: ProtocolVersion::CURRENT,
+                capabilities: Capabilities::PLAINTEXT_DATA,
+            },
+        );
+        let data = Msg::Transfer {
+            host: host_a.clone(),
+            payload: b"falcon".to_vec(),
+        };
+        assert_eq!(seal_msg(&host_b, data.clone()).unwrap(), data);
+        // 控制消息照常加密
+        let fetch = Msg::Fetch {
+            host: host_a.clone(),
+            hash: 42,
+            token: None,
+        };
+        assert!(matches!(
+            seal_msg(&host_b, fetch).unwrap(),
+            Msg::Sealed { control: true, .. }
+        ));
+
+        // 对个别对端强制恢复加密
+        plaintext_data().set_encrypted(&host_b, true);
+        assert!(plaintext_data().is_encrypted(&host_b));
+        assert!(matches!(
+            seal_msg(&host_b, data).unwrap(),
+            Msg::Sealed { control: false, .. }
+        ));
+        plaintext_data().set_encrypted(&host_b, false);
+        assert!(!plaintext_data().is_encrypted(&host_b));
+        // 没有协商的对端总是加密
+        assert!(plaintext_data().is_encrypted(&host_a));
+    }
+}
@@ -0,0 +1,76 @@
+use std::{future::Future, sync::OnceLock};
+use tokio::task::JoinHandle;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::info;
+
+/// 把各个子系统串在一起的关停信号
+///
+/// 子系统用 `spawn` 启动后台任务，并在 `cancelled` 触发后自行收尾（排空队列、落盘等），
+/// `shutdown` 会等所有任务退出后才返回
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    cancel: CancellationToken,
+    tracker: TaskTracker,
+}
+
+pub fn shutdown_token() -> &'static ShutdownToken {
+    static SHUTDOWN_TOKEN: OnceLock<ShutdownToken> = OnceLock::new();
+    SHUTDOWN_TOKEN.get_or_init(ShutdownToken::new)
+}
+
+impl ShutdownToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tracker.spawn(task)
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// 发出关停信号并等待所有登记的任务结束
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        self.tracker.close();
+        self.tracker.wait().await;
+        info!("All subsystems have stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    #[tokio::test]
+    async fn wait_for_cleanup() {
+        let token = ShutdownToken::new();
+        let cleaned = Arc::new(AtomicBool::new(false));
+        token.spawn({
+            let token = token.clone();
+            let cleaned = cleaned.clone();
+            async move {
+                token.cancelled().await;
+                tokio::task::yield_now().await;
+                cleaned.store(true, Ordering::Release);
+            }
+        });
+        token.shutdown().await;
+        assert!(token.is_shutdown());
+        assert!(cleaned.load(Ordering::Acquire));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::hot_file::{FileMultiRange, FileRange};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 发出后这么久仍未被确认的范围会重新发送
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(3);
+/// 接收端每收到这么多字节确认一次
+pub const ACK_EVERY_BYTES: usize = 1024 * 1024;
+
+/// 已发出但尚未被确认的范围
+///
+/// 在途字节数受窗口限制，窗口满后发送端停止读盘，等确认或超时腾出空间
+#[derive(Debug)]
+pub struct Outstanding {
+    sent: Vec<(FileRange, Instant)>,
+    window: usize,
+    inflight: usize,
+}
+
+impl Default for Outstanding {
+    /// 不限制在途字节数
+    fn default() -> Self {
+        Self::with_window(usize::MAX)
+    }
+}
+
+impl Outstanding {
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            sent: Vec::new(),
+            window,
+            inflight: 0,
+        }
+    }
+
+    pub fn sent(&mut self, rgn: FileRange) {
+        self.inflight += rgn.interval();
+        self.sent.push((rgn, Instant::now()));
+    }
+
+    /// 已发出、尚未确认的字节数
+    pub fn inflight(&self) -> usize {
+        self.inflight
+    }
+
+    /// 窗口内还能再发出的字节数
+    pub fn room(&self) -> usize {
+        self.window.saturating_sub(self.inflight)
+    }
+
+    /// 去掉对端已确认的部分，部分确认的范围只保留缺失的片段
+    pub fn acknowledge(&mut self, received: &FileMultiRange) {
+        self.sent = self
+            .sent
+            .drain(..)
+            .flat_map(|(rgn, at)| {
+                FileMultiRange::from(rgn)
+                    .subtract(received)
+                    .iter()
+                    .map(|missing| (*missing, at))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.inflight = self.sent.iter().map(|(rgn, _)| rgn.interval()).sum();
+    }
+
+    /// 移除超时未确认的范围并返回，调用方据此重传
+    pub fn expire(&mut self, timeout: Duration) -> FileMultiRange {
+        let mut expired = FileMultiRange::new();
+        self.sent.retain(|(rgn, at)| {
+            let alive = at.elapsed() < timeout;
+            if !alive {
+                expired.add(*rgn);
+                self.inflight -= rgn.interval();
+            }
+            alive
+        });
+        expired
+    }
+
+    /// 仍在途中的范围，计算待发送部分时需要排除
+    pub fn ranges(&self) -> FileMultiRange {
+        let mut ranges = FileMultiRange::new();
+        for (rgn, _) in &self.sent {
+            ranges.add(*rgn);
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn retransmit_unacked() {
+        let mut outstanding = Outstanding::default();
+        outstanding.sent(FileRange::new(0, 100));
+        outstanding.sent(FileRange::new(100, 200));
+        outstanding.acknowledge(&FileRange::new(0, 150).into());
+        assert_eq!(outstanding.ranges(), FileRange::new(150, 200).into());
+        assert!(outstanding.expire(RETRANSMIT_TIMEOUT).is_empty());
+        tokio::time::advance(RETRANSMIT_TIMEOUT).await;
+        assert_eq!(
+            outstanding.expire(RETRANSMIT_TIMEOUT),
+            FileRange::new(150, 200).into()
+        );
+        assert!(outstanding.ranges().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn window_bounds_inflight() {
+        let mut outstanding = Outstanding::with_window(300);
+        outstanding.sent(FileRange::new(0, 100));
+        outstanding.sent(FileRange::new(100, 300));
+        assert_eq!(outstanding.inflight(), 300);
+        assert_eq!(outstanding.room(), 0);
+        // 部分确认只释放已确认的字节
+        outstanding.acknowledge(&FileRange::new(0, 150).into());
+        assert_eq!(outstanding.inflight(), 150);
+        assert_eq!(outstanding.room(), 150);
+        tokio::time::advance(RETRANSMIT_TIMEOUT).await;
+        outstanding.expire(RETRANSMIT_TIMEOUT);
+        assert_eq!(outstanding.inflight(), 0);
+        assert_eq!(outstanding.room(), 300);
+    }
+}
@@ -0,0 +1,233 @@
+use super::{FileHash, PART_SUFFIX};
+use crate::{history::HistoryRecord, hot_file::extended_path, policy::TokenBucket};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{collections::HashMap, hash::Hasher, io};
+use tokio::{fs::File, io::AsyncReadExt, task::JoinHandle};
+use tracing::{info, warn};
+use xxhash_rust::xxh3::Xxh3;
+
+/// 目录批量校验的结果，路径均相对于下载根目录
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BatchReport {
+    pub ok: Vec<Utf8PathBuf>,
+    pub corrupted: Vec<Utf8PathBuf>,
+    /// 有记录但磁盘上不存在
+    pub missing: Vec<Utf8PathBuf>,
+    /// 磁盘上存在但没有任何记录
+    pub unknown: Vec<Utf8PathBuf>,
+    /// 读取失败的文件或目录，其余文件照常校验
+    pub errors: Vec<(Utf8PathBuf, String)>,
+}
+
+/// 后台运行的批量校验任务，drop 时终止
+pub struct BatchVerifyJob {
+    handle: JoinHandle<io::Result<BatchReport>>,
+}
+
+impl BatchVerifyJob {
+    /// `max_bytes_per_sec` 为 0 表示不限速
+    pub fn spawn(
+        root: Utf8PathBuf,
+        expected: HashMap<Utf8PathBuf, FileHash>,
+        max_bytes_per_sec: u64,
+    ) -> Self {
+        let handle = tokio::spawn(async move {
+            verify_directory(&root, &expected, max_bytes_per_sec).await
+        });
+        Self { handle }
+    }
+
+    pub async fn report(mut self) -> io::Result<BatchReport> {
+        (&mut self.handle).await.map_err(io::Error::other)?
+    }
+}
+
+impl Drop for BatchVerifyJob {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// 由历史中完成的下载推出各文件应有的哈希，同一路径以最新的记录为准
+///
+/// `records` 新的在前，与 `HistoryLog::recent` 一致
+pub fn expected_from_history(
+    records: &[HistoryRecord],
+    per_peer: bool,
+) -> HashMap<Utf8PathBuf, FileHash> {
+    let mut expected = HashMap::new();
+    for record in records.iter().filter(|record| record.is_completed()) {
+        let Ok(hash) = FileHash::from_str_radix(&record.hash, 16) else {
+            continue;
+        };
+        let rel = match per_peer {
+            true => Utf8Path::new(&record.peer).join(&record.file_name),
+            false => record.file_name.as_str().into(),
+        };
+        expected.entry(rel).or_insert(hash);
+    }
+    expected
+}
+
+/// 遍历下载目录，与记录中的整文件哈希比对
+///
+/// 只有根目录无法读取时返回错误，单个文件的失败记入报告
+pub async fn verify_directory(
+    root: &Utf8Path,
+    expected: &HashMap<Utf8PathBuf, FileHash>,
+    max_bytes_per_sec: u64,
+) -> io::Result<BatchReport> {
+    let mut report = BatchReport::default();
+    let limiter = TokenBucket::new(max_bytes_per_sec);
+    let walk_root = root.to_owned();
+    let (on_disk, errors) = tokio::task::spawn_blocking(move || {
+        let (mut out, mut errors) = (Vec::new(), Vec::new());
+        collect_files(&walk_root, &walk_root, &mut out, &mut errors).map(|_| (out, errors))
+    })
+    .await
+    .map_err(io::Error::other)??;
+    report.errors = errors;
+    for rel in on_disk {
+        let Some(&hash) = expected.get(&rel) else {
+            report.unknown.push(rel);
+            continue;
+        };
+        match hash_file(&root.join(&rel), &limiter).await {
+            Ok(actual) if actual == hash => report.ok.push(rel),
+            Ok(_) => report.corrupted.push(rel),
+            Err(err) => {
+                warn!("Failed to verify {rel}: {err}");
+                report.errors.push((rel, err.to_string()));
+            }
+        }
+    }
+    report.missing = expected
+        .keys()
+        .filter(|rel| !root.join(rel).is_file())
+        .cloned()
+        .collect();
+    report.missing.sort();
+    info!(
+        "Verified {root}: {} ok, {} corrupted, {} missing, {} unknown, {} errors",
+        report.ok.len(),
+        report.corrupted.len(),
+        report.missing.len(),
+        report.unknown.len(),
+        report.errors.len()
+    );
+    Ok(report)
+}
+
+/// 同步遍历，须在阻塞线程中调用；只有 `dir` 本身无法读取时返回错误
+///
+/// 未完成下载的临时文件不参与校验
+fn collect_files(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    out: &mut Vec<Utf8PathBuf>,
+    errors: &mut Vec<(Utf8PathBuf, String)>,
+) -> io::Result<()> {
+    let relative = |path: &Utf8Path| path.strip_prefix(root).unwrap_or(path).to_owned();
+    let mut entries = Vec::new();
+    for entry in dir.read_dir_utf8()? {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(err) => errors.push((relative(dir), err.to_string())),
+        }
+    }
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    for entry in entries {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => {
+                if let Err(err) = collect_files(root, path, out, errors) {
+                    errors.push((relative(path), err.to_string()));
+                }
+            }
+            Ok(_) if path.as_str().ends_with(PART_SUFFIX) => {}
+            Ok(_) => out.push(relative(path)),
+            Err(err) => errors.push((relative(path), err.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// 流式计算整文件哈希，与 `HotFile::hash` 结果一致
+pub async fn hash_path(path: &Utf8Path) -> io::Result<FileHash> {
+    hash_file(path, &TokenBucket::unlimited()).await
+}
+
+async fn hash_file(path: &Utf8Path, limiter: &TokenBucket) -> io::Result<FileHash> {
+    const CHUNK: usize = 1024 * 1024;
+    let mut file = File::open(extended_path(path.as_std_path())).await?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        limiter.acquire(n).await;
+    }
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hot_file::HotFile;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn classify_files() {
+        let dir = tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        std::fs::create_dir(root.join("peer")).unwrap();
+        std::fs::write(root.join("peer/ok.bin"), b"falcon").unwrap();
+        std::fs::write(root.join("broken.bin"), b"falcoN").unwrap();
+        std::fs::write(root.join("extra.bin"), b"?").unwrap();
+        std::fs::write(root.join("next.bin.falcon.part"), b"fal").unwrap();
+
+        let expected = HashMap::from([
+            ("peer/ok.bin".into(), HotFile::hash([b"falcon"])),
+            ("broken.bin".into(), HotFile::hash([b"falcon"])),
+            ("gone.bin".into(), HotFile::hash([b"falcon"])),
+        ]);
+        let report = BatchVerifyJob::spawn(root, expected, 1024 * 1024)
+            .report()
+            .await
+            .unwrap();
+        assert_eq!(report.ok, vec![Utf8PathBuf::from("peer/ok.bin")]);
+        assert_eq!(report.corrupted, vec![Utf8PathBuf::from("broken.bin")]);
+        assert_eq!(report.missing, vec![Utf8PathBuf::from("gone.bin")]);
+        assert_eq!(report.unknown, vec![Utf8PathBuf::from("extra.bin")]);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn newest_completed_record_wins() {
+        use crate::{inbound::HostId, task::CompletedTransfer};
+        use std::time::Duration;
+
+        let peer = HostId::random();
+        let done = |hash| {
+            HistoryRecord::completed(&CompletedTransfer {
+                path: "/tmp/movie.mkv".into(),
+                peer: peer.clone(),
+                hash,
+                size: 6,
+                elapsed: Duration::from_secs(1),
+                copy_method: None,
+            })
+        };
+        let failed = HistoryRecord::failed("movie.mkv", 6, &peer, 3, Duration::ZERO, "x");
+        let records = [failed, done(2), done(1)];
+
+        let flat = expected_from_history(&records, false);
+        assert_eq!(flat, HashMap::from([("movie.mkv".into(), 2)]));
+        let nested = expected_from_history(&records, true);
+        let rel = Utf8Path::new(&peer.to_string()).join("movie.mkv");
+        assert_eq!(nested, HashMap::from([(rel, 2)]));
+    }
+}
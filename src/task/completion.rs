@@ -0,0 +1,22 @@
+use super::{CopyMethod, FileHash};
+use crate::inbound::HostId;
+use camino::Utf8PathBuf;
+use std::time::Duration;
+
+/// 传输完成后交给上层的记录
+#[derive(Debug, Clone)]
+pub struct CompletedTransfer {
+    pub path: Utf8PathBuf,
+    pub peer: HostId,
+    pub hash: FileHash,
+    pub size: u64,
+    pub elapsed: Duration,
+    /// 仅当走本地短路时有值，记录使用了 reflink 还是普通复制
+    pub copy_method: Option<CopyMethod>,
+}
+
+impl CompletedTransfer {
+    pub fn is_local(&self) -> bool {
+        self.copy_method.is_some()
+    }
+}
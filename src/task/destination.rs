@@ -0,0 +1,216 @@
+use super::{TaskError, part_path};
+use crate::{
+    config::{ConfigItem, ConfigManager, default_download_dir},
+    hot_file::extended_path,
+    inbound::HostId,
+};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+use tokio::fs::try_exists;
+
+/// 目标文件已存在时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// 改存为 `name (1).ext`
+    #[default]
+    Rename,
+    /// 完成时覆盖已有文件
+    Overwrite,
+    /// 不下载，由上层告知对端
+    Reject,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rename" => Ok(CollisionPolicy::Rename),
+            "overwrite" => Ok(CollisionPolicy::Overwrite),
+            "reject" => Ok(CollisionPolicy::Reject),
+            other => Err(format!(
+                "unknown collision policy: {other}, expected rename, overwrite or reject"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CollisionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollisionPolicy::Rename => f.write_str("rename"),
+            CollisionPolicy::Overwrite => f.write_str("overwrite"),
+            CollisionPolicy::Reject => f.write_str("reject"),
+        }
+    }
+}
+
+/// 接收文件的保存位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadPolicy {
+    pub root: PathBuf,
+    /// 每个对端一个以主机 id 命名的子目录
+    pub per_peer: bool,
+    pub collision: CollisionPolicy,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        Self {
+            root: default_download_dir().into(),
+            per_peer: false,
+            collision: CollisionPolicy::default(),
+        }
+    }
+}
+
+impl DownloadPolicy {
+    /// 序号用尽时视为冲突，避免目录被同名文件塞满时无限尝试
+    const MAX_RENAME: usize = 1000;
+
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            root: cfg
+                .get_typed(ConfigItem::DownloadDir)
+                .await
+                .unwrap_or(default.root),
+            per_peer: cfg
+                .get_typed(ConfigItem::DownloadPerPeer)
+                .await
+                .unwrap_or(default.per_peer),
+            collision: cfg
+                .get_typed(ConfigItem::DownloadCollision)
+                .await
+                .unwrap_or(default.collision),
+        }
+    }
+
+    pub fn dir_for(&self, peer: &HostId) -> PathBuf {
+        match self.per_peer {
+            true => self.root.join(peer.to_string()),
+            false => self.root.clone(),
+        }
+    }
+
+    /// 确定保存路径并创建所在目录，路径在返回的占用释放前不会再分给别的下载
+    ///
+    /// 临时文件总是新建，已有的临时文件不论是别的下载正在写还是上次遗留的都算冲突
+    pub async fn resolve(&self, file_name: &Path, peer: &HostId) -> Result<Claim, TaskError> {
+        let dir = self.dir_for(peer);
+        tokio::fs::create_dir_all(extended_path(&dir)).await?;
+        let target = dir.join(file_name);
+        let overwrite = self.collision == CollisionPolicy::Overwrite;
+        if let Some(claim) = claim_free(&target, overwrite).await? {
+            return Ok(claim);
+        }
+        if self.collision != CollisionPolicy::Rename {
+            return Err(TaskError::FileExists(target));
+        }
+        for n in 1..=Self::MAX_RENAME {
+            if let Some(claim) = claim_free(&numbered(&target, n), false).await? {
+                return Ok(claim);
+            }
+        }
+        Err(TaskError::FileExists(target))
+    }
+}
+
+/// 分给进行中下载的保存路径
+fn claimed() -> &'static Mutex<HashSet<PathBuf>> {
+    static CLAIMED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    CLAIMED.get_or_init(Default::default)
+}
+
+/// 下载对保存路径的占用，随任务结束释放
+#[derive(Debug)]
+pub struct Claim(PathBuf);
+
+impl Claim {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        claimed().lock().unwrap().remove(&self.0);
+    }
+}
+
+/// 路径与临时文件都空闲时占用它，`overwrite` 时允许目标文件已存在
+async fn claim_free(path: &Path, overwrite: bool) -> std::io::Result<Option<Claim>> {
+    if exists(&part_path(path)).await? || (!overwrite && exists(path).await?) {
+        return Ok(None);
+    }
+    let fresh = claimed().lock().unwrap().insert(path.to_path_buf());
+    Ok(fresh.then(|| Claim(path.to_path_buf())))
+}
+
+async fn exists(path: &Path) -> std::io::Result<bool> {
+    try_exists(extended_path(path)).await
+}
+
+/// `movie.mkv` -> `movie (1).mkv`
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_stem().unwrap_or_default());
+    name.push(format!(" ({n})"));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn resolve_collisions() {
+        let dir = tempdir().unwrap();
+        let peer = HostId::random();
+        let mut policy = DownloadPolicy {
+            root: dir.path().to_path_buf(),
+            per_peer: true,
+            collision: CollisionPolicy::Rename,
+        };
+        let name = Path::new("movie.mkv");
+        let first = policy.resolve(name, &peer).await.unwrap();
+        assert_eq!(first.path(), dir.path().join(peer.to_string()).join(name));
+
+        // 占用未释放时同名下载改存到下一个序号
+        let concurrent = policy.resolve(name, &peer).await.unwrap();
+        assert_eq!(concurrent.path().file_name().unwrap(), "movie (1).mkv");
+        let target = first.path().to_path_buf();
+        drop((first, concurrent));
+
+        std::fs::write(&target, b"falcon").unwrap();
+        std::fs::write(part_path(&numbered(&target, 1)), b"").unwrap();
+        let renamed = policy.resolve(name, &peer).await.unwrap();
+        assert_eq!(renamed.path().file_name().unwrap(), "movie (2).mkv");
+
+        policy.collision = CollisionPolicy::Overwrite;
+        assert_eq!(policy.resolve(name, &peer).await.unwrap().path(), target);
+        // 遗留的临时文件同样算冲突，覆盖也不能沿用
+        std::fs::write(part_path(&target), b"").unwrap();
+        assert!(matches!(
+            policy.resolve(name, &peer).await,
+            Err(TaskError::FileExists(_))
+        ));
+        std::fs::remove_file(part_path(&target)).unwrap();
+        policy.collision = CollisionPolicy::Reject;
+        assert!(matches!(
+            policy.resolve(name, &peer).await,
+            Err(TaskError::FileExists(_))
+        ));
+        assert_eq!(numbered(Path::new("README"), 3), Path::new("README (3)"));
+    }
+}
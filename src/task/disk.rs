@@ -0,0 +1,220 @@
+use super::{FileHash, TaskCommand, TaskCtrl, TaskError};
+use crate::config::{ConfigItem, ConfigManager};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::AbortHandle,
+    time::{MissedTickBehavior, interval},
+};
+use tracing::{info, warn};
+
+/// 磁盘空间检查策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskPolicy {
+    /// 下载时至少保留的剩余空间
+    pub low_watermark: u64,
+    pub check_interval: Duration,
+}
+
+impl Default for DiskPolicy {
+    fn default() -> Self {
+        Self {
+            low_watermark: 512 << 20,
+            check_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl DiskPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            low_watermark: cfg
+                .get_typed(ConfigItem::DiskLowWatermark)
+                .await
+                .unwrap_or(default.low_watermark),
+            check_interval: cfg
+                .get_typed(ConfigItem::DiskCheckIntervalMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.check_interval),
+        }
+    }
+
+    /// 写入 `needed` 字节后仍需留有水位线以上的空间
+    ///
+    /// 平台不支持查询时放行，由写入时的错误兜底
+    pub fn preflight(&self, dir: &Path, needed: u64) -> Result<(), TaskError> {
+        let available = match available_space(dir) {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        match needed.saturating_add(self.low_watermark) <= available {
+            true => Ok(()),
+            false => Err(TaskError::InsufficientSpace { needed, available }),
+        }
+    }
+}
+
+/// 文件所在目录，相对路径的父目录为空时取当前目录
+pub fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// 目录所在文件系统对当前用户可用的字节数
+#[cfg(target_os = "linux")]
+pub fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path 以 0 结尾，stat 仅在调用成功后读取
+    match unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } {
+        0 => {
+            let stat = unsafe { stat.assume_init() };
+            Ok(stat.f_bavail * stat.f_frsize)
+        }
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(windows)]
+pub fn available_space(dir: &Path) -> io::Result<u64> {
+    use std::{iter, os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let path = dir
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+    let mut available = 0;
+    // SAFETY: path 以 0 结尾，不需要的输出传空指针
+    match unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(available),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn available_space(_dir: &Path) -> io::Result<u64> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// 面向用户的磁盘空间事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskNotice {
+    /// 剩余空间低于水位线，下载已暂停
+    LowSpace {
+        hash: FileHash,
+        available: u64,
+        low_watermark: u64,
+    },
+    /// 空间恢复，下载已继续
+    Recovered { hash: FileHash, available: u64 },
+}
+
+/// 定期检查下载目录的剩余空间，低于水位线时暂停任务，恢复后继续
+pub struct DiskWatcher {
+    abort: AbortHandle,
+}
+
+impl DiskWatcher {
+    pub fn run(
+        hash: FileHash,
+        dir: PathBuf,
+        policy: DiskPolicy,
+        ctrl: mpsc::Sender<TaskCtrl>,
+        notices: broadcast::Sender<DiskNotice>,
+    ) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut ticker = interval(policy.check_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut paused = false;
+            loop {
+                ticker.tick().await;
+                let available = match available_space(&dir) {
+                    Ok(available) => available,
+                    Err(err) if err.kind() == io::ErrorKind::Unsupported => return,
+                    Err(err) => {
+                        warn!("Failed to query free space of {dir:?}: {err}");
+                        continue;
+                    }
+                };
+                let (command, notice) = match (paused, available < policy.low_watermark) {
+                    (false, true) => (
+                        TaskCommand::Pause,
+                        DiskNotice::LowSpace {
+                            hash,
+                            available,
+                            low_watermark: policy.low_watermark,
+                        },
+                    ),
+                    (true, false) => (
+                        TaskCommand::Resume,
+                        DiskNotice::Recovered { hash, available },
+                    ),
+                    _ => continue,
+                };
+                // 任务已经退出
+                if ctrl.send(TaskCtrl::Command(command)).await.is_err() {
+                    return;
+                }
+                paused = !paused;
+                info!("Disk space of {dir:?} changed: {notice:?}");
+                let _ = notices.send(notice);
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for DiskWatcher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Disk watcher has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preflight_keeps_watermark() {
+        let dir = std::env::temp_dir();
+        let Ok(available) = available_space(&dir) else {
+            return;
+        };
+        let policy = DiskPolicy {
+            low_watermark: 1 << 20,
+            ..Default::default()
+        };
+        assert!(policy.preflight(&dir, 0).is_ok() || available < 1 << 20);
+        assert!(matches!(
+            policy.preflight(&dir, available),
+            Err(TaskError::InsufficientSpace { .. })
+        ));
+    }
+
+    #[test]
+    fn relative_parent() {
+        assert_eq!(parent_dir(Path::new("a.bin")), Path::new("."));
+        assert_eq!(parent_dir(Path::new("dl/a.bin")), Path::new("dl"));
+    }
+}
@@ -1,13 +1,34 @@
 use super::{
-    FileHash, OptSource, Payload, TaggedTaskEvent, TaskCommand, TaskCtrl, TaskEvent, TaskState,
+    ACK_EVERY_BYTES, MANIFEST_BLOCK_SIZE, checksum_algorithm, reusable_ranges, verify_against,
+};
+use super::{
+    CompletedTransfer, FileHash, FileMeta, OptSource, Payload, RangeReport, ScratchPolicy,
+    SwarmScheduler, TaggedTaskEvent, TaskCommand, TaskCtrl, TaskError, TaskEvent, TaskState,
+    hash_path,
 };
 use crate::{
-    hot_file::{FileRange, HotFile, arrange_bytes_to_vec},
+    history::{HistoryRecord, record_history},
+    hot_file::{
+        BlockManifest, Checksum, ChecksumAlgorithm, ChecksumPurpose, FileMultiRange, FileRange,
+        FlushPolicy, HotFile, HotFileError, IncrementalHash, extended_path,
+    },
+    policy::Throttle,
     utils::{HostId, Uid},
 };
-use tokio::sync::{mpsc, watch};
+use camino::Utf8PathBuf;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    sync::{broadcast, mpsc, watch},
+    time::Instant,
+};
+use tracing::{info, warn};
 
-async fn verify_hash_or_correct(
+/// 对端给出的部分哈希与本地不一致时回传本地数据修正
+pub(super) async fn verify_hash_or_correct(
     file: &HotFile,
     range: FileRange,
     remote: FileHash,
@@ -15,10 +36,10 @@ async fn verify_hash_or_correct(
     status_in: &watch::Sender<TaskState>,
     host: HostId,
 ) {
-    match file.read(range.into()).await {
+    match file.read_vectored(range.into()).await {
         Ok(bufs) => {
-            if HotFile::hash(&bufs) != remote {
-                let payload = Payload::new(range.start(), arrange_bytes_to_vec(bufs.into_iter()));
+            if HotFile::hash(bufs.iter()) != remote {
+                let payload = Payload::from_bytes(range.start(), bufs.into_bytes());
                 if let Err(err) = event_in
                     .send(((0, host.clone()), TaskEvent::Confirm(payload)))
                     .await
@@ -35,44 +56,393 @@ async fn verify_hash_or_correct(
     }
 }
 
+/// 对照清单找出损坏的块，逐块向发送端发起校验，由对方回传修正数据
+async fn request_corrupted(
+    file: &HotFile,
+    manifest: &BlockManifest,
+    event_in: &mpsc::Sender<TaggedTaskEvent>,
+    status_in: &watch::Sender<TaskState>,
+    host: HostId,
+) {
+    let report = match verify_against(file, manifest).await {
+        Ok(report) => report,
+        Err(err) => {
+            status_in.send_modify(|state| state.set_download_err(err));
+            return;
+        }
+    };
+    for rgn in report.mismatched.iter() {
+        let partial_hash = match file.read((*rgn).into()).await {
+            Ok(bufs) => HotFile::hash(&bufs),
+            // 本地读不到就用一个必然不匹配的哈希，让对方整块重传
+            Err(_) => 0,
+        };
+        let check = TaskEvent::Check {
+            range: *rgn,
+            partial_hash,
+        };
+        // 与 verify_hash_or_correct 一致，文件 id 由任务管理器在外层补全
+        if let Err(err) = event_in.send(((0, host.clone()), check)).await {
+            status_in.send_modify(|state| state.set_download_err(err));
+            return;
+        }
+    }
+}
+
+/// 对照清单复用本地已有的块，计入下载进度后返回复用的字节数
+async fn reuse_local(
+    file: &HotFile,
+    manifest: &BlockManifest,
+    status_in: &watch::Sender<TaskState>,
+) -> usize {
+    let reusable = match reusable_ranges(file, manifest).await {
+        Ok(reusable) => reusable,
+        // 比对失败不影响下载，只是全部重新获取
+        Err(err) => {
+            warn!("Failed to compare local blocks against manifest: {err}");
+            return 0;
+        }
+    };
+    let mut reused = 0;
+    status_in.send_modify(|state| {
+        let Ok(progress) = state.get_download_progress() else {
+            return;
+        };
+        let fresh = reusable.subtract(progress.progress());
+        for rgn in fresh.iter() {
+            if state.download(*rgn).is_ok() {
+                reused += rgn.interval();
+            }
+        }
+    });
+    reused
+}
+
+/// 下载中的文件与目标文件同目录，完成前不会出现在目标位置
+pub const PART_SUFFIX: &str = ".falcon.part";
+
+/// `<name>.falcon.part`
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(PART_SUFFIX);
+    part.into()
+}
+
+/// 校验整文件哈希并落盘，还原元数据后原子地改名为目标文件
+///
+/// 发送端给出了摘要时再按摘要的算法核对一次，文件哈希只能发现意外损坏；
+/// 写入时已经增量算好的哈希直接使用，不再整个读回
+async fn finalize(
+    file: &HotFile,
+    path: &Path,
+    hash: FileHash,
+    digest: Option<Checksum>,
+    incremental: IncrementalHash,
+    meta: FileMeta,
+) -> Result<Utf8PathBuf, TaskError> {
+    let utf8 = |path: PathBuf| {
+        Utf8PathBuf::from_path_buf(path)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path is not utf-8"))
+    };
+    let (part, target) = (utf8(part_path(path))?, utf8(path.to_path_buf())?);
+    file.sync().await?;
+    let (actual, streamed) = match incremental.finish(file).await? {
+        Some(hashes) => hashes,
+        None => (hash_path(&part).await?, None),
+    };
+    if actual != hash {
+        return Err(TaskError::HashMismatch {
+            expected: hash,
+            actual,
+        });
+    }
+    if let Some(expected) = digest {
+        let actual = match streamed {
+            Some(streamed) if streamed.algorithm() == expected.algorithm() => streamed,
+            _ => file.checksum(expected.algorithm()).await?,
+        };
+        if actual != expected {
+            return Err(TaskError::ChecksumMismatch { expected, actual });
+        }
+        info!("Verified {part} with {}", expected.algorithm());
+    }
+    // 在改名之前还原，目标文件一出现就带着正确的时间与权限
+    if let Err(err) = meta.apply(&part).await {
+        warn!("Failed to restore metadata of {part}: {err}");
+    }
+    tokio::fs::rename(
+        extended_path(part.as_std_path()),
+        extended_path(target.as_std_path()),
+    )
+    .await?;
+    sync_parent(path).await;
+    Ok(target)
+}
+
+/// 让改名本身落盘，失败只影响掉电时的持久性
+async fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let result = match tokio::fs::File::open(dir).await {
+            Ok(dir) => dir.sync_all().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!("Failed to sync directory {dir:?}: {err}");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// 取消且不保留时删除临时文件，连同尚未落盘的数据
+///
+/// 校验等任务仍持有文件时无法关闭，先截断释放磁盘空间再删除路径
+async fn discard(file: Arc<HotFile>) -> Result<(), HotFileError> {
+    match Arc::try_unwrap(file) {
+        Ok(file) => file.purge().await,
+        Err(file) => {
+            file.truncate(0).await?;
+            Ok(tokio::fs::remove_file(extended_path(file.path())).await?)
+        }
+    }
+}
+
+/// 尚未收到且发送端没有声明不可用的范围
+fn missing(status_in: &watch::Sender<TaskState>) -> Option<FileMultiRange> {
+    let state = status_in.borrow();
+    let progress = state.get_download_progress().as_ref().ok()?;
+    let full = match state.total_len() {
+        0 => FileMultiRange::new(),
+        total => FileRange::new(0, total).into(),
+    };
+    Some(
+        full.subtract(progress.progress())
+            .subtract(state.unavailable()),
+    )
+}
+
+/// 多源下载时给快用完分片的对端补充分片，只剩一个来源时让它发送全部缺失的部分
+async fn request_pieces(
+    swarm: &mut SwarmScheduler,
+    status_in: &watch::Sender<TaskState>,
+    event_in: &mpsc::Sender<TaggedTaskEvent>,
+) {
+    let Some(missing) = missing(status_in) else {
+        return;
+    };
+    let requests = match swarm.is_swarm() {
+        true => swarm.refill(&missing),
+        false => swarm
+            .hosts()
+            .map(|host| (host.clone(), missing.clone()))
+            .collect(),
+    };
+    for (host, wanted) in requests {
+        // 文件 id 由任务管理器在外层补全
+        let want = ((0, host), TaskEvent::Want(wanted.to_wire()));
+        if event_in.send(want).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn is_complete(status_in: &watch::Sender<TaskState>) -> bool {
+    let state = status_in.borrow();
+    !state.has_download_error() && state.downloaded_len() >= state.total_len()
+}
+
+/// 除发送端声明不可用的范围外都已收到时给出范围报告
+fn partially_complete(status_in: &watch::Sender<TaskState>) -> Option<RangeReport> {
+    let state = status_in.borrow();
+    (!state.has_download_error() && state.is_partially_complete()).then(|| state.report())
+}
+
+fn is_paused(status_in: &watch::Sender<TaskState>) -> bool {
+    status_in
+        .borrow()
+        .get_download_progress()
+        .as_ref()
+        .is_ok_and(|progress| progress.is_paused())
+}
+
 pub async fn main_event_loop(
-    remote: HostId, // 主任务主机的id，只用于传递到事件而不是命令
-    file: HotFile,
-    mut ctrl_out: mpsc::Receiver<TaskCtrl>, // 被传递到这个任务的控制
+    remote: HostId,                          // 主任务主机的id，只用于传递到事件而不是命令
+    file: HotFile,                           // 写入 `part_path(&path)`，完成后改名
+    path: PathBuf,                           // 目标文件
+    hash: FileHash,                          // 完成时据此校验整个文件
+    meta: FileMeta,                          // 完成时还原时间与权限
+    mut ctrl_out: mpsc::Receiver<TaskCtrl>,  // 被传递到这个任务的控制
     event_in: mpsc::Sender<TaggedTaskEvent>, //下游网络事件输入，用于分享到其他
-    status_in: watch::Sender<TaskState>,    // 状态更新输入
+    status_in: watch::Sender<TaskState>,     // 状态更新输入
+    throttle: Throttle,                      // 全局与单任务下载限速
+    flush: FlushPolicy,                      // 后台刷盘策略，避免脏数据无限积压
+    completed: broadcast::Sender<CompletedTransfer>, // 完成后通知上层
 ) {
+    let started = Instant::now();
+    let part = part_path(&path);
+    let file = Arc::new(file);
+    let flusher = file.spawn_flusher(flush);
+    let mut manifest = None;
+    // 发送端给出的整文件摘要，没有协商 BLAKE3 或发送端未完整持有时为空
+    let mut digest = None;
+    // 协商了 BLAKE3 时顺带算出摘要，收到发送端的摘要后不必再读一遍
+    let blake3 = checksum_algorithm(ChecksumPurpose::File, &remote) == ChecksumAlgorithm::Blake3;
+    let mut incremental = IncrementalHash::new(MANIFEST_BLOCK_SIZE, blake3);
+    // 距上次确认新收到的字节数
+    let mut unacked = 0;
+    // 所有持有该文件的来源，起初只有发起邀约的对端
+    let mut swarm = SwarmScheduler::new(remote.clone());
     loop {
         if !status_in.borrow().has_download_error()
             && let Some(ctrl) = ctrl_out.recv().await
         {
-            let handle_payload = async |payload: Payload| {
+            let (source, ctrl) = match ctrl {
+                TaskCtrl::Sourced(host, event) => (host, TaskCtrl::Event(event)),
+                ctrl => (remote.clone(), ctrl),
+            };
+            let mut handle_payload = async |payload: Payload| -> Option<FileRange> {
                 let occupy = payload.occupy();
-                file.write(payload.buf(), occupy.start())
-                    .await
-                    .map_err(|err| {
+                // 暂停期间不落盘，恢复后缺失的部分由确认触发重传
+                if is_paused(&status_in) {
+                    return None;
+                }
+                throttle.acquire(payload.buf().len()).await;
+                match file.write(payload.buf(), occupy.start()).await {
+                    // 重传的数据可能与已有进度重叠，合并即可
+                    Ok(_) => {
                         status_in.send_modify(|state| {
-                            state.set_download_err(err);
-                        })
-                    });
+                            let _ = state.download(occupy);
+                            state.record_received(payload.buf().len());
+                        });
+                        incremental
+                            .record(&file, occupy.start(), payload.buf())
+                            .await;
+                    }
+                    // 磁盘写满时暂停而不是失败，等空间检查发现恢复后继续
+                    Err(HotFileError::IoError(err)) if err.kind() == io::ErrorKind::StorageFull => {
+                        warn!("Disk full while writing {path:?}, pause download");
+                        status_in.send_modify(|state| {
+                            let _ = state.stop_download(OptSource::Local);
+                        });
+                        return None;
+                    }
+                    Err(err) => status_in.send_modify(|state| {
+                        state.set_download_err(err);
+                    }),
+                }
+                Some(occupy)
+            };
+            // 攒够一定字节或下载完成时，把已收到的范围告诉所有发送端
+            let ack = async |unacked: &mut usize, swarm: &SwarmScheduler| {
+                let encoded = {
+                    let state = status_in.borrow();
+                    let complete = state.downloaded_len() >= state.total_len();
+                    let Ok(progress) = state.get_download_progress() else {
+                        return;
+                    };
+                    if *unacked < ACK_EVERY_BYTES && !complete {
+                        return;
+                    }
+                    progress.progress().to_wire()
+                };
+                *unacked = 0;
+                for host in swarm.hosts() {
+                    // 文件 id 由任务管理器在外层补全
+                    let ack = ((0, host.clone()), TaskEvent::Ack(encoded.clone()));
+                    if event_in.send(ack).await.is_err() {
+                        return;
+                    }
+                }
             };
             use TaskCommand::*;
             use TaskCtrl::*;
             use TaskEvent::*;
             match ctrl {
                 Event(New(_)) => unreachable!(),
-                Event(Append(payload)) => handle_payload(payload).await, // 实现恢复
+                // 进入循环时已拆成来源与事件
+                Sourced(..) => unreachable!(),
+                Event(Append(payload)) => {
+                    if let Some(occupy) = handle_payload(payload).await {
+                        unacked += occupy.interval();
+                        swarm.received(&source, occupy);
+                    }
+                    ack(&mut unacked, &swarm).await;
+                    if swarm.is_swarm() {
+                        request_pieces(&mut swarm, &status_in, &event_in).await;
+                    }
+                }
                 Event(Confirm(patch)) => {
                     file.sync().await.unwrap();
-                    handle_payload(patch).await;
+                    if let Some(occupy) = handle_payload(patch).await {
+                        unacked += occupy.interval();
+                        swarm.received(&source, occupy);
+                    }
+                    ack(&mut unacked, &swarm).await;
+                    if let Some(manifest) = &manifest {
+                        request_corrupted(&file, manifest, &event_in, &status_in, source.clone())
+                            .await;
+                    }
+                }
+                Event(Manifest(m)) => {
+                    let reused = reuse_local(&file, &m, &status_in).await;
+                    if reused > 0 {
+                        info!("Reuse {reused} bytes of {path:?} already present locally");
+                    }
+                    // 发送端收到这次确认才开始发送，无论是否复用都立即确认
+                    unacked = unacked.max(ACK_EVERY_BYTES);
+                    ack(&mut unacked, &swarm).await;
+                    manifest = Some(m);
+                }
+                // 只采信协商过的算法，避免对端借此绕过更强的校验
+                Event(Digest(d)) => {
+                    match d.algorithm() == checksum_algorithm(ChecksumPurpose::File, &source) {
+                        true => digest = Some(d),
+                        false => warn!("Ignore {} digest from {source}", d.algorithm()),
+                    }
+                }
+                // 多源下载时一个来源退出，它的分片交给其余来源
+                Event(Cancel) if swarm.is_swarm() => {
+                    swarm.leave(&source);
+                    info!("{source} left the swarm downloading {path:?}");
+                    request_pieces(&mut swarm, &status_in, &event_in).await;
                 }
                 Event(Cancel) => {
                     status_in.send_modify(|state| {
-                        state.stop_download(OptSource::Remote).map_err(|err| {
+                        if let Err(err) = state.stop_download(OptSource::Remote) {
                             state.set_download_err(err);
-                        });
+                        }
                     });
                 }
+                // 发送端暂停后不再有数据过来，恢复时由它重新开始发送
+                Event(TaskEvent::Pause) => {
+                    status_in.send_modify(|state| {
+                        let _ = state.stop_download(OptSource::Remote);
+                    });
+                    info!("{source} paused sending {path:?}");
+                }
+                Event(TaskEvent::Resume) => {
+                    // 本地暂停不因对端恢复而解除
+                    let remote_paused = status_in
+                        .borrow()
+                        .get_download_progress()
+                        .as_ref()
+                        .is_ok_and(|progress| progress.paused_by() == Some(OptSource::Remote));
+                    if remote_paused {
+                        status_in.send_modify(|state| {
+                            let _ = state.resume_download();
+                        });
+                        unacked = unacked.max(ACK_EVERY_BYTES);
+                        ack(&mut unacked, &swarm).await;
+                    }
+                }
+                // 拒绝由任务管理器直接发往对端，不会进入运行中的任务
+                Event(Decline(_)) => {}
+                // 确认与分片请求只发往共享任务
+                Event(Ack(_) | Want(_)) => {}
+                Event(Unavailable(range)) => {
+                    status_in.send_modify(|state| state.mark_unavailable(range));
+                }
                 Event(Check {
                     range,
                     partial_hash,
@@ -83,15 +453,178 @@ pub async fn main_event_loop(
                         partial_hash,
                         &event_in,
                         &status_in,
-                        remote.clone(),
+                        source.clone(),
                     )
                     .await
                 }
 
-                Command(Rescind(_)) => todo!(), //那还有想办法保存另一个任务的状态
-                Command(Share(_)) => todo!(),   // 启动另外的任务
-                Command(Open(_)) => todo!(), // 需要维护一个分享表，映射到任务的取消token和watch上
+                Command(Rescind(policy)) => {
+                    // 保留的部分先落盘才能在之后续传，丢弃时不必再写
+                    if policy == ScratchPolicy::PreserveForResume
+                        && let Err(err) = file.sync().await
+                    {
+                        warn!("Failed to flush cancelled download {path:?}: {err}");
+                    }
+                    status_in.send_modify(|state| {
+                        let _ = state.stop_download(OptSource::Local);
+                    });
+                    // 让所有来源的上传任务停下
+                    for host in swarm.hosts() {
+                        let _ = event_in.send(((0, host.clone()), TaskEvent::Cancel)).await;
+                    }
+                    if policy == ScratchPolicy::Discard {
+                        drop(flusher);
+                        if let Err(err) = discard(file).await {
+                            warn!("Failed to remove cancelled download {part:?}: {err}");
+                        }
+                    }
+                    let (done, total) = {
+                        let state = status_in.borrow();
+                        let done = match state.get_download_progress() {
+                            Ok(progress) => progress.progress().clone(),
+                            Err(_) => FileMultiRange::new(),
+                        };
+                        (done, state.total_len())
+                    };
+                    info!(
+                        "Download of {path:?} from {remote} cancelled with {}",
+                        done.summary(total)
+                    );
+                    return;
+                }
+                Command(TaskCommand::Pause) => {
+                    if let Err(err) = file.sync().await {
+                        warn!("Failed to flush paused download {path:?}: {err}");
+                    }
+                    status_in.send_modify(|state| {
+                        let _ = state.stop_download(OptSource::Local);
+                    });
+                    // 让所有来源停止发送，免得暂停期间白白占用带宽
+                    for host in swarm.hosts() {
+                        let _ = event_in.send(((0, host.clone()), TaskEvent::Pause)).await;
+                    }
+                }
+                Command(TaskCommand::Resume) => {
+                    status_in.send_modify(|state| {
+                        let _ = state.resume_download();
+                    });
+                    for host in swarm.hosts() {
+                        let _ = event_in.send(((0, host.clone()), TaskEvent::Resume)).await;
+                    }
+                    // 暂停期间丢弃的数据要靠这次确认让发送端重传
+                    unacked = unacked.max(ACK_EVERY_BYTES);
+                    ack(&mut unacked, &swarm).await;
+                }
+                Command(Join(host)) => {
+                    if swarm.join(host.clone()) {
+                        info!("{host} joined the swarm downloading {path:?}");
+                        request_pieces(&mut swarm, &status_in, &event_in).await;
+                    }
+                }
+                Command(Share(_)) => todo!(), // 启动另外的任务
+                Command(Open(_)) => todo!(),  // 需要维护一个分享表，映射到任务的取消token和watch上
+            }
+            // 数据到齐后收尾并退出，来源随之结束各自的分享任务
+            if is_complete(&status_in) {
+                for host in swarm.hosts() {
+                    let _ = event_in.send(((0, host.clone()), TaskEvent::Cancel)).await;
+                }
+                match finalize(&file, &path, hash, digest, incremental, meta).await {
+                    Ok(target) => {
+                        info!("Download of {target} from {remote} finished");
+                        let done = CompletedTransfer {
+                            path: target,
+                            peer: remote,
+                            hash,
+                            size: meta.size,
+                            elapsed: started.elapsed(),
+                            copy_method: None,
+                        };
+                        record_history(HistoryRecord::completed(&done));
+                        let _ = completed.send(done);
+                    }
+                    Err(err) => {
+                        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                        record_history(HistoryRecord::failed(
+                            file_name,
+                            meta.size,
+                            &remote,
+                            hash,
+                            started.elapsed(),
+                            &err,
+                        ));
+                        status_in.send_modify(|state| state.set_download_err(err));
+                    }
+                }
+                return;
+            }
+            // 其余范围到齐后不再等待读不出的范围，临时文件留着等别的来源补齐
+            if let Some(report) = partially_complete(&status_in) {
+                for host in swarm.hosts() {
+                    let _ = event_in.send(((0, host.clone()), TaskEvent::Cancel)).await;
+                }
+                if let Err(err) = file.sync().await {
+                    warn!("Failed to sync {part:?}: {err}");
+                }
+                let unavailable = report.unavailable.interval() as u64;
+                warn!(
+                    "Download of {path:?} from {remote} partially completed, unavailable ranges: {:?}",
+                    report.unavailable
+                );
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                record_history(HistoryRecord::partial(
+                    file_name,
+                    meta.size,
+                    &remote,
+                    hash,
+                    started.elapsed(),
+                    unavailable,
+                ));
+                return;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// 没有经过事件循环写入，收尾时整个读回
+    fn unhashed() -> IncrementalHash {
+        IncrementalHash::new(MANIFEST_BLOCK_SIZE, false)
+    }
+
+    #[tokio::test]
+    async fn finalize_renames_after_verify() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.bin");
+        let file = HotFile::open_new(part_path(&path)).await.unwrap();
+        file.write(b"falcon", 0).await.unwrap();
+        let meta = FileMeta::from_wire(6, None, None);
+
+        let err = finalize(&file, &path, 0, None, unhashed(), meta)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TaskError::HashMismatch { .. }));
+        assert!(!path.exists() && part_path(&path).exists());
+
+        let hash = HotFile::hash([b"falcon"]);
+        // 文件哈希一致，但摘要对不上
+        let forged = ChecksumAlgorithm::Blake3.digest([b"falcoN"]);
+        let err = finalize(&file, &path, hash, Some(forged), unhashed(), meta)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TaskError::ChecksumMismatch { .. }));
+        assert!(!path.exists());
+
+        let digest = ChecksumAlgorithm::Blake3.digest([b"falcon"]);
+        let target = finalize(&file, &path, hash, Some(digest), unhashed(), meta)
+            .await
+            .unwrap();
+        assert_eq!(target.as_std_path(), path);
+        assert_eq!(std::fs::read(&path).unwrap(), b"falcon");
+        assert!(!part_path(&path).exists());
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{hot_file::FileRange, utils::HostId};
+use super::{FileMeta, ScratchPolicy};
+use crate::{
+    hot_file::{BlockManifest, Checksum, FileRange},
+    utils::HostId,
+};
 use bytes::Bytes;
 use std::{
     path::{Path, PathBuf},
@@ -15,7 +19,22 @@ pub enum TaskEvent {
     Append(Payload),
     Confirm(Payload),
     Cancel,
-    // Resume(progress)//来自远方的请求恢复事件，并携带了进度
+    /// 接收端拒绝邀约，只发往对端
+    Decline(String),
+    /// 发送端读盘失败的范围，接收端不再等待这些数据
+    Unavailable(FileRange),
+    /// 接收端已收到范围的紧凑编码（见 `FileMultiRange::to_wire`），发送端据此重传缺失部分
+    Ack(Vec<u8>),
+    /// 发送端给出的块清单，接收端据此只重传损坏的块
+    Manifest(BlockManifest),
+    /// 发送端按协商的算法算出的整文件校验值，接收端完成时在文件哈希之外再核对一次
+    Digest(Checksum),
+    /// 多源下载时接收端只希望该对端发送的范围，编码同 `Ack`
+    Want(Vec<u8>),
+    /// 对端暂停了这个文件的收发，本机停止向它发送
+    Pause,
+    /// 对端恢复收发，接收端随后的确认会触发缺失部分的重传
+    Resume,
     Check {
         range: FileRange,
         partial_hash: FileHash,
@@ -26,11 +45,19 @@ pub enum TaskEvent {
 pub enum TaskCommand {
     Open(FileInfo), // 已经open 了就不能new了
     Share(TaskTag),
-    Rescind(TaskTag), //
+    /// 本地取消，按策略处理已下载的部分并通知对端停止上传
+    Rescind(ScratchPolicy),
+    /// 暂停写入，收到的数据直接丢弃，恢复后由确认触发重传
+    Pause,
+    Resume,
+    /// 加入另一个同样持有该文件的对端，之后按速率分片并行下载
+    Join(HostId),
 }
 
 pub enum TaskCtrl {
     Event(TaskEvent),
+    /// 带有发送方的网络事件，多源下载据此区分各个对端
+    Sourced(HostId, TaskEvent),
     Command(TaskCommand),
 }
 
@@ -40,7 +67,7 @@ pub type TaggedTaskEvent = (TaskTag, TaskEvent);
 pub struct FileInfo {
     file_hash: FileHash,
     file_name: String, //文件名
-    size: usize,
+    meta: FileMeta,    // 大小、修改时间与权限
 }
 
 // //     let comp = path.components().last()?;
@@ -50,12 +77,24 @@ pub struct FileInfo {
 //         return None;
 //     }
 impl FileInfo {
+    pub fn new(file_hash: FileHash, file_name: String, meta: FileMeta) -> Self {
+        Self {
+            file_hash,
+            file_name,
+            meta,
+        }
+    }
+
     pub fn file_hash(&self) -> FileHash {
         self.file_hash
     }
 
     pub fn size(&self) -> usize {
-        self.size
+        self.meta.size as usize
+    }
+
+    pub fn meta(&self) -> &FileMeta {
+        &self.meta
     }
 
     pub fn file_name(&self) -> &Path {
@@ -77,6 +116,11 @@ impl Payload {
         }
     }
 
+    /// 单块读出的数据不再拷贝
+    pub fn from_bytes(offset: usize, buf: Bytes) -> Self {
+        Self { offset, buf }
+    }
+
     pub fn buf(&self) -> &[u8] {
         self.buf.as_ref()
     }
@@ -0,0 +1,62 @@
+use super::{FileHash, TaskCommand, TaskCtrl};
+use crate::{
+    inbound::HostId,
+    link::{LinkEvent, link_state_table},
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},
+    task::AbortHandle,
+};
+use tracing::{info, warn};
+
+/// 来源主机的最后一条链路失效时暂停下载，出现新的健康链路后自动继续
+pub struct LinkWatcher {
+    abort: AbortHandle,
+}
+
+impl LinkWatcher {
+    pub fn run(hash: FileHash, remote: HostId, ctrl: mpsc::Sender<TaskCtrl>) -> Self {
+        // 先订阅再启动，不会错过任务创建后紧接着发生的变化
+        let mut events = link_state_table().on_link_event();
+        let abort = tokio::spawn(async move {
+            let mut paused = false;
+            loop {
+                let reachable = match events.recv().await {
+                    Ok(LinkEvent::HostUnreachable(host)) if host == remote => false,
+                    Ok(LinkEvent::HostReachable(host)) if host == remote => true,
+                    Ok(_) => continue,
+                    // 错过的事件无法补回，以链路表当前的状态为准
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Link watcher of {hash:016x} skipped {skipped} events");
+                        link_state_table().is_reachable(&remote)
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let command = match (paused, reachable) {
+                    (false, false) => TaskCommand::Pause,
+                    // 恢复时的确认会让发送端重传暂停期间丢失的部分
+                    (true, true) => TaskCommand::Resume,
+                    _ => continue,
+                };
+                // 任务已经退出
+                if ctrl.send(TaskCtrl::Command(command)).await.is_err() {
+                    return;
+                }
+                paused = !paused;
+                match paused {
+                    true => info!("Pause {hash:016x}: {remote} became unreachable"),
+                    false => info!("Resume {hash:016x}: {remote} is reachable again"),
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+}
+
+impl Drop for LinkWatcher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Link watcher has been dropped");
+    }
+}
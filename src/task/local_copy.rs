@@ -0,0 +1,135 @@
+use crate::hot_file::extended_path;
+use camino::Utf8Path;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    time::Duration,
+};
+use tokio::{task::spawn_blocking, time::interval};
+use tracing::debug;
+
+/// 普通复制期间查看目标文件长度的间隔，也是进度回调的粒度
+const PROGRESS_POLL: Duration = Duration::from_millis(100);
+
+/// 本地复制实际采用的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// 文件系统支持 clone（btrfs/XFS/APFS/ReFS），只复制元数据
+    Reflink,
+    /// 不支持 clone 或跨文件系统时退化为普通复制
+    Copy,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalCopy {
+    pub method: CopyMethod,
+    pub size: u64,
+}
+
+/// 先尝试 reflink，失败则回退到普通复制
+///
+/// 目标文件已存在时返回错误，不会覆盖
+pub async fn local_copy(src: &Utf8Path, dst: &Utf8Path) -> io::Result<LocalCopy> {
+    local_copy_with_progress(src, dst, |_| {}).await
+}
+
+/// 同 `local_copy`，复制过程中以已复制的字节数回调 progress
+///
+/// reflink 瞬间完成，只在结束时回调一次；普通复制以 `create_new` 打开目标后交给 `io::copy`，
+/// 由它使用 copy_file_range 等内核路径，期间定期按目标文件长度回调
+pub async fn local_copy_with_progress(
+    src: &Utf8Path,
+    dst: &Utf8Path,
+    mut progress: impl FnMut(u64),
+) -> io::Result<LocalCopy> {
+    let (src, dst) = (
+        extended_path(src.as_std_path()).into_owned(),
+        extended_path(dst.as_std_path()).into_owned(),
+    );
+    let (size, reflinked) = spawn_blocking({
+        let (src, dst) = (src.clone(), dst.clone());
+        move || {
+            let size = std::fs::metadata(&src)?.len();
+            let reflinked = reflink_copy::reflink(&src, &dst)
+                .inspect_err(|err| {
+                    debug!(
+                        "Reflink {} unavailable, fall back to copy: {err}",
+                        dst.display()
+                    )
+                })
+                .is_ok();
+            Ok::<_, io::Error>((size, reflinked))
+        }
+    })
+    .await
+    .map_err(io::Error::other)??;
+    let method = match reflinked {
+        true => CopyMethod::Reflink,
+        false => {
+            let mut copy = spawn_blocking({
+                let dst = dst.clone();
+                // 检查与创建合为一步，目标在此期间出现也不会被覆盖
+                move || {
+                    let mut to = OpenOptions::new().write(true).create_new(true).open(&dst)?;
+                    io::copy(&mut File::open(&src)?, &mut to)
+                }
+            });
+            let mut poll = interval(PROGRESS_POLL);
+            loop {
+                tokio::select! {
+                    copied = &mut copy => {
+                        copied.map_err(io::Error::other)??;
+                        break;
+                    }
+                    // 预先分配长度的平台上会一下子跳到文件大小，不影响结果
+                    _ = poll.tick() => if let Ok(meta) = tokio::fs::metadata(&dst).await {
+                        progress(meta.len().min(size));
+                    }
+                }
+            }
+            CopyMethod::Copy
+        }
+    };
+    progress(size);
+    Ok(LocalCopy { method, size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn copy_content() {
+        let dir = tempdir().unwrap();
+        let src = Utf8PathBuf::try_from(dir.path().join("src")).unwrap();
+        let dst = Utf8PathBuf::try_from(dir.path().join("dst")).unwrap();
+        std::fs::write(&src, b"falcon").unwrap();
+
+        let copied = local_copy(&src, &dst).await.unwrap();
+        assert_eq!(copied.size, 6);
+        assert_eq!(std::fs::read(&dst).unwrap(), b"falcon");
+
+        // 目标已存在时不覆盖
+        assert!(local_copy(&src, &dst).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn progress_reaches_size() {
+        let dir = tempdir().unwrap();
+        let src = Utf8PathBuf::try_from(dir.path().join("src")).unwrap();
+        let dst = Utf8PathBuf::try_from(dir.path().join("dst")).unwrap();
+        let data = vec![7u8; 3 * 1024 * 1024 + 1];
+        std::fs::write(&src, &data).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let copied = local_copy_with_progress(&src, &dst, move |done| tx.send(done).unwrap())
+            .await
+            .unwrap();
+        let reported = rx.iter().collect::<Vec<_>>();
+        assert!(reported.is_sorted());
+        assert_eq!(reported.last(), Some(&copied.size));
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+    }
+}
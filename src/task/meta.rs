@@ -0,0 +1,143 @@
+use super::TaskState;
+use crate::hot_file::extended_path;
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// 传输前交换的文件元数据，接收端据此预分配空间，完成后还原时间与权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileMeta {
+    pub size: u64,
+    pub mtime: Option<SystemTime>,
+    /// unix 下为完整的 mode 位，其他平台只区分只读（0o444）与可写（0o644）
+    pub permissions: Option<u32>,
+}
+
+impl From<&Metadata> for FileMeta {
+    fn from(meta: &Metadata) -> Self {
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            meta.permissions().mode() & 0o7777
+        };
+        #[cfg(not(unix))]
+        let permissions = match meta.permissions().readonly() {
+            true => 0o444,
+            false => 0o644,
+        };
+        Self {
+            size: meta.len(),
+            mtime: meta.modified().ok(),
+            permissions: Some(permissions),
+        }
+    }
+}
+
+impl FileMeta {
+    pub async fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        tokio::fs::metadata(path)
+            .await
+            .map(|meta| Self::from(&meta))
+    }
+
+    /// 报文中的时间以 unix 毫秒表示，早于纪元的时间不传
+    pub fn mtime_millis(&self) -> Option<u64> {
+        self.mtime?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|elapsed| u64::try_from(elapsed.as_millis()).ok())
+    }
+
+    pub fn from_wire(size: u64, mtime_millis: Option<u64>, permissions: Option<u32>) -> Self {
+        Self {
+            size,
+            mtime: mtime_millis.map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+            permissions,
+        }
+    }
+
+    /// 还原修改时间与权限，任一失败都会返回错误，但不会中断另一项
+    pub async fn apply(self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = extended_path(&path.into()).into_owned();
+        tokio::task::spawn_blocking(move || {
+            let mtime = match self.mtime {
+                Some(mtime) => fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(mtime)),
+                None => Ok(()),
+            };
+            let permissions = match self.permissions {
+                Some(mode) => Self::set_mode(&path, mode),
+                None => Ok(()),
+            };
+            mtime.and(permissions)
+        })
+        .await?
+    }
+
+    #[cfg(unix)]
+    fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+        let mut permissions = fs::metadata(path)?.permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        fs::set_permissions(path, permissions)
+    }
+
+    /// 等待下载完成后还原元数据，任务出错或被丢弃时直接退出
+    pub async fn restore_when_complete(
+        self,
+        path: PathBuf,
+        mut status: watch::Receiver<TaskState>,
+    ) {
+        loop {
+            {
+                let state = status.borrow_and_update();
+                if state.has_download_error() {
+                    return;
+                }
+                if state.total_len() > 0 && state.downloaded_len() >= state.total_len() {
+                    break;
+                }
+            }
+            if status.changed().await.is_err() {
+                return;
+            }
+        }
+        match self.apply(&path).await {
+            Ok(()) => debug!("Restored metadata of {}", path.display()),
+            Err(err) => warn!("Failed to restore metadata of {}: {err}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn apply_restores_mtime_and_mode() {
+        let file = NamedTempFile::new().unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let meta = FileMeta::from_wire(0, Some(1_700_000_000_123), Some(0o640));
+        assert_eq!(meta.mtime, Some(mtime));
+        assert_eq!(meta.mtime_millis(), Some(1_700_000_000_123));
+        meta.apply(file.path()).await.unwrap();
+
+        let restored = FileMeta::read(file.path()).await.unwrap();
+        assert_eq!(restored.mtime, Some(mtime));
+        #[cfg(unix)]
+        assert_eq!(restored.permissions, Some(0o640));
+    }
+}
@@ -10,3 +10,39 @@ mod download_task;
 pub use download_task::*;
 mod share_task;
 pub use share_task::*;
+mod local_copy;
+pub use local_copy::*;
+mod completion;
+pub use completion::*;
+mod verify_task;
+pub use verify_task::*;
+mod retention;
+pub use retention::*;
+mod batch_verify;
+pub use batch_verify::*;
+mod scratch;
+pub use scratch::*;
+mod persist;
+pub use persist::*;
+mod stripe;
+pub use stripe::*;
+mod progress;
+pub use progress::*;
+mod rate;
+pub use rate::*;
+mod meta;
+pub use meta::*;
+mod ack;
+pub use ack::*;
+mod disk;
+pub use disk::*;
+mod link_watch;
+pub use link_watch::*;
+mod swarm;
+pub use swarm::*;
+mod destination;
+pub use destination::*;
+mod sanitize;
+pub use sanitize::*;
+mod wire;
+pub use wire::*;
@@ -0,0 +1,106 @@
+use super::FileHash;
+use crate::hot_file::{FileMultiRange, FileRange};
+use atomicwrites::{AtomicFile, OverwriteBehavior::AllowOverwrite};
+use bincode::{Decode, Encode};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::io::{self, Write};
+use thiserror::Error;
+
+/// 进度旁车文件的后缀，紧跟在目标文件名之后
+pub const STATE_SUFFIX: &str = ".falcon.state";
+const SNAPSHOT_VERSION: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum PersistError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error(transparent)]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error("Unsupported snapshot version {0}")]
+    Version(u8),
+    #[error("Snapshot ranges exceed file size {0}")]
+    OutOfBounds(u64),
+}
+
+/// 落盘的任务进度，只记录下载方需要续传的部分
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct TaskSnapshot {
+    pub version: u8,
+    pub hash: FileHash,
+    pub total: u64,
+    pub completed: Vec<(u64, u64)>,
+    pub unavailable: Vec<(u64, u64)>,
+}
+
+impl TaskSnapshot {
+    pub fn new(
+        hash: FileHash,
+        total: usize,
+        completed: &FileMultiRange,
+        unavailable: &FileMultiRange,
+    ) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            hash,
+            total: total as u64,
+            completed: encode_ranges(completed),
+            unavailable: encode_ranges(unavailable),
+        }
+    }
+
+    pub fn completed(&self) -> Result<FileMultiRange, PersistError> {
+        decode_ranges(&self.completed, self.total)
+    }
+
+    pub fn unavailable(&self) -> Result<FileMultiRange, PersistError> {
+        decode_ranges(&self.unavailable, self.total)
+    }
+
+    /// 原子写入，崩溃时要么是旧快照要么是新快照
+    pub fn save(&self, path: &Utf8Path) -> Result<(), PersistError> {
+        let buf = bincode::encode_to_vec(self, bincode::config::standard())?;
+        AtomicFile::new(path, AllowOverwrite)
+            .write(|f| f.write_all(&buf))
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Utf8Path) -> Result<Self, PersistError> {
+        let buf = std::fs::read(path)?;
+        let (snapshot, _): (Self, _) =
+            bincode::decode_from_slice(&buf, bincode::config::standard())?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(PersistError::Version(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// `movie.mkv` -> `movie.mkv.falcon.state`
+pub fn state_path(file: &Utf8Path) -> Utf8PathBuf {
+    let mut path = file.as_str().to_owned();
+    path.push_str(STATE_SUFFIX);
+    path.into()
+}
+
+fn encode_ranges(ranges: &FileMultiRange) -> Vec<(u64, u64)> {
+    ranges
+        .iter()
+        .map(|r| (r.start() as u64, r.end() as u64))
+        .collect()
+}
+
+fn decode_ranges(pairs: &[(u64, u64)], total: u64) -> Result<FileMultiRange, PersistError> {
+    let mut ranges = FileMultiRange::new();
+    for &(start, end) in pairs {
+        if end > total {
+            return Err(PersistError::OutOfBounds(total));
+        }
+        let rgn = FileRange::try_new(start as usize, end as usize)
+            .map_err(|_| PersistError::OutOfBounds(total))?;
+        ranges.add(rgn);
+    }
+    Ok(ranges)
+}
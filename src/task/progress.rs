@@ -0,0 +1,150 @@
+use super::{FileHash, TaskState};
+use crate::inbound::HostId;
+use futures::{Stream, StreamExt, future::ready};
+use std::time::Duration;
+use tokio::{
+    sync::{broadcast, watch},
+    task::AbortHandle,
+    time::sleep,
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::warn;
+
+/// 广播的订阅流，消费过慢时跳过积压的部分而不是终止流
+pub fn lossy_stream<T: Clone + Send + 'static>(
+    rx: broadcast::Receiver<T>,
+) -> impl Stream<Item = T> {
+    BroadcastStream::new(rx).filter_map(|item| {
+        ready(
+            item.inspect_err(|err| warn!("Subscriber lagged behind: {err}"))
+                .ok(),
+        )
+    })
+}
+
+/// 某个对端从本机拉取的进度
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostProgress {
+    pub host: HostId,
+    pub done: usize,
+}
+
+/// 面向界面的结构化进度事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressEvent {
+    pub hash: FileHash,
+    pub done: usize,
+    pub total: usize,
+    /// 平滑后的下载速率，字节每秒
+    pub rate: f64,
+    /// 速率为零时无法估计
+    pub eta: Option<Duration>,
+    /// 向所有对端上传的合计速率，字节每秒
+    pub upload_rate: f64,
+    pub uploads: Vec<HostProgress>,
+}
+
+impl ProgressEvent {
+    pub fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+/// 订阅各任务的 `TaskState`，把变化整理成进度事件广播出去
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    tx: broadcast::Sender<ProgressEvent>,
+}
+
+impl ProgressReporter {
+    const CAPACITY: usize = 256;
+    /// 同一任务两次事件的最小间隔
+    const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(Self::CAPACITY).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.tx.subscribe()
+    }
+
+    /// 跟随任务状态直到任务结束，结束前补发最后一次进度
+    pub fn watch(&self, hash: FileHash, mut status: watch::Receiver<TaskState>) -> AbortHandle {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let closed = status.changed().await.is_err();
+                let event = {
+                    let state = status.borrow_and_update();
+                    let done = state.downloaded_len();
+                    let total = state.total_len();
+                    let rate = state.download_rate().rate();
+                    let eta = state.download_rate().eta(total - done.min(total));
+                    ProgressEvent {
+                        hash,
+                        done,
+                        total,
+                        rate,
+                        eta,
+                        upload_rate: state.upload_rate().rate(),
+                        uploads: state
+                            .uploaded_lens()
+                            .map(|(host, done)| HostProgress {
+                                host: host.clone(),
+                                done,
+                            })
+                            .collect(),
+                    }
+                };
+                let _ = tx.send(event);
+                if closed {
+                    break;
+                }
+                // 合并短时间内的多次变化
+                sleep(Self::MIN_INTERVAL).await;
+            }
+        })
+        .abort_handle()
+    }
+}
+
+impl Default for ProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hot_file::FileRange;
+
+    #[tokio::test(start_paused = true)]
+    async fn report_until_finished() {
+        let reporter = ProgressReporter::new();
+        let mut rx = reporter.subscribe();
+        let (status_in, status_out) = watch::channel(TaskState::try_new(1000).unwrap());
+        reporter.watch(42, status_out);
+
+        status_in.send_modify(|state| {
+            state.download(FileRange::new(0, 400)).unwrap();
+            state.record_received(400);
+        });
+        let event = rx.recv().await.unwrap();
+        assert_eq!((event.hash, event.done, event.total), (42, 400, 1000));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        status_in.send_modify(|state| {
+            state.download(FileRange::new(400, 1000)).unwrap();
+            state.record_received(600);
+        });
+        drop(status_in);
+        let event = rx.recv().await.unwrap();
+        assert!(event.is_finished());
+        assert!(event.rate > 0.0);
+        assert_eq!(event.eta, Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// 按固定窗口统计字节数，每个窗口结束时并入指数加权平均
+///
+/// 空闲的窗口按零计入，传输停下后速率会逐渐衰减到零
+#[derive(Debug, Clone, Copy)]
+pub struct RateEstimator {
+    start: Instant,
+    bytes: usize,
+    rate: f64,
+    primed: bool,
+}
+
+impl Default for RateEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateEstimator {
+    pub const WINDOW: Duration = Duration::from_secs(1);
+    const ALPHA: f64 = 0.5;
+    /// 空闲超过这么多窗口后速率已可忽略，不必逐个衰减
+    const MAX_IDLE: u32 = 16;
+
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            bytes: 0,
+            rate: 0.0,
+            primed: false,
+        }
+    }
+
+    pub fn record(&mut self, bytes: usize) {
+        self.roll(Instant::now());
+        self.bytes += bytes;
+    }
+
+    /// 平滑后的速率，字节每秒
+    pub fn rate(&self) -> f64 {
+        let mut estimator = *self;
+        estimator.roll(Instant::now());
+        estimator.rate
+    }
+
+    /// 按剩余字节估计完成时间，速率为零时无法估计
+    pub fn eta(&self, remaining: usize) -> Option<Duration> {
+        let rate = self.rate();
+        (rate > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let windows =
+            (now.duration_since(self.start).as_secs_f64() / Self::WINDOW.as_secs_f64()) as u32;
+        if windows == 0 {
+            return;
+        }
+        self.fold(self.bytes as f64 / Self::WINDOW.as_secs_f64());
+        for _ in 1..windows.min(Self::MAX_IDLE) {
+            self.fold(0.0);
+        }
+        if windows >= Self::MAX_IDLE {
+            self.rate = 0.0;
+        }
+        self.start += Self::WINDOW * windows;
+        self.bytes = 0;
+    }
+
+    fn fold(&mut self, sample: f64) {
+        // 第一个窗口直接作为初值，避免开始时从零慢慢爬升
+        self.rate = match self.primed {
+            true => Self::ALPHA * sample + (1.0 - Self::ALPHA) * self.rate,
+            false => sample,
+        };
+        self.primed = true;
+    }
+}
+
+/// 所有任务合计的收发速率
+#[derive(Debug, Default)]
+pub struct GlobalRates {
+    download: Mutex<RateEstimator>,
+    upload: Mutex<RateEstimator>,
+}
+
+pub fn global_rates() -> &'static GlobalRates {
+    static GLOBAL_RATES: OnceLock<GlobalRates> = OnceLock::new();
+    GLOBAL_RATES.get_or_init(GlobalRates::default)
+}
+
+impl GlobalRates {
+    pub fn record_download(&self, bytes: usize) {
+        self.download.lock().unwrap().record(bytes);
+    }
+
+    pub fn record_upload(&self, bytes: usize) {
+        self.upload.lock().unwrap().record(bytes);
+    }
+
+    pub fn download(&self) -> f64 {
+        self.download.lock().unwrap().rate()
+    }
+
+    pub fn upload(&self) -> f64 {
+        self.upload.lock().unwrap().rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn smooth_and_decay() {
+        let mut estimator = RateEstimator::new();
+        estimator.record(1000);
+        // 窗口未结束前没有样本
+        assert_eq!(estimator.rate(), 0.0);
+        assert_eq!(estimator.eta(500), None);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(estimator.rate(), 1000.0);
+        assert_eq!(estimator.eta(500), Some(Duration::from_millis(500)));
+        estimator.record(3000);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(estimator.rate(), 2000.0);
+        // 空闲一个窗口减半
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(estimator.rate(), 1000.0);
+
+        tokio::time::advance(RateEstimator::WINDOW * 16).await;
+        assert_eq!(estimator.rate(), 0.0);
+    }
+}
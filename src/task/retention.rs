@@ -0,0 +1,207 @@
+use super::FileHash;
+use crate::config::{ConfigItem, ConfigManager};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    io,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// 历史、日志、清单等记录的保留策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetentionPolicy {
+    pub max_entries: usize,
+    pub max_age: Duration,
+    pub max_bytes: u64,
+}
+
+/// 可被裁剪的归档记录
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// 在所属归档内唯一
+    pub id: u64,
+    pub hash: FileHash,
+    pub finished_at: SystemTime,
+    pub size: u64,
+}
+
+/// 历史记录、任务日志等持久化存储实现此 trait 以接受统一裁剪
+pub trait Archive: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn entries(&self) -> io::Result<Vec<ArchiveEntry>>;
+    fn remove(&self, ids: &HashSet<u64>) -> io::Result<()>;
+}
+
+/// 全局单例的归档也能直接交给 `ArchiveMaintainer`
+impl<A: Archive> Archive for &'static A {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn entries(&self) -> io::Result<Vec<ArchiveEntry>> {
+        (**self).entries()
+    }
+
+    fn remove(&self, ids: &HashSet<u64>) -> io::Result<()> {
+        (**self).remove(ids)
+    }
+}
+
+impl RetentionPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        async fn parse<T: FromStr>(cfg: &ConfigManager, item: ConfigItem) -> T {
+            cfg.get(item).await.parse().unwrap_or_else(|_| {
+                item.default()
+                    .parse()
+                    .unwrap_or_else(|_| unreachable!("default of {item} must be valid"))
+            })
+        }
+        Self {
+            max_entries: parse(cfg, ConfigItem::RetentionMaxEntries).await,
+            max_age: Duration::from_days(parse(cfg, ConfigItem::RetentionMaxAgeDays).await),
+            max_bytes: parse(cfg, ConfigItem::RetentionMaxBytes).await,
+        }
+    }
+
+    /// 挑出需要删除的记录，活跃任务的记录永远保留
+    ///
+    /// 从新到旧累计，超出条数或体积上限、或超过年龄的记录都会被删除
+    pub fn select_doomed(
+        &self,
+        entries: &[ArchiveEntry],
+        active: &HashSet<FileHash>,
+        now: SystemTime,
+    ) -> HashSet<u64> {
+        let mut newest_first = entries.iter().collect::<Vec<_>>();
+        newest_first.sort_by_key(|entry| Reverse(entry.finished_at));
+        let (mut kept, mut kept_bytes) = (0usize, 0u64);
+        newest_first
+            .into_iter()
+            .filter(|entry| {
+                if active.contains(&entry.hash) {
+                    return false;
+                }
+                let too_old = now
+                    .duration_since(entry.finished_at)
+                    .is_ok_and(|age| age > self.max_age);
+                let over_quota =
+                    kept >= self.max_entries || kept_bytes.saturating_add(entry.size) > self.max_bytes;
+                if too_old || over_quota {
+                    return true;
+                }
+                kept += 1;
+                kept_bytes += entry.size;
+                false
+            })
+            .map(|entry| entry.id)
+            .collect()
+    }
+}
+
+/// 返回正在进行的任务，它们的记录不会被裁剪
+pub type ActiveTasks = Arc<dyn Fn() -> HashSet<FileHash> + Send + Sync>;
+
+/// 定期按策略裁剪所有归档
+pub struct ArchiveMaintainer {
+    abort: AbortHandle,
+}
+
+impl ArchiveMaintainer {
+    const INTERVAL: Duration = Duration::from_hours(1);
+
+    pub fn run(
+        cfg: &'static ConfigManager,
+        archives: Vec<Arc<dyn Archive>>,
+        active: ActiveTasks,
+    ) -> Self {
+        let abort = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Self::INTERVAL);
+            loop {
+                interval.tick().await;
+                // 每次重新读取，配置热更新后立即生效
+                let policy = RetentionPolicy::from_config(cfg).await;
+                let active = active();
+                for archive in &archives {
+                    if let Err(err) = Self::prune(archive.as_ref(), &policy, &active) {
+                        warn!("Failed to prune {}: {err}", archive.name());
+                    }
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    pub fn prune(
+        archive: &dyn Archive,
+        policy: &RetentionPolicy,
+        active: &HashSet<FileHash>,
+    ) -> io::Result<usize> {
+        let doomed = policy.select_doomed(&archive.entries()?, active, SystemTime::now());
+        if !doomed.is_empty() {
+            archive.remove(&doomed)?;
+            info!("Pruned {} records from {}", doomed.len(), archive.name());
+        }
+        Ok(doomed.len())
+    }
+}
+
+impl Drop for ArchiveMaintainer {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Archive maintainer has been dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, hash: FileHash, age_days: u64, size: u64, now: SystemTime) -> ArchiveEntry {
+        ArchiveEntry {
+            id,
+            hash,
+            finished_at: now - Duration::from_days(age_days),
+            size,
+        }
+    }
+
+    #[test]
+    fn prune_by_count_age_and_size() {
+        let now = SystemTime::now();
+        let policy = RetentionPolicy {
+            max_entries: 2,
+            max_age: Duration::from_days(30),
+            max_bytes: 100,
+        };
+        let entries = [
+            entry(0, 10, 1, 10, now),
+            entry(1, 11, 2, 10, now),
+            entry(2, 12, 3, 10, now), // 超出条数
+            entry(3, 13, 40, 10, now), // 过期
+        ];
+        let doomed = policy.select_doomed(&entries, &HashSet::new(), now);
+        assert_eq!(doomed, HashSet::from([2, 3]));
+
+        let entries = [entry(0, 10, 1, 90, now), entry(1, 11, 2, 20, now)];
+        let doomed = policy.select_doomed(&entries, &HashSet::new(), now);
+        assert_eq!(doomed, HashSet::from([1]));
+    }
+
+    #[test]
+    fn never_prune_active() {
+        let now = SystemTime::now();
+        let policy = RetentionPolicy {
+            max_entries: 0,
+            max_age: Duration::ZERO,
+            max_bytes: 0,
+        };
+        let entries = [entry(0, 10, 100, 10, now), entry(1, 11, 100, 10, now)];
+        let doomed = policy.select_doomed(&entries, &HashSet::from([10]), now);
+        assert_eq!(doomed, HashSet::from([1]));
+    }
+}
@@ -0,0 +1,109 @@
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+/// 常见文件系统单个文件名的长度上限，Windows 上按 UTF-16 码元计，其他平台按字节计
+pub const MAX_FILE_NAME: usize = 255;
+
+/// Windows 上无论扩展名如何都指向设备的名字
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FileNameError {
+    #[error("File name is empty")]
+    Empty,
+    #[error("{0:?} is a reserved device name")]
+    Reserved(String),
+    #[error("File name is {0} units long, longer than {MAX_FILE_NAME}")]
+    TooLong(usize),
+}
+
+/// 对端给出的文件名只取最后一段，清理后才能拼到下载目录下
+///
+/// 先按 NFC 规范化，避免同一个名字在不同平台上出现两种写法
+pub fn sanitize_file_name(raw: &str) -> Result<String, FileNameError> {
+    // 两种分隔符都要处理，对端可能来自另一个平台
+    let last = raw.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = last
+        .nfc()
+        .map(|ch| match ch {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect::<String>();
+    // Windows 会静默去掉结尾的点和空格，两端得到的名字应当一致
+    let name = name.trim_end_matches(['.', ' ']).trim_start();
+    if name.is_empty() {
+        return Err(FileNameError::Empty);
+    }
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        return Err(FileNameError::Reserved(name.to_string()));
+    }
+    let len = name_len(name);
+    if len > MAX_FILE_NAME {
+        return Err(FileNameError::TooLong(len));
+    }
+    Ok(name.to_string())
+}
+
+/// NTFS 按 UTF-16 码元限制文件名，按字节计会把中日韩文件名截到三分之一
+fn name_len(name: &str) -> usize {
+    match cfg!(windows) {
+        true => name.encode_utf16().count(),
+        false => name.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_and_reject() {
+        assert_eq!(sanitize_file_name("../../.bashrc").unwrap(), ".bashrc");
+        assert_eq!(sanitize_file_name("C:\\Windows\\a.txt").unwrap(), "a.txt");
+        assert_eq!(sanitize_file_name("/etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_file_name("what?.txt. ").unwrap(), "what_.txt");
+        assert_eq!(sanitize_file_name("bell\u{7}.bin").unwrap(), "bell_.bin");
+        // 组合字符被合成为单个码位
+        assert_eq!(sanitize_file_name("cafe\u{301}").unwrap(), "caf\u{e9}");
+
+        assert_eq!(sanitize_file_name("dir/"), Err(FileNameError::Empty));
+        assert_eq!(sanitize_file_name(".."), Err(FileNameError::Empty));
+        assert!(matches!(
+            sanitize_file_name("nul.txt"),
+            Err(FileNameError::Reserved(_))
+        ));
+        assert!(matches!(
+            sanitize_file_name("Com1"),
+            Err(FileNameError::Reserved(_))
+        ));
+        assert!(sanitize_file_name("console.log").is_ok());
+        assert_eq!(
+            sanitize_file_name(&"a".repeat(256)),
+            Err(FileNameError::TooLong(256))
+        );
+    }
+
+    #[test]
+    fn cjk_names() {
+        // 全角标点是合法字符，不会被替换
+        assert_eq!(
+            sanitize_file_name("资料/报告：第一季度.pdf").unwrap(),
+            "报告：第一季度.pdf"
+        );
+        // 100 个汉字在 Windows 上是 100 个码元，在其他平台上是 300 字节
+        let long = "猎".repeat(100);
+        match cfg!(windows) {
+            true => assert_eq!(sanitize_file_name(&long).unwrap(), long),
+            false => assert_eq!(sanitize_file_name(&long), Err(FileNameError::TooLong(300))),
+        }
+    }
+}
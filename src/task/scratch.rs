@@ -0,0 +1,283 @@
+use super::FileHash;
+use atomicwrites::{AtomicFile, OverwriteBehavior::AllowOverwrite};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+/// 临时文件统一放在下载目录的这个子目录下
+pub const SCRATCH_DIR: &str = ".falcon";
+const JOURNAL_NAME: &str = "scratch.json";
+const SCRATCH_SUFFIX: &str = ".part";
+
+/// 任务失败、取消或被丢弃时如何处理临时文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScratchPolicy {
+    #[default]
+    Discard,
+    /// 留给之后的续传
+    PreserveForResume,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScratchEntry {
+    pub hash: FileHash,
+    pub file_name: String,
+    pub policy: ScratchPolicy,
+    /// unix 秒
+    pub created: u64,
+}
+
+/// 记录所有在用临时文件的日志，启动时由清理程序对照
+#[derive(Debug)]
+pub struct ScratchJournal {
+    path: Utf8PathBuf,
+    entries: Mutex<HashMap<String, ScratchEntry>>,
+}
+
+impl ScratchJournal {
+    pub fn open(path: &Utf8Path) -> io::Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                warn!("Discard corrupted scratch journal {path}: {err}");
+                HashMap::new()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn entries(&self) -> HashMap<String, ScratchEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    fn register(&self, name: String, entry: ScratchEntry) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(name, entry);
+        self.persist(&entries)
+    }
+
+    fn unregister(&self, name: &str) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(name).is_some() {
+            self.persist(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn set_policy(&self, name: &str, policy: ScratchPolicy) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(name) {
+            entry.policy = policy;
+            self.persist(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn persist(&self, entries: &HashMap<String, ScratchEntry>) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(entries)?;
+        AtomicFile::new(&self.path, AllowOverwrite)
+            .write(|f| f.write_all(&content))
+            .map_err(io::Error::other)
+    }
+}
+
+/// 每个任务独立的临时文件空间
+#[derive(Debug, Clone)]
+pub struct ScratchSpace {
+    dir: Utf8PathBuf,
+    journal: Arc<ScratchJournal>,
+}
+
+impl ScratchSpace {
+    pub fn open(download_dir: &Utf8Path) -> io::Result<Self> {
+        let dir = download_dir.join(SCRATCH_DIR);
+        fs::create_dir_all(&dir)?;
+        let journal = Arc::new(ScratchJournal::open(&dir.join(JOURNAL_NAME))?);
+        Ok(Self { dir, journal })
+    }
+
+    pub fn journal(&self) -> &ScratchJournal {
+        &self.journal
+    }
+
+    /// 分配 `<hash>-<nanoid>.part`，同一文件的多次尝试互不干扰
+    pub fn allocate(
+        &self,
+        hash: FileHash,
+        file_name: &str,
+        policy: ScratchPolicy,
+    ) -> io::Result<ScratchGuard> {
+        let name = format!("{hash:016x}-{}{SCRATCH_SUFFIX}", nanoid::nanoid!(8));
+        let entry = ScratchEntry {
+            hash,
+            file_name: file_name.to_owned(),
+            policy,
+            created: unix_now(),
+        };
+        self.journal.register(name.clone(), entry)?;
+        Ok(ScratchGuard {
+            path: self.dir.join(&name),
+            name,
+            journal: self.journal.clone(),
+            policy,
+            committed: false,
+        })
+    }
+
+    /// 启动时的清理：删除日志外的孤儿文件，以及超龄或应丢弃的条目
+    ///
+    /// 返回删除的文件数
+    pub fn sweep(&self, max_age: Duration) -> io::Result<usize> {
+        let entries = self.journal.entries();
+        let now = unix_now();
+        let mut removed = 0;
+        for entry in self.dir.read_dir_utf8()? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if !name.ends_with(SCRATCH_SUFFIX) {
+                continue;
+            }
+            let keep = entries.get(name).is_some_and(|e| {
+                e.policy == ScratchPolicy::PreserveForResume
+                    && now.saturating_sub(e.created) < max_age.as_secs()
+            });
+            if !keep {
+                remove_file(entry.path());
+                removed += 1;
+            }
+        }
+        // 文件已不存在或不再保留的条目一并移除
+        for (name, entry) in entries {
+            let path = self.dir.join(&name);
+            if !path.exists()
+                || entry.policy == ScratchPolicy::Discard
+                || now.saturating_sub(entry.created) >= max_age.as_secs()
+            {
+                self.journal.unregister(&name)?;
+            }
+        }
+        info!("Scratch janitor removed {removed} files under {}", self.dir);
+        Ok(removed)
+    }
+}
+
+/// 任务持有的临时文件守卫，未提交就被丢弃时按策略清理
+#[derive(Debug)]
+pub struct ScratchGuard {
+    path: Utf8PathBuf,
+    name: String,
+    journal: Arc<ScratchJournal>,
+    policy: ScratchPolicy,
+    committed: bool,
+}
+
+impl ScratchGuard {
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    pub fn set_policy(&mut self, policy: ScratchPolicy) -> io::Result<()> {
+        self.policy = policy;
+        self.journal.set_policy(&self.name, policy)
+    }
+
+    /// 文件已被移到最终位置，不再归临时空间管理
+    pub fn commit(mut self) -> io::Result<()> {
+        self.committed = true;
+        self.journal.unregister(&self.name)
+    }
+}
+
+impl Drop for ScratchGuard {
+    fn drop(&mut self) {
+        if self.committed || self.policy == ScratchPolicy::PreserveForResume {
+            return;
+        }
+        remove_file(&self.path);
+        if let Err(err) = self.journal.unregister(&self.name) {
+            warn!("Failed to unregister scratch {}: {err}", self.name);
+        }
+    }
+}
+
+fn remove_file(path: &Utf8Path) {
+    match fs::remove_file(path) {
+        Ok(()) => info!("Removed scratch file {path}"),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => warn!("Failed to remove scratch file {path}: {err}"),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn space() -> (tempfile::TempDir, ScratchSpace) {
+        let dir = tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let space = ScratchSpace::open(&root).unwrap();
+        (dir, space)
+    }
+
+    #[test]
+    fn drop_discards() {
+        let (_dir, space) = space();
+        let guard = space.allocate(1, "a.bin", ScratchPolicy::Discard).unwrap();
+        fs::write(guard.path(), b"partial").unwrap();
+        let path = guard.path().to_owned();
+        drop(guard);
+        assert!(!path.exists());
+        assert!(space.journal().entries().is_empty());
+    }
+
+    #[test]
+    fn drop_preserves_for_resume() {
+        let (_dir, space) = space();
+        let guard = space
+            .allocate(1, "a.bin", ScratchPolicy::PreserveForResume)
+            .unwrap();
+        fs::write(guard.path(), b"partial").unwrap();
+        let path = guard.path().to_owned();
+        drop(guard);
+        assert!(path.exists());
+        // 重新打开后日志依然记得它
+        let reopened = ScratchSpace::open(path.parent().unwrap().parent().unwrap()).unwrap();
+        assert_eq!(reopened.journal().entries().len(), 1);
+        assert_eq!(reopened.sweep(Duration::from_days(1)).unwrap(), 0);
+        assert_eq!(reopened.sweep(Duration::ZERO).unwrap(), 1);
+        assert!(reopened.journal().entries().is_empty());
+    }
+
+    #[test]
+    fn sweep_orphans() {
+        let (_dir, space) = space();
+        let orphan = space.dir.join("deadbeef-orphan.part");
+        fs::write(&orphan, b"leak").unwrap();
+        let guard = space.allocate(2, "b.bin", ScratchPolicy::Discard).unwrap();
+        fs::write(guard.path(), b"partial").unwrap();
+        let committed = guard.path().to_owned();
+        guard.commit().unwrap();
+        // 已提交的文件本应被移走，遗留的同样视为孤儿
+        assert_eq!(space.sweep(Duration::from_days(1)).unwrap(), 2);
+        assert!(!orphan.exists() && !committed.exists());
+    }
+}
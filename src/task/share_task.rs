@@ -1,27 +1,193 @@
-use super::{Payload, TaggedTaskEvent, TaskEvent, TaskState, TaskTag};
-use crate::hot_file::{HotFile, arrange_bytes_to_vec};
+use super::{
+    FileHash, OptSource, Outstanding, Payload, RETRANSMIT_TIMEOUT, TaggedTaskEvent, TaskEvent,
+    TaskState, TaskTag, checksum_algorithm, verify_hash_or_correct,
+};
+use crate::{
+    hot_file::{ChecksumAlgorithm, ChecksumPurpose, FileMultiRange, FileRange, HotFile},
+    link::link_state_table,
+    policy::Throttle,
+    utils::HostId,
+};
 use tokio::{
     sync::{mpsc, watch},
     task::AbortHandle,
+    time::{interval, timeout},
 };
+use tracing::{debug, warn};
+
+/// 预处理阶段清单的块大小，对端按块比对本地已有数据
+pub(super) const MANIFEST_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// 记录对端确认的范围，确认即为上传进度
+fn apply_ack(
+    encoded: &[u8],
+    outstanding: &mut Outstanding,
+    status_in: &watch::Sender<TaskState>,
+    host: &HostId,
+) {
+    let received = match FileMultiRange::from_wire(encoded) {
+        Ok(received) => received,
+        Err(err) => {
+            warn!("Ignore malformed ack from {host}: {err}");
+            return;
+        }
+    };
+    outstanding.acknowledge(&received);
+    status_in.send_modify(|state| {
+        let acked = state.with_upload_mut(host.clone(), |progress| {
+            received.iter().try_for_each(|rgn| progress.add(*rgn))
+        });
+        if let Err(err) = acked {
+            debug!("Failed to record ack from {host}: {err}");
+        }
+    });
+}
+
+/// 完整持有文件时先发送块清单，对端复用本地已有的块并回传确认，之后只发送缺失的部分
+///
+/// 未完整持有时清单里含有尚未写入的块，不能作为比对依据；
+/// 协商了 BLAKE3 时在清单之前给出整文件的摘要
+async fn exchange_manifest(
+    file: &HotFile,
+    status_out: &watch::Receiver<TaskState>,
+    event_in: &mpsc::Sender<TaggedTaskEvent>,
+    tag: &TaskTag,
+    acks: &mut mpsc::Receiver<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let complete = {
+        let state = status_out.borrow();
+        state.downloaded_len() >= state.total_len()
+    };
+    if !complete {
+        return None;
+    }
+    let manifest = match file.manifest(MANIFEST_BLOCK_SIZE).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!("Failed to build manifest, send the whole file: {err}");
+            return None;
+        }
+    };
+    let (_, host) = tag;
+    if checksum_algorithm(ChecksumPurpose::File, host) == ChecksumAlgorithm::Blake3 {
+        // 算不出摘要只是少一道校验，文件哈希仍然会被核对
+        match file.checksum(ChecksumAlgorithm::Blake3).await {
+            Ok(digest) => event_in
+                .send((tag.clone(), TaskEvent::Digest(digest)))
+                .await
+                .ok()?,
+            Err(err) => warn!("Failed to digest file for {host}: {err}"),
+        }
+    }
+    let event = (tag.clone(), TaskEvent::Manifest(manifest));
+    event_in.send(event).await.ok()?;
+    // 对端比对需要读完本地文件，等不到就按对端一无所有处理
+    timeout(RETRANSMIT_TIMEOUT, acks.recv())
+        .await
+        .ok()
+        .flatten()
+}
+
+/// 分享任务接收对端事件的通道，发送端见 `TaskManager` 的 `ShareInputs`
+pub(super) struct ShareReceivers {
+    pub acks: mpsc::Receiver<Vec<u8>>,  // 对端发来的范围确认
+    pub wants: mpsc::Receiver<Vec<u8>>, // 多源下载时对端只要求本机发送的范围
+    pub pauses: mpsc::Receiver<bool>,   // 对端暂停（true）或恢复（false）接收
+    pub checks: mpsc::Receiver<(FileRange, FileHash)>, // 对端按清单发现的损坏块及其部分哈希
+}
+
+/// 分享任务的限速与读盘、发送节奏
+pub(super) struct ShareLimits {
+    pub throttle: Throttle,
+    pub read_ahead: usize, // 按顺序分块读取，预读能省下大部分寻道
+    pub window: usize,     // 已发出未确认的字节上限，见 `MemoryBudget::upload_window`
+    pub batch: usize,      // 每批发出的块数，见 `MemoryBudget::batch_size`
+}
 
 // 这个函数应当应对share 事件，且返回aborthandle
-fn spwan_share_task(
+pub(super) fn spwan_share_task(
     file: HotFile,
     mut status_out: watch::Receiver<TaskState>,
     status_in: watch::Sender<TaskState>,
     event_in: mpsc::Sender<TaggedTaskEvent>,
     tag: TaskTag,
+    receivers: ShareReceivers,
+    limits: ShareLimits,
 ) -> AbortHandle {
+    let ShareReceivers {
+        mut acks,
+        mut wants,
+        mut pauses,
+        mut checks,
+    } = receivers;
+    let ShareLimits {
+        throttle,
+        read_ahead,
+        window,
+        batch,
+    } = limits;
+    file.set_read_ahead(read_ahead);
     tokio::spawn(async move {
+        let (_, host) = tag.clone();
+        // 已发出但对端尚未确认的范围，超时后重新计入待发送；窗口满后等确认腾出空间再读盘
+        let mut outstanding = Outstanding::with_window(window);
+        let mut retransmit = interval(RETRANSMIT_TIMEOUT / 2);
+        // 为空时发送对端缺少的全部数据
+        let mut wanted: Option<FileMultiRange> = None;
+        if let Some(encoded) =
+            exchange_manifest(&file, &status_out, &event_in, &tag, &mut acks).await
+        {
+            apply_ack(&encoded, &mut outstanding, &status_in, &host);
+        }
         // 先观察当前进度，迅速生成数据流扔管道里
         'a: loop {
-            // 然后等待下载进度变化
-            if let Err(_) = status_out.changed().await {
-                break;
+            // 然后等待下载进度变化、对端确认或重传超时
+            tokio::select! {
+                changed = status_out.changed() => if changed.is_err() {
+                    break;
+                },
+                Some(encoded) = acks.recv() => {
+                    // 修改上传进度会再次唤醒本循环
+                    apply_ack(&encoded, &mut outstanding, &status_in, &host);
+                    continue;
+                }
+                Some(paused) = pauses.recv() => {
+                    // 与确认一样，修改上传状态会再次唤醒本循环
+                    status_in.send_modify(|state| {
+                        let flipped = match paused {
+                            true => state.stop_upload(host.clone(), OptSource::Remote),
+                            false => state.resume_upload(host.clone()),
+                        };
+                        if let Err(err) = flipped {
+                            debug!("Ignore pause state {paused} from {host}: {err}");
+                        }
+                    });
+                    continue;
+                }
+                Some((range, partial_hash)) = checks.recv() => {
+                    // 与本地不一致时整块回传，不占用发送窗口
+                    let host = host.clone();
+                    verify_hash_or_correct(&file, range, partial_hash, &event_in, &status_in, host)
+                        .await;
+                    continue;
+                }
+                Some(encoded) = wants.recv() => match FileMultiRange::from_wire(&encoded) {
+                    Ok(ranges) => wanted = Some(ranges),
+                    Err(err) => {
+                        warn!("Ignore malformed piece request from {host}: {err}");
+                        continue;
+                    }
+                },
+                _ = retransmit.tick() => {
+                    let expired = outstanding.expire(RETRANSMIT_TIMEOUT);
+                    if expired.is_empty() {
+                        continue;
+                    }
+                    let total = status_out.borrow().total_len();
+                    debug!("Retransmit {} to {host}", expired.summary(total));
+                }
             }
 
-            let (_, host) = tag.clone();
             // 获取下载和上传进度
             //这样会有问题吗？当然没有，主任务保存了上传进度的
             // 不过下一版本需要将上传进度改成map了
@@ -30,32 +196,80 @@ fn spwan_share_task(
                 let Ok(download) = borrowed_status.get_download_progress() else {
                     break;
                 };
-                let Some(result) = borrowed_status.get_upload_progress(&host) else {
-                    break;
-                };
-                let Ok(upload) = result else {
-                    break;
+                // 还没有收到过确认时视为对端一无所有
+                let acked = match borrowed_status.get_upload_progress(&host) {
+                    None => FileMultiRange::new(),
+                    // 对端取消或暂停后不再发送，等待恢复
+                    Some(Ok(upload)) if upload.is_paused() => continue,
+                    Some(Ok(upload)) => upload.progress().clone(),
+                    Some(Err(_)) => break,
                 };
-                download.progress().subtract(&upload.progress())
+                let remain = download
+                    .progress()
+                    .subtract(&acked)
+                    .subtract(borrowed_status.unavailable())
+                    .subtract(&outstanding.ranges());
+                match &wanted {
+                    Some(wanted) => remain.intersect(wanted),
+                    None => remain,
+                }
             };
-            // 分割成指定大小的块
-            let mut split_iter = remain.split(8); // 假设返回 Result 迭代器
+            // 每块装进一个不会分片的数据报文，链路 MTU 在探测中会变化，每轮重新取
+            let mut split_iter = remain.split(link_state_table().max_payload(&host));
+            let mut sent = 0;
             // 遍历每个分割后的区块
             while let Some(rgn_result) = split_iter.next() {
+                // 每批之间先收下已到的确认，窗口能及时腾出来
+                if sent > 0 && sent.is_multiple_of(batch.max(1)) {
+                    while let Ok(encoded) = acks.try_recv() {
+                        apply_ack(&encoded, &mut outstanding, &status_in, &host);
+                    }
+                }
                 match rgn_result {
                     Ok(rgn) => {
-                        let buf = file.read(rgn.into()).await.unwrap();
-                        let buf = arrange_bytes_to_vec(buf.into_iter());
+                        // 窗口已满，剩下的等确认或重传超时后再读，避免数据堆在通道里
+                        let room = outstanding.room();
+                        if room == 0 {
+                            break;
+                        }
+                        let rgn = FileRange::new(rgn.start(), rgn.end().min(rgn.start() + room));
+                        // 读盘失败只影响这一块，通知对方后继续发送剩余部分
+                        let event = match file.read_vectored(rgn.into()).await {
+                            Ok(buf) => {
+                                throttle.acquire(buf.len()).await;
+                                TaskEvent::Append(Payload::from_bytes(
+                                    rgn.start(),
+                                    buf.into_bytes(),
+                                ))
+                            }
+                            Err(err) => {
+                                warn!(
+                                    "Failed to read {rgn:?} for sharing, mark it unavailable: {err}"
+                                );
+                                status_in.send_modify(|state| state.mark_unavailable(rgn));
+                                TaskEvent::Unavailable(rgn)
+                            }
+                        };
                         // 构造并发送网络事件
-                        let event = (tag.clone(), TaskEvent::Append(Payload::new(0, buf)));
+                        let delivered = matches!(event, TaskEvent::Append(_));
+                        let event = (tag.clone(), event);
                         if let Err(err) = event_in.send(event).await {
                             status_in.send_modify(|state| state.set_upload_err(host.clone(), err));
                             break 'a;
                         }
+                        sent += 1;
+                        if delivered {
+                            outstanding.sent(rgn);
+                            // 只更新速率，不必为此唤醒进度订阅者
+                            status_in.send_if_modified(|state| {
+                                state.record_sent(rgn.interval());
+                                false
+                            });
+                        }
                     }
                     Err(err) => {
                         // 分割错误时更新状态并退出
-                        status_in.send_modify(|state| state.set_upload_err(host, err));
+                        status_in.send_modify(|state| state.set_upload_err(host.clone(), err));
                         break 'a;
                     }
                 }
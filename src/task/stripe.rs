@@ -0,0 +1,205 @@
+use crate::{
+    addr::EndPoint,
+    hot_file::{FileMultiRange, FileRange},
+    link::Weight,
+};
+
+/// 条带中的一条通道，对应 bond 中的一条链路
+#[derive(Debug, Clone)]
+pub struct StripeLane {
+    pub local: EndPoint,
+    pub remote: EndPoint,
+    weight: Weight,
+    assigned: FileMultiRange,
+    done: FileMultiRange,
+    healthy: bool,
+}
+
+impl StripeLane {
+    /// 分配给该通道但尚未完成的范围
+    pub fn pending(&self) -> FileMultiRange {
+        self.assigned.subtract(&self.done)
+    }
+
+    pub fn done(&self) -> &FileMultiRange {
+        &self.done
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+/// 把一个传输的剩余范围按权重切到多条链路上并发发送
+#[derive(Debug, Clone)]
+pub struct StripePlan {
+    lanes: Vec<StripeLane>,
+}
+
+impl StripePlan {
+    pub fn new(remaining: &FileMultiRange, links: &[(EndPoint, EndPoint, Weight)]) -> Self {
+        let mut lanes = links
+            .iter()
+            .map(|&(local, remote, weight)| StripeLane {
+                local,
+                remote,
+                weight,
+                assigned: FileMultiRange::new(),
+                done: FileMultiRange::new(),
+                healthy: true,
+            })
+            .collect::<Vec<_>>();
+        let targets = (0..lanes.len()).collect::<Vec<_>>();
+        distribute(&mut lanes, &targets, remaining);
+        Self { lanes }
+    }
+
+    pub fn lanes(&self) -> &[StripeLane] {
+        &self.lanes
+    }
+
+    /// 从通道的待发范围中取出不超过 budget 字节的下一段
+    ///
+    /// budget 通常取该链路拥塞窗口的剩余量，窗口满时返回 None
+    pub fn next_chunk(&self, lane: usize, budget: usize) -> Option<FileRange> {
+        let rgn = *self.lanes.get(lane)?.pending().iter().next()?;
+        let take = rgn.interval().min(budget);
+        (take > 0).then(|| FileRange::new(rgn.start(), rgn.start() + take))
+    }
+
+    /// 某条通道完成了一段数据
+    pub fn complete(&mut self, lane: usize, rgn: FileRange) {
+        if let Some(lane) = self.lanes.get_mut(lane) {
+            lane.done.add(rgn);
+        }
+    }
+
+    /// 链路劣化：把它未完成的部分按权重重新分给其余健康通道
+    ///
+    /// 没有其他健康通道时返回 false，调用方应当退回单链路模式
+    pub fn degrade(&mut self, lane: usize) -> bool {
+        let Some(degraded) = self.lanes.get_mut(lane) else {
+            return false;
+        };
+        degraded.healthy = false;
+        let orphaned = degraded.pending();
+        degraded.assigned = degraded.done.clone();
+        let targets = self
+            .lanes
+            .iter()
+            .enumerate()
+            .filter(|(_, lane)| lane.healthy)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            // 放回原处，等待链路恢复
+            let degraded = &mut self.lanes[lane];
+            for rgn in orphaned.iter() {
+                degraded.assigned.add(*rgn);
+            }
+            return false;
+        }
+        distribute(&mut self.lanes, &targets, &orphaned);
+        true
+    }
+
+    /// 链路恢复后重新参与之后的重分配
+    pub fn recover(&mut self, lane: usize) {
+        if let Some(lane) = self.lanes.get_mut(lane) {
+            lane.healthy = true;
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.lanes.iter().all(|lane| lane.pending().is_empty())
+    }
+}
+
+/// 按权重比例把 ranges 依次切给 targets 中的通道
+fn distribute(lanes: &mut [StripeLane], targets: &[usize], ranges: &FileMultiRange) {
+    let total_weight = targets
+        .iter()
+        .map(|&i| lanes[i].weight.max(1) as u128)
+        .sum::<u128>();
+    let total_bytes = ranges.interval() as u128;
+    if total_weight == 0 || total_bytes == 0 {
+        return;
+    }
+    let mut ranges = ranges.iter().copied();
+    let mut current = ranges.next();
+    for (n, &i) in targets.iter().enumerate() {
+        // 最后一条通道兜底，吸收取整误差
+        let mut quota = if n + 1 == targets.len() {
+            usize::MAX
+        } else {
+            (total_bytes * lanes[i].weight.max(1) as u128 / total_weight) as usize
+        };
+        while quota > 0
+            && let Some(rgn) = current
+        {
+            let take = rgn.interval().min(quota);
+            lanes[i]
+                .assigned
+                .add(FileRange::new(rgn.start(), rgn.start() + take));
+            quota -= take;
+            current = if take == rgn.interval() {
+                ranges.next()
+            } else {
+                Some(FileRange::new(rgn.start() + take, rgn.end()))
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::mock_endpoint_lan;
+
+    fn links(weights: &[Weight]) -> Vec<(EndPoint, EndPoint, Weight)> {
+        weights
+            .iter()
+            .map(|&w| (mock_endpoint_lan(), mock_endpoint_lan(), w))
+            .collect()
+    }
+
+    #[test]
+    fn split_by_weight() {
+        let remaining = FileMultiRange::from(FileRange::new(0, 1000));
+        let plan = StripePlan::new(&remaining, &links(&[3, 1]));
+        assert_eq!(plan.lanes()[0].pending(), FileRange::new(0, 750).into());
+        assert_eq!(plan.lanes()[1].pending(), FileRange::new(750, 1000).into());
+    }
+
+    #[test]
+    fn chunk_within_window() {
+        let remaining = FileMultiRange::from(FileRange::new(0, 1000));
+        let mut plan = StripePlan::new(&remaining, &links(&[1]));
+        assert_eq!(plan.next_chunk(0, 0), None);
+        assert_eq!(plan.next_chunk(0, 300), Some(FileRange::new(0, 300)));
+        plan.complete(0, FileRange::new(0, 300));
+        assert_eq!(plan.next_chunk(0, 4096), Some(FileRange::new(300, 1000)));
+    }
+
+    #[test]
+    fn rebalance_on_degrade() {
+        let remaining = FileMultiRange::from(FileRange::new(0, 900));
+        let mut plan = StripePlan::new(&remaining, &links(&[1, 1, 1]));
+        plan.complete(1, FileRange::new(300, 400));
+        assert!(plan.degrade(1));
+        assert_eq!(plan.lanes()[1].pending(), FileMultiRange::new());
+        // 剩下的 200 字节平分给另外两条
+        assert_eq!(plan.lanes()[0].pending().interval(), 400);
+        assert_eq!(plan.lanes()[2].pending().interval(), 400);
+        for i in [0, 2] {
+            for rgn in plan.lanes()[i].pending().iter().copied().collect::<Vec<_>>() {
+                plan.complete(i, rgn);
+            }
+        }
+        assert!(plan.is_complete());
+
+        // 最后一条健康通道劣化时无处可分
+        assert!(plan.degrade(0));
+        assert!(!plan.degrade(2));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    hot_file::{FileMultiRange, FileRange},
+    inbound::HostId,
+};
+use indexmap::IndexMap;
+use tokio::time::Instant;
+
+/// 多源下载时最快的对端每次分到的字节数
+pub const SWARM_PIECE: usize = 4 * 1024 * 1024;
+/// 再慢的对端也至少分到这么多，避免分片过碎
+const MIN_PIECE: usize = 256 * 1024;
+
+/// 单个来源的待收范围与平滑后的速率
+#[derive(Debug)]
+struct Source {
+    pending: FileMultiRange,
+    /// 字节每秒
+    rate: f64,
+    last: Instant,
+    /// 距上次计算速率新收到的字节
+    unrated: usize,
+}
+
+impl Source {
+    const ALPHA: f64 = 0.3;
+
+    fn new() -> Self {
+        Self {
+            pending: FileMultiRange::new(),
+            rate: 0.0,
+            last: Instant::now(),
+            unrated: 0,
+        }
+    }
+
+    fn record(&mut self, bytes: usize) {
+        self.unrated += bytes;
+        let now = Instant::now();
+        let secs = now.duration_since(self.last).as_secs_f64();
+        if secs > 0.0 {
+            let instant = self.unrated as f64 / secs;
+            self.rate = Self::ALPHA * instant + (1.0 - Self::ALPHA) * self.rate;
+            self.last = now;
+            self.unrated = 0;
+        }
+    }
+}
+
+/// 同一文件从多个对端并行下载时的范围调度
+///
+/// 每个对端只发送分给它的互不相交的分片，分片大小按速率与最快对端的比例缩放，
+/// 待收范围用掉一半后补充下一片
+#[derive(Debug, Default)]
+pub struct SwarmScheduler {
+    sources: IndexMap<HostId, Source>,
+}
+
+impl SwarmScheduler {
+    pub fn new(host: HostId) -> Self {
+        let mut scheduler = Self::default();
+        scheduler.join(host);
+        scheduler
+    }
+
+    /// 已经在列表里时返回 false
+    pub fn join(&mut self, host: HostId) -> bool {
+        match self.sources.contains_key(&host) {
+            true => false,
+            false => self.sources.insert(host, Source::new()).is_none(),
+        }
+    }
+
+    /// 对端离开后它未完成的分片回到待分配的部分
+    pub fn leave(&mut self, host: &HostId) -> bool {
+        self.sources.shift_remove(host).is_some()
+    }
+
+    pub fn hosts(&self) -> impl Iterator<Item = &HostId> {
+        self.sources.keys()
+    }
+
+    /// 只有一个来源时不必分片，对端照常推送全部数据
+    pub fn is_swarm(&self) -> bool {
+        self.sources.len() > 1
+    }
+
+    pub fn rate(&self, host: &HostId) -> Option<f64> {
+        self.sources.get(host).map(|source| source.rate)
+    }
+
+    /// 收到数据：计入来源的速率，并从所有分片中去掉这一段
+    pub fn received(&mut self, host: &HostId, rgn: FileRange) {
+        let rgn = FileMultiRange::from(rgn);
+        for source in self.sources.values_mut() {
+            source.pending = source.pending.subtract(&rgn);
+        }
+        if let Some(source) = self.sources.get_mut(host) {
+            source.record(rgn.interval());
+        }
+    }
+
+    /// 还没有速率时一律按最大分片
+    fn piece_size(&self, rate: f64) -> usize {
+        let fastest = self
+            .sources
+            .values()
+            .map(|source| source.rate)
+            .fold(0.0, f64::max);
+        if fastest <= 0.0 {
+            return SWARM_PIECE;
+        }
+        ((SWARM_PIECE as f64 * rate / fastest) as usize).clamp(MIN_PIECE, SWARM_PIECE)
+    }
+
+    /// 给快用完分片的对端补充新分片，返回分配有变化的对端及其完整的待收范围
+    pub fn refill(&mut self, missing: &FileMultiRange) -> Vec<(HostId, FileMultiRange)> {
+        let mut assigned = FileMultiRange::new();
+        for source in self.sources.values_mut() {
+            // 别处送达或被标记为不可用的部分不再等待
+            source.pending = source.pending.intersect(missing);
+            for rgn in source.pending.iter() {
+                assigned.add(*rgn);
+            }
+        }
+        let mut free = missing.subtract(&assigned);
+        let pieces = self
+            .sources
+            .values()
+            .map(|source| self.piece_size(source.rate))
+            .collect::<Vec<_>>();
+        let mut changed = Vec::new();
+        for ((host, source), piece) in self.sources.iter_mut().zip(pieces) {
+            if free.is_empty() {
+                break;
+            }
+            if source.pending.interval() * 2 > piece {
+                continue;
+            }
+            let taken = take_front(&free, piece);
+            free = free.subtract(&taken);
+            for rgn in taken.iter() {
+                source.pending.add(*rgn);
+            }
+            changed.push((host.clone(), source.pending.clone()));
+        }
+        changed
+    }
+}
+
+/// 从头截取不超过 len 字节
+fn take_front(ranges: &FileMultiRange, len: usize) -> FileMultiRange {
+    let mut taken = FileMultiRange::new();
+    let mut left = len;
+    for rgn in ranges.iter() {
+        if left == 0 {
+            break;
+        }
+        let take = rgn.interval().min(left);
+        taken.add(FileRange::new(rgn.start(), rgn.start() + take));
+        left -= take;
+    }
+    taken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn slower_peers_get_smaller_pieces() {
+        let (fast, slow) = (HostId::random(), HostId::random());
+        let mut swarm = SwarmScheduler::new(fast.clone());
+        assert!(!swarm.is_swarm());
+        assert!(swarm.join(slow.clone()));
+        let missing = FileMultiRange::from(FileRange::new(0, 64 * SWARM_PIECE));
+
+        let first = swarm.refill(&missing);
+        assert_eq!(first.len(), 2);
+        // 分片互不相交
+        assert!(first[0].1.intersect(&first[1].1).is_empty());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let fast_piece = *first[0].1.iter().next().unwrap();
+        let slow_piece = *first[1].1.iter().next().unwrap();
+        swarm.received(&fast, fast_piece);
+        swarm.received(
+            &slow,
+            FileRange::new(slow_piece.start(), slow_piece.start() + MIN_PIECE),
+        );
+        assert!(swarm.rate(&fast).unwrap() > swarm.rate(&slow).unwrap());
+
+        let missing = missing
+            .subtract(&fast_piece.into())
+            .subtract(&FileRange::new(slow_piece.start(), slow_piece.start() + MIN_PIECE).into());
+        let next = swarm.refill(&missing);
+        // 慢的对端还有大半片没收完，只给快的补充
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].0, fast);
+        assert_eq!(next[0].1.interval(), SWARM_PIECE);
+
+        // 慢的对端离开后，它的分片会分给剩下的对端
+        assert!(swarm.leave(&slow));
+        swarm.received(&fast, *next[0].1.iter().next().unwrap());
+        let missing = missing.subtract(&next[0].1);
+        let reclaimed = swarm.refill(&missing);
+        assert_eq!(
+            reclaimed[0].1.iter().next().unwrap().start(),
+            slow_piece.start() + MIN_PIECE
+        );
+    }
+}
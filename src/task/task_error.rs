@@ -1,5 +1,6 @@
-use super::{ProgressError, TaggedTaskEvent};
-use crate::hot_file::{FileRangeError, HotFileError};
+use super::{FileHash, FileNameError, ProgressError, TaggedTaskEvent};
+use crate::hot_file::{Checksum, FileRangeError, HotFileError};
+use std::{io, path::PathBuf};
 use thiserror::Error;
 use tokio::sync::mpsc::error::{SendError, TrySendError};
 
@@ -15,4 +16,22 @@ pub enum TaskError {
     Range(#[from] FileRangeError),
     #[error("")]
     TaskState(#[from] ProgressError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Need {needed} bytes but only {available} bytes are available")]
+    InsufficientSpace { needed: u64, available: u64 },
+    #[error(transparent)]
+    FileName(#[from] FileNameError),
+    #[error("{0:?} already exists")]
+    FileExists(PathBuf),
+    #[error("Expected hash {expected:016x} but got {actual:016x}")]
+    HashMismatch {
+        expected: FileHash,
+        actual: FileHash,
+    },
+    #[error("Expected checksum {expected} but got {actual}")]
+    ChecksumMismatch {
+        expected: Checksum,
+        actual: Checksum,
+    },
 }
@@ -1,47 +1,144 @@
 use super::{
-    FileHash, FileInfo, TaggedTaskEvent, TaskCtrl, TaskError, TaskEvent, TaskState, TaskTag,
-    main_event_loop,
+    ActiveTasks, Claim, CompletedTransfer, DiskNotice, DiskPolicy, DiskWatcher, DownloadPolicy,
+    FileHash, FileInfo, LinkWatcher, ProgressEvent, ProgressReporter, ScratchPolicy, ShareLimits,
+    ShareReceivers, TaggedTaskEvent, TaskCommand, TaskCtrl, TaskError, TaskEvent, TaskFrame,
+    TaskRole, TaskState, TaskTag, lossy_stream, main_event_loop, parent_dir, part_path,
+    sanitize_file_name, spwan_share_task,
 };
 use crate::{
-    event_handler::task::{Payload, TaskCommand},
-    hot_file::{FileMultiRange, FileRange, HotFile},
-    utils::{HostId, Uid},
+    config::MemoryBudget,
+    hot_file::{FileRange, FlushPolicy, HotFile},
+    inbound::MAX_FRAME,
+    policy::{Throttle, TokenBucket, TransferPriority, transfer_scheduler},
+    utils::HostId,
 };
-use bytes::Bytes;
-use futures::stream::SelectAll;
-use std::collections::HashMap;
+use dashmap::DashSet;
+use futures::Stream;
+use std::{collections::HashMap, path::Path, sync::Arc};
 use tokio::{
-    sync::{mpsc, watch},
+    sync::{broadcast, mpsc, watch},
     task::AbortHandle,
 };
-use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info, warn};
 
-// 通过信号量控制并行任务数量
+// 通过全局调度器的信号量控制并行任务数量
 
 type FileId = FileHash;
-struct TaskManager {
-    manager_event: mpsc::Sender<TaggedTaskEvent>,
-    event_upstream: mpsc::Receiver<TaggedTaskEvent>, // 用于接受上游网络事件，这个时候的事件还带tag，需要自己分配到对应的 event_input
-    // 下面记得套个 rwlock
-    event_downstream: SelectAll<ReceiverStream<TaggedTaskEvent>>, // 这个组用于输出发送到其他客户端的下游网络事件
+/// 发往对端的任务帧，由上层编码后交给路由
+pub type OutgoingFrame = (HostId, TaskFrame);
+
+/// 向某个对端分享文件的任务，对端发来的确认等事件经这些通道送达
+struct ShareInputs {
+    acks: mpsc::Sender<Vec<u8>>,
+    wants: mpsc::Sender<Vec<u8>>,
+    pauses: mpsc::Sender<bool>,
+    checks: mpsc::Sender<(FileRange, FileHash)>,
+    abort: AbortHandle,
+}
+
+impl Drop for ShareInputs {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+pub struct TaskManager {
+    outgoing: mpsc::Sender<OutgoingFrame>, // 各任务发往对端的事件汇总于此
     // 记得封自己的uid
     event_inputs: HashMap<FileId, mpsc::Sender<TaskCtrl>>, //不同的协程映射的网络事件接收器
     status_outputs: HashMap<FileId, watch::Receiver<TaskState>>, // 支持根据文件id访问文件状态
     running_tasks: HashMap<FileId, AbortHandle>,           // 保存协程句柄，根据文件id取消协程
+    active: Arc<DashSet<FileId>>,                          // running_tasks 的键，供不持锁读取
+    shares: HashMap<TaskTag, ShareInputs>,                 // 正在向各对端分享的任务
+    budget: MemoryBudget,                                  // 通道容量等都由内存预算推导
+    task_limits: HashMap<FileId, Arc<TokenBucket>>,        // 单任务限速，可在运行时调整
+    flush: FlushPolicy,                                    // 下载文件的后台刷盘策略
+    read_ahead: usize,                                     // 分享读盘时预读的段数
+    progress: ProgressReporter,                            // 向界面广播各任务的进度
+    pending_offers: HashMap<FileId, (FileInfo, HostId)>,   // 等待用户决定的邀约
+    disk: DiskPolicy,                                      // 剩余空间的水位线与检查间隔
+    destination: DownloadPolicy,                           // 保存目录与同名文件的处理
+    disk_watchers: HashMap<FileId, DiskWatcher>,           // 空间不足时暂停对应的下载
+    link_watchers: HashMap<FileId, LinkWatcher>,           // 来源不可达时暂停，链路恢复后继续
+    disk_notices: broadcast::Sender<DiskNotice>,           // 向界面广播空间不足与恢复
+    completed: broadcast::Sender<CompletedTransfer>,       // 校验并改名到目标位置后广播
+}
+
+/// 把任务发出的事件补上文件 id 与对端的任务角色，汇入发往对端的队列
+///
+/// 任务退出、丢弃发送端后自行结束
+fn forward(
+    file_id: FileId,
+    to: TaskRole,
+    mut events: mpsc::Receiver<TaggedTaskEvent>,
+    outgoing: mpsc::Sender<OutgoingFrame>,
+) {
+    tokio::spawn(async move {
+        while let Some(((_, host), event)) = events.recv().await {
+            let frame = TaskFrame::new(file_id, to, event);
+            if outgoing.send((host, frame)).await.is_err() {
+                break;
+            }
+        }
+    });
 }
 
 impl TaskManager {
+    /// 返回值的另一半是各任务发往对端的事件，接收方丢弃后任务的发送随之失败
+    pub fn new(
+        budget: MemoryBudget,
+        flush: FlushPolicy,
+        disk: DiskPolicy,
+        destination: DownloadPolicy,
+    ) -> (Self, mpsc::Receiver<OutgoingFrame>) {
+        const CAPACITY: usize = 64;
+        let (outgoing, outgoing_rx) = mpsc::channel(budget.channel_bound());
+        // 单个文件的脏数据也不能超过内存预算的份额
+        let flush = FlushPolicy {
+            max_dirty_bytes: flush.max_dirty_bytes.min(budget.dirty_cap()),
+            ..flush
+        };
+        let manager = Self {
+            outgoing,
+            event_inputs: HashMap::new(),
+            status_outputs: HashMap::new(),
+            running_tasks: HashMap::new(),
+            active: Arc::new(DashSet::new()),
+            shares: HashMap::new(),
+            budget,
+            task_limits: HashMap::new(),
+            flush,
+            read_ahead: HotFile::DEFAULT_READ_AHEAD,
+            progress: ProgressReporter::new(),
+            pending_offers: HashMap::new(),
+            disk,
+            destination,
+            disk_watchers: HashMap::new(),
+            link_watchers: HashMap::new(),
+            disk_notices: broadcast::channel(CAPACITY).0,
+            completed: broadcast::channel(CAPACITY).0,
+        };
+        (manager, outgoing_rx)
+    }
+
+    /// 之后开始的分享按这个段数预读，见 `HotFile::set_read_ahead`
+    pub fn set_read_ahead(&mut self, depth: usize) {
+        self.read_ahead = depth;
+    }
+
     // 在taskmanager 实例化时也插入一个
     // 这个函数只会在 new 下触发
     // 创建任务时，让他拿着一个信号量
-    pub async fn download_or_share(&mut self, file_info: FileInfo, remote: HostId) {
-        let (up_event_in, up_event_out) = mpsc::channel::<TaskCtrl>(1024);
-        let (down_event_in, down_event_out) = mpsc::channel::<TaggedTaskEvent>(1024);
+    pub async fn download_or_share(&mut self, file_info: FileInfo, remote: HostId, claim: Claim) {
+        let path = claim.path().to_path_buf();
+        let bound = self.budget.channel_bound();
+        let (up_event_in, up_event_out) = mpsc::channel::<TaskCtrl>(bound);
+        let (down_event_in, down_event_out) = mpsc::channel::<TaggedTaskEvent>(bound);
         let task_state_init = TaskState::try_new(file_info.size());
         let (status_in, status_out) = watch::channel::<TaskState>(task_state_init.into());
 
-        // 记得拼接下文件路径
-        let Ok(file) = HotFile::open_new(file_info.file_name())
+        // 大小已知，预先分配避免写入时反复扩展文件；完成前只写临时文件
+        let Ok(file) = HotFile::open_new_with_len(part_path(&path), file_info.size())
             .await
             .map_err(|err| {
                 status_in.send_modify(|state| state.set_download_err(err));
@@ -52,15 +149,418 @@ impl TaskManager {
             return;
         };
 
-        self.event_downstream
-            .push(ReceiverStream::new(down_event_out));
         let file_id = file_info.file_hash();
+        forward(
+            file_id,
+            TaskRole::Share,
+            down_event_out,
+            self.outgoing.clone(),
+        );
+        let disk_ctrl = up_event_in.clone();
+        let link_ctrl = up_event_in.clone();
         self.event_inputs.insert(file_id, up_event_in);
+        self.progress.watch(file_id, status_out.clone());
+        let watcher = DiskWatcher::run(
+            file_id,
+            parent_dir(&path).to_path_buf(),
+            self.disk,
+            disk_ctrl,
+            self.disk_notices.clone(),
+        );
+        self.disk_watchers.insert(file_id, watcher);
+        let watcher = LinkWatcher::run(file_id, remote.clone(), link_ctrl);
+        self.link_watchers.insert(file_id, watcher);
         self.status_outputs.insert(file_id, status_out);
+        let throttle = Throttle::download(0);
+        let flush = self.flush;
+        let (meta, completed) = (*file_info.meta(), self.completed.clone());
+        self.task_limits.insert(file_id, throttle.task_bucket());
         let abort = tokio::spawn(async move {
-            main_event_loop(remote, file, up_event_out, down_event_in, status_in)
+            // 任务结束或被取消前一直占着保存路径
+            let _claim = claim;
+            // 名额已满时在这里排队，网络事件暂存在通道里
+            let active = transfer_scheduler().admit(file_id).await;
+            let throttle = throttle.with_share(active.share());
+            main_event_loop(
+                remote,
+                file,
+                path,
+                file_id,
+                meta,
+                up_event_out,
+                down_event_in,
+                status_in,
+                throttle,
+                flush,
+                completed,
+            )
+            .await
         })
         .abort_handle();
         self.running_tasks.insert(file_id, abort);
+        self.active.insert(file_id);
+    }
+
+    /// 记录对端的邀约，等待上层调用 `accept_offer` 或 `reject_offer`
+    ///
+    /// 文件名不合法时直接拒绝，不会进入待决列表
+    pub async fn offer(&mut self, file_info: FileInfo, remote: HostId) -> Result<(), TaskError> {
+        let file_id = file_info.file_hash();
+        let raw = file_info.file_name().to_string_lossy();
+        let name = match sanitize_file_name(&raw) {
+            Ok(name) => name,
+            Err(err) => {
+                warn!("Reject offer of {raw:?} from {remote}: {err}");
+                self.decline(file_id, remote, err.to_string()).await;
+                return Err(err.into());
+            }
+        };
+        let file_info = FileInfo::new(file_id, name, *file_info.meta());
+        self.pending_offers.insert(file_id, (file_info, remote));
+        Ok(())
+    }
+
+    /// 接受邀约，按邀约中的长度预分配文件并开始下载
+    ///
+    /// 剩余空间不足或同名文件按策略拒绝时保留邀约，处理后可以再次接受
+    pub async fn accept_offer(&mut self, file_id: FileId) -> Result<bool, TaskError> {
+        let Some((file_info, remote)) = self.pending_offers.remove(&file_id) else {
+            return Ok(false);
+        };
+        let checked = match self
+            .destination
+            .resolve(file_info.file_name(), &remote)
+            .await
+        {
+            Ok(claim) => self
+                .disk
+                .preflight(parent_dir(claim.path()), file_info.size() as u64)
+                .map(|_| claim),
+            Err(err) => Err(err),
+        };
+        let claim = match checked {
+            Ok(claim) => claim,
+            Err(err) => {
+                self.pending_offers.insert(file_id, (file_info, remote));
+                return Err(err);
+            }
+        };
+        self.download_or_share(file_info, remote, claim).await;
+        Ok(true)
+    }
+
+    /// 让另一个同样持有该文件的对端加入下载，与已有来源并行发送不同的分片
+    pub async fn join_swarm(&self, file_id: FileId, host: HostId) -> bool {
+        let Some(input) = self.event_inputs.get(&file_id) else {
+            return false;
+        };
+        let join = TaskCtrl::Command(TaskCommand::Join(host));
+        input.send(join).await.is_ok()
+    }
+
+    /// 暂停下载：任务落盘后丢弃收到的数据，并通知所有来源停止发送
+    pub async fn pause(&self, file_id: FileId) -> bool {
+        let Some(input) = self.event_inputs.get(&file_id) else {
+            return false;
+        };
+        let pause = TaskCtrl::Command(TaskCommand::Pause);
+        input.send(pause).await.is_ok()
+    }
+
+    /// 恢复下载：通知来源继续发送，暂停期间缺失的部分由确认触发重传
+    pub async fn resume(&self, file_id: FileId) -> bool {
+        let Some(input) = self.event_inputs.get(&file_id) else {
+            return false;
+        };
+        let resume = TaskCtrl::Command(TaskCommand::Resume);
+        input.send(resume).await.is_ok()
+    }
+
+    /// 拒绝邀约并告知对端原因
+    pub async fn reject_offer(&mut self, file_id: FileId, reason: impl Into<String>) -> bool {
+        let Some((_, remote)) = self.pending_offers.remove(&file_id) else {
+            return false;
+        };
+        self.decline(file_id, remote, reason.into()).await
+    }
+
+    async fn decline(&self, file_id: FileId, remote: HostId, reason: String) -> bool {
+        let decline = TaskFrame::new(file_id, TaskRole::Share, TaskEvent::Decline(reason));
+        self.outgoing.send((remote, decline)).await.is_ok()
+    }
+
+    /// 对端的 Fetch 通过授权后开始向它分享本地文件
+    ///
+    /// 同一对端重复拉取时重新开始，之前未确认的部分由新任务补发
+    pub async fn share(
+        &mut self,
+        file_id: FileId,
+        path: &Path,
+        remote: HostId,
+    ) -> Result<(), TaskError> {
+        let tag = (file_id, remote);
+        self.shares.remove(&tag);
+        let len = tokio::fs::metadata(path).await?.len() as usize;
+        let file = HotFile::open_existed(path).await?;
+        let bound = self.budget.channel_bound();
+        let (status_in, status_out) = watch::channel::<TaskState>(TaskState::seeded(len).into());
+        let (event_in, event_out) = mpsc::channel::<TaggedTaskEvent>(bound);
+        let (acks, acks_rx) = mpsc::channel(bound);
+        let (wants, wants_rx) = mpsc::channel(bound);
+        let (pauses, pauses_rx) = mpsc::channel(bound);
+        let (checks, checks_rx) = mpsc::channel(bound);
+        forward(
+            file_id,
+            TaskRole::Download,
+            event_out,
+            self.outgoing.clone(),
+        );
+        // 缓存最多 2 * depth 段，每段按一个最大报文估算，不超过预算的预读份额
+        let read_ahead = self
+            .read_ahead
+            .min(self.budget.read_ahead() / (2 * MAX_FRAME));
+        let receivers = ShareReceivers {
+            acks: acks_rx,
+            wants: wants_rx,
+            pauses: pauses_rx,
+            checks: checks_rx,
+        };
+        let limits = ShareLimits {
+            throttle: Throttle::upload(0),
+            read_ahead,
+            window: self.budget.upload_window(),
+            batch: self.budget.batch_size(),
+        };
+        let abort = spwan_share_task(
+            file,
+            status_out,
+            status_in,
+            event_in,
+            tag.clone(),
+            receivers,
+            limits,
+        );
+        info!("Start sharing {path:?} with {}", tag.1);
+        let inputs = ShareInputs {
+            acks,
+            wants,
+            pauses,
+            checks,
+            abort,
+        };
+        self.shares.insert(tag, inputs);
+        Ok(())
+    }
+
+    /// 把对端发来的任务帧交给对应的任务，没有对应的任务或任务已经退出时返回 false
+    pub async fn route(&mut self, host: HostId, frame: TaskFrame) -> bool {
+        let TaskFrame { hash, to, event } = frame;
+        if to == TaskRole::Download {
+            let Some(input) = self.event_inputs.get(&hash) else {
+                return false;
+            };
+            return input.send(TaskCtrl::Sourced(host, event)).await.is_ok();
+        }
+        let tag = (hash, host);
+        // 对端取消或下载完成后不再需要这份分享
+        if let TaskEvent::Cancel = event {
+            return self.shares.remove(&tag).is_some();
+        }
+        let Some(share) = self.shares.get(&tag) else {
+            return false;
+        };
+        let delivered = match event {
+            TaskEvent::Ack(encoded) => share.acks.send(encoded).await.is_ok(),
+            TaskEvent::Want(encoded) => share.wants.send(encoded).await.is_ok(),
+            TaskEvent::Pause => share.pauses.send(true).await.is_ok(),
+            TaskEvent::Resume => share.pauses.send(false).await.is_ok(),
+            TaskEvent::Check {
+                range,
+                partial_hash,
+            } => share.checks.send((range, partial_hash)).await.is_ok(),
+            _ => {
+                debug!(
+                    "Ignore download event for the share of {hash:016x} to {}",
+                    tag.1
+                );
+                true
+            }
+        };
+        if !delivered {
+            self.shares.remove(&tag);
+        }
+        delivered
+    }
+
+    /// 取消下载：任务落盘后按策略处理已下载的部分，并通知对端停止上传
+    ///
+    /// 任务已经退出时直接中止协程，此时无法再通知对端
+    pub async fn cancel(&mut self, file_id: FileId, policy: ScratchPolicy) -> bool {
+        let Some(abort) = self.running_tasks.remove(&file_id) else {
+            return false;
+        };
+        self.active.remove(&file_id);
+        self.task_limits.remove(&file_id);
+        self.disk_watchers.remove(&file_id);
+        self.link_watchers.remove(&file_id);
+        let rescind = TaskCtrl::Command(TaskCommand::Rescind(policy));
+        match self.event_inputs.remove(&file_id) {
+            // 由任务自己收尾后退出
+            Some(input) if input.send(rescind).await.is_ok() => {}
+            _ => abort.abort(),
+        }
+        true
+    }
+
+    /// 订阅所有任务的进度事件
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.progress.subscribe()
+    }
+
+    /// 不必持有管理器的锁即可查询正在进行的下载，供归档裁剪跳过
+    pub fn active_tasks(&self) -> ActiveTasks {
+        let active = self.active.clone();
+        Arc::new(move || active.iter().map(|id| *id).collect())
+    }
+
+    /// 订阅已完成的下载，此时文件已位于目标路径
+    pub fn subscribe_completed(&self) -> broadcast::Receiver<CompletedTransfer> {
+        self.completed.subscribe()
+    }
+
+    /// 已完成下载的流，供嵌入方在文件落地后打开目录、调用扫描等，不必轮询状态
+    ///
+    /// 消费过慢时跳过积压的部分而不是终止流
+    pub fn completed_files(&self) -> impl Stream<Item = CompletedTransfer> + use<> {
+        lossy_stream(self.completed.subscribe())
+    }
+
+    /// 订阅磁盘空间不足导致的暂停与恢复
+    pub fn subscribe_disk(&self) -> broadcast::Receiver<DiskNotice> {
+        self.disk_notices.subscribe()
+    }
+
+    /// 调整传输的优先级，排队中与进行中的任务都立即生效
+    pub fn prioritize(&self, file_id: FileId, priority: TransferPriority) -> bool {
+        if !self.status_outputs.contains_key(&file_id) {
+            return false;
+        }
+        transfer_scheduler().set_priority(file_id, priority);
+        true
+    }
+
+    /// 调整单个任务的限速，0 表示不限速
+    pub fn limit_task(&self, file_id: FileId, rate: u64) -> bool {
+        self.task_limits
+            .get(&file_id)
+            .map(|bucket| bucket.set_rate(rate))
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addr::EndPoint,
+        inbound::{Decoded, Msg, MsgSink},
+        task::{FileMeta, hash_path},
+        testing::MemoryNetwork,
+    };
+    use camino::Utf8Path;
+    use futures::{SinkExt, StreamExt};
+    use std::{net::SocketAddr, time::Duration};
+    use tokio::time::timeout;
+
+    fn endpoint(n: u16) -> EndPoint {
+        format!("[2001:db8::{n}]:5555").parse().unwrap()
+    }
+
+    fn manager(root: &Path) -> (TaskManager, mpsc::Receiver<OutgoingFrame>) {
+        let destination = DownloadPolicy {
+            root: root.to_path_buf(),
+            ..Default::default()
+        };
+        let disk = DiskPolicy {
+            low_watermark: 0,
+            ..Default::default()
+        };
+        let flush = FlushPolicy::default();
+        TaskManager::new(MemoryBudget::default(), flush, disk, destination)
+    }
+
+    /// 和 `Transfer` 一样把任务帧装进 `Msg::Transfer` 发往对端
+    fn pump(
+        local: HostId,
+        mut frames: mpsc::Receiver<OutgoingFrame>,
+        mut sink: MsgSink,
+        dst: SocketAddr,
+    ) {
+        tokio::spawn(async move {
+            while let Some((_, frame)) = frames.recv().await {
+                let payload = frame.encode().unwrap();
+                let msg = Msg::Transfer {
+                    host: local.clone(),
+                    payload,
+                };
+                sink.send((msg, dst)).await.unwrap();
+            }
+        });
+    }
+
+    async fn deliver(manager: &mut TaskManager, decoded: Decoded) {
+        let Ok(Msg::Transfer { host, payload }) = decoded else {
+            panic!("unexpected {decoded:?}");
+        };
+        manager
+            .route(host, TaskFrame::decode(&payload).unwrap())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn share_and_download_over_memory_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src_dir, dst_dir) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(&src_dir).unwrap();
+        let src = src_dir.join("falcon.bin");
+        let data = (0..300_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        std::fs::write(&src, &data).unwrap();
+        let hash = hash_path(Utf8Path::from_path(&src).unwrap()).await.unwrap();
+
+        let (sender, receiver) = (HostId::random(), HostId::random());
+        let (mut sharing, sharing_out) = manager(&src_dir);
+        let (mut downloading, downloading_out) = manager(&dst_dir);
+        let mut completed = downloading.subscribe_completed();
+        let network = MemoryNetwork::new(1);
+        let (a, b) = (endpoint(1), endpoint(2));
+        let (_, sink_a, mut stream_a, _) = network.bind(a);
+        let (_, sink_b, mut stream_b, _) = network.bind(b);
+        pump(sender.clone(), sharing_out, sink_a, b.into());
+        pump(receiver.clone(), downloading_out, sink_b, a.into());
+
+        // 接收端接受邀约后发出 Fetch，发送端据此开始分享
+        let meta = FileMeta::from_wire(data.len() as u64, None, None);
+        let info = FileInfo::new(hash, "falcon.bin".into(), meta);
+        downloading.offer(info, sender.clone()).await.unwrap();
+        assert!(downloading.accept_offer(hash).await.unwrap());
+        sharing.share(hash, &src, receiver.clone()).await.unwrap();
+
+        let transfer = async {
+            loop {
+                tokio::select! {
+                    Some(Ok((decoded, _))) = stream_a.next() => {
+                        deliver(&mut sharing, decoded).await;
+                    }
+                    Some(Ok((decoded, _))) = stream_b.next() => {
+                        deliver(&mut downloading, decoded).await;
+                    }
+                    done = completed.recv() => break done.unwrap(),
+                }
+            }
+        };
+        let done = timeout(Duration::from_secs(30), transfer).await.unwrap();
+        assert_eq!((done.hash, &done.peer), (hash, &sender));
+        assert_eq!(done.path.as_std_path(), dst_dir.join("falcon.bin"));
+        assert_eq!(std::fs::read(&done.path).unwrap(), data);
     }
 }
@@ -1,6 +1,7 @@
+use camino::Utf8Path;
 use std::{borrow::Cow, collections::HashMap};
 
-use super::{TaskError, TaskTag};
+use super::{FileHash, PersistError, RateEstimator, TaskError, TaskSnapshot, global_rates};
 use crate::{
     hot_file::{FileMultiRange, FileRange, FileRangeError},
     utils::HostId,
@@ -21,7 +22,7 @@ pub enum ProgressError {
 }
 
 /// 操作来源（远程/本地）
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OptSource {
     Remote,
     Local,
@@ -98,6 +99,19 @@ impl ProgressState {
         }
     }
 
+    /// 是否已被本地或对端暂停
+    pub fn is_paused(&self) -> bool {
+        self.state.is_paused()
+    }
+
+    /// 暂停的来源，运行中时为空
+    pub fn paused_by(&self) -> Option<OptSource> {
+        match self.state {
+            WorkloadState::Running => None,
+            WorkloadState::Paused(src) => Some(src),
+        }
+    }
+
     /// 获取当前进度
     pub fn progress(&self) -> &FileMultiRange {
         &self.progress
@@ -124,6 +138,21 @@ pub struct TaskState {
 
     /// 完整文件范围
     full: FileMultiRange,
+
+    /// 发送端读盘失败、无法获得的范围
+    unavailable: FileMultiRange,
+
+    /// 收发速率，所有对端合计
+    download_rate: RateEstimator,
+    upload_rate: RateEstimator,
+}
+
+/// 范围级别的任务报告，用于部分完成的任务
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeReport {
+    pub completed: FileMultiRange,
+    pub unavailable: FileMultiRange,
+    pub missing: FileMultiRange,
 }
 
 impl TaskState {
@@ -132,9 +161,22 @@ impl TaskState {
             uploaded: None,
             downloaded: Ok(Default::default()),
             full: FileRange::try_new(0, total)?.into(),
+            unavailable: Default::default(),
+            download_rate: Default::default(),
+            upload_rate: Default::default(),
         })
     }
 
+    /// 本地已经完整持有的文件，分享任务据此发送全部范围
+    pub fn seeded(total: usize) -> Result<Self, ProgressError> {
+        let mut state = Self::try_new(total)?;
+        state.downloaded = Ok(ProgressState {
+            progress: state.full.clone(),
+            state: WorkloadState::Running,
+        });
+        Ok(state)
+    }
+
     fn with_download_mut<F>(&mut self, f: F) -> Result<(), TaskError>
     where
         F: FnOnce(&mut ProgressState) -> Result<(), ProgressError>,
@@ -151,6 +193,28 @@ impl TaskState {
         self.with_download_mut(|s| s.add(rgn))
     }
 
+    /// 记录从网络收到的字节，同时计入全局速率
+    ///
+    /// 本地复用的部分不经过网络，不应计入
+    pub fn record_received(&mut self, bytes: usize) {
+        self.download_rate.record(bytes);
+        global_rates().record_download(bytes);
+    }
+
+    /// 记录发给对端的字节，同时计入全局速率
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.upload_rate.record(bytes);
+        global_rates().record_upload(bytes);
+    }
+
+    pub fn download_rate(&self) -> &RateEstimator {
+        &self.download_rate
+    }
+
+    pub fn upload_rate(&self) -> &RateEstimator {
+        &self.upload_rate
+    }
+
     /// 记录上传范围
     pub fn with_upload_mut<F>(&mut self, host: HostId, f: F) -> Result<(), TaskError>
     where
@@ -168,8 +232,10 @@ impl TaskState {
                 f(state)?;
             }
             Entry::Vacant(entry) => {
-                // 没有就插入默认值
-                entry.insert(Ok(Default::default()));
+                // 没有就插入默认值，再应用本次修改
+                let mut state = ProgressState::default();
+                f(&mut state)?;
+                entry.insert(Ok(state));
             }
         }
         Ok(())
@@ -224,6 +290,78 @@ impl TaskState {
         &self.downloaded
     }
 
+    /// 文件总字节数
+    pub fn total_len(&self) -> usize {
+        self.full.interval()
+    }
+
+    /// 已下载的字节数，出错时为 0
+    pub fn downloaded_len(&self) -> usize {
+        self.downloaded
+            .as_ref()
+            .map(|s| s.progress().interval())
+            .unwrap_or_default()
+    }
+
+    /// 各对端已上传的字节数，出错的对端不计入
+    pub fn uploaded_lens(&self) -> impl Iterator<Item = (&HostId, usize)> {
+        self.uploaded.iter().flatten().filter_map(|(host, state)| {
+            state.as_ref().ok().map(|s| (host, s.progress().interval()))
+        })
+    }
+
+    /// 标记无法获得的范围，不影响其余范围继续传输
+    pub fn mark_unavailable(&mut self, rgn: FileRange) {
+        self.unavailable.add(rgn);
+    }
+
+    pub fn unavailable(&self) -> &FileMultiRange {
+        &self.unavailable
+    }
+
+    /// 除不可获得的范围外均已完成
+    pub fn is_partially_complete(&self) -> bool {
+        !self.unavailable.is_empty() && self.report().missing.is_empty()
+    }
+
+    pub fn report(&self) -> RangeReport {
+        let completed = self
+            .downloaded
+            .as_ref()
+            .map(|s| s.progress().clone())
+            .unwrap_or_default();
+        let missing = self.full.subtract(&completed).subtract(&self.unavailable);
+        RangeReport {
+            completed,
+            unavailable: self.unavailable.clone(),
+            missing,
+        }
+    }
+
+    /// 将下载进度写入旁车文件，上传进度与会话相关不做持久化
+    pub fn persist(&self, hash: FileHash, path: &Utf8Path) -> Result<(), PersistError> {
+        let completed = self
+            .downloaded
+            .as_ref()
+            .map(|s| s.progress().clone())
+            .unwrap_or_default();
+        let total = self.full.last().map(|r| r.end()).unwrap_or_default();
+        TaskSnapshot::new(hash, total, &completed, &self.unavailable).save(path)
+    }
+
+    /// 从旁车文件恢复下载进度，任务以运行状态继续
+    pub fn restore(path: &Utf8Path) -> Result<(FileHash, Self), PersistError> {
+        let snapshot = TaskSnapshot::load(path)?;
+        let mut state = Self::try_new(snapshot.total as usize)
+            .map_err(|_| PersistError::OutOfBounds(snapshot.total))?;
+        state.downloaded = Ok(ProgressState {
+            progress: snapshot.completed()?,
+            state: WorkloadState::Running,
+        });
+        state.unavailable = snapshot.unavailable()?;
+        Ok((snapshot.hash, state))
+    }
+
     pub fn get_upload_progress(&self, host: &HostId) -> Option<&Result<ProgressState, TaskError>> {
         let Some(upload_map) = self.uploaded.as_ref() else {
             return None;
@@ -241,7 +379,71 @@ impl From<Result<TaskState, ProgressError>> for TaskState {
                 uploaded: None,
                 downloaded: Err(err.into()),
                 full: Default::default(),
+                unavailable: Default::default(),
+                download_rate: Default::default(),
+                upload_rate: Default::default(),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_report() {
+        let mut state = TaskState::try_new(100).unwrap();
+        state.download(FileRange::new(0, 40)).unwrap();
+        state.mark_unavailable(FileRange::new(40, 60));
+        assert!(!state.is_partially_complete());
+        let report = state.report();
+        assert_eq!(report.missing, FileRange::new(60, 100).into());
+
+        state.download(FileRange::new(60, 100)).unwrap();
+        assert!(state.is_partially_complete());
+        let report = state.report();
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unavailable, FileRange::new(40, 60).into());
+    }
+
+    #[test]
+    fn persist_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = camino::Utf8PathBuf::try_from(dir.path().join("movie.mkv")).unwrap();
+        let path = crate::task::state_path(&file);
+        let mut state = TaskState::try_new(100).unwrap();
+        state.download(FileRange::new(0, 30)).unwrap();
+        state.download(FileRange::new(50, 70)).unwrap();
+        state.mark_unavailable(FileRange::new(90, 100));
+        state.persist(42, &path).unwrap();
+
+        let (hash, restored) = TaskState::restore(&path).unwrap();
+        assert_eq!(hash, 42);
+        assert_eq!(restored.report(), state.report());
+        assert!(matches!(
+            TaskState::restore(&file),
+            Err(PersistError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn pause_records_source() {
+        let mut state = TaskState::try_new(100).unwrap();
+        state.stop_download(OptSource::Local).unwrap();
+        let paused_by = |state: &TaskState| {
+            state
+                .get_download_progress()
+                .as_ref()
+                .ok()
+                .and_then(ProgressState::paused_by)
+        };
+        assert_eq!(paused_by(&state), Some(OptSource::Local));
+        // 已暂停时不能再次暂停，来源保持不变
+        assert!(state.stop_download(OptSource::Remote).is_err());
+        assert_eq!(paused_by(&state), Some(OptSource::Local));
+        state.resume_download().unwrap();
+        assert_eq!(paused_by(&state), None);
+        assert!(state.resume_download().is_err());
+    }
+}
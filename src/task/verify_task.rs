@@ -0,0 +1,109 @@
+use crate::{
+    hot_file::{
+        BlockManifest, ChecksumAlgorithm, ChecksumPurpose, FileMultiRange, FileRange, HotFile,
+        HotFileError,
+    },
+    inbound::{Capabilities, HostId},
+    session::peer_capabilities,
+};
+use std::sync::atomic::Ordering;
+
+/// 与 host 之间某种用途的校验使用的算法，整文件校验需要双方在握手中都声明 BLAKE3
+pub fn checksum_algorithm(purpose: ChecksumPurpose, host: &HostId) -> ChecksumAlgorithm {
+    let blake3 = peer_capabilities(host).contains(Capabilities::BLAKE3);
+    ChecksumAlgorithm::for_purpose(purpose, blake3)
+}
+
+/// 仅校验不传输：对比本地文件与远端清单
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyReport {
+    /// 与远端不一致（或本地缺失）的范围
+    pub mismatched: FileMultiRange,
+    /// 本地文件长度与远端是否一致
+    pub size_matches: bool,
+}
+
+impl VerifyReport {
+    pub fn is_identical(&self) -> bool {
+        self.size_matches && self.mismatched.is_empty()
+    }
+}
+
+/// 按清单逐块哈希本地文件，得到需要同步的范围
+pub async fn verify_against(
+    file: &HotFile,
+    manifest: &BlockManifest,
+) -> Result<VerifyReport, HotFileError> {
+    let local_len = file.sync_len_state.load(Ordering::Relaxed);
+    let mut mismatched = FileMultiRange::new();
+    for (rgn, remote) in manifest.blocks() {
+        if rgn.end() > local_len {
+            // 本地长度不足的部分一律视为不一致
+            if rgn.start() < local_len {
+                mismatched.add(rgn);
+            } else {
+                mismatched.add(FileRange::new(rgn.start(), manifest.total()));
+                break;
+            }
+            continue;
+        }
+        if HotFile::hash(file.read(rgn.into()).await?) != remote {
+            mismatched.add(rgn);
+        }
+    }
+    Ok(VerifyReport {
+        mismatched,
+        size_matches: local_len == manifest.total(),
+    })
+}
+
+/// 本地已有且与清单一致的范围，续传或重新下载时不必再从对端获取
+pub async fn reusable_ranges(
+    file: &HotFile,
+    manifest: &BlockManifest,
+) -> Result<FileMultiRange, HotFileError> {
+    let report = verify_against(file, manifest).await?;
+    Ok(match manifest.total() {
+        0 => FileMultiRange::new(),
+        total => report.mismatched.complement(FileRange::new(0, total)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn detect_differing_blocks() {
+        let temp_dir = tempdir().unwrap();
+        let remote = HotFile::open_new(temp_dir.path().join("remote"))
+            .await
+            .unwrap();
+        remote.write(b"AAAABBBBCCCCDD", 0).await.unwrap();
+        let manifest = remote.manifest(4).await.unwrap();
+
+        let local = HotFile::open_new(temp_dir.path().join("local"))
+            .await
+            .unwrap();
+        local.write(b"AAAAXBBBCCCCDD", 0).await.unwrap();
+        let report = verify_against(&local, &manifest).await.unwrap();
+        assert!(report.size_matches);
+        assert_eq!(report.mismatched, FileRange::new(4, 8).into());
+
+        let short = HotFile::open_new(temp_dir.path().join("short"))
+            .await
+            .unwrap();
+        short.write(b"AAAABB", 0).await.unwrap();
+        let report = verify_against(&short, &manifest).await.unwrap();
+        assert!(!report.is_identical());
+        assert_eq!(report.mismatched, FileRange::new(4, 14).into());
+        assert_eq!(
+            reusable_ranges(&short, &manifest).await.unwrap(),
+            FileRange::new(0, 4).into()
+        );
+        let mut reusable = FileMultiRange::from(FileRange::new(0, 4));
+        reusable.add(FileRange::new(8, 14));
+        assert_eq!(reusable_ranges(&local, &manifest).await.unwrap(), reusable);
+    }
+}
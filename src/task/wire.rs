@@ -0,0 +1,206 @@
+use super::{FileHash, Payload, TaskEvent};
+use crate::hot_file::{BlockManifest, Checksum, FileRange, FileRangeError};
+use bincode::{
+    Decode, Encode,
+    error::{DecodeError, EncodeError},
+};
+use bytes::Bytes;
+use thiserror::Error;
+
+/// 收到任务帧的一端交给哪一类任务处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum TaskRole {
+    /// 确认、分片请求、暂停与取消，由向本端发送数据的一方发出
+    Share,
+    /// 数据、清单、摘要与不可用的范围，由分享的一方发出
+    Download,
+}
+
+#[derive(Debug, Error)]
+pub enum TaskFrameError {
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error(transparent)]
+    Range(#[from] FileRangeError),
+    #[error("Malformed block manifest")]
+    Manifest,
+    #[error("Event is not sent over the network")]
+    Local,
+}
+
+/// `Msg::Transfer` 的载荷，会话层解开后按文件与角色交给对应的任务
+pub struct TaskFrame {
+    pub hash: FileHash,
+    pub to: TaskRole,
+    pub event: TaskEvent,
+}
+
+/// 线路上的任务事件，范围与偏移一律按 u64 编码
+#[derive(Encode, Decode)]
+enum WireEvent {
+    Append {
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Confirm {
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Cancel,
+    Decline(String),
+    Unavailable {
+        start: u64,
+        end: u64,
+    },
+    Ack(Vec<u8>),
+    Manifest(Vec<u8>),
+    Digest(Checksum),
+    Want(Vec<u8>),
+    Pause,
+    Resume,
+    Check {
+        start: u64,
+        end: u64,
+        partial_hash: FileHash,
+    },
+}
+
+#[derive(Encode, Decode)]
+struct WireFrame {
+    hash: FileHash,
+    to: TaskRole,
+    event: WireEvent,
+}
+
+impl TaskFrame {
+    pub fn new(hash: FileHash, to: TaskRole, event: TaskEvent) -> Self {
+        Self { hash, to, event }
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, TaskFrameError> {
+        let payload = |payload: Payload| (payload.offset() as u64, payload.buf().to_vec());
+        let event = match self.event {
+            TaskEvent::Append(p) => {
+                let (offset, data) = payload(p);
+                WireEvent::Append { offset, data }
+            }
+            TaskEvent::Confirm(p) => {
+                let (offset, data) = payload(p);
+                WireEvent::Confirm { offset, data }
+            }
+            TaskEvent::Cancel => WireEvent::Cancel,
+            TaskEvent::Decline(reason) => WireEvent::Decline(reason),
+            TaskEvent::Unavailable(rgn) => WireEvent::Unavailable {
+                start: rgn.start() as u64,
+                end: rgn.end() as u64,
+            },
+            TaskEvent::Ack(encoded) => WireEvent::Ack(encoded),
+            TaskEvent::Manifest(manifest) => WireEvent::Manifest(manifest.to_bytes()?),
+            TaskEvent::Digest(digest) => WireEvent::Digest(digest),
+            TaskEvent::Want(encoded) => WireEvent::Want(encoded),
+            TaskEvent::Pause => WireEvent::Pause,
+            TaskEvent::Resume => WireEvent::Resume,
+            TaskEvent::Check {
+                range,
+                partial_hash,
+            } => WireEvent::Check {
+                start: range.start() as u64,
+                end: range.end() as u64,
+                partial_hash,
+            },
+            // 邀约走 `Msg::Offer`，不会作为任务帧发出
+            TaskEvent::New(_) => return Err(TaskFrameError::Local),
+        };
+        let frame = WireFrame {
+            hash: self.hash,
+            to: self.to,
+            event,
+        };
+        Ok(bincode::encode_to_vec(frame, bincode::config::standard())?)
+    }
+
+    /// 范围与清单在这里校验，任务拿到的事件总是自洽的
+    pub fn decode(buf: &[u8]) -> Result<Self, TaskFrameError> {
+        let (frame, _): (WireFrame, _) =
+            bincode::decode_from_slice(buf, bincode::config::standard())?;
+        let range = |start: u64, end: u64| FileRange::try_new(start as usize, end as usize);
+        let event = match frame.event {
+            WireEvent::Append { offset, data } => {
+                TaskEvent::Append(Payload::from_bytes(offset as usize, Bytes::from(data)))
+            }
+            WireEvent::Confirm { offset, data } => {
+                TaskEvent::Confirm(Payload::from_bytes(offset as usize, Bytes::from(data)))
+            }
+            WireEvent::Cancel => TaskEvent::Cancel,
+            WireEvent::Decline(reason) => TaskEvent::Decline(reason),
+            WireEvent::Unavailable { start, end } => TaskEvent::Unavailable(range(start, end)?),
+            WireEvent::Ack(encoded) => TaskEvent::Ack(encoded),
+            WireEvent::Manifest(buf) => TaskEvent::Manifest(
+                BlockManifest::from_bytes(&buf).ok_or(TaskFrameError::Manifest)?,
+            ),
+            WireEvent::Digest(digest) => TaskEvent::Digest(digest),
+            WireEvent::Want(encoded) => TaskEvent::Want(encoded),
+            WireEvent::Pause => TaskEvent::Pause,
+            WireEvent::Resume => TaskEvent::Resume,
+            WireEvent::Check {
+                start,
+                end,
+                partial_hash,
+            } => TaskEvent::Check {
+                range: range(start, end)?,
+                partial_hash,
+            },
+        };
+        Ok(Self {
+            hash: frame.hash,
+            to: frame.to,
+            event,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = TaskEvent::Append(Payload::new(4096, b"falcon".to_vec()));
+        let encoded = TaskFrame::new(42, TaskRole::Download, data)
+            .encode()
+            .unwrap();
+        let frame = TaskFrame::decode(&encoded).unwrap();
+        assert_eq!((frame.hash, frame.to), (42, TaskRole::Download));
+        let TaskEvent::Append(payload) = frame.event else {
+            panic!("expected append");
+        };
+        assert_eq!((payload.offset(), payload.buf()), (4096, &b"falcon"[..]));
+
+        let check = TaskEvent::Check {
+            range: FileRange::new(0, 10),
+            partial_hash: 7,
+        };
+        let encoded = TaskFrame::new(42, TaskRole::Share, check).encode().unwrap();
+        let frame = TaskFrame::decode(&encoded).unwrap();
+        assert!(matches!(
+            frame.event,
+            TaskEvent::Check { range, partial_hash: 7 } if range == FileRange::new(0, 10)
+        ));
+    }
+
+    #[test]
+    fn reject_empty_range() {
+        let frame = WireFrame {
+            hash: 1,
+            to: TaskRole::Download,
+            event: WireEvent::Unavailable { start: 8, end: 8 },
+        };
+        let encoded = bincode::encode_to_vec(frame, bincode::config::standard()).unwrap();
+        assert!(matches!(
+            TaskFrame::decode(&encoded),
+            Err(TaskFrameError::Range(_))
+        ));
+    }
+}
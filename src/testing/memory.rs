@@ -0,0 +1,272 @@
+use crate::{
+    addr::EndPoint,
+    inbound::{
+        CodecError, Msg, MsgCodec, MsgSink, MsgSinkMap, MsgStream, StreamHandle, StreamHandles,
+    },
+};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    StreamExt,
+    future::ready,
+    sink,
+    stream::{self, Abortable, SelectAll},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    time::{Instant, sleep_until},
+};
+use tokio_util::codec::{Decoder, Encoder};
+use tracing::trace;
+
+/// 单向链路上的网络条件
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkConditions {
+    /// 固定的单程延迟
+    pub latency: Duration,
+    /// 在固定延迟之上均匀分布的额外延迟
+    pub jitter: Duration,
+    /// 丢包概率
+    pub loss: f64,
+    /// 被额外推迟、从而被后发报文超过的概率
+    pub reorder: f64,
+}
+
+impl LinkConditions {
+    /// 乱序的报文至少推迟这么久，零延迟的链路上也能被超过
+    const REORDER_DELAY: Duration = Duration::from_millis(1);
+
+    pub fn latency(latency: Duration) -> Self {
+        Self {
+            latency,
+            ..Default::default()
+        }
+    }
+}
+
+/// 按到达时间与发送顺序排列，同一时刻到达的报文保持发送顺序
+type Scheduled = ((Instant, u64), (Bytes, SocketAddr));
+
+struct Inner {
+    ports: HashMap<SocketAddr, mpsc::UnboundedSender<Scheduled>>,
+    default: LinkConditions,
+    links: HashMap<(SocketAddr, SocketAddr), LinkConditions>,
+    rng: StdRng,
+    seq: u64,
+}
+
+/// 内存中的数据报网络，收发两半与真实 socket 的 `MsgSink`、`MsgStream` 相同
+///
+/// 报文经过 `MsgCodec` 编解码，随机数由种子决定；
+/// 配合 `start_paused` 的测试，同一种子下的丢包、延迟与乱序完全可复现
+#[derive(Clone)]
+pub struct MemoryNetwork {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MemoryNetwork {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                ports: HashMap::new(),
+                default: LinkConditions::default(),
+                links: HashMap::new(),
+                rng: StdRng::seed_from_u64(seed),
+                seq: 0,
+            })),
+        }
+    }
+
+    /// 没有单独设置的链路都使用这一条件
+    pub fn with_conditions(self, conditions: LinkConditions) -> Self {
+        self.inner.lock().unwrap().default = conditions;
+        self
+    }
+
+    /// 设置从 `from` 到 `to` 方向的条件，反方向不受影响
+    pub fn set_link(&self, from: EndPoint, to: EndPoint, conditions: LinkConditions) {
+        let key = (from.into(), to.into());
+        self.inner.lock().unwrap().links.insert(key, conditions);
+    }
+
+    /// 在内存中绑定一个端点，返回值与 `inbound::bind` 一致
+    ///
+    /// 重复绑定同一地址时，之后的报文只会交给新的一方
+    pub fn bind(&self, local: EndPoint) -> (EndPoint, MsgSink, MsgStream, StreamHandle) {
+        let addr = SocketAddr::from(local);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.inner.lock().unwrap().ports.insert(addr, tx);
+
+        let network = self.clone();
+        let sink = sink::unfold(
+            MsgCodec::default(),
+            move |mut codec, (msg, dst): (Msg, SocketAddr)| {
+                let network = network.clone();
+                async move {
+                    let mut buf = BytesMut::new();
+                    codec.encode(msg, &mut buf)?;
+                    network.deliver(addr, dst, buf.freeze());
+                    Ok::<_, CodecError>(codec)
+                }
+            },
+        );
+        let mut codec = MsgCodec::default();
+        let stream = arrivals(rx).filter_map(move |(datagram, src)| {
+            let mut buf = BytesMut::from(&datagram[..]);
+            ready(match codec.decode_eof(&mut buf) {
+                Ok(Some(decoded)) => Some(Ok((decoded, src))),
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            })
+        });
+        let (handle, registration) = StreamHandle::new_pair();
+        let stream = Abortable::new(stream.boxed(), registration);
+        (local, Box::pin(sink), stream, handle)
+    }
+
+    /// 一次绑定多个端点，返回值与 `inbound::split_group` 一致
+    pub fn bind_group(
+        &self,
+        locals: impl IntoIterator<Item = EndPoint>,
+    ) -> (MsgSinkMap, SelectAll<MsgStream>, StreamHandles) {
+        let mut sinks = HashMap::new();
+        let mut streams = SelectAll::new();
+        let mut handles = HashMap::new();
+        for local in locals {
+            let (addr, sink, stream, handle) = self.bind(local);
+            sinks.insert(addr, sink);
+            streams.push(stream);
+            handles.insert(addr, handle);
+        }
+        (sinks, streams, handles)
+    }
+
+    fn deliver(&self, from: SocketAddr, to: SocketAddr, datagram: Bytes) {
+        let mut inner = self.inner.lock().unwrap();
+        // 和 UDP 一样，没有人监听的地址静默丢弃
+        let Some(port) = inner.ports.get(&to).cloned() else {
+            return;
+        };
+        let conditions = inner
+            .links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(inner.default);
+        if inner.rng.random_bool(conditions.loss.clamp(0.0, 1.0)) {
+            trace!("Drop datagram from {from} to {to}");
+            return;
+        }
+        let mut delay = conditions.latency;
+        if !conditions.jitter.is_zero() {
+            delay += conditions.jitter.mul_f64(inner.rng.random());
+        }
+        if inner.rng.random_bool(conditions.reorder.clamp(0.0, 1.0)) {
+            delay += conditions.latency.max(LinkConditions::REORDER_DELAY);
+        }
+        inner.seq += 1;
+        let scheduled = ((Instant::now() + delay, inner.seq), (datagram, from));
+        if port.send(scheduled).is_err() {
+            // 接收端已经丢弃
+            inner.ports.remove(&to);
+        }
+    }
+}
+
+/// 按预定的到达时间依次交出报文
+fn arrivals(
+    rx: mpsc::UnboundedReceiver<Scheduled>,
+) -> impl futures::Stream<Item = (Bytes, SocketAddr)> + Send {
+    let pending = BTreeMap::new();
+    stream::unfold((rx, pending), |(mut rx, mut pending)| async move {
+        loop {
+            let next = pending.first_key_value().map(|(&(at, _), _)| at);
+            tokio::select! {
+                Some((key, datagram)) = rx.recv() => {
+                    pending.insert(key, datagram);
+                }
+                _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                    let (_, datagram) = pending.pop_first().expect("next comes from pending");
+                    return Some((datagram, (rx, pending)));
+                }
+                else => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inbound::HostId;
+    use futures::SinkExt;
+
+    fn endpoint(n: u16) -> EndPoint {
+        format!("[2001:db8::{n}]:5555").parse().unwrap()
+    }
+
+    /// 用端口号标记报文，便于检查到达顺序
+    fn tagged(tag: u16) -> Msg {
+        let remote = EndPoint::new(*endpoint(9).scoped_addr(), tag);
+        Msg::Discovery {
+            host: HostId::random(),
+            remote,
+        }
+    }
+
+    async fn recv(stream: &mut MsgStream) -> (u16, Instant) {
+        let (decoded, _) = stream.next().await.unwrap().unwrap();
+        match decoded.unwrap() {
+            Msg::Discovery { remote, .. } => (remote.port(), Instant::now()),
+            msg => panic!("unexpected {msg:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latency_loss_and_reorder() {
+        let (a, b) = (endpoint(1), endpoint(2));
+        let network = MemoryNetwork::new(7);
+        let (_, mut sink, _, _) = network.bind(a);
+        let (_, _, mut stream, _) = network.bind(b);
+        let dst = SocketAddr::from(b);
+
+        // 默认条件下立即按序到达
+        let start = Instant::now();
+        sink.send((tagged(1), dst)).await.unwrap();
+        sink.send((tagged(2), dst)).await.unwrap();
+        assert_eq!(recv(&mut stream).await, (1, start));
+        assert_eq!(recv(&mut stream).await, (2, start));
+
+        network.set_link(a, b, LinkConditions::latency(Duration::from_millis(50)));
+        sink.send((tagged(3), dst)).await.unwrap();
+        let (_, at) = recv(&mut stream).await;
+        assert_eq!(at - start, Duration::from_millis(50));
+
+        let lossy = LinkConditions {
+            loss: 1.0,
+            ..Default::default()
+        };
+        network.set_link(a, b, lossy);
+        sink.send((tagged(4), dst)).await.unwrap();
+        let timeout = tokio::time::timeout(Duration::from_secs(1), stream.next());
+        assert!(timeout.await.is_err());
+
+        // 第一个报文被推迟，第二个先到
+        let reorder = |reorder| LinkConditions {
+            latency: Duration::from_millis(10),
+            reorder,
+            ..Default::default()
+        };
+        network.set_link(a, b, reorder(1.0));
+        sink.send((tagged(5), dst)).await.unwrap();
+        network.set_link(a, b, reorder(0.0));
+        sink.send((tagged(6), dst)).await.unwrap();
+        assert_eq!(recv(&mut stream).await.0, 6);
+        assert_eq!(recv(&mut stream).await.0, 5);
+    }
+}
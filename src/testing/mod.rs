@@ -0,0 +1,5 @@
+mod memory;
+mod session;
+
+pub use memory::*;
+pub use session::*;
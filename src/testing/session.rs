@@ -0,0 +1,36 @@
+use crate::{
+    inbound::{Capabilities, HostId, ProtocolVersion},
+    session::{Negotiated, PATTERN, Session, Transport, record_negotiated, session_table},
+};
+
+/// 跳过发现与握手，直接在会话表中为 a、b 两端建立一对加密会话
+///
+/// 两端的会话同在本进程的会话表里：a 发往 b 的报文用键 b 的会话加密，b 用键 a 的会话解开
+pub fn establish(a: &HostId, b: &HostId, capabilities: Capabilities) -> Result<(), snow::Error> {
+    let builder = || snow::Builder::new(PATTERN.parse().unwrap());
+    let keys = builder().generate_keypair()?;
+    let mut initiator = builder()
+        .local_private_key(&keys.private)
+        .build_initiator()?;
+    let keys = builder().generate_keypair()?;
+    let mut responder = builder()
+        .local_private_key(&keys.private)
+        .build_responder()?;
+    let (mut buf, mut read) = (vec![0; 1024], vec![0; 1024]);
+    // -> e; <- e, ee, s, es; -> s, se
+    let len = initiator.write_message(&[], &mut buf)?;
+    responder.read_message(&buf[..len], &mut read)?;
+    let len = responder.write_message(&[], &mut buf)?;
+    initiator.read_message(&buf[..len], &mut read)?;
+    let len = initiator.write_message(&[], &mut buf)?;
+    responder.read_message(&buf[..len], &mut read)?;
+    let negotiated = Negotiated {
+        version: ProtocolVersion::CURRENT,
+        capabilities,
+    };
+    session_table().insert(b.clone(), Session::Transport(Transport::new(initiator)?));
+    session_table().insert(a.clone(), Session::Transport(Transport::new(responder)?));
+    record_negotiated(a, negotiated);
+    record_negotiated(b, negotiated);
+    Ok(())
+}
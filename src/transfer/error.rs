@@ -0,0 +1,36 @@
+use crate::{
+    config::ConfigManagerError,
+    history::HistoryError,
+    link::PunchError,
+    session::{KeyError, SessionError},
+    task::{FileHash, TaskError},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TransferError {
+    #[error(transparent)]
+    Config(#[from] ConfigManagerError),
+    #[error(transparent)]
+    Key(#[from] KeyError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Punch(#[from] PunchError),
+    #[error(transparent)]
+    History(#[from] HistoryError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Task(#[from] TaskError),
+    #[error("Failed to bind sockets: {0}")]
+    Network(#[source] std::io::Error),
+    #[error("Not a regular file: {0}")]
+    NotAFile(String),
+    #[error("Transfer engine has stopped")]
+    Stopped,
+    #[error("File {0:016x} is not shared")]
+    NotShared(FileHash),
+    #[error("No pending offer of {0:016x}")]
+    NotOffered(FileHash),
+}
@@ -0,0 +1,746 @@
+use super::{
+    DeadLetter, FailedTransfer, IncomingTransfer, RetryPolicy, Router, TransferError,
+    TransferNotifier, TransferProgress, loopback::Loopback,
+};
+use crate::{
+    config::{ConfigItem, MemoryBudget, config_manager},
+    inbound::{
+        Capabilities, DiscoveryCounts, HostId, Inbound, InboundPolicy, InterfacePolicy,
+        MetricsExporter, Msg, MulticastPolicy, NicWatcher, PROTOCOL_PORT, QosPolicy, QueueDepth,
+        SocketSnapshot, discovery_stats, interface_gate, metrics, multicast_membership, qos,
+        split_group,
+    },
+    addr::EndPoint,
+    history::{HistoryRecord, history_log, record_history},
+    hot_file::{FlushPolicy, HotFile},
+    link::{
+        self, Acknowledger, AnnouncePolicy, Announcer, DiscoveryPolicy, Event, HolePuncher,
+        LinkProber, LinkSnapshot, PeerChange, PeerInfo, PeerPolicy, PeerReaper, ProbePolicy,
+        PunchPolicy, RelayAgent, RelayPolicy, link_state_table, peer_table,
+    },
+    policy::{
+        RateLimitWatcher, TokenReaper, TokenScope, TransferPriority, TransferToken, token_store,
+        transfer_scheduler,
+    },
+    session::{
+        self, AuthLayer, DedupLayer, EventCounters, EventCounts, Fingerprint, HandshakePolicy,
+        HandshakeWatchdog, HostMetadata, KeepalivePolicy, MetricsLayer, Pipeline, RateLimitLayer,
+        SessionReaper, plaintext_data, static_keys,
+    },
+    shutdown::shutdown_token,
+    task::{
+        Archive, ArchiveMaintainer, BatchVerifyJob, CompletedTransfer, DiskPolicy, DownloadPolicy,
+        FileHash, FileInfo, FileMeta, OutgoingFrame, ProgressEvent, TaskEvent, TaskFrame,
+        TaskManager, expected_from_history, hash_path, lossy_stream,
+    },
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use std::{
+    collections::HashSet,
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{Mutex, broadcast, mpsc},
+    task::AbortHandle,
+};
+use tracing::{debug, info, warn};
+
+/// 本端主动分享的文件
+#[derive(Debug, Clone)]
+struct SharedFile {
+    path: Utf8PathBuf,
+    size: u64,
+    /// 通过 send_file 指定的接收方无需令牌
+    peers: HashSet<HostId>,
+    shared_at: Instant,
+}
+
+impl SharedFile {
+    fn new(path: &Utf8Path, size: u64) -> Self {
+        Self {
+            path: path.to_owned(),
+            size,
+            peers: HashSet::new(),
+            shared_at: Instant::now(),
+        }
+    }
+}
+
+type SharedFiles = Arc<DashMap<FileHash, SharedFile>>;
+type Tasks = Arc<Mutex<TaskManager>>;
+
+/// 对外的高层入口
+///
+/// 把配置、网卡发现、链路表、会话握手串成一条管线：
+/// socket -> Inbound -> 链路层拦截 -> 会话层拦截 -> 分发
+pub struct Transfer {
+    local: HostId,
+    fingerprint: Fingerprint,
+    notifier: TransferNotifier,
+    loopback: Loopback,
+    outbound: mpsc::UnboundedSender<(HostId, Msg)>,
+    shared: SharedFiles,
+    tasks: Tasks,
+    events: Arc<EventCounters>,
+    // 以下仅用于维持后台任务的生命周期，按管线倒序析构
+    _metrics: Option<MetricsExporter>,
+    _rate_limits: RateLimitWatcher,
+    _tokens: TokenReaper,
+    _dispatcher: Dispatcher,
+    _session: session::Interceptor,
+    _handshakes: HandshakeWatchdog,
+    _sessions: SessionReaper,
+    _links: link::Interceptor,
+    puncher: HolePuncher,
+    relay: RelayAgent,
+    _prober: LinkProber,
+    _acks: Acknowledger,
+    _announcer: Announcer,
+    _peers: PeerReaper,
+    _nics: NicWatcher,
+    inbound: Inbound,
+    _router: Router,
+    _archives: Option<ArchiveMaintainer>,
+}
+
+/// 某一时刻的整体状态
+#[derive(Debug, Clone, Default)]
+pub struct TransferStatus {
+    pub links: Vec<LinkSnapshot>,
+}
+
+impl TransferStatus {
+    /// 去重后的对端数
+    pub fn peer_count(&self) -> usize {
+        self.links
+            .iter()
+            .map(|l| &l.host)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+impl Transfer {
+    /// 在所有活跃网卡上启动
+    pub async fn start() -> Result<Self, TransferError> {
+        Self::start_with(Pipeline::new()).await
+    }
+
+    /// 启动并在内置的拦截链之后接上自定义的拦截
+    ///
+    /// 自定义的一环看到的是已解密、已去重与限速的事件，返回 None 即拦下该事件
+    pub async fn start_with(layers: Pipeline) -> Result<Self, TransferError> {
+        // 提前加载配置，让配置错误尽早暴露，之后的限速调整跟随配置文件
+        let cfg = config_manager()?;
+        let rate_limits = RateLimitWatcher::run(cfg);
+        let tokens = TokenReaper::run();
+        // 指标导出是可选的，端口被占用不影响传输
+        let metrics = match cfg.get_typed::<SocketAddr>(ConfigItem::MetricsListen).await {
+            Ok(listen) => MetricsExporter::run(listen)
+                .await
+                .inspect_err(|err| warn!("Failed to export metrics on {listen}: {err}"))
+                .ok(),
+            Err(_) => None,
+        };
+        let local: HostId = cfg.host_id().await?;
+        // 首次运行时生成静态密钥，之后握手都使用同一把
+        let fingerprint = static_keys()?.fingerprint();
+        // 绑定 socket 时按配置加入发现组
+        multicast_membership().configure(MulticastPolicy::from_config(cfg).await);
+        // 绑定前就要按网卡策略筛选，避免在虚拟网卡上发现风暴
+        interface_gate().configure(InterfacePolicy::from_config(cfg).await);
+        qos().configure(QosPolicy::from_config(cfg).await);
+        // 链路在发现时建立，要在那之前生效
+        link::mtu().configure(link::MtuPolicy::from_config(cfg).await);
+        session::rekeying().configure(session::RekeyPolicy::from_config(cfg).await);
+        #[cfg(feature = "chaos")]
+        crate::inbound::chaos().configure(crate::inbound::ChaosPolicy::from_config(cfg).await);
+        // 批量收发的缓冲在绑定时分配
+        #[cfg(all(target_os = "linux", feature = "gso"))]
+        crate::inbound::Offload::configure_batch_size(
+            crate::inbound::Offload::batch_size_from_config(cfg).await,
+        );
+        let (sinks, streams, handles) = split_group().await.map_err(TransferError::Network)?;
+        let (outbound, outbound_rx) = mpsc::unbounded_channel();
+        let (direct, direct_rx) = mpsc::unbounded_channel();
+        let (sink_tx, sink_rx) = mpsc::unbounded_channel();
+        let (stream_tx, stream_rx) = mpsc::unbounded_channel();
+        let locals = sinks.keys().copied().collect();
+        let notifier = TransferNotifier::new();
+        let retry_policy = RetryPolicy::from_config(cfg).await;
+        let router = Router::run(
+            sinks,
+            outbound_rx,
+            direct_rx,
+            sink_rx,
+            retry_policy,
+            notifier.clone(),
+        );
+        let punch_policy = PunchPolicy::from_config(cfg).await;
+        let probe_policy = ProbePolicy::from_config(cfg).await;
+        let (prober, probe_tx) = LinkProber::run(local.clone(), probe_policy, direct.clone());
+        let (acks, feedback_tx) = Acknowledger::run(local.clone(), direct.clone());
+        let (puncher, signal_tx) = HolePuncher::run(local.clone(), locals, punch_policy, direct);
+        let (inbound, msg_rx) =
+            Inbound::receiving(streams, stream_rx, InboundPolicy::from_config(cfg).await).await;
+        let announcer = Announcer::run(
+            local.clone(),
+            AnnouncePolicy::from_config(cfg).await,
+            sink_tx.clone(),
+        );
+        let peers = PeerReaper::run(PeerPolicy::from_config(cfg).await);
+        let nics = NicWatcher::run(cfg, local.clone(), handles, sink_tx, stream_tx);
+        let relay_policy = RelayPolicy::from_config(cfg).await;
+        let (relay, relay_tx) = RelayAgent::run(local.clone(), relay_policy, outbound.clone());
+        let discovery = DiscoveryPolicy::from_config(cfg).await;
+        let (links, event_rx) =
+            link::Interceptor::run(msg_rx, signal_tx, relay_tx, probe_tx, feedback_tx, discovery);
+        let caps = Capabilities::from_config(cfg).await;
+        let metadata = HostMetadata::new(&cfg.get(ConfigItem::HostName).await, PROTOCOL_PORT);
+        // 计数放在链首，统计的是到达会话层的全部事件
+        let metrics_layer = MetricsLayer::default();
+        let events = metrics_layer.counters();
+        let pipeline = Pipeline::new()
+            .layer(metrics_layer)
+            .layer(AuthLayer::new(local.clone(), caps, outbound.clone()).with_metadata(metadata))
+            .layer(RateLimitLayer::from_config(cfg).await)
+            .layer(DedupLayer::default())
+            .chain(layers);
+        let (session, event_rx) = session::Interceptor::run(pipeline, event_rx);
+        let policy = HandshakePolicy::from_config(cfg).await;
+        let handshakes = HandshakeWatchdog::run(policy, local.clone(), outbound.clone());
+        let keepalive = KeepalivePolicy::from_config(cfg).await;
+        let reaper = SessionReaper::run(keepalive, local.clone(), outbound.clone());
+        let shared = SharedFiles::default();
+        let destination = DownloadPolicy::from_config(cfg).await;
+        let (mut tasks, frames) = TaskManager::new(
+            MemoryBudget::from_config(cfg).await,
+            FlushPolicy::from_config(cfg).await,
+            DiskPolicy::from_config(cfg).await,
+            destination.clone(),
+        );
+        tasks.set_read_ahead(HotFile::read_ahead_from_config(cfg).await);
+        let bridge = TaskBridge {
+            local: local.clone(),
+            frames,
+            outbound: outbound.clone(),
+            progress: tasks.subscribe_progress(),
+            completed: tasks.subscribe_completed(),
+        };
+        // 历史文件不可用时只是不裁剪，不影响传输
+        let archives = match history_log() {
+            Ok(log) => {
+                let history: Arc<dyn Archive> = Arc::new(log);
+                Some(ArchiveMaintainer::run(
+                    cfg,
+                    vec![history],
+                    tasks.active_tasks(),
+                ))
+            }
+            Err(err) => {
+                warn!("History is not pruned: {err}");
+                None
+            }
+        };
+        let tasks = Arc::new(Mutex::new(tasks));
+        let dispatcher = Dispatcher::run(
+            event_rx,
+            notifier.clone(),
+            shared.clone(),
+            tasks.clone(),
+            bridge,
+        );
+        let loopback = Loopback::new(local.clone(), destination, notifier.clone());
+        info!("Transfer started as {local} ({fingerprint})");
+        Ok(Self {
+            local,
+            fingerprint,
+            notifier,
+            loopback,
+            outbound,
+            shared,
+            tasks,
+            events,
+            _metrics: metrics,
+            _rate_limits: rate_limits,
+            _tokens: tokens,
+            _dispatcher: dispatcher,
+            _session: session,
+            _handshakes: handshakes,
+            _sessions: reaper,
+            _links: links,
+            puncher,
+            relay,
+            _prober: prober,
+            _acks: acks,
+            _announcer: announcer,
+            _peers: peers,
+            _nics: nics,
+            inbound,
+            _router: router,
+            _archives: archives,
+        })
+    }
+
+    /// 排空发送队列、落盘所有 HotFile、停止调度器后返回
+    pub async fn shutdown(&self) {
+        shutdown_token().shutdown().await;
+    }
+
+    /// 到达会话层的各类事件数
+    pub fn event_counts(&self) -> EventCounts {
+        self.events.snapshot()
+    }
+
+    pub fn local_id(&self) -> &HostId {
+        &self.local
+    }
+
+    /// 入站队列深度，持续接近容量或出现丢弃说明处理跟不上接收
+    pub fn inbound_queue(&self) -> QueueDepth {
+        self.inbound.queue_depth()
+    }
+
+    /// 经配置的中继与 NAT 后的主机打洞，打通后链路出现在 `status()` 中
+    pub fn connect_wan(&self, host: &HostId) -> Result<(), TransferError> {
+        Ok(self.puncher.connect(host)?)
+    }
+
+    /// 请求可信的第三方主机 `via` 转发与 `host` 之间的报文
+    ///
+    /// 中继路径权重最低，只在直连全部失效时使用
+    pub fn relay_through(&self, host: &HostId, via: &HostId) -> Result<(), TransferError> {
+        match self.relay.request(host, via) {
+            true => Ok(()),
+            false => Err(TransferError::Stopped),
+        }
+    }
+
+    /// 中继观察到的本机公网地址
+    pub fn reflexive_endpoint(&self) -> Option<EndPoint> {
+        self.puncher.observed()
+    }
+
+    /// 本机静态公钥指纹，供用户带外核对
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// 向对端发出传输邀约，对端据此发起 Fetch
+    ///
+    /// 发给本机时不经过网络，直接复制到下载目录，照常产生进度与完成通知
+    pub async fn send_file(
+        &self,
+        path: impl AsRef<Utf8Path>,
+        host: &HostId,
+    ) -> Result<FileHash, TransferError> {
+        let path = path.as_ref();
+        let meta = tokio::fs::metadata(path).await?;
+        let file_name = path
+            .file_name()
+            .filter(|_| meta.is_file())
+            .ok_or_else(|| TransferError::NotAFile(path.to_string()))?;
+        let hash = hash_path(path).await?;
+        if host == &self.local {
+            let meta = FileMeta::from(&meta);
+            self.loopback.spawn(path.to_owned(), file_name.to_owned(), hash, meta);
+            return Ok(hash);
+        }
+        self.shared
+            .entry(hash)
+            .or_insert_with(|| SharedFile::new(path, meta.len()))
+            .peers
+            .insert(host.clone());
+        let meta = FileMeta::from(&meta);
+        let offer = Msg::Offer {
+            host: self.local.clone(),
+            hash,
+            file_name: file_name.to_owned(),
+            size: meta.size,
+            mtime: meta.mtime_millis(),
+            permissions: meta.permissions,
+        };
+        self.outbound
+            .send((host.clone(), offer))
+            .map_err(|_| TransferError::Stopped)?;
+        Ok(hash)
+    }
+
+    /// 分享文件而不指定接收方，返回的令牌由用户带外交给对方
+    ///
+    /// 对方凭令牌调用 `fetch_with_token` 下载一次；`scope` 可以限定只有某个对端能用
+    pub async fn share_with_token(
+        &self,
+        path: impl AsRef<Utf8Path>,
+        scope: TokenScope,
+        ttl: Duration,
+    ) -> Result<(FileHash, TransferToken), TransferError> {
+        let path = path.as_ref();
+        let meta = tokio::fs::metadata(path).await?;
+        if !meta.is_file() {
+            return Err(TransferError::NotAFile(path.to_string()));
+        }
+        let hash = hash_path(path).await?;
+        self.shared
+            .entry(hash)
+            .or_insert_with(|| SharedFile::new(path, meta.len()));
+        Ok((hash, token_store().issue(hash, scope, ttl)))
+    }
+
+    /// 对端与本机都开启了 `plaintext_data` 时，是否仍然加密发往它的数据
+    ///
+    /// 只用于可信局域网测速，握手与控制消息总是加密
+    pub fn set_data_encryption(&self, host: &HostId, encrypted: bool) {
+        plaintext_data().set_encrypted(host, encrypted);
+    }
+
+    /// 发往 host 的数据是否加密
+    pub fn is_data_encrypted(&self, host: &HostId) -> bool {
+        plaintext_data().is_encrypted(host)
+    }
+
+    /// 接受邀约，按下载策略确定保存位置、检查剩余空间后向对端发起 Fetch
+    ///
+    /// 同名文件按策略拒绝或空间不足时邀约仍然保留，处理后可以再次接受
+    pub async fn accept(&self, offer: &IncomingTransfer) -> Result<(), TransferError> {
+        self.fetch(offer, None).await
+    }
+
+    /// 凭带外拿到的令牌下载对端没有向本机发出邀约的文件
+    ///
+    /// `offer` 由调用方按分享方一同给出的主机、哈希、文件名与大小构造
+    pub async fn fetch_with_token(
+        &self,
+        offer: &IncomingTransfer,
+        token: TransferToken,
+    ) -> Result<(), TransferError> {
+        let info = FileInfo::new(offer.hash, offer.file_name.clone(), offer.meta);
+        self.tasks
+            .lock()
+            .await
+            .offer(info, offer.peer.clone())
+            .await?;
+        self.fetch(offer, Some(token)).await
+    }
+
+    async fn fetch(
+        &self,
+        offer: &IncomingTransfer,
+        token: Option<TransferToken>,
+    ) -> Result<(), TransferError> {
+        if !self.tasks.lock().await.accept_offer(offer.hash).await? {
+            return Err(TransferError::NotOffered(offer.hash));
+        }
+        let fetch = Msg::Fetch {
+            host: self.local.clone(),
+            hash: offer.hash,
+            token: token.map(|token| token.as_str().to_owned()),
+        };
+        self.outbound
+            .send((offer.peer.clone(), fetch))
+            .map_err(|_| TransferError::Stopped)
+    }
+
+    /// 调整传输的优先级，接受邀约之前或下载进行中都可以设置
+    ///
+    /// 设置了全局下载限速时，活跃的传输按优先级的权重分配带宽
+    pub fn set_priority(&self, hash: FileHash, priority: TransferPriority) {
+        transfer_scheduler().set_priority(hash, priority);
+    }
+
+    pub fn priority(&self, hash: FileHash) -> TransferPriority {
+        transfer_scheduler().priority(hash)
+    }
+
+    /// 拒绝邀约，对端会撤销对本机的授权
+    pub async fn decline(
+        &self,
+        offer: &IncomingTransfer,
+        reason: impl Into<String>,
+    ) -> Result<(), TransferError> {
+        match self.tasks.lock().await.reject_offer(offer.hash, reason).await {
+            true => Ok(()),
+            false => Err(TransferError::NotOffered(offer.hash)),
+        }
+    }
+
+    /// 撤回分享，之后的 Fetch 都会被拒绝
+    pub fn unshare(&self, hash: FileHash) -> Result<(), TransferError> {
+        self.shared
+            .remove(&hash)
+            .map(|_| ())
+            .ok_or(TransferError::NotShared(hash))
+    }
+
+    /// 本端正在分享的文件
+    pub fn shared_files(&self) -> Vec<(FileHash, Utf8PathBuf)> {
+        self.shared
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().path.clone()))
+            .collect()
+    }
+
+    /// 对方发来的传输请求
+    ///
+    /// ```ignore
+    /// let mut incoming = pin!(transfer.subscribe_incoming());
+    /// while let Some(offer) = incoming.next().await { .. }
+    /// ```
+    pub fn subscribe_incoming(&self) -> impl Stream<Item = IncomingTransfer> + use<> {
+        self.notifier.incoming()
+    }
+
+    /// 已完成的传输
+    pub fn completions(&self) -> impl Stream<Item = CompletedTransfer> + use<> {
+        self.notifier.completions()
+    }
+
+    /// 没有完成的传输，原因同时记入历史
+    pub fn failures(&self) -> impl Stream<Item = FailedTransfer> + use<> {
+        self.notifier.failures()
+    }
+
+    /// 最近结束的传输，新的在前，重启后依然可查
+    pub fn history(&self, limit: usize) -> Result<Vec<HistoryRecord>, TransferError> {
+        Ok(history_log()?.recent(limit)?)
+    }
+
+    /// 按历史中完成的下载在后台重新校验下载目录，适用于磁盘故障或从备份恢复之后
+    ///
+    /// `max_bytes_per_sec` 为 0 表示不限速
+    pub async fn verify_downloads(
+        &self,
+        max_bytes_per_sec: u64,
+    ) -> Result<BatchVerifyJob, TransferError> {
+        let destination = DownloadPolicy::from_config(config_manager()?).await;
+        let root = Utf8PathBuf::from_path_buf(destination.root).map_err(|root| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not valid UTF-8", root.display()),
+            )
+        })?;
+        let records = history_log()?.recent(usize::MAX)?;
+        let expected = expected_from_history(&records, destination.per_peer);
+        Ok(BatchVerifyJob::spawn(root, expected, max_bytes_per_sec))
+    }
+
+    /// 进行中传输的进度
+    pub fn progress(&self) -> impl Stream<Item = TransferProgress> + use<> {
+        self.notifier.progress()
+    }
+
+    /// 重试耗尽或永久失败、最终没能发出的报文
+    ///
+    /// 邀约发不出去时对应的分享已被标记为失败并记入历史
+    pub fn dead_letters(&self) -> impl Stream<Item = DeadLetter> + use<> {
+        self.notifier.dead_letters()
+    }
+
+    pub fn status(&self) -> TransferStatus {
+        TransferStatus {
+            links: link_state_table().snapshot(),
+        }
+    }
+
+    /// 各接口 socket 的收发计数
+    pub fn metrics(&self) -> Vec<SocketSnapshot> {
+        metrics()
+    }
+
+    /// 发现报文的处理计数，含去重与限速丢弃的数量
+    pub fn discovery_counts(&self) -> DiscoveryCounts {
+        discovery_stats().snapshot()
+    }
+
+    /// 当前存活的对端，含主机名与握手进度
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        peer_table().list_peers()
+    }
+
+    /// 订阅对端加入、信息变化与过期
+    pub fn peer_changes(&self) -> broadcast::Receiver<PeerChange> {
+        peer_table().on_peer_change()
+    }
+}
+
+/// 任务层的对外出口：任务帧经路由发往对端，进度与完成转交给订阅者
+struct TaskBridge {
+    local: HostId,
+    frames: mpsc::Receiver<OutgoingFrame>,
+    outbound: mpsc::UnboundedSender<(HostId, Msg)>,
+    progress: broadcast::Receiver<ProgressEvent>,
+    completed: broadcast::Receiver<CompletedTransfer>,
+}
+
+impl TaskBridge {
+    /// 与分发分开运行：路由任务帧时可能要等任务腾出通道，而任务又在等这里取走它发出的帧
+    fn run(self, notifier: TransferNotifier) -> AbortHandle {
+        let Self {
+            local,
+            mut frames,
+            outbound,
+            progress,
+            completed,
+        } = self;
+        let mut progress = Box::pin(lossy_stream(progress));
+        let mut completed = Box::pin(lossy_stream(completed));
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    frame = frames.recv() => {
+                        let Some((host, TaskFrame { hash, to, event })) = frame else {
+                            break;
+                        };
+                        // 拒绝以独立的消息发出，对端据此撤销授权
+                        let msg = match event {
+                            TaskEvent::Decline(reason) => Msg::Decline {
+                                host: local.clone(),
+                                hash,
+                                reason,
+                            },
+                            event => match TaskFrame::new(hash, to, event).encode() {
+                                Ok(payload) => Msg::Transfer {
+                                    host: local.clone(),
+                                    payload,
+                                },
+                                Err(err) => {
+                                    warn!("Failed to encode task frame for {host}: {err}");
+                                    continue;
+                                }
+                            },
+                        };
+                        if outbound.send((host, msg)).is_err() {
+                            break;
+                        }
+                    }
+                    Some(event) = progress.next() => {
+                        notifier.notify_progress(TransferProgress::from(&event));
+                    }
+                    Some(done) = completed.next() => notifier.notify_completed(done),
+                }
+            }
+        })
+        .abort_handle()
+    }
+}
+
+/// 会话层之上的事件分发
+struct Dispatcher {
+    abort: AbortHandle,
+    bridge: AbortHandle,
+}
+
+impl Dispatcher {
+    fn run(
+        mut rx: mpsc::Receiver<Event>,
+        notifier: TransferNotifier,
+        shared: SharedFiles,
+        tasks: Tasks,
+        bridge: TaskBridge,
+    ) -> Self {
+        let bridge = bridge.run(notifier.clone());
+        let abort = tokio::spawn(async move {
+            let mut dead_letters = Box::pin(notifier.dead_letters());
+            loop {
+                // 死信流与 notifier 同生命周期，以事件通道关闭为准退出
+                let event = tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(event) => event,
+                        None => break,
+                    },
+                    Some(letter) = dead_letters.next() => {
+                        Self::fail(&shared, letter);
+                        continue;
+                    }
+                };
+                match event {
+                    event @ Event::Offer { .. } => {
+                        let Some(offer) = IncomingTransfer::from_event(&event) else {
+                            continue;
+                        };
+                        // 记入待决列表后才交给上层，`accept` 据此确定保存位置
+                        let info = FileInfo::new(offer.hash, offer.file_name.clone(), offer.meta);
+                        match tasks.lock().await.offer(info, offer.peer.clone()).await {
+                            Ok(()) => notifier.notify_incoming(offer),
+                            Err(err) => debug!("Drop offer of {:016x}: {err}", offer.hash),
+                        }
+                    }
+                    Event::Decline { host, hash, reason } => {
+                        info!("{host} declined {hash:016x}: {reason}");
+                        if let Some(mut file) = shared.get_mut(&hash) {
+                            file.peers.remove(&host);
+                        }
+                    }
+                    Event::Fetch { host, hash, token } => {
+                        // 先确认还在分享，免得白白消费掉令牌
+                        let Some((path, invited)) = shared
+                            .get(&hash)
+                            .map(|file| (file.path.clone(), file.peers.contains(&host)))
+                        else {
+                            warn!("Fetch of {hash:016x} from {host} has no local file to share");
+                            continue;
+                        };
+                        if !invited
+                            && let Err(err) = token_store().authorize_fetch(&host, hash, token.as_ref())
+                        {
+                            warn!("Reject fetch of {hash:016x} from {host}: {err}");
+                            continue;
+                        }
+                        debug!("Accept fetch of {hash:016x} from {host}");
+                        let started = tasks
+                            .lock()
+                            .await
+                            .share(hash, path.as_std_path(), host.clone())
+                            .await;
+                        if let Err(err) = started {
+                            warn!("Failed to share {path} with {host}: {err}");
+                        }
+                    }
+                    Event::Transfer { host, payload } => match TaskFrame::decode(&payload) {
+                        Ok(frame) => {
+                            if !tasks.lock().await.route(host.clone(), frame).await {
+                                debug!("No running task for the transfer payload from {host}");
+                            }
+                        }
+                        Err(err) => warn!("Drop malformed transfer payload from {host}: {err}"),
+                    },
+                    // 握手事件与密文在会话层已经消化
+                    Event::Auth { .. } | Event::Sealed { .. } => {}
+                }
+            }
+        })
+        .abort_handle();
+        Self { abort, bridge }
+    }
+
+    /// 发给某个接收方的邀约最终没能发出，撤销它的授权并把这次分享记为失败
+    fn fail(shared: &SharedFiles, letter: DeadLetter) {
+        let Some(hash) = letter.hash else { return };
+        let Some(mut file) = shared.get_mut(&hash) else { return };
+        if !file.peers.remove(&letter.host) {
+            return;
+        }
+        let file_name = file.path.file_name().unwrap_or(file.path.as_str());
+        record_history(HistoryRecord::failed(
+            file_name,
+            file.size,
+            &letter.host,
+            hash,
+            file.shared_at.elapsed(),
+            &letter.failure,
+        ));
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.abort.abort();
+        self.bridge.abort();
+        info!("Dispatcher has been dropped");
+    }
+}
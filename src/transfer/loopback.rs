@@ -0,0 +1,203 @@
+use super::{FailedTransfer, TransferNotifier, TransferProgress};
+use crate::{
+    history::{HistoryRecord, record_history},
+    hot_file::extended_path,
+    inbound::HostId,
+    task::{
+        CompletedTransfer, DownloadPolicy, FileHash, FileMeta, LocalCopy, PART_SUFFIX,
+        RateEstimator, TaskError, local_copy_with_progress,
+    },
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// 进度事件的最小间隔，普通复制每 1 MiB 回调一次，全部广播会淹没订阅者
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 发给自己的文件不经过网络，直接复制到下载目录
+///
+/// 保存位置、冲突处理、进度、完成通知与历史记录都与网络下载一致
+#[derive(Debug, Clone)]
+pub(super) struct Loopback {
+    local: HostId,
+    destination: DownloadPolicy,
+    notifier: TransferNotifier,
+}
+
+impl Loopback {
+    pub(super) fn new(
+        local: HostId,
+        destination: DownloadPolicy,
+        notifier: TransferNotifier,
+    ) -> Self {
+        Self {
+            local,
+            destination,
+            notifier,
+        }
+    }
+
+    /// 在后台复制，结果经通知与历史记录告知上层
+    pub(super) fn spawn(
+        &self,
+        src: Utf8PathBuf,
+        file_name: String,
+        hash: FileHash,
+        meta: FileMeta,
+    ) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            match this.copy(&src, &file_name, hash, meta).await {
+                Ok((path, copied)) => {
+                    info!("Copied {src} to {path} locally ({:?})", copied.method);
+                    let done = CompletedTransfer {
+                        path,
+                        peer: this.local,
+                        hash,
+                        size: copied.size,
+                        elapsed: start.elapsed(),
+                        copy_method: Some(copied.method),
+                    };
+                    record_history(HistoryRecord::completed(&done));
+                    this.notifier.notify_completed(done);
+                }
+                Err(err) => {
+                    warn!("Failed to copy {src} locally: {err}");
+                    record_history(HistoryRecord::failed(
+                        &file_name,
+                        meta.size,
+                        &this.local,
+                        hash,
+                        start.elapsed(),
+                        &err,
+                    ));
+                    this.notifier.notify_failed(FailedTransfer {
+                        peer: this.local,
+                        hash,
+                        file_name,
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        });
+    }
+
+    async fn copy(
+        &self,
+        src: &Utf8Path,
+        file_name: &str,
+        hash: FileHash,
+        meta: FileMeta,
+    ) -> Result<(Utf8PathBuf, LocalCopy), TaskError> {
+        // 改名完成前占着保存路径
+        let claim = self
+            .destination
+            .resolve(Path::new(file_name), &self.local)
+            .await?;
+        let target =
+            Utf8PathBuf::try_from(claim.path().to_path_buf()).map_err(|err| err.into_io_error())?;
+        // 与网络下载一样先写临时文件，完成后再改名，覆盖策略也由改名实现；
+        // 同名文件可能同时在复制，临时文件名各不相同
+        let part = target.with_file_name(format!(
+            ".{}.{}{PART_SUFFIX}",
+            target.file_name().unwrap_or(file_name),
+            nanoid::nanoid!(8)
+        ));
+        let progress = self.progress(hash, meta.size as usize);
+        let copied = match local_copy_with_progress(src, &part, progress).await {
+            Ok(copied) => copied,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(extended_path(part.as_std_path())).await;
+                return Err(err.into());
+            }
+        };
+        if let Err(err) = meta.apply(part.as_std_path()).await {
+            warn!("Failed to restore metadata of {part}: {err}");
+        }
+        tokio::fs::rename(
+            extended_path(part.as_std_path()),
+            extended_path(target.as_std_path()),
+        )
+        .await?;
+        Ok((target, copied))
+    }
+
+    /// 节流后的进度回调，最后一次总会广播
+    fn progress(&self, hash: FileHash, total: usize) -> impl FnMut(u64) + Send + 'static {
+        let notifier = self.notifier.clone();
+        let mut rate = RateEstimator::new();
+        let (mut reported, mut last) = (0, Instant::now());
+        move |done| {
+            let done = done as usize;
+            rate.record(done.saturating_sub(reported));
+            reported = done;
+            if done < total && last.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+            last = Instant::now();
+            notifier.notify_progress(TransferProgress {
+                hash,
+                done,
+                total,
+                rate: rate.rate(),
+                eta: rate.eta(total.saturating_sub(done)),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::{CollisionPolicy, hash_path};
+    use futures::StreamExt;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn send_file_to_local() {
+        let dir = tempdir().unwrap();
+        let src = Utf8PathBuf::try_from(dir.path().join("falcon.bin")).unwrap();
+        let data = vec![7u8; 3 * 1024 * 1024];
+        std::fs::write(&src, &data).unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().join("downloads")).unwrap();
+        let destination = DownloadPolicy {
+            root: root.clone().into(),
+            per_peer: false,
+            collision: CollisionPolicy::Reject,
+        };
+        let notifier = TransferNotifier::new();
+        let loopback = Loopback::new(HostId::random(), destination, notifier.clone());
+        let mut progress = Box::pin(notifier.progress());
+        let mut completions = Box::pin(notifier.completions());
+        let mut failures = Box::pin(notifier.failures());
+
+        let hash = hash_path(&src).await.unwrap();
+        let meta = FileMeta::from(&std::fs::metadata(&src).unwrap());
+        loopback.spawn(src.clone(), "falcon.bin".into(), hash, meta);
+        let done = completions.next().await.unwrap();
+        assert_eq!(done.path, root.join("falcon.bin"));
+        assert_eq!((done.hash, done.size), (hash, data.len() as u64));
+        assert_eq!(std::fs::read(&done.path).unwrap(), data);
+        // 最后一次进度总会广播
+        loop {
+            let update = progress.next().await.unwrap();
+            assert_eq!((update.hash, update.total), (hash, data.len()));
+            if update.done == update.total {
+                break;
+            }
+        }
+
+        // 同名文件已存在且策略为拒绝，失败同样通知上层
+        loopback.spawn(src, "falcon.bin".into(), hash, meta);
+        let failed = failures.next().await.unwrap();
+        assert_eq!(
+            (failed.hash, failed.file_name.as_str()),
+            (hash, "falcon.bin")
+        );
+    }
+}
@@ -0,0 +1,14 @@
+mod error;
+mod facade;
+mod loopback;
+mod notify;
+mod priority;
+mod retry;
+mod router;
+
+pub use error::*;
+pub use facade::*;
+pub use notify::*;
+pub use priority::*;
+pub use retry::*;
+pub use router::*;
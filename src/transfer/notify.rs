@@ -0,0 +1,174 @@
+use super::DeadLetter;
+use crate::{
+    inbound::HostId,
+    link::Event,
+    task::{
+        CompletedTransfer, FileHash, FileMeta, ProgressEvent, lossy_stream, sanitize_file_name,
+    },
+};
+use futures::Stream;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// 对方发来的传输请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingTransfer {
+    pub peer: HostId,
+    pub hash: FileHash,
+    pub file_name: String,
+    pub size: usize,
+    /// 完成后还原的修改时间与权限
+    pub meta: FileMeta,
+}
+
+impl IncomingTransfer {
+    /// 文件名清理失败的邀约直接丢弃，不会交给上层
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::Offer {
+                owner,
+                hash,
+                file_name,
+                meta,
+            } => {
+                let file_name = sanitize_file_name(file_name.as_str())
+                    .inspect_err(|err| warn!("Drop offer of {file_name:?} from {owner}: {err}"))
+                    .ok()?;
+                Some(Self {
+                    peer: owner.clone(),
+                    hash: *hash,
+                    file_name,
+                    size: meta.size as usize,
+                    meta: *meta,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 某个传输当前已完成的字节数与速率
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferProgress {
+    pub hash: FileHash,
+    pub done: usize,
+    pub total: usize,
+    /// 平滑后的下载速率，字节每秒
+    pub rate: f64,
+    /// 速率为零时无法估计
+    pub eta: Option<Duration>,
+}
+
+impl From<&ProgressEvent> for TransferProgress {
+    fn from(event: &ProgressEvent) -> Self {
+        Self {
+            hash: event.hash,
+            done: event.done,
+            total: event.total,
+            rate: event.rate,
+            eta: event.eta,
+        }
+    }
+}
+
+/// 没有完成的传输，与写入历史的失败记录一一对应
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedTransfer {
+    pub peer: HostId,
+    pub hash: FileHash,
+    pub file_name: String,
+    pub reason: String,
+}
+
+/// 内部事件到外部订阅者的广播
+#[derive(Debug, Clone)]
+pub struct TransferNotifier {
+    incoming: broadcast::Sender<IncomingTransfer>,
+    completed: broadcast::Sender<CompletedTransfer>,
+    failed: broadcast::Sender<FailedTransfer>,
+    progress: broadcast::Sender<TransferProgress>,
+    dead_letters: broadcast::Sender<DeadLetter>,
+}
+
+impl TransferNotifier {
+    const CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self {
+            incoming: broadcast::channel(Self::CAPACITY).0,
+            completed: broadcast::channel(Self::CAPACITY).0,
+            failed: broadcast::channel(Self::CAPACITY).0,
+            progress: broadcast::channel(Self::CAPACITY).0,
+            dead_letters: broadcast::channel(Self::CAPACITY).0,
+        }
+    }
+
+    /// 没有订阅者时直接丢弃
+    pub fn notify_incoming(&self, incoming: IncomingTransfer) {
+        let _ = self.incoming.send(incoming);
+    }
+
+    pub fn notify_completed(&self, completed: CompletedTransfer) {
+        let _ = self.completed.send(completed);
+    }
+
+    pub fn notify_failed(&self, failed: FailedTransfer) {
+        let _ = self.failed.send(failed);
+    }
+
+    pub fn notify_progress(&self, progress: TransferProgress) {
+        let _ = self.progress.send(progress);
+    }
+
+    pub fn notify_dead_letter(&self, letter: DeadLetter) {
+        let _ = self.dead_letters.send(letter);
+    }
+
+    pub fn incoming(&self) -> impl Stream<Item = IncomingTransfer> + use<> {
+        lossy_stream(self.incoming.subscribe())
+    }
+
+    pub fn completions(&self) -> impl Stream<Item = CompletedTransfer> + use<> {
+        lossy_stream(self.completed.subscribe())
+    }
+
+    pub fn failures(&self) -> impl Stream<Item = FailedTransfer> + use<> {
+        lossy_stream(self.failed.subscribe())
+    }
+
+    pub fn progress(&self) -> impl Stream<Item = TransferProgress> + use<> {
+        lossy_stream(self.progress.subscribe())
+    }
+
+    pub fn dead_letters(&self) -> impl Stream<Item = DeadLetter> + use<> {
+        lossy_stream(self.dead_letters.subscribe())
+    }
+}
+
+impl Default for TransferNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn stream_incoming() {
+        let notifier = TransferNotifier::new();
+        let mut incoming = Box::pin(notifier.incoming());
+        let offer = IncomingTransfer {
+            peer: HostId::random(),
+            hash: 42,
+            file_name: "falcon.bin".into(),
+            size: 1024,
+            meta: FileMeta::from_wire(1024, None, None),
+        };
+        notifier.notify_incoming(offer.clone());
+        assert_eq!(incoming.next().await, Some(offer));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::inbound::Msg;
+use std::collections::VecDeque;
+
+/// 出站消息的优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// 握手、确认、取消等控制消息
+    Control,
+    /// 文件数据
+    Data,
+}
+
+impl From<&Msg> for Priority {
+    fn from(msg: &Msg) -> Self {
+        if msg.is_control() {
+            Priority::Control
+        } else {
+            Priority::Data
+        }
+    }
+}
+
+/// 加权轮转的双队列：每连续发出 `control_weight` 个控制消息后让出一次给数据
+///
+/// 控制消息不会排在数据之后，数据也不会被控制消息完全饿死
+#[derive(Debug)]
+pub struct WeightedQueue<T> {
+    control: VecDeque<T>,
+    data: VecDeque<T>,
+    control_weight: usize,
+    /// 本轮还能连续发出的控制消息数
+    credit: usize,
+}
+
+impl<T> WeightedQueue<T> {
+    pub const DEFAULT_CONTROL_WEIGHT: usize = 8;
+
+    pub fn new(control_weight: usize) -> Self {
+        let control_weight = control_weight.max(1);
+        Self {
+            control: VecDeque::new(),
+            data: VecDeque::new(),
+            control_weight,
+            credit: control_weight,
+        }
+    }
+
+    pub fn push(&mut self, priority: Priority, item: T) {
+        match priority {
+            Priority::Control => self.control.push_back(item),
+            Priority::Data => self.data.push_back(item),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(Priority, T)> {
+        let serve_control = !self.control.is_empty() && (self.credit > 0 || self.data.is_empty());
+        if serve_control {
+            self.credit = self.credit.saturating_sub(1);
+            return self
+                .control
+                .pop_front()
+                .map(|item| (Priority::Control, item));
+        }
+        // 数据出队后重新积累控制配额
+        self.credit = self.control_weight;
+        self.data.pop_front().map(|item| (Priority::Data, item))
+    }
+
+    pub fn len(&self) -> usize {
+        self.control.len() + self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.control.is_empty() && self.data.is_empty()
+    }
+
+    /// 各优先级的排队长度
+    pub fn depth(&self) -> (usize, usize) {
+        (self.control.len(), self.data.len())
+    }
+}
+
+impl<T> Default for WeightedQueue<T> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CONTROL_WEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_jumps_data() {
+        let mut queue = WeightedQueue::new(2);
+        for i in 0..3 {
+            queue.push(Priority::Data, i);
+        }
+        for i in 10..15 {
+            queue.push(Priority::Control, i);
+        }
+        let order = std::iter::from_fn(|| queue.pop().map(|(_, i)| i)).collect::<Vec<_>>();
+        assert_eq!(order, vec![10, 11, 0, 12, 13, 1, 14, 2]);
+    }
+
+    #[test]
+    fn data_only() {
+        let mut queue = WeightedQueue::default();
+        queue.push(Priority::Data, 1);
+        queue.push(Priority::Data, 2);
+        assert_eq!(queue.pop(), Some((Priority::Data, 1)));
+        assert_eq!(queue.pop(), Some((Priority::Data, 2)));
+        assert!(queue.pop().is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    config::{ConfigItem, ConfigManager},
+    inbound::{CodecError, HostId, Msg},
+    task::FileHash,
+};
+use std::{
+    io::{self, ErrorKind},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// 控制消息发送失败后的重试策略，退避时间逐次翻倍
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub async fn from_config(cfg: &ConfigManager) -> Self {
+        let default = Self::default();
+        Self {
+            max_retries: cfg
+                .get_typed(ConfigItem::SendRetries)
+                .await
+                .unwrap_or(default.max_retries),
+            initial_backoff: cfg
+                .get_typed(ConfigItem::SendBackoffMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.initial_backoff),
+            max_backoff: cfg
+                .get_typed(ConfigItem::SendMaxBackoffMs)
+                .await
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_backoff),
+        }
+    }
+
+    /// 第 attempt 次重试前等待的时间，从 1 开始
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// 发送失败的原因
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum SendFailure {
+    /// 链路表中没有可达的链路，链路恢复后可能成功
+    #[error("No reachable link: {0}")]
+    NoRoute(String),
+    /// 链路上的 io 错误，换一条链路或稍后可能成功
+    #[error("Link error: {0}")]
+    Link(String),
+    /// 会话过期后正在重新握手，握手完成后可以发出
+    #[error("Session with {0} is being re-established")]
+    Handshaking(HostId),
+    /// 报文本身无法加密或编码，重试也不会成功
+    #[error("Message rejected: {0}")]
+    Rejected(String),
+}
+
+impl SendFailure {
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, SendFailure::Rejected(_))
+    }
+
+    /// 参数错误与不支持的操作换链路也无济于事，其余 io 错误都可能是暂时的
+    pub fn from_codec(err: &CodecError) -> Self {
+        match err {
+            CodecError::Io(io) => Self::from_io(io),
+            _ => SendFailure::Rejected(err.to_string()),
+        }
+    }
+
+    pub fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::InvalidInput | ErrorKind::Unsupported => {
+                SendFailure::Rejected(err.to_string())
+            }
+            _ => SendFailure::Link(err.to_string()),
+        }
+    }
+}
+
+/// 最终没能发出的报文
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub host: HostId,
+    /// 报文涉及的文件，数据报文的载荷不透明，为 None
+    pub hash: Option<FileHash>,
+    pub control: bool,
+    /// 包括第一次在内的发送次数
+    pub attempts: u32,
+    pub failure: SendFailure,
+}
+
+impl DeadLetter {
+    pub fn new(host: HostId, msg: &Msg, attempts: u32, failure: SendFailure) -> Self {
+        let hash = match msg {
+            Msg::Offer { hash, .. } | Msg::Decline { hash, .. } | Msg::Fetch { hash, .. } => {
+                Some(*hash)
+            }
+            _ => None,
+        };
+        Self {
+            host,
+            hash,
+            control: msg.is_control(),
+            attempts,
+            failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn backoff_and_classify() {
+        let policy = RetryPolicy::default();
+        let backoffs: Vec<_> = (1..=7).map(|attempt| policy.backoff(attempt)).collect();
+        let millis = [50, 100, 200, 400, 800, 1600, 2000].map(Duration::from_millis);
+        assert_eq!(backoffs, millis);
+        assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+
+        let unreachable = CodecError::Io(io::ErrorKind::HostUnreachable.into());
+        assert!(SendFailure::from_codec(&unreachable).is_transient());
+        let invalid = CodecError::Io(io::ErrorKind::InvalidInput.into());
+        assert!(!SendFailure::from_codec(&invalid).is_transient());
+        assert!(!SendFailure::from_codec(&CodecError::Oversized(1 << 20)).is_transient());
+    }
+}
@@ -0,0 +1,599 @@
+use super::{DeadLetter, Priority, RetryPolicy, SendFailure, TransferNotifier, WeightedQueue};
+use crate::{
+    inbound::{
+        CodecError, GroupFailure, HostId, Msg, MsgSinkMap, SinkCommand, discovery_destination,
+    },
+    addr::EndPoint,
+    link::{AssignedLink, DirectParcel, LinkState, link_state_table, local_for},
+    policy::upload_limiter,
+    session::{is_established, rekey_announcement, resume_handshake, seal_msg},
+    shutdown::shutdown_token,
+};
+use futures::{
+    FutureExt, SinkExt, StreamExt,
+    future::{BoxFuture, poll_fn},
+    stream::FuturesUnordered,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{sync::mpsc, task::AbortHandle, time::sleep};
+use tracing::{debug, info, warn};
+
+/// 按目标主机查表选择链路，把消息交给对应接口的 sink
+pub struct Router {
+    abort: AbortHandle,
+}
+
+/// 发送失败后的去向：暂时性的错误按策略退避重试，重试耗尽或永久失败的报文进入死信
+struct Outbox {
+    policy: RetryPolicy,
+    retries: mpsc::UnboundedSender<Retry>,
+    notifier: TransferNotifier,
+    /// 本地 socket 与目标地址对应的主机与链路远端，批量发送失败时据此归属
+    routes: Mutex<HashMap<(EndPoint, SocketAddr), (HostId, EndPoint)>>,
+}
+
+/// 等待重发的控制消息，attempt 为已经发送过的次数
+struct Retry {
+    host: HostId,
+    msg: Msg,
+    attempt: u32,
+}
+
+impl Outbox {
+    /// 控制消息在退避后重新入队，数据报文的丢失交给确认重传，只上报永久失败
+    fn failed(&self, host: HostId, msg: Option<Msg>, attempt: u32, failure: SendFailure) {
+        match msg {
+            Some(msg) if failure.is_transient() && attempt <= self.policy.max_retries => {
+                let delay = self.policy.backoff(attempt);
+                debug!("Retry message to {host} in {delay:?} after attempt {attempt}: {failure}");
+                let retries = self.retries.clone();
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    let _ = retries.send(Retry { host, msg, attempt });
+                });
+            }
+            Some(msg) => self.dead_letter(DeadLetter::new(host, &msg, attempt, failure)),
+            None if !failure.is_transient() => self.dead_letter(DeadLetter {
+                host,
+                hash: None,
+                control: false,
+                attempts: attempt,
+                failure,
+            }),
+            None => debug!("Drop data to {host}: {failure}"),
+        }
+    }
+
+    fn route(&self, link: &AssignedLink, host: &HostId) {
+        let key = (*link.local(), (*link.remote()).into());
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(key, (host.clone(), *link.remote()));
+    }
+
+    /// 批量发送失败的各组按目标归到主机与链路，标记链路失效并上报；返回 `own` 这一组的失败，
+    /// 由发送它的调用方处理
+    fn batch_failed(
+        &self,
+        local: &EndPoint,
+        failures: Vec<GroupFailure>,
+        own: Option<SocketAddr>,
+    ) -> Option<SendFailure> {
+        let mut own_failure = None;
+        for GroupFailure { dst, count, error } in failures {
+            let failure = SendFailure::from_io(&error);
+            if Some(dst) == own {
+                own_failure = Some(failure);
+                continue;
+            }
+            let route = self.routes.lock().unwrap().get(&(*local, dst)).cloned();
+            let Some((host, remote)) = route else {
+                warn!("Failed to send {count} datagrams to {dst} via {local}: {error}");
+                continue;
+            };
+            warn!("Failed to send {count} datagrams to {host} via {remote}: {error}");
+            // 与 `send_on` 一样标记链路失效，中继链路的远端是中继本身，不在直连中
+            let table = link_state_table();
+            if let Some(link) = table.direct_link(&host, &remote)
+                && let Err(err) = table.retire(&host, link)
+            {
+                warn!("Failed to deactivate link: {err}");
+            }
+            self.failed(host, None, 1, failure);
+        }
+        own_failure
+    }
+
+    fn dead_letter(&self, letter: DeadLetter) {
+        warn!(
+            "Give up message to {} after {} attempts: {}",
+            letter.host, letter.attempts, letter.failure
+        );
+        self.notifier.notify_dead_letter(letter);
+    }
+}
+
+/// 本地与远端地址确定一条链路
+type LinkKey = (EndPoint, EndPoint);
+
+/// 拥塞窗口已满的链路上排队的数据报文，窗口腾出后按序发出，不挡其他链路
+#[derive(Default)]
+struct Parked {
+    links: HashMap<LinkKey, VecDeque<(HostId, AssignedLink, Msg, usize)>>,
+    /// 每条排队的链路一个，等到的窗口已经占下，归队头的报文
+    waits: FuturesUnordered<BoxFuture<'static, LinkKey>>,
+}
+
+impl Parked {
+    fn key(link: &AssignedLink) -> LinkKey {
+        (*link.local(), *link.remote())
+    }
+
+    fn is_waiting(&self, link: &AssignedLink) -> bool {
+        self.links.contains_key(&Self::key(link))
+    }
+
+    /// 前面没有排队且窗口放得下时直接放行，否则排到这条链路的队尾
+    fn admit(
+        &mut self,
+        host: HostId,
+        link: AssignedLink,
+        msg: Msg,
+        len: usize,
+    ) -> Option<(HostId, AssignedLink, Msg)> {
+        // 中继链路收不到对端的链路确认，不受窗口约束
+        let Some(state) = link.state().filter(|_| link.via().is_none()) else {
+            return Some((host, link, msg));
+        };
+        let key = Self::key(&link);
+        match self.links.get_mut(&key) {
+            Some(queue) => queue.push_back((host, link, msg, len)),
+            None if state.cwnd.try_acquire(len) => return Some((host, link, msg)),
+            None => {
+                Self::wait(&mut self.waits, key, &state, len);
+                self.links
+                    .insert(key, VecDeque::from([(host, link, msg, len)]));
+            }
+        }
+        None
+    }
+
+    fn wait(
+        waits: &mut FuturesUnordered<BoxFuture<'static, LinkKey>>,
+        key: LinkKey,
+        state: &Arc<LinkState>,
+        len: usize,
+    ) {
+        let (state, rto) = (state.clone(), state.rtt.rto());
+        waits.push(
+            async move {
+                state.cwnd.acquire(len, rto).await;
+                key
+            }
+            .boxed(),
+        );
+    }
+
+    /// 队头已经拿到窗口，连同之后窗口放得下的报文一起取出，放不下时接着等
+    fn release(&mut self, key: LinkKey) -> Vec<(HostId, AssignedLink, Msg)> {
+        let Some(queue) = self.links.get_mut(&key) else {
+            return Vec::new();
+        };
+        let mut ready: Vec<_> = queue.pop_front().into_iter().collect();
+        while let Some((_, link, _, len)) = queue.front() {
+            if let Some(state) = link.state()
+                && !state.cwnd.try_acquire(*len)
+            {
+                Self::wait(&mut self.waits, key, &state, *len);
+                return ready.into_iter().map(|(h, l, m, _)| (h, l, m)).collect();
+            }
+            ready.extend(queue.pop_front());
+        }
+        self.links.remove(&key);
+        ready.into_iter().map(|(h, l, m, _)| (h, l, m)).collect()
+    }
+
+    /// 退出时不再等窗口，全部取出
+    fn drain(&mut self) -> Vec<(HostId, AssignedLink, Msg)> {
+        self.waits.clear();
+        self.links
+            .drain()
+            .flat_map(|(_, queue)| queue)
+            .map(|(host, link, msg, _)| (host, link, msg))
+            .collect()
+    }
+}
+
+impl Router {
+    pub fn run(
+        mut sinks: MsgSinkMap,
+        mut rx: mpsc::UnboundedReceiver<(HostId, Msg)>,
+        mut direct: mpsc::UnboundedReceiver<DirectParcel>,
+        mut sockets: mpsc::UnboundedReceiver<SinkCommand>,
+        policy: RetryPolicy,
+        notifier: TransferNotifier,
+    ) -> Self {
+        let token = shutdown_token().clone();
+        let abort = shutdown_token().spawn(async move {
+            let mut queue = WeightedQueue::default();
+            let mut parked = Parked::default();
+            let (retries, mut retry_rx) = mpsc::unbounded_channel();
+            let outbox = Outbox {
+                policy,
+                retries,
+                notifier,
+                routes: Mutex::default(),
+            };
+            loop {
+                // 网卡变化先于其他消息生效，避免发往已消失的接口
+                while let Ok(command) = sockets.try_recv() {
+                    Self::apply(&mut sinks, command).await;
+                }
+                // 打洞报文数量少且对时序敏感，直接发出
+                while let Ok((remote, msg)) = direct.try_recv() {
+                    Self::send_direct(&mut sinks, remote, msg).await;
+                }
+                // 退避到期的控制消息重新发出
+                while let Ok(retry) = retry_rx.try_recv() {
+                    Self::retry(&mut sinks, &outbox, retry).await;
+                }
+                // 窗口腾出的链路把排队的报文接着发出
+                while let Some(Some(key)) = parked.waits.next().now_or_never() {
+                    Self::send_parked(&mut sinks, &outbox, parked.release(key)).await;
+                }
+                // 先把通道里已有的消息按优先级分拣，控制消息才能插到数据前面
+                while queue.len() < Self::MAX_BATCH {
+                    let Ok(parcel) = rx.try_recv() else { break };
+                    queue.push(Priority::from(&parcel.1), parcel);
+                }
+                let Some((priority, (host, msg))) = queue.pop() else {
+                    // 空闲前把攒下的数据报文发出去
+                    Self::flush(&mut sinks, &outbox).await;
+                    tokio::select! {
+                        Some(parcel) = rx.recv() => {
+                            queue.push(Priority::from(&parcel.1), parcel);
+                            continue;
+                        }
+                        Some((remote, msg)) = direct.recv() => {
+                            Self::send_direct(&mut sinks, remote, msg).await;
+                            continue;
+                        }
+                        Some(command) = sockets.recv() => {
+                            Self::apply(&mut sinks, command).await;
+                            continue;
+                        }
+                        Some(retry) = retry_rx.recv() => {
+                            Self::retry(&mut sinks, &outbox, retry).await;
+                            continue;
+                        }
+                        Some(key) = parked.waits.next() => {
+                            Self::send_parked(&mut sinks, &outbox, parked.release(key)).await;
+                            continue;
+                        }
+                        _ = token.cancelled() => {
+                            // 不再接收新消息，把已排队的发完再退出，退出后不再重试
+                            rx.close();
+                            Self::send_parked(&mut sinks, &outbox, parked.drain()).await;
+                            while let Some((host, msg)) = rx.recv().await {
+                                Self::send(&mut sinks, &outbox, host, msg, 1).await;
+                            }
+                            Self::flush(&mut sinks, &outbox).await;
+                            info!("Router drained");
+                            break;
+                        }
+                        else => break,
+                    }
+                };
+                if priority == Priority::Data {
+                    Self::resume(&mut sinks, &outbox, &host, msg.host()).await;
+                    Self::announce_rekey(&mut sinks, &outbox, &host, msg.host()).await;
+                    // 数据报文不保留副本，丢失由确认重传补发
+                    // 先加密，拥塞窗口按线路上的长度记账，与接收方的确认一致
+                    // 先定链路，再按这条链路的拥塞窗口节流
+                    let sealed = Self::seal(&host, msg)
+                        .and_then(|msg| Self::assign(&host).map(|link| (msg, link)));
+                    let (msg, link) = match sealed {
+                        Ok(sealed) => sealed,
+                        Err(failure) => {
+                            outbox.failed(host, None, 1, failure);
+                            continue;
+                        }
+                    };
+                    let (host, link, msg) = match msg.data_len() {
+                        Some(len) => {
+                            Self::pace(
+                                &mut sinks,
+                                &outbox,
+                                &mut rx,
+                                &mut direct,
+                                &mut queue,
+                                len,
+                            )
+                            .await;
+                            // 刚开始等窗口时先发出缓冲的数据，否则等不到它们的确认
+                            let waiting = parked.is_waiting(&link);
+                            match parked.admit(host, link, msg, len) {
+                                Some(admitted) => admitted,
+                                None if !waiting => {
+                                    Self::flush(&mut sinks, &outbox).await;
+                                    continue;
+                                }
+                                None => continue,
+                            }
+                        }
+                        None => (host, link, msg),
+                    };
+                    // 数据报文只交给 sink 缓冲，攒够一批或空闲时才真正发出
+                    let sent =
+                        Self::send_on(&mut sinks, &outbox, host.clone(), link, msg, false).await;
+                    if let Err(failure) = sent {
+                        outbox.failed(host, None, 1, failure);
+                    }
+                    continue;
+                }
+                Self::send(&mut sinks, &outbox, host, msg, 1).await;
+            }
+        })
+        .abort_handle();
+        Self { abort }
+    }
+
+    /// 单轮最多从通道分拣的消息数，避免队列无限增长
+    const MAX_BATCH: usize = 256;
+
+    /// 等待上传限速令牌，期间到达的控制消息直接发出，不被数据阻塞
+    ///
+    /// 链路的拥塞窗口不在这里等，窗口满的报文交给 `Parked` 排队
+    async fn pace(
+        sinks: &mut MsgSinkMap,
+        outbox: &Outbox,
+        rx: &mut mpsc::UnboundedReceiver<(HostId, Msg)>,
+        direct: &mut mpsc::UnboundedReceiver<DirectParcel>,
+        queue: &mut WeightedQueue<(HostId, Msg)>,
+        len: usize,
+    ) {
+        let wait = upload_limiter().acquire(len);
+        tokio::pin!(wait);
+        // 要等待时先把缓冲的数据发出
+        if futures::poll!(&mut wait).is_ready() {
+            return;
+        }
+        Self::flush(sinks, outbox).await;
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut wait => return,
+                Some((host, msg)) = rx.recv() => match Priority::from(&msg) {
+                    Priority::Control => Self::send(sinks, outbox, host, msg, 1).await,
+                    Priority::Data => queue.push(Priority::Data, (host, msg)),
+                },
+                Some((remote, msg)) = direct.recv() => Self::send_direct(sinks, remote, msg).await,
+                else => return wait.await,
+            }
+        }
+    }
+
+    /// 发出窗口腾出后取出的数据报文
+    async fn send_parked(
+        sinks: &mut MsgSinkMap,
+        outbox: &Outbox,
+        ready: Vec<(HostId, AssignedLink, Msg)>,
+    ) {
+        for (host, link, msg) in ready {
+            let sent = Self::send_on(sinks, outbox, host.clone(), link, msg, false).await;
+            if let Err(failure) = sent {
+                outbox.failed(host, None, 1, failure);
+            }
+        }
+    }
+
+    async fn apply(sinks: &mut MsgSinkMap, command: SinkCommand) {
+        match command {
+            SinkCommand::Attach(local, sink) => {
+                info!("Socket on {local} attached");
+                sinks.insert(local, sink);
+            }
+            SinkCommand::Detach(local) => {
+                if let Some(mut sink) = sinks.remove(&local) {
+                    let _ = sink.close().await;
+                    info!("Socket on {local} detached");
+                }
+            }
+            SinkCommand::Announce(host) => {
+                for (local, sink) in sinks.iter_mut() {
+                    let Some(dst) = discovery_destination(local) else {
+                        continue;
+                    };
+                    let discovery = Msg::Discovery {
+                        host: host.clone(),
+                        remote: *local,
+                    };
+                    if let Err(err) = sink.send((discovery, dst.into())).await {
+                        warn!("Failed to announce on {local}: {err}");
+                    }
+                }
+            }
+            SinkCommand::Rediscover {
+                local,
+                host,
+                targets,
+            } => {
+                let Some(sink) = sinks.get_mut(&local) else {
+                    warn!("No socket bound on {local} to rediscover from");
+                    return;
+                };
+                for remote in &targets {
+                    let discovery = Msg::Discovery {
+                        host: host.clone(),
+                        remote: local,
+                    };
+                    if let Err(err) = sink.send((discovery, (*remote).into())).await {
+                        warn!("Failed to rediscover {remote} via {local}: {err}");
+                    }
+                }
+                info!("Rediscover {} known endpoints via {local}", targets.len());
+            }
+        }
+    }
+
+    /// 不查链路表，按作用域挑选本地 socket 直接发往指定地址
+    async fn send_direct(sinks: &mut MsgSinkMap, remote: EndPoint, msg: Msg) {
+        let Some(local) = local_for(&remote, sinks.keys()).copied() else {
+            warn!("No socket can reach {remote}");
+            return;
+        };
+        let sink = sinks.get_mut(&local).expect("local endpoint comes from sinks");
+        if let Err(err) = sink.send((msg, remote.into())).await {
+            warn!("Failed to send to {remote} via {local}: {err}");
+        }
+    }
+
+    fn assign(host: &HostId) -> Result<AssignedLink, SendFailure> {
+        link_state_table()
+            .assign(host)
+            .map_err(|err| SendFailure::NoRoute(err.to_string()))
+    }
+
+    /// 会话层报文一律加密
+    fn seal(host: &HostId, msg: Msg) -> Result<Msg, SendFailure> {
+        // 首次握手或过期后的重新握手完成之前都不能退回明文，控制消息退避后重试
+        if msg.is_session() && !is_established(host) {
+            return Err(SendFailure::Handshaking(host.clone()));
+        }
+        seal_msg(host, msg).map_err(|err| SendFailure::Rejected(err.to_string()))
+    }
+
+    /// 到了换钥阈值时先发通告，确认回来之前这条及之后的报文仍用旧密钥
+    async fn announce_rekey(
+        sinks: &mut MsgSinkMap,
+        outbox: &Outbox,
+        host: &HostId,
+        local: &HostId,
+    ) {
+        if let Some(announce) = rekey_announcement(host, local) {
+            Self::send_once(sinks, outbox, host.clone(), announce, 1).await;
+        }
+    }
+
+    /// 会话已过期时先重新握手，期间的控制消息退避重试，数据交给确认重传
+    async fn resume(sinks: &mut MsgSinkMap, outbox: &Outbox, host: &HostId, local: &HostId) {
+        if let Some(hello) = resume_handshake(host, local) {
+            Self::send_once(sinks, outbox, host.clone(), hello, 1).await;
+        }
+    }
+
+    async fn retry(sinks: &mut MsgSinkMap, outbox: &Outbox, retry: Retry) {
+        let Retry { host, msg, attempt } = retry;
+        Self::send(sinks, outbox, host, msg, attempt + 1).await;
+    }
+
+    /// 控制消息，attempt 为包括这一次在内的发送次数
+    async fn send(sinks: &mut MsgSinkMap, outbox: &Outbox, host: HostId, msg: Msg, attempt: u32) {
+        Self::resume(sinks, outbox, &host, msg.host()).await;
+        Self::announce_rekey(sinks, outbox, &host, msg.host()).await;
+        Self::send_once(sinks, outbox, host, msg, attempt).await;
+    }
+
+    /// 密文每次重新封装，控制消息保留明文副本交给重试
+    async fn send_once(
+        sinks: &mut MsgSinkMap,
+        outbox: &Outbox,
+        host: HostId,
+        msg: Msg,
+        attempt: u32,
+    ) {
+        let copy = msg.is_control().then(|| msg.clone());
+        let sent = match Self::seal(&host, msg) {
+            Ok(sealed) => match Self::assign(&host) {
+                Ok(link) => Self::send_on(sinks, outbox, host.clone(), link, sealed, true).await,
+                Err(failure) => Err(failure),
+            },
+            Err(failure) => Err(failure),
+        };
+        if let Err(failure) = sent {
+            outbox.failed(host, copy, attempt, failure);
+        }
+    }
+
+    async fn send_on(
+        sinks: &mut MsgSinkMap,
+        outbox: &Outbox,
+        host: HostId,
+        link: AssignedLink,
+        msg: Msg,
+        flush: bool,
+    ) -> Result<(), SendFailure> {
+        let Some(sink) = sinks.get_mut(link.local()) else {
+            return Err(SendFailure::Link(format!("no socket bound on {}", link.local())));
+        };
+        // 中继链路的远端是中继本身，需要注明最终目标
+        let msg = match link.via() {
+            Some(_) => Msg::Relayed {
+                host: msg.host().clone(),
+                from: msg.host().clone(),
+                target: host.clone(),
+                inner: Box::new(msg),
+            },
+            None => msg,
+        };
+        let remote: SocketAddr = (*link.remote()).into();
+        outbox.route(&link, &host);
+        // 就绪前报出的是之前缓冲的批次，按各组的目标归属，不算在这条报文头上
+        let sent = loop {
+            match poll_fn(|cx| sink.poll_ready_unpin(cx)).await {
+                Err(CodecError::Batch(failures)) => {
+                    outbox.batch_failed(link.local(), failures, None);
+                }
+                ready => break ready,
+            }
+        };
+        let sent = sent
+            .and_then(|_| sink.start_send_unpin((msg, remote)))
+            .map_err(|err| SendFailure::from_codec(&err));
+        let sent = match (sent, flush) {
+            (Ok(()), true) => match sink.flush().await {
+                Err(CodecError::Batch(failures)) => {
+                    match outbox.batch_failed(link.local(), failures, Some(remote)) {
+                        Some(failure) => Err(failure),
+                        None => Ok(()),
+                    }
+                }
+                flushed => flushed.map_err(|err| SendFailure::from_codec(&err)),
+            },
+            (sent, _) => sent,
+        };
+        sent.map_err(|failure| {
+            warn!("Failed to send to {host} via {}: {failure}", link.remote());
+            // 标记链路失效，交给恢复调度，重试时会选到其他链路
+            if let Err(err) = link.solve() {
+                warn!("Failed to deactivate link: {err}");
+            }
+            failure
+        })
+    }
+
+    /// 把各接口 sink 里缓冲的报文发出，失败的各组归到各自的主机与链路
+    async fn flush(sinks: &mut MsgSinkMap, outbox: &Outbox) {
+        for (local, sink) in sinks.iter_mut() {
+            match sink.flush().await {
+                Ok(()) => {}
+                Err(CodecError::Batch(failures)) => {
+                    outbox.batch_failed(local, failures, None);
+                }
+                Err(err) => warn!("Failed to flush sink on {local}: {err}"),
+            }
+        }
+    }
+}
+
+impl Drop for Router {
+    fn drop(&mut self) {
+        self.abort.abort();
+        info!("Router has been dropped");
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    inbound::HostId,
+    task::{CompletedTransfer, FileHash},
+    transfer::{IncomingTransfer, TransferProgress},
+};
+use indexmap::IndexMap;
+use std::{collections::VecDeque, time::Duration};
+
+/// 面板中的一行传输
+#[derive(Debug, Clone)]
+pub struct TransferRow {
+    pub peer: HostId,
+    pub file_name: String,
+    pub total: usize,
+    pub done: usize,
+    /// 字节每秒
+    pub rate: f64,
+    pub eta: Option<Duration>,
+}
+
+impl TransferRow {
+    pub fn ratio(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => (self.done as f64 / total as f64).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// 面板状态，只由外观层的事件驱动
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    pub transfers: IndexMap<FileHash, TransferRow>,
+    pub events: VecDeque<String>,
+}
+
+impl Dashboard {
+    const MAX_EVENTS: usize = 64;
+
+    pub fn on_incoming(&mut self, offer: IncomingTransfer) {
+        self.log(format!(
+            "{} offers {} ({} bytes)",
+            offer.peer, offer.file_name, offer.size
+        ));
+        self.transfers.insert(
+            offer.hash,
+            TransferRow {
+                peer: offer.peer,
+                file_name: offer.file_name,
+                total: offer.size,
+                done: 0,
+                rate: 0.0,
+                eta: None,
+            },
+        );
+    }
+
+    pub fn on_progress(&mut self, progress: TransferProgress) {
+        let Some(row) = self.transfers.get_mut(&progress.hash) else {
+            return;
+        };
+        row.done = progress.done;
+        row.total = progress.total;
+        // 速率由任务按窗口统计，面板直接展示
+        row.rate = progress.rate;
+        row.eta = progress.eta;
+    }
+
+    pub fn on_completed(&mut self, done: CompletedTransfer) {
+        self.transfers.shift_remove(&done.hash);
+        self.log(format!(
+            "{} from {} finished in {:.1}s",
+            done.path,
+            done.peer,
+            done.elapsed.as_secs_f64()
+        ));
+    }
+
+    fn log(&mut self, line: String) {
+        if self.events.len() == Self::MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::FileMeta;
+
+    #[test]
+    fn track_transfer() {
+        let mut dashboard = Dashboard::default();
+        let peer = HostId::random();
+        dashboard.on_incoming(IncomingTransfer {
+            peer: peer.clone(),
+            hash: 7,
+            file_name: "falcon.bin".into(),
+            size: 1000,
+            meta: FileMeta::from_wire(1000, None, None),
+        });
+        dashboard.on_progress(TransferProgress {
+            hash: 7,
+            done: 500,
+            total: 1000,
+            rate: 250.0,
+            eta: Some(Duration::from_secs(2)),
+        });
+        let row = &dashboard.transfers[&7];
+        assert_eq!(row.ratio(), 0.5);
+        assert_eq!(row.rate, 250.0);
+
+        dashboard.on_completed(CompletedTransfer {
+            path: "falcon.bin".into(),
+            peer,
+            hash: 7,
+            size: 1000,
+            elapsed: Duration::from_secs(2),
+            copy_method: None,
+        });
+        assert!(dashboard.transfers.is_empty());
+        assert_eq!(dashboard.events.len(), 2);
+    }
+}
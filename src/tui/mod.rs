@@ -0,0 +1,5 @@
+mod dashboard;
+mod ui;
+
+pub use dashboard::*;
+pub use ui::*;
@@ -0,0 +1,107 @@
+use super::Dashboard;
+use crate::transfer::{Transfer, TransferStatus};
+use futures::StreamExt;
+use ratatui::{
+    Frame,
+    crossterm::event::{self, Event, KeyCode},
+    layout::{Constraint, Layout},
+    style::{Color, Style},
+    widgets::{Block, Gauge, List, Row, Table},
+};
+use std::{io, pin::pin, time::Duration};
+use tokio::time::interval;
+
+const TICK: Duration = Duration::from_millis(250);
+
+/// 在当前终端运行面板，按 q 退出
+pub async fn run_dashboard(transfer: &Transfer) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result: io::Result<()> = async {
+        let mut dashboard = Dashboard::default();
+        let mut incoming = pin!(transfer.subscribe_incoming());
+        let mut progress = pin!(transfer.progress());
+        let mut completions = pin!(transfer.completions());
+        let mut tick = interval(TICK);
+        loop {
+            tokio::select! {
+                Some(offer) = incoming.next() => dashboard.on_incoming(offer),
+                Some(p) = progress.next() => dashboard.on_progress(p),
+                Some(done) = completions.next() => dashboard.on_completed(done),
+                _ = tick.tick() => {
+                    let status = transfer.status();
+                    terminal.draw(|frame| draw(frame, &dashboard, &status))?;
+                    if event::poll(Duration::ZERO)?
+                        && let Event::Key(key) = event::read()?
+                        && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+    .await;
+    ratatui::restore();
+    result
+}
+
+pub fn draw(frame: &mut Frame, dashboard: &Dashboard, status: &TransferStatus) {
+    let [links_area, transfers_area, events_area] = Layout::vertical([
+        Constraint::Percentage(30),
+        Constraint::Percentage(40),
+        Constraint::Percentage(30),
+    ])
+    .areas(frame.area());
+
+    let rows = status.links.iter().map(|link| {
+        let health = if link.healthy { "up" } else { "down" };
+        let rtt = link
+            .srtt
+            .map(|d| format!("{:.1}ms", d.as_secs_f64() * 1000.0))
+            .unwrap_or_else(|| "-".into());
+        Row::new([
+            link.host.to_string(),
+            format!("{} -> {}", link.local, link.remote),
+            health.into(),
+            link.metric.to_string(),
+            rtt,
+        ])
+        .style(Style::default().fg(if link.healthy { Color::Green } else { Color::Red }))
+    });
+    let links = Table::new(
+        rows,
+        [
+            Constraint::Length(24),
+            Constraint::Fill(1),
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Length(10),
+        ],
+    )
+    .header(Row::new(["peer", "link", "health", "metric", "rtt"]))
+    .block(Block::bordered().title(format!(" Peers ({}) ", status.peer_count())));
+    frame.render_widget(links, links_area);
+
+    let block = Block::bordered().title(" Transfers ");
+    let inner = block.inner(transfers_area);
+    frame.render_widget(block, transfers_area);
+    let slots = Layout::vertical(vec![Constraint::Length(1); dashboard.transfers.len()]).split(inner);
+    for (row, slot) in dashboard.transfers.values().zip(slots.iter()) {
+        let gauge = Gauge::default()
+            .ratio(row.ratio())
+            .label(format!(
+                "{} from {}  {:.0}%  {:.1} MiB/s  ETA {}",
+                row.file_name,
+                row.peer,
+                row.ratio() * 100.0,
+                row.rate / (1024.0 * 1024.0),
+                row.eta.map_or("--".to_string(), |eta| format!("{}s", eta.as_secs()))
+            ))
+            .gauge_style(Style::default().fg(Color::Cyan));
+        frame.render_widget(gauge, *slot);
+    }
+
+    let events = List::new(dashboard.events.iter().rev().map(String::as_str))
+        .block(Block::bordered().title(" Events "));
+    frame.render_widget(events, events_area);
+}